@@ -0,0 +1,138 @@
+//! Round trips on an interactive connection that shares a saturated link with a bulk transfer,
+//! with both at the same priority, against the interactive one at a higher one.
+//!
+//! ```text
+//! cargo bench --bench priority
+//! ```
+//!
+//! The link refuses packets once its own queue is full, so the sender's outbound queue backs
+//! up behind it, the way it does on a busy tun device. Times are virtual.
+
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use trust::{Impairments, InterfaceBuilder, Priority, SimNet, TcpConfig};
+
+const PINGS: usize = 50;
+const LATENCY: Duration = Duration::from_millis(10);
+const BUFFER: usize = 1 << 20;
+
+/// Ping from one end to the other `PINGS` times, alongside a bulk transfer if `bulk` says at
+/// what priority, returning the median round trip.
+fn rtt(bulk: Option<Priority>, interactive: Priority) -> io::Result<Duration> {
+    let link = Impairments {
+        latency: LATENCY,
+        bandwidth: Some(1 << 20),
+        queue: Some(16),
+        backpressure: true,
+        ..Default::default()
+    };
+    let back = Impairments {
+        latency: LATENCY,
+        ..Default::default()
+    };
+    let (net, a, b) = SimNet::new(1, link, back)?;
+    let (ia, ib) = (Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2));
+    let config = TcpConfig {
+        send_buffer: BUFFER,
+        recv_buffer: BUFFER,
+        ..Default::default()
+    };
+
+    let mut x = InterfaceBuilder::new();
+    x.config(config.clone())
+        .clock(net.clock())
+        .add_nic(a, &[ia]);
+    let mut x = x.build()?;
+    let mut y = InterfaceBuilder::new();
+    y.config(config).clock(net.clock()).add_nic(b, &[ib]);
+    let mut y = y.build()?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let clock = {
+        let (net, stop) = (net.clone(), stop.clone());
+        thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                net.advance(Duration::from_millis(1));
+                thread::sleep(Duration::from_millis(1));
+            }
+        })
+    };
+
+    let done = Arc::new(AtomicBool::new(false));
+    let listener = y.bind(80)?;
+    let bulk = match bulk {
+        Some(priority) => {
+            let stream = x.connect(ia, SocketAddrV4::new(ib, 80))?;
+            stream.set_priority(priority)?;
+            let mut sink = listener.accept()?;
+            let done = done.clone();
+            let writer = thread::spawn(move || {
+                let chunk = vec![0u8; 64 << 10];
+                while !done.load(Ordering::Relaxed) {
+                    (&stream).write_all(&chunk)?;
+                }
+                Ok::<_, io::Error>(stream)
+            });
+            let reader = thread::spawn(move || {
+                let mut buf = vec![0u8; 64 << 10];
+                while sink.read(&mut buf)? != 0 {}
+                Ok::<_, io::Error>(())
+            });
+            // let the transfer fill the queues first
+            thread::sleep(Duration::from_millis(500));
+            Some((writer, reader))
+        }
+        None => None,
+    };
+
+    let mut ping = x.connect(ia, SocketAddrV4::new(ib, 80))?;
+    ping.set_priority(interactive)?;
+    let mut pong = listener.accept()?;
+    let echo = thread::spawn(move || {
+        let mut byte = [0u8];
+        while pong.read(&mut byte)? != 0 {
+            pong.write_all(&byte)?;
+        }
+        Ok::<_, io::Error>(())
+    });
+    let mut rtts = Vec::with_capacity(PINGS);
+    for _ in 0..PINGS {
+        let start = net.now();
+        ping.write_all(b"x")?;
+        ping.read_exact(&mut [0u8])?;
+        rtts.push(net.now() - start);
+    }
+    ping.shutdown(std::net::Shutdown::Write)?;
+    echo.join().unwrap()?;
+
+    done.store(true, Ordering::Relaxed);
+    if let Some((writer, reader)) = bulk {
+        let stream = writer.join().unwrap()?;
+        stream.shutdown(std::net::Shutdown::Write)?;
+        reader.join().unwrap()?;
+    }
+    stop.store(true, Ordering::Relaxed);
+    clock.join().unwrap();
+
+    rtts.sort();
+    Ok(rtts[PINGS / 2])
+}
+
+fn main() -> io::Result<()> {
+    let report = |what: &str, rtt: Duration| println!("{:<36} median rtt {:>8.1?}", what, rtt);
+    report("path, no bulk transfer", rtt(None, Priority::Normal)?);
+    report(
+        "bulk and interactive both normal",
+        rtt(Some(Priority::Normal), Priority::Normal)?,
+    );
+    report(
+        "bulk low, interactive high",
+        rtt(Some(Priority::Low), Priority::High)?,
+    );
+    Ok(())
+}
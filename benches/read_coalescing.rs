@@ -0,0 +1,88 @@
+//! How often a bulk transfer wakes its reader, with read coalescing off against on.
+//!
+//! ```text
+//! cargo bench --bench read_coalescing
+//! ```
+//!
+//! The reader reads as soon as it is woken, so the reads it takes are the wakeups. Without
+//! coalescing, every batch of segments the packet loop takes in wakes it, however little the
+//! batch brings; with it, the reader waits for a worthwhile amount, for the sender to push, or
+//! for the delay to run out.
+
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use trust::{Impairments, InterfaceBuilder, ReadCoalescing, SimNet, TcpConfig};
+
+const TRANSFER: usize = 32 << 20;
+const BUFFER: usize = 1 << 20;
+
+/// Send `TRANSFER` bytes from one interface to another, returning how many reads it took to
+/// take them in and how long it took.
+fn transfer(coalescing: Option<ReadCoalescing>) -> io::Result<(usize, Duration)> {
+    let (_net, a, b) = SimNet::new(1, Impairments::default(), Impairments::default())?;
+    let (ia, ib) = (Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2));
+
+    let config = TcpConfig {
+        send_buffer: BUFFER,
+        recv_buffer: BUFFER,
+        ..Default::default()
+    };
+
+    let mut x = InterfaceBuilder::new();
+    x.config(config.clone()).add_nic(a, &[ia]);
+    let mut x = x.build()?;
+    let mut y = InterfaceBuilder::new();
+    y.config(config).add_nic(b, &[ib]);
+    let mut y = y.build()?;
+
+    let listener = y.bind(80)?;
+    let start = Instant::now();
+    let sender = thread::spawn(move || -> io::Result<_> {
+        let mut stream = x.connect(ia, SocketAddrV4::new(ib, 80))?;
+        let chunk = vec![0u8; 64 << 10];
+        for _ in 0..TRANSFER / chunk.len() {
+            stream.write_all(&chunk)?;
+        }
+        stream.shutdown(std::net::Shutdown::Write)?;
+        // the interface has to outlive what is still in its send buffer
+        Ok(x)
+    });
+    let mut stream = listener.accept()?;
+    stream.set_read_coalescing(coalescing)?;
+    let mut buf = vec![0u8; 256 << 10];
+    let mut total = 0;
+    let mut reads = 0;
+    loop {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        total += n;
+        reads += 1;
+    }
+    let elapsed = start.elapsed();
+    drop(sender.join().unwrap()?);
+    assert_eq!(total, TRANSFER);
+    Ok((reads, elapsed))
+}
+
+fn report(what: &str, reads: usize, elapsed: Duration) {
+    println!(
+        "{:<20} {:>7} reads of {:>6} bytes on average, in {:>7.1?}",
+        what,
+        reads,
+        TRANSFER / reads,
+        elapsed
+    );
+}
+
+fn main() -> io::Result<()> {
+    let (reads, elapsed) = transfer(None)?;
+    report("without coalescing", reads, elapsed);
+    let (reads, elapsed) = transfer(Some(ReadCoalescing::default()))?;
+    report("with coalescing", reads, elapsed);
+    Ok(())
+}
@@ -0,0 +1,113 @@
+//! Packets per second received one at a time, against in batches.
+//!
+//! ```text
+//! cargo bench --bench recv_batch
+//! ```
+//!
+//! First straight off a pair of simulated nics, then for a bulk transfer between two
+//! interfaces over them, where batching also lets both ends send one ACK and one burst of data
+//! per batch rather than per segment. The transfer uses buffers big enough that the window is
+//! never what holds it up.
+
+use std::io::{self, IoSliceMut, Read, Write};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use trust::{Impairments, InterfaceBuilder, Nic, SimNet, TcpConfig};
+
+const PACKETS: usize = 200_000;
+const TRANSFER: usize = 64 << 20;
+const BUFFER: usize = 1 << 20;
+
+fn report(what: &str, packets: usize, elapsed: Duration) {
+    println!(
+        "{:<28} {:>9} packets in {:>7.1?}: {:>10.0} packets/s",
+        what,
+        packets,
+        elapsed,
+        packets as f64 / elapsed.as_secs_f64()
+    );
+}
+
+/// Drain `PACKETS` small packets from a nic, `batch` at a time.
+fn nic_pair(batch: usize) -> io::Result<Duration> {
+    let (_net, mut a, mut b) = SimNet::new(1, Impairments::default(), Impairments::default())?;
+    let packet = [0u8; 64];
+    let mut bufs: Vec<Vec<u8>> = (0..batch).map(|_| vec![0; 1500]).collect();
+    let mut lens = vec![0; batch];
+    let mut received = 0;
+    let start = Instant::now();
+    while received < PACKETS {
+        for _ in 0..batch {
+            a.send(&packet)?;
+        }
+        let mut slices: Vec<_> = bufs.iter_mut().map(|b| IoSliceMut::new(b)).collect();
+        received += if batch == 1 {
+            b.recv(&mut slices[0])?;
+            1
+        } else {
+            b.recv_batch(&mut slices, &mut lens)?
+        };
+    }
+    Ok(start.elapsed())
+}
+
+/// Send `TRANSFER` bytes from one interface to another, returning how many packets the two
+/// took in between them and how long it took.
+fn transfer(batched: bool) -> io::Result<(usize, Duration)> {
+    let (net, mut a, mut b) = SimNet::new(1, Impairments::default(), Impairments::default())?;
+    a.set_batched(batched);
+    b.set_batched(batched);
+    let (ia, ib) = (Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2));
+
+    let config = TcpConfig {
+        send_buffer: BUFFER,
+        recv_buffer: BUFFER,
+        ..Default::default()
+    };
+
+    let mut x = InterfaceBuilder::new();
+    x.config(config.clone()).add_nic(a, &[ia]);
+    let mut x = x.build()?;
+    let mut y = InterfaceBuilder::new();
+    y.config(config).add_nic(b, &[ib]);
+    let mut y = y.build()?;
+
+    let listener = y.bind(80)?;
+    let start = Instant::now();
+    let sender = thread::spawn(move || -> io::Result<_> {
+        let mut stream = x.connect(ia, SocketAddrV4::new(ib, 80))?;
+        let chunk = vec![0u8; 64 << 10];
+        for _ in 0..TRANSFER / chunk.len() {
+            stream.write_all(&chunk)?;
+        }
+        stream.shutdown(std::net::Shutdown::Write)?;
+        // the interface has to outlive what is still in its send buffer
+        Ok(x)
+    });
+    let mut stream = listener.accept()?;
+    let mut buf = vec![0u8; 64 << 10];
+    let mut total = 0;
+    loop {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        total += n;
+    }
+    let elapsed = start.elapsed();
+    drop(sender.join().unwrap()?);
+    assert_eq!(total, TRANSFER);
+    Ok((net.received() as usize, elapsed))
+}
+
+fn main() -> io::Result<()> {
+    report("nic pair, one at a time", PACKETS, nic_pair(1)?);
+    report("nic pair, batches of 32", PACKETS, nic_pair(32)?);
+    let (packets, elapsed) = transfer(false)?;
+    report("transfer, one at a time", packets, elapsed);
+    let (packets, elapsed) = transfer(true)?;
+    report("transfer, batched", packets, elapsed);
+    Ok(())
+}
@@ -0,0 +1,109 @@
+//! Sending a file with `send_file`, against reading it into a buffer and writing that.
+//!
+//! ```text
+//! cargo bench --bench send_file
+//! ```
+//!
+//! Both send the same file, from the page cache, between two interfaces over a perfect
+//! simulated link, with buffers big enough that the window is never what holds them up. The
+//! copy loop takes each byte from the file into its buffer and from there into the send
+//! buffer; `send_file` reads it into the send buffer directly.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use trust::{Impairments, InterfaceBuilder, SimNet, TcpConfig, TcpStream};
+
+const FILE: usize = 64 << 20;
+const BUFFER: usize = 1 << 20;
+const CHUNK: usize = 64 << 10;
+
+/// Send the file at `path` from one interface to another with `send`, returning how long it
+/// took until the peer had all of it.
+fn transfer(
+    path: &Path,
+    send: fn(&TcpStream, &mut File) -> io::Result<()>,
+) -> io::Result<Duration> {
+    let (_net, a, b) = SimNet::new(1, Impairments::default(), Impairments::default())?;
+    let (ia, ib) = (Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2));
+
+    let config = TcpConfig {
+        send_buffer: BUFFER,
+        recv_buffer: BUFFER,
+        ..Default::default()
+    };
+
+    let mut x = InterfaceBuilder::new();
+    x.config(config.clone()).add_nic(a, &[ia]);
+    let mut x = x.build()?;
+    let mut y = InterfaceBuilder::new();
+    y.config(config).add_nic(b, &[ib]);
+    let mut y = y.build()?;
+
+    let listener = y.bind(80)?;
+    let mut file = File::open(path)?;
+    let start = Instant::now();
+    let sender = thread::spawn(move || -> io::Result<_> {
+        let stream = x.connect(ia, SocketAddrV4::new(ib, 80))?;
+        send(&stream, &mut file)?;
+        stream.shutdown(std::net::Shutdown::Write)?;
+        // the interface has to outlive what is still in its send buffer
+        Ok(x)
+    });
+    let mut stream = listener.accept()?;
+    let mut buf = vec![0u8; 256 << 10];
+    let mut total = 0;
+    loop {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        total += n;
+    }
+    let elapsed = start.elapsed();
+    drop(sender.join().unwrap()?);
+    assert_eq!(total, FILE);
+    Ok(elapsed)
+}
+
+fn copy_loop(mut stream: &TcpStream, file: &mut File) -> io::Result<()> {
+    let mut buf = vec![0u8; CHUNK];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        stream.write_all(&buf[..n])?;
+    }
+}
+
+fn send_file(stream: &TcpStream, file: &mut File) -> io::Result<()> {
+    let sent = stream.send_file(file, 0, FILE)?;
+    assert_eq!(sent, FILE);
+    Ok(())
+}
+
+fn report(what: &str, elapsed: Duration) {
+    println!(
+        "{:<12} {:>6.1} MB/s, in {:>7.1?}",
+        what,
+        FILE as f64 / elapsed.as_secs_f64() / 1e6,
+        elapsed
+    );
+}
+
+fn main() -> io::Result<()> {
+    let path = std::env::temp_dir().join(format!("trust-send-file-{}", std::process::id()));
+    let contents: Vec<u8> = (0..FILE).map(|i| (i % 251) as u8).collect();
+    fs::write(&path, contents)?;
+    let copied = transfer(&path, copy_loop);
+    let sent = transfer(&path, send_file);
+    fs::remove_file(&path)?;
+    report("copy loop", copied?);
+    report("send_file", sent?);
+    Ok(())
+}
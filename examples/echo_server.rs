@@ -0,0 +1,62 @@
+//! Echoes back whatever each client sends, one thread per connection.
+//!
+//! Over the stack, on a tun device set up beforehand:
+//!
+//! ```text
+//! sudo ip tuntap add dev tun0 mode tun user $USER
+//! sudo ip addr add 192.168.0.1/24 dev tun0
+//! sudo ip link set up dev tun0
+//! cargo run --example echo_server &
+//! nc 192.168.0.2 7000
+//! ```
+//!
+//! Any address in 192.168.0.0/24 other than 192.168.0.1 reaches the stack. With `--std`, the
+//! same server runs over the operating system's TCP instead, on 127.0.0.1:7000.
+
+use std::io;
+use std::net::Shutdown;
+use std::thread;
+
+use trust::NetStream;
+
+const PORT: u16 = 7000;
+
+fn serve<S: NetStream>(mut stream: S) -> io::Result<()> {
+    let peer = stream.peer_addr()?;
+    eprintln!("{} connected", peer);
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        stream.write_all(&buf[..n])?;
+    }
+    stream.shutdown(Shutdown::Write)?;
+    eprintln!("{} done", peer);
+    Ok(())
+}
+
+fn spawn<S: NetStream + Send + 'static>(stream: S) {
+    thread::spawn(move || {
+        if let Err(e) = serve(stream) {
+            eprintln!("connection failed: {}", e);
+        }
+    });
+}
+
+fn main() -> io::Result<()> {
+    if std::env::args().any(|arg| arg == "--std") {
+        let listener = std::net::TcpListener::bind(("127.0.0.1", PORT))?;
+        for stream in listener.incoming() {
+            spawn(stream?);
+        }
+        return Ok(());
+    }
+
+    let mut interface = trust::Interface::new()?;
+    let listener = interface.bind(PORT)?;
+    loop {
+        spawn(listener.accept()?);
+    }
+}
@@ -0,0 +1,53 @@
+//! Fetches a page over HTTP/1.0 with an active open, and prints the response.
+//!
+//! Set up a tun device beforehand, and start an HTTP server on the host's end of it:
+//!
+//! ```text
+//! sudo ip tuntap add dev tun0 mode tun user $USER
+//! sudo ip addr add 192.168.0.1/24 dev tun0
+//! sudo ip link set up dev tun0
+//! python3 -m http.server 8080 --bind 192.168.0.1 &
+//! cargo run --example http_get 192.168.0.1:8080 /
+//! ```
+//!
+//! The device has to be up before the example starts: sending the SYN through a device that
+//! is down fails. The stack's own address is 192.168.0.2.
+
+use std::io;
+use std::io::prelude::*;
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+use trust::NetStream;
+
+const LOCAL: Ipv4Addr = Ipv4Addr::new(192, 168, 0, 2);
+
+fn get<S: NetStream>(mut stream: S, host: &str, path: &str) -> io::Result<Vec<u8>> {
+    write!(
+        stream,
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, host
+    )?;
+    stream.flush()?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    Ok(response)
+}
+
+fn main() -> io::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let (Some(server), path) = (args.next(), args.next()) else {
+        eprintln!("usage: http_get <ip:port> [path]");
+        std::process::exit(2);
+    };
+    let path = path.unwrap_or_else(|| "/".to_string());
+    let server: SocketAddrV4 = server
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let mut interface = trust::Interface::new()?;
+    let stream = interface.connect(LOCAL, server)?;
+    eprintln!("{} -> {}", stream.local_addr()?, stream.peer_addr()?);
+    let response = get(stream, &server.to_string(), &path)?;
+    io::stdout().write_all(&response)?;
+    Ok(())
+}
@@ -0,0 +1,63 @@
+//! Replays a capture of a TCP connection against the stack, standing in for one side of it,
+//! and reports where what the stack sends differs from what that side sent.
+//!
+//! ```text
+//! cargo run --example replay -- capture.pcap <our ip:port> <peer ip:port>
+//! cargo run --example replay -- tests/captures/linux-handshake.pcap \
+//!     192.168.0.2:40000 192.168.0.1:8080
+//! ```
+//!
+//! The capture holds both directions of the connection, as raw IP, Ethernet or Linux cooked frames.
+//! The peer's segments go into the stack over a [`SimNet`](trust::SimNet) at the times they were
+//! captured, with virtual time following the capture's timestamps, and the application on our side
+//! writes what our side sent, when it sent it, and closes when it did. There is no telling from a
+//! capture when an application read, so it reads what has arrived whenever our side's window opens
+//! up; a capture of one that was blocked reading as data came in, and so read it before the stack
+//! acknowledged it, has windows the replay cannot match, and one with timestamps has TSvals it
+//! cannot match, since no two clocks agree. The stack starts with the initial sequence number and
+//! MSS our side had, and offers SACK and timestamps if our side did, so the two can be compared
+//! field by field: segments are paired up by the sequence numbers they cover, not by when they went
+//! out, and any that only one side has are reported as well. It exits with 1 if anything differs.
+//!
+//! The captures under `tests/captures` are replayed by `tests/replay.rs`, which has to find no
+//! difference.
+
+mod replay;
+
+use std::fs;
+use std::io;
+use std::net::SocketAddrV4;
+
+use replay::replay;
+
+fn parse_addr(arg: Option<String>) -> io::Result<SocketAddrV4> {
+    arg.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "missing address"))?
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+fn main() -> io::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let Some(path) = args.next() else {
+        eprintln!("usage: replay <capture.pcap> <our ip:port> <peer ip:port>");
+        std::process::exit(2);
+    };
+    let us = parse_addr(args.next())?;
+    let peer = parse_addr(args.next())?;
+
+    let outcome = replay(&fs::read(&path)?, us, peer)?;
+    println!(
+        "{} segments from {} in the capture, {} from the stack",
+        outcome.captured.len(),
+        us,
+        outcome.sent.len()
+    );
+    for diff in &outcome.diffs {
+        println!("{}", diff);
+    }
+    if !outcome.diffs.is_empty() {
+        std::process::exit(1);
+    }
+    println!("no differences");
+    Ok(())
+}
@@ -0,0 +1,499 @@
+//! The replay itself, which the example runs on the capture it is given, and `tests/replay.rs`
+//! on the captures checked in under `tests/captures`.
+
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io;
+use std::net::SocketAddrV4;
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+use etherparse::{Ipv4HeaderSlice, TcpHeaderSlice};
+use trust::{Impairments, InterfaceBuilder, InterfaceEvent, Nic, Quad, SimNet, SimNic, TcpConfig};
+
+/// The link types of the captures this reads (see pcap-linktype(7)).
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+/// How often the connections' timers run, in virtual time.
+const TIMER_STEP: Duration = Duration::from_millis(10);
+
+/// A TCP segment from the capture, or one the stack sent.
+pub struct Segment {
+    /// when it went out, counting from the first packet of the capture
+    pub at: Duration,
+    /// the whole IP packet
+    pub packet: Vec<u8>,
+    pub from_us: bool,
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: String,
+    pub window: u16,
+    pub options: Vec<u8>,
+    pub payload: Vec<u8>,
+}
+
+impl Segment {
+    fn parse(at: Duration, packet: &[u8], us: SocketAddrV4, peer: SocketAddrV4) -> Option<Self> {
+        let iph = Ipv4HeaderSlice::from_slice(packet).ok()?;
+        if iph.protocol() != 6 {
+            return None;
+        }
+        let ip_end = (iph.total_len() as usize).min(packet.len());
+        let tcph = TcpHeaderSlice::from_slice(&packet[iph.slice().len()..ip_end]).ok()?;
+        let src = SocketAddrV4::new(iph.source_addr(), tcph.source_port());
+        let dst = SocketAddrV4::new(iph.destination_addr(), tcph.destination_port());
+        let from_us = match (src, dst) {
+            (src, dst) if src == us && dst == peer => true,
+            (src, dst) if src == peer && dst == us => false,
+            _ => return None,
+        };
+        let flags = [
+            (tcph.syn(), 'S'),
+            (tcph.fin(), 'F'),
+            (tcph.rst(), 'R'),
+            (tcph.psh(), 'P'),
+            (tcph.ack(), '.'),
+        ]
+        .iter()
+        .filter(|&&(set, _)| set)
+        .map(|&(_, c)| c)
+        .collect();
+        Some(Segment {
+            at,
+            packet: packet[..ip_end].to_vec(),
+            from_us,
+            seq: tcph.sequence_number(),
+            ack: if tcph.ack() {
+                tcph.acknowledgment_number()
+            } else {
+                0
+            },
+            flags,
+            window: tcph.window_size(),
+            options: tcph.options().to_vec(),
+            payload: packet[iph.slice().len() + tcph.slice().len()..ip_end].to_vec(),
+        })
+    }
+
+    pub fn syn(&self) -> bool {
+        self.flags.contains('S')
+    }
+
+    pub fn fin(&self) -> bool {
+        self.flags.contains('F')
+    }
+
+    /// Whether it takes up sequence space, rather than being a bare ACK (or RST).
+    pub fn sequenced(&self) -> bool {
+        !self.payload.is_empty() || self.syn() || self.fin()
+    }
+
+    /// The MSS its options announce, if they do.
+    pub fn mss(&self) -> Option<u16> {
+        options(&self.options).into_iter().find_map(|(kind, data)| {
+            (kind == 2 && data.len() == 2).then(|| u16::from_be_bytes([data[0], data[1]]))
+        })
+    }
+
+    /// It, with its sequence numbers relative to our ISS and the peer's, as tcpdump would
+    /// have them.
+    pub fn describe(&self, iss: u32, irs: Option<u32>) -> String {
+        let (ours, theirs) = if self.from_us {
+            (iss, irs)
+        } else {
+            (irs.unwrap_or(0), Some(iss))
+        };
+        let mut s = format!(
+            "{:>10.6}s [{}] seq {}",
+            self.at.as_secs_f64(),
+            self.flags,
+            self.seq.wrapping_sub(ours)
+        );
+        if self.flags.contains('.') {
+            let _ = write!(s, " ack {}", self.ack.wrapping_sub(theirs.unwrap_or(0)));
+        }
+        let _ = write!(s, " win {} len {}", self.window, self.payload.len());
+        if !self.options.is_empty() {
+            let _ = write!(s, " <{}>", describe_options(&self.options));
+        }
+        s
+    }
+}
+
+/// The options in `raw`, as kinds and their data, with padding left out.
+fn options(raw: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut found = Vec::new();
+    let mut at = 0;
+    while at < raw.len() {
+        match raw[at] {
+            0 => break,
+            1 => at += 1,
+            kind => {
+                let len = raw.get(at + 1).map_or(0, |&len| len as usize);
+                if len < 2 || at + len > raw.len() {
+                    found.push((kind, &raw[at + 1..]));
+                    break;
+                }
+                found.push((kind, &raw[at + 2..at + len]));
+                at += len;
+            }
+        }
+    }
+    found
+}
+
+fn describe_options(raw: &[u8]) -> String {
+    let named: Vec<_> = options(raw)
+        .into_iter()
+        .map(|(kind, data)| match (kind, data) {
+            (2, &[a, b]) => format!("mss {}", u16::from_be_bytes([a, b])),
+            (3, &[shift]) => format!("wscale {}", shift),
+            (4, &[]) => "sackOK".to_string(),
+            (5, _) => format!("sack {}", data.len() / 8),
+            (8, _) if data.len() == 8 => format!(
+                "TS val {} ecr {}",
+                u32::from_be_bytes(data[..4].try_into().unwrap()),
+                u32::from_be_bytes(data[4..].try_into().unwrap())
+            ),
+            (19, _) => "md5".to_string(),
+            (34, _) => format!("tfo cookie {} bytes", data.len()),
+            _ => format!("option {} ({} bytes)", kind, data.len()),
+        })
+        .collect();
+    named.join(", ")
+}
+
+/// The IPv4 packets in the pcap file `bytes`, with when each was captured, counting from the
+/// first.
+fn read_pcap(bytes: &[u8]) -> io::Result<Vec<(Duration, Vec<u8>)>> {
+    let bad = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+    if bytes.len() < 24 {
+        return Err(bad("not a pcap file"));
+    }
+    let magic = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+    let (little, nanos) = match magic {
+        0xa1b2c3d4 => (true, false),
+        0xa1b23c4d => (true, true),
+        0xd4c3b2a1 => (false, false),
+        0x4d3cb2a1 => (false, true),
+        _ => {
+            return Err(bad(
+                "not a pcap file (pcapng is not read; convert it with editcap)",
+            ))
+        }
+    };
+    let word = |at: usize| {
+        let b: [u8; 4] = bytes[at..at + 4].try_into().unwrap();
+        if little {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        }
+    };
+    let linktype = word(20) & 0xffff;
+
+    let mut packets = Vec::new();
+    let mut first = None;
+    let mut at = 24;
+    while at + 16 <= bytes.len() {
+        let secs = word(at) as u64;
+        let frac = word(at + 4) as u64;
+        let len = word(at + 8) as usize;
+        let frame = bytes
+            .get(at + 16..at + 16 + len)
+            .ok_or_else(|| bad("capture cut short"))?;
+        at += 16 + len;
+
+        let time = Duration::from_secs(secs)
+            + if nanos {
+                Duration::from_nanos(frac)
+            } else {
+                Duration::from_micros(frac)
+            };
+        let first = *first.get_or_insert(time);
+        let ipv4 = match linktype {
+            LINKTYPE_RAW | LINKTYPE_IPV4 => Some(frame),
+            LINKTYPE_ETHERNET => frame
+                .get(12..14)
+                .filter(|t| t == &[8, 0])
+                .map(|_| &frame[14..]),
+            LINKTYPE_LINUX_SLL => frame
+                .get(14..16)
+                .filter(|t| t == &[8, 0])
+                .map(|_| &frame[16..]),
+            LINKTYPE_LINUX_SLL2 => frame
+                .get(..2)
+                .filter(|t| t == &[8, 0])
+                .map(|_| &frame[20..]),
+            other => return Err(bad(&format!("link type {} is not read", other))),
+        };
+        if let Some(packet) = ipv4.filter(|p| p.first().is_some_and(|b| b >> 4 == 4)) {
+            packets.push((time.saturating_sub(first), packet.to_vec()));
+        }
+    }
+    Ok(packets)
+}
+
+/// Our side of the connection, as the stack plays it: an interface polled on this thread, on
+/// one end of a network that does nothing to packets, with the other end ours to inject the
+/// peer's segments into and collect the stack's from.
+struct Replay {
+    net: SimNet,
+    peer_nic: SimNic,
+    interface: trust::Interface,
+    us: SocketAddrV4,
+    peer: SocketAddrV4,
+    quad: Option<Quad>,
+    /// how much of our side's data the application has written
+    written: usize,
+    /// the window our side last advertised in the capture
+    window: Option<u16>,
+    closed: bool,
+    now: Duration,
+    sent: Vec<Segment>,
+}
+
+impl Replay {
+    /// Let the interface take in what has arrived, collecting what it sends, until it has
+    /// nothing more to do. Its packet loop ticks, running the connections' timers and sending
+    /// what the application has written since the last tick, whenever virtual time has moved
+    /// on 10 milliseconds since the last one.
+    fn settle(&mut self) -> io::Result<()> {
+        loop {
+            self.net.advance(Duration::ZERO);
+            let events = self.interface.poll_once(Duration::ZERO)?;
+            let mut busy = !events.is_empty();
+            for event in events {
+                match event {
+                    InterfaceEvent::NewConnection(quad) => self.quad = Some(quad),
+                    InterfaceEvent::Error(_, e, _) => eprintln!("the connection failed: {}", e),
+                    _ => {}
+                }
+            }
+            self.net.advance(Duration::ZERO);
+            let mut buf = [0; 65536];
+            loop {
+                match self.peer_nic.recv(&mut buf) {
+                    Ok(0) => {}
+                    Ok(n) => {
+                        busy = true;
+                        if let Some(segment) =
+                            Segment::parse(self.now, &buf[..n], self.us, self.peer)
+                        {
+                            self.sent.push(segment);
+                        }
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            if !busy {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Move virtual time on to `at`, running the interface's timers on the way as it would
+    /// have: a tick for each 10 milliseconds.
+    fn advance_to(&mut self, at: Duration) -> io::Result<()> {
+        while self.now < at {
+            let step = TIMER_STEP.min(at - self.now);
+            self.net.advance(step);
+            self.now += step;
+            self.settle()?;
+        }
+        Ok(())
+    }
+
+    /// Have the application do what it must have done for our side to send `segment`: read
+    /// what has arrived if the window opened up, write its data, and close after it.
+    fn act_on(&mut self, segment: &Segment, iss: u32) -> io::Result<()> {
+        let Some(quad) = self.quad else {
+            return Ok(());
+        };
+        if self.window.is_some_and(|window| segment.window > window) {
+            let mut buf = [0; 4096];
+            while matches!(self.interface.read_on(quad, &mut buf), Ok(n) if n > 0) {}
+        }
+        self.window = Some(segment.window);
+        let start = segment.seq.wrapping_sub(iss.wrapping_add(1)) as usize;
+        let end = start + segment.payload.len();
+        if end > self.written && start <= self.written {
+            let new = &segment.payload[self.written - start..];
+            let n = self.interface.write_on(quad, new)?;
+            self.written += n;
+        }
+        if segment.fin() && !self.closed {
+            self.closed = true;
+            self.interface.close_on(quad);
+        }
+        Ok(())
+    }
+}
+
+/// Pair what our side sent with what the stack sent, by the sequence numbers they cover, and
+/// bare ACKs by what they acknowledge; returns the differences, one per line.
+fn compare(captured: &[Segment], sent: &[Segment], iss: u32, irs: Option<u32>) -> Vec<String> {
+    let mut unmatched: VecDeque<&Segment> = sent.iter().collect();
+    let mut diffs = Vec::new();
+    for c in captured {
+        let same_range = |s: &&Segment| {
+            s.seq == c.seq
+                && s.payload.len() == c.payload.len()
+                && s.syn() == c.syn()
+                && s.fin() == c.fin()
+                && s.sequenced() == c.sequenced()
+        };
+        let found = unmatched
+            .iter()
+            .position(|s| same_range(s) && (c.sequenced() || s.ack == c.ack))
+            .or_else(|| unmatched.iter().position(same_range));
+        let Some(s) = found.and_then(|i| unmatched.remove(i)) else {
+            diffs.push(format!("missing: {}", c.describe(iss, irs)));
+            continue;
+        };
+        let mut fields = Vec::new();
+        if s.flags != c.flags {
+            fields.push(format!("flags [{}], captured [{}]", s.flags, c.flags));
+        }
+        if s.ack != c.ack {
+            fields.push(format!("ack {:+}", s.ack.wrapping_sub(c.ack) as i32));
+        }
+        if s.window != c.window {
+            fields.push(format!("win {}, captured {}", s.window, c.window));
+        }
+        if s.options != c.options {
+            fields.push(format!(
+                "options <{}>, captured <{}>",
+                describe_options(&s.options),
+                describe_options(&c.options)
+            ));
+        }
+        if s.payload != c.payload {
+            fields.push("different data".to_string());
+        }
+        if !fields.is_empty() {
+            diffs.push(format!(
+                "differs: {}\n    {}",
+                c.describe(iss, irs),
+                fields.join("\n    ")
+            ));
+        }
+    }
+    for s in unmatched {
+        diffs.push(format!("extra: {}", s.describe(iss, irs)));
+    }
+    diffs
+}
+
+/// What came of replaying a capture.
+pub struct Outcome {
+    /// what our side sent in the capture
+    pub captured: Vec<Segment>,
+    /// what the stack sent in its place
+    pub sent: Vec<Segment>,
+    /// our side's initial sequence number, and the peer's if the capture has its SYN
+    pub iss: u32,
+    pub irs: Option<u32>,
+    /// where the two differ, one per line
+    pub diffs: Vec<String>,
+}
+
+/// Replay the pcap file `capture` against the stack, standing in for `us` in its connection
+/// with `peer`.
+pub fn replay(capture: &[u8], us: SocketAddrV4, peer: SocketAddrV4) -> io::Result<Outcome> {
+    let segments: Vec<_> = read_pcap(capture)?
+        .into_iter()
+        .filter_map(|(at, packet)| Segment::parse(at, &packet, us, peer))
+        .collect();
+    let Some(our_syn) = segments.iter().find(|s| s.from_us && s.syn()) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("the capture has no SYN from {}", us),
+        ));
+    };
+    let active = !our_syn.flags.contains('.');
+    let iss = our_syn.seq;
+    let irs = segments
+        .iter()
+        .find(|s| !s.from_us && s.syn())
+        .map(|s| s.seq);
+
+    let (net, nic, peer_nic) = SimNet::new(0, Impairments::default(), Impairments::default())?;
+    // the stack's segments are collected without waiting for them
+    let fd = peer_nic.as_raw_fd();
+    unsafe {
+        libc::fcntl(
+            fd,
+            libc::F_SETFL,
+            libc::fcntl(fd, libc::F_GETFL) | libc::O_NONBLOCK,
+        )
+    };
+    // offer what our side offered, so that the handshakes settle the same
+    let offered = |kind| options(&our_syn.options).iter().any(|&(k, _)| k == kind);
+    let config = TcpConfig {
+        sack: offered(4),
+        timestamps: offered(8),
+        ..TcpConfig::default()
+    };
+    let mut builder = InterfaceBuilder::new();
+    builder.config(config).clock(net.clock()).fixed_iss(iss);
+    let device = builder.add_nic(nic, &[*us.ip()]);
+    if let Some(mss) = our_syn.mss() {
+        builder.set_mtu(device, mss as usize + 40);
+    }
+    let mut interface = builder.build_polled()?;
+    let listener = if active {
+        None
+    } else {
+        Some(interface.bind(us.port())?)
+    };
+    let mut replay = Replay {
+        net,
+        peer_nic,
+        interface,
+        us,
+        peer,
+        quad: None,
+        written: 0,
+        window: None,
+        closed: false,
+        now: Duration::ZERO,
+        sent: Vec::new(),
+    };
+
+    for segment in &segments {
+        replay.advance_to(segment.at)?;
+        if segment.from_us {
+            if active && segment.syn() && replay.quad.is_none() {
+                let stream = replay.interface.connect_from(us, peer)?;
+                replay.quad = Some(stream.into_quad());
+            }
+            replay.act_on(segment, iss)?;
+            // what the application did goes out on the next tick, so virtual time runs up to
+            // a tick behind the capture from here on, which changes nothing but when
+            let next_tick = replay.now + TIMER_STEP;
+            replay.advance_to(next_tick)?;
+        } else {
+            replay.peer_nic.send(&segment.packet)?;
+            replay.settle()?;
+        }
+    }
+    drop(listener);
+
+    let captured: Vec<_> = segments.into_iter().filter(|s| s.from_us).collect();
+    let diffs = compare(&captured, &replay.sent, iss, irs);
+    Ok(Outcome {
+        captured,
+        sent: replay.sent,
+        iss,
+        irs,
+        diffs,
+    })
+}
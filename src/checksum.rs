@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+use std::net::Ipv4Addr;
+
+/// How many bytes of the send buffer each cached sum covers. A power of two, so that chunks
+/// stay aligned as the sequence numbers wrap.
+const CHUNK: u32 = 512;
+
+/// The one's complement sum of `data` as 16-bit big-endian words, an odd last byte padded with
+/// a zero (RFC 1071), before folding.
+fn add(mut sum: u64, data: &[u8]) -> u64 {
+    let mut words = data.chunks_exact(2);
+    for word in &mut words {
+        sum += u16::from_be_bytes([word[0], word[1]]) as u64;
+    }
+    if let [last] = words.remainder() {
+        sum += (*last as u64) << 8;
+    }
+    sum
+}
+
+/// `sum` folded into 16 bits, carries wrapped around.
+fn fold(mut sum: u64) -> u16 {
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+/// The one's complement sum of `data`.
+fn sum(data: &[u8]) -> u16 {
+    fold(add(0, data))
+}
+
+/// The sum of bytes that follow others: a sum taken from an odd offset has its bytes in the
+/// other halves of the words, which swapping them puts right (RFC 1071 S2(B)).
+fn shifted(sum: u16, odd: bool) -> u16 {
+    if odd {
+        sum.swap_bytes()
+    } else {
+        sum
+    }
+}
+
+/// The sum of the bytes between `from` and `to` in `ring`.
+fn sum_range(ring: &VecDeque<u8>, from: usize, to: usize) -> u16 {
+    let (head, tail) = ring.as_slices();
+    let in_head = &head[from.min(head.len())..to.min(head.len())];
+    let in_tail = &tail[from.saturating_sub(head.len())..to.saturating_sub(head.len())];
+    let head_sum = sum(in_head);
+    let tail_sum = shifted(sum(in_tail), in_head.len() % 2 == 1);
+    fold(head_sum as u64 + tail_sum as u64)
+}
+
+/// The TCP checksum of a segment from `src` to `dst`, given its header, with the checksum
+/// field zeroed, and the sum of its payload.
+pub(crate) fn tcp_ipv4(
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    header: &[u8],
+    payload_len: usize,
+    payload_sum: u16,
+) -> u16 {
+    let mut total = add(0, &src.octets());
+    total = add(total, &dst.octets());
+    total += 6 + (header.len() + payload_len) as u64;
+    total = add(total, header);
+    // TCP headers come in whole words, so the payload starts at an even offset
+    total += payload_sum as u64;
+    !fold(total)
+}
+
+/// The sums of the data in a connection's send buffer, in chunks of [`CHUNK`] bytes aligned
+/// to where its data starts in the sequence space, kept as segments are first summed so that
+/// sending the data again, cut up the same way or not, only sums the ends of segments that fall
+/// inside chunks. Data in the buffer never changes once written, so neither do the sums.
+#[derive(Debug, Default)]
+pub(crate) struct SendSums {
+    /// the sequence number the first chunk starts at
+    first: u32,
+    /// the sums of the chunks from `first` on, for those summed so far
+    chunks: VecDeque<Option<u16>>,
+}
+
+impl SendSums {
+    /// The sum of the `len` bytes at sequence number `seq` in `ring`, the send buffer of a
+    /// connection whose data started at `origin` and now starts at `start`.
+    pub(crate) fn payload(
+        &mut self,
+        ring: &VecDeque<u8>,
+        origin: u32,
+        start: u32,
+        seq: u32,
+        len: usize,
+    ) -> u16 {
+        self.forget_before(origin, start);
+        let chunk = CHUNK as usize;
+        // how far into its chunk the start of the ring is
+        let lead = start.wrapping_sub(self.first) as usize;
+        let from = seq.wrapping_sub(start) as usize;
+        let to = from + len;
+        let mut total = 0;
+        let mut at = from;
+        while at < to {
+            let index = (lead + at) / chunk;
+            let chunk_end = (index + 1) * chunk - lead;
+            let piece = if (lead + at).is_multiple_of(chunk) && chunk_end <= to {
+                if self.chunks.len() <= index {
+                    self.chunks.resize(index + 1, None);
+                }
+                *self.chunks[index].get_or_insert_with(|| sum_range(ring, at, chunk_end))
+            } else {
+                sum_range(ring, at, chunk_end.min(to))
+            };
+            total += shifted(piece, (at - from) % 2 == 1) as u64;
+            at = chunk_end.min(to);
+        }
+        fold(total)
+    }
+
+    /// Let go of the sums of chunks that lie wholly before `start`, which the peer has
+    /// acknowledged; with none left, start over at the chunk `start` is in, counting from
+    /// `origin`.
+    fn forget_before(&mut self, origin: u32, start: u32) {
+        while !self.chunks.is_empty() && start.wrapping_sub(self.first) >= CHUNK {
+            self.chunks.pop_front();
+            self.first = self.first.wrapping_add(CHUNK);
+        }
+        if self.chunks.is_empty() {
+            self.first = start.wrapping_sub(start.wrapping_sub(origin) % CHUNK);
+        }
+    }
+}
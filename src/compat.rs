@@ -0,0 +1,48 @@
+use std::io;
+use std::io::prelude::*;
+use std::net::{Shutdown, SocketAddr};
+
+/// What code written against a TCP stream usually needs of one, so that it can run over
+/// either this crate's [`TcpStream`](crate::TcpStream) or the operating system's.
+///
+/// The two behave alike as far as these methods go, but for one thing: `flush` on this
+/// crate's stream waits until the peer has acknowledged everything written, where the
+/// operating system's returns right away.
+pub trait NetStream: Read + Write {
+    /// Shut down reading, writing or both halves of the connection.
+    fn shutdown(&self, how: Shutdown) -> io::Result<()>;
+
+    /// The address and port of the other end of the connection.
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Our address and port on the connection.
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+}
+
+impl NetStream for crate::TcpStream {
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        crate::TcpStream::shutdown(self, how)
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        crate::TcpStream::peer_addr(self)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        crate::TcpStream::local_addr(self)
+    }
+}
+
+impl NetStream for std::net::TcpStream {
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        std::net::TcpStream::shutdown(self, how)
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        std::net::TcpStream::peer_addr(self)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        std::net::TcpStream::local_addr(self)
+    }
+}
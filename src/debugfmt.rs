@@ -0,0 +1,352 @@
+//! Human-readable renderings of raw IPv4/TCP packets, for debugging and test failure messages.
+//!
+//! Both functions take whatever bytes they are given: a truncated or malformed packet is
+//! described as such rather than causing a panic.
+
+use std::cmp;
+use std::fmt::Write;
+use std::net::Ipv4Addr;
+
+/// A tcpdump-like one-line summary of `packet`, such as
+///
+/// ```text
+/// 10.0.0.1.443 > 10.0.0.2.51000: Flags [S.], seq 0, ack 101, win 10, options [mss 1460], length 0
+/// ```
+///
+/// Sequence numbers are absolute, and a segment carrying data shows the range it covers
+/// (`seq 1:101`), as tcpdump does.
+pub fn summarize(packet: &[u8]) -> String {
+    let Some(ip) = Ip::parse(packet) else {
+        return format!("[|ip] truncated, {} bytes", packet.len());
+    };
+    if ip.protocol != 6 {
+        return format!(
+            "{} > {}: ip-proto-{}, length {}",
+            ip.src,
+            ip.dst,
+            ip.protocol,
+            ip.payload.len()
+        );
+    }
+    let Some(tcp) = Tcp::parse(ip.payload) else {
+        return format!(
+            "{} > {}: [|tcp] truncated, {} bytes",
+            ip.src,
+            ip.dst,
+            ip.payload.len()
+        );
+    };
+
+    let mut s = format!(
+        "{}.{} > {}.{}: Flags [{}], seq {}",
+        ip.src,
+        tcp.src_port,
+        ip.dst,
+        tcp.dst_port,
+        flags(tcp.flags),
+        tcp.seq
+    );
+    if !tcp.payload.is_empty() {
+        let _ = write!(s, ":{}", tcp.seq.wrapping_add(tcp.payload.len() as u32));
+    }
+    if tcp.flags & ACK != 0 {
+        let _ = write!(s, ", ack {}", tcp.ack);
+    }
+    let _ = write!(s, ", win {}", tcp.window);
+    if tcp.flags & URG != 0 {
+        let _ = write!(s, ", urg {}", tcp.urgent);
+    }
+    if !tcp.options.is_empty() {
+        let _ = write!(s, ", options [{}]", options(tcp.options));
+    }
+    let _ = write!(s, ", length {}", tcp.payload.len());
+    s
+}
+
+/// A hexdump of `packet` with one line per IP and TCP header field, giving its offset, bytes,
+/// name and value, followed by the payload in the usual 16-bytes-a-line form.
+pub fn hexdump(packet: &[u8]) -> String {
+    let mut d = Dump {
+        s: String::new(),
+        packet,
+        at: 0,
+    };
+    let ihl = packet.first().map_or(20, |b| (b & 0xf) as usize * 4);
+    let ip_fields: [(usize, &str, Render); 10] = [
+        (1, "ip.version/ihl", &|b| {
+            format!("{} / {}", b[0] >> 4, (b[0] & 0xf) * 4)
+        }),
+        (1, "ip.tos", &|b| format!("{:#04x}", b[0])),
+        (2, "ip.total_len", &be16),
+        (2, "ip.id", &be16),
+        (2, "ip.flags/frag", &|b| {
+            let v = u16::from_be_bytes([b[0], b[1]]);
+            format!("{:#05b} / {}", v >> 13, v & 0x1fff)
+        }),
+        (1, "ip.ttl", &|b| b[0].to_string()),
+        (1, "ip.protocol", &|b| b[0].to_string()),
+        (2, "ip.checksum", &hex16),
+        (4, "ip.src", &|b| {
+            Ipv4Addr::new(b[0], b[1], b[2], b[3]).to_string()
+        }),
+        (4, "ip.dst", &|b| {
+            Ipv4Addr::new(b[0], b[1], b[2], b[3]).to_string()
+        }),
+    ];
+    if !d.fields(&ip_fields) {
+        return d.s;
+    }
+    if ihl > 20 {
+        d.field(ihl - 20, "ip.options", &|_| String::new());
+    }
+    if packet.get(9) != Some(&6) {
+        d.payload();
+        return d.s;
+    }
+
+    let doff = packet.get(d.at + 12).map_or(20, |b| (b >> 4) as usize * 4);
+    let tcp_fields: [(usize, &str, Render); 9] = [
+        (2, "tcp.src_port", &be16),
+        (2, "tcp.dst_port", &be16),
+        (4, "tcp.seq", &be32),
+        (4, "tcp.ack", &be32),
+        (1, "tcp.data_off", &|b| {
+            ((b[0] >> 4) as usize * 4).to_string()
+        }),
+        (1, "tcp.flags", &|b| format!("[{}]", flags(b[0]))),
+        (2, "tcp.window", &be16),
+        (2, "tcp.checksum", &hex16),
+        (2, "tcp.urgent", &be16),
+    ];
+    if !d.fields(&tcp_fields) {
+        return d.s;
+    }
+    if doff > 20 {
+        d.field(doff - 20, "tcp.options", &|b| format!("[{}]", options(b)));
+    }
+    d.payload();
+    d.s
+}
+
+/// Renders the bytes of a header field as its value.
+type Render<'a> = &'a dyn Fn(&[u8]) -> String;
+
+fn be16(b: &[u8]) -> String {
+    u16::from_be_bytes([b[0], b[1]]).to_string()
+}
+
+fn be32(b: &[u8]) -> String {
+    u32::from_be_bytes([b[0], b[1], b[2], b[3]]).to_string()
+}
+
+fn hex16(b: &[u8]) -> String {
+    format!("{:#06x}", u16::from_be_bytes([b[0], b[1]]))
+}
+
+/// A [`hexdump`] in progress, `at` bytes into the packet.
+struct Dump<'a> {
+    s: String,
+    packet: &'a [u8],
+    at: usize,
+}
+
+impl Dump<'_> {
+    /// Dump `fields` in turn, returning whether the packet goes on past them.
+    fn fields(&mut self, fields: &[(usize, &str, Render)]) -> bool {
+        for &(len, name, render) in fields {
+            self.field(len, name, render);
+        }
+        self.at < self.packet.len()
+    }
+
+    /// Dump the next `len` bytes as the field `name`, as far as the packet has them.
+    fn field(&mut self, len: usize, name: &str, render: Render) {
+        if self.at >= self.packet.len() {
+            return;
+        }
+        let end = cmp::min(self.at + len, self.packet.len());
+        let bytes = &self.packet[self.at..end];
+        let hex: Vec<_> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let value = if bytes.len() == len {
+            render(bytes)
+        } else {
+            "(truncated)".to_string()
+        };
+        let _ = writeln!(
+            self.s,
+            "{:04x}  {:<12} {:<16} {}",
+            self.at,
+            hex.join(" "),
+            name,
+            value
+        );
+        self.at = end;
+    }
+
+    /// The rest of the packet, 16 bytes a line, with the printable ones alongside.
+    fn payload(&mut self) {
+        let rest = &self.packet[cmp::min(self.at, self.packet.len())..];
+        if rest.is_empty() {
+            return;
+        }
+        let _ = writeln!(self.s, "payload, {} bytes:", rest.len());
+        for (i, line) in rest.chunks(16).enumerate() {
+            let hex: Vec<_> = line.iter().map(|b| format!("{:02x}", b)).collect();
+            let text: String = line
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            let _ = writeln!(
+                self.s,
+                "{:04x}  {:<47}  {}",
+                self.at + 16 * i,
+                hex.join(" "),
+                text
+            );
+        }
+    }
+}
+
+const FIN: u8 = 0x01;
+const SYN: u8 = 0x02;
+const RST: u8 = 0x04;
+const PSH: u8 = 0x08;
+const ACK: u8 = 0x10;
+const URG: u8 = 0x20;
+const ECE: u8 = 0x40;
+const CWR: u8 = 0x80;
+
+/// The flags in tcpdump's letters and order, with `.` for ACK.
+fn flags(bits: u8) -> String {
+    let letters = [
+        (FIN, 'F'),
+        (SYN, 'S'),
+        (RST, 'R'),
+        (PSH, 'P'),
+        (ACK, '.'),
+        (URG, 'U'),
+        (ECE, 'E'),
+        (CWR, 'W'),
+    ];
+    let s: String = letters
+        .iter()
+        .filter(|&&(bit, _)| bits & bit != 0)
+        .map(|&(_, c)| c)
+        .collect();
+    if s.is_empty() {
+        "none".to_string()
+    } else {
+        s
+    }
+}
+
+/// TCP options as tcpdump lists them, up to the first one that doesn't parse.
+fn options(mut raw: &[u8]) -> String {
+    let mut out = Vec::new();
+    while let Some(&kind) = raw.first() {
+        match kind {
+            0 => {
+                out.push("eol".to_string());
+                break;
+            }
+            1 => {
+                out.push("nop".to_string());
+                raw = &raw[1..];
+                continue;
+            }
+            _ => {}
+        }
+        let len = match raw.get(1) {
+            Some(&len) if len >= 2 && raw.len() >= len as usize => len as usize,
+            _ => {
+                out.push("[bad opt]".to_string());
+                break;
+            }
+        };
+        let value = &raw[2..len];
+        out.push(match (kind, value) {
+            (2, &[hi, lo]) => format!("mss {}", u16::from_be_bytes([hi, lo])),
+            (3, &[shift]) => format!("wscale {}", shift),
+            (4, &[]) => "sackOK".to_string(),
+            (8, v) if v.len() == 8 => format!(
+                "TS val {} ecr {}",
+                u32::from_be_bytes([v[0], v[1], v[2], v[3]]),
+                u32::from_be_bytes([v[4], v[5], v[6], v[7]])
+            ),
+            (19, v) if v.len() == 16 => "md5".to_string(),
+            _ => format!("opt-{}:{}", kind, len),
+        });
+        raw = &raw[len..];
+    }
+    out.join(",")
+}
+
+struct Ip<'a> {
+    protocol: u8,
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    payload: &'a [u8],
+}
+
+impl<'a> Ip<'a> {
+    fn parse(packet: &'a [u8]) -> Option<Self> {
+        if packet.len() < 20 || packet[0] >> 4 != 4 {
+            return None;
+        }
+        let ihl = (packet[0] & 0xf) as usize * 4;
+        let total = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+        // believe the total length only as far as the bytes we have go
+        let end = cmp::min(cmp::max(total, ihl), packet.len());
+        Some(Ip {
+            protocol: packet[9],
+            src: Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]),
+            dst: Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]),
+            payload: packet.get(ihl..end)?,
+        })
+    }
+}
+
+struct Tcp<'a> {
+    src_port: u16,
+    dst_port: u16,
+    seq: u32,
+    ack: u32,
+    flags: u8,
+    window: u16,
+    urgent: u16,
+    options: &'a [u8],
+    payload: &'a [u8],
+}
+
+impl<'a> Tcp<'a> {
+    fn parse(segment: &'a [u8]) -> Option<Self> {
+        if segment.len() < 20 {
+            return None;
+        }
+        let doff = (segment[12] >> 4) as usize * 4;
+        if doff < 20 {
+            return None;
+        }
+        let be16 = |i: usize| u16::from_be_bytes([segment[i], segment[i + 1]]);
+        let be32 = |i: usize| {
+            u32::from_be_bytes([segment[i], segment[i + 1], segment[i + 2], segment[i + 3]])
+        };
+        Some(Tcp {
+            src_port: be16(0),
+            dst_port: be16(2),
+            seq: be32(4),
+            ack: be32(8),
+            flags: segment[13],
+            window: be16(14),
+            urgent: be16(18),
+            options: segment.get(20..doff)?,
+            payload: &segment[doff..],
+        })
+    }
+}
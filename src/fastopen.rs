@@ -0,0 +1,117 @@
+//! TCP Fast Open (RFC 7413): data in the SYN, from clients the server has handed a cookie to on
+//! an earlier connection.
+
+use std::cmp;
+use std::net::Ipv4Addr;
+
+use crate::md5::Md5;
+
+/// The length of the cookies we hand out.
+const COOKIE_LEN: usize = 8;
+
+/// The longest cookie the option carries (RFC 7413 S4.1.1).
+const MAX_COOKIE_LEN: usize = 16;
+
+/// A Fast Open cookie, as it travels in the option. An empty one asks the server for a cookie.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Cookie {
+    len: u8,
+    bytes: [u8; MAX_COOKIE_LEN],
+}
+
+impl Cookie {
+    /// The empty cookie, which asks the server for one.
+    pub(crate) const REQUEST: Cookie = Cookie {
+        len: 0,
+        bytes: [0; MAX_COOKIE_LEN],
+    };
+
+    /// The cookie in the value of a Fast Open option, if it is one: empty, or 4 to 16 bytes
+    /// and an even number of them.
+    pub(crate) fn from_option(value: &[u8]) -> Option<Self> {
+        if !value.is_empty() && (value.len() < 4 || value.len() > MAX_COOKIE_LEN) {
+            return None;
+        }
+        if !value.len().is_multiple_of(2) {
+            return None;
+        }
+        let mut cookie = Cookie {
+            len: value.len() as u8,
+            ..Cookie::REQUEST
+        };
+        cookie.bytes[..value.len()].copy_from_slice(value);
+        Some(cookie)
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    pub(crate) fn is_request(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Hands out and checks a server's cookies, which are a keyed hash of the client's address
+/// (RFC 7413 S4.1.2): a client can only use a cookie from the address it was given to.
+pub(crate) struct CookieKey {
+    secret: [u8; 16],
+}
+
+impl CookieKey {
+    pub(crate) fn new(secret: [u8; 16]) -> Self {
+        CookieKey { secret }
+    }
+
+    /// The cookie for the client at `client`.
+    pub(crate) fn cookie(&self, client: Ipv4Addr) -> Cookie {
+        let mut md5 = Md5::new();
+        md5.update(&client.octets());
+        md5.update(&self.secret);
+        let digest = md5.finish();
+        Cookie::from_option(&digest[..COOKIE_LEN]).expect("our cookies are a valid length")
+    }
+
+    /// Whether `cookie` is the one we hand the client at `client`.
+    pub(crate) fn valid(&self, client: Ipv4Addr, cookie: &Cookie) -> bool {
+        !cookie.is_request() && *cookie == self.cookie(client)
+    }
+
+    /// What a listener that takes up to `max_early_data` bytes in a SYN makes of the client
+    /// at `client` bringing `cookie`: the right cookie gets its data taken, anything else a
+    /// (new) cookie for next time and an ordinary handshake.
+    pub(crate) fn answer(
+        &self,
+        client: Ipv4Addr,
+        cookie: &Cookie,
+        max_early_data: usize,
+    ) -> Answer {
+        if self.valid(client, cookie) {
+            Answer {
+                cookie: None,
+                early_data: max_early_data,
+            }
+        } else {
+            Answer {
+                cookie: Some(self.cookie(client)),
+                early_data: 0,
+            }
+        }
+    }
+}
+
+/// What a listener does about the Fast Open option in a SYN it accepts.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Answer {
+    /// the cookie to hand the client in our SYN-ACK
+    pub(crate) cookie: Option<Cookie>,
+    /// how much of the data in the SYN to take
+    pub(crate) early_data: usize,
+}
+
+impl Answer {
+    /// How many bytes of `data` in the SYN to take, with room for `room` of them.
+    pub(crate) fn take(&self, data: &[u8], room: usize) -> usize {
+        cmp::min(cmp::min(self.early_data, room), data.len())
+    }
+}
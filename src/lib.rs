@@ -0,0 +1,2912 @@
+//! A TCP stack in userspace, over a tun device or any other [`Nic`] that carries raw IPv4
+//! packets.
+//!
+//! An [`Interface`] runs the stack on a device of its own, or several from an
+//! [`InterfaceBuilder`]. It hands out [`TcpListener`]s and [`TcpStream`]s that work much like
+//! those in `std::net`, configured by a [`TcpConfig`], and reports on its connections through
+//! [`ConnectionInfo`], [`Drops`] and [`InterfaceEvent`]s. How the stack handles segments is its
+//! own business, and is not part of this API.
+//!
+//! ```no_run
+//! use std::io::prelude::*;
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let mut interface = trust::Interface::new()?;
+//!     let listener = interface.bind(7000)?;
+//!     let mut stream = listener.accept()?;
+//!     let mut buf = [0u8; 4096];
+//!     let n = stream.read(&mut buf)?;
+//!     stream.write_all(&buf[..n])?;
+//!     println!("{:?}", stream.info()?.state);
+//!     Ok(())
+//! }
+//! ```
+
+use std::cmp;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{IoSlice, IoSliceMut};
+use std::mem::MaybeUninit;
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, SocketAddrV4};
+use std::ops::Range;
+use std::os::unix::fs::FileExt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod checksum;
+mod clock;
+mod compat;
+pub mod debugfmt;
+mod fastopen;
+mod md5;
+mod memory;
+mod nic;
+mod poll;
+mod ratelimit;
+#[cfg(feature = "backend-raw")]
+mod raw;
+mod reassembly;
+mod scoreboard;
+mod shutdown;
+mod sim;
+mod stats;
+mod tcp;
+mod trace;
+
+pub use clock::{Clock, MockClock, SystemClock};
+pub use compat::NetStream;
+pub use memory::{MemoryPressure, RecvMemory};
+pub use nic::{DeviceId, InterfaceError, Nic, Priority};
+pub use poll::InterfaceEvent;
+pub use ratelimit::{HalfOpenLimit, RateLimit, Rejection, SourceLimit, WhenFull};
+#[cfg(feature = "backend-raw")]
+pub use raw::RawSocket;
+pub use shutdown::{ShutdownHandle, ShutdownMode};
+pub use sim::{Impairments, SimNet, SimNic};
+pub use stats::{DropReason, Drops, RateLimited, Violation, Violations};
+pub use tcp::{
+    CloseReason, ConfigError, ConfigProblem, ConnectionInfo, LossResponse, PendingAccept, Profile,
+    ReadCoalescing, ReadTrigger, State, Strictness, SynMetadata, TcpConfig,
+};
+pub use trace::{Direction, TracedPacket};
+
+/// How much of a file [`TcpStream::send_file`] reads into the send buffer at most while it holds
+/// the connection table.
+const SEND_FILE_PIECE: usize = 64 * 1024;
+
+/// First port handed out to active opens (the IANA dynamic port range).
+const EPHEMERAL_PORT_START: u16 = 49152;
+
+/// How often the packet loop runs connection timers, and the longest it waits for a packet.
+const TICK: Duration = Duration::from_millis(10);
+
+/// Which connection a segment, or an event, is for: the peer's address and port (`src`), and
+/// ours (`dst`).
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub struct Quad {
+    src: (Ipv4Addr, u16),
+    dst: (Ipv4Addr, u16),
+}
+
+impl Quad {
+    /// Our end of the connection.
+    pub fn local(&self) -> SocketAddrV4 {
+        SocketAddrV4::new(self.dst.0, self.dst.1)
+    }
+
+    /// The peer's end of the connection.
+    pub fn remote(&self) -> SocketAddrV4 {
+        SocketAddrV4::new(self.src.0, self.src.1)
+    }
+}
+
+/// What a listener is bound to: a port, either on one device or on all of them.
+type ListenKey = (Option<DeviceId>, u16);
+
+/// A passive endpoint, from the time it is bound: what it takes connections on, how it treats
+/// their SYNs, and the connections waiting to be accepted along with the threads waiting to
+/// accept them. A SYN is checked against it before anything is set up for a connection.
+#[derive(Default)]
+struct Listener {
+    /// TCP MD5 keys, by peer address
+    md5_keys: HashMap<Ipv4Addr, Vec<u8>>,
+    /// how much connections waiting in the accept queue buffer, if capped
+    accept_window: Option<usize>,
+    /// how much data a SYN with TCP Fast Open brings, if the listener takes any
+    fast_open: Option<usize>,
+    /// how fast SYNs are answered, if limited
+    syn_limit: Option<ratelimit::TokenBucket>,
+    /// how many connections one address may hold at once, if limited
+    source_limit: Option<SourceLimit>,
+    /// how many connections may be in their handshake at once
+    half_open_limit: HalfOpenLimit,
+    /// connections taken in whose handshake is still under way, oldest first, each with when
+    /// it gives up; they go on `queue` once it completes
+    half_open: VecDeque<(Quad, Option<Instant>)>,
+    /// connections that gave up on their handshake
+    half_open_expired: u64,
+    /// connections dropped in their handshake to make room for newer ones
+    half_open_recycled: u64,
+    /// what connections taken in are configured with, if not the interface's configuration
+    config: Option<TcpConfig>,
+    /// what the streams of connections taken in start out with, as set on them with the
+    /// stream's setters of the same names
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    read_trigger: ReadTrigger,
+    read_coalescing: Option<ReadCoalescing>,
+    cork: bool,
+    priority: Priority,
+    queue: VecDeque<Quad>,
+    /// threads blocked in `accept`, the one waiting longest first, by ticket, each with what
+    /// wakes it
+    waiters: VecDeque<(u64, Arc<Condvar>)>,
+    /// connections handed to a waiter, by its ticket, that it has yet to pick up
+    granted: HashMap<u64, Quad>,
+    next_ticket: u64,
+    /// the listener was closed, and takes no more connections
+    closed: bool,
+}
+
+impl Listener {
+    /// Queue the new connection on `quad`, or rather hand it straight to the thread waiting
+    /// longest to accept one, if any; that thread then needs waking, with what this returns.
+    fn push(&mut self, quad: Quad) -> Option<Arc<Condvar>> {
+        let Some((ticket, waker)) = self.waiters.pop_front() else {
+            self.queue.push_back(quad);
+            return None;
+        };
+        self.granted.insert(ticket, quad);
+        Some(waker)
+    }
+}
+
+struct ConnectionManager {
+    terminate: bool,
+    connections: HashMap<Quad, tcp::Connection>,
+    /// connections that took in packets of the batch the packet loop is processing, and have yet
+    /// to send what they owe in reply
+    received: HashSet<Quad>,
+    listeners: HashMap<ListenKey, Listener>,
+    /// hands out and checks the Fast Open cookies of our listeners
+    cookie_key: fastopen::CookieKey,
+    /// Fast Open cookies servers have handed us, by server address
+    fast_open_cookies: HashMap<Ipv4Addr, fastopen::Cookie>,
+    accept_hook: Option<AcceptHook>,
+    /// how fast connections answer segments they cannot take, if limited
+    limits: ratelimit::ReplyLimits,
+    /// how much the connections' receive buffers may hold together, if limited
+    recv_budget: memory::RecvBudget,
+    /// segments discarded, by reason, whether by a connection or before reaching one
+    drops: Drops,
+    /// packets a device failed to send, with an error the packet loop carried on after
+    send_failures: u64,
+    /// connections aborted because their sequence spaces came apart
+    desync_aborts: u64,
+    /// connections aborted because the peer's window stayed closed for too long
+    zero_window_aborts: u64,
+    /// packets handed to [`Interface::send_raw`], for the packet loop to send
+    raw_out: VecDeque<(DeviceId, Vec<u8>)>,
+    /// connections taken out of the table along with their listener, for the packet loop to
+    /// reset
+    resets: Vec<tcp::Connection>,
+    /// connections [`Interface::abort`] was asked to abort, for the packet loop to reset
+    aborts: Vec<Quad>,
+    /// connections closed within the last 2MSL that did not sit out TIME-WAIT here, by quad
+    recently_closed: HashMap<Quad, tcp::Incarnation>,
+    /// why connections that ran their course closed, while the application still holds them
+    close_reasons: HashMap<Quad, CloseReason>,
+    /// when a shutdown a [`ShutdownHandle`] asked for resets whatever is still open, once it
+    /// has started
+    shutdown_deadline: Option<Instant>,
+    iss: tcp::IssGenerator,
+    devices: Vec<nic::Device>,
+    next_port: u16,
+    config: TcpConfig,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for ConnectionManager {
+    fn default() -> Self {
+        ConnectionManager {
+            terminate: false,
+            connections: Default::default(),
+            received: Default::default(),
+            listeners: Default::default(),
+            cookie_key: fastopen::CookieKey::new(tcp::random_secret()),
+            fast_open_cookies: Default::default(),
+            accept_hook: None,
+            limits: Default::default(),
+            recv_budget: Default::default(),
+            drops: Drops::default(),
+            send_failures: 0,
+            desync_aborts: 0,
+            zero_window_aborts: 0,
+            raw_out: Default::default(),
+            resets: Default::default(),
+            aborts: Default::default(),
+            recently_closed: Default::default(),
+            close_reasons: Default::default(),
+            shutdown_deadline: None,
+            iss: tcp::IssGenerator::new(Instant::now(), TcpConfig::default().msl),
+            devices: Default::default(),
+            next_port: EPHEMERAL_PORT_START,
+            config: Default::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl ConnectionManager {
+    /// The listener a SYN for `port` arriving on `device` belongs to, if any. A listener bound to
+    /// the device takes precedence over one bound to all devices.
+    fn listener(&self, device: DeviceId, port: u16) -> Option<ListenKey> {
+        [(Some(device), port), (None, port)]
+            .into_iter()
+            .find(|key| self.listeners.get(key).is_some_and(|l| !l.closed))
+    }
+
+    /// Stop the listener on `key` taking connections, and reset those it took that nobody
+    /// accepted, right away; the packet loop tells their peers. Returns what wakes the threads
+    /// waiting in `accept`, which all fail.
+    fn close_listener(&mut self, key: ListenKey) -> Vec<Arc<Condvar>> {
+        let listener = self
+            .listeners
+            .get_mut(&key)
+            .expect("port closed while listener still active");
+        listener.closed = true;
+        let queue = std::mem::take(&mut listener.queue);
+        let half_open = std::mem::take(&mut listener.half_open);
+        let wakers = listener.waiters.iter().map(|(_, w)| w.clone()).collect();
+
+        for quad in queue
+            .into_iter()
+            .chain(half_open.into_iter().map(|(q, _)| q))
+        {
+            if let Some(c) = self.connections.remove(&quad) {
+                if let Some(incarnation) = c.incarnation() {
+                    self.recently_closed.insert(quad, incarnation);
+                }
+                self.resets.push(c);
+            }
+        }
+        wakers
+    }
+
+    /// The ISS for a new connection on `q`: clear of the sequence numbers of the last
+    /// connection on it, if that one closed recently.
+    fn iss(&self, q: &Quad) -> u32 {
+        let iss = self.iss.generate(q.dst, q.src, self.clock.now());
+        match self.recently_closed.get(q) {
+            Some(prior) => prior.iss_after(iss),
+            None => iss,
+        }
+    }
+
+    /// Take connections that are done out of the table, remembering where those that may
+    /// still have segments in the network left off.
+    fn remove_finished(&mut self) {
+        let now = self.clock.now();
+        self.recently_closed.retain(|_, prior| !prior.is_over(now));
+        let finished: Vec<_> = self
+            .connections
+            .iter()
+            .filter(|(_, c)| c.is_finished())
+            .map(|(&q, _)| q)
+            .collect();
+        for q in finished {
+            self.retire(q);
+        }
+    }
+
+    /// Take the connection on `q`, which is finished, out of the table, remembering where it
+    /// left off if it may still have segments in the network.
+    fn retire(&mut self, q: Quad) {
+        let Some(c) = self.connections.remove(&q) else {
+            return;
+        };
+        if let Some(incarnation) = c.incarnation() {
+            self.recently_closed.insert(q, incarnation);
+        }
+        if let (false, false, Some(reason)) = (c.orphaned, c.half_open, c.close_reason()) {
+            self.close_reasons.insert(q, reason);
+        }
+        if let (true, Some(key)) = (c.half_open, c.listener) {
+            // failed in its handshake, before anyone heard of it
+            if let Some(listener) = self.listeners.get_mut(&key) {
+                listener.half_open.retain(|&(held, _)| held != q);
+            }
+        }
+    }
+
+    /// Why the connection on `quad` closed, whether or not it is still in the table.
+    fn close_reason(&self, quad: Quad) -> Option<CloseReason> {
+        match self.connections.get(&quad) {
+            Some(c) => c.close_reason(),
+            None => self.close_reasons.get(&quad).copied(),
+        }
+    }
+
+    /// The application is done with the connection on `quad`: close it, and leave it to the
+    /// packet loop to remove once it is done.
+    fn release(&mut self, quad: Quad) {
+        self.close_reasons.remove(&quad);
+        if let Some(c) = self.connections.get_mut(&quad) {
+            if c.error.is_some() {
+                // the connection is already gone; nothing left to tell the peer
+                if let Some(incarnation) = c.incarnation() {
+                    self.recently_closed.insert(quad, incarnation);
+                }
+                self.connections.remove(&quad);
+            } else if c.abort_on_drop && !c.closed {
+                // the packet loop sends the RST, and removes the connection right after
+                c.orphaned = true;
+                self.aborts.push(quad);
+            } else {
+                // the packet loop sends our FIN and removes the connection once it is done
+                c.orphaned = true;
+                c.close();
+            }
+        }
+    }
+
+    /// Whether the listener `key` may answer another SYN now, taking it out of its limit.
+    fn syn_allowed(&mut self, key: ListenKey) -> bool {
+        let now = self.clock.now();
+        let allowed = self
+            .listeners
+            .get_mut(&key)
+            .and_then(|l| l.syn_limit.as_mut())
+            .is_none_or(|bucket| bucket.take(now));
+        if !allowed {
+            self.limits.rate_limited.syn_acks += 1;
+        }
+        allowed
+    }
+
+    /// How many connections each address holds against the listener on `key`, counting those
+    /// in their handshake and not those done with.
+    fn sources(&self, key: ListenKey) -> HashMap<Ipv4Addr, usize> {
+        let mut sources = HashMap::new();
+        for (q, c) in &self.connections {
+            if c.listener == Some(key) && !c.is_done() {
+                *sources.entry(q.src.0).or_default() += 1;
+            }
+        }
+        sources
+    }
+
+    /// Whether the listener on `key` takes one more connection from `src`.
+    fn source_allowed(&self, key: ListenKey, src: Ipv4Addr) -> bool {
+        let Some(limit) = self.listeners[&key].source_limit else {
+            return true;
+        };
+        // counted as top_sources counts them, so that what it shows is what the limit sees
+        let held = self.sources(key).get(&src).copied().unwrap_or(0);
+        held < limit.connections
+    }
+
+    /// Whether the listener on `key` may start another handshake, making room for it if its
+    /// limit says to.
+    fn half_open_room(&mut self, key: ListenKey) -> bool {
+        let listener = self.listeners.get_mut(&key).unwrap();
+        let limit = listener.half_open_limit;
+        if listener.half_open.len() < limit.connections {
+            return true;
+        }
+        if limit.when_full == WhenFull::Drop || limit.connections == 0 {
+            return false;
+        }
+        // the limit may have been lowered since the oldest came in
+        while listener.half_open.len() >= limit.connections {
+            let (oldest, _) = listener.half_open.pop_front().unwrap();
+            self.connections.remove(&oldest);
+            listener.half_open_recycled += 1;
+        }
+        true
+    }
+
+    /// Hand the connection on `q`, just taken in by the listener on `key`, to the application,
+    /// or rather have the listener hold on to it until its handshake completes; one whose SYN
+    /// brought data with Fast Open goes right away, as there is something to read. Returns what
+    /// wakes the thread it is handed to, if any.
+    fn admit(&mut self, key: ListenKey, q: Quad) -> Option<Arc<Condvar>> {
+        let c = self.connections.get_mut(&q).unwrap();
+        let listener = self.listeners.get_mut(&key).unwrap();
+        if c.info().fast_open {
+            return listener.push(q);
+        }
+        c.half_open = true;
+        listener.half_open.push_back((q, c.handshake_deadline()));
+        None
+    }
+
+    /// The connection on `q` has completed its handshake: the listener that held on to it
+    /// hands it to the application. Returns what wakes the thread it is handed to, if any.
+    fn complete_handshake(&mut self, q: Quad) -> Option<Arc<Condvar>> {
+        let c = self.connections.get_mut(&q)?;
+        let listener = self.listeners.get_mut(&c.listener?)?;
+        c.half_open = false;
+        listener.half_open.retain(|&(held, _)| held != q);
+        listener.push(q)
+    }
+
+    /// Drop the connections whose handshake has run out of time, without a word to their
+    /// peers, which have given up by then or soon will.
+    fn expire_half_open(&mut self) {
+        let now = self.clock.now();
+        for listener in self.listeners.values_mut() {
+            listener.half_open.retain(|&(q, deadline)| {
+                if deadline.is_none_or(|deadline| now < deadline) {
+                    return true;
+                }
+                self.connections.remove(&q);
+                listener.half_open_expired += 1;
+                false
+            });
+        }
+    }
+
+    /// The device that owns our address `local`, or the only one there is.
+    fn device_for(&self, local: Ipv4Addr) -> io::Result<DeviceId> {
+        self.devices
+            .iter()
+            .position(|d| d.addrs.contains(&local))
+            .or(if self.devices.len() == 1 {
+                Some(0)
+            } else {
+                None
+            })
+            .map(DeviceId)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::AddrNotAvailable,
+                    "no device has the local address",
+                )
+            })
+    }
+}
+
+/// Takes the packets TCP has no use for; see [`Interface::set_raw_handler`].
+type RawHandler = Box<dyn FnMut(&[u8]) + Send>;
+
+/// Looks over connections before their SYN-ACK goes out; see [`Interface::set_accept_hook`].
+type AcceptHook = Box<dyn FnMut(&mut PendingAccept) -> bool + Send>;
+
+#[derive(Default)]
+struct Shared {
+    manager: Mutex<ConnectionManager>,
+    rcv_var: Condvar,
+    snd_var: Condvar,
+    raw_handler: Mutex<Option<RawHandler>>,
+    shutdown: Arc<shutdown::Requests>,
+}
+
+type InterfaceHandle = Arc<Shared>;
+
+/// A TCP stack running on a set of devices.
+///
+/// The packet loop runs on a thread of its own, unless the interface is built with
+/// [`InterfaceBuilder::build_polled`], and all of a connection's state sits behind one lock
+/// shared with it. So streams, their halves and listeners can be moved to and shared between
+/// threads freely, and their blocking calls only wait on the loop, never on each other. The
+/// interface itself can be moved to another thread, polled interfaces included, which is why
+/// devices have to be [`Send`].
+pub struct Interface {
+    ih: Option<InterfaceHandle>,
+    /// the thread running the packet loop, unless the interface is polled
+    jh: Option<thread::JoinHandle<io::Result<()>>>,
+    /// what [`Interface::poll_once`] runs the packet loop with, if the interface is polled
+    polled: Option<poll::Polled>,
+}
+
+impl Drop for Interface {
+    fn drop(&mut self) {
+        stop(self.ih.as_ref().unwrap());
+        drop(self.ih.take());
+        if let Some(jh) = self.jh.take() {
+            // however the packet loop ended, a device failing or a panic, the connections
+            // failed over it already, and the application had `run` to ask why; dropping the
+            // interface must not panic over it
+            let _ = jh.join();
+        }
+    }
+}
+
+/// Stop the packet loop for good: nothing will be sent or received on any connection again, so
+/// fail whoever waits on one, or in `accept` on a listener.
+///
+/// This also runs as a packet loop that panicked unwinds, so a lock it poisoned is taken as is,
+/// and cleared: the connections are all failed by then, so what they were in the middle of no
+/// longer matters.
+fn stop(ih: &InterfaceHandle) {
+    let mut cm = ih.manager.lock().unwrap_or_else(PoisonError::into_inner);
+    cm.terminate = true;
+    for c in cm.connections.values_mut() {
+        c.shut_down();
+    }
+    let open: Vec<_> = cm
+        .listeners
+        .iter()
+        .filter(|(_, listener)| !listener.closed)
+        .map(|(&key, _)| key)
+        .collect();
+    let wakers: Vec<_> = open
+        .into_iter()
+        .flat_map(|key| cm.close_listener(key))
+        .collect();
+    drop(cm);
+    ih.manager.clear_poison();
+    ih.rcv_var.notify_all();
+    ih.snd_var.notify_all();
+    for waker in wakers {
+        waker.notify_one();
+    }
+}
+
+/// Stops the interface however the packet loop on its thread ends: a device failing, an error
+/// from somewhere else, or a panic, as well as a shutdown or the interface being dropped, for
+/// which stopping again changes nothing. Without it, whoever waits on the interface would wait
+/// forever.
+struct StopOnExit<'a>(&'a InterfaceHandle);
+
+impl Drop for StopOnExit<'_> {
+    fn drop(&mut self) {
+        stop(self.0);
+    }
+}
+
+fn packet_loop(nics: Vec<Box<dyn Nic>>, ih: InterfaceHandle) -> io::Result<()> {
+    let _stop = StopOnExit(&ih);
+    let mut driver = Driver::new(nics, &ih);
+    while driver.round(&ih, None)? {}
+    Ok(())
+}
+
+/// What the packet loop runs on: the interface's devices, with their queues, and the buffers
+/// it receives into. An interface built with [`InterfaceBuilder::build_polled`] keeps it for
+/// [`Interface::poll_once`] to run rounds of the loop on the caller's thread instead.
+struct Driver {
+    nics: Vec<nic::Outbound>,
+    pfds: Vec<libc::pollfd>,
+    pool: nic::RecvPool,
+    /// the interface's clock, which says when a tick is due
+    clock: Arc<dyn Clock>,
+    next_tick: Instant,
+}
+
+impl Driver {
+    fn new(nics: Vec<Box<dyn Nic>>, ih: &InterfaceHandle) -> Self {
+        let pfds = nics
+            .iter()
+            .map(|nic| libc::pollfd {
+                fd: nic.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+        let (local, clock): (Vec<_>, _) = {
+            let cm = ih.manager.lock().unwrap();
+            let local = cm.devices.iter().flat_map(|d| d.addrs.clone()).collect();
+            (local, cm.clock.clone())
+        };
+        let nics = nics
+            .into_iter()
+            .enumerate()
+            .map(|(i, nic)| nic::Outbound::new(nic, DeviceId(i), local.clone()))
+            .collect();
+        Driver {
+            nics,
+            pfds,
+            // big enough for the largest IPv4 packet, whatever MTU a device has
+            pool: nic::RecvPool::new(u16::MAX as usize),
+            next_tick: clock.now() + TICK,
+            clock,
+        }
+    }
+
+    /// Run one round of the packet loop: wait up to `timeout` (or indefinitely), but not past
+    /// the next tick, for a device to have packets; take in a batch from each one that does;
+    /// run the connections' timers if a tick is due; and send what all of that produced.
+    /// Returns false once the interface is being dropped, or has shut down.
+    ///
+    /// A device failing for good, which is all that fails a round, stops the interface.
+    fn round(&mut self, ih: &InterfaceHandle, timeout: Option<Duration>) -> io::Result<bool> {
+        let result = self.run_round(ih, timeout);
+        let failed: u64 = self.nics.iter_mut().map(nic::Outbound::take_failed).sum();
+        if failed > 0 {
+            ih.manager
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .send_failures += failed;
+        }
+        if result.is_err() {
+            stop(ih);
+        }
+        result
+    }
+
+    fn run_round(&mut self, ih: &InterfaceHandle, timeout: Option<Duration>) -> io::Result<bool> {
+        let Driver {
+            nics,
+            pfds,
+            pool,
+            clock,
+            next_tick,
+        } = self;
+        for (pfd, nic) in pfds.iter_mut().zip(&*nics) {
+            pfd.events = if nic.wants_write() {
+                libc::POLLIN | libc::POLLOUT
+            } else {
+                libc::POLLIN
+            };
+        }
+        // wait for a packet to arrive, but not past the next tick, so timers keep running; a
+        // clock that stands still until it is moved leaves this at a whole tick, which only
+        // bounds how long we wait, while the tick itself waits for the clock
+        let until_tick = next_tick.saturating_duration_since(clock.now());
+        let timeout = timeout.map_or(until_tick, |timeout| cmp::min(timeout, until_tick));
+        let n = unsafe {
+            libc::poll(
+                pfds.as_mut_ptr(),
+                pfds.len() as libc::nfds_t,
+                timeout.as_micros().div_ceil(1000) as libc::c_int,
+            )
+        };
+        if n < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                return Ok(true);
+            }
+            return Err(e);
+        }
+        // tick however busy the devices keep us: connections only send what the application
+        // wrote, or their SYN, on a tick
+        let now = clock.now();
+        if now >= *next_tick {
+            *next_tick = now + TICK;
+            let mut cmg = ih.manager.lock().unwrap();
+            if cmg.terminate {
+                return Ok(false);
+            }
+            // not every device can tell us when it has room again, so try every tick
+            for nic in nics.iter_mut().filter(|nic| nic.wants_write()) {
+                nic.on_writable()?;
+            }
+            let cm = &mut *cmg;
+            let aborts = cm.desync_aborts + cm.zero_window_aborts;
+            let mut coalesced = false;
+            for connection in cm.connections.values_mut() {
+                let nic = &mut nics[connection.device.0];
+                // before the timers act on sequence spaces that have come apart; whatever the
+                // tick itself puts out of joint, the next one finds
+                if connection.check_sync(nic)? {
+                    cm.desync_aborts += 1;
+                    cm.drops.record(DropReason::Desync);
+                    continue;
+                }
+                connection.on_tick(nic)?;
+                coalesced |= connection.check_read_coalescing();
+                if connection.take_zero_window_abort() {
+                    cm.zero_window_aborts += 1;
+                }
+            }
+            let aborted = cm.desync_aborts + cm.zero_window_aborts != aborts;
+            let (shut_down, wakers) = cm.drive_shutdown(&ih.shutdown, nics)?;
+            cm.expire_half_open();
+            cm.remove_finished();
+            cm.balance_recv_memory();
+            drop(cmg);
+            ih.snd_var.notify_all();
+            if aborted || coalesced || shut_down {
+                ih.rcv_var.notify_all();
+            }
+            for waker in wakers {
+                waker.notify_one();
+            }
+        }
+
+        for (i, pfd) in pfds.iter().enumerate() {
+            if pfd.revents & libc::POLLOUT != 0 {
+                nics[i].on_writable()?;
+                let mut cmg = ih.manager.lock().unwrap();
+                let cm = &mut *cmg;
+                let desync_aborts = cm.desync_aborts;
+                for connection in cm.connections.values_mut() {
+                    if connection.device == DeviceId(i) {
+                        connection.on_writable(&mut nics[i])?;
+                        if connection.check_sync(&mut nics[i])? {
+                            cm.desync_aborts += 1;
+                            cm.drops.record(DropReason::Desync);
+                        }
+                    }
+                }
+                if cm.desync_aborts != desync_aborts {
+                    drop(cmg);
+                    ih.rcv_var.notify_all();
+                    ih.snd_var.notify_all();
+                }
+            }
+            if pfd.revents & libc::POLLNVAL != 0 {
+                return Err(InterfaceError::wrap(
+                    DeviceId(i),
+                    io::Error::from_raw_os_error(libc::EBADF),
+                ));
+            }
+            if pfd.revents & libc::POLLIN == 0 {
+                continue;
+            }
+            match nics[i].recv_batch(pool) {
+                Ok(_) => {}
+                Err(e) if InterfaceError::is_fatal(&e) => {
+                    return Err(InterfaceError::wrap(DeviceId(i), e));
+                }
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+                    ) =>
+                {
+                    continue
+                }
+                Err(_) => {
+                    // one packet's worth of trouble
+                    ih.manager
+                        .lock()
+                        .unwrap()
+                        .drops
+                        .record(DropReason::RecvFailed);
+                    continue;
+                }
+            }
+            for packet in pool.packets() {
+                receive(ih, &mut nics[i], DeviceId(i), packet)?;
+            }
+        }
+        flush_received(ih, nics)?;
+        send_raw(ih, nics)?;
+        send_resets(ih, nics)?;
+        send_aborts(ih, nics)?;
+        loop_back(ih, nics)?;
+        flush(nics)?;
+        Ok(true)
+    }
+}
+
+/// Hand each device what was queued for it this round.
+fn flush(nics: &mut [nic::Outbound]) -> io::Result<()> {
+    for nic in nics {
+        nic.flush()?;
+    }
+    Ok(())
+}
+
+/// Process the packets connections sent to our own addresses, and whatever they send in reply,
+/// as if each had arrived on the device that has its destination address (or the one it was
+/// sent through, if none has).
+fn loop_back(ih: &InterfaceHandle, nics: &mut [nic::Outbound]) -> io::Result<()> {
+    loop {
+        let mut any = false;
+        while let Some((from, packet)) = nics
+            .iter_mut()
+            .enumerate()
+            .find_map(|(i, nic)| Some((i, nic.take_looped()?)))
+        {
+            let to = {
+                let cm = ih.manager.lock().unwrap();
+                let dst = nic::destination(&packet);
+                cm.devices
+                    .iter()
+                    .position(|d| dst.is_some_and(|dst| d.addrs.contains(&dst)))
+                    .unwrap_or(from)
+            };
+            receive(ih, &mut nics[to], DeviceId(to), &packet)?;
+            any = true;
+        }
+        if !any {
+            return Ok(());
+        }
+        // the replies may be looped back in turn
+        flush_received(ih, nics)?;
+    }
+}
+
+/// Send what the connections that took in the last batch of packets have to send: new data
+/// their ACKs made room for, and one ACK for all the data they received, unless a data
+/// segment carries it. Then wake whoever waits on them.
+fn flush_received(ih: &InterfaceHandle, nics: &mut [nic::Outbound]) -> io::Result<()> {
+    let mut cmg = ih.manager.lock().unwrap();
+    let cm = &mut *cmg;
+    if cm.received.is_empty() {
+        return Ok(());
+    }
+    let mut wake_readers = false;
+    for q in cm.received.drain() {
+        let Some(c) = cm.connections.get_mut(&q) else {
+            continue;
+        };
+        let nic = &mut nics[c.device.0];
+        c.flush_received(nic)?;
+        if c.check_sync(nic)? {
+            cm.desync_aborts += 1;
+            cm.drops.record(DropReason::Desync);
+        }
+        wake_readers |= c.wakes_readers();
+    }
+    drop(cmg);
+    if wake_readers {
+        ih.rcv_var.notify_all();
+    }
+    ih.snd_var.notify_all();
+    Ok(())
+}
+
+/// Send the packets handed to [`Interface::send_raw`] since the last round.
+fn send_raw(ih: &InterfaceHandle, nics: &mut [nic::Outbound]) -> io::Result<()> {
+    let raw = std::mem::take(&mut ih.manager.lock().unwrap().raw_out);
+    for (device, packet) in raw {
+        nics[device.0].send(packet, Priority::Normal, false, None)?;
+    }
+    Ok(())
+}
+
+/// Reset the connections dropped listeners left unaccepted since the last round.
+fn send_resets(ih: &InterfaceHandle, nics: &mut [nic::Outbound]) -> io::Result<()> {
+    let resets = std::mem::take(&mut ih.manager.lock().unwrap().resets);
+    for mut c in resets {
+        c.reset(&mut nics[c.device.0])?;
+    }
+    Ok(())
+}
+
+/// Abort the connections the application asked [`Interface::abort`] to since the last round,
+/// and wake whoever waits on them.
+fn send_aborts(ih: &InterfaceHandle, nics: &mut [nic::Outbound]) -> io::Result<()> {
+    let mut cmg = ih.manager.lock().unwrap();
+    if cmg.aborts.is_empty() {
+        return Ok(());
+    }
+    let cm = &mut *cmg;
+    for q in std::mem::take(&mut cm.aborts) {
+        let Some(c) = cm.connections.get_mut(&q) else {
+            continue;
+        };
+        for listener in cm.listeners.values_mut() {
+            if let Some(at) = listener.queue.iter().position(|&queued| queued == q) {
+                // nobody accepted it, so nobody is left to hear of it: it goes once it is done
+                listener.queue.remove(at);
+                c.orphaned = true;
+            }
+        }
+        c.abort_by_application(&mut nics[c.device.0])?;
+    }
+    // those the application let go of are done with, and their quads free for new ones
+    cm.remove_finished();
+    drop(cmg);
+    ih.rcv_var.notify_all();
+    ih.snd_var.notify_all();
+    Ok(())
+}
+
+/// Process a packet received on `device`: TCP gets it first, and the raw handler, if there is
+/// one, whatever TCP has no use for.
+fn receive(
+    ih: &InterfaceHandle,
+    nic: &mut nic::Outbound,
+    device: DeviceId,
+    buf: &[u8],
+) -> io::Result<()> {
+    if buf.is_empty() {
+        // the device had nothing for us after all
+        return Ok(());
+    }
+    if !on_packet(ih, nic, device, buf)? {
+        if let Some(handler) = ih.raw_handler.lock().unwrap().as_mut() {
+            handler(buf);
+        }
+    }
+    Ok(())
+}
+
+/// Demultiplex a packet received on `device` to the connection (or listener) it is for,
+/// returning whether it was TCP for us at all: anything else (another protocol, another
+/// address, a port nobody listens on) is left to the raw handler.
+fn on_packet(
+    ih: &InterfaceHandle,
+    nic: &mut nic::Outbound,
+    device: DeviceId,
+    buf: &[u8],
+) -> io::Result<bool> {
+    match etherparse::Ipv4HeaderSlice::from_slice(buf) {
+        Ok(iph) => {
+            let total_len = iph.total_len() as usize;
+            if total_len > buf.len() {
+                // cut short on the way: whatever the headers say, part of the packet is missing
+                ih.manager
+                    .lock()
+                    .unwrap()
+                    .drops
+                    .record(DropReason::Truncated);
+                return Ok(false);
+            }
+            // anything past the total length is padding (as a short Ethernet frame has), not
+            // data
+            let buf = &buf[..total_len];
+            let src = iph.source_addr();
+            let dst = iph.destination_addr();
+            if iph.protocol() != 0x06 {
+                // not tcp
+                ih.manager.lock().unwrap().drops.record(DropReason::NotTcp);
+                return Ok(false);
+            }
+
+            match etherparse::TcpHeaderSlice::from_slice(&buf[iph.slice().len()..]) {
+                Ok(tcph) => {
+                    use std::collections::hash_map::Entry;
+                    let datai = iph.slice().len() + tcph.slice().len();
+                    let mut cmg = ih.manager.lock().unwrap();
+                    let cm = &mut *cmg;
+                    if !cm.devices[device.0].owns(dst) {
+                        // not for us
+                        cm.drops.record(DropReason::NotOurs);
+                        return Ok(false);
+                    }
+                    if tcph.calc_checksum_ipv4(&iph, &buf[datai..]).ok() != Some(tcph.checksum()) {
+                        cm.drops.record(DropReason::BadChecksum);
+                        return Ok(true);
+                    }
+                    let mtu = cm.devices[device.0].mtu;
+                    let q = Quad {
+                        src: (src, tcph.source_port()),
+                        dst: (dst, tcph.destination_port()),
+                    };
+                    let listener = cm.listener(device, tcph.destination_port());
+
+                    // the connection, or else the listener that would accept it, decides whether
+                    // segments have to be signed
+                    let md5_key = match cm.connections.get(&q) {
+                        Some(c) if !c.is_expired() => c.md5_key.as_deref(),
+                        _ => listener
+                            .and_then(|key| cm.listeners[&key].md5_keys.get(&src))
+                            .map(Vec::as_slice),
+                    };
+                    if !tcp::md5_valid(&iph, &tcph, &buf[datai..], md5_key) {
+                        cm.drops.record(DropReason::BadMd5);
+                        return Ok(true);
+                    }
+
+                    let accept = |cm: &mut ConnectionManager, nic: &mut nic::Outbound| {
+                        let clock = cm.clock.clone();
+                        match listener {
+                            Some(key) => {
+                                if tcph.syn() && !cm.syn_allowed(key) {
+                                    cm.drops.record(DropReason::RateLimited);
+                                    return Ok(None);
+                                }
+                                // a SYN turned away without a reply costs no connection at all;
+                                // one turned away with an RST only the one the RST comes from
+                                let refused = !cm.source_allowed(key, src);
+                                if refused {
+                                    cm.drops.record(DropReason::SourceLimit);
+                                    let over = cm.listeners[&key].source_limit.unwrap().over;
+                                    if over == Rejection::Drop
+                                        || !cm.limits.allow_rst(cm.clock.now())
+                                    {
+                                        return Ok(None);
+                                    }
+                                }
+                                let iss = cm.iss(&q);
+                                let own = cm.listeners[&key].config.as_ref();
+                                let config = own.unwrap_or(&cm.config).clone();
+                                let fast_open = cm.listeners[&key].fast_open.and_then(|max| {
+                                    let cookie = tcp::fast_open_cookie(&tcph)?;
+                                    Some(cm.cookie_key.answer(src, &cookie, max))
+                                });
+                                let pending = tcp::Connection::prepare_accept(
+                                    &cm.listeners[&key],
+                                    iph.clone(),
+                                    tcph.clone(),
+                                    &buf[datai..],
+                                    config,
+                                    mtu,
+                                    clock,
+                                    iss,
+                                    fast_open,
+                                );
+                                let Some(mut pending) = pending else {
+                                    cm.drops.record(DropReason::NoConnection);
+                                    return Ok(None);
+                                };
+                                let ts_clock = cm.iss.ts_clock(q.dst, q.src);
+                                pending.connection_mut().set_ts_clock(ts_clock);
+                                if refused {
+                                    pending.refuse(nic)?;
+                                    return Ok(None);
+                                }
+                                if let Some(hook) = &mut cm.accept_hook {
+                                    if !hook(&mut pending) {
+                                        cm.drops.record(DropReason::Refused);
+                                        return Ok(None);
+                                    }
+                                }
+                                // one that goes to the application right away takes no room
+                                // among the handshakes under way
+                                if !pending.info().fast_open && !cm.half_open_room(key) {
+                                    cm.drops.record(DropReason::HalfOpenLimit);
+                                    return Ok(None);
+                                }
+                                cm.admit_recv_memory(pending.connection_mut());
+                                let mut c = pending.commit(nic)?;
+                                c.listener = Some(key);
+                                Ok(Some((c, key)))
+                            }
+                            None => {
+                                cm.drops.record(DropReason::NoListener);
+                                // tell the peer nobody is there, rather than have it try
+                                // again until it gives up; the raw handler still sees the
+                                // segment
+                                if !tcph.rst() && cm.limits.allow_rst(cm.clock.now()) {
+                                    tcp::reset_unknown(nic, &iph, &tcph, buf.len() - datai)?;
+                                }
+                                Ok::<_, io::Error>(None)
+                            }
+                        }
+                    };
+                    // a connection the listener accepts goes in under this quad, and the lock is let
+                    // go before accept() is woken to take it
+                    let open = |mut cmg: MutexGuard<'_, ConnectionManager>,
+                                nic: &mut nic::Outbound| {
+                        let cm = &mut *cmg;
+                        let Some((mut c, key)) = accept(cm, nic)? else {
+                            return Ok(false);
+                        };
+                        c.device = device;
+                        cm.connections.insert(q, c);
+                        let waker = cm.admit(key, q);
+                        drop(cmg);
+                        if let Some(waker) = waker {
+                            waker.notify_one();
+                        }
+                        Ok(true)
+                    };
+                    let live = cm.connections.get(&q).is_some_and(|c| !c.is_expired());
+                    if !live
+                        && tcph.syn()
+                        && cm
+                            .recently_closed
+                            .get(&q)
+                            .is_some_and(|prior| !prior.admits_syn(&tcph))
+                    {
+                        cm.drops.record(DropReason::OldIncarnation);
+                        return Ok(true);
+                    }
+                    match cm.connections.entry(q) {
+                        Entry::Occupied(mut c) if !c.get().is_expired() => {
+                            let c = c.get_mut();
+                            // replies go out the way the connection's first packet came in
+                            let nic = if c.device == device {
+                                nic
+                            } else {
+                                cm.drops.record(DropReason::WrongDevice);
+                                return Ok(true);
+                            };
+                            let available = c.on_packet(
+                                nic,
+                                &mut cm.limits,
+                                iph.clone(),
+                                tcph.clone(),
+                                &buf[datai..],
+                            )?;
+                            if available.reopen {
+                                // TIME-WAIT made way for a new connection, which the listener
+                                // takes the SYN for, its ISS clear of the old one's
+                                cm.retire(q);
+                                return open(cmg, nic);
+                            }
+                            let completed = c.half_open && c.is_synchronized();
+                            if let Some(reason) = available.dropped {
+                                cm.drops.record(reason);
+                            }
+                            if let Some(cookie) = available.fast_open_cookie {
+                                cm.fast_open_cookies.insert(src, cookie);
+                            }
+                            if available.remove {
+                                cm.retire(q);
+                            } else {
+                                // what it sends in reply waits for the rest of the batch, but
+                                // readers can get going on the data right away
+                                cm.received.insert(q);
+                            }
+                            let waker = if completed {
+                                cm.complete_handshake(q)
+                            } else {
+                                None
+                            };
+                            drop(cmg);
+                            if let Some(waker) = waker {
+                                waker.notify_one();
+                            }
+                            if available.readable || available.closed {
+                                ih.rcv_var.notify_all();
+                            }
+                            if available.writable || available.closed {
+                                ih.snd_var.notify_all();
+                            }
+                        }
+                        Entry::Occupied(c) => {
+                            // TIME-WAIT is over, so this quad is free for a new connection
+                            c.remove();
+                            return open(cmg, nic);
+                        }
+                        Entry::Vacant(_) => return open(cmg, nic),
+                    }
+                }
+                Err(e) => {
+                    // counted rather than logged: a device can hand us any amount of garbage
+                    ih.manager.lock().unwrap().drops.record(parse_error(&e));
+                }
+            }
+        }
+        Err(e) => {
+            ih.manager.lock().unwrap().drops.record(match e {
+                etherparse::ReadError::Ipv4UnexpectedVersion(_) => DropReason::NotIpv4,
+                e => parse_error(&e),
+            });
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Why a packet whose IPv4 or TCP header would not parse is dropped.
+fn parse_error(e: &etherparse::ReadError) -> DropReason {
+    match e {
+        etherparse::ReadError::UnexpectedEndOfSlice(_) => DropReason::Truncated,
+        etherparse::ReadError::TcpDataOffsetTooSmall(_) => DropReason::BadTcpHeader,
+        _ => DropReason::BadIpHeader,
+    }
+}
+
+fn check_mtu(mtu: usize) -> io::Result<usize> {
+    if mtu < nic::MIN_MTU {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "MTU is below the minimum IPv4 allows",
+        ));
+    }
+    Ok(mtu)
+}
+
+/// Sets up an [`Interface`] over one or more devices.
+///
+/// All devices share one connection table, so connections are found by their addresses and
+/// ports whichever device their packets arrive on, but each connection only ever sends through
+/// the device its first packet went through.
+#[derive(Default)]
+pub struct InterfaceBuilder {
+    nics: Vec<Box<dyn Nic>>,
+    devices: Vec<nic::Device>,
+    /// MTUs set with [`InterfaceBuilder::set_mtu`], over what the devices report
+    mtus: Vec<Option<usize>>,
+    config: TcpConfig,
+    clock: Option<Arc<dyn Clock>>,
+    rst_limit: Option<RateLimit>,
+    challenge_ack_limit: Option<RateLimit>,
+    recv_memory_limit: Option<usize>,
+    fixed_iss: Option<u32>,
+}
+
+impl InterfaceBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Configure the connections made through the interface. Building the interface fails with
+    /// a [`ConfigError`] if `config` does not pass [`TcpConfig::validate`].
+    pub fn config(&mut self, config: TcpConfig) -> &mut Self {
+        self.config = config;
+        self
+    }
+
+    /// Run all connection timers off `clock` rather than the system clock.
+    ///
+    /// The packet loop ticks, running the timers and sending what the application has written
+    /// since the last tick, whenever `clock` has moved on a tick's worth (10 milliseconds) since
+    /// the last one. With a [`MockClock`], such as [`SimNet::clock`], that happens only as it
+    /// is advanced, however long the loop waits in real time.
+    pub fn clock<C: Clock + 'static>(&mut self, clock: C) -> &mut Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// Send no more than `limit` RSTs in reply to segments that connections cannot take, such
+    /// as a SYN-ACK to a connection still in SYN-SENT that does not acknowledge our SYN, or a
+    /// SYN for a port nobody listens on. RSTs beyond it are not sent; aborting a connection
+    /// always sends one.
+    pub fn rst_limit(&mut self, limit: RateLimit) -> &mut Self {
+        self.rst_limit = Some(limit);
+        self
+    }
+
+    /// Send no more than `limit` ACKs in reply to segments outside a connection's window
+    /// (challenge ACKs, in RFC 5961's terms), across all connections. ACKs beyond it are not
+    /// sent.
+    pub fn challenge_ack_limit(&mut self, limit: RateLimit) -> &mut Self {
+        self.challenge_ack_limit = Some(limit);
+        self
+    }
+
+    /// Keep what the connections' receive buffers hold, and what their windows let peers send
+    /// on top of that, within `limit` bytes across the interface. Nearing it, windows stop
+    /// opening up, those of connections whose application is not reading first, and new
+    /// connections start with smaller ones; data already received is never dropped for it.
+    /// See [`Interface::recv_memory`].
+    pub fn recv_memory_limit(&mut self, limit: usize) -> &mut Self {
+        self.recv_memory_limit = Some(limit);
+        self
+    }
+
+    /// Start every connection's sequence numbers at `iss`, rather than at one nobody can
+    /// guess, unless an earlier connection on the same addresses and ports closed recently.
+    /// This is for testing, such as how connections fare as their sequence numbers wrap
+    /// around; it makes them easy to spoof segments into.
+    pub fn fixed_iss(&mut self, iss: u32) -> &mut Self {
+        self.fixed_iss = Some(iss);
+        self
+    }
+
+    /// Send and receive packets through `nic`, which carries traffic for the local addresses
+    /// `addrs` (or for any address, if `addrs` is empty). Packets are sized to the MTU the device
+    /// reports, unless [`InterfaceBuilder::set_mtu`] says otherwise.
+    pub fn add_nic<N: Nic + 'static>(&mut self, nic: N, addrs: &[Ipv4Addr]) -> DeviceId {
+        self.nics.push(Box::new(nic));
+        self.devices.push(nic::Device {
+            addrs: addrs.to_vec(),
+            mtu: nic::DEFAULT_MTU,
+        });
+        self.mtus.push(None);
+        DeviceId(self.nics.len() - 1)
+    }
+
+    /// Send packets of up to `mtu` bytes through `device`, whatever MTU it reports.
+    pub fn set_mtu(&mut self, device: DeviceId, mtu: usize) -> &mut Self {
+        self.mtus[device.0] = Some(mtu);
+        self
+    }
+
+    /// Start processing packets on all added devices, on a thread of the interface's own.
+    pub fn build(self) -> io::Result<Interface> {
+        let (ih, nics) = self.setup()?;
+        let jh = {
+            let ih = ih.clone();
+            thread::spawn(move || packet_loop(nics, ih))
+        };
+
+        Ok(Interface {
+            ih: Some(ih),
+            jh: Some(jh),
+            polled: None,
+        })
+    }
+
+    /// Set up the interface without starting a thread for it: nothing happens on its devices
+    /// until the caller runs [`Interface::poll_once`], which it should do in a loop, whatever
+    /// the events.
+    ///
+    /// Blocking calls on its streams and listeners wait for a packet loop that is not running,
+    /// so connections are best handled by [`Quad`] with the interface's non-blocking calls;
+    /// see [`Interface::poll_once`].
+    pub fn build_polled(self) -> io::Result<Interface> {
+        let (ih, nics) = self.setup()?;
+        let driver = Driver::new(nics, &ih);
+        Ok(Interface {
+            ih: Some(ih),
+            jh: None,
+            polled: Some(poll::Polled::new(driver)),
+        })
+    }
+
+    /// Check the devices and set up the interface's state, handing back the devices for
+    /// whatever runs the packet loop.
+    fn setup(self) -> io::Result<(InterfaceHandle, Vec<Box<dyn Nic>>)> {
+        if self.nics.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "an interface needs at least one device",
+            ));
+        }
+
+        let config = self.config.validate()?;
+
+        let mut devices = self.devices;
+        for ((device, mtu), nic) in devices.iter_mut().zip(self.mtus).zip(&self.nics) {
+            device.mtu = check_mtu(match mtu {
+                Some(mtu) => mtu,
+                None => nic.mtu()?,
+            })?;
+        }
+
+        let ih: InterfaceHandle = Arc::default();
+        {
+            let mut cm = ih.manager.lock().unwrap();
+            cm.config = config;
+            cm.devices = devices;
+            if let Some(clock) = self.clock {
+                cm.clock = clock;
+            }
+            let now = cm.clock.now();
+            cm.iss = tcp::IssGenerator::new(now, cm.config.msl);
+            if let Some(iss) = self.fixed_iss {
+                cm.iss.fix(iss);
+            }
+            cm.limits.rst = self
+                .rst_limit
+                .map(|limit| ratelimit::TokenBucket::new(limit, now));
+            cm.limits.challenge_ack = self
+                .challenge_ack_limit
+                .map(|limit| ratelimit::TokenBucket::new(limit, now));
+            cm.recv_budget = memory::RecvBudget::new(self.recv_memory_limit);
+        }
+
+        Ok((ih, self.nics))
+    }
+}
+
+impl Interface {
+    pub fn new() -> io::Result<Self> {
+        Self::with_config(TcpConfig::default())
+    }
+
+    /// Like [`Interface::new`], but with connections configured by `config`.
+    pub fn with_config(config: TcpConfig) -> io::Result<Self> {
+        let nic = tun_tap::Iface::without_packet_info("tun0", tun_tap::Mode::Tun)?;
+        let mut builder = InterfaceBuilder::new();
+        builder.config(config).add_nic(nic, &[]);
+        builder.build()
+    }
+
+    /// Send packets of up to `mtu` bytes through `device` from now on, rather than what it
+    /// reported when the interface was built. Connections already on the device send no larger
+    /// segments than fit, but only new ones make use of a larger MTU.
+    pub fn set_mtu(&mut self, device: DeviceId, mtu: usize) -> io::Result<()> {
+        let mtu = check_mtu(mtu)?;
+        let mut cm = self.ih.as_mut().unwrap().manager.lock().unwrap();
+        cm.devices
+            .get_mut(device.0)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no such device"))?
+            .mtu = mtu;
+        for c in cm.connections.values_mut() {
+            if c.device == device {
+                c.set_mtu(mtu);
+            }
+        }
+        Ok(())
+    }
+
+    /// Listen on `port` on all devices.
+    ///
+    /// This fails with `AddrInUse` while another listener has the port, or while connections on
+    /// it are still open, such as those a previous listener took in (connections in TIME-WAIT do
+    /// not count); see [`Interface::bind_reuse`]. Dropping a listener resets the connections it
+    /// took in that were never accepted, and frees the port right away.
+    pub fn bind(&mut self, port: u16) -> io::Result<TcpListener> {
+        self.listen((None, port), false)
+    }
+
+    /// Listen on `port` on `device` only.
+    pub fn bind_on(&mut self, device: DeviceId, port: u16) -> io::Result<TcpListener> {
+        self.listen((Some(device), port), false)
+    }
+
+    /// Like [`Interface::bind`], but even while connections on `port` are still open, as when a
+    /// server restarts with its old connections still being served. They carry on as before.
+    pub fn bind_reuse(&mut self, port: u16) -> io::Result<TcpListener> {
+        self.listen((None, port), true)
+    }
+
+    /// Like [`Interface::bind_on`], but even while connections on `port` are still open.
+    pub fn bind_on_reuse(&mut self, device: DeviceId, port: u16) -> io::Result<TcpListener> {
+        self.listen((Some(device), port), true)
+    }
+
+    fn listen(&mut self, key: ListenKey, reuse: bool) -> io::Result<TcpListener> {
+        let mut cm = self.ih.as_mut().unwrap().manager.lock().unwrap();
+        if let (Some(device), _) = key {
+            if device.0 >= cm.devices.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "no such device",
+                ));
+            }
+        }
+        if cm.listeners.contains_key(&key) {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "port already bound",
+            ));
+        }
+        let (device, port) = key;
+        if !reuse
+            && cm.connections.iter().any(|(q, c)| {
+                q.dst.1 == port && device.is_none_or(|d| c.device == d) && !c.is_done()
+            })
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "connections on the port are still open",
+            ));
+        }
+        cm.listeners.insert(key, Listener::default());
+        drop(cm);
+        Ok(TcpListener {
+            key,
+            h: self.ih.as_mut().unwrap().clone(),
+        })
+    }
+
+    /// Open a connection from our address `local` to `remote`, through the device that owns
+    /// `local`.
+    ///
+    /// This returns as soon as the connection is set up, without waiting for the handshake;
+    /// anything written to the stream in the meantime is sent once it completes, and fails if
+    /// the handshake does.
+    ///
+    /// `remote` may be one of our own addresses, to connect to a listener on this interface;
+    /// the segments of such a connection never leave it.
+    pub fn connect(&mut self, local: Ipv4Addr, remote: SocketAddrV4) -> io::Result<TcpStream> {
+        self.open(local, None, remote, None)
+    }
+
+    /// Like [`Interface::connect`], but from the port of `local` rather than one picked for
+    /// the connection. Fails with `AddrInUse` if there already is a connection between `local`
+    /// and `remote`. `local` may be `remote` itself, for a connection to itself, which opens
+    /// as a simultaneous open does.
+    pub fn connect_from(
+        &mut self,
+        local: SocketAddrV4,
+        remote: SocketAddrV4,
+    ) -> io::Result<TcpStream> {
+        self.open(*local.ip(), Some(local.port()), remote, None)
+    }
+
+    /// Like [`Interface::connect`], but signing every segment of the connection with `key`,
+    /// and requiring the peer to do the same (RFC 2385).
+    pub fn connect_with_md5(
+        &mut self,
+        local: Ipv4Addr,
+        remote: SocketAddrV4,
+        key: &[u8],
+    ) -> io::Result<TcpStream> {
+        check_md5_key(key)?;
+        self.open(local, None, remote, Some(key.to_vec()))
+    }
+
+    /// Like [`Interface::connect`], but with TCP Fast Open (RFC 7413): if a listener at
+    /// `remote`'s address has handed us a cookie before, `data` goes out in the SYN, for the
+    /// server to take without waiting for the handshake. Otherwise the SYN asks for a cookie for
+    /// next time, and `data` goes out once the handshake is done, as if written to the stream.
+    ///
+    /// A server may take the data in a SYN more than once, if the SYN is duplicated or replayed,
+    /// so it should only be data that does no harm if it does.
+    pub fn connect_fast_open(
+        &mut self,
+        local: Ipv4Addr,
+        remote: SocketAddrV4,
+        data: &[u8],
+    ) -> io::Result<TcpStream> {
+        let stream = self.open(local, None, remote, None)?;
+        let mut cm = self.ih.as_mut().unwrap().manager.lock().unwrap();
+        let cookie = cm
+            .fast_open_cookies
+            .get(remote.ip())
+            .copied()
+            .unwrap_or(fastopen::Cookie::REQUEST);
+        cm.connections
+            .get_mut(&stream.quad)
+            .expect("connection just opened")
+            .offer_fast_open(cookie, data);
+        drop(cm);
+        Ok(stream)
+    }
+
+    /// How many segments have been dropped because their TCP MD5 signature was missing, wrong,
+    /// or not expected at all.
+    pub fn md5_failures(&self) -> u64 {
+        self.drops().get(DropReason::BadMd5)
+    }
+
+    /// Have `handler` called with every packet the interface receives that TCP has no use for:
+    /// packets that are not IPv4 or not TCP, are for addresses we do not have, or are TCP for a
+    /// port with neither a connection nor a listener. The packet is passed on as received; for
+    /// the last of those, the interface has answered it with an RST already, unless it was one
+    /// or [`InterfaceBuilder::rst_limit`] held the RST back.
+    ///
+    /// The handler runs on the packet loop, after TCP is done with the packet, so it holds up
+    /// every connection for as long as it takes: it should be quick, and must not set another
+    /// handler. Packets that reach it still count as dropped in [`Interface::drops`].
+    pub fn set_raw_handler(&mut self, handler: impl FnMut(&[u8]) + Send + 'static) {
+        *self.ih.as_ref().unwrap().raw_handler.lock().unwrap() = Some(Box::new(handler));
+    }
+
+    /// Have `hook` look over every connection a listener takes in, before the SYN-ACK answering
+    /// its SYN goes out: to add options of its own to the SYN-ACK with
+    /// [`PendingAccept::set_extra_options`], or to turn the connection down by returning false,
+    /// in which case the SYN is dropped as [`DropReason::Refused`] without a reply.
+    ///
+    /// The hook runs on the packet loop with the interface locked, so it should be quick, and
+    /// must not call back into the interface or its streams.
+    pub fn set_accept_hook(
+        &mut self,
+        hook: impl FnMut(&mut PendingAccept) -> bool + Send + 'static,
+    ) {
+        let mut cm = self.ih.as_ref().unwrap().manager.lock().unwrap();
+        cm.accept_hook = Some(Box::new(hook));
+    }
+
+    /// Send `packet`, a complete IPv4 packet built by the caller, out the device that has its
+    /// source address (or the only device), through the same queue as the interface's own
+    /// segments. It goes out on the packet loop's next round, within a tick.
+    pub fn send_raw(&self, packet: &[u8]) -> io::Result<()> {
+        let iph = etherparse::Ipv4HeaderSlice::from_slice(packet)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "not an IPv4 packet"))?;
+        let mut cm = self.ih.as_ref().unwrap().manager.lock().unwrap();
+        let device = cm.device_for(iph.source_addr())?;
+        if packet.len() > cm.devices[device.0].mtu {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "packet is larger than the device's MTU",
+            ));
+        }
+        cm.raw_out.push_back((device, packet.to_vec()));
+        Ok(())
+    }
+
+    /// How many packets a device failed to send with an error that did not say it was gone
+    /// for good, such as `ENOBUFS`. Each is dropped, as if lost on the wire, for the
+    /// connection that sent it to retransmit.
+    pub fn send_failures(&self) -> u64 {
+        self.ih
+            .as_ref()
+            .unwrap()
+            .manager
+            .lock()
+            .unwrap()
+            .send_failures
+    }
+
+    /// How many connections have been aborted because their sequence numbers no longer made
+    /// sense, which takes a bug in this crate.
+    pub fn desync_aborts(&self) -> u64 {
+        self.ih
+            .as_ref()
+            .unwrap()
+            .manager
+            .lock()
+            .unwrap()
+            .desync_aborts
+    }
+
+    /// How many connections have been aborted because the peer kept its window closed for
+    /// longer than [`TcpConfig::zero_window_timeout`].
+    pub fn zero_window_aborts(&self) -> u64 {
+        self.ih
+            .as_ref()
+            .unwrap()
+            .manager
+            .lock()
+            .unwrap()
+            .zero_window_aborts
+    }
+
+    /// Every connection in the table, listeners' unaccepted ones and those closing after the
+    /// application let go of them included, with what [`TcpStream::info`] would say of it, in
+    /// no particular order.
+    pub fn connections(&self) -> Vec<(Quad, ConnectionInfo)> {
+        let cm = self.ih.as_ref().unwrap().manager.lock().unwrap();
+        cm.connections.iter().map(|(&q, c)| (q, c.info())).collect()
+    }
+
+    /// Tear the connection on `quad` down with an RST, as one that is stuck may need, on the
+    /// packet loop's next round. Blocked and later calls on its stream then fail with
+    /// `ConnectionAborted`, and its close reason is [`CloseReason::LocalAbort`]. A connection
+    /// still in SYN-SENT goes without an RST, since the peer never heard from us, and one that
+    /// is already done is left alone. Fails with `NotFound` if there is no connection on
+    /// `quad`.
+    pub fn abort(&self, quad: Quad) -> io::Result<()> {
+        let mut cm = self.ih.as_ref().unwrap().manager.lock().unwrap();
+        if !cm.connections.contains_key(&quad) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no connection on that quad",
+            ));
+        }
+        cm.aborts.push(quad);
+        Ok(())
+    }
+
+    /// Push the send space of the connection on `quad` out of joint, as only a bug in this crate
+    /// could, for tests of what the packet loop does about it. Not for applications.
+    #[doc(hidden)]
+    pub fn corrupt_send_space(&self, quad: Quad) -> io::Result<()> {
+        let mut cm = self.ih.as_ref().unwrap().manager.lock().unwrap();
+        let c = cm
+            .connections
+            .get_mut(&quad)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no connection on that quad"))?;
+        c.corrupt_send_space();
+        Ok(())
+    }
+
+    /// [`Interface::abort`] every connection `matches` picks out of those in
+    /// [`Interface::connections`] that are not done yet, say all of those to a backend that went
+    /// away, returning how many it picked. `matches` runs without the connection table locked.
+    pub fn abort_matching(&self, mut matches: impl FnMut(Quad, &ConnectionInfo) -> bool) -> usize {
+        self.connections()
+            .into_iter()
+            .filter(|(_, info)| !matches!(info.state, State::TimeWait | State::Closed))
+            .filter(|(q, info)| matches(*q, info))
+            .filter(|(q, _)| self.abort(*q).is_ok())
+            .count()
+    }
+
+    /// How many segments have been discarded on any device, by reason, whether by a connection
+    /// or before reaching one.
+    pub fn drops(&self) -> Drops {
+        self.ih.as_ref().unwrap().manager.lock().unwrap().drops
+    }
+
+    /// How many replies the interface's rate limits, and those of its listeners, have held
+    /// back.
+    pub fn rate_limited(&self) -> RateLimited {
+        self.ih
+            .as_ref()
+            .unwrap()
+            .manager
+            .lock()
+            .unwrap()
+            .limits
+            .rate_limited
+    }
+
+    /// Open a connection from `local`, on `port` or else on the next ephemeral port free for
+    /// `remote`. The port is picked and the connection goes into the table under one lock, so
+    /// the packet loop never sees one without the other.
+    fn open(
+        &mut self,
+        local: Ipv4Addr,
+        port: Option<u16>,
+        remote: SocketAddrV4,
+        md5_key: Option<Vec<u8>>,
+    ) -> io::Result<TcpStream> {
+        let mut cm = self.ih.as_mut().unwrap().manager.lock().unwrap();
+        let device = cm.device_for(local)?;
+        let quad_from = |port| Quad {
+            src: (*remote.ip(), remote.port()),
+            dst: (local, port),
+        };
+
+        let quad = match port {
+            Some(port) => {
+                let q = quad_from(port);
+                if cm.connections.contains_key(&q) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        "already connected from that port",
+                    ));
+                }
+                q
+            }
+            None => {
+                let mut quad = None;
+                for _ in 0..=(u16::MAX - EPHEMERAL_PORT_START) {
+                    let port = cm.next_port;
+                    cm.next_port = port.checked_add(1).unwrap_or(EPHEMERAL_PORT_START);
+                    let q = quad_from(port);
+                    // a port that makes the quad the same both ways would have the connection
+                    // talk to itself, which nobody asking for just any port wants
+                    if q.src != q.dst && !cm.connections.contains_key(&q) {
+                        quad = Some(q);
+                        break;
+                    }
+                }
+                quad.ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::AddrNotAvailable, "no free local ports")
+                })?
+            }
+        };
+
+        let mtu = cm.devices[device.0].mtu;
+        let iss = cm.iss(&quad);
+        let mut c = tcp::Connection::connect(
+            quad.dst,
+            quad.src,
+            iss,
+            cm.config.clone(),
+            mtu,
+            cm.clock.clone(),
+        );
+        c.device = device;
+        c.md5_key = md5_key;
+        c.set_ts_clock(cm.iss.ts_clock(quad.dst, quad.src));
+        cm.admit_recv_memory(&mut c);
+        cm.connections.insert(quad, c);
+        drop(cm);
+        Ok(TcpStream {
+            quad,
+            h: self.ih.as_mut().unwrap().clone(),
+        })
+    }
+}
+
+pub struct TcpStream {
+    quad: Quad,
+    h: InterfaceHandle,
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        self.h.manager.lock().unwrap().release(self.quad);
+    }
+}
+
+impl Read for TcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        (&*self).read_vectored(bufs)
+    }
+}
+
+impl Read for &TcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_with(buf.len(), true, |incoming, n| {
+            copy_out(incoming, 0, &mut buf[..n])
+        })
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        let len = bufs.iter().map(|buf| buf.len()).sum();
+        self.read_with(len, true, |incoming, n| {
+            let mut from = 0;
+            for buf in bufs.iter_mut() {
+                let k = cmp::min(buf.len(), n - from);
+                copy_out(incoming, from, &mut buf[..k]);
+                from += k;
+            }
+        })
+    }
+}
+
+impl Write for TcpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        (&*self).write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&*self).flush()
+    }
+}
+
+/// Writes return once all of the data is in the send buffer, which takes it in as it has room,
+/// waiting for the peer to acknowledge earlier data as often as it takes. A write cut short by
+/// the write timeout, by the stream being shut down for writing, or by the connection failing
+/// returns how much it got in, and the next write reports why; only a write that got nothing in
+/// fails right away. `flush` waits until the peer has acknowledged every byte written so far.
+impl Write for &TcpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_with(buf.len(), |unacked, range| {
+            unacked.extend(&buf[range.clone()]);
+            Ok(range.len())
+        })
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let len = bufs.iter().map(|buf| buf.len()).sum();
+        self.write_with(len, |unacked, range| {
+            // the range is of all the buffers end to end
+            let mut start = 0;
+            for buf in bufs {
+                let end = start + buf.len();
+                if start < range.end && range.start < end {
+                    let from = range.start.saturating_sub(start);
+                    let to = cmp::min(range.end, end) - start;
+                    unacked.extend(&buf[from..to]);
+                }
+                start = end;
+            }
+            Ok(range.len())
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut cm = self.h.manager.lock().unwrap();
+        let deadline = self.deadline(&cm, |c| c.write_timeout);
+        loop {
+            let Some(c) = cm.connections.get(&self.quad) else {
+                // a connection that failed stays around until the application has seen the
+                // error, so this one ran its course with everything acknowledged
+                return Ok(());
+            };
+
+            if let Some(e) = c.error {
+                return Err(io::Error::from(e));
+            }
+            if c.unacked.is_empty() {
+                return Ok(());
+            }
+
+            cm = wait_until(&self.h.snd_var, cm, deadline)?;
+        }
+    }
+}
+
+impl TcpStream {
+    /// Send `len` bytes of `file`, starting at `offset`, as `write_all` would send them after
+    /// reading them in: the file is read straight into the send buffer as it has room, with no
+    /// buffer in between. Returns once all of it is in the send buffer, which keeps it until
+    /// the peer acknowledges it, so the file may change afterwards; `flush` waits for that.
+    ///
+    /// Returns how many bytes were sent, which is fewer than `len` if the file ends first. A
+    /// send cut short otherwise returns how much it got in, and the next call reports why, as
+    /// with `write`.
+    pub fn send_file(&self, file: &File, offset: u64, len: usize) -> io::Result<usize> {
+        let mut sent = 0;
+        while sent < len {
+            // a piece at a time, so that the packet loop is not kept waiting on the file for long
+            let piece = cmp::min(len - sent, SEND_FILE_PIECE);
+            let at = offset + sent as u64;
+            match self.write_with(piece, |unacked, range| {
+                append_from_file(unacked, file, at + range.start as u64, range.len())
+            }) {
+                Ok(n) => {
+                    sent += n;
+                    if n < piece {
+                        break;
+                    }
+                }
+                Err(_) if sent > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Read into possibly uninitialized memory: like `read`, but the bytes past what it returns
+    /// are left alone rather than having to be initialized first.
+    pub fn read_buf(&mut self, buf: &mut [MaybeUninit<u8>]) -> io::Result<usize> {
+        self.read_with(buf.len(), true, |incoming, n| {
+            for (dst, src) in buf.iter_mut().zip(incoming.range(..n)) {
+                dst.write(*src);
+            }
+        })
+    }
+
+    /// Make blocking reads give up with `WouldBlock` once they have waited for `timeout`
+    /// without any data arriving, or wait indefinitely with `None`. A zero timeout is an error.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.set_timeout(timeout, |c| &mut c.read_timeout)
+    }
+
+    /// Make blocking writes give up with `WouldBlock` once they have waited for `timeout`
+    /// in all for room in the send buffer, or wait indefinitely with `None`; a write that got
+    /// some of its data in by then returns how much instead. A zero timeout is an error.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.set_timeout(timeout, |c| &mut c.write_timeout)
+    }
+
+    pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        self.with_connection(|c| c.read_timeout)
+    }
+
+    /// Only wake a blocked read once `trigger` is met: once enough bytes, or a whole line or
+    /// frame ending in a delimiter, have arrived. The end of the stream, an error, urgent data
+    /// or a full receive buffer wake it regardless. A read that finds data already there when
+    /// called still returns it right away, whether or not it meets the trigger.
+    pub fn set_read_trigger(&self, trigger: ReadTrigger) -> io::Result<()> {
+        self.with_connection(|c| c.set_read_trigger(trigger))
+    }
+
+    pub fn read_trigger(&self) -> io::Result<ReadTrigger> {
+        self.with_connection(|c| c.read_trigger())
+    }
+
+    /// Hold back waking a blocked read until `coalescing.bytes` are buffered, the peer pushes
+    /// what it has sent (sets PSH), or the oldest unread data has waited `coalescing.delay`,
+    /// whichever comes first, rather than waking it for every segment; `None` turns this off.
+    /// It comes on top of the read trigger, and what wakes a read regardless of the trigger
+    /// wakes it regardless of this too. A read that finds data already there when called
+    /// still returns it right away.
+    ///
+    /// The delay is checked when the packet loop ticks, every 10ms, so shorter delays run out
+    /// on the next tick.
+    pub fn set_read_coalescing(&self, coalescing: Option<ReadCoalescing>) -> io::Result<()> {
+        self.with_connection(|c| c.set_read_coalescing(coalescing))
+    }
+
+    pub fn read_coalescing(&self) -> io::Result<Option<ReadCoalescing>> {
+        self.with_connection(|c| c.read_coalescing())
+    }
+
+    pub fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        self.with_connection(|c| c.write_timeout)
+    }
+
+    /// Cork the stream (like Linux's `TCP_CORK`): hold back data that doesn't fill a segment,
+    /// so that what is written next goes out in the same segment. Full segments are still sent
+    /// right away, and held data goes out anyway after [`TcpConfig::cork_timeout`]. Uncorking
+    /// sends whatever was held back on the next tick.
+    pub fn set_cork(&self, cork: bool) -> io::Result<()> {
+        self.with_connection(|c| c.corked = cork)
+    }
+
+    pub fn cork(&self) -> io::Result<bool> {
+        self.with_connection(|c| c.corked)
+    }
+
+    /// Have the stream's segments go out ahead of those of lower priority once its device is
+    /// backed up, the way interactive traffic should not wait behind a bulk transfer. Lower
+    /// priorities still get a share of the device. Pure ACKs, SYNs and RSTs go ahead of
+    /// everything regardless.
+    pub fn set_priority(&self, priority: Priority) -> io::Result<()> {
+        self.with_connection(|c| c.priority = priority)
+    }
+
+    pub fn priority(&self) -> io::Result<Priority> {
+        self.with_connection(|c| c.priority)
+    }
+
+    /// Have dropping the stream reset its connection with an RST at SND.NXT (like `SO_LINGER`
+    /// with a zero timeout), which frees its quad right away with no TIME-WAIT, for clients
+    /// that open connections by the thousand. Whatever was not sent yet is discarded. A stream
+    /// shut down for writing before it is dropped still closes gracefully. The default is
+    /// [`TcpConfig::abort_on_drop`].
+    pub fn set_abort_on_drop(&self, abort: bool) -> io::Result<()> {
+        self.with_connection(|c| c.abort_on_drop = abort)
+    }
+
+    pub fn abort_on_drop(&self) -> io::Result<bool> {
+        self.with_connection(|c| c.abort_on_drop)
+    }
+
+    fn set_timeout(
+        &self,
+        timeout: Option<Duration>,
+        field: impl FnOnce(&mut tcp::Connection) -> &mut Option<Duration>,
+    ) -> io::Result<()> {
+        check_timeout(timeout)?;
+        self.with_connection(|c| *field(c) = timeout)
+    }
+
+    fn with_connection<T>(&self, f: impl FnOnce(&mut tcp::Connection) -> T) -> io::Result<T> {
+        let mut cm = self.h.manager.lock().unwrap();
+        let c = cm
+            .connections
+            .get_mut(&self.quad)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "connection is closed"))?;
+        Ok(f(c))
+    }
+
+    /// When a blocking call that starts now should give up, by the timeout `timeout` picks.
+    fn deadline(
+        &self,
+        cm: &ConnectionManager,
+        timeout: impl FnOnce(&tcp::Connection) -> Option<Duration>,
+    ) -> Option<Instant> {
+        deadline(cm, self.quad, timeout)
+    }
+
+    /// `read_vectored` fills every buffer straight from the receive buffer.
+    pub fn is_read_vectored(&self) -> bool {
+        true
+    }
+
+    /// `write_vectored` appends every buffer straight to the send buffer.
+    pub fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    /// Like `read`, but leave the data in the receive buffer, for the next read to return again.
+    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_with(buf.len(), false, |incoming, n| {
+            copy_out(incoming, 0, &mut buf[..n])
+        })
+    }
+
+    fn read_with(
+        &self,
+        len: usize,
+        consume: bool,
+        fill: impl FnOnce(&VecDeque<u8>, usize),
+    ) -> io::Result<usize> {
+        self.h.read_with(self.quad, true, len, consume, fill)
+    }
+
+    fn write_with(
+        &self,
+        len: usize,
+        append: impl FnMut(&mut VecDeque<u8>, Range<usize>) -> io::Result<usize>,
+    ) -> io::Result<usize> {
+        self.h.write_with(self.quad, true, len, append)
+    }
+}
+
+impl Shared {
+    /// Wait until there is data to read on `quad` (or, unless `block`, fail with `WouldBlock`
+    /// if there is none), then have `fill` copy out the first `n` bytes of it,
+    /// with `n` at most `len`, and `consume` them unless peeking. Returns 0 at the end of the
+    /// stream.
+    fn read_with(
+        &self,
+        quad: Quad,
+        block: bool,
+        len: usize,
+        consume: bool,
+        fill: impl FnOnce(&VecDeque<u8>, usize),
+    ) -> io::Result<usize> {
+        let mut cm = self.manager.lock().unwrap();
+        let deadline = deadline(&cm, quad, |c| c.read_timeout);
+        // whatever has arrived by the time of the call is returned right away, but once we have
+        // had to wait, only what the read trigger waits for is worth waking up for
+        let mut waited = false;
+        loop {
+            let c = match cm.connections.get_mut(&quad) {
+                Some(c) => c,
+                // the connection ran its course, including the peer's FIN
+                None => return Ok(0),
+            };
+
+            if let Some(e) = c.error {
+                return Err(io::Error::from(e));
+            }
+
+            if !c.incoming.is_empty()
+                && (!waited || c.urgent.is_some() || c.is_rcv_closed() || c.read_ready())
+            {
+                // stop short of the urgent mark, so the application can tell it has got there
+                let available = match c.urgent_mark {
+                    Some(mark) if mark > 0 => mark,
+                    _ => c.incoming.len(),
+                };
+                let nread = cmp::min(len, available);
+                fill(&c.incoming, nread);
+                if !consume {
+                    return Ok(nread);
+                }
+                drop(c.incoming.drain(..nread));
+                c.consumed(nread);
+                if nread > 0 {
+                    c.urgent_mark = match c.urgent_mark {
+                        Some(0) | None => None,
+                        Some(mark) => Some(mark - nread),
+                    };
+                }
+                return Ok(nread);
+            }
+
+            if c.is_rcv_closed() {
+                // no more data will arrive
+                return Ok(0);
+            }
+
+            if !block {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            cm = wait_until(&self.rcv_var, cm, deadline)?;
+            waited = true;
+        }
+    }
+
+    /// Have `append` add `len` bytes to `quad`'s send buffer, a range of them at a time as
+    /// room frees up, and wait until all of them are in. Unless `block`, add only as many as
+    /// fit now, and fail with `WouldBlock` if none do. `append` returns how many of the range
+    /// it added: fewer means its source has run dry, and the write stops there.
+    ///
+    /// A write that stops short, for the write timeout or the connection failing or being shut
+    /// down for writing, or for `append` failing, returns how many bytes it added, if any; the
+    /// next one fails.
+    fn write_with(
+        &self,
+        quad: Quad,
+        block: bool,
+        len: usize,
+        mut append: impl FnMut(&mut VecDeque<u8>, Range<usize>) -> io::Result<usize>,
+    ) -> io::Result<usize> {
+        if len == 0 {
+            return Ok(0);
+        }
+        let mut cm = self.manager.lock().unwrap();
+        let deadline = deadline(&cm, quad, |c| c.write_timeout);
+        let mut written = 0;
+        let stopped = |written, e| if written > 0 { Ok(written) } else { Err(e) };
+        loop {
+            let Some(c) = cm.connections.get_mut(&quad) else {
+                let e = io::Error::new(io::ErrorKind::BrokenPipe, "connection is closed");
+                return stopped(written, e);
+            };
+
+            if let Some(e) = c.error {
+                return stopped(written, io::Error::from(e));
+            }
+            if c.closed {
+                let e = io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "stream was shut down for writing",
+                );
+                return stopped(written, e);
+            }
+
+            let room = c.send_room();
+            if room > 0 {
+                // the packet loop picks this up on its next tick, or as soon as the handshake
+                // completes if it has not yet
+                let nwrite = cmp::min(len - written, room);
+                let appended = match append(&mut c.unacked, written..written + nwrite) {
+                    Ok(appended) => appended,
+                    Err(e) => return stopped(written, e),
+                };
+                written += appended;
+                if written == len || appended < nwrite || !block {
+                    return Ok(written);
+                }
+            } else if !block {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            cm = match wait_until(&self.snd_var, cm, deadline) {
+                Ok(cm) => cm,
+                Err(e) => return stopped(written, e),
+            };
+        }
+    }
+
+    /// Shut `quad` down for writing, reading or both; see [`TcpStream::shutdown`].
+    fn shutdown(&self, quad: Quad, how: Shutdown) -> io::Result<()> {
+        let mut cm = self.manager.lock().unwrap();
+        let c = cm
+            .connections
+            .get_mut(&quad)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "connection is closed"))?;
+        if let Shutdown::Write | Shutdown::Both = how {
+            c.close();
+        }
+        if let Shutdown::Read | Shutdown::Both = how {
+            c.shutdown_read();
+            // readers blocked on the stream are at its end now
+            self.rcv_var.notify_all();
+        }
+        Ok(())
+    }
+}
+
+impl TcpStream {
+    /// What the connection negotiated with the peer, and its current state.
+    pub fn info(&self) -> io::Result<ConnectionInfo> {
+        let cm = self.h.manager.lock().unwrap();
+        let c = cm
+            .connections
+            .get(&self.quad)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "connection is closed"))?;
+        Ok(c.info())
+    }
+
+    /// Why the connection closed, once it has: whether the application or the peer closed it
+    /// first, or why it failed. It stays available after reads have returned the end of the
+    /// stream, or reads and writes have failed, until the stream is dropped.
+    pub fn close_reason(&self) -> Option<CloseReason> {
+        self.h.manager.lock().unwrap().close_reason(self.quad)
+    }
+
+    /// What the peer's SYN looked like, for a connection a listener took in; `None` for one we
+    /// opened.
+    pub fn syn_metadata(&self) -> io::Result<Option<SynMetadata>> {
+        self.with_connection(|c| c.syn_metadata())
+    }
+
+    /// The latest segments the connection sent and received, oldest first, as many as
+    /// [`TcpConfig::trace_packets`] asks it to keep. Each has its headers and the first 64 bytes
+    /// of its data. The trace outlives a failure, so it can be had once reads or writes have
+    /// failed, until the stream is dropped.
+    pub fn trace_dump(&self) -> io::Result<Vec<TracedPacket>> {
+        self.with_connection(|c| c.trace_dump())
+    }
+
+    /// How many times the peer broke one of the rules [`TcpConfig::strictness`] is about, by
+    /// rule, whether or not the connection let it pass.
+    pub fn violations(&self) -> io::Result<Violations> {
+        self.with_connection(|c| c.violations())
+    }
+
+    /// How many of the peer's segments this connection has discarded, by reason.
+    pub fn drops(&self) -> io::Result<Drops> {
+        self.with_connection(|c| c.drops())
+    }
+
+    /// Send `buf` with its last byte as urgent data (BSD's `send(MSG_OOB)`), waiting until all
+    /// of it fits in the send buffer.
+    pub fn send_urgent(&self, buf: &[u8]) -> io::Result<()> {
+        let mut cm = self.h.manager.lock().unwrap();
+        let deadline = self.deadline(&cm, |c| c.write_timeout);
+        loop {
+            let c = cm
+                .connections
+                .get_mut(&self.quad)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "connection is closed"))?;
+
+            if let Some(e) = c.error {
+                return Err(io::Error::from(e));
+            }
+            if c.closed {
+                return Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "stream was shut down for writing",
+                ));
+            }
+
+            if buf.is_empty() || buf.len() > c.send_buffer() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "urgent data must be non-empty and fit in the send buffer",
+                ));
+            }
+            if buf.len() <= c.send_room() {
+                c.unacked.extend(buf);
+                c.mark_urgent();
+                return Ok(());
+            }
+
+            cm = wait_until(&self.h.snd_var, cm, deadline)?;
+        }
+    }
+
+    /// Take the byte of urgent data the peer sent last (BSD's `recv(MSG_OOB)`), which is not
+    /// part of what `read` returns.
+    ///
+    /// Fails with `WouldBlock` if the peer has announced urgent data that has not arrived yet,
+    /// and with `InvalidInput` if there is none to wait for.
+    pub fn recv_urgent(&self) -> io::Result<u8> {
+        self.with_connection(|c| c.take_urgent())?
+    }
+
+    /// Wait until urgent data from the peer has arrived, for `recv_urgent` to pick up.
+    ///
+    /// Fails with `WouldBlock` if the read timeout passes first.
+    pub fn wait_urgent(&self) -> io::Result<()> {
+        let mut cm = self.h.manager.lock().unwrap();
+        let deadline = self.deadline(&cm, |c| c.read_timeout);
+        loop {
+            let c = cm.connections.get(&self.quad).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotConnected, "connection is closed")
+            })?;
+
+            if let Some(e) = c.error {
+                return Err(io::Error::from(e));
+            }
+            if c.urgent.is_some() {
+                return Ok(());
+            }
+            if c.is_rcv_closed() {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "the peer closed without sending urgent data",
+                ));
+            }
+
+            cm = wait_until(&self.h.rcv_var, cm, deadline)?;
+        }
+    }
+
+    /// Whether everything the peer sent before its latest urgent data has been read, so the
+    /// next `read` returns what followed it (BSD's `SIOCATMARK`).
+    pub fn at_urgent_mark(&self) -> io::Result<bool> {
+        let cm = self.h.manager.lock().unwrap();
+        let c = cm
+            .connections
+            .get(&self.quad)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "connection is closed"))?;
+        Ok(c.urgent_mark == Some(0))
+    }
+
+    /// Shut the connection down for writing, which sends our FIN once everything written has
+    /// gone out, or for reading, after which reads return 0 and data from the peer is
+    /// acknowledged but discarded, or both.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.h.shutdown(self.quad, how)
+    }
+
+    /// The address and port of the other end of the connection.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        let (ip, port) = self.quad.src;
+        Ok(SocketAddr::V4(SocketAddrV4::new(ip, port)))
+    }
+
+    /// Our address and port on the connection.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        let (ip, port) = self.quad.dst;
+        Ok(SocketAddr::V4(SocketAddrV4::new(ip, port)))
+    }
+
+    /// The connection, as the interface's calls by [`Quad`] and its events name it.
+    pub fn quad(&self) -> Quad {
+        self.quad
+    }
+
+    /// Hand the connection over to the interface's calls by [`Quad`] (see
+    /// [`Interface::poll_once`]): unlike dropping the stream, this leaves it open, for
+    /// [`Interface::close_on`] to close.
+    pub fn into_quad(self) -> Quad {
+        let stream = std::mem::ManuallyDrop::new(self);
+        // the stream itself is never dropped, so its handle has to be, exactly once
+        drop(unsafe { std::ptr::read(&stream.h) });
+        stream.quad
+    }
+}
+
+impl TcpStream {
+    /// Split the stream into halves for reading and writing that can go their separate ways,
+    /// to different threads for instance.
+    ///
+    /// Dropping the write half shuts the stream down for writing, and the connection is
+    /// released once both halves are gone, just as if the stream itself had been dropped.
+    pub fn split(self) -> (ReadHalf, WriteHalf) {
+        let stream = Arc::new(self);
+        (ReadHalf(stream.clone()), WriteHalf(Some(stream)))
+    }
+}
+
+/// The reading half of a [`TcpStream`], from [`TcpStream::split`].
+pub struct ReadHalf(Arc<TcpStream>);
+
+/// The writing half of a [`TcpStream`], from [`TcpStream::split`].
+pub struct WriteHalf(Option<Arc<TcpStream>>);
+
+impl ReadHalf {
+    /// Put the stream back together from the halves [`TcpStream::split`] made of it.
+    pub fn reunite(self, mut other: WriteHalf) -> Result<TcpStream, ReuniteError> {
+        if !Arc::ptr_eq(&self.0, other.stream()) {
+            return Err(ReuniteError(self, other));
+        }
+        let stream = other.0.take().unwrap();
+        drop(self);
+        Ok(Arc::try_unwrap(stream)
+            .ok()
+            .expect("only the two halves share the stream"))
+    }
+
+    /// See [`TcpStream::peek`].
+    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.peek(buf)
+    }
+
+    /// See [`TcpStream::close_reason`].
+    pub fn close_reason(&self) -> Option<CloseReason> {
+        self.0.close_reason()
+    }
+
+    /// See [`TcpStream::set_read_timeout`].
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.0.set_read_timeout(timeout)
+    }
+
+    pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        self.0.read_timeout()
+    }
+
+    /// See [`TcpStream::set_read_trigger`].
+    pub fn set_read_trigger(&self, trigger: ReadTrigger) -> io::Result<()> {
+        self.0.set_read_trigger(trigger)
+    }
+
+    pub fn read_trigger(&self) -> io::Result<ReadTrigger> {
+        self.0.read_trigger()
+    }
+
+    /// See [`TcpStream::set_read_coalescing`].
+    pub fn set_read_coalescing(&self, coalescing: Option<ReadCoalescing>) -> io::Result<()> {
+        self.0.set_read_coalescing(coalescing)
+    }
+
+    pub fn read_coalescing(&self) -> io::Result<Option<ReadCoalescing>> {
+        self.0.read_coalescing()
+    }
+
+    /// See [`TcpStream::recv_urgent`].
+    pub fn recv_urgent(&self) -> io::Result<u8> {
+        self.0.recv_urgent()
+    }
+
+    /// See [`TcpStream::wait_urgent`].
+    pub fn wait_urgent(&self) -> io::Result<()> {
+        self.0.wait_urgent()
+    }
+
+    /// See [`TcpStream::at_urgent_mark`].
+    pub fn at_urgent_mark(&self) -> io::Result<bool> {
+        self.0.at_urgent_mark()
+    }
+}
+
+impl Read for ReadHalf {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self.0).read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        (&*self.0).read_vectored(bufs)
+    }
+}
+
+impl WriteHalf {
+    fn stream(&self) -> &Arc<TcpStream> {
+        self.0.as_ref().expect("only reunite takes the stream")
+    }
+
+    /// See [`TcpStream::set_write_timeout`].
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream().set_write_timeout(timeout)
+    }
+
+    pub fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        self.stream().write_timeout()
+    }
+
+    /// See [`TcpStream::set_cork`].
+    pub fn set_cork(&self, cork: bool) -> io::Result<()> {
+        self.stream().set_cork(cork)
+    }
+
+    pub fn cork(&self) -> io::Result<bool> {
+        self.stream().cork()
+    }
+
+    /// See [`TcpStream::set_priority`].
+    pub fn set_priority(&self, priority: Priority) -> io::Result<()> {
+        self.stream().set_priority(priority)
+    }
+
+    pub fn priority(&self) -> io::Result<Priority> {
+        self.stream().priority()
+    }
+
+    /// See [`TcpStream::send_urgent`].
+    pub fn send_urgent(&self, buf: &[u8]) -> io::Result<()> {
+        self.stream().send_urgent(buf)
+    }
+
+    /// See [`TcpStream::send_file`].
+    pub fn send_file(&self, file: &File, offset: u64, len: usize) -> io::Result<usize> {
+        self.stream().send_file(file, offset, len)
+    }
+}
+
+impl Write for WriteHalf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&**self.stream()).write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        (&**self.stream()).write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&**self.stream()).flush()
+    }
+}
+
+impl Drop for WriteHalf {
+    fn drop(&mut self) {
+        if let Some(stream) = &self.0 {
+            // the connection may be gone already, and then there is nothing to shut down
+            let _ = stream.shutdown(Shutdown::Write);
+        }
+    }
+}
+
+/// The halves given to [`ReadHalf::reunite`] came from different streams; here they are back.
+pub struct ReuniteError(pub ReadHalf, pub WriteHalf);
+
+impl std::fmt::Debug for ReuniteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ReuniteError").finish_non_exhaustive()
+    }
+}
+
+impl std::fmt::Display for ReuniteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "tried to reunite halves of different streams")
+    }
+}
+
+impl std::error::Error for ReuniteError {}
+
+pub struct TcpListener {
+    key: ListenKey,
+    h: InterfaceHandle,
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        let mut cm = self.h.manager.lock().unwrap();
+        // connections nobody accepted go with the listener, right away, so that the port is
+        // free for a new one; nobody can be waiting in `accept` on a listener being dropped
+        cm.close_listener(self.key);
+        cm.listeners.remove(&self.key);
+    }
+}
+
+impl TcpListener {
+    /// Require connections from `peer` to sign their segments with `key`, and sign ours to
+    /// them likewise (RFC 2385). Connections already accepted keep the key they started with.
+    pub fn set_md5_key(&mut self, peer: Ipv4Addr, key: &[u8]) -> io::Result<()> {
+        check_md5_key(key)?;
+        self.with_listener(|l| l.md5_keys.insert(peer, key.to_vec()));
+        Ok(())
+    }
+
+    /// Stop requiring signatures from `peer`.
+    pub fn remove_md5_key(&mut self, peer: Ipv4Addr) {
+        self.with_listener(|l| l.md5_keys.remove(&peer));
+    }
+
+    /// Cap how much data a connection buffers while it waits to be accepted at `window` bytes,
+    /// which is also all the window it advertises, so that connections nobody accepts cannot
+    /// pile up data nobody reads. Once accepted, the connection gets its whole receive buffer
+    /// and tells the peer so. A window of 0 holds back the peer's data altogether until then.
+    ///
+    /// Connections already queued keep the window they started with.
+    pub fn set_accept_window(&mut self, window: Option<usize>) {
+        self.with_listener(|l| l.accept_window = window);
+    }
+
+    /// Take up to `max_early_data` bytes of data in the SYNs of clients that bring a TCP Fast
+    /// Open cookie (RFC 7413), handing them out to clients that ask for one, or disable Fast
+    /// Open with `None`. The data is there to read as soon as the connection is accepted, before
+    /// the handshake completes; [`ConnectionInfo::fast_open`] tells connections that got any.
+    ///
+    /// A SYN can be duplicated or replayed, and its data taken again each time, so only enable
+    /// this for services whose first request does no harm if repeated.
+    pub fn set_fast_open(&mut self, max_early_data: Option<usize>) {
+        self.with_listener(|l| l.fast_open = max_early_data);
+    }
+
+    /// Answer no more than `limit` SYNs with a SYN-ACK, so that a flood of them costs little
+    /// more than reading them. SYNs beyond it are dropped without a reply, which the peer, if
+    /// there is one, takes for a lost SYN and sends again.
+    pub fn set_syn_limit(&mut self, limit: Option<RateLimit>) {
+        let now = self.h.manager.lock().unwrap().clock.now();
+        self.with_listener(|l| {
+            l.syn_limit = limit.map(|limit| ratelimit::TokenBucket::new(limit, now))
+        });
+    }
+
+    /// Take no more than `limit.connections` connections from any one address at once, turning
+    /// away SYNs beyond that as `limit.over` says, or take any number with `None`. SYNs turned
+    /// away count as dropped for [`DropReason::SourceLimit`] in [`Interface::drops`].
+    pub fn set_source_limit(&mut self, limit: Option<SourceLimit>) {
+        self.with_listener(|l| l.source_limit = limit);
+    }
+
+    /// Keep no more than `limit.connections` connections in their handshake at once, dealing
+    /// with SYNs beyond that as `limit.when_full` says; 256, with new SYNs dropped, until set.
+    /// Connections only go to `accept` once their handshake completes, except those whose SYN
+    /// brought data with Fast Open, which take no room among the others. SYNs dropped count as
+    /// dropped for [`DropReason::HalfOpenLimit`] in [`Interface::drops`].
+    pub fn set_half_open_limit(&mut self, limit: HalfOpenLimit) {
+        self.with_listener(|l| l.half_open_limit = limit);
+    }
+
+    pub fn half_open_limit(&self) -> HalfOpenLimit {
+        self.with_listener(|l| l.half_open_limit)
+    }
+
+    /// How many connections the listener holds on to while their handshake is under way.
+    pub fn half_open(&self) -> usize {
+        self.with_listener(|l| l.half_open.len())
+    }
+
+    /// How many connections gave up on their handshake, with their SYN-ACK still unanswered
+    /// after [`TcpConfig::syn_ack_retries`] retransmissions.
+    pub fn half_open_expired(&self) -> u64 {
+        self.with_listener(|l| l.half_open_expired)
+    }
+
+    /// How many connections were dropped in their handshake to make room for newer ones, as
+    /// [`WhenFull::RecycleOldest`] has it.
+    pub fn half_open_recycled(&self) -> u64 {
+        self.with_listener(|l| l.half_open_recycled)
+    }
+
+    /// Configure the connections the listener takes in from now on with `config` rather than
+    /// the interface's configuration, or go back to that with `None`. It applies from their
+    /// SYNs on, so the window of the SYN-ACK already reflects its receive buffer. Fails with a
+    /// [`ConfigError`] if `config` does not pass [`TcpConfig::validate`].
+    pub fn set_config(&mut self, config: Option<TcpConfig>) -> io::Result<()> {
+        let config = config.map(|config| config.validate()).transpose()?;
+        self.with_listener(|l| l.config = config);
+        Ok(())
+    }
+
+    /// What the connections the listener takes in are configured with.
+    pub fn config(&self) -> TcpConfig {
+        let cm = self.h.manager.lock().unwrap();
+        cm.listeners[&self.key]
+            .config
+            .clone()
+            .unwrap_or_else(|| cm.config.clone())
+    }
+
+    /// Have the streams of connections taken in from now on start out with `timeout` as their
+    /// read timeout; see [`TcpStream::set_read_timeout`]. Streams can still change it once
+    /// accepted, as they can everything set on the listener.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        check_timeout(timeout)?;
+        self.with_listener(|l| l.read_timeout = timeout);
+        Ok(())
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.with_listener(|l| l.read_timeout)
+    }
+
+    /// Like [`TcpListener::set_read_timeout`], for [`TcpStream::set_write_timeout`].
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        check_timeout(timeout)?;
+        self.with_listener(|l| l.write_timeout = timeout);
+        Ok(())
+    }
+
+    pub fn write_timeout(&self) -> Option<Duration> {
+        self.with_listener(|l| l.write_timeout)
+    }
+
+    /// Like [`TcpListener::set_read_timeout`], for [`TcpStream::set_read_trigger`].
+    pub fn set_read_trigger(&mut self, trigger: ReadTrigger) {
+        self.with_listener(|l| l.read_trigger = trigger);
+    }
+
+    pub fn read_trigger(&self) -> ReadTrigger {
+        self.with_listener(|l| l.read_trigger)
+    }
+
+    /// Like [`TcpListener::set_read_timeout`], for [`TcpStream::set_read_coalescing`].
+    pub fn set_read_coalescing(&mut self, coalescing: Option<ReadCoalescing>) {
+        self.with_listener(|l| l.read_coalescing = coalescing);
+    }
+
+    pub fn read_coalescing(&self) -> Option<ReadCoalescing> {
+        self.with_listener(|l| l.read_coalescing)
+    }
+
+    /// Like [`TcpListener::set_read_timeout`], for [`TcpStream::set_cork`].
+    pub fn set_cork(&mut self, cork: bool) {
+        self.with_listener(|l| l.cork = cork);
+    }
+
+    pub fn cork(&self) -> bool {
+        self.with_listener(|l| l.cork)
+    }
+
+    /// Like [`TcpListener::set_read_timeout`], for [`TcpStream::set_priority`].
+    pub fn set_priority(&mut self, priority: Priority) {
+        self.with_listener(|l| l.priority = priority);
+    }
+
+    pub fn priority(&self) -> Priority {
+        self.with_listener(|l| l.priority)
+    }
+
+    /// Like [`TcpListener::set_read_timeout`], for [`TcpStream::set_abort_on_drop`]; this sets
+    /// [`TcpConfig::abort_on_drop`] in the listener's configuration, which starts out as the
+    /// interface's if the listener has none of its own.
+    pub fn set_abort_on_drop(&mut self, abort: bool) {
+        let mut cm = self.h.manager.lock().unwrap();
+        let default = cm.config.clone();
+        let listener = cm
+            .listeners
+            .get_mut(&self.key)
+            .expect("port closed while listener still active");
+        listener.config.get_or_insert(default).abort_on_drop = abort;
+    }
+
+    pub fn abort_on_drop(&self) -> bool {
+        self.config().abort_on_drop
+    }
+
+    /// The `n` addresses that hold the most connections against the listener, with how many
+    /// each holds, most first; counted as [`TcpListener::set_source_limit`] counts them.
+    pub fn top_sources(&self, n: usize) -> Vec<(Ipv4Addr, usize)> {
+        let cm = self.h.manager.lock().unwrap();
+        let mut sources: Vec<_> = cm.sources(self.key).into_iter().collect();
+        sources.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        sources.truncate(n);
+        sources
+    }
+
+    /// Wait for a connection to come in and take it.
+    ///
+    /// Any number of threads can wait here on the same listener. Each connection goes to just
+    /// one of them, the one that has waited longest, and wakes only that one. They all fail
+    /// once the listener is [closed](TcpListener::close).
+    pub fn accept(&self) -> io::Result<TcpStream> {
+        let mut cm = self.h.manager.lock().unwrap();
+        let listener = cm
+            .listeners
+            .get_mut(&self.key)
+            .expect("port closed while listener still active");
+        if listener.closed {
+            return Err(listener_closed(cm.terminate));
+        }
+        // connections only queue up while nobody waits for them, so one that is queued is
+        // this thread's to take
+        if let Some(quad) = listener.queue.pop_front() {
+            return Ok(self.accepted(&mut cm, quad));
+        }
+
+        let ticket = listener.next_ticket;
+        listener.next_ticket += 1;
+        let waker = Arc::new(Condvar::new());
+        listener.waiters.push_back((ticket, waker.clone()));
+        loop {
+            cm = waker.wait(cm).unwrap();
+            let listener = cm
+                .listeners
+                .get_mut(&self.key)
+                .expect("port closed while listener still active");
+            if let Some(quad) = listener.granted.remove(&ticket) {
+                return Ok(self.accepted(&mut cm, quad));
+            }
+            if listener.closed {
+                listener.waiters.retain(|&(t, _)| t != ticket);
+                return Err(listener_closed(cm.terminate));
+            }
+        }
+    }
+
+    /// Stop taking connections, even before the listener is dropped: those that came in but
+    /// were not accepted yet are reset, and `accept` fails from now on, in the threads waiting
+    /// in it too. The port stays bound until the listener is dropped.
+    pub fn close(&self) {
+        let wakers = self.h.manager.lock().unwrap().close_listener(self.key);
+        for waker in wakers {
+            waker.notify_one();
+        }
+    }
+
+    /// Change what the listener's record says, under the lock.
+    fn with_listener<T>(&self, change: impl FnOnce(&mut Listener) -> T) -> T {
+        let mut cm = self.h.manager.lock().unwrap();
+        change(
+            cm.listeners
+                .get_mut(&self.key)
+                .expect("port closed while listener still active"),
+        )
+    }
+
+    fn accepted(&self, cm: &mut ConnectionManager, quad: Quad) -> TcpStream {
+        // the packet loop sends the window update on its next tick
+        if let Some(c) = cm.connections.get_mut(&quad) {
+            c.accepted();
+        }
+        TcpStream {
+            quad,
+            h: self.h.clone(),
+        }
+    }
+}
+
+/// What `accept` fails with on a closed listener: one the application closed, or one the
+/// interface stopped along with everything else.
+fn listener_closed(interface_down: bool) -> io::Error {
+    if interface_down {
+        io::Error::new(io::ErrorKind::ConnectionAborted, "the interface is down")
+    } else {
+        io::Error::new(io::ErrorKind::InvalidInput, "listener was closed")
+    }
+}
+
+fn check_timeout(timeout: Option<Duration>) -> io::Result<()> {
+    if timeout == Some(Duration::ZERO) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "cannot set a zero duration timeout",
+        ));
+    }
+    Ok(())
+}
+
+fn check_md5_key(key: &[u8]) -> io::Result<()> {
+    if key.is_empty() || key.len() > tcp::MAX_MD5_KEY {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "TCP MD5 keys are 1 to 80 bytes long",
+        ));
+    }
+    Ok(())
+}
+
+/// Copy the bytes of `ring` from `from` on into `dst`, as many as it holds.
+fn copy_out(ring: &VecDeque<u8>, from: usize, dst: &mut [u8]) {
+    let (head, tail) = ring.as_slices();
+    let end = from + dst.len();
+    let in_head = &head[cmp::min(from, head.len())..cmp::min(end, head.len())];
+    dst[..in_head.len()].copy_from_slice(in_head);
+    let in_tail = &tail[from.saturating_sub(head.len())..end.saturating_sub(head.len())];
+    dst[in_head.len()..].copy_from_slice(in_tail);
+}
+
+/// Read up to `n` bytes of `file` from `offset` on onto the end of `ring`, straight into its
+/// storage, returning how many there were before the end of the file.
+fn append_from_file(
+    ring: &mut VecDeque<u8>,
+    file: &File,
+    offset: u64,
+    n: usize,
+) -> io::Result<usize> {
+    let start = ring.len();
+    ring.resize(start + n, 0);
+    // the new bytes are the last `n`, which may start in the head and run on into the tail
+    let (head, tail) = ring.as_mut_slices();
+    let in_tail = cmp::min(n, tail.len());
+    let (head_len, tail_len) = (head.len(), tail.len());
+    let parts = [
+        &mut head[head_len - (n - in_tail)..],
+        &mut tail[tail_len - in_tail..],
+    ];
+    let mut read = 0;
+    let mut result = Ok(());
+    for part in parts {
+        let mut filled = 0;
+        while filled < part.len() {
+            match file.read_at(&mut part[filled..], offset + (read + filled) as u64) {
+                Ok(0) => break,
+                Ok(k) => filled += k,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        read += filled;
+        if filled < part.len() {
+            break;
+        }
+    }
+    ring.truncate(start + read);
+    result.map(|()| read)
+}
+
+/// When a blocking call on `quad` that starts now should give up, by the timeout `timeout`
+/// picks.
+fn deadline(
+    cm: &ConnectionManager,
+    quad: Quad,
+    timeout: impl FnOnce(&tcp::Connection) -> Option<Duration>,
+) -> Option<Instant> {
+    let timeout = cm.connections.get(&quad).and_then(timeout)?;
+    Some(Instant::now() + timeout)
+}
+
+/// Wait on `var` like `Condvar::wait` does, but fail with `WouldBlock` once `deadline` has
+/// passed.
+fn wait_until<'a>(
+    var: &Condvar,
+    cm: MutexGuard<'a, ConnectionManager>,
+    deadline: Option<Instant>,
+) -> io::Result<MutexGuard<'a, ConnectionManager>> {
+    let Some(deadline) = deadline else {
+        return Ok(var.wait(cm).unwrap());
+    };
+    let now = Instant::now();
+    if now >= deadline {
+        return Err(io::Error::new(io::ErrorKind::WouldBlock, "timed out"));
+    }
+    Ok(var.wait_timeout(cm, deadline - now).unwrap().0)
+}
@@ -1,74 +1,29 @@
-use std::collections::HashMap;
 use std::io;
-use std::net::Ipv4Addr;
-
-mod tcp;
-
-#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
-struct Quad {
-    src: (Ipv4Addr, u16),
-    dst: (Ipv4Addr, u16),
-}
+use std::io::prelude::*;
+use std::net::Shutdown;
+use std::thread;
 
 fn main() -> io::Result<()> {
-    let mut connections: HashMap<Quad, tcp::Connection> = Default::default();
-    let mut nic = tun_tap::Iface::without_packet_info("tun0", tun_tap::Mode::Tun)?;
-    let mut buf = [0u8; 1504];
-    loop {
-        let nbytes = nic.recv(&mut buf[..])?;
-        // if s/without_packet_info/new/:
-        //
-        // let _eth_flags = u16::from_be_bytes([buf[0], buf[1]]);
-        // let eth_proto = u16::from_be_bytes([buf[2], buf[3]]);
-        // if eth_proto != 0x0800 {
-        //    // not ipv4
-        //    continue;
-        // }
-        //
-        // and also include on send
-
-        match etherparse::Ipv4HeaderSlice::from_slice(&buf[..nbytes]) {
-            Ok(iph) => {
-                let src = iph.source_addr();
-                let dst = iph.destination_addr();
-                if iph.protocol() != 0x06 {
-                    // not tcp
-                    continue;
+    let mut i = trust::Interface::new()?;
+    eprintln!("created interface");
+    let l1 = i.bind(8000)?;
+    while let Ok(mut stream) = l1.accept() {
+        eprintln!("got connection!");
+        thread::spawn(move || {
+            stream.write_all(b"hello from rust-tcp!\n").unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+            loop {
+                let mut buf = [0; 512];
+                let n = stream.read(&mut buf[..]).unwrap();
+                eprintln!("read {}b of data", n);
+                if n == 0 {
+                    eprintln!("no more data!");
+                    break;
+                } else {
+                    println!("{}", String::from_utf8_lossy(&buf[..n]));
                 }
-
-                match etherparse::TcpHeaderSlice::from_slice(&buf[iph.slice().len()..nbytes]) {
-                    Ok(tcph) => {
-                        use std::collections::hash_map::Entry;
-                        let datai = iph.slice().len() + tcph.slice().len();
-                        match connections.entry(Quad {
-                            src: (src, tcph.source_port()),
-                            dst: (dst, tcph.destination_port()),
-                        }) {
-                            Entry::Occupied(mut c) => {
-                                c.get_mut()
-                                    .on_packet(&mut nic, iph, tcph, &buf[datai..nbytes])?;
-                            }
-                            Entry::Vacant(mut e) => {
-                                if let Some(c) = tcp::Connection::accept(
-                                    &mut nic,
-                                    iph,
-                                    tcph,
-                                    &buf[datai..nbytes],
-                                )? {
-                                    e.insert(c);
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("ignoring weird tcp packet {:?}", e)
-                    }
-                }
-            }
-            Err(e) => {
-                // eprintln!("ignoring weird packet {:?}", e)
             }
-        }
+        });
     }
     Ok(())
 }
@@ -0,0 +1,167 @@
+use std::cmp;
+use std::time::Duration;
+
+use crate::{tcp, ConnectionManager, Interface, Quad};
+
+/// How long data has to wait unread before its connection counts as not reading, and has its
+/// window clamped ahead of those that do.
+const UNREAD: Duration = Duration::from_millis(100);
+
+/// How close an interface is to its receive memory limit; see
+/// [`InterfaceBuilder::recv_memory_limit`](crate::InterfaceBuilder::recv_memory_limit).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MemoryPressure {
+    /// every connection gets the window its buffer has room for
+    #[default]
+    Normal,
+    /// connections whose application is not reading share what those that do leave over
+    ClampingUnread,
+    /// connections whose application is reading share what is left, and the rest get no more
+    ClampingAll,
+}
+
+/// What the receive buffers of an interface's connections hold, as
+/// [`Interface::recv_memory`] has it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RecvMemory {
+    /// the limit, if there is one
+    pub limit: Option<usize>,
+    /// data received that the applications have not read yet
+    pub buffered: usize,
+    /// that, and what the windows we advertised let peers send on top of it
+    pub committed: usize,
+    pub pressure: MemoryPressure,
+}
+
+/// The interface's receive memory limit, and what the packet loop made of it on its last tick.
+#[derive(Debug, Default)]
+pub(crate) struct RecvBudget {
+    limit: Option<usize>,
+    pressure: MemoryPressure,
+    /// how much of the limit no connection may use yet, for connections opened before the
+    /// next tick
+    spare: usize,
+}
+
+impl RecvBudget {
+    pub(crate) fn new(limit: Option<usize>) -> Self {
+        RecvBudget {
+            limit,
+            pressure: MemoryPressure::Normal,
+            spare: limit.unwrap_or(0),
+        }
+    }
+}
+
+impl Interface {
+    /// How much memory the connections' receive buffers hold, and how close that is to the
+    /// limit.
+    pub fn recv_memory(&self) -> RecvMemory {
+        let cm = self.ih.as_ref().unwrap().manager.lock().unwrap();
+        let mut memory = RecvMemory {
+            limit: cm.recv_budget.limit,
+            pressure: cm.recv_budget.pressure,
+            ..Default::default()
+        };
+        for c in cm.connections.values() {
+            let rcv = c.rcv_memory();
+            memory.buffered += rcv.buffered;
+            memory.committed += rcv.committed;
+        }
+        memory
+    }
+}
+
+impl ConnectionManager {
+    /// Share out what the receive memory limit leaves, on a tick of the packet loop. Each
+    /// connection may have no more than its share on top of what it has committed already,
+    /// so that the buffers and windows together stay within the limit; an eighth of what is
+    /// left is kept back for connections opened before the next tick. Connections whose
+    /// application is not reading give up their share first.
+    pub(crate) fn balance_recv_memory(&mut self) {
+        let Some(limit) = self.recv_budget.limit else {
+            return;
+        };
+        let mut committed = 0;
+        let mut wanted = 0;
+        let mut reading = Vec::new();
+        let mut unread = Vec::new();
+        for (&q, c) in &self.connections {
+            let rcv = c.rcv_memory();
+            committed += rcv.committed;
+            wanted += rcv.wanted;
+            let share = (q, rcv.committed, rcv.wanted);
+            if rcv
+                .unread_for
+                .is_some_and(|unread_for| unread_for >= UNREAD)
+            {
+                unread.push(share);
+            } else {
+                reading.push(share);
+            }
+        }
+        let free = limit.saturating_sub(committed);
+        let reading_wants: usize = reading.iter().map(|&(_, _, w)| w).sum();
+        let (pressure, mut budget) = if committed + wanted <= limit {
+            (MemoryPressure::Normal, free)
+        } else if reading_wants <= free - free / 8 {
+            (MemoryPressure::ClampingUnread, free - free / 8)
+        } else {
+            (MemoryPressure::ClampingAll, free - free / 8)
+        };
+        let shared = budget;
+        let mut grants = Vec::with_capacity(self.connections.len());
+        match pressure {
+            MemoryPressure::Normal => {
+                grants.extend(reading.iter().chain(&unread).map(|&(q, c, w)| (q, c + w)));
+                budget -= wanted;
+            }
+            MemoryPressure::ClampingUnread => {
+                grants.extend(reading.iter().map(|&(q, c, w)| (q, c + w)));
+                budget -= reading_wants;
+                share_out(&mut unread, &mut budget, &mut grants);
+            }
+            MemoryPressure::ClampingAll => {
+                share_out(&mut reading, &mut budget, &mut grants);
+                grants.extend(unread.iter().map(|&(q, c, _)| (q, c)));
+            }
+        }
+        for (q, grant) in grants {
+            if let Some(c) = self.connections.get_mut(&q) {
+                c.set_rcv_limit(Some(grant));
+            }
+        }
+        self.recv_budget.pressure = pressure;
+        self.recv_budget.spare = free - (shared - budget);
+    }
+
+    /// Give a connection being opened a share of the receive memory kept back for it, before
+    /// it advertises its first window: all it wants, if that is no more than half of what is
+    /// left, and half otherwise.
+    pub(crate) fn admit_recv_memory(&mut self, c: &mut tcp::Connection) {
+        if self.recv_budget.limit.is_none() {
+            return;
+        }
+        let rcv = c.rcv_memory();
+        let grant = cmp::min(rcv.wanted, self.recv_budget.spare / 2);
+        self.recv_budget.spare -= grant;
+        c.set_rcv_limit(Some(rcv.committed + grant));
+    }
+}
+
+/// Share `budget` out among `shares` of (quad, committed, wanted) fairly: those wanting less
+/// than an even split get all they want, and the rest split what that leaves evenly. What is
+/// not given out stays in `budget`.
+fn share_out(
+    shares: &mut [(Quad, usize, usize)],
+    budget: &mut usize,
+    grants: &mut Vec<(Quad, usize)>,
+) {
+    shares.sort_by_key(|&(_, _, wanted)| wanted);
+    for (i, &(q, committed, wanted)) in shares.iter().enumerate() {
+        let grant = cmp::min(wanted, *budget / (shares.len() - i));
+        *budget -= grant;
+        grants.push((q, committed + grant));
+    }
+}
@@ -0,0 +1,602 @@
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::CString;
+use std::io::{self, IoSlice, IoSliceMut};
+use std::net::Ipv4Addr;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+/// How many packets an [`Outbound`] queues for its device in each class. Once the device is
+/// backed up that far, new packets of the class are refused, except for SYNs and RSTs and
+/// those of connections with nothing else in flight.
+const QUEUE_LIMIT: usize = 256;
+
+/// How many bytes each class may send per round of the deficit scheme, once the device is
+/// backed up, by [`Priority::index`]: high, normal, low.
+const QUANTA: [usize; 3] = [4 * DEFAULT_MTU, 2 * DEFAULT_MTU, DEFAULT_MTU];
+
+/// The MTU of a device that cannot tell: Ethernet's.
+pub(crate) const DEFAULT_MTU: usize = 1500;
+
+/// The smallest MTU IPv4 allows (RFC 791).
+pub(crate) const MIN_MTU: usize = 68;
+
+/// How many packets the packet loop takes from a device at once, before processing them.
+pub(crate) const BATCH: usize = 32;
+
+/// Longest interface name, with its NUL (linux/if.h).
+const IFNAMSIZ: usize = 16;
+
+/// Keeps [`Nic`] to the devices here, so that its methods can change without breaking anyone.
+pub(crate) mod sealed {
+    pub trait Sealed {}
+}
+
+/// A device that carries raw IPv4 packets to and from the network, like a tun device.
+///
+/// The packet loop polls the device's file descriptor for readability, and only calls `recv`
+/// once it is readable.
+///
+/// The trait is sealed. It is implemented for tun devices, [`SimNic`](crate::SimNic) and, with
+/// the `backend-raw` feature, [`RawSocket`](crate::RawSocket).
+pub trait Nic: sealed::Sealed + AsRawFd + Send {
+    /// Receive a single packet into `buf`, returning its length, or 0 if what arrived wasn't a
+    /// packet for us after all.
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Receive as many packets as have arrived, up to one per buffer in `bufs`, returning how
+    /// many: the `i`th goes into `bufs[i]`, with its length (or 0, as for `recv`) in `lens[i]`.
+    ///
+    /// Like `recv`, it is only called once the device is readable, and must not block after the
+    /// first packet. Devices that can fetch several packets in one system call should; the
+    /// default calls `recv` for as long as the device stays readable.
+    fn recv_batch(&mut self, bufs: &mut [IoSliceMut<'_>], lens: &mut [usize]) -> io::Result<usize> {
+        let max = cmp::min(bufs.len(), lens.len());
+        let mut n = 0;
+        while n < max && (n == 0 || readable(self.as_raw_fd())?) {
+            match self.recv(&mut bufs[n]) {
+                Ok(len) => lens[n] = len,
+                // keep what has arrived so far
+                Err(e) if n > 0 && e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    /// Send the packet in `buf`, returning its length.
+    ///
+    /// A device that is out of room may fail with `WouldBlock`, or return less than the whole
+    /// length; either way, the packet counts as not sent.
+    fn send(&mut self, buf: &[u8]) -> io::Result<usize>;
+
+    /// Send the packets in `packets` in order, returning how many of them the device took
+    /// before it ran out of room, or before one failed. Only a failure of the first packet is
+    /// an error: the packet loop drops that one and carries on with the rest, unless the error
+    /// says the device is gone.
+    ///
+    /// Devices that can send several packets in one system call should; the default calls
+    /// `send` for each in turn.
+    fn send_batch(&mut self, packets: &[IoSlice<'_>]) -> io::Result<usize> {
+        for (n, packet) in packets.iter().enumerate() {
+            match self.send(packet) {
+                Ok(len) if len == packet.len() => {}
+                Ok(_) => return Ok(n),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(n),
+                // keep what went out so far; the failing packet comes first next time
+                Err(_) if n > 0 => return Ok(n),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(packets.len())
+    }
+
+    /// The largest packet the device carries. Devices that cannot tell report Ethernet's.
+    fn mtu(&self) -> io::Result<usize> {
+        Ok(DEFAULT_MTU)
+    }
+}
+
+impl sealed::Sealed for tun_tap::Iface {}
+
+impl Nic for tun_tap::Iface {
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        tun_tap::Iface::recv(self, buf)
+    }
+
+    fn send(&mut self, buf: &[u8]) -> io::Result<usize> {
+        tun_tap::Iface::send(self, buf)
+    }
+
+    fn mtu(&self) -> io::Result<usize> {
+        interface_mtu(self.name())
+    }
+}
+
+/// The MTU configured on the network interface `ifname` (`SIOCGIFMTU`).
+pub(crate) fn interface_mtu(ifname: &str) -> io::Result<usize> {
+    /// `struct ifreq` as `SIOCGIFMTU` fills it in
+    #[repr(C)]
+    struct IfReq {
+        name: [libc::c_char; IFNAMSIZ],
+        mtu: libc::c_int,
+        _rest: [u8; 20],
+    }
+
+    let name = CString::new(ifname)
+        .ok()
+        .filter(|name| name.as_bytes_with_nul().len() <= IFNAMSIZ)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "bad interface name"))?;
+    let mut req = IfReq {
+        name: [0; IFNAMSIZ],
+        mtu: 0,
+        _rest: [0; 20],
+    };
+    for (dst, &src) in req.name.iter_mut().zip(name.as_bytes()) {
+        *dst = src as libc::c_char;
+    }
+
+    // any socket will do for asking about an interface
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let sock = unsafe { OwnedFd::from_raw_fd(fd) };
+    if unsafe { libc::ioctl(sock.as_raw_fd(), libc::SIOCGIFMTU, &mut req) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(req.mtu as usize)
+}
+
+/// Identifies one of the devices registered with an [`InterfaceBuilder`](crate::InterfaceBuilder).
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+pub struct DeviceId(pub(crate) usize);
+
+/// A device failed for good: its file descriptor went bad, or the device itself went away. The
+/// packet loop stops over it, failing every connection, and the error comes out of
+/// [`Interface::run`](crate::Interface::run) or
+/// [`Interface::poll_once`](crate::Interface::poll_once) as the inner error of an
+/// [`io::Error`], which [`InterfaceError::of`] finds. Any other error a device reports just
+/// costs the packet: on receiving, as [`DropReason::RecvFailed`](crate::DropReason::RecvFailed),
+/// and on sending, as one of [`Interface::send_failures`](crate::Interface::send_failures).
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct InterfaceError {
+    pub device: DeviceId,
+    pub error: io::Error,
+}
+
+impl InterfaceError {
+    /// The device failure behind `e`, if that is what it is.
+    pub fn of(e: &io::Error) -> Option<&InterfaceError> {
+        e.get_ref()?.downcast_ref()
+    }
+
+    /// Whether `e` from a device means it is gone for good, rather than that it had trouble with
+    /// one packet.
+    pub(crate) fn is_fatal(e: &io::Error) -> bool {
+        matches!(
+            e.raw_os_error(),
+            Some(libc::EBADF | libc::ENODEV | libc::ENXIO | libc::EIO)
+        )
+    }
+
+    pub(crate) fn wrap(device: DeviceId, error: io::Error) -> io::Error {
+        io::Error::new(error.kind(), InterfaceError { device, error })
+    }
+}
+
+impl std::fmt::Display for InterfaceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "device {} failed: {}", self.device.0, self.error)
+    }
+}
+
+impl std::error::Error for InterfaceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// What the packet loop needs to know about a device besides how to talk to it.
+#[derive(Clone, Debug)]
+pub(crate) struct Device {
+    /// the addresses we own on this device; empty means any address routed to it
+    pub(crate) addrs: Vec<Ipv4Addr>,
+    pub(crate) mtu: usize,
+}
+
+impl Device {
+    pub(crate) fn owns(&self, addr: Ipv4Addr) -> bool {
+        self.addrs.is_empty() || self.addrs.contains(&addr)
+    }
+}
+
+/// How urgently a connection's segments leave once its device is backed up; see
+/// [`TcpStream::set_priority`](crate::TcpStream::set_priority).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// bulk transfers, which can wait
+    Low,
+    #[default]
+    Normal,
+    /// interactive traffic, which should not wait behind bulk transfers
+    High,
+}
+
+impl Priority {
+    /// Where the class sits in [`Outbound`]'s queues: the highest first.
+    fn index(self) -> usize {
+        match self {
+            Priority::High => 0,
+            Priority::Normal => 1,
+            Priority::Low => 2,
+        }
+    }
+}
+
+/// A device, along with the packets queued for it.
+///
+/// Connections only queue their packets; the packet loop hands them to the device once per
+/// round, in batches, and keeps what the device has no room for until it does. Only once a
+/// queue is full does it refuse packets: anything but a SYN or an RST is then as good as lost
+/// on the wire. A connection sends refused data again once the device is writable, or when its
+/// retransmission timer runs out.
+///
+/// Pure ACKs, SYNs and RSTs go out ahead of everything else, since they are small and hold up
+/// whole connections. The rest waits by the [`Priority`] of the connection that sent it, and
+/// the classes take turns by deficit round robin: each turn a class may send up to its share of
+/// [`QUANTA`], so a higher class gets more of the device without starving a lower one. A FIN
+/// keeps to its connection's class: the peer drops segments that arrive ahead of the data
+/// before them, so overtaking it would only get the FIN resent.
+///
+/// Packets to one of our own addresses never reach the device: they wait in `looped` for the
+/// packet loop to process them as if they had just arrived.
+///
+/// A packet the device fails to send is dropped, as if lost on the wire, and counted in
+/// `failed`; only an error that says the device is gone fails the queue, as an
+/// [`InterfaceError`].
+pub(crate) struct Outbound {
+    nic: Box<dyn Nic>,
+    device: DeviceId,
+    /// pure ACKs, SYNs and RSTs for the device, in the order they were sent
+    control: VecDeque<Vec<u8>>,
+    /// the rest of the packets for the device, by [`Priority::index`], each in the order they
+    /// were sent
+    classes: [VecDeque<Vec<u8>>; 3],
+    /// how many bytes each class may still send this turn
+    deficits: [usize; 3],
+    /// the class whose turn it is
+    turn: usize,
+    /// whether the device has refused a packet since it last became writable
+    blocked: bool,
+    /// the addresses given to any of the interface's devices
+    local: Vec<Ipv4Addr>,
+    looped: VecDeque<Vec<u8>>,
+    /// for each connection with segments in the queues that take up sequence space, how far
+    /// its sequence numbers have gone to the device
+    held: HashMap<Flow, Held>,
+    /// packets the device failed to send since the packet loop last took the count
+    failed: u64,
+}
+
+/// A connection's addresses and ports, as its segments have them: source, then destination.
+pub(crate) type Flow = ((Ipv4Addr, u16), (Ipv4Addr, u16));
+
+/// What [`Outbound`] has of a connection's segments that take up sequence space.
+struct Held {
+    /// how many of them are still queued
+    queued: usize,
+    /// the sequence number just past the last of them the device took
+    emitted: u32,
+}
+
+impl Outbound {
+    pub(crate) fn new(nic: Box<dyn Nic>, device: DeviceId, local: Vec<Ipv4Addr>) -> Self {
+        Outbound {
+            nic,
+            device,
+            control: VecDeque::new(),
+            classes: Default::default(),
+            deficits: [QUANTA[0], 0, 0],
+            turn: 0,
+            blocked: false,
+            local,
+            looped: VecDeque::new(),
+            held: HashMap::new(),
+            failed: 0,
+        }
+    }
+
+    pub(crate) fn recv_batch(&mut self, pool: &mut RecvPool) -> io::Result<usize> {
+        let mut bufs = pool.bufs.each_mut().map(|buf| IoSliceMut::new(buf));
+        pool.received = self.nic.recv_batch(&mut bufs, &mut pool.lens)?;
+        Ok(pool.received)
+    }
+
+    /// Queue `packet` for the device, behind those of higher `priority`, returning whether
+    /// there was room for it. A packet from a sender with nothing else in flight (`idle`) gets
+    /// in regardless, so that a busy connection, which refills the queue as fast as it drains,
+    /// cannot shut out a quiet one.
+    ///
+    /// A connection sending gives `snd_nxt`, where its sequence numbers stood before the
+    /// packet: as far as the device has them, if none of its segments are queued.
+    pub(crate) fn send(
+        &mut self,
+        packet: Vec<u8>,
+        priority: Priority,
+        idle: bool,
+        snd_nxt: Option<u32>,
+    ) -> io::Result<bool> {
+        if self.is_local(&packet) {
+            self.looped.push_back(packet);
+            return Ok(true);
+        }
+        let segment = segment(&packet);
+        let class = match segment {
+            Some((flags, len)) if flags & (SYN | RST) != 0 || (flags & FIN == 0 && len == 0) => {
+                None
+            }
+            _ => Some(priority.index()),
+        };
+        if self.queue(class).len() >= QUEUE_LIMIT {
+            // make room, if the device has any
+            self.flush()?;
+        }
+        // an RST goes out only once, and a lost SYN holds the handshake up for a whole
+        // initial RTO
+        let opens_or_resets = segment.is_some_and(|(flags, _)| flags & (SYN | RST) != 0);
+        if self.queue(class).len() >= QUEUE_LIMIT && !opens_or_resets && !idle {
+            return Ok(false);
+        }
+        if let (Some(snd_nxt), Some((flow, _))) = (snd_nxt, sequenced(&packet)) {
+            self.held
+                .entry(flow)
+                .or_insert(Held {
+                    queued: 0,
+                    emitted: snd_nxt,
+                })
+                .queued += 1;
+        }
+        self.queue(class).push_back(packet);
+        Ok(true)
+    }
+
+    /// How far the sequence numbers of the connection that sends as `flow` have gone to the
+    /// device, if any of its segments are still queued; if none are, all of them have.
+    pub(crate) fn emitted(&self, flow: Flow) -> Option<u32> {
+        self.held.get(&flow).map(|held| held.emitted)
+    }
+
+    /// Note that the device took `packet`.
+    fn on_emitted(&mut self, packet: &[u8]) {
+        let Some((flow, end)) = sequenced(packet) else {
+            return;
+        };
+        let Some(held) = self.held.get_mut(&flow) else {
+            return;
+        };
+        if end.wrapping_sub(held.emitted) < 1 << 31 {
+            held.emitted = end;
+        }
+        held.queued -= 1;
+        if held.queued == 0 {
+            self.held.remove(&flow);
+        }
+    }
+
+    /// The queue for the control packets with `None`, or else for the class.
+    fn queue(&mut self, class: Option<usize>) -> &mut VecDeque<Vec<u8>> {
+        match class {
+            Some(class) => &mut self.classes[class],
+            None => &mut self.control,
+        }
+    }
+
+    /// Take the packet to go out next, along with its class (see [`Outbound::queue`]).
+    fn pop(&mut self) -> Option<(Option<usize>, Vec<u8>)> {
+        if let Some(packet) = self.control.pop_front() {
+            return Some((None, packet));
+        }
+        if self.classes.iter().all(VecDeque::is_empty) {
+            return None;
+        }
+        loop {
+            let class = self.turn;
+            match self.classes[class].front() {
+                Some(packet) if packet.len() <= self.deficits[class] => {
+                    self.deficits[class] -= packet.len();
+                    return Some((Some(class), self.classes[class].pop_front()?));
+                }
+                Some(_) => {}
+                // a class with nothing to send saves nothing up for later
+                None => self.deficits[class] = 0,
+            }
+            self.turn = (self.turn + 1) % QUANTA.len();
+            if !self.classes[self.turn].is_empty() {
+                self.deficits[self.turn] += QUANTA[self.turn];
+            }
+        }
+    }
+
+    /// Put back a packet [`Outbound::pop`] took, which the device had no room for after all.
+    /// Its class gets back what it spent on it, but no more than a turn's worth: a class that
+    /// saved up a whole batch would hold the device for that long once it has room again.
+    fn unpop(&mut self, class: Option<usize>, packet: Vec<u8>) {
+        if let Some(class) = class {
+            self.deficits[class] = cmp::min(self.deficits[class] + packet.len(), QUANTA[class]);
+        }
+        self.queue(class).push_front(packet);
+    }
+
+    /// A packet sent to one of our own addresses, to be processed as received.
+    pub(crate) fn take_looped(&mut self) -> Option<Vec<u8>> {
+        self.looped.pop_front()
+    }
+
+    /// Whether `packet` is for one of our own addresses: one given to a device, or the one it
+    /// comes from. (A device that takes any address does not make every address ours.)
+    fn is_local(&self, packet: &[u8]) -> bool {
+        match (source(packet), destination(packet)) {
+            (Some(src), Some(dst)) => src == dst || self.local.contains(&dst),
+            _ => false,
+        }
+    }
+
+    /// How many packets the device failed to send since the last call.
+    pub(crate) fn take_failed(&mut self) -> u64 {
+        std::mem::take(&mut self.failed)
+    }
+
+    /// Whether the packet loop should poll the device for writability.
+    pub(crate) fn wants_write(&self) -> bool {
+        self.blocked || !self.control.is_empty() || self.classes.iter().any(|q| !q.is_empty())
+    }
+
+    /// The device may have room again: send what is queued, as far as it goes.
+    pub(crate) fn on_writable(&mut self) -> io::Result<()> {
+        self.blocked = false;
+        self.flush()
+    }
+
+    /// Hand the device what is queued, in batches, until it is all gone or the device has no
+    /// more room.
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        if self.blocked {
+            // wait for it to become writable
+            return Ok(());
+        }
+        let mut batch: [(Option<usize>, Vec<u8>); BATCH] = Default::default();
+        loop {
+            let mut n = 0;
+            while n < BATCH {
+                let Some(next) = self.pop() else {
+                    break;
+                };
+                batch[n] = next;
+                n += 1;
+            }
+            if n == 0 {
+                return Ok(());
+            }
+            let mut packets = [IoSlice::new(&[]); BATCH];
+            for (slice, (_, packet)) in packets.iter_mut().zip(&batch[..n]) {
+                *slice = IoSlice::new(packet);
+            }
+            // a device that fails the first packet took none, but has no less room for it
+            let (sent, failed) = match self.nic.send_batch(&packets[..n]) {
+                Ok(sent) => (cmp::min(sent, n), false),
+                Err(e) if InterfaceError::is_fatal(&e) => {
+                    return Err(InterfaceError::wrap(self.device, e));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => (0, false),
+                Err(_) => (1, true),
+            };
+            if failed {
+                // as good as lost on the wire
+                self.failed += 1;
+            }
+            for (_, packet) in &batch[..sent] {
+                self.on_emitted(packet);
+            }
+            // what the device had no room for, or did not get to, goes back in front, in order
+            for (class, packet) in batch[sent..n].iter_mut().rev() {
+                self.unpop(*class, std::mem::take(packet));
+            }
+            if sent < n && !failed {
+                self.blocked = true;
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Buffers for the packet loop to receive a batch of packets into, allocated once and reused
+/// for every batch.
+pub(crate) struct RecvPool {
+    bufs: [Vec<u8>; BATCH],
+    lens: [usize; BATCH],
+    /// how many of the buffers the last batch filled
+    received: usize,
+}
+
+impl RecvPool {
+    /// A pool of buffers that each hold a packet of up to `size` bytes.
+    pub(crate) fn new(size: usize) -> Self {
+        RecvPool {
+            bufs: std::array::from_fn(|_| vec![0; size]),
+            lens: [0; BATCH],
+            received: 0,
+        }
+    }
+
+    /// The packets of the last batch.
+    pub(crate) fn packets(&self) -> impl Iterator<Item = &[u8]> {
+        self.bufs[..self.received]
+            .iter()
+            .zip(&self.lens)
+            .map(|(buf, &len)| &buf[..len])
+    }
+}
+
+/// Whether `fd` has something to read right now.
+fn readable(fd: RawFd) -> io::Result<bool> {
+    let mut pfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let n = unsafe { libc::poll(&mut pfd, 1, 0) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(pfd.revents & libc::POLLIN != 0)
+}
+
+const FIN: u8 = 0x01;
+const SYN: u8 = 0x02;
+const RST: u8 = 0x04;
+
+/// The flags of a TCP segment, and how much data it carries, if `packet` is one.
+fn segment(packet: &[u8]) -> Option<(u8, usize)> {
+    let ihl = (*packet.first()? & 0x0f) as usize * 4;
+    if packet.get(9) != Some(&6) {
+        return None;
+    }
+    let doff = (*packet.get(ihl + 12)? >> 4) as usize * 4;
+    let flags = *packet.get(ihl + 13)?;
+    Some((flags, packet.len().saturating_sub(ihl + doff)))
+}
+
+/// The connection a TCP segment is from, and the sequence number just past it, if `packet` is
+/// one that takes up sequence space.
+fn sequenced(packet: &[u8]) -> Option<(Flow, u32)> {
+    let (flags, len) = segment(packet)?;
+    if flags & RST != 0 || (flags & (SYN | FIN) == 0 && len == 0) {
+        return None;
+    }
+    let ihl = (*packet.first()? & 0x0f) as usize * 4;
+    let port = |at: usize| Some(u16::from_be_bytes(packet.get(at..at + 2)?.try_into().ok()?));
+    let seq = u32::from_be_bytes(packet.get(ihl + 4..ihl + 8)?.try_into().ok()?);
+    let flow = (
+        (source(packet)?, port(ihl)?),
+        (destination(packet)?, port(ihl + 2)?),
+    );
+    let end = seq
+        .wrapping_add(len as u32)
+        .wrapping_add((flags & SYN != 0) as u32)
+        .wrapping_add((flags & FIN != 0) as u32);
+    Some((flow, end))
+}
+
+/// The source address of an IPv4 packet.
+fn source(packet: &[u8]) -> Option<Ipv4Addr> {
+    let octets: [u8; 4] = packet.get(12..16)?.try_into().ok()?;
+    Some(Ipv4Addr::from(octets))
+}
+
+/// The destination address of an IPv4 packet.
+pub(crate) fn destination(packet: &[u8]) -> Option<Ipv4Addr> {
+    let octets: [u8; 4] = packet.get(16..20)?.try_into().ok()?;
+    Some(Ipv4Addr::from(octets))
+}
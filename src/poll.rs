@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::Shutdown;
+use std::time::Duration;
+
+use crate::{CloseReason, Driver, Interface, Quad, TracedPacket};
+
+/// Something that happened to a connection on a polled interface; see
+/// [`Interface::poll_once`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum InterfaceEvent {
+    /// a listener took in a connection, which is the application's to read and write from now
+    /// on; it may still be finishing its handshake if its SYN brought data with Fast Open
+    NewConnection(Quad),
+    /// there is data to read, or the end of the stream, and there was not as of the last poll
+    /// or read; data waits, as it does for a blocked reader, until the connection's read
+    /// trigger and read coalescing let it through
+    Readable(Quad),
+    /// the connection can take data, and could not as of the last poll or write
+    Writable(Quad),
+    /// the connection is done in both directions, for the reason given
+    Closed(Quad, CloseReason),
+    /// the connection failed; [`Interface::close_on`] clears it away. Along with the error come
+    /// the segments it traced last, if [`TcpConfig::trace_packets`](crate::TcpConfig::trace_packets) asks for
+    /// any.
+    Error(Quad, io::Error, Vec<TracedPacket>),
+}
+
+/// What a connection looked like to the application, as far as events go.
+#[derive(Default)]
+struct Seen {
+    readable: bool,
+    writable: bool,
+    done: bool,
+    failed: bool,
+}
+
+/// What [`Interface::poll_once`] runs the packet loop with, and what it has reported so far.
+pub(crate) struct Polled {
+    pub(crate) driver: Driver,
+    seen: HashMap<Quad, Seen>,
+}
+
+impl Polled {
+    pub(crate) fn new(driver: Driver) -> Self {
+        Polled {
+            driver,
+            seen: HashMap::new(),
+        }
+    }
+}
+
+impl Interface {
+    /// Run one round of the packet loop on the calling thread, for an interface built with
+    /// [`InterfaceBuilder::build_polled`](crate::InterfaceBuilder::build_polled): wait up to
+    /// `timeout` for packets, take in a batch from each device that has some, run the
+    /// connections' timers if they are due, and send what all of that produced. Then report
+    /// what changed for the application.
+    ///
+    /// Timers only run while the application polls, so it should poll again within a few
+    /// milliseconds, whether or not there were events; the call returns early, without any, if
+    /// the timers are due before `timeout` is up.
+    ///
+    /// Listeners hand the connections they take in to this call rather than to
+    /// [`TcpListener::accept`](crate::TcpListener::accept), as [`InterfaceEvent::NewConnection`].
+    /// Those, and streams handed over with [`TcpStream::into_quad`](crate::TcpStream::into_quad),
+    /// are read and written with [`Interface::read_on`] and [`Interface::write_on`], which never
+    /// block, and closed with [`Interface::close_on`]. Events are edge-triggered: a connection
+    /// is reported readable once, until a read finds nothing more, and likewise writable.
+    pub fn poll_once(&mut self, timeout: Duration) -> io::Result<Vec<InterfaceEvent>> {
+        let ih = self.ih.as_ref().unwrap();
+        let polled = self.polled.as_mut().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the interface runs its own packet loop",
+            )
+        })?;
+        polled.driver.round(ih, Some(timeout))?;
+
+        let mut events = Vec::new();
+        let mut cmg = ih.manager.lock().unwrap();
+        let cm = &mut *cmg;
+        for listener in cm.listeners.values_mut() {
+            for quad in listener.queue.drain(..) {
+                if let Some(c) = cm.connections.get_mut(&quad) {
+                    c.accepted();
+                }
+                polled.seen.insert(quad, Seen::default());
+                events.push(InterfaceEvent::NewConnection(quad));
+            }
+        }
+
+        for (&quad, c) in cm.connections.iter_mut() {
+            if c.orphaned || c.half_open {
+                // closed by the application, which is done hearing about it, or not yet its
+                continue;
+            }
+            let seen = polled.seen.entry(quad).or_default();
+            if let Some(e) = c.failure() {
+                if !std::mem::replace(&mut seen.failed, true) {
+                    events.push(match c.close_reason() {
+                        // the application's own doing, not a failure to tell it about
+                        Some(CloseReason::LocalAbort) => {
+                            InterfaceEvent::Closed(quad, CloseReason::LocalAbort)
+                        }
+                        _ => InterfaceEvent::Error(quad, e, c.trace_dump()),
+                    });
+                }
+                continue;
+            }
+            // held back, as blocked readers are, until the read trigger and read coalescing
+            // let it go
+            let readable = (!c.incoming.is_empty() || c.is_rcv_closed()) && c.wakes_readers();
+            if readable && !std::mem::replace(&mut seen.readable, true) {
+                events.push(InterfaceEvent::Readable(quad));
+            }
+            let writable = c.is_writable();
+            if writable && !std::mem::replace(&mut seen.writable, true) {
+                events.push(InterfaceEvent::Writable(quad));
+            }
+            if let Some(reason) = c.close_reason() {
+                if !std::mem::replace(&mut seen.done, true) {
+                    events.push(InterfaceEvent::Closed(quad, reason));
+                }
+            }
+        }
+        // and those that ran their course since the last poll
+        polled.seen.retain(|quad, seen| {
+            if cm.connections.contains_key(quad) {
+                return true;
+            }
+            match cm.close_reasons.get(quad) {
+                Some(&reason) if !seen.done && !seen.failed => {
+                    events.push(InterfaceEvent::Closed(*quad, reason));
+                }
+                _ => {}
+            }
+            false
+        });
+        Ok(events)
+    }
+
+    /// Read what has arrived on `quad` into `buf`, without waiting: fails with `WouldBlock` if
+    /// nothing has, and returns 0 at the end of the stream.
+    pub fn read_on(&mut self, quad: Quad, buf: &mut [u8]) -> io::Result<usize> {
+        let ih = self.ih.as_ref().unwrap();
+        let read = ih.read_with(quad, false, buf.len(), true, |incoming, n| {
+            crate::copy_out(incoming, 0, &mut buf[..n])
+        });
+        if let (Err(e), Some(seen)) = (
+            &read,
+            self.polled.as_mut().and_then(|p| p.seen.get_mut(&quad)),
+        ) {
+            if e.kind() == io::ErrorKind::WouldBlock {
+                seen.readable = false;
+            }
+        }
+        read
+    }
+
+    /// Write as much of `buf` to `quad` as its send buffer has room for, without waiting:
+    /// fails with `WouldBlock` if it has none.
+    pub fn write_on(&mut self, quad: Quad, buf: &[u8]) -> io::Result<usize> {
+        let ih = self.ih.as_ref().unwrap();
+        let written = ih.write_with(quad, false, buf.len(), |unacked, range| {
+            unacked.extend(&buf[range.clone()]);
+            Ok(range.len())
+        });
+        if let (Err(e), Some(seen)) = (
+            &written,
+            self.polled.as_mut().and_then(|p| p.seen.get_mut(&quad)),
+        ) {
+            if e.kind() == io::ErrorKind::WouldBlock {
+                seen.writable = false;
+            }
+        }
+        written
+    }
+
+    /// Shut the connection on `quad` down for writing, reading or both, as
+    /// [`TcpStream::shutdown`](crate::TcpStream::shutdown) does, while it can still be read
+    /// from or written to in the other direction.
+    pub fn shutdown_on(&mut self, quad: Quad, how: Shutdown) -> io::Result<()> {
+        self.ih.as_ref().unwrap().shutdown(quad, how)
+    }
+
+    /// Close the connection on `quad`, as dropping its stream would: whatever was written still
+    /// goes out, followed by our FIN. A failed connection is cleared away.
+    pub fn close_on(&mut self, quad: Quad) {
+        self.ih
+            .as_ref()
+            .unwrap()
+            .manager
+            .lock()
+            .unwrap()
+            .release(quad);
+        if let Some(polled) = &mut self.polled {
+            polled.seen.remove(&quad);
+        }
+    }
+}
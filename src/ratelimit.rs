@@ -0,0 +1,157 @@
+use std::cmp;
+use std::time::{Duration, Instant};
+
+use crate::stats::RateLimited;
+
+/// How many packets of some kind may go out: `per_second` on average, and up to `burst` back
+/// to back after a quiet spell.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RateLimit {
+    pub per_second: u32,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    /// `per_second` packets a second, of which a whole second's worth may go out at once.
+    pub fn per_second(per_second: u32) -> Self {
+        RateLimit {
+            per_second,
+            burst: per_second,
+        }
+    }
+}
+
+/// How many connections one address may hold against a listener at once, counting those
+/// still in their handshake and not those done with, TIME-WAIT included; see
+/// [`TcpListener::set_source_limit`](crate::TcpListener::set_source_limit).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SourceLimit {
+    pub connections: usize,
+    /// what becomes of a SYN from an address that holds that many already
+    pub over: Rejection,
+}
+
+/// How many connections a listener keeps while their handshakes are under way, answered with
+/// a SYN-ACK but not yet acknowledged; see
+/// [`TcpListener::set_half_open_limit`](crate::TcpListener::set_half_open_limit). Each gives
+/// up once its SYN-ACK has gone out
+/// [`TcpConfig::syn_ack_retries`](crate::TcpConfig::syn_ack_retries) more times without an
+/// answer, whatever the limit.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HalfOpenLimit {
+    pub connections: usize,
+    /// what becomes of a SYN that comes in with that many already under way
+    pub when_full: WhenFull,
+}
+
+impl Default for HalfOpenLimit {
+    /// 256 connections, new SYNs dropped beyond that.
+    fn default() -> Self {
+        HalfOpenLimit {
+            connections: 256,
+            when_full: WhenFull::Drop,
+        }
+    }
+}
+
+/// What a listener with as many handshakes under way as it keeps does with another SYN.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum WhenFull {
+    /// drop it, as if it had never arrived; the peer sends it again until it gives up
+    #[default]
+    Drop,
+    /// drop the connection whose handshake has been under way longest, without a word to its
+    /// peer, and answer the SYN in its place, so that a flood of SYNs does not keep out those
+    /// who complete their handshakes promptly
+    RecycleOldest,
+}
+
+/// What a listener does with a SYN it turns away.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Rejection {
+    /// nothing, as if it had never arrived; the peer sends it again until it gives up
+    #[default]
+    Drop,
+    /// answer it with an RST, which fails the peer's connect right away; subject to the
+    /// interface's limit on RSTs
+    Reset,
+}
+
+/// A token bucket enforcing a [`RateLimit`]: it starts out full, and earns a token every
+/// 1/`per_second` of a second, up to `burst`.
+#[derive(Clone, Debug)]
+pub(crate) struct TokenBucket {
+    limit: RateLimit,
+    tokens: u32,
+    /// up to when the tokens earned so far have been counted
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(limit: RateLimit, now: Instant) -> Self {
+        TokenBucket {
+            limit,
+            tokens: limit.burst,
+            refilled: now,
+        }
+    }
+
+    /// Take a token if there is one, returning whether there was.
+    pub(crate) fn take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens == 0 {
+            return false;
+        }
+        self.tokens -= 1;
+        true
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let per_second = self.limit.per_second as u128;
+        let elapsed = now.saturating_duration_since(self.refilled);
+        let earned = elapsed.as_nanos() * per_second / 1_000_000_000;
+        if earned == 0 {
+            return;
+        }
+        let room = (self.limit.burst - cmp::min(self.tokens, self.limit.burst)) as u128;
+        if earned >= room {
+            // full: time spent full earns nothing later on
+            self.tokens = self.limit.burst;
+            self.refilled = now;
+        } else {
+            // keep what was earned towards the next token
+            self.tokens += earned as u32;
+            let spent = earned * 1_000_000_000 / per_second;
+            self.refilled += Duration::from_nanos(spent as u64);
+        }
+    }
+}
+
+/// The interface's limits on replies to segments it has no other use for, and how many
+/// replies they have held back.
+#[derive(Debug, Default)]
+pub(crate) struct ReplyLimits {
+    pub(crate) rst: Option<TokenBucket>,
+    pub(crate) challenge_ack: Option<TokenBucket>,
+    pub(crate) rate_limited: RateLimited,
+}
+
+impl ReplyLimits {
+    /// Whether an RST may go out in reply to a segment now.
+    pub(crate) fn allow_rst(&mut self, now: Instant) -> bool {
+        let allowed = self.rst.as_mut().is_none_or(|b| b.take(now));
+        if !allowed {
+            self.rate_limited.rsts += 1;
+        }
+        allowed
+    }
+
+    /// Whether an ACK may go out in reply to a segment outside the window now.
+    pub(crate) fn allow_challenge_ack(&mut self, now: Instant) -> bool {
+        let allowed = self.challenge_ack.as_mut().is_none_or(|b| b.take(now));
+        if !allowed {
+            self.rate_limited.challenge_acks += 1;
+        }
+        allowed
+    }
+}
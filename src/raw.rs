@@ -0,0 +1,511 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs;
+use std::io::{self, IoSlice, IoSliceMut};
+use std::mem;
+use std::net::Ipv4Addr;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+use crate::nic::{self, Nic};
+
+const ETH_HEADER_LEN: usize = 14;
+const ETH_P_IP: u16 = 0x0800;
+const ETH_P_ARP: u16 = 0x0806;
+const BROADCAST: [u8; 6] = [0xff; 6];
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+/// `sll_pkttype` of frames we sent ourselves (linux/if_packet.h)
+const PACKET_OUTGOING: u8 = 4;
+/// the socket option, and control message, that carry a frame's `tpacket_auxdata`
+const PACKET_AUXDATA: libc::c_int = 8;
+/// `tp_status` of a frame whose transport checksum the sender left for the device to fill in
+const TP_STATUS_CSUMNOTREADY: u32 = 1 << 3;
+/// room for the control message carrying a `tpacket_auxdata`, which is 20 bytes
+const CONTROL_LEN: usize = 64;
+
+/// A [`Nic`] that sends and receives ethernet frames through an `AF_PACKET` socket on an
+/// existing network interface, for when tun devices aren't available but `CAP_NET_RAW` is.
+///
+/// The addresses given to [`RawSocket::bind`] must not be configured on the interface, or the
+/// kernel's own stack would answer the same segments with resets. Instead, the socket does its
+/// own ARP for them, so peers have to be on the same link. Packets to a peer whose hardware
+/// address we don't know yet are dropped while we ask for it; retransmission takes care of
+/// them once the peer has answered.
+pub struct RawSocket {
+    fd: OwnedFd,
+    ifname: String,
+    mac: [u8; 6],
+    addrs: Vec<Ipv4Addr>,
+    neighbours: HashMap<Ipv4Addr, [u8; 6]>,
+    frame: Vec<u8>,
+}
+
+impl RawSocket {
+    /// Open a packet socket on the interface `ifname` that receives the TCP packets (and ARP
+    /// messages) for `addrs`.
+    pub fn bind(ifname: &str, addrs: &[Ipv4Addr]) -> io::Result<Self> {
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a raw socket needs at least one address",
+            ));
+        }
+
+        let name = CString::new(ifname)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "bad interface name"))?;
+        let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if ifindex == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mac = read_mac(ifname)?;
+
+        let protocol = (libc::ETH_P_ALL as u16).to_be();
+        let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW, protocol as libc::c_int) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let sock = RawSocket {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            ifname: ifname.to_string(),
+            mac,
+            addrs: addrs.to_vec(),
+            neighbours: HashMap::new(),
+            frame: vec![0; ETH_HEADER_LEN + u16::MAX as usize],
+        };
+
+        // install the filter before binding, so we never see anyone else's traffic
+        let mut filter = address_filter(addrs);
+        let prog = libc::sock_fprog {
+            len: filter.len() as u16,
+            filter: filter.as_mut_ptr(),
+        };
+        let r = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_ATTACH_FILTER,
+                &prog as *const libc::sock_fprog as *const libc::c_void,
+                mem::size_of::<libc::sock_fprog>() as libc::socklen_t,
+            )
+        };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // frames sent from this host, as over a veth pair, may come with their TCP checksum
+        // left for a device to fill in; the kernel says which
+        let on: libc::c_int = 1;
+        let r = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_PACKET,
+                PACKET_AUXDATA,
+                &on as *const libc::c_int as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut sll: libc::sockaddr_ll = unsafe { mem::zeroed() };
+        sll.sll_family = libc::AF_PACKET as u16;
+        sll.sll_protocol = protocol;
+        sll.sll_ifindex = ifindex as libc::c_int;
+        let r = unsafe {
+            libc::bind(
+                fd,
+                &sll as *const libc::sockaddr_ll as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(sock)
+    }
+
+    /// Learn the sender of an ARP request or reply for one of our addresses, and answer the
+    /// request.
+    fn on_arp(&mut self, arp: &[u8]) -> io::Result<()> {
+        if arp.len() < 28 || arp[..6] != [0, 1, 8, 0, 6, 4] {
+            // not ethernet/IPv4
+            return Ok(());
+        }
+        let op = u16::from_be_bytes([arp[6], arp[7]]);
+        let sha: [u8; 6] = arp[8..14].try_into().unwrap();
+        let spa = Ipv4Addr::new(arp[14], arp[15], arp[16], arp[17]);
+        let tpa = Ipv4Addr::new(arp[24], arp[25], arp[26], arp[27]);
+        if !self.addrs.contains(&tpa) {
+            return Ok(());
+        }
+        self.neighbours.insert(spa, sha);
+        if op == ARP_REQUEST {
+            self.send_arp(ARP_REPLY, tpa, sha, spa)?;
+        }
+        Ok(())
+    }
+
+    /// Make sense of a frame with the ethernet header `header` and the payload `payload` that
+    /// arrived as `pkttype`, returning the length of the IPv4 packet at the start of
+    /// `payload`, or 0 if it carried none for us.
+    fn on_frame(&mut self, pkttype: u8, header: &[u8], payload: &[u8]) -> io::Result<usize> {
+        if pkttype == PACKET_OUTGOING {
+            // our own frames, looped back to us
+            return Ok(0);
+        }
+        let ethertype = u16::from_be_bytes([header[12], header[13]]);
+        match ethertype {
+            ETH_P_ARP => self.on_arp(payload).map(|_| 0),
+            ETH_P_IP if payload.len() >= 20 => {
+                let src = Ipv4Addr::new(payload[12], payload[13], payload[14], payload[15]);
+                self.neighbours
+                    .insert(src, header[6..12].try_into().unwrap());
+                Ok(payload.len())
+            }
+            _ => Ok(0),
+        }
+    }
+
+    /// The ethernet header for the IPv4 packet `packet`, or `None` if we don't know the
+    /// destination's hardware address yet, in which case we ask for it.
+    fn ip_header(&self, packet: &[u8]) -> io::Result<Option<[u8; ETH_HEADER_LEN]>> {
+        if packet.len() < 20 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "packet too short for an IPv4 header",
+            ));
+        }
+        let dst = Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]);
+        let Some(dst_mac) = self.neighbours.get(&dst) else {
+            let src = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+            self.send_arp(ARP_REQUEST, src, [0; 6], dst)?;
+            return Ok(None);
+        };
+        let mut header = [0u8; ETH_HEADER_LEN];
+        header[0..6].copy_from_slice(dst_mac);
+        header[6..12].copy_from_slice(&self.mac);
+        header[12..14].copy_from_slice(&ETH_P_IP.to_be_bytes());
+        Ok(Some(header))
+    }
+
+    fn send_arp(&self, op: u16, spa: Ipv4Addr, tha: [u8; 6], tpa: Ipv4Addr) -> io::Result<()> {
+        let mut frame = [0u8; ETH_HEADER_LEN + 28];
+        frame[0..6].copy_from_slice(if op == ARP_REQUEST { &BROADCAST } else { &tha });
+        frame[6..12].copy_from_slice(&self.mac);
+        frame[12..14].copy_from_slice(&ETH_P_ARP.to_be_bytes());
+        let arp = &mut frame[ETH_HEADER_LEN..];
+        arp[..6].copy_from_slice(&[0, 1, 8, 0, 6, 4]);
+        arp[6..8].copy_from_slice(&op.to_be_bytes());
+        arp[8..14].copy_from_slice(&self.mac);
+        arp[14..18].copy_from_slice(&spa.octets());
+        arp[18..24].copy_from_slice(&tha);
+        arp[24..28].copy_from_slice(&tpa.octets());
+        self.send_frame(&frame)?;
+        Ok(())
+    }
+
+    fn send_frame(&self, frame: &[u8]) -> io::Result<usize> {
+        let n = unsafe {
+            libc::send(
+                self.fd.as_raw_fd(),
+                frame.as_ptr() as *const libc::c_void,
+                frame.len(),
+                0,
+            )
+        };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(n as usize)
+    }
+}
+
+/// The hardware address of the interface `ifname`.
+fn read_mac(ifname: &str) -> io::Result<[u8; 6]> {
+    let s = fs::read_to_string(format!("/sys/class/net/{}/address", ifname))?;
+    let mut mac = [0u8; 6];
+    let mut octets = s.trim().split(':');
+    for b in mac.iter_mut() {
+        *b = octets
+            .next()
+            .and_then(|o| u8::from_str_radix(o, 16).ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bad hardware address"))?;
+    }
+    Ok(mac)
+}
+
+/// Whether the `tpacket_auxdata` that came with the frame received into `msg` says its
+/// checksum was left for a device to fill in, as checksum offload does for frames sent from
+/// this host.
+fn checksum_not_ready(msg: &libc::msghdr) -> bool {
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(msg) };
+    while !cmsg.is_null() {
+        let c = unsafe { &*cmsg };
+        if c.cmsg_level == libc::SOL_PACKET && c.cmsg_type == PACKET_AUXDATA {
+            // tp_status comes first
+            let status = unsafe { std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const u32) };
+            return status & TP_STATUS_CSUMNOTREADY != 0;
+        }
+        cmsg = unsafe { libc::CMSG_NXTHDR(msg, cmsg) };
+    }
+    false
+}
+
+/// Fill in the TCP checksum of the IPv4 packet `packet`, whose sender left it to the device;
+/// anything else is left as it is.
+fn complete_checksum(packet: &mut [u8]) {
+    if packet.len() < 20 || packet[0] >> 4 != 4 || packet[9] != libc::IPPROTO_TCP as u8 {
+        return;
+    }
+    let ihl = (packet[0] & 0xf) as usize * 4;
+    let total = cmp::min(
+        u16::from_be_bytes([packet[2], packet[3]]) as usize,
+        packet.len(),
+    );
+    if total < ihl + 20 {
+        return;
+    }
+    let sum = |bytes: &[u8], mut sum: u32| {
+        for word in bytes.chunks(2) {
+            sum += u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]) as u32;
+        }
+        sum
+    };
+    let tcp_len = total - ihl;
+    packet[ihl + 16..ihl + 18].fill(0);
+    let mut total_sum = sum(
+        &packet[ihl..total],
+        sum(&packet[12..20], 6 + tcp_len as u32),
+    );
+    while total_sum > 0xffff {
+        total_sum = (total_sum & 0xffff) + (total_sum >> 16);
+    }
+    packet[ihl + 16..ihl + 18].copy_from_slice(&(!(total_sum as u16)).to_be_bytes());
+}
+
+/// A classic BPF program that accepts the ethernet frames carrying either TCP packets or ARP
+/// messages for one of `addrs`, and drops everything else in the kernel.
+fn address_filter(addrs: &[Ipv4Addr]) -> Vec<libc::sock_filter> {
+    let n = addrs.len();
+    assert!(2 * n + 9 < 256, "too many addresses for a jump offset");
+
+    // where the jumps below land
+    let drop = 6 + n;
+    let arp = drop + 1;
+    let accept = arp + n + 2;
+
+    let stmt = |code: u32, k: u32| libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    };
+    let jeq = |at: usize, k: u32, jt: usize, jf: usize| libc::sock_filter {
+        code: (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16,
+        jt: (jt - at - 1) as u8,
+        jf: (jf - at - 1) as u8,
+        k,
+    };
+
+    let mut f = Vec::with_capacity(accept + 1);
+    // ethertype
+    f.push(stmt(libc::BPF_LD | libc::BPF_H | libc::BPF_ABS, 12));
+    f.push(jeq(1, ETH_P_ARP as u32, arp, 2));
+    f.push(jeq(2, ETH_P_IP as u32, 3, drop));
+    // IP protocol
+    f.push(stmt(libc::BPF_LD | libc::BPF_B | libc::BPF_ABS, 23));
+    f.push(jeq(4, libc::IPPROTO_TCP as u32, 5, drop));
+    // IP destination
+    f.push(stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 30));
+    for (i, addr) in addrs.iter().enumerate() {
+        f.push(jeq(6 + i, u32::from(*addr), accept, 6 + i + 1));
+    }
+    f.push(stmt(libc::BPF_RET | libc::BPF_K, 0));
+    // ARP target protocol address
+    f.push(stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 38));
+    for (i, addr) in addrs.iter().enumerate() {
+        f.push(jeq(arp + 1 + i, u32::from(*addr), accept, arp + 1 + i + 1));
+    }
+    f.push(stmt(libc::BPF_RET | libc::BPF_K, 0));
+    f.push(stmt(libc::BPF_RET | libc::BPF_K, u32::MAX));
+    debug_assert_eq!(f.len(), accept + 1);
+    f
+}
+
+impl AsRawFd for RawSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl nic::sealed::Sealed for RawSocket {}
+
+impl Nic for RawSocket {
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut sll: libc::sockaddr_ll = unsafe { mem::zeroed() };
+        let mut control = [0u64; CONTROL_LEN / 8];
+        let mut iov = libc::iovec {
+            iov_base: self.frame.as_mut_ptr() as *mut libc::c_void,
+            iov_len: self.frame.len(),
+        };
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_name = &mut sll as *mut libc::sockaddr_ll as *mut libc::c_void;
+        msg.msg_namelen = mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = CONTROL_LEN as _;
+        let n = unsafe { libc::recvmsg(self.fd.as_raw_fd(), &mut msg, 0) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let n = n as usize;
+        if n < ETH_HEADER_LEN {
+            return Ok(0);
+        }
+
+        let mut frame = mem::take(&mut self.frame);
+        if checksum_not_ready(&msg) {
+            complete_checksum(&mut frame[ETH_HEADER_LEN..n]);
+        }
+        let (header, payload) = frame[..n].split_at(ETH_HEADER_LEN);
+        let r = self.on_frame(sll.sll_pkttype, header, payload).map(|len| {
+            let len = len.min(buf.len());
+            buf[..len].copy_from_slice(&payload[..len]);
+            len
+        });
+        self.frame = frame;
+        r
+    }
+
+    /// Receive a batch with `recvmmsg`, scattering each frame's ethernet header into an array
+    /// of our own and its payload straight into the caller's buffer.
+    fn recv_batch(&mut self, bufs: &mut [IoSliceMut<'_>], lens: &mut [usize]) -> io::Result<usize> {
+        let max = cmp::min(cmp::min(bufs.len(), lens.len()), nic::BATCH);
+        let mut headers = [[0u8; ETH_HEADER_LEN]; nic::BATCH];
+        let mut addrs: [libc::sockaddr_ll; nic::BATCH] = unsafe { mem::zeroed() };
+        let mut iovs: [[libc::iovec; 2]; nic::BATCH] = unsafe { mem::zeroed() };
+        let mut msgs: [libc::mmsghdr; nic::BATCH] = unsafe { mem::zeroed() };
+        let mut controls = [[0u64; CONTROL_LEN / 8]; nic::BATCH];
+        for i in 0..max {
+            iovs[i] = [
+                libc::iovec {
+                    iov_base: headers[i].as_mut_ptr() as *mut libc::c_void,
+                    iov_len: ETH_HEADER_LEN,
+                },
+                libc::iovec {
+                    iov_base: bufs[i].as_mut_ptr() as *mut libc::c_void,
+                    iov_len: bufs[i].len(),
+                },
+            ];
+            let hdr = &mut msgs[i].msg_hdr;
+            hdr.msg_name = &mut addrs[i] as *mut libc::sockaddr_ll as *mut libc::c_void;
+            hdr.msg_namelen = mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+            hdr.msg_iov = iovs[i].as_mut_ptr();
+            hdr.msg_iovlen = 2;
+            hdr.msg_control = controls[i].as_mut_ptr() as *mut libc::c_void;
+            hdr.msg_controllen = CONTROL_LEN as _;
+        }
+        // block for the first frame only: the device is readable, so it is already there
+        let n = unsafe {
+            libc::recvmmsg(
+                self.fd.as_raw_fd(),
+                msgs.as_mut_ptr(),
+                max as libc::c_uint,
+                libc::MSG_WAITFORONE,
+                std::ptr::null_mut(),
+            )
+        };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let n = n as usize;
+        for i in 0..n {
+            let len = msgs[i].msg_len as usize;
+            lens[i] = match len.checked_sub(ETH_HEADER_LEN) {
+                Some(len) => {
+                    let len = cmp::min(len, bufs[i].len());
+                    let payload = &mut bufs[i][..len];
+                    if checksum_not_ready(&msgs[i].msg_hdr) {
+                        complete_checksum(payload);
+                    }
+                    self.on_frame(addrs[i].sll_pkttype, &headers[i], payload)?
+                }
+                None => 0,
+            };
+        }
+        Ok(n)
+    }
+
+    fn send(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(header) = self.ip_header(buf)? else {
+            // as good as lost on the wire
+            return Ok(buf.len());
+        };
+
+        let mut frame = Vec::with_capacity(ETH_HEADER_LEN + buf.len());
+        frame.extend_from_slice(&header);
+        frame.extend_from_slice(buf);
+        let n = self.send_frame(&frame)?;
+        Ok(n.saturating_sub(ETH_HEADER_LEN))
+    }
+
+    /// Send a batch with `sendmmsg`, gathering each frame from an ethernet header of our own
+    /// and the caller's packet.
+    fn send_batch(&mut self, packets: &[IoSlice<'_>]) -> io::Result<usize> {
+        let count = cmp::min(packets.len(), nic::BATCH);
+        let mut headers = [[0u8; ETH_HEADER_LEN]; nic::BATCH];
+        // which packet each message carries: those to neighbours we are still asking for go
+        // nowhere, as in `send`
+        let mut carries = [0; nic::BATCH];
+        let mut m = 0;
+        for (i, packet) in packets[..count].iter().enumerate() {
+            if let Some(header) = self.ip_header(packet)? {
+                headers[m] = header;
+                carries[m] = i;
+                m += 1;
+            }
+        }
+        if m == 0 {
+            return Ok(count);
+        }
+
+        let mut iovs: [[libc::iovec; 2]; nic::BATCH] = unsafe { mem::zeroed() };
+        let mut msgs: [libc::mmsghdr; nic::BATCH] = unsafe { mem::zeroed() };
+        for k in 0..m {
+            let packet = &packets[carries[k]];
+            iovs[k] = [
+                libc::iovec {
+                    iov_base: headers[k].as_mut_ptr() as *mut libc::c_void,
+                    iov_len: ETH_HEADER_LEN,
+                },
+                libc::iovec {
+                    iov_base: packet.as_ptr() as *mut libc::c_void,
+                    iov_len: packet.len(),
+                },
+            ];
+            msgs[k].msg_hdr.msg_iov = iovs[k].as_mut_ptr();
+            msgs[k].msg_hdr.msg_iovlen = 2;
+        }
+        let sent =
+            unsafe { libc::sendmmsg(self.fd.as_raw_fd(), msgs.as_mut_ptr(), m as libc::c_uint, 0) };
+        if sent < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::WouldBlock {
+                return Ok(carries[0]);
+            }
+            return Err(e);
+        }
+        // everything before the first message that did not go out
+        let sent = sent as usize;
+        Ok(if sent < m { carries[sent] } else { count })
+    }
+
+    fn mtu(&self) -> io::Result<usize> {
+        nic::interface_mtu(&self.ifname)
+    }
+}
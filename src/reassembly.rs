@@ -0,0 +1,141 @@
+use std::ops::Range;
+
+/// How many separate pieces of data the queue holds at most; a peer that spreads tiny segments
+/// across the window gets the rest dropped, as it would from Linux once its out-of-order queue
+/// is pruned.
+const MAX_PIECES: usize = 256;
+
+/// Data that arrived beyond RCV.NXT, held until what comes before it does (RFC 9293 S3.10.7.4
+/// allows keeping it). Pieces never overlap, and are kept in sequence order.
+#[derive(Debug, Default)]
+pub(crate) struct Reassembly {
+    /// each piece with the sequence number of its first byte
+    pieces: Vec<(u32, Vec<u8>)>,
+}
+
+impl Reassembly {
+    /// How many bytes are held.
+    pub(crate) fn len(&self) -> usize {
+        self.pieces.iter().map(|(_, data)| data.len()).sum()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pieces.is_empty()
+    }
+
+    /// Hold `data`, which starts at `seq`, somewhere after `rcv_nxt`. Whatever of it is held
+    /// already is left out. Returns how many bytes that was, or `None` if there was no room
+    /// for the rest.
+    pub(crate) fn insert(&mut self, rcv_nxt: u32, seq: u32, data: &[u8]) -> Option<usize> {
+        let offset = |seq: u32| seq.wrapping_sub(rcv_nxt);
+        let (start, end) = (offset(seq), offset(seq) + data.len() as u32);
+        // the parts of [start, end) that no piece covers
+        let mut gaps = Vec::new();
+        let mut at = start;
+        for (s, d) in &self.pieces {
+            let (s, e) = (offset(*s), offset(*s) + d.len() as u32);
+            if e <= at {
+                continue;
+            }
+            if s >= end {
+                break;
+            }
+            if s > at {
+                gaps.push(at..s);
+            }
+            at = e;
+        }
+        if at < end {
+            gaps.push(at..end);
+        }
+        if self.pieces.len() + gaps.len() > MAX_PIECES {
+            return None;
+        }
+        let mut new = 0;
+        for gap in gaps {
+            new += gap.len();
+            let piece = data[(gap.start - start) as usize..(gap.end - start) as usize].to_vec();
+            let at = self.pieces.partition_point(|(s, _)| offset(*s) < gap.start);
+            self.pieces
+                .insert(at, (rcv_nxt.wrapping_add(gap.start), piece));
+        }
+        Some(data.len() - new)
+    }
+
+    /// Take the first piece, if it starts at or before `rcv_nxt`, with the sequence number
+    /// of its first byte. Part of it may have arrived again in order since it was held.
+    pub(crate) fn pop_ready(&mut self, rcv_nxt: u32) -> Option<(u32, Vec<u8>)> {
+        let &(seq, _) = self.pieces.first()?;
+        // ahead of RCV.NXT by more than half the sequence space means behind it
+        if seq != rcv_nxt && seq.wrapping_sub(rcv_nxt) < 1 << 31 {
+            return None;
+        }
+        Some(self.pieces.remove(0))
+    }
+
+    /// The stretches of sequence space held, each as offsets from `rcv_nxt`, in order, with
+    /// pieces that adjoin one another run together.
+    pub(crate) fn runs(&self, rcv_nxt: u32) -> Vec<Range<u32>> {
+        let mut runs: Vec<Range<u32>> = Vec::new();
+        for (seq, data) in &self.pieces {
+            let start = seq.wrapping_sub(rcv_nxt);
+            let end = start + data.len() as u32;
+            match runs.last_mut() {
+                Some(last) if last.end == start => last.end = end,
+                _ => runs.push(start..end),
+            }
+        }
+        runs
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.pieces.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlapping_pieces_are_held_once() {
+        let mut r = Reassembly::default();
+        let nxt = u32::MAX - 5;
+        assert_eq!(r.insert(nxt, nxt.wrapping_add(10), &[1; 10]), Some(0));
+        assert_eq!(r.insert(nxt, nxt.wrapping_add(30), &[3; 5]), Some(0));
+        // covers the end of the first, the gap, and the start of the second
+        assert_eq!(r.insert(nxt, nxt.wrapping_add(15), &[2; 20]), Some(10));
+        assert_eq!(r.len(), 25);
+        assert_eq!(r.insert(nxt, nxt.wrapping_add(12), &[9; 4]), Some(4));
+
+        assert_eq!(r.pop_ready(nxt), None);
+        let (seq, data) = r.pop_ready(nxt.wrapping_add(10)).unwrap();
+        assert_eq!((seq, data), (nxt.wrapping_add(10), vec![1; 10]));
+        // RCV.NXT got past the start of the next piece on its own
+        let (seq, data) = r.pop_ready(nxt.wrapping_add(25)).unwrap();
+        assert_eq!((seq, data.len()), (nxt.wrapping_add(20), 10));
+        assert!(r.pop_ready(nxt.wrapping_add(25)).is_none());
+    }
+
+    #[test]
+    fn adjoining_pieces_make_one_run() {
+        let mut r = Reassembly::default();
+        let nxt = u32::MAX - 5;
+        r.insert(nxt, nxt.wrapping_add(20), &[2; 5]);
+        r.insert(nxt, nxt.wrapping_add(10), &[1; 10]);
+        r.insert(nxt, nxt.wrapping_add(30), &[3; 5]);
+        assert_eq!(r.runs(nxt), vec![10..25, 30..35]);
+        assert_eq!(r.runs(nxt.wrapping_add(10)), vec![0..15, 20..25]);
+    }
+
+    #[test]
+    fn too_many_pieces_are_refused() {
+        let mut r = Reassembly::default();
+        for i in 0..MAX_PIECES as u32 {
+            assert_eq!(r.insert(0, 1 + 2 * i, &[0]), Some(0));
+        }
+        assert_eq!(r.insert(0, 1 + 2 * MAX_PIECES as u32, &[0]), None);
+        // what is held already costs nothing
+        assert_eq!(r.insert(0, 1, &[0]), Some(1));
+    }
+}
@@ -0,0 +1,251 @@
+use std::cmp;
+
+/// How many separate stretches the scoreboard keeps at most; a peer that SACKs every other
+/// byte gets the highest of them forgotten, which only makes us take more for in flight.
+const MAX_BLOCKS: usize = 256;
+
+/// What the peer has said it holds beyond SND.UNA, going by the SACK blocks of its ACKs
+/// (RFC 6675 S3). Blocks never overlap or adjoin, and are kept in sequence order.
+#[derive(Debug, Default)]
+pub(crate) struct Scoreboard {
+    /// each stretch SACKed, from its first sequence number to just past its last
+    blocks: Vec<(u32, u32)>,
+    /// how many bytes all of them cover
+    sacked: u32,
+}
+
+/// When RFC 6675's IsLost takes a segment for lost: once `dup_thresh` separate stretches
+/// above it, or more than `dup_thresh - 1` segments' worth of bytes above it, have been SACKed.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct LossRule {
+    pub(crate) mss: u32,
+    pub(crate) dup_thresh: u32,
+}
+
+impl Scoreboard {
+    /// How many bytes past SND.UNA the peer has SACKed.
+    pub(crate) fn sacked(&self) -> u32 {
+        self.sacked
+    }
+
+    /// Take in the SACK block `[left, right)` from an ACK, with `una` and `max` the first and
+    /// just past the last sequence numbers we have outstanding. A block that reaches past what
+    /// we sent is bogus and left out; one that starts before `una` only counts from there.
+    /// Returns how many bytes it newly SACKed.
+    pub(crate) fn insert(&mut self, una: u32, max: u32, left: u32, right: u32) -> u32 {
+        let offset = |seq: u32| seq.wrapping_sub(una);
+        let end = offset(right);
+        if end == 0 || end > offset(max) {
+            return 0;
+        }
+        let start = match offset(left) {
+            // ahead of SND.UNA by more than half the sequence space means behind it
+            start if start >= 1 << 31 => 0,
+            start if start >= end => return 0,
+            start => start,
+        };
+        // the blocks it overlaps or adjoins, which it swallows
+        let first = self.blocks.partition_point(|&(_, e)| offset(e) < start);
+        let last = self.blocks.partition_point(|&(s, _)| offset(s) <= end);
+        let (mut merged_start, mut merged_end) = (start, end);
+        let mut swallowed = 0;
+        for &(s, e) in &self.blocks[first..last] {
+            merged_start = cmp::min(merged_start, offset(s));
+            merged_end = cmp::max(merged_end, offset(e));
+            swallowed += e.wrapping_sub(s);
+        }
+        let merged = (una.wrapping_add(merged_start), una.wrapping_add(merged_end));
+        self.blocks.splice(first..last, [merged]);
+        let new = (merged_end - merged_start) - swallowed;
+        self.sacked += new;
+        if self.blocks.len() > MAX_BLOCKS {
+            let (s, e) = self.blocks.pop().unwrap();
+            self.sacked -= e.wrapping_sub(s);
+        }
+        new
+    }
+
+    /// Forget what lies before `una`, now that the peer has ACKed it cumulatively.
+    pub(crate) fn advance(&mut self, old_una: u32, una: u32) {
+        let cut = una.wrapping_sub(old_una);
+        let offset = |seq: u32| seq.wrapping_sub(old_una);
+        while let Some(&(s, e)) = self.blocks.first() {
+            if offset(s) >= cut {
+                break;
+            }
+            if offset(e) <= cut {
+                self.sacked -= e.wrapping_sub(s);
+                self.blocks.remove(0);
+            } else {
+                self.sacked -= una.wrapping_sub(s);
+                self.blocks[0].0 = una;
+                break;
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.blocks.clear();
+        self.sacked = 0;
+    }
+
+    /// The offset from `una` below which every byte not SACKed is lost by `rule`, going
+    /// through the blocks from the top, as IsLost does for each byte.
+    fn lost_below(&self, una: u32, rule: LossRule) -> u32 {
+        let mut above = 0;
+        for (count, &(s, e)) in self.blocks.iter().rev().enumerate() {
+            above += e.wrapping_sub(s);
+            if count as u32 + 1 >= rule.dup_thresh || above > (rule.dup_thresh - 1) * rule.mss {
+                return s.wrapping_sub(una);
+            }
+        }
+        0
+    }
+
+    /// The stretches from `una` to `nxt` the peer has not SACKed, as offsets from `una`.
+    fn holes(&self, una: u32, nxt: u32) -> impl Iterator<Item = (u32, u32)> + '_ {
+        let end = nxt.wrapping_sub(una);
+        let mut at = 0;
+        self.blocks
+            .iter()
+            .map(move |&(s, e)| (s.wrapping_sub(una), e.wrapping_sub(una)))
+            .chain([(end, end)])
+            .filter_map(move |(s, e)| {
+                let hole = (at, cmp::min(s, end));
+                at = cmp::max(at, e);
+                (hole.0 < hole.1).then_some(hole)
+            })
+    }
+
+    /// RFC 6675's pipe (S4 SetPipe): of the bytes from `una` to `nxt` not SACKed, one for each
+    /// that is not lost, and another for each that has been sent again, which is everything
+    /// before `high_rxt`.
+    pub(crate) fn pipe(&self, una: u32, nxt: u32, high_rxt: u32, rule: LossRule) -> u32 {
+        let lost = self.lost_below(una, rule);
+        let rxt = cmp::min(high_rxt.wrapping_sub(una), nxt.wrapping_sub(una));
+        let rxt = if rxt >= 1 << 31 { 0 } else { rxt };
+        self.holes(una, nxt)
+            .map(|(s, e)| {
+                let in_flight = e - cmp::max(s, cmp::min(lost, e));
+                let resent = cmp::min(e, rxt).saturating_sub(s);
+                in_flight + resent
+            })
+            .sum()
+    }
+
+    /// The first stretch of lost bytes from `from` on that is not SACKed, as its first sequence
+    /// number and length: what RFC 6675's NextSeg (S4 (1)) picks to send again.
+    pub(crate) fn next_lost(
+        &self,
+        una: u32,
+        nxt: u32,
+        from: u32,
+        rule: LossRule,
+    ) -> Option<(u32, u32)> {
+        let lost = self.lost_below(una, rule);
+        let from = from.wrapping_sub(una);
+        let from = if from >= 1 << 31 { 0 } else { from };
+        self.holes(una, nxt).find_map(|(s, e)| {
+            let (s, e) = (cmp::max(s, from), cmp::min(e, lost));
+            (s < e).then(|| (una.wrapping_add(s), e - s))
+        })
+    }
+
+    /// Whether the byte at `una` is lost by `rule`, which lets loss recovery start before
+    /// enough duplicate ACKs have come in (RFC 6675 S5 (2)).
+    pub(crate) fn is_lost(&self, una: u32, rule: LossRule) -> bool {
+        self.lost_below(una, rule) > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULE: LossRule = LossRule {
+        mss: 100,
+        dup_thresh: 3,
+    };
+
+    #[test]
+    fn blocks_merge_and_count_once() {
+        let mut s = Scoreboard::default();
+        let una = u32::MAX - 50;
+        let max = una.wrapping_add(1000);
+        assert_eq!(
+            s.insert(una, max, una.wrapping_add(100), una.wrapping_add(200)),
+            100
+        );
+        assert_eq!(
+            s.insert(una, max, una.wrapping_add(300), una.wrapping_add(400)),
+            100
+        );
+        // the same again, and one that bridges the two
+        assert_eq!(
+            s.insert(una, max, una.wrapping_add(100), una.wrapping_add(200)),
+            0
+        );
+        assert_eq!(
+            s.insert(una, max, una.wrapping_add(150), una.wrapping_add(350)),
+            100
+        );
+        assert_eq!(s.sacked(), 300);
+        // past what we sent, and wholly before SND.UNA
+        assert_eq!(
+            s.insert(una, max, una.wrapping_add(900), una.wrapping_add(1100)),
+            0
+        );
+        assert_eq!(s.insert(una, max, una.wrapping_sub(100), una), 0);
+        assert_eq!(s.sacked(), 300);
+
+        s.advance(una, una.wrapping_add(250));
+        assert_eq!(s.sacked(), 150);
+        s.advance(una.wrapping_add(250), una.wrapping_add(400));
+        assert!(s.blocks.is_empty());
+        assert_eq!(s.sacked(), 0);
+    }
+
+    #[test]
+    fn pipe_counts_what_is_neither_sacked_nor_lost() {
+        // ten segments of 100 bytes out, the 1st and 3rd lost, the rest SACKed up to the 8th
+        let mut s = Scoreboard::default();
+        let una = 1000;
+        let nxt = 2000;
+        s.insert(una, nxt, 1100, 1200);
+        assert_eq!(s.pipe(una, nxt, una, RULE), 900);
+        assert!(!s.is_lost(una, RULE));
+        s.insert(una, nxt, 1300, 1800);
+        // more than two segments SACKed above both holes: 800 in flight, less 500 SACKed, less
+        // the two lost
+        assert!(s.is_lost(una, RULE));
+        assert_eq!(s.pipe(una, nxt, una, RULE), 200);
+        assert_eq!(s.next_lost(una, nxt, una, RULE), Some((1000, 100)));
+        assert_eq!(s.next_lost(una, nxt, 1100, RULE), Some((1200, 100)));
+        assert_eq!(s.next_lost(una, nxt, 1300, RULE), None);
+        // the first sent again is in flight twice over, as lost and as resent
+        assert_eq!(s.pipe(una, nxt, 1100, RULE), 300);
+    }
+
+    #[test]
+    fn separate_blocks_make_a_loss_without_enough_bytes() {
+        let mut s = Scoreboard::default();
+        let (una, nxt) = (0, 1000);
+        for start in [100, 300, 500] {
+            s.insert(una, nxt, start, start + 10);
+        }
+        assert_eq!(s.next_lost(una, nxt, una, RULE), Some((0, 100)));
+        // the holes above the lowest block have fewer than three blocks over them
+        assert_eq!(s.next_lost(una, nxt, 100, RULE), None);
+    }
+
+    #[test]
+    fn too_many_blocks_forget_the_highest() {
+        let mut s = Scoreboard::default();
+        let (una, max) = (0, 10_000);
+        for i in 0..=MAX_BLOCKS as u32 {
+            s.insert(una, max, 1 + 2 * i, 2 + 2 * i);
+        }
+        assert_eq!(s.blocks.len(), MAX_BLOCKS);
+        assert_eq!(s.sacked(), MAX_BLOCKS as u32);
+    }
+}
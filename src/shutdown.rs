@@ -0,0 +1,146 @@
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::{nic, ConnectionManager, Interface};
+
+/// How an interface shuts down once a [`ShutdownHandle`] says so.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShutdownMode {
+    /// close every connection, as dropping its stream would, and give them up to this long to
+    /// finish before resetting what is left; connections in TIME-WAIT count as finished
+    Graceful(Duration),
+    /// reset every connection right away
+    Abort,
+}
+
+/// Shutdowns asked for, by the handles of one interface.
+#[derive(Default)]
+pub(crate) struct Requests {
+    /// one more than the index in `modes` of the handle that signaled first, or 0 while none
+    /// has
+    signaled: AtomicUsize,
+    /// the mode of each handle handed out
+    modes: Mutex<Vec<ShutdownMode>>,
+}
+
+impl Requests {
+    /// How the interface is to shut down, if a handle has said so.
+    fn requested(&self) -> Option<ShutdownMode> {
+        match self.signaled.load(Ordering::SeqCst) {
+            0 => None,
+            id => Some(self.modes.lock().unwrap()[id - 1]),
+        }
+    }
+}
+
+/// Shuts an interface down from anywhere, a signal handler included; see
+/// [`Interface::shutdown_handle`].
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    requests: Arc<Requests>,
+    /// where the handle's mode is in `requests`
+    id: usize,
+}
+
+impl ShutdownHandle {
+    /// Have the packet loop shut the interface down, in the mode the handle was made with,
+    /// unless another handle got there first. This only sets an atomic flag, so it is safe to
+    /// call from a signal handler; the loop notices on its next tick, within 10 milliseconds.
+    pub fn signal(&self) {
+        let _ = self.requests.signaled.compare_exchange(
+            0,
+            self.id + 1,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        );
+    }
+}
+
+impl Interface {
+    /// A handle that shuts the interface down in `mode` when signaled, and then has
+    /// [`Interface::run`] return. Dropping handles without signaling does nothing.
+    pub fn shutdown_handle(&self, mode: ShutdownMode) -> ShutdownHandle {
+        let requests = self.ih.as_ref().unwrap().shutdown.clone();
+        let mut modes = requests.modes.lock().unwrap();
+        modes.push(mode);
+        let id = modes.len() - 1;
+        drop(modes);
+        ShutdownHandle { requests, id }
+    }
+
+    /// Wait until the interface has shut down, for a [`ShutdownHandle`] having signaled, and
+    /// its devices are closed; an interface built with
+    /// [`InterfaceBuilder::build_polled`](crate::InterfaceBuilder::build_polled) runs its
+    /// packet loop on the calling thread until then. Fails if the packet loop did.
+    ///
+    /// Listeners stop taking connections as the shutdown starts, and blocked calls on streams
+    /// fail once it is complete.
+    ///
+    /// If the packet loop stops on its own instead, for a device having failed, this returns
+    /// its error, and an error of kind `Other` if it panicked; blocked calls on streams and
+    /// listeners fail either way.
+    pub fn run(&mut self) -> io::Result<()> {
+        if let Some(jh) = self.jh.take() {
+            return jh
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("the packet loop panicked")));
+        }
+        let ih = self.ih.as_ref().unwrap();
+        if let Some(polled) = self.polled.as_mut() {
+            while polled.driver.round(ih, None)? {}
+        }
+        Ok(())
+    }
+}
+
+impl ConnectionManager {
+    /// Carry a shutdown some handle asked for forward, on a tick of the packet loop: stop the
+    /// listeners and close or reset every connection once it starts, and reset whatever is
+    /// left when it runs out of time. Returns whether it is complete, so the loop should stop,
+    /// along with what wakes the threads waiting to accept on the listeners it stopped.
+    pub(crate) fn drive_shutdown(
+        &mut self,
+        requests: &Requests,
+        nics: &mut [nic::Outbound],
+    ) -> io::Result<(bool, Vec<Arc<Condvar>>)> {
+        let Some(mode) = requests.requested() else {
+            return Ok((false, Vec::new()));
+        };
+        let now = self.clock.now();
+        let mut wakers = Vec::new();
+        let deadline = match self.shutdown_deadline {
+            Some(deadline) => deadline,
+            None => {
+                let open: Vec<_> = self
+                    .listeners
+                    .iter()
+                    .filter(|(_, listener)| !listener.closed)
+                    .map(|(&key, _)| key)
+                    .collect();
+                for key in open {
+                    wakers.extend(self.close_listener(key));
+                }
+                let grace = match mode {
+                    ShutdownMode::Graceful(grace) => {
+                        for c in self.connections.values_mut() {
+                            c.close();
+                        }
+                        grace
+                    }
+                    ShutdownMode::Abort => Duration::ZERO,
+                };
+                *self.shutdown_deadline.insert(now + grace)
+            }
+        };
+        if now < deadline && !self.connections.values().all(|c| c.is_done()) {
+            return Ok((false, wakers));
+        }
+        for c in self.connections.values_mut() {
+            c.abort_for_shutdown(&mut nics[c.device.0])?;
+        }
+        self.terminate = true;
+        Ok((true, wakers))
+    }
+}
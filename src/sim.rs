@@ -0,0 +1,402 @@
+use std::cmp::{self, Reverse};
+use std::collections::{BinaryHeap, VecDeque};
+use std::io::{self, IoSliceMut};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::clock::MockClock;
+use crate::nic::{self, Nic};
+
+/// How a [`SimNet`] mistreats the packets going one way.
+///
+/// The default is a perfect link: no loss, no reordering, no duplication, no latency and
+/// unlimited bandwidth.
+#[derive(Clone, Debug, Default)]
+pub struct Impairments {
+    /// probability that a packet is lost
+    pub drop: f64,
+    /// probability that a packet is held back by up to `reorder_window`, letting later ones
+    /// overtake it
+    pub reorder: f64,
+    pub reorder_window: Duration,
+    /// probability that a packet is delivered twice
+    pub duplicate: f64,
+    /// delay every packet gets
+    pub latency: Duration,
+    /// up to this much extra delay, picked at random per packet
+    pub jitter: Duration,
+    /// bytes per second the link carries, if limited
+    pub bandwidth: Option<u64>,
+    /// with `bandwidth`, how many packets may wait for the link before further ones are dropped
+    pub queue: Option<usize>,
+    /// with `queue`, refuse packets it has no room for, the way a device with a full transmit
+    /// queue does, rather than dropping them: the sender's `send` fails with `WouldBlock`, and
+    /// the sender's own queue backs up instead
+    pub backpressure: bool,
+}
+
+/// A simulated network connecting two [`SimNic`]s, for putting connections through controlled
+/// adversity.
+///
+/// Time on the network is virtual: packets in flight only arrive once [`SimNet::advance`] has
+/// moved the clock past their delivery time, and all randomness comes from per-direction
+/// generators seeded from the seed given to [`SimNet::new`], so a run can be replayed exactly.
+/// Give [`SimNet::clock`] to the interfaces on either end, and their timers run on the same
+/// virtual time.
+#[derive(Clone)]
+pub struct SimNet {
+    inner: Arc<Mutex<Inner>>,
+}
+
+/// One end of a [`SimNet`].
+pub struct SimNic {
+    inner: Arc<Mutex<Inner>>,
+    side: usize,
+    /// readable once for every packet in our inbox, so the packet loop can poll for them
+    ready: OwnedFd,
+    /// whether `recv_batch` takes in more than one packet at a time
+    batched: bool,
+}
+
+struct Inner {
+    clock: MockClock,
+    /// `links[i]` carries what side `i` sends to the other side
+    links: [Link; 2],
+    inboxes: [VecDeque<Vec<u8>>; 2],
+    notify: [OwnedFd; 2],
+    /// whether the nics are gone, failing everything as a device that went away does
+    unplugged: bool,
+    /// how many more sends fail, and with what OS error
+    failing_sends: (usize, i32),
+    /// packets the nics have taken in
+    received: u64,
+}
+
+struct Link {
+    impairments: Impairments,
+    rng: Rng,
+    in_flight: BinaryHeap<Reverse<(Duration, u64, Vec<u8>)>>,
+    /// tie-breaker keeping packets due at the same time in the order they were sent
+    sent: u64,
+    /// when the last packet finishes going onto the wire, if bandwidth is limited
+    busy_until: Duration,
+    /// when each packet still waiting for the link gets onto the wire
+    queued: VecDeque<Duration>,
+    /// packets dropped because the queue was full
+    overflows: u64,
+}
+
+impl SimNet {
+    /// Connect two new nics through a network that impairs packets from the first to the
+    /// second according to `a_to_b`, and the other way according to `b_to_a`.
+    pub fn new(
+        seed: u64,
+        a_to_b: Impairments,
+        b_to_a: Impairments,
+    ) -> io::Result<(SimNet, SimNic, SimNic)> {
+        let (ready_a, notify_a) = pipe()?;
+        let (ready_b, notify_b) = pipe()?;
+        let inner = Arc::new(Mutex::new(Inner {
+            clock: MockClock::new(),
+            links: [Link::new(a_to_b, seed), Link::new(b_to_a, !seed)],
+            inboxes: Default::default(),
+            notify: [notify_a, notify_b],
+            unplugged: false,
+            failing_sends: (0, 0),
+            received: 0,
+        }));
+        let nic = |side, ready| SimNic {
+            inner: inner.clone(),
+            side,
+            ready,
+            batched: true,
+        };
+        let (a, b) = (nic(0, ready_a), nic(1, ready_b));
+        Ok((SimNet { inner }, a, b))
+    }
+
+    /// How much virtual time has passed since the network was created.
+    pub fn now(&self) -> Duration {
+        self.inner.lock().unwrap().clock.elapsed()
+    }
+
+    /// The network's virtual time, as a clock for the interfaces on it.
+    pub fn clock(&self) -> MockClock {
+        self.inner.lock().unwrap().clock.clone()
+    }
+
+    /// Move virtual time forward by `by`, delivering every packet that arrives in the meantime.
+    pub fn advance(&self, by: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.clock.advance(by);
+        inner.deliver();
+    }
+
+    /// Have the links treat packets sent from now on according to `a_to_b` and `b_to_a`
+    /// instead, as a path does when it changes under a connection; packets already on their
+    /// way arrive when they were due to.
+    pub fn set_impairments(&self, a_to_b: Impairments, b_to_a: Impairments) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.links[0].impairments = a_to_b;
+        inner.links[1].impairments = b_to_a;
+    }
+
+    /// How many packets the nics have taken in between them.
+    pub fn received(&self) -> u64 {
+        self.inner.lock().unwrap().received
+    }
+
+    /// How many packets have been dropped in either direction because a link's queue was full.
+    pub fn overflows(&self) -> u64 {
+        let inner = self.inner.lock().unwrap();
+        inner.links.iter().map(|l| l.overflows).sum()
+    }
+
+    /// Have the next `count` packets either nic sends fail with the OS error `errno`, such as
+    /// `ENOBUFS`, without going anywhere.
+    pub fn fail_sends(&self, count: usize, errno: i32) {
+        self.inner.lock().unwrap().failing_sends = (count, errno);
+    }
+
+    /// Take both nics off the network for good: from now on they fail every call with
+    /// `ENODEV`, as a device that went away does, and each shows up readable once more so that
+    /// whoever polls it finds out.
+    pub fn unplug(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.unplugged = true;
+        for side in 0..2 {
+            wake(&inner.notify[side]);
+        }
+    }
+
+    /// How many packets are on their way in either direction.
+    pub fn in_flight(&self) -> usize {
+        let inner = self.inner.lock().unwrap();
+        inner.links.iter().map(|l| l.in_flight.len()).sum()
+    }
+}
+
+impl Inner {
+    fn deliver(&mut self) {
+        let now = self.clock.elapsed();
+        for side in 0..2 {
+            let link = &mut self.links[side];
+            while let Some(Reverse((due, _, _))) = link.in_flight.peek() {
+                if *due > now {
+                    break;
+                }
+                let Reverse((_, _, packet)) = link.in_flight.pop().unwrap();
+                self.inboxes[1 - side].push_back(packet);
+                wake(&self.notify[1 - side]);
+            }
+        }
+    }
+
+    fn check_plugged(&self) -> io::Result<()> {
+        if self.unplugged {
+            return Err(io::Error::from_raw_os_error(libc::ENODEV));
+        }
+        Ok(())
+    }
+}
+
+impl Link {
+    fn new(impairments: Impairments, seed: u64) -> Self {
+        Link {
+            impairments,
+            rng: Rng(seed),
+            in_flight: BinaryHeap::new(),
+            sent: 0,
+            busy_until: Duration::ZERO,
+            queued: VecDeque::new(),
+            overflows: 0,
+        }
+    }
+
+    /// Put `packet` on the link, returning false if the link refuses it (see
+    /// [`Impairments::backpressure`]).
+    fn send(&mut self, now: Duration, packet: &[u8]) -> bool {
+        let imp = &self.impairments;
+        if self.rng.chance(imp.drop) {
+            return true;
+        }
+
+        let mut on_wire = now;
+        if let Some(bandwidth) = imp.bandwidth {
+            while self.queued.front().is_some_and(|&t| t <= now) {
+                self.queued.pop_front();
+            }
+            if imp.queue.is_some_and(|limit| self.queued.len() >= limit) {
+                if imp.backpressure {
+                    return false;
+                }
+                self.overflows += 1;
+                return true;
+            }
+            let start = self.busy_until.max(now);
+            let nanos = packet.len() as u128 * 1_000_000_000 / bandwidth.max(1) as u128;
+            self.busy_until = start + Duration::from_nanos(nanos as u64);
+            on_wire = self.busy_until;
+            self.queued.push_back(on_wire);
+        }
+
+        let copies = if self.rng.chance(imp.duplicate) { 2 } else { 1 };
+        for _ in 0..copies {
+            let imp = &self.impairments;
+            let mut due = on_wire + imp.latency + self.rng.upto(imp.jitter);
+            if self.rng.chance(imp.reorder) {
+                due += self.rng.upto(imp.reorder_window);
+            }
+            self.sent += 1;
+            self.in_flight
+                .push(Reverse((due, self.sent, packet.to_vec())));
+        }
+        true
+    }
+}
+
+impl AsRawFd for SimNic {
+    fn as_raw_fd(&self) -> RawFd {
+        self.ready.as_raw_fd()
+    }
+}
+
+impl SimNic {
+    /// Have `recv_batch` take in one packet at a time, as a device does that cannot fetch more
+    /// in one system call, unless `batched`.
+    pub fn set_batched(&mut self, batched: bool) {
+        self.batched = batched;
+    }
+}
+
+impl nic::sealed::Sealed for SimNic {}
+
+impl Nic for SimNic {
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.lock().unwrap().check_plugged()?;
+        let mut b = 0u8;
+        let n = unsafe {
+            libc::read(
+                self.ready.as_raw_fd(),
+                &mut b as *mut u8 as *mut libc::c_void,
+                1,
+            )
+        };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        match inner.inboxes[self.side].pop_front() {
+            Some(packet) => {
+                inner.received += 1;
+                let len = packet.len().min(buf.len());
+                buf[..len].copy_from_slice(&packet[..len]);
+                Ok(len)
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn recv_batch(&mut self, bufs: &mut [IoSliceMut<'_>], lens: &mut [usize]) -> io::Result<usize> {
+        self.inner.lock().unwrap().check_plugged()?;
+        if !self.batched {
+            lens[0] = self.recv(&mut bufs[0])?;
+            return Ok(1);
+        }
+        // one wakeup byte per packet: take as many of both as fit in one go
+        let max = cmp::min(bufs.len(), lens.len());
+        let mut wakeups = [0u8; nic::BATCH];
+        let n = unsafe {
+            libc::read(
+                self.ready.as_raw_fd(),
+                wakeups.as_mut_ptr() as *mut libc::c_void,
+                cmp::min(max, wakeups.len()),
+            )
+        };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        let inbox = &mut inner.inboxes[self.side];
+        let mut received = 0;
+        while received < max {
+            let Some(packet) = inbox.pop_front() else {
+                break;
+            };
+            let len = packet.len().min(bufs[received].len());
+            bufs[received][..len].copy_from_slice(&packet[..len]);
+            lens[received] = len;
+            received += 1;
+        }
+        inner.received += received as u64;
+        Ok(received)
+    }
+
+    fn send(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.check_plugged()?;
+        if let (count @ 1.., errno) = inner.failing_sends {
+            inner.failing_sends.0 = count - 1;
+            return Err(io::Error::from_raw_os_error(errno));
+        }
+        let now = inner.clock.elapsed();
+        if !inner.links[self.side].send(now, buf) {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        // a perfect link delivers right away
+        inner.deliver();
+        Ok(buf.len())
+    }
+}
+
+/// Make the nic whose pipe `notify` writes to readable once more.
+fn wake(notify: &OwnedFd) {
+    // the pipe is only a wakeup; if it is somehow full, the reader is awake anyway
+    let _ = unsafe { libc::write(notify.as_raw_fd(), [0u8].as_ptr() as *const libc::c_void, 1) };
+}
+
+/// A pipe whose write end doesn't block, as (read end, write end).
+fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let (r, w) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+    let flags = unsafe { libc::fcntl(w.as_raw_fd(), libc::F_GETFL) };
+    if flags < 0
+        || unsafe { libc::fcntl(w.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((r, w))
+}
+
+/// A small deterministic random number generator (SplitMix64), so simulations don't depend on
+/// where their randomness comes from.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// true with probability `p`
+    fn chance(&mut self, p: f64) -> bool {
+        p > 0.0 && ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+
+    /// a duration picked uniformly from `[0, max]`
+    fn upto(&mut self, max: Duration) -> Duration {
+        if max.is_zero() {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos(self.next() % (max.as_nanos() as u64 + 1))
+    }
+}
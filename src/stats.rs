@@ -0,0 +1,208 @@
+use std::fmt;
+
+/// Why an incoming segment was discarded (in part, for [`DropReason::OutOfOrder`]).
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum DropReason {
+    /// the device failed to hand over a packet, with an error the packet loop carries on after
+    RecvFailed,
+    /// not an IP version 4 packet at all
+    NotIpv4,
+    /// the IPv4 header is malformed
+    BadIpHeader,
+    /// shorter than its headers, or than its IPv4 header says the packet is
+    Truncated,
+    /// an IPv4 packet, but not TCP
+    NotTcp,
+    /// the TCP header is malformed
+    BadTcpHeader,
+    BadChecksum,
+    /// addressed to an address none of our devices has
+    NotOurs,
+    /// a TCP MD5 signature missing, wrong or not expected at all (RFC 2385)
+    BadMd5,
+    /// arrived on a different device than the connection it is for uses
+    WrongDevice,
+    /// no connection, and nobody listening on the port
+    NoListener,
+    /// no connection, and not a SYN that a listener could accept
+    NoConnection,
+    /// a SYN that starts within what the last connection on the same addresses and ports
+    /// received, so most likely an old duplicate of its SYN; or, if that connection had
+    /// timestamps, one with a timestamp no newer than its last (RFC 6191)
+    OldIncarnation,
+    /// a SYN beyond the rate its listener answers them at
+    RateLimited,
+    /// a SYN from an address that holds as many connections against its listener as the
+    /// listener allows one address
+    SourceLimit,
+    /// a SYN that came in while its listener had as many handshakes under way as it keeps
+    HalfOpenLimit,
+    /// a SYN the accept hook turned down
+    Refused,
+    /// acknowledges something we have not sent
+    BadAck,
+    /// acknowledges less than the peer has acknowledged before, or in the handshake, not our
+    /// SYN
+    OldAck,
+    /// lacks the ACK bit where one is required
+    NoAckFlag,
+    /// outside the receive window (RFC 793 p.69)
+    OutOfWindow,
+    /// with a timestamp older than the latest the peer sent in order, so most likely an old
+    /// duplicate, whatever its sequence number (PAWS, RFC 7323 S5)
+    Paws,
+    /// data beyond RCV.NXT, with no room left to hold it until what comes before arrives
+    OutOfOrder,
+    /// an RST that does not pass the checks for the state it arrives in
+    BadRst,
+    /// in SYN-SENT, neither a SYN nor an RST
+    NoSyn,
+    /// in TIME-WAIT, anything but a retransmission of the peer's FIN
+    TimeWait,
+    /// for a connection that is already closed
+    AfterClose,
+    /// breaks a rule that [`Strictness::Strict`](crate::Strictness::Strict) holds the peer to;
+    /// the connection's [`Violations`] say which
+    Violation,
+    /// not a segment: the connection's sequence spaces came apart, which takes a bug here, and
+    /// it was aborted, with everything still on its way to it; counted once per abort
+    Desync,
+}
+
+impl DropReason {
+    /// Every reason, in the order [`Drops::iter`] goes through them.
+    pub const ALL: [DropReason; 29] = [
+        DropReason::RecvFailed,
+        DropReason::NotIpv4,
+        DropReason::BadIpHeader,
+        DropReason::Truncated,
+        DropReason::NotTcp,
+        DropReason::BadTcpHeader,
+        DropReason::BadChecksum,
+        DropReason::NotOurs,
+        DropReason::BadMd5,
+        DropReason::WrongDevice,
+        DropReason::NoListener,
+        DropReason::NoConnection,
+        DropReason::OldIncarnation,
+        DropReason::RateLimited,
+        DropReason::SourceLimit,
+        DropReason::HalfOpenLimit,
+        DropReason::Refused,
+        DropReason::BadAck,
+        DropReason::OldAck,
+        DropReason::NoAckFlag,
+        DropReason::OutOfWindow,
+        DropReason::Paws,
+        DropReason::OutOfOrder,
+        DropReason::BadRst,
+        DropReason::NoSyn,
+        DropReason::TimeWait,
+        DropReason::AfterClose,
+        DropReason::Violation,
+        DropReason::Desync,
+    ];
+}
+
+impl fmt::Display for DropReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// How many segments have been discarded, by reason.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Drops {
+    counts: [u64; DropReason::ALL.len()],
+}
+
+impl Drops {
+    /// How many segments have been discarded for `reason`.
+    pub fn get(&self, reason: DropReason) -> u64 {
+        self.counts[reason as usize]
+    }
+
+    /// How many segments have been discarded altogether.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// The count for every reason.
+    pub fn iter(&self) -> impl Iterator<Item = (DropReason, u64)> + '_ {
+        DropReason::ALL.into_iter().zip(self.counts)
+    }
+
+    pub(crate) fn record(&mut self, reason: DropReason) {
+        self.counts[reason as usize] += 1;
+    }
+}
+
+/// A rule of the protocol a peer broke, which [`TcpConfig::strictness`](crate::TcpConfig::strictness)
+/// decides what to make of.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Violation {
+    /// data in SYN-RECEIVED, on a segment without the ACK bit that would complete the handshake
+    DataBeforeAck,
+    /// an ACK that moves the right edge of the send window to the left (RFC 1122 S4.2.2.16)
+    ShrunkWindow,
+    /// a keepalive or window probe without the ACK bit
+    ProbeWithoutAck,
+}
+
+impl Violation {
+    /// Every violation, in the order [`Violations::iter`] goes through them.
+    pub const ALL: [Violation; 3] = [
+        Violation::DataBeforeAck,
+        Violation::ShrunkWindow,
+        Violation::ProbeWithoutAck,
+    ];
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// How many times the peer broke a rule, by rule, whether or not the segments were let through.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Violations {
+    counts: [u64; Violation::ALL.len()],
+}
+
+impl Violations {
+    /// How many times the peer broke `rule`.
+    pub fn get(&self, rule: Violation) -> u64 {
+        self.counts[rule as usize]
+    }
+
+    /// How many times the peer broke any rule.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// The count for every rule.
+    pub fn iter(&self) -> impl Iterator<Item = (Violation, u64)> + '_ {
+        Violation::ALL.into_iter().zip(self.counts)
+    }
+
+    pub(crate) fn record(&mut self, rule: Violation) {
+        self.counts[rule as usize] += 1;
+    }
+}
+
+/// How many replies rate limits have held back, by kind.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct RateLimited {
+    /// SYN-ACKs to SYNs beyond their listener's limit; the SYNs count as dropped too
+    pub syn_acks: u64,
+    /// RSTs in reply to segments a connection cannot take
+    pub rsts: u64,
+    /// ACKs in reply to segments outside the window
+    pub challenge_acks: u64,
+}
@@ -1,35 +1,1045 @@
+use std::cmp;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
 use std::io;
-use std::io::prelude::*;
+use std::mem;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::checksum::{self, SendSums};
+use crate::clock::Clock;
+use crate::fastopen::{self, Cookie};
+use crate::md5::Md5;
+use crate::nic::{DeviceId, Flow, Outbound, Priority};
+use crate::ratelimit::ReplyLimits;
+use crate::reassembly::Reassembly;
+use crate::scoreboard::{LossRule, Scoreboard};
+use crate::stats::{DropReason, Drops, Violation, Violations};
+use crate::trace::{Direction, PacketTrace, TracedPacket};
+use crate::{ListenKey, Listener};
+
+/// The largest window scale shift there is (RFC 7323 S2.3).
+const MAX_WSCALE: u8 = 14;
+
+/// Lower bound on the retransmission timeout (RFC 6298 (2.4)).
+const MIN_RTO: Duration = Duration::from_secs(1);
+
+/// Upper bound on the retransmission timeout as it backs off (RFC 6298 (2.5)).
+const MAX_RTO: Duration = Duration::from_secs(60);
+
+/// The largest packet we build, whatever the device's MTU: the most an IPv4 header can describe.
+const MAX_PACKET: usize = u16::MAX as usize;
+
+/// Space taken up by the IP and TCP headers (without options) in every packet.
+const HEADERS_LEN: usize = 40;
+
+/// Duplicate ACKs that signal a lost segment (RFC 5681 S3.2).
+const DUP_ACK_THRESHOLD: u32 = 3;
+
+/// The MSS to assume when the peer's SYN does not carry the option (RFC 1122 S4.2.2.6).
+const DEFAULT_MSS: u16 = 536;
+
+/// The smallest MSS we take a peer at its word for, as Linux; anything smaller would leave a
+/// segment no room for data, or next to none.
+const MIN_MSS: u16 = 88;
+
+/// TCP option kinds (RFC 793 S3.1, RFC 7323 S2-3, RFC 2018 S2, RFC 2385 S3.0).
+const OPTION_END: u8 = 0;
+const OPTION_NOP: u8 = 1;
+const OPTION_MSS: u8 = 2;
+const OPTION_WSCALE: u8 = 3;
+const OPTION_SACK_PERMITTED: u8 = 4;
+const OPTION_SACK: u8 = 5;
+const OPTION_TIMESTAMPS: u8 = 8;
+const OPTION_MD5: u8 = 19;
+const OPTION_FAST_OPEN: u8 = 34;
+
+/// The most options a TCP header has room for.
+const MAX_OPTIONS_LEN: usize = 40;
+
+/// Room the MD5 signature option takes up, aligned with two NOPs.
+const MD5_OPTION_LEN: usize = 20;
+
+/// Room the timestamps option takes up on a segment other than a SYN, aligned with two NOPs.
+const TIMESTAMPS_OPTION_LEN: usize = 12;
+
+/// How long TS.Recent stays good for PAWS without being refreshed: after this, the peer's
+/// timestamp clock may have gone more than halfway round (RFC 7323 S5.5).
+const PAWS_IDLE: Duration = Duration::from_secs(24 * 24 * 60 * 60);
+
+/// The longest TCP MD5 signature key we take, as in Linux.
+pub(crate) const MAX_MD5_KEY: usize = 80;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum State {
-    Closed,
-    Listen,
+    SynSent,
     SynRcvd,
     Estab,
     FinWait1,
     FinWait2,
+    CloseWait,
+    LastAck,
     Closing,
+    TimeWait,
+    Closed,
 }
 
 impl State {
     fn is_synchronized(&self) -> bool {
         match *self {
-            State::SynRcvd => false,
-            State::Estab | State::FinWait1 | State::FinWait2 | State::Closing => true,
+            State::SynSent | State::SynRcvd | State::Closed => false,
+            State::Estab
+            | State::FinWait1
+            | State::FinWait2
+            | State::CloseWait
+            | State::LastAck
+            | State::Closing
+            | State::TimeWait => true,
+        }
+    }
+
+    /// Whether the peer has sent its FIN, so no more data will arrive.
+    fn is_rcv_closed(&self) -> bool {
+        matches!(
+            *self,
+            State::CloseWait | State::LastAck | State::Closing | State::TimeWait | State::Closed
+        )
+    }
+}
+
+/// Why a connection closed, as recorded when it leaves the synchronized states; see
+/// [`TcpStream::close_reason`](crate::TcpStream::close_reason).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CloseReason {
+    /// the application closed first, or before the handshake got anywhere, and the peer
+    /// followed
+    Local,
+    /// the peer sent its FIN first, and the application followed
+    PeerFin,
+    /// the peer reset the connection
+    PeerReset,
+    /// the peer answered our SYN with an RST
+    Refused,
+    /// the peer sent nothing in FIN-WAIT-2 for [`TcpConfig::fin_wait2_timeout`]
+    FinWait2Timeout,
+    /// the peer sent nothing for [`TcpConfig::idle_timeout`]
+    IdleTimeout,
+    /// the peer kept its window closed for [`TcpConfig::zero_window_timeout`]
+    ZeroWindowTimeout,
+    /// the listener that took the connection in closed before the application accepted it
+    ListenerClosed,
+    /// the sequence spaces came apart, which takes a bug here
+    Desync,
+    /// the interface was dropped with the connection still open
+    InterfaceShutdown,
+    /// the application aborted it with [`Interface::abort`](crate::Interface::abort)
+    LocalAbort,
+}
+
+/// Tunables that affect how a connection behaves on the wire.
+#[derive(Clone, Debug)]
+pub struct TcpConfig {
+    /// When data is written during an active open, carry up to one segment of it on the ACK
+    /// that completes the handshake instead of sending a bare ACK first.
+    pub piggyback_handshake_data: bool,
+    /// How long a connection may sit in FIN-WAIT-2 without hearing from the peer before it is
+    /// dropped, without sending anything (like Linux's `tcp_fin_timeout`).
+    pub fin_wait2_timeout: Duration,
+    /// If set, an established connection that has received nothing from the peer for this long
+    /// is aborted with an RST. Unlike a keepalive, nothing is sent to check on the peer first.
+    pub idle_timeout: Option<Duration>,
+    /// Spread new data evenly over each round trip, at roughly one send window per smoothed
+    /// RTT, rather than sending everything the window allows back to back. Retransmissions and
+    /// pure ACKs are never held back.
+    pub pacing: bool,
+    /// With `pacing`, how many full-sized segments may still go out back to back after the
+    /// connection has been quiet for a while.
+    pub pacing_burst: usize,
+    /// How many full-sized segments' worth an ACK may grow the congestion window by in slow
+    /// start, however much it acknowledges (L in RFC 3465 S2.2); 1 or 2.
+    pub abc_limit: usize,
+    /// ACKs that end inside a segment we sent and acknowledge fewer bytes than this, as a
+    /// receiver splitting its ACKs to have the window grow faster sends, do not grow the
+    /// congestion window by themselves: what they acknowledge counts towards the next ACK
+    /// that ends where a segment does, and the limit on that ACK covers it all. Receivers
+    /// that delay their ACKs, or get small segments, ACK where segments end, and are not
+    /// held back. 0 turns the check off.
+    pub split_ack_floor: usize,
+    /// Send a new segment on each duplicate ACK short of the threshold, the first two by
+    /// default (RFC 3042), so that a loss with only a few segments in flight still draws
+    /// enough duplicate ACKs for a fast retransmit.
+    pub limited_transmit: bool,
+    /// After a retransmission timeout, check whether it was spurious before going back N, and
+    /// undo the congestion response if it was: by the TSecr of the first ACK (Eifel, RFC 3522)
+    /// on a connection with timestamps, and with F-RTO (RFC 5682) on one without.
+    pub frto: bool,
+    /// How many full-sized segments the congestion window starts out at, though never more
+    /// than 1460 bytes' worth each, nor fewer than two of them unless this says so; 10 by
+    /// default, as in RFC 6928.
+    pub initial_cwnd_segments: u32,
+    /// Shrink the congestion window back to the initial window after sending nothing for a
+    /// retransmission timeout (RFC 5681 S4.1), since what it had grown to says little about
+    /// the network by then. Congestion window validation takes over from this when enabled.
+    /// There is no such caution after ICMP soft errors, such as a redirect: the stack takes in
+    /// no ICMP.
+    pub slow_start_after_idle: bool,
+    /// If set, validate the congestion window as RFC 7661 does: it only grows while the
+    /// connection uses at least half of it, rather than the application sending too little to
+    /// fill it, and each time this long goes by without the connection doing so, it shrinks
+    /// halfway to the most that was in flight meanwhile, but not below the initial window.
+    /// RFC 7661 waits 5 minutes.
+    pub cwnd_validation: Option<Duration>,
+    /// How long a corked stream may hold back less than a full segment of data before sending
+    /// it anyway (like Linux's 200ms for `TCP_CORK`).
+    pub cork_timeout: Duration,
+    /// Hold back less than a full segment of data while anything sent is unacknowledged
+    /// (Nagle's algorithm, RFC 896 and RFC 1122 S4.2.3.4), so that small writes go out together
+    /// once the ACK comes. Off by default, as though every stream had `TCP_NODELAY` set: what
+    /// is written goes out on the next tick, however little it is.
+    pub nagle: bool,
+    /// How much data the application may have written but the peer not yet acknowledged.
+    pub send_buffer: usize,
+    /// How much received data is buffered for the application before the window closes; where
+    /// the buffer starts out when auto-tuning.
+    pub recv_buffer: usize,
+    /// Grow the receive buffer, and with it the window, while the peer sends more per round
+    /// trip than it holds and the application keeps up, up to `recv_buffer_max`; shrink it back
+    /// once the connection goes idle.
+    pub recv_autotune: bool,
+    pub recv_buffer_max: usize,
+    /// If set, a connection whose peer has kept its window closed for this long, answering
+    /// every probe with a zero window while our data waits, is aborted with an RST rather than
+    /// left to hold on to its send buffer for good.
+    pub zero_window_timeout: Option<Duration>,
+    /// The maximum segment lifetime: how long a segment may survive in the network (RFC 793
+    /// S3.3). TIME-WAIT lasts twice this long, and so does the record a connection closed
+    /// elsewhere leaves behind to keep the next one on its quad clear of its segments; the
+    /// clock initial sequence numbers are picked from (see RFC 6528) is slowed down if need
+    /// be not to come round in that time. It is 30 seconds by default, as on Linux, rather
+    /// than RFC 793's 2 minutes, and only takes effect for an interface when it is built.
+    pub msl: Duration,
+    /// Let a SYN for the addresses and ports of a connection in TIME-WAIT end it early and
+    /// open a new connection, if it starts past everything the old one received and carries a
+    /// newer timestamp than the old one's last (RFC 6191). Only connections that had
+    /// timestamps qualify. Off by default, so that TIME-WAIT always lasts twice the MSL.
+    pub time_wait_reuse: bool,
+    /// How readily connections take something for lost and send it again; the fields below
+    /// override single values of it.
+    pub profile: Profile,
+    /// How many duplicate ACKs call for a fast retransmit, if not as `profile` has it; at
+    /// least 1.
+    pub dup_ack_threshold: Option<u32>,
+    /// If set, raise the duplicate ACK threshold as the network is found to reorder segments,
+    /// to one more than the most duplicate ACKs a segment that was only late has drawn, but to
+    /// no more than this; it goes back down after a retransmission timeout. A fast retransmit
+    /// is taken to have been for a late segment when the hole it was for fills in less than
+    /// half a round trip after it, and undone if nothing else turns out missing.
+    pub max_dup_ack_threshold: Option<u32>,
+    /// The least the retransmission timeout may be, if not as `profile` has it. Timers only
+    /// run every 10 milliseconds, so anything shorter is as good as 10 milliseconds.
+    pub min_rto: Option<Duration>,
+    /// The most the retransmission timeout backs off to, if not as `profile` has it.
+    pub max_rto: Option<Duration>,
+    /// The retransmission timeout until the first round trip is measured, SYNs included, if
+    /// not as `profile` has it.
+    pub initial_rto: Option<Duration>,
+    /// How many times a connection taken in by a listener sends its SYN-ACK again, backing
+    /// off as retransmissions do, before it gives up on the handshake and is dropped without
+    /// a word to the peer; 5 by default, as Linux's `tcp_synack_retries`.
+    pub syn_ack_retries: u32,
+    /// How many of its latest segments each connection keeps a copy of, headers and the first
+    /// 64 bytes of data, for [`TcpStream::trace_dump`](crate::TcpStream::trace_dump); none by
+    /// default.
+    pub trace_packets: usize,
+    /// Offer SACK-permitted in our SYNs (RFC 2018), and once the peer has offered it too,
+    /// report the data we hold beyond a gap in SACK blocks on every segment we send while the
+    /// gap is there, and recover from loss by the peer's blocks (RFC 6675): only what they
+    /// leave out goes again, as soon as enough was SACKed beyond it.
+    pub sack: bool,
+    /// Offer the timestamps option in our SYNs (RFC 7323), and once the peer has offered it
+    /// too, carry it on every segment but an RST, echoing the peer's latest. The clock it
+    /// reads ticks every millisecond, from an offset of each quad's own.
+    pub timestamps: bool,
+    /// What connections make of peers that break the rules in [`Violation`].
+    pub strictness: Strictness,
+    /// Whether dropping a stream resets its connection rather than closing it; see
+    /// [`TcpStream::set_abort_on_drop`](crate::TcpStream::set_abort_on_drop).
+    pub abort_on_drop: bool,
+}
+
+impl Default for TcpConfig {
+    fn default() -> Self {
+        TcpConfig {
+            piggyback_handshake_data: false,
+            fin_wait2_timeout: Duration::from_secs(60),
+            idle_timeout: None,
+            pacing: false,
+            pacing_burst: 4,
+            abc_limit: 2,
+            split_ack_floor: 536,
+            limited_transmit: true,
+            frto: true,
+            initial_cwnd_segments: 10,
+            slow_start_after_idle: true,
+            cwnd_validation: None,
+            cork_timeout: Duration::from_millis(200),
+            nagle: false,
+            send_buffer: 64 * 1024,
+            recv_buffer: u16::MAX as usize,
+            recv_autotune: false,
+            recv_buffer_max: 4 * 1024 * 1024,
+            zero_window_timeout: None,
+            msl: Duration::from_secs(30),
+            time_wait_reuse: false,
+            profile: Profile::Internet,
+            dup_ack_threshold: None,
+            max_dup_ack_threshold: None,
+            min_rto: None,
+            max_rto: None,
+            initial_rto: None,
+            syn_ack_retries: 5,
+            trace_packets: 0,
+            sack: true,
+            timestamps: true,
+            strictness: Strictness::default(),
+            abort_on_drop: false,
+        }
+    }
+}
+
+impl TcpConfig {
+    /// What connections made with this configuration go by: the profile's values, with the
+    /// overrides in place.
+    pub fn loss_response(&self) -> LossResponse {
+        let profile = self.profile.loss_response();
+        LossResponse {
+            dup_ack_threshold: cmp::max(
+                self.dup_ack_threshold.unwrap_or(profile.dup_ack_threshold),
+                1,
+            ),
+            min_rto: self.min_rto.unwrap_or(profile.min_rto),
+            max_rto: self.max_rto.unwrap_or(profile.max_rto),
+            initial_rto: self.initial_rto.unwrap_or(profile.initial_rto),
+        }
+    }
+
+    /// The configuration as connections get it, or everything wrong with it.
+    ///
+    /// Settings no connection could run with are errors, all of them listed in the
+    /// [`ConfigError`]. A few others are only clamped: `abc_limit` to 2, the most RFC 3465
+    /// allows, and `recv_buffer` and `recv_buffer_max` to the largest window a scaled window
+    /// field can advertise (65535 << 14 bytes). [`InterfaceBuilder`](crate::InterfaceBuilder)
+    /// validates its configuration when building an interface, so there is no need to call this
+    /// first but to find out early.
+    pub fn validate(&self) -> Result<TcpConfig, ConfigError> {
+        let mut problems = Vec::new();
+        if self.send_buffer == 0 {
+            problems.push(ConfigProblem::NoSendBuffer);
+        }
+        if self.recv_buffer == 0 {
+            problems.push(ConfigProblem::NoRecvBuffer);
+        }
+        if self.recv_autotune && self.recv_buffer_max < self.recv_buffer {
+            problems.push(ConfigProblem::RecvBufferMaxBelowRecvBuffer);
+        }
+        if self.abc_limit == 0 {
+            problems.push(ConfigProblem::NoAbcLimit);
+        }
+        if self.initial_cwnd_segments == 0 {
+            problems.push(ConfigProblem::NoInitialCwnd);
+        }
+        if self.pacing && self.pacing_burst == 0 {
+            problems.push(ConfigProblem::NoPacingBurst);
+        }
+        if self.dup_ack_threshold == Some(0) {
+            problems.push(ConfigProblem::NoDupAckThreshold);
+        }
+        let loss = self.loss_response();
+        if self
+            .max_dup_ack_threshold
+            .is_some_and(|max| max < loss.dup_ack_threshold)
+        {
+            problems.push(ConfigProblem::MaxDupAckThresholdBelowDupAckThreshold);
+        }
+        if loss.min_rto > loss.max_rto {
+            problems.push(ConfigProblem::MinRtoAboveMaxRto);
+        }
+        if loss.initial_rto.is_zero() {
+            problems.push(ConfigProblem::NoInitialRto);
+        }
+        if !problems.is_empty() {
+            return Err(ConfigError { problems });
+        }
+
+        let most = (u16::MAX as usize) << MAX_WSCALE;
+        let mut config = self.clone();
+        config.abc_limit = cmp::min(config.abc_limit, 2);
+        config.recv_buffer = cmp::min(config.recv_buffer, most);
+        config.recv_buffer_max = cmp::min(config.recv_buffer_max, most);
+        Ok(config)
+    }
+}
+
+/// A [`TcpConfig`] no connection could run with, carried in the [`io::Error`] building an
+/// interface with it fails with, which [`ConfigError::of`] finds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ConfigError {
+    /// everything wrong with it, in the order of the fields
+    pub problems: Vec<ConfigProblem>,
+}
+
+impl ConfigError {
+    /// The configuration problems behind `e`, if that is what it is.
+    pub fn of(e: &io::Error) -> Option<&ConfigError> {
+        e.get_ref()?.downcast_ref()
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid TCP configuration")?;
+        for (i, problem) in self.problems.iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { ": " } else { "; " }, problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<ConfigError> for io::Error {
+    fn from(e: ConfigError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, e)
+    }
+}
+
+/// One thing wrong with a [`TcpConfig`]; see [`TcpConfig::validate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ConfigProblem {
+    /// `send_buffer` is 0, so nothing could ever be written
+    NoSendBuffer,
+    /// `recv_buffer` is 0, so the window would never open
+    NoRecvBuffer,
+    /// `recv_autotune` is on with `recv_buffer_max` below `recv_buffer`
+    RecvBufferMaxBelowRecvBuffer,
+    /// `abc_limit` is 0, so slow start would never grow the congestion window
+    NoAbcLimit,
+    /// `initial_cwnd_segments` is 0, so nothing could be sent
+    NoInitialCwnd,
+    /// `pacing` is on with a `pacing_burst` of 0
+    NoPacingBurst,
+    /// `dup_ack_threshold` is 0
+    NoDupAckThreshold,
+    /// `max_dup_ack_threshold` is below the duplicate ACK threshold, overrides and profile
+    /// taken together
+    MaxDupAckThresholdBelowDupAckThreshold,
+    /// the least retransmission timeout is above the most, overrides and profile taken together
+    MinRtoAboveMaxRto,
+    /// the initial retransmission timeout is zero
+    NoInitialRto,
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ConfigProblem::NoSendBuffer => "send_buffer is 0",
+            ConfigProblem::NoRecvBuffer => "recv_buffer is 0",
+            ConfigProblem::RecvBufferMaxBelowRecvBuffer => {
+                "recv_buffer_max is below recv_buffer with recv_autotune on"
+            }
+            ConfigProblem::NoAbcLimit => "abc_limit is 0",
+            ConfigProblem::NoInitialCwnd => "initial_cwnd_segments is 0",
+            ConfigProblem::NoPacingBurst => "pacing_burst is 0 with pacing on",
+            ConfigProblem::NoDupAckThreshold => "dup_ack_threshold is 0",
+            ConfigProblem::MaxDupAckThresholdBelowDupAckThreshold => {
+                "max_dup_ack_threshold is below dup_ack_threshold"
+            }
+            ConfigProblem::MinRtoAboveMaxRto => "min_rto is above max_rto",
+            ConfigProblem::NoInitialRto => "initial_rto is zero",
+        })
+    }
+}
+
+/// Sets of values for how connections respond to loss, for the kind of network they run over;
+/// see [`TcpConfig::profile`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Profile {
+    /// short, clean paths with round trips well under a millisecond: retransmit on the second
+    /// duplicate ACK, and after as little as 10 milliseconds without one
+    Datacenter,
+    /// what the RFCs recommend for the Internet at large (RFC 5681, RFC 6298)
+    #[default]
+    Internet,
+    /// slow paths that lose and hold back packets now and then, such as cellular links, where
+    /// retransmitting early wastes more than waiting does
+    Lossy,
+}
+
+impl Profile {
+    /// The profile's values, before any [`TcpConfig`] overrides.
+    pub fn loss_response(self) -> LossResponse {
+        match self {
+            Profile::Datacenter => LossResponse {
+                dup_ack_threshold: 2,
+                min_rto: Duration::from_millis(10),
+                max_rto: Duration::from_secs(10),
+                initial_rto: Duration::from_millis(200),
+            },
+            Profile::Internet => LossResponse {
+                dup_ack_threshold: DUP_ACK_THRESHOLD,
+                min_rto: MIN_RTO,
+                max_rto: MAX_RTO,
+                initial_rto: MIN_RTO,
+            },
+            // RFC 6298 S5.7 falls back to RFC 2988's initial 3 seconds when the SYN or its
+            // answer is lost
+            Profile::Lossy => LossResponse {
+                dup_ack_threshold: DUP_ACK_THRESHOLD,
+                min_rto: MIN_RTO,
+                max_rto: Duration::from_secs(120),
+                initial_rto: Duration::from_secs(3),
+            },
+        }
+    }
+}
+
+/// What a connection makes of a peer that breaks one of the smaller rules of the protocol, the
+/// ones some embedded stacks get wrong; see [`Violation`] for which. Either way, the connection
+/// counts each time it happens, in [`TcpStream::violations`](crate::TcpStream::violations).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Strictness {
+    /// discard segments that break a rule, as [`DropReason::Violation`], and keep the send
+    /// window to the right edge the peer advertised before it shrank it
+    #[default]
+    Strict,
+    /// make the most of them: data before the ACK that completes the handshake is taken as
+    /// having acknowledged our SYN, a shrunk window is taken as it is, and a keepalive without
+    /// the ACK bit is answered as if it had it
+    Permissive,
+}
+
+/// How a connection responds to loss; see [`TcpConfig::loss_response`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LossResponse {
+    /// duplicate ACKs that call for a fast retransmit
+    pub dup_ack_threshold: u32,
+    /// the least the retransmission timeout may be
+    pub min_rto: Duration,
+    /// the most the retransmission timeout backs off to
+    pub max_rto: Duration,
+    /// the retransmission timeout before a round trip has been measured
+    pub initial_rto: Duration,
+}
+
+/// What a connection negotiated during its handshake, and where it stands now.
+///
+/// The negotiated fields do not change once the handshake has completed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ConnectionInfo {
+    pub state: State,
+    /// the largest segment we send
+    pub mss: u16,
+    /// the largest segment the peer takes, going by its SYN (536 bytes if the SYN does not
+    /// say), once that is in
+    pub peer_mss: Option<u16>,
+    /// shift applied to the windows we advertise
+    pub rcv_wscale: u8,
+    /// shift applied to the windows the peer advertises
+    pub snd_wscale: u8,
+    pub sack: bool,
+    pub timestamps: bool,
+    pub ecn: bool,
+    /// data in the client's SYN was taken with TCP Fast Open (RFC 7413), so the server may
+    /// have seen it more than once
+    pub fast_open: bool,
+    /// smoothed round-trip time
+    pub srtt: Duration,
+    /// the peer's last advertised window, in bytes
+    pub peer_window: u32,
+    /// congestion window, in bytes
+    pub cwnd: u32,
+    /// data sent and not yet known to have left the network, which the congestion window
+    /// bounds, in bytes
+    pub bytes_in_flight: u32,
+    /// slow start threshold, in bytes
+    pub ssthresh: u32,
+    /// how much received data we buffer now, in bytes
+    pub rcv_buffer: u32,
+    /// how much data the application may have written but the peer not yet acknowledged, in
+    /// bytes
+    pub send_buffer: u32,
+    /// retransmission timeouts found to be spurious, and undone
+    pub spurious_rtos: u32,
+    /// fast retransmits found to be for segments that were only late
+    pub spurious_fast_retransmits: u32,
+    /// the most segments seen to overtake one that was only late, by the duplicate ACKs it
+    /// drew or the data SACKed beyond it
+    pub reordering_seen: u32,
+    /// how many duplicate ACKs call for a fast retransmit now, which
+    /// [`TcpConfig::max_dup_ack_threshold`] may have raised
+    pub dup_ack_threshold: u32,
+    /// ACKs that ended inside a segment and acknowledged less than
+    /// [`TcpConfig::split_ack_floor`], which a receiver out to grow the window faster sends
+    pub suspicious_acks: u64,
+    /// ACKs of data we had not sent yet, or had only queued for the device, which a receiver
+    /// out to have us send faster than the network allows guesses at; discarded
+    pub optimistic_acks: u64,
+    /// the connection used at least half of its congestion window over the last round trip,
+    /// so that the window reflects the network rather than how much the application sends
+    /// (RFC 7661's validated phase)
+    pub cwnd_validated: bool,
+    /// bytes the peer sent again that we had already received, and discarded
+    pub duplicate_bytes: u64,
+    /// bytes that arrived beyond a gap, held until it fills
+    pub out_of_order_bytes: u32,
+    /// bytes the peer sent past the right edge of the window we advertised, and discarded
+    pub window_overrun_bytes: u64,
+    /// ACKs sent unasked to tell the peer that a window we had closed is open again, repeats
+    /// included
+    pub window_reopen_acks: u64,
+    /// why the connection closed, once it has
+    pub close_reason: Option<CloseReason>,
+    /// the profile the connection was made with
+    pub profile: Profile,
+    /// how the connection responds to loss: its profile's values, with the overrides in place
+    pub loss_response: LossResponse,
+}
+
+/// What has to have arrived before readers blocked on a stream are woken; see
+/// [`TcpStream::set_read_trigger`](crate::TcpStream::set_read_trigger).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReadTrigger {
+    /// any data at all
+    #[default]
+    Any,
+    /// at least this many bytes
+    Bytes(usize),
+    /// a byte of this value, such as the `b'\n'` that ends a line
+    Delimiter(u8),
+}
+
+/// How long data may sit unread before readers blocked on a stream are woken for it; see
+/// [`TcpStream::set_read_coalescing`](crate::TcpStream::set_read_coalescing).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReadCoalescing {
+    /// wake readers once this much data is buffered
+    pub bytes: usize,
+    /// or once the oldest unread data has waited this long
+    pub delay: Duration,
+}
+
+impl Default for ReadCoalescing {
+    fn default() -> Self {
+        ReadCoalescing {
+            bytes: 16 * 1024,
+            delay: Duration::from_millis(1),
+        }
+    }
+}
+
+/// What the peer's SYN looked like on the wire, for logging or telling stacks apart by; see
+/// [`TcpStream::syn_metadata`](crate::TcpStream::syn_metadata).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SynMetadata {
+    /// the TCP options, byte for byte and in the order they came, padding included
+    pub options: Vec<u8>,
+    /// the window, which a SYN never scales
+    pub window: u16,
+    pub ttl: u8,
+    /// the IPv4 identification field
+    pub ip_id: u16,
+}
+
+/// A connection a listener is taking in, before it answers the peer's SYN; see
+/// [`Interface::set_accept_hook`](crate::Interface::set_accept_hook).
+pub struct PendingAccept {
+    c: Connection,
+}
+
+impl PendingAccept {
+    /// Our end of the connection.
+    pub fn local_addr(&self) -> SocketAddrV4 {
+        SocketAddrV4::new(self.c.ip.source.into(), self.c.ports.0)
+    }
+
+    /// The end that sent the SYN.
+    pub fn peer_addr(&self) -> SocketAddrV4 {
+        SocketAddrV4::new(self.c.ip.destination.into(), self.c.ports.1)
+    }
+
+    /// What the handshake settles, going by the peer's SYN and what we offer in reply.
+    pub fn info(&self) -> ConnectionInfo {
+        self.c.info()
+    }
+
+    /// What the peer's SYN looked like.
+    pub fn syn_metadata(&self) -> &SynMetadata {
+        self.c.syn.as_ref().expect("taken in from a SYN")
+    }
+
+    /// How many bytes of options the SYN-ACK has room for besides its own.
+    pub fn options_room(&self) -> usize {
+        self.c.syn_options_room()
+    }
+
+    /// Put `options`, as they go on the wire, in the SYN-ACK after its own ones, with NOPs
+    /// after them up to a multiple of four bytes. Retransmissions of the SYN-ACK carry them
+    /// too. Fails with `InvalidInput` if that does not fit in [`PendingAccept::options_room`].
+    pub fn set_extra_options(&mut self, options: &[u8]) -> io::Result<()> {
+        let padded = options.len().div_ceil(4) * 4;
+        if padded > self.options_room() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "options do not fit in the SYN-ACK",
+            ));
         }
+        self.c.extra_syn_options = options.to_vec();
+        self.c.extra_syn_options.resize(padded, OPTION_NOP);
+        Ok(())
+    }
+
+    /// The options [`PendingAccept::set_extra_options`] put in the SYN-ACK, padding included.
+    pub fn extra_options(&self) -> &[u8] {
+        &self.c.extra_syn_options
+    }
+
+    /// Turn the SYN away with an RST, acknowledging it so that the peer takes the RST
+    /// (<SEQ=0><ACK=SEG.SEQ+SEG.LEN><CTL=RST,ACK>, RFC 793 p.65).
+    pub(crate) fn refuse(self, nic: &mut Outbound) -> io::Result<()> {
+        let mut c = self.c;
+        c.send_rst(nic, 0, true)
+    }
+
+    /// The connection as it will be handed over.
+    pub(crate) fn connection_mut(&mut self) -> &mut Connection {
+        &mut self.c
+    }
+
+    /// Answer the SYN, and hand over the connection.
+    pub(crate) fn commit(self, nic: &mut Outbound) -> io::Result<Connection> {
+        let mut c = self.c;
+        c.emit(nic, c.syn_flags(), c.send.nxt, 0)?;
+        Ok(c)
     }
 }
 
-pub struct Connection {
+/// What a connection's receive side holds, for the interface to keep within its receive memory
+/// limit.
+pub(crate) struct RcvMemory {
+    /// data waiting for the application
+    pub(crate) buffered: usize,
+    /// data waiting, and the window we advertised that the peer may still fill
+    pub(crate) committed: usize,
+    /// how much more the buffer would take
+    pub(crate) wanted: usize,
+    /// how long data has waited without the application reading any, if there is some
+    pub(crate) unread_for: Option<Duration>,
+}
+
+pub(crate) struct Connection {
     state: State,
+    /// the device the connection's packets go out on
+    pub(crate) device: DeviceId,
+    /// the options we offer in our SYN, and those of the peer's once it is in
+    handshake: Handshake,
+    /// both ends put timestamps on every segment, as the handshake settled
+    timestamps: bool,
+    /// both ends take SACK blocks, as the handshake settled
+    sack: bool,
+    /// where the TSvals we send come from
+    ts_clock: TsClock,
+    /// the peer's TSval to echo in ours (TS.Recent, RFC 7323 S4.3)
+    ts_recent: u32,
+    /// when TS.Recent last changed, for PAWS to tell whether it is still good
+    ts_recent_at: Instant,
+    /// the largest segment we send: the peer's MSS, capped by our own
+    mss: u16,
+    /// the largest packet we send, as the device allows
+    mtu: usize,
+    /// segments discarded so far, by reason
+    drops: Drops,
+    /// rules the peer broke so far
+    violations: Violations,
+    /// why the segment being processed was discarded, if it was
+    dropped: Option<DropReason>,
+    /// bytes received again that were already in, and discarded
+    duplicate_bytes: u64,
+    /// bytes received past the right edge of the window we advertised, and discarded
+    window_overrun_bytes: u64,
+    /// shift applied to the windows we advertise; until the handshake is done, the one we offer
+    rcv_wscale: u8,
+    /// shift applied to the windows the peer advertises
+    snd_wscale: u8,
+    /// how much received data we buffer before the window closes
+    rcv_buffer: usize,
+    /// while the connection waits in a listener's accept queue, the most it buffers instead
+    accept_window: Option<usize>,
+    /// the most the interface's receive memory cap lets the buffer and window together come
+    /// to, while it is near
+    rcv_limit: Option<usize>,
+    /// the window has opened up since we last said so; tell the peer once synchronized
+    window_update: bool,
+    /// when we last told the peer a window we had closed is open again, and how long to wait
+    /// for it to send something before saying so again, in case the ACK was lost
+    window_reopened: Option<(Instant, Duration)>,
+    window_reopen_acks: u64,
+    /// the right edge of the window we last advertised, which must not move back (RFC 7323
+    /// S2.4)
+    rcv_adv: u32,
+    /// data received since `rcv_space_since`, for tuning `rcv_buffer` once per round trip
+    rcv_space: usize,
+    rcv_space_since: Instant,
     send: SendSequenceSpace,
     recv: RecvSequenceSpace,
     ip: etherparse::Ipv4Header,
-    tcp: etherparse::TcpHeader,
+    /// our port and the peer's
+    ports: (u16, u16),
+    /// there is a SYN of the peer's to acknowledge, so every segment from here on carries an
+    /// ACK
+    acking: bool,
+    timers: Timers,
+    cc: Congestion,
+    /// how the connection responds to loss, as its configuration had it when it was made
+    loss: LossResponse,
+    /// the latest segments sent and received, if the configuration asks for them
+    trace: PacketTrace,
+    config: TcpConfig,
+    /// where all of the timers above get the time from
+    clock: Arc<dyn Clock>,
+    /// if set, every segment either way is signed with this key (RFC 2385)
+    pub(crate) md5_key: Option<Vec<u8>>,
+    /// options the application added to our SYN-ACK, after those of our own, padded to a
+    /// multiple of four bytes
+    extra_syn_options: Vec<u8>,
+
+    /// data received from the peer that the application has not read yet
+    pub(crate) incoming: VecDeque<u8>,
+    /// the peer's last byte of urgent data, taken out of `incoming` (BSD's out-of-band byte)
+    pub(crate) urgent: Option<u8>,
+    /// how many bytes of `incoming` come before the urgent mark, while it is not yet read past
+    pub(crate) urgent_mark: Option<usize>,
+    /// data that arrived beyond RCV.NXT, until the gap before it fills
+    reassembly: Reassembly,
+    /// where the data held last starts, which the first SACK block reports (RFC 2018 S4)
+    sack_latest: u32,
+    /// what the peer has SACKed of what we sent
+    scoreboard: Scoreboard,
+    /// where the peer's FIN is, if it arrived while data before it is still missing
+    fin_held: Option<u32>,
+    /// data written by the application that the peer has not acknowledged yet. the first byte
+    /// is at SND.UNA once our SYN has been acknowledged, and at ISS+1 before that.
+    pub(crate) unacked: VecDeque<u8>,
+    /// the sums of `unacked`, for checksums
+    unacked_sums: SendSums,
+    /// the application is done writing; a FIN follows the last byte of `unacked`
+    pub(crate) closed: bool,
+    /// the application is done reading; what arrives from now on is acknowledged and discarded
+    read_shut: bool,
+    /// hold back data that doesn't fill a segment, for the application to add to it
+    pub(crate) corked: bool,
+    /// how our segments queue for the device behind other connections'
+    pub(crate) priority: Priority,
+    /// once the application drops its stream, reset the connection rather than close it
+    pub(crate) abort_on_drop: bool,
+    /// sequence number our FIN was (first) sent with
+    closed_at: Option<u32>,
+    /// why the connection failed, reported to the application on its next call
+    pub(crate) error: Option<io::ErrorKind>,
+    /// what was wrong with the sequence spaces, if the connection was aborted over them
+    desync: Option<String>,
+    /// a SYN ended TIME-WAIT for a new connection, for the packet loop to open
+    reopen: bool,
+    /// why the connection closed, set once as it does
+    close_reason: Option<CloseReason>,
+    /// the application has dropped its handle, so nobody is left to collect `error`
+    pub(crate) orphaned: bool,
+    /// the listener that took the connection in holds on to it until its handshake completes,
+    /// and has yet to hand it to the application
+    pub(crate) half_open: bool,
+    /// the listener that took the connection in, if one did
+    pub(crate) listener: Option<ListenKey>,
+    /// what the peer's SYN looked like, if the connection was taken in from one
+    syn: Option<SynMetadata>,
+    /// how long blocking reads and writes on the stream wait before giving up, if at all
+    pub(crate) read_timeout: Option<Duration>,
+    pub(crate) write_timeout: Option<Duration>,
+    /// what has to be in `incoming` before blocked readers are woken
+    read_trigger: ReadTrigger,
+    /// how far into `incoming` the trigger's delimiter has been looked for: up to the first
+    /// one, if there is one
+    delimiter_scanned: usize,
+    /// if set, how much data, or how long, blocked readers wait for on top of the trigger
+    read_coalescing: Option<ReadCoalescing>,
+    /// when the oldest data in `incoming` arrived
+    unread_since: Option<Instant>,
+    /// when the application last read from `incoming`
+    read_at: Option<Instant>,
+    /// the peer pushed data still in `incoming`, or the coalescing delay ran out on it, so
+    /// readers need not wait for more
+    pushed: bool,
+    /// when we (last) entered TIME-WAIT; the 2MSL timer runs from here
+    time_wait_start: Option<Instant>,
+    /// the connection has just been aborted for the peer's window staying closed
+    zero_window_aborted: bool,
+    /// RCV.NXT as of the last segment we sent: anything received past it is yet to be
+    /// acknowledged
+    rcv_acked: u32,
+    /// data rode on the client's SYN, and the server took it
+    fast_open: bool,
+    /// a Fast Open cookie the server handed us, for the next connection to it
+    fast_open_cookie: Option<Cookie>,
+}
+
+struct Timers {
+    /// when each outstanding segment was sent, keyed by the sequence number just past its end,
+    /// so a segment only leaves once it is acknowledged in full; the oldest is not always
+    /// first, once the sequence numbers wrap
+    send_times: BTreeMap<u32, Instant>,
+    /// smoothed round-trip time
+    srtt: Duration,
+    /// round-trip time variation, once there has been a sample to take it from
+    rttvar: Option<Duration>,
+    /// current retransmission timeout, including any backoff
+    rto: Duration,
+    /// how many times the oldest outstanding segment has been retransmitted
+    backoffs: u32,
+    /// when the retransmission timer was last (re)started, while it runs (RFC 6298 S5)
+    rto_started: Option<Instant>,
+    /// when we last received a segment from the peer
+    last_recv: Instant,
+    /// when we last sent data, or anything else that takes up sequence space
+    last_send: Instant,
+    /// bytes of new data pacing lets us send right now
+    pace_credit: usize,
+    /// when `pace_credit` was last topped up
+    pace_updated: Instant,
+    /// when the cork first held back the data that is waiting now
+    cork_held: Option<Instant>,
+    /// when the next zero-window probe is due, while the peer's window holds our data back
+    persist_due: Option<Instant>,
+    /// zero-window probes sent since the peer's window last opened
+    probes: u32,
+    /// since when the peer's window has been closed, if it is
+    zero_window_since: Option<Instant>,
+}
+
+/// Congestion control state (RFC 5681), grown by the bytes each ACK covers rather than by the
+/// number of ACKs (RFC 3465).
+struct Congestion {
+    cwnd: u32,
+    ssthresh: u32,
+    /// bytes acknowledged since `cwnd` last grew in congestion avoidance
+    bytes_acked: u32,
+    /// bytes acknowledged by ACKs that ended inside a segment, not yet counted towards `cwnd`
+    split_acked: u32,
+    /// duplicate ACKs in a row
+    dup_acks: u32,
+    /// how many of them call for a fast retransmit, raised from the configured number as
+    /// reordering is seen
+    dup_ack_threshold: u32,
+    /// the most duplicate ACKs a segment that was only late has drawn
+    reordering: u32,
+    /// while in fast recovery, SND.NXT when it started; recovery ends once all of it is ACKed
+    recover: Option<u32>,
+    /// while in SACK-based recovery, just past the last byte sent again (RFC 6675's HighRxt)
+    high_rxt: Option<u32>,
+    /// the fast retransmit that started recovery, until the next ACK says whether it was needed
+    fast_retransmit: Option<FastRetransmit>,
+    spurious_fast_retransmits: u32,
+    /// after a timeout, the check of whether it was spurious
+    frto: Option<Frto>,
+    spurious_rtos: u32,
+    suspicious_acks: u64,
+    optimistic_acks: u64,
+    /// the most that has been in flight in the current round trip, and when that began
+    flight_max: u32,
+    round_start: Instant,
+    /// whether the last round trip used at least half of `cwnd`
+    validated: bool,
+    /// since when no round trip has, if that is the case, and the most in flight since
+    unvalidated_since: Option<Instant>,
+    unvalidated_used: u32,
+}
+
+/// A fast retransmit whose hole has not filled in yet.
+#[derive(Clone, Copy)]
+struct FastRetransmit {
+    at: Instant,
+    /// `cwnd` and `ssthresh` from before it, restored if it was spurious
+    cwnd: u32,
+    ssthresh: u32,
+}
+
+/// A check of whether a timeout was spurious in progress: by Eifel (RFC 3522) if the connection
+/// has timestamps, by F-RTO (RFC 5682 S2.1) if not.
+#[derive(Clone, Copy)]
+struct Frto {
+    /// SND.NXT when the timeout fired
+    recover: u32,
+    /// with timestamps, the TSval of the first retransmission: an ACK that echoes an older one
+    /// was drawn by a segment sent before the timeout, which was only late
+    eifel: Option<u32>,
+    /// whether the first ACK after the timeout has come in, and new data has gone out since
+    sent_new: bool,
+    /// `cwnd` and `ssthresh` from before the timeout, restored if it was spurious
+    cwnd: u32,
+    ssthresh: u32,
+}
+
+/// What a segment changed about a connection, as [`Connection::on_packet`] tells the packet
+/// loop.
+#[derive(Debug, Default)]
+pub(crate) struct Available {
+    /// readers blocked on the stream have something to wake up for
+    pub(crate) readable: bool,
+    /// writers blocked on it may: the peer acknowledged data or moved its window, the
+    /// connection changed state, or it failed
+    pub(crate) writable: bool,
+    /// the connection is done in both directions, as of this segment
+    pub(crate) closed: bool,
+    /// the connection has run its course, and can come out of the connection table
+    pub(crate) remove: bool,
+    /// why the segment was discarded, if it was
+    pub(crate) dropped: Option<DropReason>,
+    /// a Fast Open cookie the server handed us in its SYN-ACK
+    pub(crate) fast_open_cookie: Option<Cookie>,
+    /// the segment is a SYN that ended TIME-WAIT to open a new connection in its place, which
+    /// is up to the listener
+    pub(crate) reopen: bool,
+}
+
+/// What processing a segment calls for us to send, held back until it is done.
+#[derive(Default)]
+struct Reply {
+    /// resend the segment at SND.UNA: a fast retransmit, or one in fast recovery
+    retransmit: bool,
+    /// send new data for F-RTO to see whether it gets through
+    frto_new_data: bool,
+    /// in SACK-based recovery, resend what the scoreboard takes for lost, as pipe allows
+    retransmit_lost: bool,
+    /// acknowledge the segment right away
+    ack: bool,
+}
+
+/// The control flags a segment is sent with, which only the caller knows. FIN, PSH and URG
+/// follow from the data the segment carries, so [`Connection::emit`] works those out itself.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct SegmentFlags {
+    syn: bool,
+    ack: bool,
+    rst: bool,
 }
 
 /// State of Send Sequence Space (RFC793 S3.2 F4)
 ///
+/// ```text
 ///                   1         2          3          4
 ///              ----------|----------|----------|----------
 ///                     SND.UNA    SND.NXT    SND.UNA
@@ -39,286 +1049,3279 @@ pub struct Connection {
 ///        2 - sequence numbers of unacknowledged data
 ///        3 - sequence numbers allowed for new data transmission
 ///        4 - future sequence numbers which are not yet allowed
+/// ```
+#[derive(Debug)]
+struct SendSequenceSpace {
+    /// send unacknowledged
+    una: u32,
+    /// send next
+    nxt: u32,
+    /// the furthest SND.NXT has been, before going back N wound it back
+    max: u32,
+    /// send window, scaled
+    wnd: u32,
+    /// send urgent pointer: the sequence number just past our urgent data, until it is acked
+    up: Option<u32>,
+    /// segment sequence number used for last window update
+    wl1: u32,
+    /// segment acknowledgment number used for last window update
+    wl2: u32,
+    /// initial send sequence number
+    iss: u32,
+}
+
+/// State of Receive Sequence Space (RFC793 S3.2 F5)
+///
+/// ```text
+///                       1          2          3
+///                   ----------|----------|----------
+///                          RCV.NXT    RCV.NXT
+///                                    +RCV.WND
+///
+///        1 - old sequence numbers which have been acknowledged
+///        2 - sequence numbers allowed for new reception
+///        3 - future sequence numbers which are not yet allowed
+/// ```
+#[derive(Debug)]
+struct RecvSequenceSpace {
+    /// receive next
+    nxt: u32,
+    /// receive window, as last advertised, scaled
+    wnd: u32,
+    /// receive urgent pointer: the sequence number just past the peer's latest urgent data
+    up: Option<u32>,
+    /// initial receive sequence number
+    irs: u32,
+}
+
+impl Connection {
+    fn new(
+        state: State,
+        local: (Ipv4Addr, u16),
+        remote: (Ipv4Addr, u16),
+        iss: u32,
+        config: TcpConfig,
+        mtu: usize,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let mtu = cmp::min(mtu, MAX_PACKET);
+        let our_mss = (mtu - HEADERS_LEN) as u16;
+        let rcv_buffer = cmp::min(config.recv_buffer, (u16::MAX as usize) << MAX_WSCALE);
+        let most = if config.recv_autotune {
+            cmp::max(rcv_buffer, config.recv_buffer_max)
+        } else {
+            rcv_buffer
+        };
+        // offer the smallest scale the largest buffer we may grow to needs, if it needs one
+        let mut rcv_wscale = 0;
+        while rcv_wscale < MAX_WSCALE && most > (u16::MAX as usize) << rcv_wscale {
+            rcv_wscale += 1;
+        }
+        let wnd = cmp::min(rcv_buffer, u16::MAX as usize) as u16;
+        let loss = config.loss_response();
+        let abort_on_drop = config.abort_on_drop;
+        Connection {
+            state,
+            device: DeviceId::default(),
+            handshake: Handshake {
+                ours: SynOptions {
+                    mss: Some(our_mss),
+                    wscale: Some(rcv_wscale).filter(|&shift| shift > 0),
+                    sack_permitted: config.sack,
+                    // the TSval is read off the clock as each SYN goes out
+                    timestamp: config.timestamps.then_some(0),
+                    fast_open: None,
+                },
+                peer: None,
+            },
+            timestamps: true,
+            sack: true,
+            ts_clock: TsClock {
+                epoch: clock.now(),
+                offset: 0,
+            },
+            ts_recent: 0,
+            ts_recent_at: clock.now(),
+            mss: cmp::min(DEFAULT_MSS, our_mss),
+            mtu,
+            drops: Drops::default(),
+            violations: Violations::default(),
+            dropped: None,
+            duplicate_bytes: 0,
+            window_overrun_bytes: 0,
+            rcv_wscale,
+            snd_wscale: 0,
+            rcv_buffer,
+            accept_window: None,
+            rcv_limit: None,
+            window_update: false,
+            window_reopened: None,
+            window_reopen_acks: 0,
+            rcv_adv: 0,
+            rcv_space: 0,
+            rcv_space_since: clock.now(),
+            send: SendSequenceSpace {
+                iss,
+                una: iss,
+                nxt: iss,
+                max: iss,
+                wnd: 0,
+                up: None,
+                wl1: 0,
+                wl2: 0,
+            },
+            recv: RecvSequenceSpace {
+                irs: 0,
+                nxt: 0,
+                wnd: wnd as u32,
+                up: None,
+            },
+            ports: (local.1, remote.1),
+            acking: false,
+            ip: etherparse::Ipv4Header::new(
+                0,
+                64,
+                etherparse::IpTrafficClass::Tcp,
+                local.0.octets(),
+                remote.0.octets(),
+            ),
+            timers: Timers {
+                send_times: Default::default(),
+                srtt: loss.initial_rto,
+                rttvar: None,
+                rto: loss.initial_rto,
+                backoffs: 0,
+                rto_started: None,
+                last_recv: clock.now(),
+                last_send: clock.now(),
+                pace_credit: config.pacing_burst * our_mss as usize,
+                pace_updated: clock.now(),
+                cork_held: None,
+                persist_due: None,
+                probes: 0,
+                zero_window_since: None,
+            },
+            cc: Congestion {
+                cwnd: initial_window(DEFAULT_MSS, config.initial_cwnd_segments),
+                ssthresh: u32::MAX,
+                bytes_acked: 0,
+                split_acked: 0,
+                dup_acks: 0,
+                dup_ack_threshold: loss.dup_ack_threshold,
+                reordering: 0,
+                recover: None,
+                fast_retransmit: None,
+                spurious_fast_retransmits: 0,
+                frto: None,
+                spurious_rtos: 0,
+                high_rxt: None,
+                suspicious_acks: 0,
+                optimistic_acks: 0,
+                flight_max: 0,
+                round_start: clock.now(),
+                validated: true,
+                unvalidated_since: None,
+                unvalidated_used: 0,
+            },
+            loss,
+            trace: PacketTrace::new(config.trace_packets),
+            config,
+            clock,
+            md5_key: None,
+            extra_syn_options: Vec::new(),
+            incoming: Default::default(),
+            reassembly: Default::default(),
+            sack_latest: 0,
+            scoreboard: Default::default(),
+            fin_held: None,
+            urgent: None,
+            urgent_mark: None,
+            unacked: Default::default(),
+            unacked_sums: Default::default(),
+            closed: false,
+            read_shut: false,
+            corked: false,
+            priority: Priority::Normal,
+            abort_on_drop,
+            closed_at: None,
+            error: None,
+            desync: None,
+            reopen: false,
+            close_reason: None,
+            orphaned: false,
+            half_open: false,
+            listener: None,
+            syn: None,
+            read_timeout: None,
+            write_timeout: None,
+            read_trigger: ReadTrigger::Any,
+            delimiter_scanned: 0,
+            read_coalescing: None,
+            unread_since: None,
+            read_at: None,
+            pushed: false,
+            time_wait_start: None,
+            zero_window_aborted: false,
+            rcv_acked: 0,
+            fast_open: false,
+            fast_open_cookie: None,
+        }
+    }
+
+    /// Take in the SYN of a passive open on `listener`, and set up the connection it asks for,
+    /// up to the point of answering it: the SYN-ACK only goes out on [`PendingAccept::commit`].
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn prepare_accept<'a>(
+        listener: &Listener,
+        iph: etherparse::Ipv4HeaderSlice<'a>,
+        tcph: etherparse::TcpHeaderSlice<'a>,
+        data: &'a [u8],
+        config: TcpConfig,
+        mtu: usize,
+        clock: Arc<dyn Clock>,
+        iss: u32,
+        fast_open: Option<fastopen::Answer>,
+    ) -> Option<PendingAccept> {
+        if !tcph.syn() {
+            // only expected SYN packet
+            return None;
+        }
+
+        let mut c = Connection::new(
+            State::SynRcvd,
+            (iph.destination_addr(), tcph.destination_port()),
+            (iph.source_addr(), tcph.source_port()),
+            iss,
+            config,
+            mtu,
+            clock,
+        );
+        c.recv.irs = tcph.sequence_number();
+        c.recv.nxt = tcph.sequence_number().wrapping_add(1);
+        c.acking = true;
+        c.rcv_adv = c.recv.nxt;
+        c.send.wnd = tcph.window_size() as u32;
+        c.md5_key = listener.md5_keys.get(&iph.source_addr()).cloned();
+        c.accept_window = listener.accept_window;
+        c.read_timeout = listener.read_timeout;
+        c.write_timeout = listener.write_timeout;
+        c.set_read_trigger(listener.read_trigger);
+        c.set_read_coalescing(listener.read_coalescing);
+        c.corked = listener.cork;
+        c.priority = listener.priority;
+        c.trace_received(&iph, &tcph, data);
+        c.syn = Some(SynMetadata {
+            options: tcph.options().to_vec(),
+            window: tcph.window_size(),
+            ttl: iph.ttl(),
+            ip_id: iph.identification(),
+        });
+        c.negotiate(&tcph);
+        if let Some(answer) = fast_open {
+            c.handshake.ours.fast_open = answer.cookie;
+            // the data is the application's to read once accepted, ahead of the handshake
+            // completing (RFC 7413 S4.2.2)
+            let early = answer.take(data, c.rcv_room());
+            if early > 0 {
+                c.receive(&data[..early]);
+                c.fast_open = true;
+            }
+        }
+        Some(PendingAccept { c })
+    }
+
+    /// Start an active open towards `remote`.
+    ///
+    /// The SYN is not sent from here, since the caller does not own the nic; the next
+    /// [`Connection::on_tick`] sends it. Until the handshake completes, data written by the
+    /// application just queues up in `unacked`.
+    pub(crate) fn connect(
+        local: (Ipv4Addr, u16),
+        remote: (Ipv4Addr, u16),
+        iss: u32,
+        config: TcpConfig,
+        mtu: usize,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Connection::new(State::SynSent, local, remote, iss, config, mtu, clock)
+    }
+
+    /// Have the connection read its TSvals off `clock`, rather than one of its own starting at 0.
+    pub(crate) fn set_ts_clock(&mut self, clock: TsClock) {
+        self.ts_clock = clock;
+    }
+
+    /// Make the SYN of an active open carry `cookie`, and with it `data` if it is a cookie
+    /// rather than a request for one (RFC 7413 S4.2.1). Whatever of `data` does not fit in the
+    /// SYN, or the server does not take, goes once the handshake is done.
+    pub(crate) fn offer_fast_open(&mut self, cookie: Cookie, data: &[u8]) {
+        self.handshake.ours.fast_open = Some(cookie);
+        self.unacked.extend(data);
+    }
+
+    /// How much room the options of our SYN (or SYN-ACK) leave, MD5 signature included.
+    fn syn_options_room(&self) -> usize {
+        let mut options = [0u8; MAX_OPTIONS_LEN];
+        let md5 = self.md5_key.is_some();
+        let len = self.handshake.write_options(md5, 0, &mut options);
+        MAX_OPTIONS_LEN - len - if md5 { MD5_OPTION_LEN } else { 0 }
+    }
+
+    /// How much data our SYN may carry: as much as fits, if it carries a Fast Open cookie.
+    fn syn_data_limit(&self) -> usize {
+        let offer = self.handshake.offered(self.md5_key.is_some());
+        match offer.fast_open {
+            Some(cookie) if self.state == State::SynSent && !cookie.is_request() => usize::MAX,
+            _ => 0,
+        }
+    }
+
+    pub(crate) fn info(&self) -> ConnectionInfo {
+        let negotiated = self.handshake.negotiated(self.md5_key.is_some());
+        ConnectionInfo {
+            state: self.state,
+            mss: self.mss,
+            peer_mss: self
+                .handshake
+                .peer
+                .map(|peer| peer.mss.unwrap_or(DEFAULT_MSS)),
+            rcv_wscale: self.rcv_wscale,
+            snd_wscale: self.snd_wscale,
+            sack: negotiated.sack,
+            timestamps: negotiated.timestamps,
+            // we never offer ECN in our SYN, so it is not in effect whatever the peer sends
+            ecn: false,
+            fast_open: self.fast_open,
+            srtt: self.timers.srtt,
+            peer_window: self.send.wnd,
+            cwnd: self.cc.cwnd,
+            bytes_in_flight: self.in_flight(),
+            ssthresh: self.cc.ssthresh,
+            rcv_buffer: self.rcv_buffer as u32,
+            send_buffer: self.config.send_buffer as u32,
+            spurious_rtos: self.cc.spurious_rtos,
+            spurious_fast_retransmits: self.cc.spurious_fast_retransmits,
+            reordering_seen: self.cc.reordering,
+            dup_ack_threshold: self.cc.dup_ack_threshold,
+            suspicious_acks: self.cc.suspicious_acks,
+            optimistic_acks: self.cc.optimistic_acks,
+            cwnd_validated: self.cwnd_validated(),
+            duplicate_bytes: self.duplicate_bytes,
+            out_of_order_bytes: self.reassembly.len() as u32,
+            window_overrun_bytes: self.window_overrun_bytes,
+            window_reopen_acks: self.window_reopen_acks,
+            close_reason: self.close_reason,
+            profile: self.config.profile,
+            loss_response: self.loss,
+        }
+    }
+
+    /// The latest segments sent and received, oldest first.
+    pub(crate) fn trace_dump(&self) -> Vec<TracedPacket> {
+        self.trace.dump()
+    }
+
+    fn trace_received(
+        &mut self,
+        iph: &etherparse::Ipv4HeaderSlice<'_>,
+        tcph: &etherparse::TcpHeaderSlice<'_>,
+        data: &[u8],
+    ) {
+        if self.trace.is_enabled() {
+            let (ip, tcp) = (iph.slice(), tcph.slice());
+            self.trace.record(
+                self.clock.now(),
+                Direction::Received,
+                ip.len() + tcp.len(),
+                &[ip, tcp, data],
+            );
+        }
+    }
+
+    /// Why the connection closed, once it has.
+    pub(crate) fn close_reason(&self) -> Option<CloseReason> {
+        self.close_reason
+    }
+
+    /// Record why the connection is closing, as it leaves the synchronized states for
+    /// TIME-WAIT or CLOSED.
+    fn set_close_reason(&mut self, reason: CloseReason) {
+        debug_assert!(
+            self.close_reason.is_none(),
+            "closed for {:?} after {:?}",
+            reason,
+            self.close_reason
+        );
+        self.close_reason.get_or_insert(reason);
+    }
+
+    /// Whether the peer has closed its half of the connection (or it is gone altogether), or
+    /// the application has shut it down for reading, so reads should return EOF once
+    /// `incoming` is drained.
+    pub(crate) fn is_rcv_closed(&self) -> bool {
+        self.read_shut || self.state.is_rcv_closed()
+    }
+
+    /// Whether the application can write to the connection now: the handshake is done, the
+    /// application has not shut it down for writing, and the send buffer has room.
+    pub(crate) fn is_writable(&self) -> bool {
+        self.state.is_synchronized() && !self.closed && self.send_room() > 0
+    }
+
+    /// Whether the handshake has gone through, and the connection has not failed or closed
+    /// since.
+    pub(crate) fn is_synchronized(&self) -> bool {
+        self.state.is_synchronized()
+    }
+
+    /// Whether both sides are done with the connection, whatever is left of it in the
+    /// connection table.
+    pub(crate) fn is_done(&self) -> bool {
+        matches!(self.state, State::TimeWait | State::Closed)
+    }
+
+    /// Whether the connection has run its course and can be removed from the connection table.
+    pub(crate) fn is_finished(&self) -> bool {
+        matches!(self.state, State::Closed)
+            && (self.error.is_none() || self.orphaned || self.half_open)
+            || self.is_expired()
+    }
+
+    /// The application has accepted the connection off the listener's queue, so the whole
+    /// receive buffer is there for it from now on.
+    pub(crate) fn accepted(&mut self) {
+        if self.accept_window.take().is_some() {
+            self.window_update = true;
+        }
+    }
+
+    /// When a connection taken in by a listener, just now, gives up on its handshake: once its
+    /// SYN-ACK has gone out [`TcpConfig::syn_ack_retries`] more times, backing off as
+    /// retransmissions do, and the last of them has timed out too. `None` if that is further
+    /// off than the clock can count.
+    pub(crate) fn handshake_deadline(&self) -> Option<Instant> {
+        let retries = self.config.syn_ack_retries;
+        let mut rto = self.timers.rto;
+        let mut wait = rto;
+        for retry in 0..retries {
+            if rto >= self.loss.max_rto {
+                // the rest all wait as long
+                wait = wait.checked_add(rto.checked_mul(retries - retry)?)?;
+                break;
+            }
+            rto = cmp::min(2 * rto, self.loss.max_rto);
+            wait = wait.checked_add(rto)?;
+        }
+        self.clock.now().checked_add(wait)
+    }
+
+    pub(crate) fn set_read_trigger(&mut self, trigger: ReadTrigger) {
+        self.read_trigger = trigger;
+        self.delimiter_scanned = 0;
+    }
+
+    pub(crate) fn read_trigger(&self) -> ReadTrigger {
+        self.read_trigger
+    }
+
+    pub(crate) fn set_read_coalescing(&mut self, coalescing: Option<ReadCoalescing>) {
+        self.read_coalescing = coalescing;
+    }
+
+    pub(crate) fn read_coalescing(&self) -> Option<ReadCoalescing> {
+        self.read_coalescing
+    }
+
+    /// Whether the coalescing delay has just run out on data nobody has read, which then
+    /// counts as pushed. Checked on every tick.
+    pub(crate) fn check_read_coalescing(&mut self) -> bool {
+        let (Some(coalescing), Some(since)) = (self.read_coalescing, self.unread_since) else {
+            return false;
+        };
+        if self.pushed || self.since(since) < coalescing.delay {
+            return false;
+        }
+        self.pushed = true;
+        true
+    }
+
+    /// Whether `incoming` holds what the read trigger waits for, and, with read coalescing, has
+    /// been pushed, has filled up to the coalescing threshold or has waited out the delay. A
+    /// full receive buffer does as well, since nothing more arrives until the application
+    /// reads. Only the bytes that arrived since the last call are searched for a delimiter.
+    pub(crate) fn read_ready(&mut self) -> bool {
+        if self.incoming.is_empty() {
+            return false;
+        }
+        let ready = match self.read_trigger {
+            ReadTrigger::Any => true,
+            ReadTrigger::Bytes(n) => self.incoming.len() >= n,
+            ReadTrigger::Delimiter(delimiter) => {
+                match self
+                    .incoming
+                    .range(self.delimiter_scanned..)
+                    .position(|&b| b == delimiter)
+                {
+                    Some(at) => {
+                        self.delimiter_scanned += at;
+                        true
+                    }
+                    None => {
+                        self.delimiter_scanned = self.incoming.len();
+                        false
+                    }
+                }
+            }
+        };
+        let coalesced = self
+            .read_coalescing
+            .is_none_or(|coalescing| self.pushed || self.incoming.len() >= coalescing.bytes);
+        (ready && coalesced) || self.rcv_room() == 0
+    }
+
+    /// The application has read the first `n` bytes out of `incoming`.
+    pub(crate) fn consumed(&mut self, n: usize) {
+        self.delimiter_scanned = self.delimiter_scanned.saturating_sub(n);
+        self.read_at = Some(self.clock.now());
+        if self.incoming.is_empty() {
+            self.unread_since = None;
+            self.pushed = false;
+        }
+        self.check_window_reopened();
+    }
+
+    /// Cap what the buffer and the window together come to at `limit`, or lift the cap. The
+    /// window never shrinks for it, so it only closes as the peer fills what we advertised.
+    pub(crate) fn set_rcv_limit(&mut self, limit: Option<usize>) {
+        let lifted = match (self.rcv_limit, limit) {
+            (Some(old), Some(new)) => new > old,
+            (old, new) => old.is_some() && new.is_none(),
+        };
+        self.rcv_limit = limit;
+        if lifted {
+            self.check_window_reopened();
+        }
+    }
+
+    /// How much memory the connection's receive side holds and would like to.
+    pub(crate) fn rcv_memory(&self) -> RcvMemory {
+        let (committed, buffer) = if self.is_rcv_closed() {
+            // the peer sends no more, whatever window it had
+            (self.incoming.len(), 0)
+        } else {
+            (
+                self.incoming.len() + self.rcv_promised() as usize,
+                cmp::min(self.rcv_buffer, self.accept_window.unwrap_or(usize::MAX)),
+            )
+        };
+        let unread_for = match (self.unread_since, self.read_at) {
+            (Some(since), Some(read)) => Some(self.since(cmp::max(since, read))),
+            (Some(since), None) => Some(self.since(since)),
+            (None, _) => None,
+        };
+        RcvMemory {
+            buffered: self.incoming.len(),
+            committed,
+            wanted: buffer.saturating_sub(committed),
+            unread_for,
+        }
+    }
+
+    /// Set `window_update` if the window can open up by enough to be worth saying so.
+    fn check_window_reopened(&mut self) {
+        // a window we closed stays closed until we say otherwise, and one too small for a
+        // full-sized segment all but closed, as a sender avoiding silly windows will not use it:
+        // say so once it can open up by as much as is worth offering (RFC 1122 S4.2.3.3)
+        let worth = cmp::min(self.mss as usize, self.rcv_buffer / 2);
+        if self.recv.wnd < self.mss as u32
+            && self.rcv_room().saturating_sub(self.recv.wnd as usize) >= worth
+        {
+            self.window_update = true;
+        }
+    }
+
+    /// Whether readers blocked on the stream have anything to wake up for: what the read
+    /// trigger waits for, urgent data, the end of the stream or an error.
+    pub(crate) fn wakes_readers(&mut self) -> bool {
+        (self.read_trigger == ReadTrigger::Any && self.read_coalescing.is_none())
+            || self.error.is_some()
+            || self.urgent.is_some()
+            || self.is_rcv_closed()
+            || self.read_ready()
+    }
+
+    /// The application is done writing. Per RFC 793 ("CLOSE Call"), the FIN is queued behind
+    /// any data still in `unacked`, but the state changes right away.
+    pub(crate) fn close(&mut self) {
+        if self.closed {
+            return;
+        }
+        self.closed = true;
+        match self.state {
+            State::SynSent => {
+                // nothing has been synchronized yet, so there is nobody to say goodbye to
+                self.state = State::Closed;
+                self.set_close_reason(CloseReason::Local);
+            }
+            // in SYN-RECEIVED, the FIN waits until the handshake completes (RFC 793 p.60)
+            State::Estab => self.state = State::FinWait1,
+            State::CloseWait => self.state = State::LastAck,
+            _ => {}
+        }
+    }
+
+    /// The application is done reading: what it has yet to read is thrown away, and so is
+    /// whatever arrives later, which is still acknowledged so the peer is not left
+    /// retransmitting it (as Linux does after `shutdown(SHUT_RD)`).
+    pub(crate) fn shutdown_read(&mut self) {
+        if self.read_shut {
+            return;
+        }
+        self.read_shut = true;
+        let unread = self.incoming.len();
+        self.incoming.clear();
+        self.reassembly.clear();
+        self.consumed(unread);
+        self.urgent = None;
+        self.urgent_mark = None;
+    }
+
+    /// The device's MTU changed: segments must fit from now on, but what we told the peer about
+    /// our MSS in the handshake stands.
+    pub(crate) fn set_mtu(&mut self, mtu: usize) {
+        self.mtu = cmp::min(mtu, MAX_PACKET);
+        let most = (self.mtu - HEADERS_LEN) as u16;
+        if let Some(our_mss) = &mut self.handshake.ours.mss {
+            *our_mss = cmp::min(*our_mss, most);
+        }
+        self.mss = cmp::min(self.mss, most);
+    }
+
+    /// What the peer's SYN looked like, if the connection was taken in from one.
+    pub(crate) fn syn_metadata(&self) -> Option<SynMetadata> {
+        self.syn.clone()
+    }
+
+    /// Segments discarded so far, by reason.
+    pub(crate) fn drops(&self) -> Drops {
+        self.drops
+    }
+
+    fn discard(&mut self, reason: DropReason) {
+        self.drops.record(reason);
+        self.dropped = Some(reason);
+    }
+
+    /// Rules the peer broke so far, by rule.
+    pub(crate) fn violations(&self) -> Violations {
+        self.violations
+    }
+
+    /// Count the peer breaking `rule`, and say whether to make the most of it anyway.
+    fn tolerate(&mut self, rule: Violation) -> bool {
+        self.violations.record(rule);
+        self.config.strictness == Strictness::Permissive
+    }
+
+    /// How much the application may write before the send buffer is full.
+    pub(crate) fn send_buffer(&self) -> usize {
+        self.config.send_buffer
+    }
+
+    /// How much more the application may write right now.
+    pub(crate) fn send_room(&self) -> usize {
+        self.config.send_buffer.saturating_sub(self.unacked.len())
+    }
+
+    /// Make the last byte in `unacked` urgent (RFC 793 p.56, as BSD reads the urgent pointer
+    /// per RFC 6093): our segments point past it until the peer has acknowledged it.
+    pub(crate) fn mark_urgent(&mut self) {
+        self.send.up = Some(self.data_start().wrapping_add(self.unacked.len() as u32));
+    }
+
+    /// Take the peer's urgent byte, if it has arrived. Without any urgent data on its way, this
+    /// fails like BSD's `recv(MSG_OOB)` does.
+    pub(crate) fn take_urgent(&mut self) -> io::Result<u8> {
+        if let Some(b) = self.urgent.take() {
+            return Ok(b);
+        }
+        match self.recv.up {
+            Some(up) if wrapping_lt(self.recv.nxt, up) => Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "urgent data has not arrived yet",
+            )),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no urgent data pending",
+            )),
+        }
+    }
+
+    /// The first sequence number of the data in `unacked`.
+    fn data_start(&self) -> u32 {
+        if self.send.una == self.send.iss {
+            // our SYN has not been ACKed yet, and occupies ISS
+            self.send.iss.wrapping_add(1)
+        } else {
+            self.send.una
+        }
+    }
+
+    /// The sequence number of our FIN, if the application has closed: a "virtual byte" right
+    /// after the last byte of data. Once sent, it stays where it went out, even after the ACK
+    /// for it has moved SND.UNA past it.
+    fn fin_seq(&self) -> Option<u32> {
+        if self.closed {
+            Some(
+                self.closed_at
+                    .unwrap_or_else(|| self.data_start().wrapping_add(self.unacked.len() as u32)),
+            )
+        } else {
+            None
+        }
+    }
+
+    /// Whether the peer has acknowledged our FIN.
+    fn fin_acked(&self) -> bool {
+        self.closed_at
+            .is_some_and(|fin| wrapping_lt(fin, self.send.una))
+    }
+
+    /// The flags of an ordinary segment: just the ACK, once there is a SYN of the peer's to
+    /// acknowledge.
+    fn segment_flags(&self) -> SegmentFlags {
+        SegmentFlags {
+            ack: self.acking,
+            ..SegmentFlags::default()
+        }
+    }
+
+    /// The flags of our SYN, or of our SYN-ACK once there is a SYN of the peer's.
+    fn syn_flags(&self) -> SegmentFlags {
+        SegmentFlags {
+            syn: true,
+            ..self.segment_flags()
+        }
+    }
+
+    /// Send a segment with `flags`, starting at `seq`, carrying at most `limit` bytes of data
+    /// from `unacked`. Its header is made up afresh, so nothing carries over from one segment
+    /// to the next.
+    ///
+    /// A segment that reaches the end of the data after the application has closed carries our
+    /// FIN as well, whether this is its first transmission or a retransmission; one that is not
+    /// a SYN or an RST, that is.
+    ///
+    /// Returns how many bytes of data the segment carries, SYN and FIN not counted.
+    fn emit(
+        &mut self,
+        nic: &mut Outbound,
+        flags: SegmentFlags,
+        seq: u32,
+        limit: usize,
+    ) -> io::Result<usize> {
+        debug_assert!(
+            self.state == State::Closed || self.desync().is_none(),
+            "sending from desynchronized sequence spaces: {:?} {:?}",
+            self.send,
+            self.recv
+        );
+        let mut buf = vec![0u8; self.mtu];
+        let window = self.advertise_window(flags.syn);
+        let mut tcp = etherparse::TcpHeader::new(self.ports.0, self.ports.1, seq, window);
+        tcp.syn = flags.syn;
+        tcp.rst = flags.rst;
+        if flags.ack {
+            tcp.ack = true;
+            tcp.acknowledgment_number = self.recv.nxt;
+        }
+        self.rcv_acked = self.recv.nxt;
+        // point past the urgent data from every segment before it, as far as the field reaches
+        if let Some(up) = self
+            .send
+            .up
+            .filter(|&up| !flags.syn && wrapping_lt(seq, up))
+        {
+            tcp.urg = true;
+            tcp.urgent_pointer = cmp::min(up.wrapping_sub(seq), u16::MAX as u32) as u16;
+        }
+        let mut options = [0u8; MAX_OPTIONS_LEN];
+        let mut options_len = 0;
+        let ts_val = self.ts_clock.read(self.clock.now());
+        if flags.syn {
+            options_len =
+                self.handshake
+                    .write_options(self.md5_key.is_some(), ts_val, &mut options);
+            let extra = &self.extra_syn_options;
+            options[options_len..options_len + extra.len()].copy_from_slice(extra);
+            options_len += extra.len();
+        } else if !flags.rst {
+            options_len = self.write_segment_options(ts_val, &mut options);
+        }
+        // the signature itself is filled in below, once the rest of the segment is known
+        let md5_at = self.md5_key.as_ref().map(|_| {
+            options[options_len..options_len + 4]
+                .copy_from_slice(&[OPTION_NOP, OPTION_NOP, OPTION_MD5, 18]);
+            options_len += MD5_OPTION_LEN;
+            options_len - 16
+        });
+        tcp.set_options_raw(&options[..options_len])
+            .expect("our options fit in the tcp header");
+
+        // pick the payload out of the send buffer. a SYN only carries any with Fast Open, and
+        // then from the start; a FIN may sit right after the last byte of data.
+        let offset = if flags.syn {
+            0
+        } else {
+            cmp::min(
+                seq.wrapping_sub(self.data_start()) as usize,
+                self.unacked.len(),
+            )
+        };
+        // the MSS leaves no room for options, so they come out of the data (RFC 6691)
+        let max_data = cmp::min(
+            (self.mss as usize).saturating_sub(tcp.options_len()),
+            buf.len()
+                .saturating_sub(tcp.header_len() as usize + self.ip.header_len()),
+        );
+        let nbytes = cmp::min(cmp::min(limit, max_data), self.unacked.len() - offset);
+        // the payload goes straight from the send buffer to its place behind the headers
+        let size = tcp.header_len() as usize + self.ip.header_len() + nbytes;
+        let data_at = size - nbytes;
+        crate::copy_out(&self.unacked, offset, &mut buf[data_at..size]);
+        if !flags.syn && !flags.rst {
+            if let Some(fin) = self.fin_seq() {
+                if seq.wrapping_add(nbytes as u32) == fin {
+                    tcp.fin = true;
+                    self.closed_at.get_or_insert(fin);
+                }
+            }
+        }
+        // like BSD, push whatever catches up with what the application has written so far
+        tcp.psh = nbytes > 0 && offset + nbytes == self.unacked.len();
+
+        self.ip
+            .set_payload_len(size - self.ip.header_len())
+            .expect("payload fits in an ip packet");
+
+        if let (Some(at), Some(key)) = (md5_at, &self.md5_key) {
+            let mut header = [0u8; 60];
+            tcp.write(&mut &mut header[..])?;
+            let signature = md5_signature(
+                self.ip.source,
+                self.ip.destination,
+                &header[..20],
+                tcp.header_len() + nbytes as u16,
+                &buf[data_at..size],
+                key,
+            );
+            options[at..at + 16].copy_from_slice(&signature);
+            tcp.set_options_raw(&options[..options_len])
+                .expect("our options fit in the tcp header");
+        }
+
+        // write out the headers in front of the payload, and then fill in the checksum, from a
+        // sum of the payload that is mostly kept from before if it is sent again
+        let mut unwritten = &mut buf[..data_at];
+        self.ip
+            .write(&mut unwritten)
+            .map_err(|e| io::Error::other(format!("{:?}", e)))?;
+        tcp.write(&mut unwritten)?;
+        let start = self.data_start();
+        let payload_sum = self.unacked_sums.payload(
+            &self.unacked,
+            self.send.iss.wrapping_add(1),
+            start,
+            start.wrapping_add(offset as u32),
+            nbytes,
+        );
+        let header = &mut buf[self.ip.header_len()..data_at];
+        let checksum = checksum::tcp_ipv4(
+            self.ip.source.into(),
+            self.ip.destination.into(),
+            header,
+            nbytes,
+            payload_sum,
+        );
+        header[16..18].copy_from_slice(&checksum.to_be_bytes());
+
+        let mut next_seq = seq.wrapping_add(nbytes as u32);
+        let mut occupies_sequence_space = nbytes != 0;
+        if tcp.syn {
+            next_seq = next_seq.wrapping_add(1);
+            occupies_sequence_space = true;
+        }
+        if tcp.fin {
+            next_seq = next_seq.wrapping_add(1);
+            occupies_sequence_space = true;
+        }
+
+        // a segment the device's queue has no room for is as good as never sent
+        buf.truncate(size);
+        let idle = self.send.nxt == self.send.una;
+        let traced = self.trace.is_enabled().then(|| buf.clone());
+        if !nic.send(buf, self.priority, idle, Some(self.send.max))? {
+            return Ok(0);
+        }
+        if let Some(packet) = traced {
+            let headers = self.ip.header_len() + tcp.header_len() as usize;
+            self.trace
+                .record(self.clock.now(), Direction::Sent, headers, &[&packet]);
+        }
+        if occupies_sequence_space {
+            if wrapping_lt(self.send.nxt, next_seq) {
+                self.send.nxt = next_seq;
+            }
+            if wrapping_lt(self.send.max, next_seq) {
+                self.send.max = next_seq;
+            }
+            let now = self.clock.now();
+            self.timers.send_times.insert(next_seq, now);
+            self.timers.rto_started.get_or_insert(now);
+            self.timers.last_send = now;
+            let in_flight = self.in_flight();
+            self.cc.flight_max = cmp::max(self.cc.flight_max, in_flight);
+        }
+        Ok(nbytes)
+    }
+
+    /// Send a bare ACK. Once going back N has wound SND.NXT back, it goes out at SND.MAX
+    /// instead: the peer may have had everything up to there all along, and would take a
+    /// sequence number before its RCV.NXT for an old duplicate and drop the ACK with it. With
+    /// both ends gone back N at once, neither would hear of the other's progress again. A FIN
+    /// that has yet to go out waits for the data before it, though, so then the ACK stays at
+    /// SND.NXT.
+    fn send_ack(&mut self, nic: &mut Outbound) -> io::Result<()> {
+        let seq = if self.fin_seq() == Some(self.send.max) {
+            self.send.nxt
+        } else {
+            self.send.max
+        };
+        self.emit(nic, self.segment_flags(), seq, 0)?;
+        Ok(())
+    }
+
+    /// Send an RST with sequence number `seq`, acknowledging what we have received if `ack`.
+    fn send_rst(&mut self, nic: &mut Outbound, seq: u32, ack: bool) -> io::Result<()> {
+        // which sequence number (SEG.ACK, or zero with an ACK of the segment) is up to the
+        // caller, going by the segment being answered (RFC 793 p.36). A synchronized connection
+        // never gets here over an unacceptable segment: on_unacceptable and on_optimistic_ack
+        // answer it with an ACK instead.
+        let flags = SegmentFlags {
+            rst: true,
+            ack,
+            ..SegmentFlags::default()
+        };
+        let nxt = self.send.nxt;
+        let res = self.emit(nic, flags, seq, 0);
+        // an RST does not consume sequence space
+        self.send.nxt = nxt;
+        res.map(|_| ())
+    }
+
+    /// Send as much not-yet-sent data as the peer's window allows, followed by our FIN once all
+    /// of it is out and the application has closed.
+    fn flush(&mut self, nic: &mut Outbound) -> io::Result<()> {
+        if !self.state.is_synchronized() {
+            return Ok(());
+        }
+        if self.config.pacing {
+            self.refill_pace_credit();
+        }
+        self.restart_after_idle();
+        loop {
+            if self
+                .fin_seq()
+                .is_some_and(|fin| wrapping_lt(fin, self.send.nxt))
+            {
+                // everything, up to and including the FIN, has been sent
+                return Ok(());
+            }
+            let in_flight = self.in_flight() as usize;
+            let sent = self.send.nxt.wrapping_sub(self.data_start()) as usize;
+            let unsent = self.unacked.len() - sent;
+            // a peer that shrank its window can leave it short of what is in flight already
+            let allowed = self.send_window().saturating_sub(in_flight);
+            if unsent == 0 {
+                if self.closed && allowed > 0 {
+                    // the FIN did not fit on the last data segment; send it by itself, once the
+                    // window has room for it, as the peer would drop it past the window's end
+                    self.emit(nic, self.segment_flags(), self.send.nxt, 0)?;
+                }
+                return Ok(());
+            }
+            if allowed == 0 {
+                return Ok(());
+            }
+            if self.cork_holds(unsent) {
+                return Ok(());
+            }
+            let limit = cmp::min(unsent, allowed);
+            if self.nagle_holds(limit, in_flight) {
+                return Ok(());
+            }
+            if self.config.pacing {
+                if self.timers.pace_credit < cmp::min(limit, self.mss as usize) {
+                    // the next tick tops the credit up again
+                    return Ok(());
+                }
+                let sent = self.emit(nic, self.segment_flags(), self.send.nxt, limit)?;
+                self.timers.pace_credit -= sent;
+                if sent == 0 {
+                    // the device has no room; it tells us when it does
+                    return Ok(());
+                }
+            } else if self.emit(nic, self.segment_flags(), self.send.nxt, limit)? == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Go back to slow start from the initial window, unless the congestion window is smaller
+    /// already, if nothing is in flight and nothing has been sent for a retransmission timeout
+    /// (RFC 5681 S4.1).
+    fn restart_after_idle(&mut self) {
+        if !self.config.slow_start_after_idle
+            || self.config.cwnd_validation.is_some()
+            || self.send.nxt != self.send.una
+            || self.since(self.timers.last_send) <= self.timers.rto
+        {
+            return;
+        }
+        let iw = initial_window(self.mss, self.config.initial_cwnd_segments);
+        self.cc.cwnd = cmp::min(self.cc.cwnd, iw);
+    }
+
+    /// Whether the connection uses enough of its congestion window for the window to mean
+    /// something: at least half of it, in the last round trip or this one so far.
+    fn cwnd_validated(&self) -> bool {
+        self.cc.validated || self.cc.flight_max >= self.cc.cwnd / 2
+    }
+
+    /// Once a round trip is over, note whether it validated the congestion window, and with
+    /// congestion window validation, shrink the window each time it has gone unvalidated for
+    /// the configured period (RFC 7661 S4.4.3, but halfway to the most used, as RFC 2861 does
+    /// for an application-limited connection). Run on every tick.
+    fn validate_cwnd(&mut self) {
+        if self.since(self.cc.round_start) < self.timers.srtt {
+            return;
+        }
+        let now = self.clock.now();
+        let used = self.cc.flight_max;
+        self.cc.validated = used >= self.cc.cwnd / 2;
+        self.cc.round_start = now;
+        self.cc.flight_max = self.in_flight();
+        if self.cc.validated {
+            self.cc.unvalidated_since = None;
+            self.cc.unvalidated_used = 0;
+            return;
+        }
+        let since = *self.cc.unvalidated_since.get_or_insert(now);
+        self.cc.unvalidated_used = cmp::max(self.cc.unvalidated_used, used);
+        let Some(period) = self.config.cwnd_validation else {
+            return;
+        };
+        if now.saturating_duration_since(since) < period {
+            return;
+        }
+        // slow start can take the window most of the way back, should the application want
+        // it again
+        self.cc.ssthresh = cmp::max(self.cc.ssthresh, self.cc.cwnd / 4 * 3);
+        let iw = initial_window(self.mss, self.config.initial_cwnd_segments);
+        self.cc.cwnd = cmp::max((self.cc.cwnd + self.cc.unvalidated_used) / 2, iw);
+        self.cc.unvalidated_since = Some(now);
+        self.cc.unvalidated_used = 0;
+    }
+
+    /// How much of what we sent is still in the network, as far as we can tell. In SACK-based
+    /// loss recovery, that is RFC 6675's pipe, which leaves out what the peer has SACKed and
+    /// what the scoreboard takes for lost, and counts what was sent again twice over.
+    /// Otherwise it is the flight size, as RFC 5681 has it: before recovery, limited transmit
+    /// is what lets SACKed data make room for more.
+    fn in_flight(&self) -> u32 {
+        match self.cc.high_rxt {
+            Some(high_rxt) => {
+                self.scoreboard
+                    .pipe(self.send.una, self.send.nxt, high_rxt, self.loss_rule())
+            }
+            None => self.flight_size(),
+        }
+    }
+
+    /// Everything from SND.UNA to SND.NXT: RFC 5681's FlightSize, which the response to a loss
+    /// halves.
+    fn flight_size(&self) -> u32 {
+        self.send.nxt.wrapping_sub(self.send.una)
+    }
+
+    /// When the scoreboard takes a segment for lost.
+    fn loss_rule(&self) -> LossRule {
+        LossRule {
+            mss: self.mss as u32,
+            dup_thresh: self.cc.dup_ack_threshold,
+        }
+    }
+
+    /// How much may be in flight: the peer's window, or our congestion window if smaller.
+    fn send_window(&self) -> usize {
+        let mut cwnd = self.cc.cwnd;
+        if self.config.limited_transmit && self.cc.recover.is_none() {
+            cwnd += cmp::min(self.cc.dup_acks, self.cc.dup_ack_threshold - 1) * self.mss as u32;
+        }
+        cmp::min(self.send.wnd, cwnd) as usize
+    }
+
+    /// Add the pacing credit earned since we last did, at one send window per SRTT, up to the
+    /// allowed burst.
+    fn refill_pace_credit(&mut self) {
+        let now = self.clock.now();
+        let elapsed = now.saturating_duration_since(self.timers.pace_updated);
+        self.timers.pace_updated = now;
+
+        let srtt = cmp::max(self.timers.srtt, Duration::from_millis(1));
+        let earned = self.send_window() as f64 * elapsed.as_secs_f64() / srtt.as_secs_f64();
+        let max = self.config.pacing_burst * self.mss as usize;
+        self.timers.pace_credit = cmp::min(self.timers.pace_credit + earned as usize, max);
+    }
+
+    /// Drive timers: retransmit what has gone unacknowledged for too long, and send anything the
+    /// application has queued since we last had a chance.
+    pub(crate) fn on_tick(&mut self, nic: &mut Outbound) -> io::Result<()> {
+        match self.state {
+            State::SynSent | State::SynRcvd if self.send.nxt == self.send.iss => {
+                // connect() only queued the connection, or the device had no room for our SYN
+                // (or SYN-ACK) so far; get the handshake going
+                self.emit(nic, self.syn_flags(), self.send.iss, self.syn_data_limit())?;
+                return Ok(());
+            }
+            State::FinWait2 => {
+                if self.since(self.timers.last_recv) >= self.config.fin_wait2_timeout {
+                    // the peer went away without sending its FIN; give up quietly
+                    self.error = Some(io::ErrorKind::TimedOut);
+                    self.state = State::Closed;
+                    self.set_close_reason(CloseReason::FinWait2Timeout);
+                }
+                return Ok(());
+            }
+            State::Estab
+                if self
+                    .config
+                    .idle_timeout
+                    .is_some_and(|idle| self.since(self.timers.last_recv) >= idle) =>
+            {
+                return self.abort(nic, io::ErrorKind::TimedOut, CloseReason::IdleTimeout);
+            }
+            State::TimeWait | State::Closed => return Ok(()),
+            _ => {}
+        }
+        if self.config.recv_autotune {
+            self.shrink_rcv_buffer();
+        }
+        self.validate_cwnd();
+        if self.window_update && self.state.is_synchronized() {
+            self.window_update = false;
+            let closed = self.recv.wnd < self.mss as u32;
+            self.send_ack(nic)?;
+            if closed && self.recv.wnd >= self.mss as u32 {
+                self.window_reopen_acks += 1;
+                self.window_reopened = Some((self.clock.now(), self.timers.rto));
+            }
+        } else if let Some((at, wait)) = self.window_reopened {
+            // the peer may have missed the update, and be sitting out its persist timer with
+            // data to send: say it again, less and less often, until the peer sends anything,
+            // or until we have waited as long as a retransmission ever does, as a peer with
+            // nothing to send stays silent for good
+            if self.since(at) >= wait {
+                self.window_reopen_acks += 1;
+                let next = 2 * wait;
+                self.window_reopened =
+                    (next <= self.loss.max_rto).then(|| (self.clock.now(), next));
+                self.send_ack(nic)?;
+            }
+        }
+
+        let waited_for = self.timers.rto_started.map(|started| self.since(started));
+        if waited_for.is_some_and(|waited_for| waited_for > self.timers.rto) {
+            // back off until something gets through (RFC 6298 (5.5))
+            self.timers.rto = cmp::min(2 * self.timers.rto, self.loss.max_rto);
+            // a segment timing out again leaves F-RTO nothing to go by, but a TSecr still
+            // tells the first retransmission from what went before it
+            self.cc.frto = self.cc.frto.filter(|frto| frto.eifel.is_some());
+            if self.timers.backoffs == 0 {
+                // RFC 5681 (4); a segment timing out again says nothing new about the network
+                let flight = self.flight_size();
+                if self.config.frto && self.state.is_synchronized() && self.cc.recover.is_none() {
+                    self.cc.frto = Some(Frto {
+                        recover: self.send.nxt,
+                        eifel: self
+                            .timestamps
+                            .then(|| self.ts_clock.read(self.clock.now())),
+                        sent_new: false,
+                        cwnd: self.cc.cwnd,
+                        ssthresh: self.cc.ssthresh,
+                    });
+                }
+                self.cc.ssthresh = cmp::max(flight / 2, 2 * self.mss as u32);
+            }
+            self.timers.backoffs += 1;
+            self.cc.cwnd = self.mss as u32;
+            self.cc.bytes_acked = 0;
+            self.cc.split_acked = 0;
+            self.cc.dup_acks = 0;
+            self.cc.dup_ack_threshold = self.loss.dup_ack_threshold;
+            self.cc.recover = None;
+            self.cc.high_rxt = None;
+            self.cc.fast_retransmit = None;
+
+            if self.cc.frto.is_none() {
+                self.go_back_n();
+            }
+            self.timers.rto_started = Some(self.clock.now());
+            return self.retransmit(nic);
+        }
+
+        if self.window_stalled()
+            && self.timers.probes > 0
+            && self.config.zero_window_timeout.is_some_and(|limit| {
+                self.timers
+                    .zero_window_since
+                    .is_some_and(|since| self.since(since) >= limit)
+            })
+        {
+            self.zero_window_aborted = true;
+            return self.abort(nic, io::ErrorKind::TimedOut, CloseReason::ZeroWindowTimeout);
+        }
+        self.probe_window(nic)?;
+        self.flush(nic)
+    }
+
+    /// Whether the peer's window is closed while data or our FIN waits to go out and nothing is
+    /// in flight, so that nothing but a probe would draw the ACK that opens it again.
+    fn window_stalled(&self) -> bool {
+        let sent = self.send.nxt.wrapping_sub(self.data_start()) as usize;
+        self.state.is_synchronized()
+            && self.send.wnd == 0
+            && self.send.nxt == self.send.una
+            && (self.unacked.len() > sent || self.fin_seq() == Some(self.send.nxt))
+    }
+
+    /// Run the persist timer (RFC 9293 S3.8.6.1): while the window is stalled, send a probe
+    /// every so often, backing off like the retransmission timer does. The probe is an empty
+    /// segment just before SND.UNA, as Linux sends, which the peer answers with an ACK giving
+    /// its window without anything entering the sequence space.
+    fn probe_window(&mut self, nic: &mut Outbound) -> io::Result<()> {
+        if !self.window_stalled() {
+            self.timers.persist_due = None;
+            return Ok(());
+        }
+        let now = self.clock.now();
+        let interval = cmp::min(
+            self.timers
+                .rto
+                .saturating_mul(1 << cmp::min(self.timers.probes, 16)),
+            self.loss.max_rto,
+        );
+        let due = *self.timers.persist_due.get_or_insert(now + interval);
+        if now < due {
+            return Ok(());
+        }
+        self.emit(nic, self.segment_flags(), self.send.una.wrapping_sub(1), 0)?;
+        self.timers.probes += 1;
+        self.timers.persist_due = None;
+        Ok(())
+    }
+
+    /// Whether the connection has been aborted for the peer's window staying closed since the
+    /// last call.
+    pub(crate) fn take_zero_window_abort(&mut self) -> bool {
+        std::mem::take(&mut self.zero_window_aborted)
+    }
+
+    /// The device has room again: send what it refused before, and whatever else is waiting.
+    pub(crate) fn on_writable(&mut self, nic: &mut Outbound) -> io::Result<()> {
+        match self.state {
+            State::SynSent | State::SynRcvd if self.send.nxt == self.send.iss => {
+                self.emit(nic, self.syn_flags(), self.send.iss, self.syn_data_limit())?;
+                Ok(())
+            }
+            State::TimeWait | State::Closed => Ok(()),
+            _ => self.flush(nic),
+        }
+    }
+
+    /// The listener that took the connection in went away before the application accepted it:
+    /// tear it down with an RST, which also answers a SYN we have not even answered yet.
+    pub(crate) fn reset(&mut self, nic: &mut Outbound) -> io::Result<()> {
+        match self.state {
+            State::TimeWait | State::Closed => Ok(()),
+            _ => self.abort(
+                nic,
+                io::ErrorKind::ConnectionReset,
+                CloseReason::ListenerClosed,
+            ),
+        }
+    }
+
+    /// A shutdown of the interface found the connection still open: tear it down with an RST,
+    /// unless the peer never heard from us, failing the application's next call.
+    pub(crate) fn abort_for_shutdown(&mut self, nic: &mut Outbound) -> io::Result<()> {
+        self.abort_open(nic, CloseReason::InterfaceShutdown)
+    }
+
+    /// The application asked for the connection to be aborted; see [`Interface::abort`].
+    ///
+    /// [`Interface::abort`]: crate::Interface::abort
+    pub(crate) fn abort_by_application(&mut self, nic: &mut Outbound) -> io::Result<()> {
+        self.abort_open(nic, CloseReason::LocalAbort)
+    }
+
+    /// Tear the connection down with an RST if it is still open, unless the peer never heard
+    /// from us, failing the application's next call with `ConnectionAborted`.
+    fn abort_open(&mut self, nic: &mut Outbound, reason: CloseReason) -> io::Result<()> {
+        match self.state {
+            State::TimeWait | State::Closed => Ok(()),
+            State::SynSent => {
+                self.error = Some(io::ErrorKind::ConnectionAborted);
+                self.state = State::Closed;
+                self.set_close_reason(reason);
+                Ok(())
+            }
+            _ => self.abort(nic, io::ErrorKind::ConnectionAborted, reason),
+        }
+    }
+
+    /// The interface is going away with the connection still open: nothing more is sent or
+    /// received on it, so the application's next call fails.
+    pub(crate) fn shut_down(&mut self) {
+        if self.is_done() {
+            return;
+        }
+        self.error = Some(io::ErrorKind::ConnectionAborted);
+        self.state = State::Closed;
+        self.set_close_reason(CloseReason::InterfaceShutdown);
+    }
+
+    /// Tear the connection down with an RST, failing the application's next call with `kind`.
+    fn abort(
+        &mut self,
+        nic: &mut Outbound,
+        kind: io::ErrorKind,
+        reason: CloseReason,
+    ) -> io::Result<()> {
+        self.error = Some(kind);
+        self.state = State::Closed;
+        self.set_close_reason(reason);
+        self.send_rst(nic, self.send.nxt, self.acking)
+    }
+
+    /// What is wrong with the sequence spaces, if they have come apart. None of this can come
+    /// from anything the peer sends, so it takes a bug here.
+    fn desync(&self) -> Option<&'static str> {
+        let in_flight = self.send.nxt.wrapping_sub(self.send.una) as usize;
+        if wrapping_lt(self.send.nxt, self.send.una) {
+            Some("SND.UNA is past SND.NXT")
+        } else if in_flight > self.unacked.len() + 2 {
+            // our SYN and FIN are all that is in flight without being in `unacked`
+            Some("more in flight than was ever written")
+        } else if self.incoming.len() > (u16::MAX as usize) << MAX_WSCALE {
+            Some("more received than any window allows")
+        } else {
+            None
+        }
+    }
+
+    /// Abort the connection with an RST if its sequence spaces have come apart, rather than
+    /// carry on corrupting the stream either way, returning whether it did.
+    pub(crate) fn check_sync(&mut self, nic: &mut Outbound) -> io::Result<bool> {
+        if self.state == State::Closed {
+            return Ok(false);
+        }
+        let Some(what) = self.desync() else {
+            return Ok(false);
+        };
+        self.drops.record(DropReason::Desync);
+        self.desync = Some(format!(
+            "sequence spaces came apart: {}; {:?} {:?}",
+            what, self.send, self.recv
+        ));
+        self.abort(nic, io::ErrorKind::ConnectionAborted, CloseReason::Desync)?;
+        Ok(true)
+    }
+
+    /// The error the connection failed with, if it did, with what was wrong with its sequence
+    /// spaces if that is why.
+    pub(crate) fn failure(&self) -> Option<io::Error> {
+        let kind = self.error?;
+        Some(match &self.desync {
+            Some(what) => io::Error::new(kind, what.clone()),
+            None => io::Error::from(kind),
+        })
+    }
+
+    /// Push SND.UNA past SND.NXT, as only a bug could, for tests of [`Connection::check_sync`].
+    pub(crate) fn corrupt_send_space(&mut self) {
+        self.send.una = self.send.nxt.wrapping_add(1);
+    }
+
+    /// Resend the oldest unacknowledged segment, whether it is our SYN (or SYN-ACK), data, our
+    /// FIN, or data followed by our FIN.
+    ///
+    /// Data is cut from the send buffer afresh, a full segment's worth from SND.UNA, however it
+    /// was split up when it first went out: small writes lost together come back as one
+    /// segment. Our FIN only goes along if that reaches it.
+    fn retransmit(&mut self, nic: &mut Outbound) -> io::Result<()> {
+        if self.send.una == self.send.iss {
+            self.emit(nic, self.syn_flags(), self.send.iss, 0)?;
+        } else {
+            // no further than the peer's window now reaches, should it have shrunk since the
+            // data first went out; with the window closed, a segment still goes, as a probe
+            let limit = match self.send.wnd as usize {
+                0 => self.mss as usize,
+                wnd => cmp::min(wnd, self.mss as usize),
+            };
+            let sent = self.emit(nic, self.segment_flags(), self.send.una, limit)?;
+            let end = self.send.una.wrapping_add(sent as u32);
+            if let Some(high_rxt) = self.cc.high_rxt.as_mut() {
+                if wrapping_lt(*high_rxt, end) {
+                    *high_rxt = end;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// In SACK-based loss recovery, send again what the scoreboard takes for lost, lowest
+    /// first, for as long as pipe leaves a segment's room in the congestion window (RFC 6675
+    /// S5 (C), NextSeg (1)); new data follows from flush, on the same terms. Returns whether
+    /// anything went.
+    fn retransmit_lost(&mut self, nic: &mut Outbound) -> io::Result<bool> {
+        let Some(mut high_rxt) = self.cc.high_rxt else {
+            return Ok(false);
+        };
+        let rule = self.loss_rule();
+        let mut sent_any = false;
+        while self.cc.cwnd.saturating_sub(self.in_flight()) >= rule.mss {
+            let (una, nxt) = (self.send.una, self.send.nxt);
+            let from = if wrapping_lt(high_rxt, una) {
+                una
+            } else {
+                high_rxt
+            };
+            let Some((seq, len)) = self.scoreboard.next_lost(una, nxt, from, rule) else {
+                break;
+            };
+            let limit = cmp::min(len, rule.mss) as usize;
+            let sent = self.emit(nic, self.segment_flags(), seq, limit)?;
+            if sent == 0 {
+                break;
+            }
+            high_rxt = seq.wrapping_add(sent as u32);
+            self.cc.high_rxt = Some(high_rxt);
+            sent_any = true;
+        }
+        Ok(sent_any)
+    }
+
+    /// Take the SACK blocks of an ACK into the scoreboard (RFC 6675 S5 (1)).
+    fn take_sack_blocks(&mut self, tcph: &etherparse::TcpHeaderSlice<'_>) {
+        let blocks = options(tcph.options())
+            .filter(|&(kind, _)| kind == OPTION_SACK)
+            .flat_map(|(_, value)| value.chunks_exact(8));
+        for block in blocks {
+            let left = u32::from_be_bytes([block[0], block[1], block[2], block[3]]);
+            let right = u32::from_be_bytes([block[4], block[5], block[6], block[7]]);
+            self.scoreboard
+                .insert(self.send.una, self.send.max, left, right);
+        }
+    }
+
+    /// Whether the cork keeps `unsent` bytes back for now: less than a full segment's worth,
+    /// before the application has closed, and not for longer than the cork timeout.
+    fn cork_holds(&mut self, unsent: usize) -> bool {
+        let full = (self.mss as usize).saturating_sub(self.data_options_len());
+        if !self.corked || unsent >= full || self.closed {
+            self.timers.cork_held = None;
+            return false;
+        }
+        let held = *self.timers.cork_held.get_or_insert(self.clock.now());
+        if self.since(held) < self.config.cork_timeout {
+            return true;
+        }
+        self.timers.cork_held = None;
+        false
+    }
+
+    /// Whether Nagle's algorithm keeps back a segment of `len` bytes for now: less than a full
+    /// one, with `in_flight` bytes still to be acknowledged. The last segment before our FIN
+    /// goes regardless, as the FIN would only wait behind it.
+    fn nagle_holds(&self, len: usize, in_flight: usize) -> bool {
+        let full = (self.mss as usize).saturating_sub(self.data_options_len());
+        self.config.nagle && len < full && in_flight > 0 && !self.closed
+    }
+
+    /// How much room options take up in a segment carrying data, SACK blocks aside.
+    fn data_options_len(&self) -> usize {
+        let mut len = 0;
+        if self.timestamps {
+            len += TIMESTAMPS_OPTION_LEN;
+        }
+        if self.md5_key.is_some() {
+            len += MD5_OPTION_LEN;
+        }
+        len
+    }
+
+    /// Write the options of a segment other than a SYN or an RST into `out`, with `ts_val` for
+    /// the TSval, leaving room for an MD5 signature if there is one, and return how much of it
+    /// they take up: the timestamps, then as many SACK blocks as fit while data is held beyond
+    /// a gap. The first block covers the data held last, and the rest follow in sequence order
+    /// (RFC 2018 S4).
+    fn write_segment_options(&self, ts_val: u32, out: &mut [u8]) -> usize {
+        let mut len = 0;
+        let mut put = |bytes: &[u8]| {
+            out[len..len + bytes.len()].copy_from_slice(bytes);
+            len += bytes.len();
+        };
+        if self.timestamps {
+            put(&[OPTION_NOP, OPTION_NOP, OPTION_TIMESTAMPS, 10]);
+            put(&ts_val.to_be_bytes());
+            put(&self.ts_recent.to_be_bytes());
+        }
+        if self.sack && !self.reassembly.is_empty() {
+            let room = MAX_OPTIONS_LEN - self.data_options_len();
+            let fit = room.saturating_sub(4) / 8;
+            let mut runs = self.reassembly.runs(self.recv.nxt);
+            let latest = runs
+                .iter()
+                .position(|run| run.contains(&self.sack_latest.wrapping_sub(self.recv.nxt)))
+                .unwrap_or(0);
+            runs[..=latest].rotate_right(1);
+            let blocks = cmp::min(fit, runs.len());
+            if blocks > 0 {
+                put(&[OPTION_NOP, OPTION_NOP, OPTION_SACK, 2 + 8 * blocks as u8]);
+                for run in &runs[..blocks] {
+                    put(&self.recv.nxt.wrapping_add(run.start).to_be_bytes());
+                    put(&self.recv.nxt.wrapping_add(run.end).to_be_bytes());
+                }
+            }
+        }
+        len
+    }
+
+    /// How long ago `then` was, by our clock.
+    fn since(&self, then: Instant) -> Duration {
+        self.clock.now().saturating_duration_since(then)
+    }
+
+    /// Where the connection leaves its sequence numbers, for the next one on the same quad to
+    /// stay clear of: `None` if it never got synchronized, or sat out TIME-WAIT in full.
+    pub(crate) fn incarnation(&self) -> Option<Incarnation> {
+        if !wrapping_lt(self.send.iss, self.send.una) || self.is_expired() {
+            return None;
+        }
+        Some(Incarnation {
+            snd_nxt: self.send.nxt,
+            rcv_nxt: self.recv.nxt,
+            ts_recent: self.timestamps.then_some(self.ts_recent),
+            until: self.clock.now() + 2 * self.config.msl,
+        })
+    }
+
+    /// Whether the 2MSL TIME-WAIT timer has run out, so the quad can be reused.
+    pub(crate) fn is_expired(&self) -> bool {
+        match self.time_wait_start {
+            Some(start) => self.since(start) >= 2 * self.config.msl,
+            None => false,
+        }
+    }
+
+    /// Whether a segment is a keepalive (RFC 9293 S3.8.4) or a zero window probe sent the same
+    /// way: one sequence number before RCV.NXT, carrying at most a byte we already have.
+    fn is_probe(&self, tcph: &etherparse::TcpHeaderSlice<'_>, data: &[u8]) -> bool {
+        self.state.is_synchronized()
+            && tcph.sequence_number() == self.recv.nxt.wrapping_sub(1)
+            && data.len() <= 1
+            && !tcph.syn()
+            && !tcph.fin()
+            && !tcph.rst()
+    }
+
+    /// Whether an ACK is a duplicate in the sense of RFC 5681 S2: it acknowledges nothing new
+    /// while data is outstanding, and carries nothing else either.
+    fn is_dup_ack(&self, tcph: &etherparse::TcpHeaderSlice<'_>, data: &[u8]) -> bool {
+        tcph.acknowledgment_number() == self.send.una
+            && self.send.una != self.send.nxt
+            && data.is_empty()
+            && !tcph.syn()
+            && !tcph.fin()
+            && self.peer_window(tcph) == self.send.wnd
+    }
+
+    fn on_dup_ack(&mut self, reply: &mut Reply) {
+        let mss = self.mss as u32;
+        self.cc.dup_acks += 1;
+        if self.cc.recover.is_some() {
+            if self.sack {
+                // what the ACK SACKed has left pipe already (RFC 6675 S5 (C))
+                reply.retransmit_lost = true;
+            } else {
+                // another segment has left the network (RFC 5681 S3.2 (4))
+                self.cc.cwnd = self.cc.cwnd.saturating_add(mss);
+            }
+        } else if self.cc.dup_acks == self.cc.dup_ack_threshold
+            || (self.sack && self.scoreboard.is_lost(self.send.una, self.loss_rule()))
+        {
+            // fast retransmit, and fast recovery until everything sent so far is ACKed; with
+            // SACK, as soon as the scoreboard says the first segment is lost (RFC 6675 S5 (2))
+            self.cc.fast_retransmit = Some(FastRetransmit {
+                at: self.clock.now(),
+                cwnd: self.cc.cwnd,
+                ssthresh: self.cc.ssthresh,
+            });
+            let flight = self.flight_size();
+            self.cc.ssthresh = cmp::max(flight / 2, 2 * mss);
+            self.cc.recover = Some(self.send.nxt);
+            reply.retransmit = true;
+            if self.sack {
+                // pipe, not an inflated window, says what more may go (RFC 6675 S5 (4.2))
+                self.cc.cwnd = self.cc.ssthresh;
+                self.cc.high_rxt = Some(self.send.una);
+                reply.retransmit_lost = true;
+            } else {
+                self.cc.cwnd = self.cc.ssthresh + self.cc.dup_ack_threshold * mss;
+            }
+        }
+        // below the threshold, limited transmit lets flush send a little more (send_window)
+    }
+
+    /// Take the check of whether a timeout was spurious one step further on an ACK that came in
+    /// after it, echoing `ts_ecr` if it has timestamps. With timestamps, the first ACK of new
+    /// data settles it: the timeout was spurious if the ACK echoes a TSval from before the
+    /// retransmission (RFC 3522 S3.2). Without, F-RTO (RFC 5682 S2.1) takes it for spurious if
+    /// neither of the next two ACKs is a duplicate, and the first lets us send new data rather
+    /// than covering everything that was outstanding.
+    fn on_frto_ack(
+        &mut self,
+        reply: &mut Reply,
+        dup_ack: bool,
+        ackn: u32,
+        acked: u32,
+        ts_ecr: Option<u32>,
+    ) {
+        let Some(frto) = self.cc.frto else {
+            return;
+        };
+        if !dup_ack && acked == 0 {
+            return;
+        }
+
+        if let Some(retransmitted) = frto.eifel {
+            if acked == 0 {
+                // a duplicate ACK is drawn by whatever arrived, so says nothing either way
+                return;
+            }
+            if ts_ecr.is_some_and(|ts_ecr| wrapping_lt(ts_ecr, retransmitted)) {
+                // the segments we thought lost were only late: restore the window, and carry
+                // on with new data from where we were (RFC 4015 S3)
+                self.cc.cwnd = frto.cwnd;
+                self.cc.ssthresh = frto.ssthresh;
+                self.cc.spurious_rtos += 1;
+            } else {
+                self.go_back_n();
+            }
+            self.cc.frto = None;
+            return;
+        }
+
+        if frto.sent_new {
+            if dup_ack {
+                // (3a) something was lost after all
+                self.cc.cwnd = cmp::min(self.cc.cwnd, 3 * self.mss as u32);
+                self.go_back_n();
+            } else {
+                // (3b) the segments we thought lost were only late: carry on where we were
+                self.cc.cwnd = frto.cwnd;
+                self.cc.ssthresh = frto.ssthresh;
+                self.cc.spurious_rtos += 1;
+            }
+            self.cc.frto = None;
+            return;
+        }
+
+        if dup_ack || !wrapping_lt(ackn, frto.recover) {
+            // (2a)
+            self.go_back_n();
+            self.cc.frto = None;
+            return;
+        }
+        // (2b) the retransmission got through, and not everything after it has yet: see whether
+        // up to two segments of new data get through as well
+        let flight = self.flight_size();
+        self.cc.cwnd = flight + 2 * self.mss as u32;
+        reply.frto_new_data = true;
+    }
+
+    /// Send the new data F-RTO wants to see get through (RFC 5682 S2.1 (2b)), if there is
+    /// any. Returns whether there was.
+    fn send_frto_new_data(&mut self, nic: &mut Outbound) -> io::Result<bool> {
+        let Some(frto) = self.cc.frto else {
+            return Ok(false);
+        };
+        let nxt = self.send.nxt;
+        self.flush(nic)?;
+        if self.send.nxt == nxt {
+            // nothing new to send, so there will be nothing to tell
+            self.cc.cwnd = self.mss as u32;
+            self.go_back_n();
+            self.cc.frto = None;
+            return Ok(false);
+        }
+        self.cc.frto = Some(Frto {
+            sent_new: true,
+            ..frto
+        });
+        Ok(true)
+    }
+
+    /// Forget what was sent after SND.UNA, so it is sent again as the window opens, since the
+    /// peer may well have dropped it.
+    fn go_back_n(&mut self) {
+        self.timers.send_times.clear();
+        self.send.nxt = self.send.una;
+        // the peer may have thrown away what it SACKed (RFC 2018 S8)
+        self.scoreboard.clear();
+    }
+
+    /// React to an ACK that acknowledged `acked` new bytes, up to `ackn`, with `sacked` bytes
+    /// SACKed beyond SND.UNA before it came.
+    fn on_new_ack(&mut self, reply: &mut Reply, ackn: u32, acked: u32, sacked: u32) {
+        let mss = self.mss as u32;
+        let dup_acks = mem::take(&mut self.cc.dup_acks);
+        if self.cc.recover.is_none() {
+            // the hole filled in before the threshold was reached, with nothing sent again: as
+            // many segments overtook it as the peer SACKed beyond it, or drew duplicate ACKs
+            let overtaken = cmp::max(dup_acks, sacked.div_ceil(mss));
+            if overtaken > 0 {
+                self.saw_reordering(overtaken);
+            }
+        }
+        if let Some(fast) = self.cc.fast_retransmit.take() {
+            // without timestamps to tell which transmission this ACK is for, go by how soon it
+            // came: the retransmission cannot have made it there and back in half a round trip
+            if self.since(fast.at) < self.timers.srtt / 2 {
+                self.saw_reordering(dup_acks);
+                self.cc.spurious_fast_retransmits += 1;
+                // undo the congestion response if nothing else is missing either; a receiver
+                // that does not keep what arrives out of order has dropped what overtook the
+                // late segment, and recovery carries on to send that again
+                if self
+                    .cc
+                    .recover
+                    .is_some_and(|recover| !wrapping_lt(ackn, recover))
+                {
+                    self.cc.cwnd = fast.cwnd;
+                    self.cc.ssthresh = fast.ssthresh;
+                    self.cc.recover = None;
+                    self.cc.high_rxt = None;
+                    return;
+                }
+            }
+        }
+        if let Some(recover) = self.cc.recover {
+            if wrapping_lt(ackn, recover) {
+                // a partial ACK: the segment after it was lost too (RFC 6582 S3.2 (5))
+                if self.sack {
+                    // unless it went again already; then what else is lost, as pipe allows
+                    reply.retransmit = self
+                        .cc
+                        .high_rxt
+                        .is_none_or(|high_rxt| !wrapping_lt(ackn, high_rxt));
+                    reply.retransmit_lost = true;
+                } else {
+                    reply.retransmit = true;
+                    self.cc.cwnd = self.cc.cwnd.saturating_sub(acked);
+                    if acked >= mss {
+                        self.cc.cwnd += mss;
+                    }
+                }
+            } else {
+                let flight = self.flight_size();
+                self.cc.cwnd = cmp::min(self.cc.ssthresh, cmp::max(flight, mss) + mss);
+                self.cc.recover = None;
+                self.cc.high_rxt = None;
+            }
+        }
+    }
+
+    /// Note that a segment turned up late after drawing `dup_acks` duplicate ACKs, and raise
+    /// the threshold past that if the configuration allows.
+    fn saw_reordering(&mut self, dup_acks: u32) {
+        self.cc.reordering = cmp::max(self.cc.reordering, dup_acks);
+        if let Some(max) = self.config.max_dup_ack_threshold {
+            let wanted = cmp::min(dup_acks.saturating_add(1), max);
+            self.cc.dup_ack_threshold = cmp::max(self.cc.dup_ack_threshold, wanted);
+        }
+    }
+
+    /// Whether an ACK up to `ackn`, acknowledging `acked` new bytes, ends inside a segment we
+    /// sent and acknowledges too little to be anything but one of several for it; counts it if
+    /// so. Segments sent again after a timeout may be cut up differently, so ACKs are only
+    /// judged against segments whose send time is known.
+    fn is_split_ack(&mut self, ackn: u32, acked: usize) -> bool {
+        let split = acked < self.config.split_ack_floor
+            && ackn != self.send.nxt
+            && !self.timers.send_times.is_empty()
+            && !self.timers.send_times.contains_key(&ackn);
+        if split {
+            self.cc.suspicious_acks += 1;
+        }
+        split
+    }
+
+    /// Open the congestion window for `acked` newly acknowledged bytes (RFC 3465 S2).
+    fn grow_cwnd(&mut self, acked: u32) {
+        let mss = self.mss as u32;
+        if self.cc.cwnd < self.cc.ssthresh {
+            // slow start
+            let limit = self.config.abc_limit as u32 * mss;
+            self.cc.cwnd = self.cc.cwnd.saturating_add(cmp::min(acked, limit));
+        } else {
+            // congestion avoidance: one segment per window's worth of bytes
+            self.cc.bytes_acked += acked;
+            if self.cc.bytes_acked >= self.cc.cwnd {
+                self.cc.bytes_acked -= self.cc.cwnd;
+                self.cc.cwnd = self.cc.cwnd.saturating_add(mss);
+            }
+        }
+    }
+
+    /// Process an acceptable ACK that moves SND.UNA forward to `ackn`.
+    fn on_ack(&mut self, ackn: u32) {
+        let una = self.send.una;
+        let mut acked = ackn.wrapping_sub(una) as usize;
+        if acked == 0 {
+            return;
+        }
+        if self.send.una == self.send.iss {
+            // the first sequence number is our SYN, which is not in `unacked`
+            acked -= 1;
+        }
+        let split = self.is_split_ack(ackn, acked);
+        let acked_data = cmp::min(acked, self.unacked.len());
+        self.unacked.drain(..acked_data);
+        self.send.una = ackn;
+        self.scoreboard.advance(una, ackn);
+        if wrapping_lt(self.send.nxt, ackn) {
+            // the peer had what going back N was about to send again
+            self.send.nxt = ackn;
+        }
+        if self.send.up.is_some_and(|up| !wrapping_lt(ackn, up)) {
+            self.send.up = None;
+        }
+        // with congestion window validation, a window the connection does not use does not
+        // grow either
+        if self.cc.recover.is_none()
+            && self.cc.frto.is_none()
+            && (self.config.cwnd_validation.is_none() || self.cwnd_validated())
+        {
+            if split {
+                self.cc.split_acked = self.cc.split_acked.saturating_add(acked as u32);
+            } else {
+                let acked = (acked as u32).saturating_add(mem::take(&mut self.cc.split_acked));
+                self.grow_cwnd(acked);
+            }
+        }
+        // restart the retransmission timer, or stop it if nothing is left (RFC 6298 (5.2), (5.3))
+        self.timers.rto_started = if ackn == self.send.nxt {
+            None
+        } else {
+            Some(self.clock.now())
+        };
+
+        // take an RTT sample from the oldest segment now acknowledged in full, and forget all of them
+        let now = self.clock.now();
+        let mut sample = None;
+        // the segments outstanding end between `una` and SND.NXT, and those ending past the
+        // point where the sequence numbers wrap come after those ending before it
+        let acked: Vec<_> = self
+            .timers
+            .send_times
+            .range(una..)
+            .chain(self.timers.send_times.range(..una))
+            .map(|(&end, &sent)| (end, sent))
+            .take_while(|&(end, _)| !wrapping_lt(ackn, end))
+            .collect();
+        for (end, sent) in acked {
+            sample.get_or_insert(now.saturating_duration_since(sent));
+            self.timers.send_times.remove(&end);
+        }
+        if self.timers.backoffs > 0 {
+            // the sample could belong to any of the transmissions, so do not take it (Karn's
+            // algorithm), but the backed-off timeout has done its job.
+            self.timers.backoffs = 0;
+        } else if let Some(rtt) = sample {
+            self.take_rtt_sample(rtt);
+        }
+        self.timers.rto = match self.timers.rttvar {
+            // RFC 6298 (2.2), (2.3): RTO = SRTT + max(G, K*RTTVAR), with K = 4, and G the
+            // granularity the timers go off at
+            Some(rttvar) => cmp::min(
+                cmp::max(
+                    self.loss.min_rto,
+                    self.timers.srtt + cmp::max(crate::TICK, 4 * rttvar),
+                ),
+                self.loss.max_rto,
+            ),
+            // RFC 6298 (2.1): the initial RTO until there is a sample
+            None => self.loss.initial_rto,
+        };
+    }
+
+    /// Fold the round-trip time `rtt` into SRTT and RTTVAR (RFC 6298 (2.2), (2.3)), with
+    /// alpha = 1/8 and beta = 1/4.
+    fn take_rtt_sample(&mut self, rtt: Duration) {
+        let srtt = self.timers.srtt;
+        self.timers.rttvar = Some(match self.timers.rttvar {
+            None => {
+                self.timers.srtt = rtt;
+                rtt / 2
+            }
+            Some(rttvar) => {
+                let deviation = srtt.abs_diff(rtt);
+                self.timers.srtt = srtt * 7 / 8 + rtt / 8;
+                rttvar * 3 / 4 + deviation / 4
+            }
+        });
+    }
+
+    /// Process a segment for the connection, and say what it changed for the packet loop to
+    /// act on.
+    pub(crate) fn on_packet<'a>(
+        &mut self,
+        nic: &mut Outbound,
+        limits: &mut ReplyLimits,
+        iph: etherparse::Ipv4HeaderSlice<'a>,
+        tcph: etherparse::TcpHeaderSlice<'a>,
+        data: &'a [u8],
+    ) -> io::Result<Available> {
+        let (state, una, wnd) = (self.state, self.send.una, self.send.wnd);
+        let failed = self.error.is_some();
+        self.on_segment(nic, limits, iph, tcph, data)?;
+        Ok(Available {
+            readable: self.wakes_readers(),
+            writable: self.send.una != una
+                || self.send.wnd != wnd
+                || self.state != state
+                || self.error.is_some() != failed,
+            closed: self.is_done() && !matches!(state, State::TimeWait | State::Closed),
+            remove: self.is_finished(),
+            dropped: self.dropped.take(),
+            fast_open_cookie: self.fast_open_cookie.take(),
+            reopen: std::mem::take(&mut self.reopen),
+        })
+    }
+
+    fn on_segment<'a>(
+        &mut self,
+        nic: &mut Outbound,
+        limits: &mut ReplyLimits,
+        iph: etherparse::Ipv4HeaderSlice<'a>,
+        tcph: etherparse::TcpHeaderSlice<'a>,
+        data: &'a [u8],
+    ) -> io::Result<()> {
+        self.timers.last_recv = self.clock.now();
+        // the peer has heard the window is open, or can tell from what we send in reply
+        self.window_reopened = None;
+        self.trace_received(&iph, &tcph, data);
+        match self.state {
+            State::TimeWait => {
+                // TIME-WAIT has its own rules (RFC 793 p.73, RFC 1337): the usual acceptance
+                // checks below would reject a retransmitted FIN, since it lies just before
+                // RCV.NXT.
+                return self.on_time_wait_packet(nic, tcph, data);
+            }
+            State::SynSent => return self.on_syn_sent_packet(nic, limits, tcph),
+            State::Closed => {
+                self.discard(DropReason::AfterClose);
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        // first, check that sequence numbers are valid (RFC 793 s3.3)
+        //
+        // acceptable ack check
+        // SND.UNA < SEG.ACK =< SND.NXT
+        // but remember wrapping!
+        //
+        // once synchronized, going back N may have wound SND.NXT back over data the peer has
+        // had all along, so the ACK may go up to SND.MAX instead, as in BSD.
+        //
+        // a duplicate ACK (SEG.ACK = SND.UNA) is let through as well once synchronized, since
+        // the segment may still carry data or a FIN we need to process.
+        //
+        // a segment without the ACK bit (say, a retransmitted SYN) has no ACK to check.
+        //
+        let ackn = tcph.acknowledgment_number();
+        let sent = if self.state.is_synchronized() {
+            self.send.max
+        } else {
+            self.send.nxt
+        };
+        if tcph.ack()
+            && (!is_between_wrapped(self.send.una.wrapping_sub(1), ackn, sent.wrapping_add(1))
+                || (!self.state.is_synchronized() && ackn == self.send.una))
+        {
+            if wrapping_lt(sent, ackn) {
+                self.discard(DropReason::BadAck);
+                if self.state.is_synchronized() {
+                    return self.on_optimistic_ack(nic, limits);
+                }
+            } else {
+                self.discard(DropReason::OldAck);
+            }
+            if !self.state.is_synchronized() && !tcph.rst() && limits.allow_rst(self.clock.now()) {
+                // <SEQ=SEG.ACK><CTL=RST> (RFC 793 p.65): the sequence number the peer expects
+                // of us, going by its ACK, without an ACK of our own. in SYN-RECEIVED that
+                // means any ACK but of our SYN, and whatever it carried; never an RST, though
+                self.send_rst(nic, ackn, false)?;
+            }
+            return Ok(());
+        }
+
+        // SND.NXT counts segments still queued for the device, which the peer cannot have seen
+        if tcph.ack()
+            && self.state.is_synchronized()
+            && nic
+                .emitted(self.flow())
+                .is_some_and(|emitted| wrapping_lt(emitted, ackn))
+        {
+            self.discard(DropReason::BadAck);
+            return self.on_optimistic_ack(nic, limits);
+        }
+
+        // PAWS (RFC 7323 S5.3 R1): a timestamp older than TS.Recent marks an old duplicate,
+        // however well its sequence numbers fit the window, unless TS.Recent has gone unchanged
+        // for so long that the peer's clock may have come round since
+        if let Some((ts_val, _)) = segment_timestamps(&tcph).filter(|_| self.timestamps) {
+            if !tcph.rst()
+                && wrapping_lt(ts_val, self.ts_recent)
+                && self.since(self.ts_recent_at) < PAWS_IDLE
+            {
+                self.discard(DropReason::Paws);
+                return self.on_unacceptable(nic, limits, &tcph);
+            }
+        }
+
+        // a keepalive or window probe: no data, or one byte of garbage, just before RCV.NXT, as
+        // Linux sends them. All it asks for is our ACK, which goes out right away, with nothing
+        // else about the segment taken in, and regardless of the limit on challenge ACKs: forging
+        // one takes knowing RCV.NXT exactly.
+        if self.is_probe(&tcph, data) {
+            if !tcph.ack() && !self.tolerate(Violation::ProbeWithoutAck) {
+                self.discard(DropReason::Violation);
+                return Ok(());
+            }
+            self.duplicate_bytes += data.len() as u64;
+            self.send_ack(nic)?;
+            return Ok(());
+        }
+
+        // valid segment check (RFC 793 p.69)
+        //
+        let mut seqn = tcph.sequence_number();
+        let mut slen = data.len() as u32;
+        if tcph.fin() {
+            slen += 1;
+        };
+        if tcph.syn() {
+            if self.state == State::SynRcvd && tcph.ack() && seqn == self.recv.irs {
+                // the peer's answer to our SYN in a simultaneous open: it repeats the SYN we
+                // took in in SYN-SENT, just before RCV.NXT, and its ACK is all that is news
+                seqn = seqn.wrapping_add(1);
+            } else {
+                slen += 1;
+            }
+        };
+        // the window ends where we said it does, however much has come in since: RCV.WND shrinks
+        // as RCV.NXT moves up to that edge
+        let window = match segment_acceptable(self.recv.nxt, self.rcv_promised(), seqn, slen) {
+            Acceptance::Acceptable(window) => window,
+            Acceptance::Unacceptable => {
+                if !wrapping_lt(self.recv.nxt, seqn.wrapping_add(data.len() as u32)) {
+                    // a retransmission of data we already have, all of it
+                    self.duplicate_bytes += data.len() as u64;
+                } else {
+                    self.window_overrun_bytes += data.len() as u64;
+                }
+                self.discard(DropReason::OutOfWindow);
+                return self.on_unacceptable(nic, limits, &tcph);
+            }
+        };
+
+        // the TSval to echo is that of the earliest segment we have yet to acknowledge, or
+        // of the last one, if it was in order (RFC 7323 S4.3)
+        if let Some((ts_val, _)) = segment_timestamps(&tcph).filter(|_| self.timestamps) {
+            if !wrapping_lt(ts_val, self.ts_recent) && !wrapping_lt(self.rcv_acked, seqn) {
+                self.ts_recent = ts_val;
+                self.ts_recent_at = self.clock.now();
+            }
+        }
+
+        if tcph.rst() {
+            self.error = Some(io::ErrorKind::ConnectionReset);
+            self.state = State::Closed;
+            self.set_close_reason(CloseReason::PeerReset);
+            return Ok(());
+        }
+
+        // what the segment calls for goes out only once all of it has been taken in
+        let mut reply = Reply::default();
+
+        // a window that shrank is only taken as it is from a lenient connection; a strict one
+        // keeps to the old right edge, and whatever it sends past the new one the peer drops
+        let window_ok = !(self.state.is_synchronized() && self.shrinks_window(&tcph))
+            || self.tolerate(Violation::ShrunkWindow);
+
+        // If the data flow is momentarily idle and all data
+        //sent has been acknowledged then the three variables will be equal
+        if tcph.ack() {
+            let dup_ack = self.is_dup_ack(&tcph, data);
+            let acked = ackn.wrapping_sub(self.send.una);
+            let sacked = self.scoreboard.sacked();
+            self.on_ack(ackn);
+            if self.sack {
+                self.take_sack_blocks(&tcph);
+            }
+            if self.state.is_synchronized() {
+                if window_ok {
+                    self.update_send_window(&tcph);
+                } else {
+                    self.keep_right_edge(&tcph);
+                }
+            }
+            if self.cc.frto.is_some() {
+                let ts_ecr = segment_timestamps(&tcph).map(|(_, ts_ecr)| ts_ecr);
+                self.on_frto_ack(&mut reply, dup_ack, ackn, acked, ts_ecr);
+            } else if dup_ack {
+                self.on_dup_ack(&mut reply);
+            } else if acked > 0 {
+                self.on_new_ack(&mut reply, ackn, acked, sacked);
+            }
+        }
+
+        match self.state {
+            State::SynRcvd => {
+                // expect to get an ACK for our SYN
+                let inferred = !tcph.ack() && !data.is_empty() && !tcph.syn();
+                if !tcph.ack() {
+                    if !inferred {
+                        self.discard(DropReason::NoAckFlag);
+                        return Ok(());
+                    }
+                    if !self.tolerate(Violation::DataBeforeAck) {
+                        self.discard(DropReason::Violation);
+                        return Ok(());
+                    }
+                    // the peer cannot have sent data before it had our SYN-ACK, so take the
+                    // segment as acknowledging our SYN, which is all there is to acknowledge
+                    self.on_ack(self.send.iss.wrapping_add(1));
+                }
+                // must have ACKed our SYN, since we detected at least one acked byte, and we have
+                // only sent one byte (the SYN).
+                self.state = if self.closed {
+                    State::FinWait1
+                } else {
+                    State::Estab
+                };
+                // the send window so far was the one in the peer's SYN, which is never scaled;
+                // from here on it is the ACK's, scaled as settled (RFC 793 p.72)
+                self.set_send_window(&tcph);
+                if inferred {
+                    self.send.wl2 = self.send.una;
+                }
+            }
+            State::FinWait1 | State::Closing | State::LastAck if self.fin_acked() => {
+                match self.state {
+                    State::FinWait1 => self.state = State::FinWait2,
+                    State::Closing => self.enter_time_wait(),
+                    State::LastAck => {
+                        self.state = State::Closed;
+                        self.set_close_reason(CloseReason::PeerFin);
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            _ => {}
+        }
+
+        // process the segment text
+        let nxt = self.recv.nxt;
+        let had_gap = !self.reassembly.is_empty();
+        let mut needs_ack = false;
+        if let State::Estab | State::FinWait1 | State::FinWait2 = self.state {
+            if tcph.urg() && tcph.urgent_pointer() != 0 {
+                // only a mark further on than the last one is news; every segment up to the
+                // urgent data repeats it
+                let up = seqn.wrapping_add(tcph.urgent_pointer() as u32);
+                if self.recv.up.is_none_or(|last| wrapping_lt(last, up))
+                    && wrapping_lt(self.recv.nxt, up)
+                {
+                    // a byte we have not picked up yet is overtaken, as in BSD
+                    self.recv.up = Some(up);
+                    self.urgent = None;
+                }
+            }
+
+            let fin_seq = seqn.wrapping_add(data.len() as u32);
+            if !data.is_empty() {
+                needs_ack = true;
+                // only the part of the data inside the window; a FIN just past it is not. what
+                // comes before it we already have
+                let skip = window.start.wrapping_sub(seqn) as usize;
+                let end = cmp::min(window.end.wrapping_sub(seqn) as usize, data.len());
+                self.window_overrun_bytes += (data.len() - end) as u64;
+                if window.start != self.recv.nxt {
+                    // ahead of a gap: hold on to it until the gap fills, while the ACK asks for
+                    // what is missing (RFC 9293 S3.10.7.4)
+                    if skip < end {
+                        match self
+                            .reassembly
+                            .insert(self.recv.nxt, window.start, &data[skip..end])
+                        {
+                            Some(held) => {
+                                self.duplicate_bytes += held as u64;
+                                self.sack_latest = window.start;
+                            }
+                            None => self.discard(DropReason::OutOfOrder),
+                        }
+                    }
+                } else {
+                    self.duplicate_bytes += cmp::min(skip, data.len()) as u64;
+                    if skip < end {
+                        let room = self.rcv_room();
+                        let take = cmp::min(end - skip, room);
+                        self.window_overrun_bytes += (end - skip - take) as u64;
+                        self.receive(&data[skip..skip + take]);
+                        // the sender wants what it has sent so far delivered without waiting
+                        // for more (RFC 9293 S3.9.1.2)
+                        if tcph.psh() && take > 0 {
+                            self.pushed = true;
+                        }
+                        self.reassemble();
+                    }
+                }
+            }
+
+            // a FIN inside the window is where the stream ends, even if data before it is
+            // missing still
+            if tcph.fin() && window.end == fin_seq.wrapping_add(1) {
+                self.fin_held = Some(fin_seq);
+            }
+            if self.fin_held == Some(self.recv.nxt) {
+                // the peer is done sending; everything before its FIN has been received. a FIN on
+                // a segment whose data was cut short by the room we had waits for the
+                // retransmission of the rest. the data goes to the application ahead of the end
+                // of the stream, and the ACK covers both.
+                self.fin_held = None;
+                self.recv.nxt = self.recv.nxt.wrapping_add(1);
+                needs_ack = true;
+                match self.state {
+                    State::Estab => self.state = State::CloseWait,
+                    State::FinWait1 => self.state = State::Closing,
+                    State::FinWait2 => self.enter_time_wait(),
+                    _ => unreachable!(),
+                }
+            }
+        }
+
+        // whatever the segment's ACK lets us send goes out once the rest of the batch it came
+        // in with is in too (see `flush_received`), and so does its own ACK, unless it brought
+        // nothing new (the peer should hear of a gap, or of a full buffer, at once) or two
+        // full-sized segments have gone unacknowledged (RFC 5681 S4.2)
+        reply.ack = needs_ack
+            && (self.recv.nxt == nxt
+                || had_gap
+                || self.recv.nxt.wrapping_sub(self.rcv_acked) >= 2 * self.mss as u32);
+        self.send_reply(nic, reply)
+    }
+
+    /// Send what processing a segment called for, now that it has been, so that it carries
+    /// RCV.NXT, SND.NXT and the window as the segment left them. Data sent carries the ACK,
+    /// which then need not go out on its own.
+    fn send_reply(&mut self, nic: &mut Outbound, reply: Reply) -> io::Result<()> {
+        let mut acked = false;
+        if reply.retransmit {
+            self.retransmit(nic)?;
+            acked = true;
+        }
+        if reply.retransmit_lost {
+            acked |= self.retransmit_lost(nic)?;
+        }
+        if reply.frto_new_data {
+            acked |= self.send_frto_new_data(nic)?;
+        }
+        if reply.ack && !acked {
+            self.send_ack(nic)?;
+        }
+        Ok(())
+    }
+
+    /// Send what the segments that arrived since the last call made room for, and acknowledge
+    /// their data unless one of those segments already did.
+    pub(crate) fn flush_received(&mut self, nic: &mut Outbound) -> io::Result<()> {
+        self.flush(nic)?;
+        if self.recv.nxt != self.rcv_acked && self.state != State::Closed {
+            self.send_ack(nic)?;
+        }
+        Ok(())
+    }
+
+    /// Take what was held beyond a gap, now that in-order data has come up to it.
+    fn reassemble(&mut self) {
+        while let Some((seq, data)) = self.reassembly.pop_ready(self.recv.nxt) {
+            let skip = cmp::min(self.recv.nxt.wrapping_sub(seq) as usize, data.len());
+            self.duplicate_bytes += skip as u64;
+            let take = cmp::min(data.len() - skip, self.rcv_room());
+            self.window_overrun_bytes += (data.len() - skip - take) as u64;
+            self.receive(&data[skip..skip + take]);
+        }
+    }
+
+    /// Append in-order data starting at RCV.NXT to `incoming`, except for the urgent byte if it
+    /// is among it, which is kept aside with a mark where it was.
+    fn receive(&mut self, data: &[u8]) {
+        if self.read_shut {
+            self.recv.nxt = self.recv.nxt.wrapping_add(data.len() as u32);
+            return;
+        }
+        if self.incoming.is_empty() && !data.is_empty() {
+            self.unread_since = Some(self.clock.now());
+        }
+        let urgent_at = self
+            .recv
+            .up
+            .map(|up| up.wrapping_sub(1).wrapping_sub(self.recv.nxt) as usize)
+            .filter(|&at| at < data.len());
+        match urgent_at {
+            Some(at) => {
+                self.incoming.extend(&data[..at]);
+                self.urgent = Some(data[at]);
+                self.urgent_mark = Some(self.incoming.len());
+                self.incoming.extend(&data[at + 1..]);
+            }
+            None => self.incoming.extend(data),
+        }
+        self.recv.nxt = self.recv.nxt.wrapping_add(data.len() as u32);
+        if self.config.recv_autotune {
+            self.tune_rcv_buffer(data.len());
+        }
+    }
+
+    /// Grow the receive buffer to twice what arrived in the last round trip, if that is more
+    /// than it holds and the application is keeping up with it, so the peer's window never
+    /// limits it (dynamic right-sizing, as Linux does in `tcp_rcv_space_adjust`).
+    fn tune_rcv_buffer(&mut self, received: usize) {
+        self.rcv_space += received;
+        if self.since(self.rcv_space_since) < self.timers.srtt {
+            return;
+        }
+        let wanted = 2 * self.rcv_space;
+        if wanted > self.rcv_buffer && self.incoming.len() < self.rcv_buffer / 2 {
+            self.rcv_buffer = cmp::min(wanted, self.rcv_buffer_limit());
+        }
+        self.rcv_space = 0;
+        self.rcv_space_since = self.clock.now();
+    }
+
+    /// The most the receive buffer may grow to: as configured, and as far as our window scale
+    /// lets us advertise.
+    fn rcv_buffer_limit(&self) -> usize {
+        let limit = cmp::max(self.config.recv_buffer, self.config.recv_buffer_max);
+        cmp::min(limit, (u16::MAX as usize) << self.rcv_wscale)
+    }
+
+    /// How much more data we take in: the room left in the buffer (or in the accept window,
+    /// while the connection is queued, or under the receive memory limit), but never less than
+    /// the window we have already advertised, even if the buffer shrank since.
+    fn rcv_room(&self) -> usize {
+        cmp::max(self.rcv_free(), self.rcv_promised() as usize)
+    }
+
+    /// How much more the receive buffer holds, as far as it is open to the peer.
+    fn rcv_free(&self) -> usize {
+        let buffer = cmp::min(self.rcv_buffer, self.accept_window.unwrap_or(usize::MAX));
+        let buffer = cmp::min(buffer, self.rcv_limit.unwrap_or(usize::MAX));
+        buffer.saturating_sub(self.incoming.len())
+    }
+
+    /// How much of the window we have advertised is still open: from RCV.NXT up to its right
+    /// edge.
+    fn rcv_promised(&self) -> u32 {
+        if wrapping_lt(self.recv.nxt, self.rcv_adv) {
+            self.rcv_adv.wrapping_sub(self.recv.nxt)
+        } else {
+            0
+        }
+    }
+
+    /// Work out the window to put in the next segment, returned as it goes in the header. The
+    /// window in a SYN is never scaled
+    /// (RFC 7323 S2.2); otherwise it is rounded up so that scaling does not shrink it, unless
+    /// the buffer has no room beyond what is promised already. Rounding that up again on
+    /// every segment would move the right edge out by up to a unit each time, for as long as
+    /// the application does not read; rounded down, the edge only seems to fall back by less
+    /// than a unit, and we still take everything up to where it was.
+    fn advertise_window(&mut self, syn: bool) -> u16 {
+        let room = self.rcv_room();
+        let wnd = if syn {
+            cmp::min(room, u16::MAX as usize)
+        } else {
+            let unit = 1 << self.rcv_wscale;
+            let wnd = if self.rcv_free() >= room {
+                room.div_ceil(unit) * unit
+            } else {
+                room / unit * unit
+            };
+            cmp::min(wnd, (u16::MAX as usize) << self.rcv_wscale)
+        };
+        self.recv.wnd = wnd as u32;
+        let edge = self.recv.nxt.wrapping_add(self.recv.wnd);
+        if wrapping_lt(self.rcv_adv, edge) {
+            self.rcv_adv = edge;
+        }
+        if syn {
+            wnd as u16
+        } else {
+            (wnd >> self.rcv_wscale) as u16
+        }
+    }
+
+    /// Once the connection has gone idle, give back whatever auto-tuning added to the receive
+    /// buffer. What has been advertised stays open regardless.
+    fn shrink_rcv_buffer(&mut self) {
+        if self.rcv_buffer > self.config.recv_buffer
+            && self.incoming.is_empty()
+            && self.since(self.timers.last_recv) > self.timers.rto
+        {
+            self.rcv_buffer = self.config.recv_buffer;
+            self.rcv_space = 0;
+            self.rcv_space_since = self.clock.now();
+        }
+    }
+
+    /// Reply to a segment outside the receive window, which is most likely a duplicate, with an
+    /// ACK saying what we expect instead (RFC 793 p.69): our previous ACK may have been lost. In
+    /// SYN-RECEIVED, that is our SYN-ACK again. These replies are what a blind attacker can
+    /// draw out of us, so they count against the interface's challenge ACK limit.
+    fn on_unacceptable(
+        &mut self,
+        nic: &mut Outbound,
+        limits: &mut ReplyLimits,
+        tcph: &etherparse::TcpHeaderSlice<'_>,
+    ) -> io::Result<()> {
+        if tcph.rst() || !limits.allow_challenge_ack(self.clock.now()) {
+            return Ok(());
+        }
+        if self.state == State::SynRcvd {
+            self.emit(nic, self.syn_flags(), self.send.iss, 0)?;
+        } else {
+            self.send_ack(nic)?;
+        }
+        Ok(())
+    }
+
+    /// Reply to an ACK of data that never reached the network with an ACK of our own, which
+    /// tells the peer where we are instead (RFC 793 p.72). The peer could only have guessed at
+    /// it, so the reply counts against the interface's challenge ACK limit.
+    fn on_optimistic_ack(
+        &mut self,
+        nic: &mut Outbound,
+        limits: &mut ReplyLimits,
+    ) -> io::Result<()> {
+        self.cc.optimistic_acks += 1;
+        if !limits.allow_challenge_ack(self.clock.now()) {
+            return Ok(());
+        }
+        self.send_ack(nic)?;
+        Ok(())
+    }
 
-struct SendSequenceSpace {
-    /// send unacknowledged
-    una: u32,
-    /// send next
-    nxt: u32,
-    /// send window
-    wnd: u16,
-    /// send urgent pointer
-    up: bool,
-    /// segment sequence number used for last window update
-    wl1: usize,
-    /// segment acknowledgment number used for last window update
-    wl2: usize,
-    /// initial send sequence number
-    iss: u32,
-}
+    /// The addresses and ports of the segments we send.
+    fn flow(&self) -> Flow {
+        (
+            (self.ip.source.into(), self.ports.0),
+            (self.ip.destination.into(), self.ports.1),
+        )
+    }
 
-/// State of Receive Sequence Space (RFC793 S3.2 F5)
-///
-///
-///                       1          2          3
-///                   ----------|----------|----------
-///                          RCV.NXT    RCV.NXT
-///                                    +RCV.WND
-///
-///        1 - old sequence numbers which have been acknowledged
-///        2 - sequence numbers allowed for new reception
-///        3 - future sequence numbers which are not yet allowed
+    /// Update SND.WND from an acceptable ACK, unless it is older than the one we last took the
+    /// window from (RFC 793 p.72).
+    fn update_send_window(&mut self, tcph: &etherparse::TcpHeaderSlice<'_>) {
+        if self.is_window_update(tcph) {
+            self.set_send_window(tcph);
+        }
+    }
 
-struct RecvSequenceSpace {
-    // receive next
-    nxt: u32,
-    // receive window
-    wnd: u16,
-    // receive urgent pointer
-    up: bool,
-    // initial receive sequence number
-    irs: u32,
-}
+    /// Whether the window in an acceptable ACK is newer than the one we last took.
+    fn is_window_update(&self, tcph: &etherparse::TcpHeaderSlice<'_>) -> bool {
+        let seqn = tcph.sequence_number();
+        let ackn = tcph.acknowledgment_number();
+        wrapping_lt(self.send.wl1, seqn)
+            || (self.send.wl1 == seqn && !wrapping_lt(ackn, self.send.wl2))
+    }
 
-impl Connection {
-    pub fn accept<'a>(
-        nic: &mut tun_tap::Iface,
-        iph: etherparse::Ipv4HeaderSlice<'a>,
-        tcph: etherparse::TcpHeaderSlice<'a>,
-        data: &'a [u8],
-    ) -> io::Result<Option<Self>> {
-        let mut buf = [0u8; 1500];
-        if !tcph.syn() {
-            // only expected SYN packet
-            return Ok(None);
+    /// Whether an acceptable ACK would move the right edge of the send window to the left of
+    /// where the peer put it before. An edge that falls back by less than one unit of the
+    /// peer's window scale is only its rounding, not the peer taking anything back.
+    fn shrinks_window(&self, tcph: &etherparse::TcpHeaderSlice<'_>) -> bool {
+        if !tcph.ack() || !self.is_window_update(tcph) {
+            return false;
         }
+        let edge = self.send.wl2.wrapping_add(self.send.wnd);
+        let unit = 1u32 << self.snd_wscale;
+        let new_edge = tcph
+            .acknowledgment_number()
+            .wrapping_add(self.peer_window(tcph));
+        wrapping_lt(new_edge.wrapping_add(unit - 1), edge)
+    }
 
-        let iss = 0;
-        let wnd = 10;
-        let mut c = Connection {
-            state: State::SynRcvd,
-            send: SendSequenceSpace {
-                iss,
-                una: iss,
-                nxt: iss,
-                wnd: wnd,
-                up: false,
-                wl1: 0,
-                wl2: 0,
-            },
-            recv: RecvSequenceSpace {
-                irs: tcph.sequence_number(),
-                nxt: tcph.sequence_number() + 1,
-                wnd: tcph.window_size(),
-                up: false,
-            },
-            tcp: etherparse::TcpHeader::new(tcph.destination_port(), tcph.source_port(), iss, wnd),
-            ip: etherparse::Ipv4Header::new(
-                0,
-                64,
-                etherparse::IpTrafficClass::Tcp,
-                [
-                    iph.destination()[0],
-                    iph.destination()[1],
-                    iph.destination()[2],
-                    iph.destination()[3],
-                ],
-                [
-                    iph.source()[0],
-                    iph.source()[1],
-                    iph.source()[2],
-                    iph.source()[3],
-                ],
-            ),
-        };
+    /// Take the segment as the one the window came from, but with the right edge where it was.
+    fn keep_right_edge(&mut self, tcph: &etherparse::TcpHeaderSlice<'_>) {
+        let edge = self.send.wl2.wrapping_add(self.send.wnd);
+        self.send.wnd = edge.wrapping_sub(tcph.acknowledgment_number());
+        self.send.wl1 = tcph.sequence_number();
+        self.send.wl2 = tcph.acknowledgment_number();
+    }
+
+    /// Take SND.WND from the segment, and remember it as the one the window came from.
+    fn set_send_window(&mut self, tcph: &etherparse::TcpHeaderSlice<'_>) {
+        self.send.wnd = self.peer_window(tcph);
+        self.send.wl1 = tcph.sequence_number();
+        self.send.wl2 = tcph.acknowledgment_number();
+        if self.send.wnd == 0 {
+            let now = self.clock.now();
+            self.timers.zero_window_since.get_or_insert(now);
+        } else {
+            self.timers.zero_window_since = None;
+            self.timers.probes = 0;
+        }
+    }
+
+    /// The window a segment from the peer advertises, in bytes.
+    fn peer_window(&self, tcph: &etherparse::TcpHeaderSlice<'_>) -> u32 {
+        if tcph.syn() {
+            tcph.window_size() as u32
+        } else {
+            (tcph.window_size() as u32) << self.snd_wscale
+        }
+    }
 
-        // need to start establishing a connection
-        self.tcp.syn = true;
-        self.tcp.ack = true;
-        c.write(nic, &[])?;
-        Ok(Some(c))
+    /// Take on what the handshake settles, now that the peer's SYN (or SYN-ACK) is in.
+    fn negotiate(&mut self, syn: &etherparse::TcpHeaderSlice<'_>) {
+        self.handshake.peer = Some(SynOptions::parse(syn.options()));
+        let negotiated = self.handshake.negotiated(self.md5_key.is_some());
+        self.mss = negotiated.mss;
+        (self.rcv_wscale, self.snd_wscale) = negotiated.wscale.unwrap_or((0, 0));
+        self.sack = negotiated.sack;
+        self.timestamps = negotiated.timestamps;
+        self.ts_recent = self
+            .handshake
+            .peer
+            .and_then(|peer| peer.timestamp)
+            .unwrap_or(0);
+        self.ts_recent_at = self.clock.now();
+        self.rcv_buffer = cmp::min(self.rcv_buffer, self.rcv_buffer_limit());
+        self.cc.cwnd = initial_window(self.mss, self.config.initial_cwnd_segments);
     }
 
-    fn write(&mut self, nic: &mut tun_tap::Iface, payload: &[u8]) -> io::Result<usize> {
-        let mut buf = [0u8; 1500];
-        self.tcp.sequence_number = self.send.nxt;
-        self.tcp.acknowledgment_number = self.recv.nxt;
+    /// Our FIN went first, or crossed the peer's, and the exchange of FINs is complete.
+    fn enter_time_wait(&mut self) {
+        self.state = State::TimeWait;
+        self.set_close_reason(CloseReason::Local);
+        self.time_wait_start = Some(self.clock.now());
+    }
 
-        let size = std::cmp::min(
-            buf.len(),
-            self.tcp.header_len() + self.ip.header_len() as usize + payload.len(),
-        );
-        self.ip.set_payload_len(size);
-
-        // the kernal is nice and does this for us
-        // self.tcp.checksum = self.tcp
-        //    .calc_checksum_ipv4(&c.ip, &[])
-        //    .expect("faild to compute checksum");
-
-        // write out the headers
-        use std::io::Write;
-        let mut unwritten = &mut buf[..];
-        self.ip.write(&mut unwritten);
-        self.tcp.write(&mut unwritten);
-        let payload_bytes = unwritten.write(payload)?;
-        let unwitten = unwitten.len();
-        self.send.nxt.wrapping_add(payload_bytes as u32);
-        if self.tcp.syn {
-            self.send.nxt.wrapping_add(1);
-            self.tcp.syn = false;
-        }
-        if self.tcp.fin {
-            self.send.nxt.wrapping_add(1);
-            self.tcp.fin = false;
-        }
-
-        nic.send(&bufi[..buf.len() - unwritten])?;
-        Ok(payload_bytes);
-    }
-
-    fn send_rst<'a>(&mut self, nic: &mut tun_tap::Iface) -> io::Result<()> {
-        self.tcp.rst = true;
-        // TODO: fix sequence numbers here
-        // If the incoming segment has an ACK field, the reset takes its
-        // sequence number from the ACK field of the segment, otherwise the
-        // reset has sequence number zero and the ACK field is set to the sum
-        // of the sequence number and segment length of the incoming segment.
-        // The connection remains in the same state.
-        //
-        // TODO: handle synchronized RST
-        //  3.  If the connection is in a synchronized state (ESTABLISHED,
-        // FIN-WAIT-1, FIN-WAIT-2, CLOSE-WAIT, CLOSING, LAST-ACK, TIME-WAIT),
-        // any unacceptable segment (out of window sequence number or
-        // unacceptible acknowledgment number) must elicit only an empty
-        // acknowledgment segment containing the current send-sequence number
-        // and an acknowledgment indicating the next sequence number expected
-        // to be received, and the connection remains in the same state.
-        self.tcp.sequence_number = 0;
-        self.tcp.acknowledgment_number = 0;
-        self.write(nic, &[])?;
-        Ok(());
-    }
-
-    pub fn on_packet<'a>(
+    /// Segment processing for a connection in SYN-SENT (RFC 793 p.66).
+    fn on_syn_sent_packet<'a>(
         &mut self,
-        nic: &mut tun_tap::Iface,
-        iph: etherparse::Ipv4HeaderSlice<'a>,
+        nic: &mut Outbound,
+        limits: &mut ReplyLimits,
         tcph: etherparse::TcpHeaderSlice<'a>,
-        data: &'a [u8],
     ) -> io::Result<()> {
-        // first, check that sequence numbers are valid (RFC 793 s3.3)
-        //
-        // acceptable ack check
-        // SND.UNA < SEG.ACK =< SND.NXT
-        // but remember wrapping!
-        //
         let ackn = tcph.acknowledgment_number();
-        if !is_between_wrapped(self.send.una, ackn, self.send.nxt.wrapping_add(1)) {
-            if !self.state.is_synchronized() {
-                //accourding top Reset generation, we should send a
-                self.send_rst(nic);
+        if tcph.ack() && !is_between_wrapped(self.send.iss, ackn, self.send.nxt.wrapping_add(1)) {
+            // not an ACK of our SYN (and of the data with it, if any)
+            self.discard(DropReason::OldAck);
+            if !tcph.rst() && limits.allow_rst(self.clock.now()) {
+                self.send_rst(nic, ackn, false)?;
             }
             return Ok(());
         }
-        // If the data flow is momentarily idle and all data
-        //sent has been acknowledged then the three variables will be equal
-        self.send.una = ackn;
+        if tcph.rst() {
+            if tcph.ack() {
+                // the handshake failed; anything the application queued up fails with it
+                self.error = Some(io::ErrorKind::ConnectionRefused);
+                self.state = State::Closed;
+                self.set_close_reason(CloseReason::Refused);
+                self.unacked.clear();
+            } else {
+                self.discard(DropReason::BadRst);
+            }
+            return Ok(());
+        }
+        if !tcph.syn() {
+            self.discard(DropReason::NoSyn);
+            return Ok(());
+        }
 
-        //
-        // valid segment check. okay if it acks at least one byte, which means that at least one of
-        // the following is true:
-        //
-        // RCV.NXT =< SEG.SEQ < RCV.NXT+RCV.WND
-        // RCV.NXT =< SEG.SEQ+SEG.LEN-1 < RCV.NXT+RCV.WND
-        //
-        let seqn = tcph.sequence_number();
-        let mut slen = data.len() as u32;
-        if tcph.fin() {
-            slen += 1;
+        self.recv.irs = tcph.sequence_number();
+        self.recv.nxt = tcph.sequence_number().wrapping_add(1);
+        self.acking = true;
+        self.rcv_adv = self.recv.nxt;
+        // only what the SYN-ACK echoes of our offer takes effect, down to nothing but the
+        // default MSS if it carries no options at all; whatever it has that we did not offer
+        // is ignored
+        self.negotiate(&tcph);
+        // the window in a SYN is never scaled (RFC 7323 S2.2), whatever the options settle, and
+        // one closed from the start is timed from here
+        self.set_send_window(&tcph);
+        if !tcph.ack() {
+            // a simultaneous open: the peer connected to us just as we did to it. answer its SYN
+            // with a SYN-ACK of our own, repeating our ISS, and wait for its ACK of our SYN as a
+            // listener would (RFC 793 p.32). data in either SYN is left for the sender to
+            // resend once the handshake is done.
+            self.state = State::SynRcvd;
+            self.emit(nic, self.syn_flags(), self.send.iss, 0)?;
+            return Ok(());
+        }
+        if self.handshake.ours.fast_open.is_some() {
+            self.fast_open_cookie = self
+                .handshake
+                .peer
+                .and_then(|peer| peer.fast_open)
+                .filter(|cookie| !cookie.is_request());
+        }
+
+        self.on_ack(ackn);
+        if wrapping_lt(ackn, self.send.nxt) {
+            // the server did not take (all of) the data in our SYN; it goes again right away
+            // (RFC 7413 S4.2.2)
+            self.go_back_n();
+        } else if ackn != self.send.iss.wrapping_add(1) {
+            self.fast_open = true;
+        }
+        self.state = if self.closed {
+            State::FinWait1
+        } else {
+            State::Estab
         };
-        if tcph.syn() {
-            slen += 1;
+
+        // writes made while the handshake was in flight go out right away. either the first of
+        // those segments doubles as the ACK of their SYN, or we send a bare ACK first.
+        if self.unacked.is_empty() || !self.config.piggyback_handshake_data {
+            self.send_ack(nic)?;
+        }
+        self.flush(nic)
+    }
+
+    /// Segment processing for a connection in TIME-WAIT.
+    ///
+    /// There are five kinds of segment we can see here:
+    ///
+    ///  - a retransmission of the peer's FIN, meaning our final ACK was lost: ACK it again and
+    ///    restart the 2MSL timer (RFC 793 p.73).
+    ///  - a retransmission of data from before the FIN, for the same reason: the peer goes back
+    ///    to SND.UNA when it times out, and only gets to its FIN once what comes before is
+    ///    acknowledged, so ACK it again too (as RFC 793 p.69 does for any old duplicate), but
+    ///    leave the timer be.
+    ///  - an RST: ignored, so that a stray or forged reset cannot cut TIME-WAIT short and let an
+    ///    old duplicate into a new incarnation of the connection (RFC 1337, "TIME-WAIT
+    ///    assassination").
+    ///  - a SYN for a new incarnation: with [`TcpConfig::time_wait_reuse`], one that
+    ///    [`admits_new_syn`](Self::admits_new_syn) ends TIME-WAIT early and is handed to the
+    ///    listener as a new connection (RFC 6191); otherwise it is dropped like the rest.
+    ///  - anything else: an old duplicate, dropped.
+    fn on_time_wait_packet<'a>(
+        &mut self,
+        nic: &mut Outbound,
+        tcph: etherparse::TcpHeaderSlice<'a>,
+        data: &[u8],
+    ) -> io::Result<()> {
+        let seg_end = tcph
+            .sequence_number()
+            .wrapping_add(data.len() as u32 + tcph.fin() as u32);
+        if tcph.fin() && !tcph.rst() && !tcph.syn() && seg_end == self.recv.nxt {
+            self.send_ack(nic)?;
+            self.time_wait_start = Some(self.clock.now());
+        } else if !data.is_empty()
+            && !tcph.rst()
+            && !tcph.syn()
+            && !wrapping_lt(self.recv.nxt, seg_end)
+        {
+            self.send_ack(nic)?;
+            self.discard(DropReason::TimeWait);
+        } else if self.admits_new_syn(&tcph) {
+            self.reopen = true;
+        } else {
+            self.discard(DropReason::TimeWait);
+        }
+        Ok(())
+    }
+
+    /// Whether a SYN in TIME-WAIT may end it for a new connection on the quad: with
+    /// [`TcpConfig::time_wait_reuse`], one past RCV.NXT whose timestamp is newer than
+    /// TS.Recent, so that nothing of the old connection can pass for the new one's (RFC 6191
+    /// S2).
+    fn admits_new_syn(&self, tcph: &etherparse::TcpHeaderSlice<'_>) -> bool {
+        self.config.time_wait_reuse
+            && self.timestamps
+            && tcph.syn()
+            && !tcph.ack()
+            && !tcph.rst()
+            && wrapping_lt(self.recv.nxt, tcph.sequence_number())
+            && segment_timestamps(tcph)
+                .is_some_and(|(ts_val, _)| wrapping_lt(self.ts_recent, ts_val))
+    }
+}
+
+/// Picks initial sequence numbers as RFC 6528 does: a clock ticking every 4 microseconds, plus
+/// a keyed hash of the connection's addresses and ports. The ISSs of one quad keep increasing,
+/// but nobody without the key can tell what they are.
+///
+/// The clock comes round every 4.77 hours; should twice the MSL be longer than that, it ticks
+/// more slowly, so that a quad's ISSs cannot repeat while its old segments may be around.
+pub(crate) struct IssGenerator {
+    secret: [u8; 16],
+    /// when the clock started ticking
+    epoch: Instant,
+    /// how long each tick of the clock takes, in nanoseconds
+    tick: u128,
+    /// the ISS of every connection instead, if the interface was built with one
+    fixed: Option<u32>,
+}
+
+impl IssGenerator {
+    pub(crate) fn new(epoch: Instant, msl: Duration) -> Self {
+        IssGenerator {
+            secret: random_secret(),
+            epoch,
+            tick: cmp::max(4_000, (2 * msl).as_nanos().div_ceil(1 << 32)),
+            fixed: None,
+        }
+    }
+
+    /// Have every connection start at `iss` instead.
+    pub(crate) fn fix(&mut self, iss: u32) {
+        self.fixed = Some(iss);
+    }
+
+    /// The ISS for a connection from `local` to `remote` opened at `now`.
+    pub(crate) fn generate(
+        &self,
+        local: (Ipv4Addr, u16),
+        remote: (Ipv4Addr, u16),
+        now: Instant,
+    ) -> u32 {
+        if let Some(iss) = self.fixed {
+            return iss;
+        }
+        let ticks = (now.saturating_duration_since(self.epoch).as_nanos() / self.tick) as u32;
+        let mut md5 = Md5::new();
+        md5.update(&local.0.octets());
+        md5.update(&local.1.to_be_bytes());
+        md5.update(&remote.0.octets());
+        md5.update(&remote.1.to_be_bytes());
+        md5.update(&self.secret);
+        let [a, b, c, d, ..] = md5.finish();
+        ticks.wrapping_add(u32::from_be_bytes([a, b, c, d]))
+    }
+
+    /// The clock the TSvals of connections from `local` to `remote` come from: the same for
+    /// every one of them, so that a new connection's TSvals carry on past the last one's. Its
+    /// offset is a keyed hash of the quad, like the ISS's, or 0 if the ISS is fixed.
+    pub(crate) fn ts_clock(&self, local: (Ipv4Addr, u16), remote: (Ipv4Addr, u16)) -> TsClock {
+        let offset = if self.fixed.is_some() {
+            0
+        } else {
+            let mut md5 = Md5::new();
+            md5.update(b"timestamps");
+            md5.update(&local.0.octets());
+            md5.update(&local.1.to_be_bytes());
+            md5.update(&remote.0.octets());
+            md5.update(&remote.1.to_be_bytes());
+            md5.update(&self.secret);
+            let [a, b, c, d, ..] = md5.finish();
+            u32::from_be_bytes([a, b, c, d])
         };
-        let wend = self.recv.nxt.wrapping_add(self.recv.wnd as u32);
-        if slen == 0 {
-            // zero-length segment has separate rules for acceptance
-            if self.recv.wnd == 0 {
-                if seqn != self.recv.nxt {
-                    return Ok(());
-                }
-            } else {
-                if !is_between_wrapped(self.recv.nxt.wrapping_sub(1), seqn, wend) {
-                    return Ok(());
-                }
-            }
+        TsClock {
+            epoch: self.epoch,
+            offset,
+        }
+    }
+}
+
+/// The clock our TSvals are read off (RFC 7323 S5.4): a millisecond tick, from an offset that
+/// keeps how long the interface has been up to ourselves (RFC 7323 S7.1).
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TsClock {
+    epoch: Instant,
+    offset: u32,
+}
+
+impl TsClock {
+    /// The TSval for a segment sent at `now`.
+    fn read(&self, now: Instant) -> u32 {
+        let ms = now.saturating_duration_since(self.epoch).as_millis() as u32;
+        self.offset.wrapping_add(ms)
+    }
+}
+
+/// A key for hashes that nobody outside should be able to work out.
+pub(crate) fn random_secret() -> [u8; 16] {
+    let mut secret = [0u8; 16];
+    let n = unsafe { libc::getrandom(secret.as_mut_ptr() as *mut libc::c_void, secret.len(), 0) };
+    if n != secret.len() as isize {
+        // no randomness to be had; the hashes still spread their inputs apart, if predictably
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        secret = (now.as_nanos() ^ std::process::id() as u128).to_le_bytes();
+    }
+    secret
+}
+
+/// Where an earlier connection on a quad left its sequence numbers, kept for 2MSL after it
+/// closed so that a new connection on the quad cannot take in its delayed segments.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Incarnation {
+    snd_nxt: u32,
+    rcv_nxt: u32,
+    /// TS.Recent, if the connection had timestamps
+    ts_recent: Option<u32>,
+    /// when the connection's last segments are gone from the network
+    until: Instant,
+}
+
+impl Incarnation {
+    /// Whether old segments of the connection can still be around at `now`.
+    pub(crate) fn is_over(&self, now: Instant) -> bool {
+        now >= self.until
+    }
+
+    /// Whether the peer's SYN for a new connection is no old duplicate: one that starts past
+    /// everything the peer sent on the old connection (RFC 1122 S4.2.2.13), or, if both SYNs
+    /// carry timestamps, one with a newer timestamp than the old connection's last, wherever
+    /// it starts (RFC 6191 S2).
+    pub(crate) fn admits_syn(&self, syn: &etherparse::TcpHeaderSlice<'_>) -> bool {
+        match self.ts_recent.zip(segment_timestamps(syn)) {
+            Some((recent, (ts_val, _))) => wrapping_lt(recent, ts_val),
+            None => !wrapping_lt(syn.sequence_number(), self.rcv_nxt),
+        }
+    }
+
+    /// `iss` if it lies past everything we sent on the old connection, so that the peer's
+    /// old ACKs cannot pass for new ones; otherwise one a window further on, as Linux picks.
+    pub(crate) fn iss_after(&self, iss: u32) -> u32 {
+        if wrapping_lt(self.snd_nxt, iss) {
+            iss
         } else {
-            if self.recv.wnd == 0 {
-                return Ok(());
-            } else if !is_between_wrapped(self.recv.nxt.wrapping_sub(1), seqn, wend)
-                && !is_between_wrapped(
-                    self.recv.nxt.wrapping_sub(1),
-                    seqn.wrapping_add(slen - 1),
-                    wend,
-                )
-            {
-                return Ok(());
-            }
+            self.snd_nxt.wrapping_add(u16::MAX as u32 + 2)
         }
+    }
+}
 
-        // The sender of data keeps track of the next sequence number to use in
-        // the variable SND.NXT
-        self.recv.nxt = seqn.wrapping_add(slen);
+/// The congestion window to start out with, `segments` segments of `mss` bytes as RFC 6928 S2
+/// counts them: min(10*MSS, max(2*MSS, 14600)) for 10.
+fn initial_window(mss: u16, segments: u32) -> u32 {
+    let mss = mss as u32;
+    cmp::min(
+        segments.saturating_mul(mss),
+        cmp::max(2 * mss, segments.saturating_mul(1460)),
+    )
+}
 
-        // TODO: make sure this
+/// The options of a SYN (or SYN-ACK) that the handshake settles.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct SynOptions {
+    mss: Option<u16>,
+    wscale: Option<u8>,
+    sack_permitted: bool,
+    /// TSval, if the timestamps option is there
+    timestamp: Option<u32>,
+    /// a TCP Fast Open cookie, or the request for one
+    fast_open: Option<Cookie>,
+}
 
-        match self.state {
-            State::SynRcvd => {
-                // expect to get an ACK for our SYN
-                if !tcph.ack() {
-                    return Ok(());
+impl SynOptions {
+    fn parse(raw: &[u8]) -> Self {
+        let mut syn = SynOptions::default();
+        for (kind, value) in options(raw) {
+            match (kind, value) {
+                (OPTION_MSS, &[hi, lo]) => {
+                    syn.mss = Some(cmp::max(u16::from_be_bytes([hi, lo]), MIN_MSS))
                 }
-                // must have ACKed our SYN, since we detected at least one acked byte, and we have
-                // only sent one byte (the SYN).
-                self.state = State::Estab;
+                // a larger shift is taken as the largest there is (RFC 7323 S2.3)
+                (OPTION_WSCALE, &[shift]) => syn.wscale = Some(cmp::min(shift, MAX_WSCALE)),
+                (OPTION_SACK_PERMITTED, &[]) => syn.sack_permitted = true,
+                (OPTION_TIMESTAMPS, &[a, b, c, d, ..]) if value.len() == 8 => {
+                    syn.timestamp = Some(u32::from_be_bytes([a, b, c, d]))
+                }
+                (OPTION_FAST_OPEN, value) => syn.fast_open = Cookie::from_option(value),
+                _ => {}
+            }
+        }
+        syn
+    }
+
+    /// How much room the options take up in the header, as [`SynOptions::write`] lays them
+    /// out.
+    fn len(&self) -> usize {
+        let mut len = 0;
+        if self.mss.is_some() {
+            len += 4;
+        }
+        if self.wscale.is_some() {
+            len += 4;
+        }
+        // SACK-permitted fills the padding in front of the timestamps, if they are there
+        match (self.sack_permitted, self.timestamp.is_some()) {
+            (_, true) => len += 12,
+            (true, false) => len += 4,
+            (false, false) => {}
+        }
+        if let Some(cookie) = self.fast_open {
+            // padded out to a multiple of four in front
+            len += (cookie.as_bytes().len() + 2).next_multiple_of(4);
+        }
+        len
+    }
 
-                // now let's terminate the connection!
-                // TODO: needs to be stored in the retransmission queue!
-                self.tcp.fin = true;
-                self.write(nic, &[])?;
-                self.state = State::FinWait1;
+    /// Lay the options out at the start of `out`, echoing `ts_ecr` in the timestamps, and
+    /// return how much of it they take up.
+    fn write(&self, ts_ecr: u32, out: &mut [u8]) -> usize {
+        let mut len = 0;
+        let mut put = |bytes: &[u8]| {
+            out[len..len + bytes.len()].copy_from_slice(bytes);
+            len += bytes.len();
+        };
+        if let Some(mss) = self.mss {
+            let [hi, lo] = mss.to_be_bytes();
+            put(&[OPTION_MSS, 4, hi, lo]);
+        }
+        if let Some(shift) = self.wscale {
+            put(&[OPTION_NOP, OPTION_WSCALE, 3, shift]);
+        }
+        match (self.sack_permitted, self.timestamp) {
+            (sack_permitted, Some(ts_val)) => {
+                if sack_permitted {
+                    put(&[OPTION_SACK_PERMITTED, 2]);
+                } else {
+                    put(&[OPTION_NOP, OPTION_NOP]);
+                }
+                put(&[OPTION_TIMESTAMPS, 10]);
+                put(&ts_val.to_be_bytes());
+                put(&ts_ecr.to_be_bytes());
             }
-            State::Estab => {
-                unimplemented!();
+            (true, None) => put(&[OPTION_NOP, OPTION_NOP, OPTION_SACK_PERMITTED, 2]),
+            (false, None) => {}
+        }
+        if let Some(cookie) = self.fast_open {
+            let cookie = cookie.as_bytes();
+            if (2 + cookie.len()) % 4 != 0 {
+                put(&[OPTION_NOP, OPTION_NOP]);
             }
-            State::FinWait1 => {
-                if !tcph.fin() || !data.is_empty() {
-                    unimplemented!();
-                }
+            put(&[OPTION_FAST_OPEN, 2 + cookie.len() as u8]);
+            put(cookie);
+        }
+        len
+    }
+}
 
-                // must have ACKed our SYN, since we detected at least one acked byte, and we have
-                // only sent one byte (the FIN).
-                self.state = State::FinWait2;
+/// Settles the options of a connection from the two SYNs of its handshake, the same way whether
+/// we sent the first SYN or the peer did.
+///
+/// Window scaling, SACK and timestamps are only in effect if both SYNs carry them, and a SYN-ACK
+/// only carries those the peer's SYN did (RFC 7323 S1.3, RFC 2018 S2).
+#[derive(Clone, Copy, Debug)]
+struct Handshake {
+    /// what we would put in our SYN, if it all fit
+    ours: SynOptions,
+    /// what the peer's SYN carried, once it is in
+    peer: Option<SynOptions>,
+}
+
+/// The outcome of a [`Handshake`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Negotiated {
+    /// the largest segment to send the peer
+    mss: u16,
+    /// the shifts for the windows we advertise and those the peer does, if scaling is on
+    wscale: Option<(u8, u8)>,
+    sack: bool,
+    timestamps: bool,
+}
+
+impl Handshake {
+    /// What we offer in our SYN: as much of `ours` as fits alongside an MD5 signature, if the
+    /// connection is signed. Options go in order of how little they matter: Fast Open first,
+    /// then timestamps, SACK-permitted and window scaling; the MSS always stays.
+    fn offered(&self, md5: bool) -> SynOptions {
+        let room = MAX_OPTIONS_LEN - if md5 { MD5_OPTION_LEN } else { 0 };
+        let mut offer = self.ours;
+        let least_first: [fn(&mut SynOptions); 4] = [
+            |o| o.fast_open = None,
+            |o| o.timestamp = None,
+            |o| o.sack_permitted = false,
+            |o| o.wscale = None,
+        ];
+        for leave_out in least_first {
+            if offer.len() <= room {
+                break;
             }
-            State::Closing => {
-                if !tcph.fin() || !data.is_empty() {
-                    unimplemented!();
-                }
-                // must have ACKed our SYN, since we detected at least one acked byte, and we have
-                // only sent one byte (the FIN).
-                self.tcp.fin = false;
-                self.write(nic, &[])?;
-                self.state = State::Closing;
+            leave_out(&mut offer);
+        }
+        offer
+    }
+
+    /// The options that go in our SYN, or in our SYN-ACK once the peer's SYN is in.
+    fn options(&self, md5: bool) -> SynOptions {
+        let offer = self.offered(md5);
+        match self.peer {
+            None => offer,
+            Some(peer) => SynOptions {
+                mss: offer.mss,
+                wscale: offer.wscale.filter(|_| peer.wscale.is_some()),
+                sack_permitted: offer.sack_permitted && peer.sack_permitted,
+                timestamp: offer.timestamp.filter(|_| peer.timestamp.is_some()),
+                fast_open: offer.fast_open.filter(|_| peer.fast_open.is_some()),
+            },
+        }
+    }
+
+    /// Write the options for our SYN (or SYN-ACK) into `out`, with `ts_val` for the TSval,
+    /// leaving room for an MD5 signature if `md5` is set, and return how much of it they take
+    /// up.
+    fn write_options(&self, md5: bool, ts_val: u32, out: &mut [u8]) -> usize {
+        let ts_ecr = self.peer.and_then(|peer| peer.timestamp).unwrap_or(0);
+        let mut options = self.options(md5);
+        options.timestamp = options.timestamp.map(|_| ts_val);
+        options.write(ts_ecr, out)
+    }
+
+    /// What is in effect, going by what we offered and what the peer's SYN carried. Before the
+    /// peer's SYN is in, that is nothing beyond the MSS to assume.
+    fn negotiated(&self, md5: bool) -> Negotiated {
+        let offer = self.offered(md5);
+        let peer = self.peer.unwrap_or_default();
+        // the MSS is no negotiation: each side just says what it can take (RFC 1122 S4.2.2.6)
+        let mss = cmp::min(
+            peer.mss.unwrap_or(DEFAULT_MSS),
+            offer.mss.unwrap_or(DEFAULT_MSS),
+        );
+        Negotiated {
+            mss,
+            wscale: offer.wscale.zip(peer.wscale),
+            sack: offer.sack_permitted && peer.sack_permitted,
+            timestamps: offer.timestamp.is_some() && peer.timestamp.is_some(),
+        }
+    }
+}
+
+/// The (kind, value) pairs in a TCP header's options, up to the end of the list or the first
+/// malformed one. Unlike etherparse's iterator, this carries on past kinds it doesn't know.
+fn options(mut raw: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    std::iter::from_fn(move || loop {
+        match *raw {
+            [] | [OPTION_END, ..] => return None,
+            [OPTION_NOP, ref rest @ ..] => raw = rest,
+            [kind, len, ..] if len >= 2 && raw.len() >= len as usize => {
+                let value = &raw[2..len as usize];
+                raw = &raw[len as usize..];
+                return Some((kind, value));
             }
+            _ => return None,
+        }
+    })
+}
+
+/// The TCP Fast Open cookie (or request for one) in a SYN, if it carries the option.
+pub(crate) fn fast_open_cookie(tcph: &etherparse::TcpHeaderSlice<'_>) -> Option<Cookie> {
+    SynOptions::parse(tcph.options()).fast_open
+}
+
+/// The (TSval, TSecr) of a segment, if it carries the timestamps option.
+fn segment_timestamps(tcph: &etherparse::TcpHeaderSlice<'_>) -> Option<(u32, u32)> {
+    options(tcph.options()).find_map(|(kind, value)| match (kind, value) {
+        (OPTION_TIMESTAMPS, &[a, b, c, d, e, f, g, h]) => Some((
+            u32::from_be_bytes([a, b, c, d]),
+            u32::from_be_bytes([e, f, g, h]),
+        )),
+        _ => None,
+    })
+}
+
+/// Answer a segment for which there is no connection, nor a listener to take one, with an RST
+/// (RFC 793 p.36, "If the connection does not exist"): one that acknowledges something is
+/// reset from the sequence number it acknowledges, and one that does not is acknowledged,
+/// with the reset itself at sequence number 0. An RST is never answered.
+pub(crate) fn reset_unknown(
+    nic: &mut Outbound,
+    iph: &etherparse::Ipv4HeaderSlice<'_>,
+    tcph: &etherparse::TcpHeaderSlice<'_>,
+    data_len: usize,
+) -> io::Result<()> {
+    if tcph.rst() {
+        return Ok(());
+    }
+    let (seq, ack) = if tcph.ack() {
+        (tcph.acknowledgment_number(), None)
+    } else {
+        let len = data_len as u32 + tcph.syn() as u32 + tcph.fin() as u32;
+        (0, Some(tcph.sequence_number().wrapping_add(len)))
+    };
+    let mut tcp = etherparse::TcpHeader::new(tcph.destination_port(), tcph.source_port(), seq, 0);
+    tcp.rst = true;
+    if let Some(ack) = ack {
+        tcp.ack = true;
+        tcp.acknowledgment_number = ack;
+    }
+    let mut ip = etherparse::Ipv4Header::new(
+        tcp.header_len(),
+        64,
+        etherparse::IpTrafficClass::Tcp,
+        iph.destination_addr().octets(),
+        iph.source_addr().octets(),
+    );
+    tcp.checksum = tcp
+        .calc_checksum_ipv4(&ip, &[])
+        .expect("an empty segment fits in an ip packet");
+    ip.set_payload_len(tcp.header_len() as usize)
+        .expect("an empty segment fits in an ip packet");
+    let mut packet = Vec::with_capacity(HEADERS_LEN);
+    ip.write(&mut packet)
+        .map_err(|e| io::Error::other(format!("{:?}", e)))?;
+    tcp.write(&mut packet)?;
+    nic.send(packet, Priority::Normal, true, None)?;
+    Ok(())
+}
+
+/// Whether a segment is signed as it should be: with `key` if there is one (RFC 2385 S4.0),
+/// and not at all otherwise.
+pub(crate) fn md5_valid(
+    iph: &etherparse::Ipv4HeaderSlice<'_>,
+    tcph: &etherparse::TcpHeaderSlice<'_>,
+    data: &[u8],
+    key: Option<&[u8]>,
+) -> bool {
+    let signature = options(tcph.options()).find_map(|(kind, value)| match kind {
+        OPTION_MD5 => Some(value),
+        _ => None,
+    });
+    match (key, signature) {
+        (None, None) => true,
+        (Some(key), Some(signature)) => {
+            let tcp_len = tcph.slice().len() + data.len();
+            let expected = md5_signature(
+                iph.source_addr().octets(),
+                iph.destination_addr().octets(),
+                &tcph.slice()[..20],
+                tcp_len as u16,
+                data,
+                key,
+            );
+            signature == expected
+        }
+        _ => false,
+    }
+}
+
+/// The MD5 digest a segment is signed with (RFC 2385 S2.0), over the pseudo-header, the fixed
+/// part of the TCP `header` with its checksum taken as zero, the data and the key.
+fn md5_signature(
+    src: [u8; 4],
+    dst: [u8; 4],
+    header: &[u8],
+    tcp_len: u16,
+    data: &[u8],
+    key: &[u8],
+) -> [u8; 16] {
+    let mut md5 = Md5::new();
+    md5.update(&src);
+    md5.update(&dst);
+    md5.update(&[0, 6]);
+    md5.update(&tcp_len.to_be_bytes());
+    md5.update(&header[..16]);
+    md5.update(&[0, 0]);
+    md5.update(&header[18..20]);
+    md5.update(data);
+    md5.update(key);
+    md5.finish()
+}
+
+/// Whether a segment is acceptable in the receive window, and if so, which of its sequence
+/// numbers lie inside the window.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Acceptance {
+    Unacceptable,
+    /// the in-window sequence numbers; empty for an acceptable segment without any
+    Acceptable(Range<u32>),
+}
+
+/// The segment acceptance test (RFC 793 p.69) for a segment occupying `seg_len` sequence numbers
+/// from `seg_seq`, against a receive window of `rcv_wnd` from `rcv_nxt`:
+///
+/// ```text
+///     Segment Receive  Test
+///     Length  Window
+///     ------- -------  -------------------------------------------
+///        0       0     SEG.SEQ = RCV.NXT
+///        0      >0     RCV.NXT =< SEG.SEQ < RCV.NXT+RCV.WND
+///       >0       0     not acceptable
+///       >0      >0     RCV.NXT =< SEG.SEQ < RCV.NXT+RCV.WND
+///                   or RCV.NXT =< SEG.SEQ+SEG.LEN-1 < RCV.NXT+RCV.WND
+/// ```
+fn segment_acceptable(rcv_nxt: u32, rcv_wnd: u32, seg_seq: u32, seg_len: u32) -> Acceptance {
+    // RCV.NXT =< x < RCV.NXT+RCV.WND, modulo 2^32
+    let in_window = |x: u32| x.wrapping_sub(rcv_nxt) < rcv_wnd;
+    let seg_end = seg_seq.wrapping_add(seg_len);
+
+    if seg_len == 0 {
+        if seg_seq == rcv_nxt || in_window(seg_seq) {
+            return Acceptance::Acceptable(seg_seq..seg_seq);
         }
+        return Acceptance::Unacceptable;
+    }
 
-        Ok(());
+    let first_in = in_window(seg_seq);
+    let last_in = in_window(seg_end.wrapping_sub(1));
+    if !first_in && !last_in {
+        return Acceptance::Unacceptable;
     }
+    let start = if first_in { seg_seq } else { rcv_nxt };
+    let end = if last_in {
+        seg_end
+    } else {
+        rcv_nxt.wrapping_add(rcv_wnd)
+    };
+    Acceptance::Acceptable(start..end)
+}
+
+fn wrapping_lt(lhs: u32, rhs: u32) -> bool {
+    // From RFC1323:
+    //     TCP determines if a data segment is "old" or "new" by testing
+    //     whether its sequence number is within 2**31 bytes of the left edge
+    //     of the window, and if it is not, discarding the data as "old".  To
+    //     insure that new data is never mistakenly considered old and vice-
+    //     versa, the left edge of the sender's window has to be at most
+    //     2**31 away from the right edge of the receiver's window.
+    lhs.wrapping_sub(rhs) > (1 << 31)
 }
 
 fn is_between_wrapped(start: u32, x: u32, end: u32) -> bool {
     use std::cmp::Ordering;
-    match start.cmp(x) {
-        Ordering::Equal => false,
+    match start.cmp(&x) {
+        Ordering::Equal => return false,
         Ordering::Less => {
             // we have:
             //
@@ -375,3 +4378,179 @@ fn is_between_wrapped(start: u32, x: u32, end: u32) -> bool {
     }
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// The sequence space of the brute-force test: sequence numbers are taken modulo 64 there,
+    /// and scaled by this onto the real, modulo 2^32 one, where sums and differences come out
+    /// the same.
+    const STEP: u32 = 1 << 26;
+
+    /// The sequence numbers from `start` on, `len` of them, modulo 64, as a bit set.
+    fn span(start: u32, len: u32) -> u64 {
+        (0..len).fold(0, |set, i| set | 1 << ((start + i) % 64))
+    }
+
+    /// RFC 793's acceptance test spelled out over a sequence space of 64, with windows and
+    /// segments shorter than half of it: whether the segment passes, and if so, which of its
+    /// numbers are inside the window.
+    fn reference(rcv_nxt: u32, rcv_wnd: u32, seg_seq: u32, seg_len: u32) -> Option<u64> {
+        let window = span(rcv_nxt, rcv_wnd);
+        let inside = |x: u32| window & 1 << (x % 64) != 0;
+        let acceptable = match (seg_len, rcv_wnd) {
+            (0, 0) => seg_seq == rcv_nxt,
+            (0, _) => inside(seg_seq),
+            (_, 0) => false,
+            _ => inside(seg_seq) || inside(seg_seq + seg_len - 1),
+        };
+        acceptable.then(|| span(seg_seq, seg_len) & window)
+    }
+
+    #[test]
+    fn acceptance_matches_rfc_793_modulo_64() {
+        for rcv_nxt in 0..64 {
+            for rcv_wnd in 0..32 {
+                for seg_seq in 0..64 {
+                    for seg_len in 0..32 {
+                        let got = segment_acceptable(
+                            rcv_nxt * STEP,
+                            rcv_wnd * STEP,
+                            seg_seq * STEP,
+                            seg_len * STEP,
+                        );
+                        let got = match got {
+                            Acceptance::Unacceptable => None,
+                            Acceptance::Acceptable(r) => {
+                                assert_eq!(r.start % STEP, 0);
+                                assert_eq!(r.end % STEP, 0);
+                                if seg_len == 0 {
+                                    assert_eq!(r, seg_seq * STEP..seg_seq * STEP);
+                                }
+                                Some(span(r.start / STEP, r.end.wrapping_sub(r.start) / STEP))
+                            }
+                        };
+                        assert_eq!(
+                            got,
+                            reference(rcv_nxt, rcv_wnd, seg_seq, seg_len),
+                            "RCV.NXT={} RCV.WND={} SEG.SEQ={} SEG.LEN={}",
+                            rcv_nxt,
+                            rcv_wnd,
+                            seg_seq,
+                            seg_len
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    proptest! {
+        /// Around the wrap of the sequence space, against the same test done on unwrapped
+        /// offsets from RCV.NXT.
+        #[test]
+        fn acceptance_across_the_wrap(
+            rcv_nxt in (u32::MAX - 100_000)..=u32::MAX,
+            rcv_wnd in 0u32..70_000,
+            offset in prop_oneof![-150_000i64..150_000, -3i64..3],
+            from_window_end: bool,
+            seg_len in prop_oneof![0u32..70_000, 0u32..3],
+        ) {
+            // near either edge of the window is where an off-by-one would show
+            let offset = if from_window_end { offset + rcv_wnd as i64 } else { offset };
+            let seg_seq = (rcv_nxt as i64 + offset).rem_euclid(1 << 32) as u32;
+            let (first, last) = (offset, offset + seg_len as i64 - 1);
+            let inside = |x: i64| 0 <= x && x < rcv_wnd as i64;
+            let acceptable = match (seg_len, rcv_wnd) {
+                (0, 0) => offset == 0,
+                (0, _) => inside(offset),
+                (_, 0) => false,
+                _ => inside(first) || inside(last),
+            };
+            let expected = if !acceptable {
+                Acceptance::Unacceptable
+            } else if seg_len == 0 {
+                Acceptance::Acceptable(seg_seq..seg_seq)
+            } else {
+                let start = first.max(0);
+                let end = (last + 1).min(rcv_wnd as i64);
+                let at = |x: i64| rcv_nxt.wrapping_add(x as u32);
+                Acceptance::Acceptable(at(start)..at(end))
+            };
+            prop_assert_eq!(segment_acceptable(rcv_nxt, rcv_wnd, seg_seq, seg_len), expected);
+        }
+    }
+
+    /// Every combination of options a peer's SYN may carry, against every combination of SACK
+    /// and timestamps we may offer: each is on only if both SYNs carry it, and our SYN-ACK
+    /// carries no more than both offered, echoing the peer's TSval.
+    #[test]
+    fn negotiation_matrix() {
+        for bits in 0..16u8 {
+            let peer = SynOptions {
+                mss: (bits & 1 != 0).then_some(1200),
+                wscale: (bits & 2 != 0).then_some(7),
+                sack_permitted: bits & 4 != 0,
+                timestamp: (bits & 8 != 0).then_some(12345),
+                fast_open: None,
+            };
+            for (sack, timestamps) in [(false, false), (false, true), (true, false), (true, true)] {
+                let handshake = Handshake {
+                    ours: SynOptions {
+                        mss: Some(1460),
+                        wscale: Some(2),
+                        sack_permitted: sack,
+                        timestamp: timestamps.then_some(0),
+                        fast_open: None,
+                    },
+                    peer: Some(peer),
+                };
+                let case = format!("peer {:?}, sack {}, timestamps {}", peer, sack, timestamps);
+                let negotiated = handshake.negotiated(false);
+                assert_eq!(
+                    negotiated,
+                    Negotiated {
+                        mss: if peer.mss.is_some() {
+                            1200
+                        } else {
+                            DEFAULT_MSS
+                        },
+                        wscale: peer.wscale.map(|shift| (2, shift)),
+                        sack: sack && peer.sack_permitted,
+                        timestamps: timestamps && peer.timestamp.is_some(),
+                    },
+                    "{}",
+                    case
+                );
+
+                let mut out = [0u8; MAX_OPTIONS_LEN];
+                let len = handshake.write_options(false, 777, &mut out);
+                assert_eq!(
+                    SynOptions::parse(&out[..len]),
+                    SynOptions {
+                        mss: Some(1460),
+                        wscale: peer.wscale.map(|_| 2),
+                        sack_permitted: negotiated.sack,
+                        timestamp: negotiated.timestamps.then_some(777),
+                        fast_open: None,
+                    },
+                    "{}",
+                    case
+                );
+                let echoed = options(&out[..len]).find_map(|(kind, value)| {
+                    (kind == OPTION_TIMESTAMPS).then(|| value[4..].to_vec())
+                });
+                assert_eq!(
+                    echoed,
+                    negotiated
+                        .timestamps
+                        .then(|| 12345u32.to_be_bytes().to_vec()),
+                    "{}",
+                    case
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// How much of a segment's data a trace keeps, past its headers.
+const PAYLOAD_KEPT: usize = 64;
+
+/// Which way a traced segment went.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// A segment of a connection, as [`TcpStream::trace_dump`](crate::TcpStream::trace_dump) has
+/// it: its IPv4 and TCP headers as they were on the wire, and the first 64 bytes of its data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TracedPacket {
+    pub at: Instant,
+    pub direction: Direction,
+    /// how long the whole packet was
+    pub len: usize,
+    /// the start of the packet
+    pub bytes: Vec<u8>,
+}
+
+/// The last segments a connection sent and received, up to
+/// [`TcpConfig::trace_packets`](crate::TcpConfig::trace_packets) of them.
+#[derive(Debug, Default)]
+pub(crate) struct PacketTrace {
+    capacity: usize,
+    packets: VecDeque<TracedPacket>,
+}
+
+impl PacketTrace {
+    pub(crate) fn new(capacity: usize) -> Self {
+        PacketTrace {
+            capacity,
+            packets: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Keep the start of `packet`, whose headers are `headers` bytes long, forgetting the oldest
+    /// segment if the trace is full. The packet comes in pieces, as received segments do.
+    pub(crate) fn record(
+        &mut self,
+        at: Instant,
+        direction: Direction,
+        headers: usize,
+        packet: &[&[u8]],
+    ) {
+        if !self.is_enabled() {
+            return;
+        }
+        let len = packet.iter().map(|piece| piece.len()).sum();
+        let mut kept = cmp::min(len, headers + PAYLOAD_KEPT);
+        let mut bytes = Vec::with_capacity(kept);
+        for piece in packet {
+            let n = cmp::min(piece.len(), kept);
+            bytes.extend_from_slice(&piece[..n]);
+            kept -= n;
+        }
+        if self.packets.len() == self.capacity {
+            self.packets.pop_front();
+        }
+        self.packets.push_back(TracedPacket {
+            at,
+            direction,
+            len,
+            bytes,
+        });
+    }
+
+    /// The segments traced, oldest first.
+    pub(crate) fn dump(&self) -> Vec<TracedPacket> {
+        self.packets.iter().cloned().collect()
+    }
+}
@@ -0,0 +1,120 @@
+//! Aborting connections from the application, one by its quad or all those a predicate picks:
+//! the peer gets an RST, blocked calls fail with `ConnectionAborted`, and the close reason is
+//! `LocalAbort`.
+
+mod common;
+
+use std::io::{self, Read};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::thread;
+use std::time::Duration;
+
+use common::{accept, connect, state, Craft, Scripted, TICK, US};
+use common::{peer, threaded::Threaded};
+use trust::{CloseReason, InterfaceEvent, Quad, State, TcpConfig};
+
+#[test]
+fn a_blocked_reader_fails_and_the_peer_is_reset() {
+    let mut t = Threaded::new();
+    let us = SocketAddrV4::new(US, 80);
+    let listener = t.interface.bind(80).unwrap();
+    t.send(Craft::new(peer(), us).syn().seq(1000).mss(1460));
+    let iss = t.next().seq;
+    t.send(Craft::new(peer(), us).seq(1001).ack(iss.wrapping_add(1)));
+    let stream = listener.accept().unwrap();
+    t.take();
+
+    thread::scope(|scope| {
+        let reader = scope.spawn(|| (&stream).read(&mut [0; 16]));
+        thread::sleep(Duration::from_millis(50));
+        assert!(!reader.is_finished());
+        t.interface.abort(stream.quad()).unwrap();
+        let err = reader.join().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionAborted);
+    });
+    let rst = t.take_one();
+    assert_eq!(
+        (rst.flags(), rst.seq),
+        ("R.".to_string(), iss.wrapping_add(1))
+    );
+    assert_eq!(stream.close_reason(), Some(CloseReason::LocalAbort));
+}
+
+#[test]
+fn abort_matching_picks_out_the_connections_to_one_host() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let dead = Ipv4Addr::new(10, 0, 0, 3);
+    let (alive, _) = accept(&mut s, 80, peer(), 1000);
+    let (gone, _) = accept(&mut s, 80, SocketAddrV4::new(dead, 40000), 1000);
+    let (also_gone, _) = accept(&mut s, 80, SocketAddrV4::new(dead, 40001), 1000);
+    s.take();
+
+    let picked = s
+        .interface
+        .abort_matching(|quad, info| *quad.remote().ip() == dead && info.state == State::Estab);
+    assert_eq!(picked, 2);
+    s.advance(TICK);
+    let mut reset: Vec<_> = s
+        .take()
+        .iter()
+        .filter(|seg| seg.rst)
+        .map(|seg| seg.dst)
+        .collect();
+    reset.sort();
+    assert_eq!(reset, [gone.remote(), also_gone.remote()]);
+    assert_eq!(state(&s.interface, alive), Some(State::Estab));
+
+    // and polled, each is reported closed for having been aborted
+    let closed: Vec<Quad> = s
+        .take_events()
+        .into_iter()
+        .filter_map(|e| match e {
+            InterfaceEvent::Closed(quad, CloseReason::LocalAbort) => Some(quad),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(closed.len(), 2);
+    assert!(closed.contains(&gone) && closed.contains(&also_gone));
+
+    // nothing left to pick the second time round
+    assert_eq!(
+        s.interface
+            .abort_matching(|quad, _| *quad.remote().ip() == dead),
+        0
+    );
+}
+
+#[test]
+fn a_connection_still_in_syn_sent_goes_without_an_rst() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let mut stream = s.interface.connect(US, peer()).unwrap();
+    s.advance(TICK);
+    s.take_one();
+    s.interface.abort(stream.quad()).unwrap();
+    s.advance(TICK);
+    assert!(s.take().is_empty());
+    assert_eq!(
+        stream.read(&mut [0; 16]).unwrap_err().kind(),
+        io::ErrorKind::ConnectionAborted
+    );
+    assert_eq!(stream.close_reason(), Some(CloseReason::LocalAbort));
+}
+
+#[test]
+fn a_connection_that_is_gone_is_not_found() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    let quad = stream.quad();
+    s.send(
+        Craft::new(peer(), quad.local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .rst(),
+    );
+    drop(stream);
+    s.advance(TICK);
+    assert!(s.interface.connections().is_empty());
+    let err = s.interface.abort(quad).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
@@ -0,0 +1,69 @@
+//! Streams set to abort when dropped: the connection is reset at SND.NXT and gone at once,
+//! with no TIME-WAIT, unless the stream was shut down for writing first.
+
+mod common;
+
+use std::io::Write;
+use std::net::Shutdown;
+
+use common::{connect, peer, state, Scripted, TICK};
+use trust::{State, TcpConfig};
+
+#[test]
+fn a_dropped_stream_resets_its_connection() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    stream.set_abort_on_drop(true).unwrap();
+    assert!(stream.abort_on_drop().unwrap());
+    // with data the peer has not acknowledged
+    stream.write_all(&[1; 3000]).unwrap();
+    s.advance(TICK);
+    assert_eq!(s.take().len(), 3);
+
+    drop(stream);
+    s.advance(TICK);
+    let rst = s.take_one();
+    assert_eq!(rst.flags(), "R.");
+    assert_eq!(rst.seq, iss.wrapping_add(1 + 3000));
+    assert!(s.interface.connections().is_empty());
+}
+
+#[test]
+fn the_default_comes_from_the_config() {
+    let mut s = Scripted::new(TcpConfig {
+        abort_on_drop: true,
+        ..TcpConfig::default()
+    });
+    let (stream, _) = connect(&mut s, peer(), 1000);
+    assert!(stream.abort_on_drop().unwrap());
+    drop(stream);
+    s.advance(TICK);
+    assert!(s.take_one().rst);
+}
+
+#[test]
+fn a_stream_shut_down_for_writing_still_closes() {
+    let mut s = Scripted::new(TcpConfig {
+        abort_on_drop: true,
+        ..TcpConfig::default()
+    });
+    let (stream, _) = connect(&mut s, peer(), 1000);
+    let quad = stream.quad();
+    stream.shutdown(Shutdown::Write).unwrap();
+    drop(stream);
+    s.advance(TICK);
+    assert_eq!(s.take_one().flags(), "F.");
+    assert_eq!(state(&s.interface, quad), Some(State::FinWait1));
+}
+
+#[test]
+fn by_default_a_dropped_stream_closes() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, _) = connect(&mut s, peer(), 1000);
+    assert!(!stream.abort_on_drop().unwrap());
+    let quad = stream.quad();
+    drop(stream);
+    s.advance(TICK);
+    assert_eq!(s.take_one().flags(), "F.");
+    assert_eq!(state(&s.interface, quad), Some(State::FinWait1));
+}
@@ -0,0 +1,135 @@
+//! The accept hook: it sees each connection a listener takes in before the SYN-ACK goes out,
+//! and can add options of its own after ours, within the 40 bytes a header has for them, or
+//! turn the SYN down.
+
+mod common;
+
+use std::io;
+use std::net::SocketAddrV4;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::{options, us, Craft, Scripted, Segment, PEER, TICK};
+use trust::{DropReason, TcpConfig, TcpListener};
+
+/// Our own kind, from the range RFC 4727 sets aside for experiments.
+const KIND: u8 = 253;
+
+fn peer(port: u16) -> SocketAddrV4 {
+    SocketAddrV4::new(PEER, port)
+}
+
+/// The SYN-ACK answering a SYN from `syn`, with a hook that adds `extra` to it, along with the
+/// listener that sent it.
+fn syn_ack(syn: Craft, extra: &'static [u8]) -> (Scripted, TcpListener, Segment) {
+    let mut s = Scripted::new(TcpConfig::default());
+    s.interface.set_accept_hook(move |pending| {
+        pending.set_extra_options(extra).unwrap();
+        true
+    });
+    let listener = s.interface.bind(80).unwrap();
+    s.send(syn);
+    let syn_ack = s.take_one();
+    assert_eq!(syn_ack.flags(), "S.");
+    (s, listener, syn_ack)
+}
+
+#[test]
+fn the_option_goes_after_our_own() {
+    let syn = Craft::new(peer(40000), us()).syn().seq(1000).mss(1460);
+    let (_, _, syn_ack) = syn_ack(syn, &[KIND, 4, 0xab, 0xcd]);
+    assert_eq!(syn_ack.options, [2, 4, 0x05, 0xb4, KIND, 4, 0xab, 0xcd]);
+}
+
+#[test]
+fn an_odd_length_is_padded_with_nops() {
+    let syn = Craft::new(peer(40000), us()).syn().seq(1000).mss(1460);
+    let (_, _, syn_ack) = syn_ack(syn, &[KIND, 3, 7]);
+    assert_eq!(syn_ack.options, [2, 4, 0x05, 0xb4, KIND, 3, 7, 1]);
+}
+
+#[test]
+fn a_retransmitted_syn_ack_carries_it_too() {
+    let syn = Craft::new(peer(40000), us()).syn().seq(1000).mss(1460);
+    let (mut s, _listener, first) = syn_ack(syn, &[KIND, 4, 0xab, 0xcd]);
+    let start = s.net.now();
+    let again = loop {
+        assert!(
+            s.net.now() - start < Duration::from_secs(5),
+            "no retransmission"
+        );
+        s.advance(TICK);
+        if let Some(seg) = s.take().into_iter().next() {
+            break seg;
+        }
+    };
+    assert_eq!(
+        (again.flags(), again.options),
+        (first.flags(), first.options)
+    );
+}
+
+#[test]
+fn the_options_budget_is_held_to() {
+    let rooms = Arc::new(Mutex::new(Vec::new()));
+    let mut s = Scripted::new(TcpConfig {
+        recv_buffer: 256 << 10,
+        ..TcpConfig::default()
+    });
+    s.interface.set_accept_hook({
+        let rooms = rooms.clone();
+        move |pending| {
+            let room = pending.options_room();
+            let too_big = vec![1; room + 1];
+            let err = pending.set_extra_options(&too_big).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(pending.extra_options().is_empty());
+            // whatever fits is taken, up to the last byte
+            let mut fits = vec![KIND, room as u8];
+            fits.resize(room, 0xee);
+            pending.set_extra_options(&fits).unwrap();
+            rooms.lock().unwrap().push(room);
+            true
+        }
+    });
+    let _listener = s.interface.bind(80).unwrap();
+
+    // a SYN offering everything, so that ours takes up as much as it can
+    s.send(
+        Craft::new(peer(40000), us())
+            .syn()
+            .seq(1000)
+            .mss(1460)
+            .wscale(7)
+            .sack_permitted()
+            .timestamps(1, 0),
+    );
+    let syn_ack = s.take_one();
+    // ours are MSS, SACK-permitted, timestamps and window scale, 19 bytes and a NOP
+    let room = rooms.lock().unwrap()[0];
+    assert_eq!(room, 40 - 20);
+    assert_eq!(syn_ack.options.len(), 40);
+    let (kind, value) = *options(&syn_ack.options).last().unwrap();
+    assert_eq!((kind, value.len()), (KIND, room - 2));
+}
+
+#[test]
+fn a_refused_syn_gets_no_answer() {
+    let mut s = Scripted::new(TcpConfig::default());
+    s.interface
+        .set_accept_hook(|pending| pending.peer_addr() != peer(40001));
+    let _listener = s.interface.bind(80).unwrap();
+
+    s.send(Craft::new(peer(40001), us()).syn().seq(1000).mss(1460));
+    assert!(s.take().is_empty());
+    assert_eq!(s.interface.drops().get(DropReason::Refused), 1);
+    assert!(s.interface.connections().is_empty());
+
+    // while anyone else is let in
+    s.send(Craft::new(peer(40000), us()).syn().seq(1000).mss(1460));
+    let syn_ack = s.take_one();
+    assert_eq!(
+        (syn_ack.flags(), syn_ack.dst),
+        ("S.".to_string(), peer(40000))
+    );
+}
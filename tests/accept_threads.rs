@@ -0,0 +1,129 @@
+//! Several threads accepting on one listener: each connection goes to one of them, the one
+//! that has waited longest, and wakes only that one, and closing the listener wakes them all.
+
+mod common;
+
+use std::collections::HashSet;
+use std::io;
+use std::net::SocketAddrV4;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use common::threaded::Threaded;
+use common::{Craft, PEER, US};
+use trust::TcpListener;
+
+const ACCEPTORS: usize = 4;
+
+/// Complete a handshake with the listener on port 80 from `port`.
+fn handshake(t: &mut Threaded, port: u16) {
+    let (from, to) = (SocketAddrV4::new(PEER, port), SocketAddrV4::new(US, 80));
+    t.send(Craft::new(from, to).syn().seq(1000).mss(1460));
+    // past the window updates the connections accepted so far send
+    let syn_ack = loop {
+        let seg = t.next();
+        if seg.syn && seg.dst.port() == port {
+            break seg;
+        }
+    };
+    t.send(
+        Craft::new(from, to)
+            .seq(1001)
+            .ack(syn_ack.seq.wrapping_add(1)),
+    );
+}
+
+/// Closes the listener however the test ends, so that the acceptors do not wait on forever.
+struct Closing<'a>(&'a TcpListener);
+
+impl Drop for Closing<'_> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// Have `ACCEPTORS` threads accept on `listener` until it fails them, reporting each
+/// connection, by the remote port, along with which of them took it, and then how it failed.
+fn acceptors<'scope>(
+    scope: &'scope thread::Scope<'scope, '_>,
+    listener: &'scope TcpListener,
+) -> mpsc::Receiver<(usize, io::Result<u16>)> {
+    let (tx, rx) = mpsc::channel();
+    for i in 0..ACCEPTORS {
+        let tx = tx.clone();
+        scope.spawn(move || loop {
+            let accepted = listener.accept().map(|s| s.quad().remote().port());
+            let failed = accepted.is_err();
+            tx.send((i, accepted)).unwrap();
+            if failed {
+                return;
+            }
+        });
+    }
+    rx
+}
+
+#[test]
+fn connections_go_round_the_waiting_threads_in_turn() {
+    let mut t = Threaded::new();
+    let listener = t.interface.bind(80).unwrap();
+    let (takers, ports) = thread::scope(|scope| {
+        let accepted = acceptors(scope, &listener);
+        let closing = Closing(&listener);
+        // each thread is back waiting before the next connection comes in
+        thread::sleep(Duration::from_millis(50));
+        let (mut takers, mut ports) = (Vec::new(), HashSet::new());
+        for port in 40000..40020 {
+            handshake(&mut t, port);
+            let (i, port) = accepted.recv_timeout(Duration::from_secs(5)).unwrap();
+            takers.push(i);
+            assert!(ports.insert(port.unwrap()), "accepted twice");
+            thread::sleep(Duration::from_millis(20));
+        }
+        drop(closing);
+        for _ in 0..ACCEPTORS {
+            let (_, e) = accepted.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(e.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+        (takers, ports)
+    });
+    assert_eq!(ports, (40000..40020).collect());
+    // the same order each time round, with every thread in it once
+    assert_eq!(
+        takers[..ACCEPTORS].iter().collect::<HashSet<_>>().len(),
+        ACCEPTORS
+    );
+    for (n, i) in takers.iter().enumerate() {
+        assert_eq!(*i, takers[n % ACCEPTORS], "{:?}", takers);
+    }
+}
+
+#[test]
+fn one_connection_wakes_one_thread_and_closing_wakes_all() {
+    let mut t = Threaded::new();
+    let listener = t.interface.bind(80).unwrap();
+    thread::scope(|scope| {
+        let accepted = acceptors(scope, &listener);
+        let closing = Closing(&listener);
+        thread::sleep(Duration::from_millis(50));
+        handshake(&mut t, 40000);
+        let (first, port) = accepted.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(port.unwrap(), 40000);
+        // the others sleep on
+        assert!(accepted.recv_timeout(Duration::from_millis(100)).is_err());
+
+        drop(closing);
+        let mut woken = HashSet::new();
+        for _ in 0..ACCEPTORS {
+            let (i, e) = accepted.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(e.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+            woken.insert(i);
+        }
+        assert!(woken.contains(&first));
+        assert_eq!(woken.len(), ACCEPTORS);
+    });
+    // and later calls fail at once
+    let e = listener.accept().map(|_| ()).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,93 @@
+//! A listener's accept window: connections waiting in its accept queue advertise no more than
+//! it, and buffer no more than it, until the application accepts them and they open up to
+//! their whole receive buffer.
+
+mod common;
+
+use std::io::Read;
+use std::net::SocketAddrV4;
+
+use common::threaded::Threaded;
+use common::{pattern, Craft, PEER, US};
+
+const CAP: usize = 1460;
+
+/// Handshake with our listener on port 80 from `port`, returning our ISS and the window our
+/// SYN-ACK advertised.
+fn handshake(t: &mut Threaded, port: u16) -> (u32, u16) {
+    let (from, to) = (SocketAddrV4::new(PEER, port), SocketAddrV4::new(US, 80));
+    t.send(Craft::new(from, to).syn().seq(1000).mss(1460));
+    let syn_ack = t.take_one();
+    let iss = syn_ack.seq;
+    t.send(Craft::new(from, to).seq(1001).ack(iss.wrapping_add(1)));
+    (iss, syn_ack.window)
+}
+
+/// Have the peer at `port` send `data` in full-sized segments, regardless of our window, and
+/// return how much of it we acknowledged, and the window in our last ACK.
+fn push(t: &mut Threaded, port: u16, iss: u32, data: &[u8]) -> (u32, u16) {
+    let (from, to) = (SocketAddrV4::new(PEER, port), SocketAddrV4::new(US, 80));
+    for (i, chunk) in data.chunks(1460).enumerate() {
+        t.send(
+            Craft::new(from, to)
+                .seq(1001 + (i * 1460) as u32)
+                .ack(iss.wrapping_add(1))
+                .payload(chunk),
+        );
+    }
+    let ack = t.take().pop().expect("the data was acknowledged");
+    (ack.ack.unwrap() - 1001, ack.window)
+}
+
+#[test]
+fn queued_connections_hold_no_more_than_the_cap() {
+    let mut t = Threaded::new();
+    let mut listener = t.interface.bind(80).unwrap();
+    listener.set_accept_window(Some(CAP));
+
+    // nobody accepts, while three peers each try to send 10 KB
+    let data = pattern(138, 10_000);
+    let mut isss = Vec::new();
+    for port in 40000..40003 {
+        let (iss, window) = handshake(&mut t, port);
+        assert_eq!(window as usize, CAP);
+        let (acked, window) = push(&mut t, port, iss, &data);
+        assert_eq!((acked as usize, window), (CAP, 0));
+        isss.push(iss);
+    }
+
+    // the first to be accepted opens its window to all its buffer has left, and only that one
+    let mut stream = listener.accept().unwrap();
+    assert_eq!(stream.quad().remote().port(), 40000);
+    let update = t.take_one();
+    assert_eq!(
+        (update.dst.port(), update.window as usize),
+        (40000, 65535 - CAP)
+    );
+
+    // and takes in the rest of what its peer had to send
+    let (acked, _) = push(&mut t, 40000, isss[0], &data);
+    assert_eq!(acked as usize, data.len());
+    let mut got = vec![0; data.len()];
+    stream.read_exact(&mut got).unwrap();
+    assert!(got == data);
+}
+
+#[test]
+fn a_window_of_nothing_holds_the_data_back_until_accept() {
+    let mut t = Threaded::new();
+    let mut listener = t.interface.bind(80).unwrap();
+    listener.set_accept_window(Some(0));
+
+    let (iss, window) = handshake(&mut t, 40000);
+    assert_eq!(window, 0);
+    let (acked, _) = push(&mut t, 40000, iss, b"too early");
+    assert_eq!(acked, 0);
+
+    let mut stream = listener.accept().unwrap();
+    assert_eq!(t.take_one().window, u16::MAX);
+    push(&mut t, 40000, iss, b"just right");
+    let mut got = [0; 10];
+    stream.read_exact(&mut got).unwrap();
+    assert_eq!(&got, b"just right");
+}
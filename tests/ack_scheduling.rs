@@ -0,0 +1,93 @@
+//! When ACKs go out: one for each request of a request/response exchange, as soon as its
+//! batch is in; one for every two full-sized segments of a bulk stream; and a window update
+//! once reads open up a window left smaller than a segment.
+
+mod common;
+
+use std::io::Read;
+
+use common::{connect, pattern, peer, Craft, Scripted, TICK};
+use trust::{Nic, TcpConfig};
+
+const MSS: u32 = 1460;
+
+#[test]
+fn each_pushed_request_is_acknowledged_at_once() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    let quad = stream.quad();
+    let mut seq = 1001;
+    let mut ours = iss.wrapping_add(1);
+    for _ in 0..10 {
+        s.send(
+            Craft::new(peer(), quad.local())
+                .seq(seq)
+                .ack(ours)
+                .psh()
+                .payload(b"GET"),
+        );
+        seq += 3;
+        // without time moving on at all
+        let acks = s.take();
+        assert_eq!(acks.len(), 1);
+        assert_eq!(acks[0].ack, Some(seq));
+        assert_eq!(s.read_all(quad), b"GET");
+
+        // and the response carries the same ACK, with nothing besides
+        s.interface.write_on(quad, b"200").unwrap();
+        s.advance(TICK);
+        let response = s.take_one();
+        assert_eq!(
+            (response.payload.as_slice(), response.ack),
+            (&b"200"[..], Some(seq))
+        );
+        ours = ours.wrapping_add(3);
+    }
+}
+
+#[test]
+fn a_bulk_stream_is_acknowledged_every_other_segment() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    let data = pattern(180, 10 * MSS as usize);
+    // ten segments in one batch
+    for (i, chunk) in data.chunks(MSS as usize).enumerate() {
+        let segment = Craft::new(peer(), stream.quad().local())
+            .seq(1001 + i as u32 * MSS)
+            .ack(iss.wrapping_add(1))
+            .payload(chunk);
+        s.peer.send(&segment.build()).unwrap();
+    }
+    s.settle();
+    let acks: Vec<_> = s.take().iter().map(|seg| seg.ack.unwrap()).collect();
+    let expected: Vec<_> = (1..=5).map(|i| 1001 + 2 * i * MSS).collect();
+    assert_eq!(acks, expected);
+}
+
+#[test]
+fn a_window_left_below_a_segment_is_reopened() {
+    let mut s = Scripted::new(TcpConfig {
+        recv_buffer: 3000,
+        ..TcpConfig::default()
+    });
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    for i in 0..2 {
+        s.send(
+            Craft::new(peer(), stream.quad().local())
+                .seq(1001 + i * MSS)
+                .ack(iss.wrapping_add(1))
+                .payload(&[0; MSS as usize]),
+        );
+    }
+    let last = s.take().pop().unwrap();
+    assert_eq!(last.window, 3000 - 2 * MSS as u16);
+    // nothing while the application has not read
+    s.advance(10 * TICK);
+    assert!(s.take().is_empty());
+
+    let mut buf = [0; 4096];
+    assert_eq!(stream.read(&mut buf).unwrap(), 2 * MSS as usize);
+    s.advance(TICK);
+    let update = s.take_one();
+    assert_eq!((update.ack, update.window), (Some(1001 + 2 * MSS), 3000));
+}
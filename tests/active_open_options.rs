@@ -0,0 +1,118 @@
+//! What an active open goes on with once the SYN-ACK is in: of the MSS, window scaling, SACK
+//! and timestamps our SYN offers, only what the SYN-ACK confirms, with the SYN-ACK's own window
+//! taken unscaled.
+
+mod common;
+
+use std::io::Write;
+use std::net::SocketAddrV4;
+
+use common::{options, Craft, Scripted, Segment, PEER, TICK, US};
+use trust::{ConnectionInfo, TcpConfig};
+
+fn peer() -> SocketAddrV4 {
+    SocketAddrV4::new(PEER, 80)
+}
+
+/// A connection whose SYN-ACK, window 1000, carries what `confirm` adds to it given our SYN:
+/// returns what it settled on, and the segments it sends for 3000 bytes of data once the peer
+/// has opened its window.
+fn open(confirm: impl FnOnce(Craft, &Segment) -> Craft) -> (ConnectionInfo, Vec<Segment>) {
+    // a buffer big enough for our SYN to offer a window scale
+    let mut s = Scripted::new(TcpConfig {
+        recv_buffer: 256 << 10,
+        ..TcpConfig::default()
+    });
+    let mut stream = s.interface.connect(US, peer()).unwrap();
+    let us = stream.quad().local();
+    s.advance(TICK);
+    let syn = s.take_one();
+    assert!(syn.mss().is_some() && syn.wscale().is_some());
+    assert!(syn.sack_permitted() && syn.timestamps().is_some());
+    let iss = syn.seq;
+    let syn_ack = Craft::new(peer(), us)
+        .syn()
+        .seq(1000)
+        .ack(iss.wrapping_add(1))
+        .window(1000);
+    s.send(confirm(syn_ack, &syn));
+    let ack = s.take_one();
+    assert_eq!(ack.ack, Some(1001));
+    let info = stream.info().unwrap();
+
+    // a bare ACK opening the window wide, whatever the scale
+    let mut ack = Craft::new(peer(), us)
+        .seq(1001)
+        .ack(iss.wrapping_add(1))
+        .window(u16::MAX);
+    if let Some((ts_val, _)) = syn.timestamps() {
+        if info.timestamps {
+            ack = ack.timestamps(2, ts_val);
+        }
+    }
+    s.send(ack);
+    stream.write_all(&[0; 3000]).unwrap();
+    s.advance(TICK);
+    (info, s.take())
+}
+
+#[test]
+fn a_syn_ack_that_confirms_nothing() {
+    let (info, sent) = open(|syn_ack, _| syn_ack);
+    assert_eq!((info.mss, info.peer_mss), (536, Some(536)));
+    assert_eq!((info.snd_wscale, info.rcv_wscale), (0, 0));
+    assert!(!info.sack && !info.timestamps);
+    assert_eq!(info.peer_window, 1000);
+    for seg in &sent {
+        assert!(seg.options.is_empty(), "{}", seg.describe());
+        assert!(seg.payload.len() <= 536);
+    }
+    // our window unscaled: all of our buffer there is room to say
+    assert_eq!(sent[0].window, u16::MAX);
+}
+
+#[test]
+fn a_syn_ack_that_confirms_everything() {
+    let (info, sent) = open(|syn_ack, syn| {
+        let (ts_val, _) = syn.timestamps().unwrap();
+        syn_ack
+            .mss(1460)
+            .wscale(7)
+            .sack_permitted()
+            .timestamps(1, ts_val)
+    });
+    assert_eq!(info.peer_mss, Some(1460));
+    assert_eq!(info.snd_wscale, 7);
+    assert!(info.rcv_wscale > 0);
+    assert!(info.sack && info.timestamps);
+    // the SYN-ACK's window is not scaled, even though the SYN-ACK agrees to scaling
+    assert_eq!(info.peer_window, 1000);
+    for seg in &sent {
+        assert!(seg.timestamps().is_some(), "{}", seg.describe());
+        assert!(seg.wscale().is_none());
+    }
+    assert_eq!(sent[0].payload.len(), 1460 - 12);
+    assert_eq!(
+        sent[0].window as u32,
+        (256 << 10) >> info.rcv_wscale,
+        "our window is scaled"
+    );
+}
+
+#[test]
+fn a_syn_ack_that_confirms_only_timestamps() {
+    let (info, sent) = open(|syn_ack, syn| syn_ack.timestamps(1, syn.timestamps().unwrap().0));
+    assert_eq!((info.mss, info.peer_mss), (536, Some(536)));
+    assert_eq!((info.snd_wscale, info.rcv_wscale), (0, 0));
+    assert!(!info.sack && info.timestamps);
+    for seg in &sent {
+        let kinds: Vec<_> = options(&seg.options)
+            .into_iter()
+            .map(|(kind, _)| kind)
+            .filter(|&kind| kind != 1)
+            .collect();
+        assert_eq!(kinds, [8], "{}", seg.describe());
+    }
+    assert_eq!(sent[0].payload.len(), 536 - 12);
+    assert_eq!(sent[0].window, u16::MAX);
+}
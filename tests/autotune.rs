@@ -0,0 +1,145 @@
+//! Receive buffer auto-tuning: on a path with more in flight than 64 KiB, the receive buffer
+//! grows to keep up with what arrives in a round trip, and falls back once the connection goes
+//! idle, without ever taking back window it has advertised.
+
+mod common;
+
+use std::io::Read;
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use common::{connection, pattern, Craft, Pair, Scripted, PEER, TICK, US};
+use trust::{Impairments, TcpConfig};
+
+#[test]
+fn a_long_fat_path_fills_up_only_when_tuned() {
+    // 100ms round trips at 8 MB/s: some 800 KB in flight to fill the path
+    let path = Impairments {
+        latency: Duration::from_millis(50),
+        bandwidth: Some(8_000_000),
+        ..Impairments::default()
+    };
+    let back = Impairments {
+        latency: Duration::from_millis(50),
+        ..Impairments::default()
+    };
+    let sender = TcpConfig {
+        send_buffer: 1 << 20,
+        // window scaling takes both ends offering it
+        recv_buffer: 256 << 10,
+        ..TcpConfig::default()
+    };
+    let data = pattern(3, 4 << 20);
+    let run = |recv_autotune| {
+        let receiver = TcpConfig {
+            recv_autotune,
+            ..TcpConfig::default()
+        };
+        let mut pair = Pair::new(1, path.clone(), back.clone(), sender.clone(), receiver);
+        let done = pair.exchange(80, &data, &[], Duration::from_secs(10));
+        assert!(done.at_b == data, "the data arrived corrupted");
+        done
+    };
+
+    let fixed = run(false);
+    assert_eq!(fixed.info_b.rcv_buffer, 65535);
+    assert_eq!(
+        fixed.info_b.rcv_wscale, 0,
+        "no need to scale a window that cannot grow"
+    );
+    // a 64 KiB window a round trip at best
+    assert!(
+        fixed.took >= Duration::from_secs(6),
+        "took {:?}",
+        fixed.took
+    );
+
+    let tuned = run(true);
+    assert!(tuned.info_b.rcv_wscale > 0);
+    assert!(
+        tuned.info_b.rcv_buffer > 512 << 10,
+        "the buffer only grew to {}",
+        tuned.info_b.rcv_buffer
+    );
+    assert!(
+        tuned.took * 3 < fixed.took,
+        "tuned {:?}, fixed {:?}",
+        tuned.took,
+        fixed.took
+    );
+}
+
+#[test]
+fn the_buffer_shrinks_back_but_the_window_does_not() {
+    let mut s = Scripted::new(TcpConfig {
+        recv_autotune: true,
+        ..TcpConfig::default()
+    });
+    let peer = SocketAddrV4::new(PEER, 40000);
+    let mut stream = s.interface.connect(US, peer).unwrap();
+    let quad = stream.quad();
+    s.advance(TICK);
+    let syn = s.take_one();
+    let shift = syn
+        .wscale()
+        .expect("window scaling is offered for the buffer to grow into");
+    let iss = syn.seq;
+    // a 90ms round trip, sampled from the handshake, is what auto-tuning measures over
+    s.advance(Duration::from_millis(90));
+    s.send(
+        Craft::new(peer, quad.local())
+            .syn()
+            .seq(1000)
+            .ack(iss.wrapping_add(1))
+            .mss(1460)
+            .wscale(0),
+    );
+    s.take();
+
+    // a full buffer's worth within a round trip, read as it comes, and then a little more
+    let data = pattern(5, 64 << 10);
+    let mut buf = vec![0; 1460];
+    let mut edge = 0;
+    for (i, chunk) in data.chunks(1460).enumerate() {
+        if i == 44 {
+            s.advance(Duration::from_millis(90));
+        }
+        s.send(
+            Craft::new(peer, quad.local())
+                .seq(1001 + (i * 1460) as u32)
+                .ack(iss.wrapping_add(1))
+                .payload(chunk),
+        );
+        stream.read_exact(&mut buf[..chunk.len()]).unwrap();
+        for seg in s.take() {
+            let acked = seg.ack.unwrap().wrapping_sub(1001) as usize;
+            edge = acked + ((seg.window as usize) << shift);
+        }
+    }
+    let grown = connection(&s.interface, quad).unwrap().rcv_buffer;
+    assert!(grown > 65535, "the buffer stayed at {}", grown);
+    assert!(
+        edge > data.len() + 65535,
+        "the window did not open past the buffer it had"
+    );
+
+    // idle for longer than a retransmission timeout, it is back to where it started
+    s.advance(Duration::from_millis(1500));
+    assert_eq!(connection(&s.interface, quad).unwrap().rcv_buffer, 65535);
+
+    // but the peer may still fill all of the window it was given, unread
+    let rest = pattern(6, edge - data.len());
+    for (i, chunk) in rest.chunks(1460).enumerate() {
+        s.send(
+            Craft::new(peer, quad.local())
+                .seq(1001 + (data.len() + i * 1460) as u32)
+                .ack(iss.wrapping_add(1))
+                .payload(chunk),
+        );
+    }
+    let last = s.take().pop().expect("the data was acknowledged");
+    assert_eq!(last.ack, Some(1001 + edge as u32));
+    let mut got = vec![0; rest.len()];
+    stream.read_exact(&mut got).unwrap();
+    assert!(got == rest);
+}
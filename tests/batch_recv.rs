@@ -0,0 +1,107 @@
+//! Packets taken from a device in batches: a device hands over as many as have arrived, one per
+//! buffer, and the interface takes in a whole batch before it answers, with one ACK for what
+//! arrived in order rather than one per segment.
+
+mod common;
+
+use std::io::{IoSliceMut, Read};
+use std::time::Duration;
+
+use common::{connect, pattern, peer, Craft, Scripted, Segment};
+use trust::{Impairments, Nic, SimNet, TcpConfig};
+
+#[test]
+fn a_device_hands_over_one_packet_per_buffer() {
+    let (net, mut a, mut b) =
+        SimNet::new(0, Impairments::default(), Impairments::default()).unwrap();
+    let packets: Vec<_> = (0..40).map(|i| pattern(i, 20 + i as usize)).collect();
+    for packet in &packets {
+        a.send(packet).unwrap();
+    }
+    net.advance(Duration::ZERO);
+
+    let mut storage = vec![[0u8; 128]; 32];
+    let mut got = Vec::new();
+    for expected in [32, 8] {
+        let mut bufs: Vec<_> = storage.iter_mut().map(|b| IoSliceMut::new(b)).collect();
+        let mut lens = [0; 32];
+        let n = b.recv_batch(&mut bufs, &mut lens).unwrap();
+        assert_eq!(n, expected);
+        got.extend((0..n).map(|i| bufs[i][..lens[i]].to_vec()));
+    }
+    assert!(
+        got == packets,
+        "the packets came through out of order or damaged"
+    );
+}
+
+/// Have the peer send `segments` all at once, for the interface to take in as one batch, and
+/// return what it sent in answer.
+fn burst(s: &mut Scripted, segments: &[Craft]) -> Vec<Segment> {
+    for segment in segments {
+        s.peer.send(&segment.build()).unwrap();
+    }
+    s.settle();
+    s.take()
+}
+
+#[test]
+fn a_batch_of_small_segments_draws_one_ack() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+    let segments: Vec<_> = (0..8)
+        .map(|i| {
+            Craft::new(peer(), us)
+                .seq(1001 + 10 * i)
+                .ack(iss.wrapping_add(1))
+                .payload(b"0123456789")
+        })
+        .collect();
+    let acks = burst(&mut s, &segments);
+    assert_eq!(acks.len(), 1, "{:?}", acks);
+    assert_eq!(acks[0].ack, Some(1081));
+
+    let mut got = [0; 80];
+    stream.read_exact(&mut got).unwrap();
+    assert!(got.chunks(10).all(|chunk| chunk == b"0123456789"));
+}
+
+#[test]
+fn full_sized_segments_are_acknowledged_every_second_one() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+    let segments: Vec<_> = (0..6)
+        .map(|i| {
+            Craft::new(peer(), us)
+                .seq(1001 + 1460 * i)
+                .ack(iss.wrapping_add(1))
+                .payload(&[i as u8; 1460])
+        })
+        .collect();
+    let acks: Vec<_> = burst(&mut s, &segments)
+        .iter()
+        .map(|seg| seg.ack.unwrap() - 1001)
+        .collect();
+    assert_eq!(acks, [2920, 5840, 8760]);
+}
+
+#[test]
+fn a_duplicate_in_a_batch_is_acknowledged_at_once() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+    let segment = |seq| {
+        Craft::new(peer(), us)
+            .seq(seq)
+            .ack(iss.wrapping_add(1))
+            .payload(b"0123456789")
+    };
+    let acks: Vec<_> = burst(&mut s, &[segment(1001), segment(1001), segment(1011)])
+        .iter()
+        .map(|seg| seg.ack.unwrap())
+        .collect();
+    // the duplicate draws an ACK of its own, and the rest of the batch one more
+    assert_eq!(acks, [1011, 1021]);
+}
@@ -0,0 +1,67 @@
+//! Both ends of a connection sending 20 MB to each other at once over a lossy, reordering
+//! path, one of them with Nagle's algorithm on: ACKs ride on data both ways, window updates
+//! compete with it, and retransmissions interleave with the other direction's data.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{pattern, Pair};
+use trust::{Impairments, TcpConfig};
+
+const SIZE: usize = 20 << 20;
+
+/// How long neither end may get anywhere, in virtual time, before the test takes it for a
+/// deadlock.
+const STALL: Duration = Duration::from_secs(30);
+
+/// FNV-1a, to tell a stream from what was sent by a number in the failure message.
+fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[test]
+fn twenty_megabytes_each_way_over_loss_and_reordering() {
+    let path = Impairments {
+        drop: 0.02,
+        reorder: 0.02,
+        reorder_window: Duration::from_millis(5),
+        latency: Duration::from_millis(5),
+        ..Default::default()
+    };
+    let config = TcpConfig {
+        send_buffer: 256 * 1024,
+        recv_buffer: 256 * 1024,
+        ..TcpConfig::default()
+    };
+    let mut pair = Pair::new(
+        185,
+        path.clone(),
+        path,
+        TcpConfig {
+            nagle: true,
+            ..config.clone()
+        },
+        config,
+    );
+    let (to_b, to_a) = (pattern(1, SIZE), pattern(2, SIZE));
+
+    // the exchange fails on its own if nothing moves for STALL, or either end does not close
+    let done = pair.exchange(80, &to_b, &to_a, STALL);
+    assert_eq!(done.at_b.len(), SIZE);
+    assert_eq!(
+        checksum(&done.at_b),
+        checksum(&to_b),
+        "a to b came through corrupted"
+    );
+    assert_eq!(done.at_a.len(), SIZE);
+    assert_eq!(
+        checksum(&done.at_a),
+        checksum(&to_a),
+        "b to a came through corrupted"
+    );
+    // both ends took a loss for congestion at some point, or the path tested nothing
+    assert!(done.info_a.ssthresh < u32::MAX && done.info_b.ssthresh < u32::MAX);
+}
@@ -0,0 +1,94 @@
+//! Appropriate Byte Counting (RFC 3465): the congestion window grows by the bytes each ACK
+//! covers, with at most L full-sized segments' worth per ACK in slow start, rather than by a
+//! segment per ACK however little or much it acknowledges.
+
+mod common;
+
+use std::net::SocketAddrV4;
+
+use common::{accept, connection, pattern, Craft, Scripted, PEER, TICK};
+use trust::{Quad, TcpConfig};
+
+const MSS: u32 = 1460;
+
+/// The initial window, ten segments of what the handshake in [`accept`] agrees on.
+const IW: u32 = 10 * MSS;
+
+/// A connection with an `abc_limit` of `limit` that has sent its whole initial window, with
+/// none of it acknowledged yet: returns the scripted peer, the quad, and SND.UNA.
+fn window_out(limit: usize) -> (Scripted, Quad, u32) {
+    let mut s = Scripted::new(TcpConfig {
+        abc_limit: limit,
+        ..TcpConfig::default()
+    });
+    let _listener = s.interface.bind(80).unwrap();
+    let peer = SocketAddrV4::new(PEER, 40000);
+    let (quad, iss) = accept(&mut s, 80, peer, 1000);
+    s.interface
+        .write_on(quad, &pattern(0, 64 * MSS as usize))
+        .unwrap();
+    s.advance(TICK);
+    assert_eq!(s.take().len(), 10);
+    assert_eq!(cwnd(&s, quad), IW);
+    (s, quad, iss.wrapping_add(1))
+}
+
+fn cwnd(s: &Scripted, quad: Quad) -> u32 {
+    connection(&s.interface, quad).unwrap().cwnd
+}
+
+/// Have the peer acknowledge up to `una + upto`.
+fn ack(s: &mut Scripted, quad: Quad, una: u32, upto: u32) {
+    s.send(
+        Craft::new(quad.remote(), quad.local())
+            .seq(1001)
+            .ack(una.wrapping_add(upto))
+            .window(u16::MAX),
+    );
+}
+
+#[test]
+fn an_ack_per_segment_doubles_the_window() {
+    let (mut s, quad, una) = window_out(2);
+    for i in 1..=10 {
+        ack(&mut s, quad, una, i * MSS);
+    }
+    assert_eq!(cwnd(&s, quad), 2 * IW);
+}
+
+#[test]
+fn an_ack_every_tenth_segment_grows_the_window_by_at_most_l_segments() {
+    // the same ten segments, acknowledged at once, count for L segments' worth and no more
+    for limit in [1, 2] {
+        let (mut s, quad, una) = window_out(limit);
+        ack(&mut s, quad, una, IW);
+        assert_eq!(cwnd(&s, quad), IW + limit as u32 * MSS, "L = {}", limit);
+    }
+
+    // and sparse ACKs keep growing it as the data keeps being acknowledged, without the
+    // ACK count mattering
+    let (mut s, quad, una) = window_out(2);
+    let mut acked = 0;
+    for _ in 0..4 {
+        acked += 10 * MSS;
+        ack(&mut s, quad, una, acked);
+        s.take();
+    }
+    assert_eq!(cwnd(&s, quad), IW + 4 * 2 * MSS);
+}
+
+#[test]
+fn acks_of_small_segments_grow_the_window_by_what_they_carried() {
+    // an application writing 100 bytes at a time gets each acknowledged on its own, which
+    // would have grown the window by a full segment per ACK when counting ACKs
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let (quad, iss) = accept(&mut s, 80, SocketAddrV4::new(PEER, 40000), 1000);
+    for i in 1..=20 {
+        s.interface.write_on(quad, &[b'x'; 100]).unwrap();
+        s.advance(TICK);
+        assert_eq!(s.take_one().payload.len(), 100);
+        ack(&mut s, quad, iss.wrapping_add(1), i * 100);
+    }
+    assert_eq!(cwnd(&s, quad), IW + 2000);
+}
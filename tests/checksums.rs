@@ -0,0 +1,99 @@
+//! The checksums of the segments we send, built from sums kept of the send buffer: right for
+//! every segment, however it is cut from the buffer, across the wrap of the sequence numbers
+//! and of the buffer itself.
+
+mod common;
+
+use std::io;
+use std::time::Duration;
+
+use common::{connect, pattern, peer, Craft, Scripted, Segment, TICK};
+use etherparse::{Ipv4HeaderSlice, TcpHeaderSlice};
+use trust::{Profile, TcpConfig};
+
+/// Whether the checksum `segment` carries is the one worked out from scratch.
+fn checksum_holds(segment: &Segment) -> bool {
+    let ip = Ipv4HeaderSlice::from_slice(&segment.packet).unwrap();
+    let tcp = TcpHeaderSlice::from_slice(&segment.packet[ip.slice().len()..]).unwrap();
+    let payload = &segment.packet[ip.slice().len() + tcp.slice().len()..];
+    tcp.calc_checksum_ipv4(&ip, payload).unwrap() == tcp.checksum()
+}
+
+#[test]
+fn every_segment_sent_has_the_right_checksum() {
+    // a small send buffer, so that it wraps many times over, and sequence numbers that wrap
+    // early on
+    let mut s = Scripted::with(
+        TcpConfig {
+            send_buffer: 4096,
+            profile: Profile::Datacenter,
+            ..TcpConfig::default()
+        },
+        |builder| {
+            builder.fixed_iss(u32::MAX - 3000);
+        },
+    );
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    let quad = stream.quad();
+    let start = iss.wrapping_add(1);
+    let data = pattern(194, 40_000);
+
+    let (mut written, mut acked, mut sent) = (0, 0, 0);
+    let (mut checked, mut odd, mut again) = (0, 0, 0);
+    let begun = s.net.now();
+    while acked < data.len() {
+        assert!(
+            s.net.now() - begun < Duration::from_secs(60),
+            "stuck at {}",
+            acked
+        );
+        // written in pieces of 777 bytes, as far as the buffer has room
+        while written < data.len() {
+            let end = data.len().min(written + 777);
+            match s.interface.write_on(quad, &data[written..end]) {
+                Ok(n) => written += n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => panic!("{}", e),
+            }
+        }
+        s.advance(TICK);
+
+        let mut furthest = acked;
+        for segment in s.take() {
+            if segment.payload.is_empty() {
+                continue;
+            }
+            assert!(checksum_holds(&segment), "{}", segment.describe());
+            let offset = segment.seq.wrapping_sub(start) as usize;
+            assert_eq!(
+                segment.payload,
+                data[offset..offset + segment.payload.len()]
+            );
+            if offset < sent {
+                again += 1;
+            }
+            furthest = furthest.max(offset + segment.payload.len());
+            checked += 1;
+            odd += offset % 2;
+        }
+        sent = sent.max(furthest);
+        // the peer has it all, but acknowledges an odd part of it, so that what is left is
+        // sent again from an offset no segment started at before
+        if furthest > acked {
+            acked += (((furthest - acked) / 2) | 1).min(furthest - acked);
+            s.send(
+                Craft::new(peer(), quad.local())
+                    .seq(1001)
+                    .ack(start.wrapping_add(acked as u32)),
+            );
+        }
+    }
+    // some of them sent again, and some starting at an odd offset
+    assert!(
+        checked > 40 && again > 0 && odd > 0,
+        "{} segments, {} sent again, {} at odd offsets",
+        checked,
+        again,
+        odd
+    );
+}
@@ -0,0 +1,35 @@
+//! The packet loop runs on the interface's clock, not on real time.
+
+mod common;
+
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use common::{Scripted, PEER, TICK, US};
+use trust::TcpConfig;
+
+#[test]
+fn ticks_follow_the_clock() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _stream = s
+        .interface
+        .connect(US, SocketAddrV4::new(PEER, 80))
+        .unwrap();
+
+    // however long the loop waits in real time, the SYN waits for a tick of virtual time
+    s.interface.poll_once(Duration::from_millis(30)).unwrap();
+    s.settle();
+    assert!(s.take().is_empty());
+
+    s.advance(TICK);
+    let syn = s.take_one();
+    assert!(syn.syn && syn.ack.is_none());
+
+    // and it goes out again only once the retransmission timeout, a second, has gone by on
+    // the clock
+    s.advance(Duration::from_secs(1));
+    assert!(s.take().is_empty());
+    s.advance(TICK);
+    let again = s.take_one();
+    assert!(again.syn && again.seq == syn.seq);
+}
@@ -0,0 +1,140 @@
+//! Why a connection closed, recorded once as it leaves the synchronized states, and kept for
+//! the stream after its reads and writes have failed or run into the end of the stream.
+
+mod common;
+
+use std::io::{self, Read};
+use std::net::Shutdown;
+use std::time::Duration;
+
+use common::{connect, peer, Craft, Scripted, TICK, US};
+use trust::{CloseReason, State, TcpConfig};
+
+fn short_msl() -> TcpConfig {
+    TcpConfig {
+        msl: Duration::from_secs(1),
+        ..TcpConfig::default()
+    }
+}
+
+#[test]
+fn we_closed_first() {
+    let mut s = Scripted::new(short_msl());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+    assert_eq!(stream.close_reason(), None);
+    stream.shutdown(Shutdown::Write).unwrap();
+    s.advance(TICK);
+    assert!(s.take_one().fin);
+    s.send(
+        Craft::new(peer(), us)
+            .seq(1001)
+            .ack(iss.wrapping_add(2))
+            .fin(),
+    );
+    s.take_one();
+    let info = stream.info().unwrap();
+    assert_eq!(
+        (info.state, info.close_reason),
+        (State::TimeWait, Some(CloseReason::Local))
+    );
+    assert_eq!(stream.read(&mut [0; 16]).unwrap(), 0);
+
+    // and it outlives TIME-WAIT, and the connection with it
+    s.advance(Duration::from_secs(3));
+    assert!(s.interface.connections().is_empty());
+    assert_eq!(stream.close_reason(), Some(CloseReason::Local));
+}
+
+#[test]
+fn the_peer_closed_first() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+    s.send(
+        Craft::new(peer(), us)
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .fin(),
+    );
+    s.take();
+    assert_eq!(stream.read(&mut [0; 16]).unwrap(), 0);
+    // half closed is not closed
+    assert_eq!(stream.close_reason(), None);
+
+    stream.shutdown(Shutdown::Write).unwrap();
+    s.advance(TICK);
+    assert!(s.take_one().fin);
+    s.send(Craft::new(peer(), us).seq(1002).ack(iss.wrapping_add(2)));
+    assert_eq!(stream.close_reason(), Some(CloseReason::PeerFin));
+}
+
+#[test]
+fn the_peer_reset_it() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .rst(),
+    );
+    assert_eq!(
+        stream.read(&mut [0; 16]).unwrap_err().kind(),
+        io::ErrorKind::ConnectionReset
+    );
+    assert_eq!(stream.close_reason(), Some(CloseReason::PeerReset));
+}
+
+#[test]
+fn the_peer_refused_our_syn() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let mut stream = s.interface.connect(US, peer()).unwrap();
+    s.advance(TICK);
+    let iss = s.take_one().seq;
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .ack(iss.wrapping_add(1))
+            .rst(),
+    );
+    assert_eq!(
+        stream.read(&mut [0; 16]).unwrap_err().kind(),
+        io::ErrorKind::ConnectionRefused
+    );
+    assert_eq!(stream.close_reason(), Some(CloseReason::Refused));
+}
+
+#[test]
+fn we_aborted_it_and_the_reason_is_set_once() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    s.interface.abort(stream.quad()).unwrap();
+    s.advance(TICK);
+    assert_eq!(s.take_one().flags(), "R.");
+    assert_eq!(
+        stream.read(&mut [0; 16]).unwrap_err().kind(),
+        io::ErrorKind::ConnectionAborted
+    );
+    assert_eq!(stream.close_reason(), Some(CloseReason::LocalAbort));
+
+    // an RST from the peer crossing ours finds nothing left to record it on
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .rst(),
+    );
+    assert_eq!(stream.close_reason(), Some(CloseReason::LocalAbort));
+}
+
+#[test]
+fn the_interface_went_away() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, _) = connect(&mut s, peer(), 1000);
+    drop(s.interface);
+    assert_eq!(
+        stream.read(&mut [0; 16]).unwrap_err().kind(),
+        io::ErrorKind::ConnectionAborted
+    );
+    assert_eq!(stream.close_reason(), Some(CloseReason::InterfaceShutdown));
+}
@@ -0,0 +1,87 @@
+//! Segments for a port with neither a connection nor a listener are answered with an RST
+//! (RFC 793 p.36).
+
+mod common;
+
+use std::net::SocketAddrV4;
+
+use common::{us, Craft, Scripted, PEER};
+use trust::{RateLimit, TcpConfig};
+
+fn peer(port: u16) -> SocketAddrV4 {
+    SocketAddrV4::new(PEER, port)
+}
+
+#[test]
+fn syn_before_bind_after_bind_and_after_close() {
+    let mut s = Scripted::new(TcpConfig::default());
+
+    s.send(Craft::new(peer(40000), us()).syn().seq(1000).mss(1460));
+    let rst = s.take_one();
+    assert_eq!(rst.flags(), "R.");
+    assert_eq!(rst.seq, 0);
+    assert_eq!(rst.ack, Some(1001));
+    assert_eq!((rst.src, rst.dst), (us(), peer(40000)));
+
+    let listener = s.interface.bind(80).unwrap();
+    s.send(Craft::new(peer(40001), us()).syn().seq(2000).mss(1460));
+    let syn_ack = s.take_one();
+    assert_eq!(syn_ack.flags(), "S.");
+    assert_eq!(syn_ack.ack, Some(2001));
+
+    drop(listener);
+    s.settle();
+    s.take();
+    s.send(Craft::new(peer(40002), us()).syn().seq(3000).mss(1460));
+    let rst = s.take_one();
+    assert_eq!(rst.flags(), "R.");
+    assert_eq!(rst.ack, Some(3001));
+}
+
+#[test]
+fn rst_takes_its_sequence_number_from_the_ack() {
+    let mut s = Scripted::new(TcpConfig::default());
+    s.send(
+        Craft::new(peer(40000), us())
+            .seq(500)
+            .ack(777)
+            .payload(b"data"),
+    );
+    let rst = s.take_one();
+    assert_eq!(rst.flags(), "R");
+    assert_eq!(rst.seq, 777);
+
+    // without an ACK, everything it occupies is acknowledged: data and FIN alike
+    s.send(
+        Craft::new(peer(40000), us())
+            .seq(500)
+            .fin()
+            .payload(b"data"),
+    );
+    let rst = s.take_one();
+    assert_eq!(rst.flags(), "R.");
+    assert_eq!(rst.ack, Some(505));
+}
+
+#[test]
+fn an_rst_is_never_answered() {
+    let mut s = Scripted::new(TcpConfig::default());
+    s.send(Craft::new(peer(40000), us()).rst().seq(1));
+    s.send(Craft::new(peer(40000), us()).rst().seq(1).ack(5));
+    assert!(s.take().is_empty());
+}
+
+#[test]
+fn rsts_for_closed_ports_are_rate_limited() {
+    let mut s = Scripted::with(TcpConfig::default(), |b| {
+        b.rst_limit(RateLimit {
+            per_second: 1,
+            burst: 2,
+        });
+    });
+    for port in 0..5 {
+        s.send(Craft::new(peer(40000 + port), us()).syn().seq(1));
+    }
+    assert_eq!(s.take().len(), 2);
+    assert_eq!(s.interface.rate_limited().rsts, 3);
+}
@@ -0,0 +1,178 @@
+//! Segments checked byte for byte against fixtures under `tests/golden`, one hex dump per file.
+//!
+//! A fixture that is missing, or differs, fails the test with the header fields that differ.
+//! Run with `TRUST_GOLDEN=overwrite` to write the fixtures out afresh from what was sent, then
+//! look over the diff before checking them in.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+
+use super::Segment;
+
+/// The fields of the IPv4 header, by offset and length.
+const IP_FIELDS: &[(&str, usize, usize)] = &[
+    ("IP version and header length", 0, 1),
+    ("IP DSCP and ECN", 1, 1),
+    ("IP total length", 2, 2),
+    ("IP identification", 4, 2),
+    ("IP flags and fragment offset", 6, 2),
+    ("IP TTL", 8, 1),
+    ("IP protocol", 9, 1),
+    ("IP header checksum", 10, 2),
+    ("IP source", 12, 4),
+    ("IP destination", 16, 4),
+];
+
+/// The fields of the TCP header, by offset from its start and length.
+const TCP_FIELDS: &[(&str, usize, usize)] = &[
+    ("TCP source port", 0, 2),
+    ("TCP destination port", 2, 2),
+    ("TCP sequence number", 4, 4),
+    ("TCP acknowledgment number", 8, 4),
+    ("TCP data offset", 12, 1),
+    ("TCP flags", 13, 1),
+    ("TCP window", 14, 2),
+    ("TCP checksum", 16, 2),
+    ("TCP urgent pointer", 18, 2),
+];
+
+fn path(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "golden", name]
+        .iter()
+        .collect::<PathBuf>()
+        .with_extension("hex")
+}
+
+/// Check `segment` against the fixture `name`, or write it out as the fixture if
+/// `TRUST_GOLDEN=overwrite`.
+pub fn check(name: &str, segment: &Segment) {
+    let path = path(name);
+    if std::env::var("TRUST_GOLDEN").is_ok_and(|v| v == "overwrite") {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, dump(segment)).unwrap();
+        return;
+    }
+    let Ok(fixture) = fs::read_to_string(&path) else {
+        panic!(
+            "no fixture at {}; run with TRUST_GOLDEN=overwrite to write it",
+            path.display()
+        );
+    };
+    let expected = parse(&fixture);
+    if expected != segment.packet {
+        panic!(
+            "{} differs from {}:\n{}\nrun with TRUST_GOLDEN=overwrite if it should",
+            segment.describe(),
+            path.display(),
+            diff(&expected, &segment.packet).join("\n")
+        );
+    }
+}
+
+/// The fixture for `segment`: a line describing it, then its bytes, 16 to a line.
+fn dump(segment: &Segment) -> String {
+    let mut s = format!("# {}\n", segment.describe());
+    for line in segment.packet.chunks(16) {
+        let words: Vec<_> = line
+            .chunks(2)
+            .map(|w| w.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+            .collect();
+        let _ = writeln!(s, "{}", words.join(" "));
+    }
+    s
+}
+
+/// The bytes of a fixture, leaving out comments and whitespace.
+pub fn parse(fixture: &str) -> Vec<u8> {
+    let digits: Vec<u8> = fixture
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .flat_map(|line| line.bytes().filter(u8::is_ascii_hexdigit))
+        .collect();
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+        .collect()
+}
+
+/// Each header field that differs between the packets `expected` and `got`, and then the
+/// options and payload, one per line.
+fn diff(expected: &[u8], got: &[u8]) -> Vec<String> {
+    let tcp_at = |packet: &[u8]| packet.first().map_or(20, |b| (b & 0xf) as usize * 4);
+    let fields = IP_FIELDS
+        .iter()
+        .map(|&(name, at, len)| (name, at, at, len))
+        .chain(
+            TCP_FIELDS
+                .iter()
+                .map(|&(name, at, len)| (name, tcp_at(expected) + at, tcp_at(got) + at, len)),
+        );
+    let mut diffs = Vec::new();
+    for (name, at_expected, at_got, len) in fields {
+        let (e, g) = (
+            expected.get(at_expected..at_expected + len),
+            got.get(at_got..at_got + len),
+        );
+        if e != g {
+            diffs.push(format!(
+                "  {}: expected {}, got {}",
+                name,
+                show(name, e),
+                show(name, g)
+            ));
+        }
+    }
+
+    let rest = |packet: &[u8]| {
+        let at = tcp_at(packet);
+        let data_at = at + packet.get(at + 12).map_or(20, |b| (b >> 4) as usize * 4);
+        let options = packet.get(at + 20..data_at).unwrap_or(&[]).to_vec();
+        let payload = packet.get(data_at..).unwrap_or(&[]).to_vec();
+        (options, payload)
+    };
+    let ((e_options, e_payload), (g_options, g_payload)) = (rest(expected), rest(got));
+    if e_options != g_options {
+        diffs.push(format!(
+            "  TCP options: expected {:02x?}, got {:02x?}",
+            e_options, g_options
+        ));
+    }
+    if e_payload != g_payload {
+        let first = e_payload
+            .iter()
+            .zip(&g_payload)
+            .position(|(e, g)| e != g)
+            .unwrap_or(e_payload.len().min(g_payload.len()));
+        diffs.push(format!(
+            "  payload: expected {} bytes, got {}, first differing at byte {}",
+            e_payload.len(),
+            g_payload.len(),
+            first
+        ));
+    }
+    diffs
+}
+
+/// A field's value the way it is usually written: addresses dotted, checksums in hex, flags
+/// as tcpdump has them, and the rest as numbers.
+fn show(name: &str, bytes: Option<&[u8]>) -> String {
+    let Some(bytes) = bytes else {
+        return "nothing".to_string();
+    };
+    let value = bytes.iter().fold(0u32, |v, &b| v << 8 | b as u32);
+    match name {
+        "IP source" | "IP destination" => Ipv4Addr::from(value).to_string(),
+        "IP header checksum" | "TCP checksum" => format!("{:#06x}", value),
+        "TCP flags" => {
+            let flags: String = [(2, 'S'), (1, 'F'), (4, 'R'), (8, 'P'), (16, '.')]
+                .iter()
+                .filter(|&&(bit, _)| value & bit != 0)
+                .map(|&(_, c)| c)
+                .collect();
+            format!("[{}]", flags)
+        }
+        _ => value.to_string(),
+    }
+}
@@ -0,0 +1,774 @@
+//! What the integration tests share: an interface driven on virtual time, facing either a
+//! scripted peer that crafts and inspects segments by hand, or another interface.
+//!
+//! Everything runs on the test's thread, on a polled interface whose packet loop only ticks
+//! as the [`SimNet`]'s clock is advanced, so a run does the same thing every time, except in
+//! [`threaded`], for what only an interface with a packet loop of its own does.
+
+#![allow(dead_code)]
+
+use std::fmt::Write as _;
+use std::io;
+use std::net::{Ipv4Addr, Shutdown, SocketAddrV4};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::Duration;
+
+use etherparse::{Ipv4HeaderSlice, PacketBuilder, TcpHeaderSlice};
+use trust::{
+    ConnectionInfo, Impairments, Interface, InterfaceBuilder, InterfaceEvent, Nic, Quad, SimNet,
+    SimNic, State, TcpConfig, TcpStream,
+};
+
+pub mod golden;
+pub mod script;
+pub mod threaded;
+
+/// The address of the interface under test.
+pub const US: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+/// The address of whatever is at the other end of the network.
+pub const PEER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+/// Where the peer talks to the interface from, in tests with one connection to it.
+pub fn peer() -> SocketAddrV4 {
+    SocketAddrV4::new(PEER, 40000)
+}
+
+/// Where the interface listens for it.
+pub fn us() -> SocketAddrV4 {
+    SocketAddrV4::new(US, 80)
+}
+
+/// How often the packet loop ticks, in virtual time.
+pub const TICK: Duration = Duration::from_millis(10);
+
+/// Whether `fd` has something to read, without waiting.
+pub fn readable(fd: RawFd) -> bool {
+    let mut pfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut pfd, 1, 0) > 0 && pfd.revents & libc::POLLIN != 0 }
+}
+
+/// A TCP segment as it went over the network, taken apart.
+#[derive(Clone, Debug)]
+pub struct Segment {
+    pub src: SocketAddrV4,
+    pub dst: SocketAddrV4,
+    pub seq: u32,
+    /// the acknowledgment number, if the ACK flag is set
+    pub ack: Option<u32>,
+    pub syn: bool,
+    pub fin: bool,
+    pub rst: bool,
+    pub psh: bool,
+    /// the urgent pointer, if the URG flag is set
+    pub urgent: Option<u16>,
+    pub window: u16,
+    pub options: Vec<u8>,
+    pub payload: Vec<u8>,
+    /// the whole IP packet
+    pub packet: Vec<u8>,
+}
+
+impl Segment {
+    pub fn parse(packet: &[u8]) -> Option<Self> {
+        let iph = Ipv4HeaderSlice::from_slice(packet).ok()?;
+        if iph.protocol() != 6 {
+            return None;
+        }
+        let end = (iph.total_len() as usize).min(packet.len());
+        let tcph = TcpHeaderSlice::from_slice(&packet[iph.slice().len()..end]).ok()?;
+        Some(Segment {
+            src: SocketAddrV4::new(iph.source_addr(), tcph.source_port()),
+            dst: SocketAddrV4::new(iph.destination_addr(), tcph.destination_port()),
+            seq: tcph.sequence_number(),
+            ack: tcph.ack().then(|| tcph.acknowledgment_number()),
+            syn: tcph.syn(),
+            fin: tcph.fin(),
+            rst: tcph.rst(),
+            psh: tcph.psh(),
+            urgent: tcph.urg().then(|| tcph.urgent_pointer()),
+            window: tcph.window_size(),
+            options: tcph.options().to_vec(),
+            payload: packet[iph.slice().len() + tcph.slice().len()..end].to_vec(),
+            packet: packet[..end].to_vec(),
+        })
+    }
+
+    /// How much sequence space it takes up.
+    pub fn len(&self) -> u32 {
+        self.payload.len() as u32 + self.syn as u32 + self.fin as u32
+    }
+
+    /// Its flags the way tcpdump writes them, such as `S.` for a SYN-ACK.
+    pub fn flags(&self) -> String {
+        [
+            (self.syn, 'S'),
+            (self.fin, 'F'),
+            (self.rst, 'R'),
+            (self.psh, 'P'),
+            (self.ack.is_some(), '.'),
+            (self.urgent.is_some(), 'U'),
+        ]
+        .iter()
+        .filter(|&&(set, _)| set)
+        .map(|&(_, c)| c)
+        .collect()
+    }
+
+    /// The data of its option `kind`, if it has one.
+    pub fn option(&self, kind: u8) -> Option<&[u8]> {
+        options(&self.options)
+            .into_iter()
+            .find_map(|(k, data)| (k == kind).then_some(data))
+    }
+
+    pub fn mss(&self) -> Option<u16> {
+        self.option(2).map(|d| u16::from_be_bytes([d[0], d[1]]))
+    }
+
+    pub fn wscale(&self) -> Option<u8> {
+        self.option(3).map(|d| d[0])
+    }
+
+    pub fn sack_permitted(&self) -> bool {
+        self.option(4).is_some()
+    }
+
+    /// Its SACK blocks, if it has any.
+    pub fn sack(&self) -> Vec<(u32, u32)> {
+        self.option(5).map_or(Vec::new(), |d| {
+            d.chunks_exact(8)
+                .map(|b| {
+                    (
+                        u32::from_be_bytes(b[..4].try_into().unwrap()),
+                        u32::from_be_bytes(b[4..].try_into().unwrap()),
+                    )
+                })
+                .collect()
+        })
+    }
+
+    /// Its timestamps, as (TSval, TSecr).
+    pub fn timestamps(&self) -> Option<(u32, u32)> {
+        self.option(8).map(|d| {
+            (
+                u32::from_be_bytes(d[..4].try_into().unwrap()),
+                u32::from_be_bytes(d[4..].try_into().unwrap()),
+            )
+        })
+    }
+
+    /// A line describing every header field, for telling two segments apart.
+    pub fn describe(&self) -> String {
+        let mut s = format!(
+            "{} > {} [{}] seq {}",
+            self.src,
+            self.dst,
+            self.flags(),
+            self.seq
+        );
+        if let Some(ack) = self.ack {
+            let _ = write!(s, " ack {}", ack);
+        }
+        let _ = write!(s, " win {} len {}", self.window, self.payload.len());
+        if let Some(urgent) = self.urgent {
+            let _ = write!(s, " urg {}", urgent);
+        }
+        if !self.options.is_empty() {
+            let _ = write!(s, " options {:02x?}", self.options);
+        }
+        s
+    }
+}
+
+/// The options in `raw`, as kinds and their data, with padding left out.
+pub fn options(raw: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut found = Vec::new();
+    let mut at = 0;
+    while at < raw.len() {
+        match raw[at] {
+            0 => break,
+            1 => at += 1,
+            kind => {
+                let len = raw.get(at + 1).map_or(0, |&len| len as usize);
+                if len < 2 || at + len > raw.len() {
+                    break;
+                }
+                found.push((kind, &raw[at + 2..at + len]));
+                at += len;
+            }
+        }
+    }
+    found
+}
+
+/// A segment for the scripted peer to send, built up field by field.
+#[derive(Clone, Debug)]
+pub struct Craft {
+    src: SocketAddrV4,
+    dst: SocketAddrV4,
+    seq: u32,
+    ack: Option<u32>,
+    syn: bool,
+    fin: bool,
+    rst: bool,
+    psh: bool,
+    urgent: Option<u16>,
+    window: u16,
+    options: Vec<u8>,
+    payload: Vec<u8>,
+}
+
+impl Craft {
+    pub fn new(src: SocketAddrV4, dst: SocketAddrV4) -> Self {
+        Craft {
+            src,
+            dst,
+            seq: 0,
+            ack: None,
+            syn: false,
+            fin: false,
+            rst: false,
+            psh: false,
+            urgent: None,
+            window: 65535,
+            options: Vec::new(),
+            payload: Vec::new(),
+        }
+    }
+
+    pub fn seq(mut self, seq: u32) -> Self {
+        self.seq = seq;
+        self
+    }
+
+    pub fn ack(mut self, ack: u32) -> Self {
+        self.ack = Some(ack);
+        self
+    }
+
+    pub fn syn(mut self) -> Self {
+        self.syn = true;
+        self
+    }
+
+    pub fn fin(mut self) -> Self {
+        self.fin = true;
+        self
+    }
+
+    pub fn rst(mut self) -> Self {
+        self.rst = true;
+        self
+    }
+
+    pub fn psh(mut self) -> Self {
+        self.psh = true;
+        self
+    }
+
+    /// Set URG, with the urgent pointer `pointer` bytes past the sequence number.
+    pub fn urgent(mut self, pointer: u16) -> Self {
+        self.urgent = Some(pointer);
+        self
+    }
+
+    pub fn window(mut self, window: u16) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn mss(mut self, mss: u16) -> Self {
+        self.options.extend([2, 4]);
+        self.options.extend(mss.to_be_bytes());
+        self
+    }
+
+    pub fn wscale(mut self, shift: u8) -> Self {
+        self.options.extend([1, 3, 3, shift]);
+        self
+    }
+
+    pub fn sack_permitted(mut self) -> Self {
+        self.options.extend([1, 1, 4, 2]);
+        self
+    }
+
+    pub fn sack(mut self, blocks: &[(u32, u32)]) -> Self {
+        self.options.extend([1, 1, 5, 2 + 8 * blocks.len() as u8]);
+        for &(left, right) in blocks {
+            self.options.extend(left.to_be_bytes());
+            self.options.extend(right.to_be_bytes());
+        }
+        self
+    }
+
+    pub fn timestamps(mut self, val: u32, ecr: u32) -> Self {
+        self.options.extend([1, 1, 8, 10]);
+        self.options.extend(val.to_be_bytes());
+        self.options.extend(ecr.to_be_bytes());
+        self
+    }
+
+    /// Options as they are, well formed or not.
+    pub fn raw_options(mut self, options: &[u8]) -> Self {
+        self.options.extend(options);
+        self
+    }
+
+    /// A Fast Open cookie, or with an empty one, a request for one.
+    pub fn fast_open(mut self, cookie: &[u8]) -> Self {
+        self.options.extend([34, 2 + cookie.len() as u8]);
+        self.options.extend(cookie);
+        self
+    }
+
+    pub fn payload(mut self, payload: &[u8]) -> Self {
+        self.payload = payload.to_vec();
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut options = self.options.clone();
+        while !options.len().is_multiple_of(4) {
+            options.push(0);
+        }
+        let mut b = PacketBuilder::ipv4(self.src.ip().octets(), self.dst.ip().octets(), 64).tcp(
+            self.src.port(),
+            self.dst.port(),
+            self.seq,
+            self.window,
+        );
+        if let Some(ack) = self.ack {
+            b = b.ack(ack);
+        }
+        if self.syn {
+            b = b.syn();
+        }
+        if self.fin {
+            b = b.fin();
+        }
+        if self.rst {
+            b = b.rst();
+        }
+        if self.psh {
+            b = b.psh();
+        }
+        if let Some(pointer) = self.urgent {
+            b = b.urg(pointer);
+        }
+        let b = b.options_raw(&options).unwrap();
+        let mut packet = Vec::with_capacity(b.size(self.payload.len()));
+        b.write(&mut packet, &self.payload).unwrap();
+        packet
+    }
+}
+
+/// An interface on one end of a [`SimNet`], with a scripted peer on the other that sends
+/// what the test crafts and collects what the interface sends.
+pub struct Scripted {
+    pub net: SimNet,
+    pub interface: Interface,
+    pub peer: SimNic,
+    /// the interface's end of the network, to tell whether it has packets waiting
+    fd: RawFd,
+    /// every event the interface has reported, in order, until the test takes them
+    pub events: Vec<InterfaceEvent>,
+    /// every segment the interface has sent, in order, until the test takes them
+    pub sent: Vec<Segment>,
+}
+
+impl Scripted {
+    /// An interface with `config` at [`US`], its timers on the network's clock.
+    pub fn new(config: TcpConfig) -> Self {
+        Self::with(config, |_| {})
+    }
+
+    /// Like [`Scripted::new`], with `setup` setting up anything else on the interface.
+    pub fn with(config: TcpConfig, setup: impl FnOnce(&mut InterfaceBuilder)) -> Self {
+        let (net, nic, peer) =
+            SimNet::new(0, Impairments::default(), Impairments::default()).unwrap();
+        let fd = nic.as_raw_fd();
+        let mut builder = InterfaceBuilder::new();
+        builder.config(config).clock(net.clock());
+        builder.add_nic(nic, &[US]);
+        setup(&mut builder);
+        let interface = builder.build_polled().unwrap();
+        Scripted {
+            net,
+            interface,
+            peer,
+            fd,
+            events: Vec::new(),
+            sent: Vec::new(),
+        }
+    }
+
+    /// Let the interface take in what has arrived and send what it has to, until neither side
+    /// has anything more to do, without moving time.
+    pub fn settle(&mut self) {
+        loop {
+            self.net.advance(Duration::ZERO);
+            let events = self.interface.poll_once(Duration::ZERO).unwrap();
+            let mut busy = !events.is_empty();
+            self.events.extend(events);
+            self.net.advance(Duration::ZERO);
+            let mut buf = [0; 65536];
+            while readable(self.peer.as_raw_fd()) {
+                let n = self.peer.recv(&mut buf).unwrap();
+                busy = true;
+                if let Some(segment) = Segment::parse(&buf[..n]) {
+                    self.sent.push(segment);
+                }
+            }
+            if !busy && !readable(self.fd) {
+                return;
+            }
+        }
+    }
+
+    /// Move virtual time on by `by`, a tick at a time, settling after each step.
+    pub fn advance(&mut self, by: Duration) {
+        let mut left = by;
+        while !left.is_zero() {
+            let step = left.min(TICK);
+            self.net.advance(step);
+            left -= step;
+            self.settle();
+        }
+    }
+
+    /// Have the peer send `segment`, and settle.
+    pub fn send(&mut self, segment: Craft) {
+        self.send_raw(&segment.build());
+    }
+
+    pub fn send_raw(&mut self, packet: &[u8]) {
+        self.peer.send(packet).unwrap();
+        self.settle();
+    }
+
+    /// Take what the interface has sent so far.
+    pub fn take(&mut self) -> Vec<Segment> {
+        std::mem::take(&mut self.sent)
+    }
+
+    /// Take what the interface has sent so far, which must be exactly one segment.
+    pub fn take_one(&mut self) -> Segment {
+        let sent = self.take();
+        assert_eq!(
+            sent.len(),
+            1,
+            "expected one segment, got {:#?}",
+            sent.iter().map(Segment::describe).collect::<Vec<_>>()
+        );
+        sent.into_iter().next().unwrap()
+    }
+
+    /// Take the events reported so far.
+    pub fn take_events(&mut self) -> Vec<InterfaceEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// The connection the listener took in last, from the events reported so far.
+    pub fn accepted(&self) -> Option<Quad> {
+        self.events.iter().rev().find_map(|e| match e {
+            InterfaceEvent::NewConnection(q) => Some(*q),
+            _ => None,
+        })
+    }
+
+    /// Read everything there is on `quad`.
+    pub fn read_all(&mut self, quad: Quad) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut buf = [0; 65536];
+        loop {
+            match self.interface.read_on(quad, &mut buf) {
+                Ok(0) => return data,
+                Ok(n) => data.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return data,
+                Err(e) => panic!("reading failed: {}", e),
+            }
+        }
+    }
+}
+
+/// A listener on `port` of a scripted interface with a handshake done with the peer at
+/// `peer`, whose ISS is `irs`: returns the quad and our ISS.
+pub fn accept(s: &mut Scripted, port: u16, peer: SocketAddrV4, irs: u32) -> (Quad, u32) {
+    let us = SocketAddrV4::new(US, port);
+    s.send(Craft::new(peer, us).syn().seq(irs).mss(1460));
+    let syn_ack = s.take_one();
+    assert!(syn_ack.syn && syn_ack.ack == Some(irs.wrapping_add(1)));
+    let iss = syn_ack.seq;
+    s.send(
+        Craft::new(peer, us)
+            .seq(irs.wrapping_add(1))
+            .ack(iss.wrapping_add(1)),
+    );
+    let quad = s.accepted().expect("the listener took the connection in");
+    (quad, iss)
+}
+
+/// A connection from the scripted interface to the peer at `peer`, whose ISS is `irs`, with
+/// the handshake done: returns the stream and our ISS. The peer's SYN-ACK carries an MSS of
+/// 1460 and nothing else.
+pub fn connect(s: &mut Scripted, peer: SocketAddrV4, irs: u32) -> (TcpStream, u32) {
+    let stream = s.interface.connect(US, peer).unwrap();
+    let us = stream.quad().local();
+    s.advance(TICK);
+    let syn = s.take_one();
+    assert_eq!(syn.flags(), "S");
+    let iss = syn.seq;
+    s.send(
+        Craft::new(peer, us)
+            .syn()
+            .seq(irs)
+            .ack(iss.wrapping_add(1))
+            .mss(1460),
+    );
+    let ack = s.take_one();
+    assert_eq!(ack.ack, Some(irs.wrapping_add(1)));
+    (stream, iss)
+}
+
+/// Two interfaces on either end of a [`SimNet`], at [`US`] and [`PEER`].
+pub struct Pair {
+    pub net: SimNet,
+    pub a: Interface,
+    pub b: Interface,
+    fds: [RawFd; 2],
+    pub events_a: Vec<InterfaceEvent>,
+    pub events_b: Vec<InterfaceEvent>,
+}
+
+impl Pair {
+    pub fn new(
+        seed: u64,
+        a_to_b: Impairments,
+        b_to_a: Impairments,
+        a: TcpConfig,
+        b: TcpConfig,
+    ) -> Self {
+        let (net, nic_a, nic_b) = SimNet::new(seed, a_to_b, b_to_a).unwrap();
+        let fds = [nic_a.as_raw_fd(), nic_b.as_raw_fd()];
+        let build = |nic, addr, config| {
+            let mut builder = InterfaceBuilder::new();
+            builder.config(config).clock(net.clock());
+            builder.add_nic(nic, &[addr]);
+            builder.build_polled().unwrap()
+        };
+        let a = build(nic_a, US, a);
+        let b = build(nic_b, PEER, b);
+        Pair {
+            net,
+            a,
+            b,
+            fds,
+            events_a: Vec::new(),
+            events_b: Vec::new(),
+        }
+    }
+
+    /// Let both interfaces take in what has arrived, back and forth, until there is nothing
+    /// left on the network that has arrived, without moving time.
+    pub fn settle(&mut self) {
+        loop {
+            self.net.advance(Duration::ZERO);
+            let events = self.a.poll_once(Duration::ZERO).unwrap();
+            self.events_a.extend(events);
+            self.net.advance(Duration::ZERO);
+            let events = self.b.poll_once(Duration::ZERO).unwrap();
+            self.events_b.extend(events);
+            self.net.advance(Duration::ZERO);
+            if !self.fds.iter().any(|&fd| readable(fd)) {
+                return;
+            }
+        }
+    }
+
+    /// Move virtual time on by `step`, no more than a tick, and settle.
+    pub fn step(&mut self, step: Duration) {
+        self.net.advance(step);
+        self.settle();
+    }
+}
+
+/// The state of `quad` on `interface`, if it still has the connection.
+pub fn state(interface: &Interface, quad: Quad) -> Option<State> {
+    connection(interface, quad).map(|info| info.state)
+}
+
+pub fn connection(interface: &Interface, quad: Quad) -> Option<ConnectionInfo> {
+    interface
+        .connections()
+        .into_iter()
+        .find_map(|(q, info)| (q == quad).then_some(info))
+}
+
+/// `len` bytes that differ from one run of them to the next, seeded by `seed`, so data that
+/// arrives out of place does not go unnoticed.
+pub fn pattern(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            // SplitMix64
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            (z ^ (z >> 31)) as u8
+        })
+        .collect()
+}
+
+/// One end of an [`Pair::exchange`].
+struct End<'a> {
+    quad: Option<Quad>,
+    out: &'a [u8],
+    written: usize,
+    shut: bool,
+    got: Vec<u8>,
+    eof: bool,
+    info: Option<ConnectionInfo>,
+}
+
+impl<'a> End<'a> {
+    fn new(quad: Option<Quad>, out: &'a [u8]) -> Self {
+        End {
+            quad,
+            out,
+            written: 0,
+            shut: false,
+            got: Vec::new(),
+            eof: false,
+            info: None,
+        }
+    }
+
+    /// Write what it can, shutting down for writing once all of it is, and read what there
+    /// is. Returns how many bytes moved.
+    fn pump(&mut self, interface: &mut Interface) -> usize {
+        let Some(quad) = self.quad else {
+            return 0;
+        };
+        let mut moved = 0;
+        while self.written < self.out.len() {
+            match interface.write_on(quad, &self.out[self.written..]) {
+                Ok(n) => {
+                    self.written += n;
+                    moved += n;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => panic!("writing to {:?} failed: {}", quad, e),
+            }
+        }
+        if self.written == self.out.len() && !self.shut {
+            interface.shutdown_on(quad, Shutdown::Write).unwrap();
+            self.shut = true;
+        }
+        let mut buf = [0; 65536];
+        while !self.eof {
+            match interface.read_on(quad, &mut buf) {
+                Ok(0) => {
+                    self.eof = true;
+                    self.info = connection(interface, quad);
+                }
+                Ok(n) => {
+                    self.got.extend_from_slice(&buf[..n]);
+                    moved += n;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => panic!("reading from {:?} failed: {}", quad, e),
+            }
+        }
+        moved
+    }
+}
+
+/// What came of a [`Pair::exchange`].
+pub struct Exchange {
+    /// what `b` read
+    pub at_b: Vec<u8>,
+    /// what `a` read
+    pub at_a: Vec<u8>,
+    /// the connection on either end as it was once its end of the stream was read
+    pub info_a: ConnectionInfo,
+    pub info_b: ConnectionInfo,
+    /// how long it all took, in virtual time
+    pub took: Duration,
+}
+
+impl Pair {
+    /// Connect `a` to a listener on `port` of `b`, have each end write what it has for the
+    /// other and then shut down for writing, and read until the end of the stream on both,
+    /// a tick at a time. Then let both ends finish closing, and fail if either does not get to
+    /// TIME-WAIT or closed. Panics if no data moves for `stall` of virtual time, with where
+    /// either end was stuck.
+    pub fn exchange(&mut self, port: u16, to_b: &[u8], to_a: &[u8], stall: Duration) -> Exchange {
+        let start = self.net.now();
+        let _listener = self.b.bind(port).unwrap();
+        let quad = self
+            .a
+            .connect(US, SocketAddrV4::new(PEER, port))
+            .unwrap()
+            .into_quad();
+        let mut a = End::new(Some(quad), to_b);
+        let mut b = End::new(None, to_a);
+        let mut last_moved = self.net.now();
+        while !(a.eof && b.eof && a.shut && b.shut) {
+            self.step(TICK);
+            for e in self.events_a.drain(..).chain(self.events_b.drain(..)) {
+                match e {
+                    InterfaceEvent::NewConnection(q) => b.quad = Some(q),
+                    InterfaceEvent::Error(q, e, _) => panic!("{:?} failed: {}", q, e),
+                    _ => {}
+                }
+            }
+            if a.pump(&mut self.a) + b.pump(&mut self.b) > 0 {
+                last_moved = self.net.now();
+            }
+            if self.net.now() - last_moved > stall {
+                panic!(
+                    "stalled at {:?}: a wrote {}/{} read {} ({:#?}), b wrote {}/{} read {} ({:#?})",
+                    self.net.now(),
+                    a.written,
+                    a.out.len(),
+                    a.got.len(),
+                    connection(&self.a, quad),
+                    b.written,
+                    b.out.len(),
+                    b.got.len(),
+                    b.quad.and_then(|q| connection(&self.b, q)),
+                );
+            }
+        }
+        let took = self.net.now() - start;
+
+        // the last FIN and its ACK may still be on their way
+        let quad_b = b.quad.unwrap();
+        let done = |s| matches!(s, None | Some(State::TimeWait | State::Closed));
+        while !(done(state(&self.a, quad)) && done(state(&self.b, quad_b))) {
+            self.step(TICK);
+            if self.net.now() - last_moved > stall {
+                panic!(
+                    "did not finish closing: a in {:?}, b in {:?}",
+                    state(&self.a, quad),
+                    state(&self.b, quad_b)
+                );
+            }
+        }
+        self.a.close_on(quad);
+        self.b.close_on(quad_b);
+        Exchange {
+            at_b: b.got,
+            at_a: a.got,
+            info_a: a.info.unwrap(),
+            info_b: b.info.unwrap(),
+            took,
+        }
+    }
+}
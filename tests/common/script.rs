@@ -0,0 +1,319 @@
+//! Tests written as scripts of timed steps, after packetdrill: what the peer sends, what the
+//! interface has to send back, and what the application does in between.
+//!
+//! ```text
+//! # a passive open
+//! +0    bind 80
+//! +0    < S seq=0 win=65535 <mss 1460>
+//! +0    > S. seq=0 ack=1 <mss 1460>
+//! +0    < . seq=1 ack=1
+//! +0    state Estab
+//! +0    write 100
+//! +0.01 > P. seq=1 ack=1 len=100
+//! ```
+//!
+//! Each line starts with how long after the last one it happens, in seconds, and virtual time
+//! moves on that much, a tick at a time, before it does. Then:
+//!
+//! - `< FLAGS FIELDS [<OPTIONS>]`: the peer sends a segment. `FLAGS` are tcpdump's (`S`, `F`,
+//!   `R`, `P` and `.` for the ACK flag), `FIELDS` any of `seq=`, `ack=`, `win=` and `len=` (of
+//!   the payload, which is made up), and `OPTIONS` any of `mss N`, `wscale N`, `sackOK` and
+//!   `TS val N ecr N`, separated by commas.
+//! - `> FLAGS FIELDS [<OPTIONS>]`: the next segment the interface sent has those flags, and
+//!   whichever fields and options the line gives.
+//! - `bind PORT`, `connect`, `write N`, `close`: the application listens, opens the connection
+//!   actively, writes N bytes or closes it.
+//! - `state STATE`: the connection is in `STATE`, as [`State`] spells it, or `gone`.
+//!
+//! Sequence numbers are relative to either side's ISS: ours is fixed, and the peer's is
+//! [`PEER_ISS`]. The peer is [`PEER`]:40000, and we are [`US`]:80 unless we opened the
+//! connection. A segment the interface sends that no `>` line expects fails the script.
+
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use trust::{Quad, TcpConfig};
+
+use super::{state, Craft, Scripted, Segment, PEER, US};
+
+/// Our ISS, fixed for the interface.
+pub const ISS: u32 = 0x1000_0000;
+/// The peer's ISS, which its sequence numbers count from.
+pub const PEER_ISS: u32 = 1000;
+
+/// A segment as a line of the script gives it: everything but the flags is left to the line.
+#[derive(Debug, Default)]
+struct Spec {
+    flags: String,
+    seq: Option<u32>,
+    ack: Option<u32>,
+    window: Option<u16>,
+    len: Option<usize>,
+    /// the options in the order given, as `Craft` would write them
+    options: Option<Vec<String>>,
+}
+
+impl Spec {
+    fn parse(words: &[&str], rest: &str) -> Result<Spec, String> {
+        let (flags, fields) = words.split_first().ok_or("no flags")?;
+        if !flags.chars().all(|c| "SFRP.".contains(c)) {
+            return Err(format!("bad flags {:?}", flags));
+        }
+        let mut spec = Spec {
+            flags: flags.to_string(),
+            ..Spec::default()
+        };
+        for field in fields {
+            let (name, value) = field
+                .split_once('=')
+                .ok_or_else(|| format!("bad field {:?}", field))?;
+            let bad = |_| format!("bad number in {:?}", field);
+            match name {
+                "seq" => spec.seq = Some(value.parse().map_err(bad)?),
+                "ack" => spec.ack = Some(value.parse().map_err(bad)?),
+                "win" => spec.window = Some(value.parse().map_err(bad)?),
+                "len" => spec.len = Some(value.parse().map_err(bad)?),
+                _ => return Err(format!("unknown field {:?}", name)),
+            }
+        }
+        if let Some(options) = rest.strip_prefix('<') {
+            let options = options.strip_suffix('>').ok_or("options are not closed")?;
+            spec.options = Some(
+                options
+                    .split(',')
+                    .map(|o| o.split_whitespace().collect::<Vec<_>>().join(" "))
+                    .collect(),
+            );
+        }
+        Ok(spec)
+    }
+}
+
+/// The options of `segment`, written the way a script gives them.
+fn describe_options(segment: &Segment) -> Vec<String> {
+    let mut found = Vec::new();
+    if let Some(mss) = segment.mss() {
+        found.push(format!("mss {}", mss));
+    }
+    if let Some(shift) = segment.wscale() {
+        found.push(format!("wscale {}", shift));
+    }
+    if segment.sack_permitted() {
+        found.push("sackOK".to_string());
+    }
+    if let Some((val, ecr)) = segment.timestamps() {
+        found.push(format!("TS val {} ecr {}", val, ecr));
+    }
+    found
+}
+
+/// A script being run: the interface, and the connection the script is about once there is
+/// one.
+struct Run {
+    s: Scripted,
+    us: SocketAddrV4,
+    quad: Option<Quad>,
+    listeners: Vec<trust::TcpListener>,
+}
+
+impl Run {
+    /// The connection with the peer, once there is one, whether or not it has been accepted.
+    fn quad(&mut self) -> Result<Quad, String> {
+        if self.quad.is_none() {
+            let peer = SocketAddrV4::new(PEER, 40000);
+            self.quad = (self.s.interface.connections().into_iter())
+                .map(|(quad, _)| quad)
+                .find(|quad| quad.remote() == peer);
+        }
+        self.quad
+            .ok_or_else(|| "there is no connection yet".to_string())
+    }
+
+    fn send(&mut self, spec: &Spec) -> Result<(), String> {
+        let peer = SocketAddrV4::new(PEER, 40000);
+        let mut craft = Craft::new(peer, self.us)
+            .seq(PEER_ISS.wrapping_add(spec.seq.unwrap_or(0)))
+            .window(spec.window.unwrap_or(65535));
+        for flag in spec.flags.chars() {
+            craft = match flag {
+                'S' => craft.syn(),
+                'F' => craft.fin(),
+                'R' => craft.rst(),
+                'P' => craft.psh(),
+                _ => {
+                    let ack = spec.ack.ok_or("an ACK needs ack=")?;
+                    craft.ack(ISS.wrapping_add(ack))
+                }
+            };
+        }
+        for option in spec.options.iter().flatten() {
+            let words: Vec<_> = option.split(' ').collect();
+            let number = |i: usize| {
+                words
+                    .get(i)
+                    .and_then(|w| w.parse::<u32>().ok())
+                    .ok_or_else(|| format!("bad option {:?}", option))
+            };
+            craft = match words[0] {
+                "mss" => craft.mss(number(1)? as u16),
+                "wscale" => craft.wscale(number(1)? as u8),
+                "sackOK" => craft.sack_permitted(),
+                "TS" => craft.timestamps(number(2)?, number(4)?),
+                _ => return Err(format!("unknown option {:?}", option)),
+            };
+        }
+        let payload: Vec<u8> = (0..spec.len.unwrap_or(0)).map(|i| i as u8).collect();
+        self.s.send(craft.payload(&payload));
+        Ok(())
+    }
+
+    fn expect(&mut self, spec: &Spec) -> Result<(), String> {
+        if self.s.sent.is_empty() {
+            return Err("nothing was sent".to_string());
+        }
+        let got = self.s.sent.remove(0);
+        let wrong = |what: &str| {
+            Err(format!(
+                "{} differs in what was sent: {}",
+                what,
+                got.describe()
+            ))
+        };
+        if got.flags() != spec.flags {
+            return wrong("flags");
+        }
+        if spec.seq.is_some_and(|seq| got.seq != ISS.wrapping_add(seq)) {
+            return wrong("seq");
+        }
+        if spec.ack.is_some() && got.ack != spec.ack.map(|ack| PEER_ISS.wrapping_add(ack)) {
+            return wrong("ack");
+        }
+        if spec.window.is_some_and(|window| got.window != window) {
+            return wrong("win");
+        }
+        if spec.len.is_some_and(|len| got.payload.len() != len) {
+            return wrong("len");
+        }
+        if spec
+            .options
+            .as_ref()
+            .is_some_and(|options| *options != describe_options(&got))
+        {
+            return wrong("options");
+        }
+        Ok(())
+    }
+
+    fn step(&mut self, words: &[&str], rest: &str) -> Result<(), String> {
+        let arg = || -> Result<usize, String> {
+            words
+                .get(1)
+                .and_then(|w| w.parse().ok())
+                .ok_or_else(|| format!("{} needs a number", words[0]))
+        };
+        match words[0] {
+            "<" => self.send(&Spec::parse(&words[1..], rest)?),
+            ">" => self.expect(&Spec::parse(&words[1..], rest)?),
+            "bind" => {
+                let port = arg()? as u16;
+                let listener = self.s.interface.bind(port).map_err(|e| e.to_string())?;
+                self.listeners.push(listener);
+                Ok(())
+            }
+            "connect" => {
+                let stream = self
+                    .s
+                    .interface
+                    .connect(US, SocketAddrV4::new(PEER, 40000))
+                    .map_err(|e| e.to_string())?;
+                let quad = stream.into_quad();
+                self.us = quad.local();
+                self.quad = Some(quad);
+                self.s.settle();
+                Ok(())
+            }
+            "write" => {
+                let quad = self.quad()?;
+                let n = arg()?;
+                let data: Vec<u8> = (0..n).map(|i| i as u8).collect();
+                self.s
+                    .interface
+                    .write_on(quad, &data)
+                    .map_err(|e| e.to_string())?;
+                Ok(())
+            }
+            "close" => {
+                let quad = self.quad()?;
+                self.s.interface.close_on(quad);
+                self.s.settle();
+                Ok(())
+            }
+            "state" => {
+                let quad = self.quad()?;
+                let got = state(&self.s.interface, quad)
+                    .map_or("gone".to_string(), |state| format!("{:?}", state));
+                match words.get(1) {
+                    Some(&want) if want == got => Ok(()),
+                    want => Err(format!("the connection is {}, not {:?}", got, want)),
+                }
+            }
+            other => Err(format!("unknown step {:?}", other)),
+        }
+    }
+}
+
+/// Run `script` against an interface with `config`, panicking at the first line that does not
+/// hold, with what went wrong.
+pub fn run(config: TcpConfig, script: &str) {
+    let mut run = Run {
+        s: Scripted::with(config, |builder| {
+            builder.fixed_iss(ISS);
+        }),
+        us: SocketAddrV4::new(US, 80),
+        quad: None,
+        listeners: Vec::new(),
+    };
+    for (n, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fail = |e: String| panic!("line {}: {}\n    {}", n + 1, e, line);
+        let (time, step) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let Some(delay) = time
+            .strip_prefix('+')
+            .and_then(|time| time.parse::<f64>().ok())
+        else {
+            fail("a step starts with +SECONDS".to_string())
+        };
+        // the options, if any, are the rest of the step from the first '<' after its first
+        // character, which may be a '<' itself
+        let step = step.trim_start();
+        let (head, rest) = match step.get(1..).and_then(|after| after.find('<')) {
+            Some(at) => step.split_at(at + 1),
+            None => (step, ""),
+        };
+        let words: Vec<_> = head.split_whitespace().collect();
+        if words.is_empty() {
+            fail("nothing to do".to_string());
+        }
+        run.s.advance(Duration::from_secs_f64(delay));
+        if words[0] != ">" {
+            if let Some(extra) = run.s.sent.first() {
+                fail(format!(
+                    "{} was sent, which nothing expects",
+                    extra.describe()
+                ));
+            }
+        }
+        if let Err(e) = run.step(&words, rest.trim()) {
+            fail(e);
+        }
+    }
+    if let Some(extra) = run.s.sent.first() {
+        panic!(
+            "after the script: {} was sent, which nothing expects",
+            extra.describe()
+        );
+    }
+}
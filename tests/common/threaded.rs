@@ -0,0 +1,81 @@
+//! An interface with a packet loop of its own, for the tests that need connections to wait in
+//! a listener's accept queue until they are accepted, which a polled interface hands out as
+//! soon as it takes them in. The packet loop runs on the system clock, so these wait for
+//! quiet rather than step time.
+
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+
+use trust::{Impairments, Interface, InterfaceBuilder, Nic, SimNet, SimNic, TcpConfig};
+
+use super::{readable, Craft, Segment, US};
+
+/// A threaded interface at [`US`], and a peer that crafts segments for it.
+pub struct Threaded {
+    _net: SimNet,
+    pub interface: Interface,
+    pub peer: SimNic,
+}
+
+impl Threaded {
+    pub fn new() -> Self {
+        Self::with(TcpConfig::default())
+    }
+
+    pub fn with(config: TcpConfig) -> Self {
+        let (net, nic, peer) =
+            SimNet::new(0, Impairments::default(), Impairments::default()).unwrap();
+        let mut builder = InterfaceBuilder::new();
+        builder.config(config);
+        builder.add_nic(nic, &[US]);
+        Threaded {
+            _net: net,
+            interface: builder.build().unwrap(),
+            peer,
+        }
+    }
+
+    pub fn send(&mut self, segment: Craft) {
+        self.peer.send(&segment.build()).unwrap();
+    }
+
+    /// Everything the interface sends until it has been quiet for a while.
+    pub fn take(&mut self) -> Vec<Segment> {
+        let mut sent = Vec::new();
+        let mut quiet_since = Instant::now();
+        let mut buf = [0; 65536];
+        while quiet_since.elapsed() < Duration::from_millis(100) {
+            if readable(self.peer.as_raw_fd()) {
+                let n = self.peer.recv(&mut buf).unwrap();
+                sent.extend(Segment::parse(&buf[..n]));
+                quiet_since = Instant::now();
+            } else {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+        sent
+    }
+
+    /// The next segment the interface sends, without waiting for quiet after it.
+    pub fn next(&mut self) -> Segment {
+        let start = Instant::now();
+        let mut buf = [0; 65536];
+        loop {
+            assert!(start.elapsed() < Duration::from_secs(5), "nothing was sent");
+            if readable(self.peer.as_raw_fd()) {
+                let n = self.peer.recv(&mut buf).unwrap();
+                if let Some(segment) = Segment::parse(&buf[..n]) {
+                    return segment;
+                }
+            } else {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+
+    pub fn take_one(&mut self) -> Segment {
+        let mut sent = self.take();
+        assert_eq!(sent.len(), 1, "expected one segment");
+        sent.pop().unwrap()
+    }
+}
@@ -0,0 +1,11 @@
+//! What the public API must not let through: each case in `tests/ui` has to fail to compile,
+//! with the errors next to it. `TRYBUILD=overwrite` writes them out afresh.
+//!
+//! The errors list the types that do implement a trait, which depend on the features enabled,
+//! so the cases are only checked with the default ones.
+
+#[test]
+#[cfg(not(feature = "backend-raw"))]
+fn ui() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
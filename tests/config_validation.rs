@@ -0,0 +1,79 @@
+//! Configurations no connection could run with are turned down when an interface is built,
+//! with everything wrong with them listed, while settings past their soft limits are only
+//! clamped.
+
+mod common;
+
+use std::io;
+use std::time::Duration;
+
+use common::US;
+use trust::{ConfigError, ConfigProblem, Impairments, InterfaceBuilder, SimNet, TcpConfig};
+
+/// Three things wrong, given out of the order of the fields.
+fn broken() -> TcpConfig {
+    TcpConfig {
+        min_rto: Some(Duration::from_secs(5)),
+        max_rto: Some(Duration::from_secs(1)),
+        recv_buffer: 0,
+        send_buffer: 0,
+        ..TcpConfig::default()
+    }
+}
+
+#[test]
+fn every_problem_is_listed_in_field_order() {
+    let err = broken().validate().unwrap_err();
+    assert_eq!(
+        err.problems,
+        [
+            ConfigProblem::NoSendBuffer,
+            ConfigProblem::NoRecvBuffer,
+            ConfigProblem::MinRtoAboveMaxRto
+        ]
+    );
+    assert_eq!(
+        err.to_string(),
+        "invalid TCP configuration: send_buffer is 0; recv_buffer is 0; min_rto is above max_rto"
+    );
+}
+
+#[test]
+fn building_an_interface_with_one_fails() {
+    let (net, nic, _peer) = SimNet::new(0, Impairments::default(), Impairments::default()).unwrap();
+    let mut builder = InterfaceBuilder::new();
+    builder.config(broken()).clock(net.clock());
+    builder.add_nic(nic, &[US]);
+    let err = builder.build_polled().map(|_| ()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(
+        ConfigError::of(&err),
+        Some(&broken().validate().unwrap_err())
+    );
+}
+
+#[test]
+fn soft_limits_are_clamped() {
+    let config = TcpConfig {
+        abc_limit: 9,
+        recv_buffer: usize::MAX,
+        ..TcpConfig::default()
+    }
+    .validate()
+    .unwrap();
+    assert_eq!(config.abc_limit, 2);
+    assert_eq!(config.recv_buffer, 65535 << 14);
+}
+
+#[test]
+fn the_default_is_valid() {
+    let config = TcpConfig::default().validate().unwrap();
+    assert_eq!(config.recv_buffer, TcpConfig::default().recv_buffer);
+    assert_eq!(config.abc_limit, TcpConfig::default().abc_limit);
+}
+
+#[test]
+fn an_io_error_of_another_kind_has_none() {
+    let err = io::Error::new(io::ErrorKind::InvalidInput, "something else");
+    assert!(ConfigError::of(&err).is_none());
+}
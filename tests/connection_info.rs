@@ -0,0 +1,123 @@
+//! `TcpStream::info`: what the handshake settled, as the peer's SYN-ACK offered it, and the
+//! peer's window as it goes on to advertise it, scaled.
+
+mod common;
+
+use std::io::Write;
+use std::time::Duration;
+
+use common::{peer, Craft, Scripted, Segment, TICK, US};
+use trust::{State, TcpConfig, TcpStream};
+
+/// Big enough that our SYN offers a window scale, or the peer's would go unused.
+const RECV_BUFFER: usize = 1 << 20;
+
+/// Open a connection to the peer, and hand back the stream with our SYN, before any answer.
+fn connect(s: &mut Scripted) -> (TcpStream, Segment) {
+    let stream = s.interface.connect(US, peer()).unwrap();
+    s.advance(TICK);
+    (stream, s.take_one())
+}
+
+#[test]
+fn reports_what_the_syn_ack_agreed_to() {
+    let mut s = Scripted::new(TcpConfig {
+        recv_buffer: RECV_BUFFER,
+        ..TcpConfig::default()
+    });
+    let (stream, syn) = connect(&mut s);
+    let ours = syn.wscale().expect("our SYN offers a window scale");
+    assert_eq!(stream.info().unwrap().state, State::SynSent);
+    assert_eq!(stream.info().unwrap().peer_mss, None);
+
+    s.send(
+        Craft::new(peer(), syn.src)
+            .syn()
+            .seq(5000)
+            .ack(syn.seq.wrapping_add(1))
+            .window(1000)
+            .mss(1200)
+            .wscale(3)
+            .sack_permitted()
+            .timestamps(7, syn.timestamps().unwrap().0),
+    );
+    let info = stream.info().unwrap();
+    assert_eq!(info.state, State::Estab);
+    assert_eq!(info.peer_mss, Some(1200));
+    assert_eq!(info.mss, 1200);
+    assert_eq!((info.rcv_wscale, info.snd_wscale), (ours, 3));
+    assert!(info.sack && info.timestamps);
+    assert!(!info.ecn && !info.fast_open);
+    // a SYN's window is never scaled
+    assert_eq!(info.peer_window, 1000);
+}
+
+#[test]
+fn leaves_out_what_the_syn_ack_did_not_offer() {
+    let mut s = Scripted::new(TcpConfig {
+        recv_buffer: RECV_BUFFER,
+        ..TcpConfig::default()
+    });
+    let (stream, syn) = connect(&mut s);
+    s.send(
+        Craft::new(peer(), syn.src)
+            .syn()
+            .seq(5000)
+            .ack(syn.seq.wrapping_add(1)),
+    );
+    let info = stream.info().unwrap();
+    // no MSS option means 536 (RFC 1122 S4.2.2.6)
+    assert_eq!((info.peer_mss, info.mss), (Some(536), 536));
+    assert_eq!((info.rcv_wscale, info.snd_wscale), (0, 0));
+    assert!(!info.sack && !info.timestamps);
+}
+
+#[test]
+fn negotiated_fields_hold_while_the_window_and_rtt_move() {
+    let mut s = Scripted::new(TcpConfig {
+        recv_buffer: RECV_BUFFER,
+        ..TcpConfig::default()
+    });
+    let (stream, syn) = connect(&mut s);
+    let us = syn.src;
+    let iss = syn.seq;
+    s.advance(Duration::from_millis(40));
+    s.send(
+        Craft::new(peer(), us)
+            .syn()
+            .seq(5000)
+            .ack(iss.wrapping_add(1))
+            .mss(1460)
+            .wscale(4),
+    );
+    s.take();
+    let at_handshake = stream.info().unwrap();
+    assert_eq!(at_handshake.srtt, Duration::from_millis(40));
+
+    (&stream).write_all(&[1; 100]).unwrap();
+    s.advance(Duration::from_millis(100));
+    s.take();
+    s.send(
+        Craft::new(peer(), us)
+            .seq(5001)
+            .ack(iss.wrapping_add(101))
+            .window(5000),
+    );
+    let later = stream.info().unwrap();
+    assert_eq!(later.peer_window, 5000 << 4);
+    assert_ne!(later.srtt, at_handshake.srtt);
+    assert_eq!(
+        (
+            later.mss,
+            later.peer_mss,
+            later.rcv_wscale,
+            later.snd_wscale
+        ),
+        (
+            at_handshake.mss,
+            at_handshake.peer_mss,
+            at_handshake.rcv_wscale,
+            at_handshake.snd_wscale
+        )
+    );
+}
@@ -0,0 +1,97 @@
+//! Corking: while a stream is corked, only full-sized segments leave, so a header and the body
+//! written after it share a segment, until the cork comes out or has been in too long.
+
+mod common;
+
+use std::io::Write;
+use std::net::Shutdown;
+use std::time::Duration;
+
+use common::{connect, peer, Craft, Scripted, TICK};
+use trust::TcpConfig;
+
+#[test]
+fn a_header_and_a_body_go_out_as_one_segment() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    stream.set_cork(true).unwrap();
+    assert!(stream.cork().unwrap());
+
+    stream.write_all(&[b'h'; 100]).unwrap();
+    s.advance(TICK);
+    stream.write_all(&[b'b'; 1000]).unwrap();
+    s.advance(TICK);
+    assert!(s.take().is_empty(), "nothing goes while corked");
+
+    stream.set_cork(false).unwrap();
+    s.advance(TICK);
+    let seg = s.take_one();
+    assert_eq!((seg.seq, seg.payload.len()), (iss.wrapping_add(1), 1100));
+    assert_eq!(&seg.payload[..101], [&[b'h'; 100][..], b"b"].concat());
+}
+
+#[test]
+fn full_segments_leave_while_the_rest_waits() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, _) = connect(&mut s, peer(), 1000);
+    stream.set_cork(true).unwrap();
+    stream.write_all(&[0; 3000]).unwrap();
+    s.advance(TICK);
+    let sizes: Vec<_> = s.take().iter().map(|seg| seg.payload.len()).collect();
+    assert_eq!(sizes, [1460, 1460]);
+
+    // closing is as good as uncorking: the tail goes with the FIN
+    stream.shutdown(Shutdown::Write).unwrap();
+    s.advance(TICK);
+    let tail = s.take_one();
+    assert_eq!((tail.payload.len(), tail.fin), (80, true));
+}
+
+#[test]
+fn a_forgotten_cork_comes_out_by_itself() {
+    let mut s = Scripted::new(TcpConfig {
+        cork_timeout: Duration::from_millis(200),
+        ..TcpConfig::default()
+    });
+    let (mut stream, _) = connect(&mut s, peer(), 1000);
+    stream.set_cork(true).unwrap();
+    stream.write_all(b"stuck").unwrap();
+    s.advance(Duration::from_millis(150));
+    assert!(s.take().is_empty());
+    s.advance(Duration::from_millis(60));
+    assert_eq!(s.take_one().payload, b"stuck");
+    assert!(
+        stream.cork().unwrap(),
+        "the stream stays corked for what comes next"
+    );
+}
+
+#[test]
+fn the_cork_holds_even_where_nagle_would_not() {
+    // with Nagle on and nothing in flight, a small segment would go at once; corked, it waits
+    let mut s = Scripted::new(TcpConfig {
+        nagle: true,
+        ..TcpConfig::default()
+    });
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    stream.set_cork(true).unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+    s.advance(TICK);
+    assert!(s.take().is_empty());
+
+    // uncorked, it goes, and Nagle then holds what follows until it is acknowledged
+    stream.set_cork(false).unwrap();
+    s.advance(TICK);
+    assert_eq!(s.take_one().payload, b"GET / HTTP/1.1\r\n");
+    stream.write_all(b"Host: x\r\n\r\n").unwrap();
+    s.advance(TICK);
+    assert!(s.take().is_empty());
+    let quad = stream.quad();
+    s.send(
+        Craft::new(quad.remote(), quad.local())
+            .seq(1001)
+            .ack(iss.wrapping_add(17)),
+    );
+    s.advance(TICK);
+    assert_eq!(s.take_one().payload, b"Host: x\r\n\r\n");
+}
@@ -0,0 +1,146 @@
+//! Congestion window validation (RFC 7661) for an application that sends in bursts: the window
+//! grows only while the connection fills it, and across an idle gap it decays towards what was
+//! used rather than staying inflated or falling back to the initial window.
+
+mod common;
+
+use std::io;
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use common::{connection, Pair, PEER, TICK, US};
+use trust::{Impairments, InterfaceEvent, Quad, TcpConfig};
+
+/// The initial window, of ten 1460-byte segments.
+const IW: u32 = 14600;
+const BURST: usize = 100_000;
+const IDLE: Duration = Duration::from_secs(5);
+
+/// A connection from `a` to `b` over a 20ms round trip, with `a` sending and `b` reading all
+/// it gets.
+struct Bursts {
+    pair: Pair,
+    a: Quad,
+    b: Quad,
+}
+
+impl Bursts {
+    fn new(config: TcpConfig) -> Self {
+        let link = Impairments {
+            latency: Duration::from_millis(10),
+            ..Impairments::default()
+        };
+        let mut pair = Pair::new(164, link.clone(), link, config, TcpConfig::default());
+        let _listener = pair.b.bind(80).unwrap();
+        let a = pair
+            .a
+            .connect(US, SocketAddrV4::new(PEER, 80))
+            .unwrap()
+            .into_quad();
+        let b = loop {
+            pair.step(TICK);
+            if let Some(InterfaceEvent::NewConnection(quad)) = pair.events_b.pop() {
+                break quad;
+            }
+        };
+        Bursts { pair, a, b }
+    }
+
+    fn step(&mut self) {
+        self.pair.step(TICK);
+        let mut buf = [0; 64 << 10];
+        while let Ok(1..) = self.pair.b.read_on(self.b, &mut buf) {}
+    }
+
+    /// Send `len` bytes, and wait until they are all acknowledged; returns whether the
+    /// connection filled enough of its window on the way to count as validated.
+    fn send(&mut self, len: usize) -> bool {
+        let mut validated = false;
+        let mut left = len;
+        while left > 0 || connection(&self.pair.a, self.a).unwrap().bytes_in_flight > 0 {
+            if left > 0 {
+                match self.pair.a.write_on(self.a, &vec![0; left]) {
+                    Ok(n) => left -= n,
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) => panic!("{}", e),
+                }
+            }
+            self.step();
+            validated |= connection(&self.pair.a, self.a).unwrap().cwnd_validated;
+        }
+        validated
+    }
+
+    fn idle(&mut self, for_: Duration) {
+        let until = self.pair.net.now() + for_;
+        while self.pair.net.now() < until {
+            self.step();
+        }
+    }
+
+    fn cwnd(&self) -> u32 {
+        connection(&self.pair.a, self.a).unwrap().cwnd
+    }
+}
+
+fn config(validation: Option<Duration>, restart: bool) -> TcpConfig {
+    TcpConfig {
+        cwnd_validation: validation,
+        slow_start_after_idle: restart,
+        send_buffer: 256 << 10,
+        ..TcpConfig::default()
+    }
+}
+
+/// The congestion window at the end of each of three bursts, and after the idle gap that
+/// follows it. Each burst fills the window, so is validated on the way.
+fn cycles(config: TcpConfig) -> Vec<(u32, u32)> {
+    let validating = config.cwnd_validation.is_some();
+    let mut b = Bursts::new(config);
+    (0..3)
+        .map(|_| {
+            let validated = b.send(BURST);
+            assert!(validated || !validating);
+            let peak = b.cwnd();
+            b.idle(IDLE);
+            (peak, b.cwnd())
+        })
+        .collect()
+}
+
+#[test]
+fn after_idle_the_window_is_between_the_initial_and_the_inflated() {
+    for (peak, after) in cycles(config(Some(Duration::from_secs(2)), true)) {
+        assert!(peak > 4 * IW, "the window only grew to {}", peak);
+        assert!(
+            IW < after && after < peak,
+            "{} after idle, from {}",
+            after,
+            peak
+        );
+    }
+}
+
+#[test]
+fn without_validation_the_window_keeps_what_it_grew_to() {
+    let seen = cycles(config(None, false));
+    for &(peak, after) in &seen {
+        assert_eq!(after, peak);
+    }
+    // and grows on from there with every burst
+    assert!(seen[2].0 > seen[0].0);
+}
+
+#[test]
+fn an_application_that_sends_too_little_does_not_grow_the_window() {
+    let trickle = |config| {
+        let mut b = Bursts::new(config);
+        for _ in 0..100 {
+            assert!(!b.send(100));
+        }
+        b.cwnd()
+    };
+    assert_eq!(trickle(config(Some(Duration::from_secs(2)), true)), IW);
+    // whereas without validation, every byte acknowledged grows it in slow start
+    assert!(trickle(config(None, false)) > IW);
+}
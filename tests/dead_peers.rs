@@ -0,0 +1,79 @@
+//! Giving up on a peer that has gone away: the FIN-WAIT-2 timeout, which drops a half-closed
+//! connection whose FIN never comes without a word, and the idle timeout, which resets an
+//! established connection that has heard nothing for too long.
+
+mod common;
+
+use std::io;
+use std::net::Shutdown;
+use std::time::Duration;
+
+use common::{connect, peer, Craft, Scripted, TICK};
+use trust::{CloseReason, State, TcpConfig};
+
+#[test]
+fn fin_wait_2_gives_up_quietly() {
+    let mut s = Scripted::new(TcpConfig {
+        fin_wait2_timeout: Duration::from_secs(5),
+        ..TcpConfig::default()
+    });
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+    stream.shutdown(Shutdown::Write).unwrap();
+    s.advance(TICK);
+    assert!(s.take_one().fin);
+    s.send(Craft::new(peer(), us).seq(1001).ack(iss.wrapping_add(2)));
+    assert_eq!(stream.info().unwrap().state, State::FinWait2);
+
+    // the peer still sending data keeps the connection going
+    s.advance(Duration::from_secs(4));
+    s.send(
+        Craft::new(peer(), us)
+            .seq(1001)
+            .ack(iss.wrapping_add(2))
+            .payload(b"x"),
+    );
+    s.take();
+    s.advance(Duration::from_secs(4));
+    assert_eq!(stream.info().unwrap().state, State::FinWait2);
+
+    s.advance(Duration::from_secs(1) + TICK);
+    assert!(s.take().is_empty(), "nothing goes to a peer that is gone");
+    assert_eq!(stream.close_reason(), Some(CloseReason::FinWait2Timeout));
+    let mut buf = [0; 16];
+    assert_eq!(
+        stream.peek(&mut buf).unwrap_err().kind(),
+        io::ErrorKind::TimedOut
+    );
+}
+
+#[test]
+fn idle_timeout_resets_a_silent_connection() {
+    let idle = Duration::from_secs(3);
+    let mut s = Scripted::new(TcpConfig {
+        idle_timeout: Some(idle),
+        ..TcpConfig::default()
+    });
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+
+    s.advance(Duration::from_secs(2));
+    s.send(Craft::new(peer(), us).seq(1001).ack(iss.wrapping_add(1)));
+    s.advance(Duration::from_secs(2));
+    assert_eq!(stream.info().unwrap().state, State::Estab);
+
+    s.advance(Duration::from_secs(1) + TICK);
+    let rst = s.take_one();
+    assert_eq!((rst.flags().as_str(), rst.seq), ("R.", iss.wrapping_add(1)));
+    assert_eq!(stream.close_reason(), Some(CloseReason::IdleTimeout));
+}
+
+#[test]
+fn without_an_idle_timeout_silence_is_no_reason_to_close() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, _) = connect(&mut s, peer(), 1000);
+    s.advance(Duration::from_secs(120));
+    assert!(s.take().is_empty());
+    assert_eq!(stream.info().unwrap().state, State::Estab);
+    assert_eq!(stream.close_reason(), None);
+}
@@ -0,0 +1,131 @@
+//! The tcpdump-style summaries and annotated hexdumps of `debugfmt`, pinned against the golden
+//! fixtures and the Linux capture, and fed every truncation of them.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::golden;
+use trust::debugfmt::{hexdump, summarize};
+
+fn fixture(path: &str) -> String {
+    fs::read_to_string(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join(path),
+    )
+    .unwrap()
+}
+
+fn golden(name: &str) -> Vec<u8> {
+    golden::parse(&fixture(&format!("golden/{}.hex", name)))
+}
+
+/// The packets of the Linux capture, in the order they were sent.
+fn linux() -> Vec<Vec<u8>> {
+    fixture("captures/linux-md5.hex")
+        .split("\n\n")
+        .map(golden::parse)
+        .filter(|packet| !packet.is_empty())
+        .collect()
+}
+
+#[test]
+fn our_segments_read_as_tcpdump_would_print_them() {
+    let cases = [
+        (
+            "syn_ack",
+            "10.0.0.1.80 > 10.0.0.2.40000: Flags [S.], seq 268435456, ack 1001, win 65535, \
+             options [mss 1460,sackOK,TS val 0 ecr 100], length 0",
+        ),
+        (
+            "data",
+            "10.0.0.1.80 > 10.0.0.2.40000: Flags [P.], seq 268435457:268435470, ack 1001, \
+             win 65535, options [nop,nop,TS val 10 ecr 101], length 13",
+        ),
+        (
+            "fin",
+            "10.0.0.1.80 > 10.0.0.2.40000: Flags [F.], seq 268435470, ack 1001, win 65535, \
+             options [nop,nop,TS val 20 ecr 101], length 0",
+        ),
+        (
+            "challenge_ack",
+            "10.0.0.1.80 > 10.0.0.2.40000: Flags [.], seq 268435470, ack 1001, win 65535, \
+             options [nop,nop,TS val 10 ecr 101], length 0",
+        ),
+        (
+            "rst",
+            "10.0.0.1.81 > 10.0.0.2.40000: Flags [R], seq 777, win 0, length 0",
+        ),
+        (
+            "rst_ack",
+            "10.0.0.1.81 > 10.0.0.2.40000: Flags [R.], seq 0, ack 1001, win 0, length 0",
+        ),
+    ];
+    for (name, expected) in cases {
+        assert_eq!(summarize(&golden(name)), expected, "{}", name);
+    }
+}
+
+#[test]
+fn linux_segments_too() {
+    let summaries: Vec<_> = linux().iter().map(|packet| summarize(packet)).collect();
+    assert_eq!(
+        summaries,
+        [
+            "10.0.0.2.40806 > 10.0.0.1.80: Flags [S], seq 4044859717, win 64240, \
+             options [nop,nop,md5,mss 1460,nop,nop,sackOK,nop,wscale 10], length 0",
+            "10.0.0.1.80 > 10.0.0.2.40806: Flags [S.], seq 268435456, ack 4044859718, \
+             win 65535, options [mss 1460,nop,nop,sackOK,nop,nop,md5], length 0",
+            "10.0.0.2.40806 > 10.0.0.1.80: Flags [.], seq 4044859718, ack 268435457, \
+             win 64240, options [nop,nop,md5], length 0",
+            "10.0.0.2.40806 > 10.0.0.1.80: Flags [P.], seq 4044859718:4044859722, \
+             ack 268435457, win 64240, options [nop,nop,md5], length 4",
+            "10.0.0.2.40806 > 10.0.0.1.80: Flags [F.], seq 4044859722, ack 268435457, \
+             win 64240, options [nop,nop,md5], length 0",
+        ]
+    );
+}
+
+#[test]
+fn the_hexdump_names_every_header_field() {
+    let dump = hexdump(&golden("syn_ack"));
+    let lines: Vec<_> = dump.lines().collect();
+    assert_eq!(lines.len(), 20, "{}", dump);
+    for (at, line) in [
+        (0, "0000  45           ip.version/ihl   4 / 20"),
+        (8, "000c  0a 00 00 01  ip.src           10.0.0.1"),
+        (12, "0018  10 00 00 00  tcp.seq          268435456"),
+        (15, "0021  12           tcp.flags        [S.]"),
+        (17, "0024  97 1e        tcp.checksum     0x971e"),
+    ] {
+        assert_eq!(lines[at], line);
+    }
+    assert!(lines[19].ends_with("tcp.options      [mss 1460,sackOK,TS val 0 ecr 100]"));
+
+    // and the payload follows the header
+    let data = hexdump(&golden("data"));
+    assert!(data.contains("tcp.data_off     32"), "{}", data);
+    assert!(data.lines().count() > 20);
+}
+
+#[test]
+fn truncated_packets_are_described_not_panicked_over() {
+    let mut packets: Vec<_> = ["syn_ack", "data", "rst"].map(golden).into();
+    packets.extend(linux());
+    for packet in &packets {
+        for len in 0..packet.len() {
+            let cut = &packet[..len];
+            summarize(cut);
+            hexdump(cut);
+        }
+    }
+    assert_eq!(summarize(&[0x45, 0]), "[|ip] truncated, 2 bytes");
+
+    // a data offset running past the end of the segment
+    let mut bad = golden("rst");
+    bad[32] = 0xf0 | (bad[32] & 0x0f);
+    summarize(&bad);
+    hexdump(&bad);
+}
@@ -0,0 +1,56 @@
+//! What becomes of a connection whose sequence spaces come apart, which takes a bug in the
+//! crate, pushed into it here through a hook for tests.
+
+mod common;
+
+use std::io;
+use std::net::SocketAddrV4;
+
+use common::{accept, connection, Craft, Scripted, PEER, TICK, US};
+use trust::{CloseReason, DropReason, InterfaceEvent, State, TcpConfig};
+
+#[test]
+fn desynchronized_connection_is_aborted() {
+    let peer = SocketAddrV4::new(PEER, 40000);
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let (quad, iss) = accept(&mut s, 80, peer, 1000);
+    s.send(
+        Craft::new(peer, SocketAddrV4::new(US, 80))
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .payload(b"hello"),
+    );
+    s.advance(TICK);
+    s.take();
+    s.take_events();
+
+    s.interface.corrupt_send_space(quad).unwrap();
+    s.advance(TICK);
+
+    let rst = s.take_one();
+    assert!(rst.rst);
+    assert_eq!(s.interface.desync_aborts(), 1);
+    assert_eq!(s.interface.drops().get(DropReason::Desync), 1);
+    let info = connection(&s.interface, quad).unwrap();
+    assert_eq!(info.state, State::Closed);
+    assert_eq!(info.close_reason, Some(CloseReason::Desync));
+
+    // the application hears of it once, with both sequence spaces to go on
+    let errors: Vec<_> = s
+        .take_events()
+        .into_iter()
+        .filter_map(|e| match e {
+            InterfaceEvent::Error(q, e, _) if q == quad => Some(e),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].kind(), io::ErrorKind::ConnectionAborted);
+    let message = errors[0].to_string();
+    assert!(message.contains("SND.UNA is past SND.NXT"), "{}", message);
+
+    // and nothing more goes out for it
+    s.advance(10 * TICK);
+    assert!(s.take().iter().all(|segment| segment.dst != peer));
+}
@@ -0,0 +1,112 @@
+//! What the packet loop does with packets it cannot make sense of and with devices that fail:
+//! only a device that is gone for good stops it.
+
+mod common;
+
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use common::{accept, peer, Craft, Scripted, TICK, US};
+use trust::{DropReason, InterfaceError, Nic, TcpConfig};
+
+#[test]
+fn garbage_does_not_stop_the_loop() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let us = SocketAddrV4::new(US, 80);
+
+    // a small deterministic generator (SplitMix64) for the garbage
+    let mut state = 0x5eed_u64;
+    let mut next = move || {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+    let mut garbage = |s: &mut Scripted, count: usize| {
+        for _ in 0..count {
+            let len = 1 + (next() % 80) as usize;
+            let mut blob: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            // half of them look like IPv4 at first glance, to get past the version check
+            if next() % 2 == 0 {
+                blob[0] = 0x45;
+            }
+            s.peer.send(&blob).unwrap();
+        }
+        s.settle();
+    };
+
+    garbage(&mut s, 400);
+    s.send(Craft::new(peer(), us).syn().seq(100).mss(1460));
+    let syn_ack = s.take_one();
+    assert!(syn_ack.syn && syn_ack.ack == Some(101));
+    garbage(&mut s, 300);
+    s.send(
+        Craft::new(peer(), us)
+            .seq(101)
+            .ack(syn_ack.seq.wrapping_add(1)),
+    );
+    garbage(&mut s, 300);
+    let quad = s.accepted().expect("the handshake completed");
+    s.send(
+        Craft::new(peer(), us)
+            .seq(101)
+            .ack(syn_ack.seq.wrapping_add(1))
+            .payload(b"still here"),
+    );
+    assert_eq!(s.read_all(quad), b"still here");
+
+    let drops = s.interface.drops();
+    let malformed: u64 = [
+        DropReason::NotIpv4,
+        DropReason::BadIpHeader,
+        DropReason::Truncated,
+        DropReason::NotTcp,
+        DropReason::BadTcpHeader,
+        DropReason::BadChecksum,
+        DropReason::NotOurs,
+    ]
+    .into_iter()
+    .map(|reason| drops.get(reason))
+    .sum();
+    assert_eq!(malformed, 1000);
+}
+
+#[test]
+fn a_failed_send_costs_only_the_packet() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let (quad, iss) = accept(&mut s, 80, peer(), 100);
+
+    s.interface.write_on(quad, b"hello").unwrap();
+    s.net.fail_sends(1, libc::ENOBUFS);
+    s.advance(TICK);
+    assert!(s.take().is_empty());
+    assert_eq!(s.interface.send_failures(), 1);
+
+    // lost as if on the wire, and sent again when the retransmission timer runs out
+    s.advance(Duration::from_secs(3));
+    let again = s.take().remove(0);
+    assert_eq!(again.seq, iss.wrapping_add(1));
+    assert_eq!(again.payload, b"hello");
+}
+
+#[test]
+fn a_device_gone_for_good_stops_the_loop() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let (quad, _) = accept(&mut s, 80, peer(), 100);
+
+    s.interface.write_on(quad, b"hello").unwrap();
+    s.net.fail_sends(1, libc::ENODEV);
+    s.net.advance(TICK);
+    let e = s.interface.poll_once(Duration::ZERO).unwrap_err();
+    let failure = InterfaceError::of(&e).expect("a device failure");
+    assert_eq!(failure.error.raw_os_error(), Some(libc::ENODEV));
+    assert_eq!(s.interface.send_failures(), 0);
+
+    // and the interface is stopped, its connections with it
+    let mut buf = [0; 16];
+    assert!(s.interface.read_on(quad, &mut buf).is_err());
+}
@@ -0,0 +1,163 @@
+//! One interface over two devices, each with an address and a network of its own: connections
+//! keep to the device they came in on, or the one with the address they are opened from, and
+//! a listener bound to one device leaves the other's SYNs alone.
+
+mod common;
+
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+use common::{readable, Craft, Segment, TICK};
+use trust::{
+    DeviceId, Impairments, Interface, InterfaceBuilder, InterfaceEvent, Nic, Quad, SimNet, SimNic,
+    TcpConfig,
+};
+
+/// Our address on each device, and the peer's at the other end of it.
+const OURS: [Ipv4Addr; 2] = [Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 1, 1)];
+const PEERS: [Ipv4Addr; 2] = [Ipv4Addr::new(10, 0, 0, 2), Ipv4Addr::new(10, 0, 1, 2)];
+
+struct Devices {
+    nets: Vec<SimNet>,
+    peers: Vec<SimNic>,
+    ids: Vec<DeviceId>,
+    interface: Interface,
+    events: Vec<InterfaceEvent>,
+}
+
+impl Devices {
+    fn new() -> Self {
+        let mut builder = InterfaceBuilder::new();
+        let (mut nets, mut peers, mut ids) = (Vec::new(), Vec::new(), Vec::new());
+        for ours in OURS {
+            let (net, nic, peer) =
+                SimNet::new(0, Impairments::default(), Impairments::default()).unwrap();
+            ids.push(builder.add_nic(nic, &[ours]));
+            nets.push(net);
+            peers.push(peer);
+        }
+        // both networks move in step, so either clock will do
+        builder.config(TcpConfig::default()).clock(nets[0].clock());
+        let interface = builder.build_polled().unwrap();
+        Devices {
+            nets,
+            peers,
+            ids,
+            interface,
+            events: Vec::new(),
+        }
+    }
+
+    /// Move time on by `by` on both networks, polling as it goes, and return what came out of
+    /// each device.
+    fn advance(&mut self, by: Duration) -> [Vec<Segment>; 2] {
+        let mut out = [Vec::new(), Vec::new()];
+        let mut left = by;
+        loop {
+            for _ in 0..3 {
+                self.nets.iter().for_each(|net| net.advance(Duration::ZERO));
+                let events = self.interface.poll_once(Duration::ZERO).unwrap();
+                self.events.extend(events);
+            }
+            self.nets.iter().for_each(|net| net.advance(Duration::ZERO));
+            for (peer, out) in self.peers.iter_mut().zip(&mut out) {
+                let mut buf = [0; 65536];
+                while readable(peer.as_raw_fd()) {
+                    let n = peer.recv(&mut buf).unwrap();
+                    out.extend(Segment::parse(&buf[..n]));
+                }
+            }
+            if left.is_zero() {
+                return out;
+            }
+            let step = left.min(TICK);
+            self.nets.iter().for_each(|net| net.advance(step));
+            left -= step;
+        }
+    }
+
+    /// Have the peer on device `i` send `segment`, and return what came out of each device.
+    fn send(&mut self, i: usize, segment: Craft) -> [Vec<Segment>; 2] {
+        self.peers[i].send(&segment.build()).unwrap();
+        self.advance(Duration::ZERO)
+    }
+
+    fn accepted(&self) -> Vec<Quad> {
+        self.events
+            .iter()
+            .filter_map(|e| match e {
+                InterfaceEvent::NewConnection(quad) => Some(*quad),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn peer(i: usize) -> SocketAddrV4 {
+    SocketAddrV4::new(PEERS[i], 40000)
+}
+
+fn us(i: usize) -> SocketAddrV4 {
+    SocketAddrV4::new(OURS[i], 80)
+}
+
+#[test]
+fn replies_leave_by_the_device_the_syn_came_in_on() {
+    let mut d = Devices::new();
+    let _listener = d.interface.bind(80).unwrap();
+
+    let mut iss = [0; 2];
+    for i in [1, 0] {
+        let [on_0, on_1] = d.send(i, Craft::new(peer(i), us(i)).syn().seq(1000).mss(1460));
+        let (here, elsewhere) = if i == 0 { (on_0, on_1) } else { (on_1, on_0) };
+        assert!(elsewhere.is_empty());
+        assert_eq!(here.len(), 1);
+        assert_eq!((here[0].flags().as_str(), here[0].src), ("S.", us(i)));
+        iss[i] = here[0].seq;
+        d.send(i, Craft::new(peer(i), us(i)).seq(1001).ack(iss[i] + 1));
+    }
+    let accepted = d.accepted();
+    assert_eq!(accepted.len(), 2);
+
+    // one connection table, and each connection's data goes out its own device
+    for &quad in &accepted {
+        d.interface.write_on(quad, b"hello").unwrap();
+    }
+    let out = d.advance(TICK);
+    for (i, sent) in out.iter().enumerate() {
+        assert_eq!(sent.len(), 1, "device {} sent {:?}", i, sent);
+        assert_eq!(
+            (sent[0].dst, sent[0].payload.as_slice()),
+            (peer(i), &b"hello"[..])
+        );
+    }
+}
+
+#[test]
+fn a_listener_on_one_device_leaves_the_other_alone() {
+    let mut d = Devices::new();
+    let _listener = d.interface.bind_on(d.ids[1], 80).unwrap();
+
+    // nobody listens on port 80 of the first device, so that gets a reset, from there
+    let [on_0, on_1] = d.send(0, Craft::new(peer(0), us(0)).syn().seq(1000).mss(1460));
+    assert!(on_1.is_empty());
+    assert_eq!(on_0.len(), 1);
+    assert_eq!(on_0[0].flags(), "R.");
+
+    let [on_0, on_1] = d.send(1, Craft::new(peer(1), us(1)).syn().seq(1000).mss(1460));
+    assert!(on_0.is_empty());
+    assert_eq!(on_1[0].flags(), "S.");
+}
+
+#[test]
+fn an_active_open_goes_out_the_device_with_its_address() {
+    let mut d = Devices::new();
+    let stream = d.interface.connect(OURS[1], peer(1)).unwrap();
+    let [on_0, on_1] = d.advance(TICK);
+    assert!(on_0.is_empty());
+    assert_eq!(on_1.len(), 1);
+    assert_eq!(on_1[0].flags(), "S");
+    assert_eq!(on_1[0].src, stream.quad().local());
+    assert_eq!(*stream.quad().local().ip(), OURS[1]);
+}
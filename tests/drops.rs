@@ -0,0 +1,162 @@
+//! Every segment thrown away is counted under why: one segment for each reason, and each
+//! moves its own counter by one and no other, on the interface and, for the reasons only a
+//! connection can have, on the connection as well.
+
+mod common;
+
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+use common::{connect, peer, Craft, Scripted, PEER, TICK, US};
+use trust::{DropReason, Drops, TcpConfig, TcpStream};
+
+/// Assert that what moved between `before` and `after` is `reason`, by exactly one.
+fn only(before: Drops, after: Drops, reason: DropReason) {
+    let moved: Vec<_> = after
+        .iter()
+        .zip(before.iter())
+        .filter(|((_, a), (_, b))| a != b)
+        .map(|((r, a), (_, b))| (r, a - b))
+        .collect();
+    assert_eq!(moved, [(reason, 1)]);
+}
+
+/// Have the peer send `packet`, and check the interface counted it under `reason` alone.
+fn drops(s: &mut Scripted, packet: &[u8], reason: DropReason) {
+    let before = s.interface.drops();
+    s.send_raw(packet);
+    only(before, s.interface.drops(), reason);
+}
+
+/// As [`drops`], for a segment `stream`'s connection throws away.
+fn discards(s: &mut Scripted, stream: &TcpStream, segment: Craft, reason: DropReason) {
+    let before = stream.drops().unwrap();
+    drops(s, &segment.build(), reason);
+    only(before, stream.drops().unwrap(), reason);
+}
+
+/// An IPv4 header from the peer to us, with a good checksum, followed by `payload`.
+fn ipv4(protocol: u8, payload: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0x45, 0, 0, 0, 0, 0, 0x40, 0, 64, protocol, 0, 0];
+    packet[2..4].copy_from_slice(&(20 + payload.len() as u16).to_be_bytes());
+    packet.extend(PEER.octets());
+    packet.extend(US.octets());
+    let mut sum: u32 = packet
+        .chunks(2)
+        .map(|word| u16::from_be_bytes([word[0], word[1]]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    packet[10..12].copy_from_slice(&(!sum as u16).to_be_bytes());
+    packet.extend(payload);
+    packet
+}
+
+#[test]
+fn before_any_connection() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let us = SocketAddrV4::new(US, 80);
+
+    drops(&mut s, &[0x60; 40], DropReason::NotIpv4);
+    drops(&mut s, &ipv4(17, &[0; 8]), DropReason::NotTcp);
+
+    let syn = Craft::new(peer(), us)
+        .syn()
+        .seq(1000)
+        .payload(b"abc")
+        .build();
+    drops(&mut s, &syn[..syn.len() - 2], DropReason::Truncated);
+    let mut corrupt = syn.clone();
+    *corrupt.last_mut().unwrap() ^= 1;
+    drops(&mut s, &corrupt, DropReason::BadChecksum);
+
+    let elsewhere = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 9), 80);
+    let syn_elsewhere = Craft::new(peer(), elsewhere).syn().seq(1000).build();
+    drops(&mut s, &syn_elsewhere, DropReason::NotOurs);
+
+    let closed_port = SocketAddrV4::new(US, 81);
+    let syn_closed = Craft::new(peer(), closed_port).syn().seq(1000).build();
+    drops(&mut s, &syn_closed, DropReason::NoListener);
+    let stray = Craft::new(peer(), us).seq(1001).ack(5000).build();
+    drops(&mut s, &stray, DropReason::NoConnection);
+
+    assert_eq!(s.interface.drops().total(), 7);
+}
+
+#[test]
+fn by_an_established_connection() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+
+    // acknowledging what we never sent
+    discards(
+        &mut s,
+        &stream,
+        Craft::new(peer(), us).seq(1001).ack(iss.wrapping_add(5000)),
+        DropReason::BadAck,
+    );
+    // far beyond the window
+    discards(
+        &mut s,
+        &stream,
+        Craft::new(peer(), us)
+            .seq(1001 + 1_000_000)
+            .ack(iss.wrapping_add(1))
+            .payload(b"later"),
+        DropReason::OutOfWindow,
+    );
+    // and after all of it, the connection still takes what fits
+    s.take();
+    s.send(
+        Craft::new(peer(), us)
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .payload(b"now"),
+    );
+    assert_eq!(s.take().last().unwrap().ack, Some(1004));
+    assert_eq!(stream.drops().unwrap().total(), 2);
+}
+
+#[test]
+fn by_a_connection_in_the_handshake() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let stream = s.interface.connect(US, peer()).unwrap();
+    let us = stream.quad().local();
+    s.advance(TICK);
+    let iss = s.take_one().seq;
+
+    // in SYN-SENT: an ACK of something other than our SYN, an RST that acknowledges nothing,
+    // and neither a SYN nor an RST
+    discards(
+        &mut s,
+        &stream,
+        Craft::new(peer(), us)
+            .syn()
+            .seq(1000)
+            .ack(iss.wrapping_add(7)),
+        DropReason::OldAck,
+    );
+    discards(
+        &mut s,
+        &stream,
+        Craft::new(peer(), us).rst().seq(1000),
+        DropReason::BadRst,
+    );
+    discards(
+        &mut s,
+        &stream,
+        Craft::new(peer(), us).seq(1000).ack(iss.wrapping_add(1)),
+        DropReason::NoSyn,
+    );
+
+    // in SYN-RECEIVED, a segment without an ACK
+    let _listener = s.interface.bind(80).unwrap();
+    let other = SocketAddrV4::new(PEER, 40001);
+    s.send(Craft::new(other, SocketAddrV4::new(US, 80)).syn().seq(7000));
+    s.take();
+    let before = s.interface.drops();
+    s.send(Craft::new(other, SocketAddrV4::new(US, 80)).seq(7001));
+    only(before, s.interface.drops(), DropReason::NoAckFlag);
+}
@@ -0,0 +1,50 @@
+//! No byte reaches the application twice, or out of place, however the network mangles what
+//! carries it.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{pattern, Pair};
+use trust::{Impairments, TcpConfig};
+
+/// FNV-1a over the whole stream, as the receiving application would check it.
+fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[test]
+fn ten_megabytes_each_way_over_loss_duplication_and_reordering() {
+    let mangling = Impairments {
+        drop: 0.10,
+        duplicate: 0.05,
+        reorder: 0.10,
+        reorder_window: Duration::from_millis(15),
+        latency: Duration::from_millis(5),
+        jitter: Duration::from_millis(2),
+        ..Default::default()
+    };
+    let to_b = pattern(1, 10 << 20);
+    let to_a = pattern(2, 10 << 20);
+    // with some of these, both ends go back N at once at some point
+    for seed in [1, 2, 157] {
+        let mut pair = Pair::new(
+            seed,
+            mangling.clone(),
+            mangling.clone(),
+            TcpConfig::default(),
+            TcpConfig::default(),
+        );
+        let done = pair.exchange(80, &to_b, &to_a, Duration::from_secs(120));
+
+        assert_eq!(done.at_b.len(), to_b.len());
+        assert_eq!(checksum(&done.at_b), checksum(&to_b));
+        assert_eq!(done.at_a.len(), to_a.len());
+        assert_eq!(checksum(&done.at_a), checksum(&to_a));
+        // the paths that trim what was had already all ran
+        assert!(done.info_a.duplicate_bytes > 0);
+        assert!(done.info_b.duplicate_bytes > 0);
+    }
+}
@@ -0,0 +1,192 @@
+//! TCP Fast Open (RFC 7413) on both ends: a listener hands out cookies and takes the data in a
+//! SYN that brings one back, up to its cap, and falls back to an ordinary handshake on a bad
+//! one; a client asks for a cookie, keeps it, and sends its first data in the next SYN.
+
+mod common;
+
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use common::{connection, Craft, Scripted, PEER, TICK, US};
+use trust::{TcpConfig, TcpListener};
+
+const CAP: usize = 100;
+
+fn from(port: u16) -> SocketAddrV4 {
+    SocketAddrV4::new(PEER, port)
+}
+
+fn server() -> SocketAddrV4 {
+    SocketAddrV4::new(US, 80)
+}
+
+/// A listener on port 80 taking up to [`CAP`] bytes of early data, and the cookie it hands the
+/// peer, from a first connection that asked for one.
+fn listening() -> (Scripted, TcpListener, Vec<u8>) {
+    let mut s = Scripted::new(TcpConfig::default());
+    let mut listener = s.interface.bind(80).unwrap();
+    listener.set_fast_open(Some(CAP));
+
+    s.send(
+        Craft::new(from(40000), server())
+            .syn()
+            .seq(1000)
+            .fast_open(&[]),
+    );
+    let syn_ack = s.take_one();
+    assert_eq!(syn_ack.ack, Some(1001));
+    let cookie = syn_ack.option(34).expect("a cookie").to_vec();
+    assert_eq!(cookie.len(), 8);
+    (s, listener, cookie)
+}
+
+#[test]
+fn the_data_in_a_syn_with_a_good_cookie_is_read_before_the_handshake_is_done() {
+    let (mut s, _listener, cookie) = listening();
+
+    s.send(
+        Craft::new(from(40001), server())
+            .syn()
+            .seq(5000)
+            .fast_open(&cookie)
+            .payload(b"GET / HTTP/1.0\r\n\r\n"),
+    );
+    let syn_ack = s.take_one();
+    assert_eq!(
+        syn_ack.ack,
+        Some(5001 + 18),
+        "the SYN-ACK takes in the data too"
+    );
+    let quad = s.accepted().unwrap();
+    assert_eq!(quad.remote(), from(40001));
+    assert_eq!(s.read_all(quad), b"GET / HTTP/1.0\r\n\r\n");
+    assert!(connection(&s.interface, quad).unwrap().fast_open);
+
+    // the cookie is the same for every port of the same address
+    s.send(
+        Craft::new(from(40002), server())
+            .syn()
+            .seq(9000)
+            .fast_open(&cookie)
+            .payload(&[7; 2 * CAP]),
+    );
+    assert_eq!(
+        s.take_one().ack,
+        Some(9001 + CAP as u32),
+        "early data stops at the cap"
+    );
+}
+
+#[test]
+fn a_bad_cookie_gets_an_ordinary_handshake_and_a_fresh_cookie() {
+    let (mut s, _listener, cookie) = listening();
+    let mut forged = cookie.clone();
+    forged[0] ^= 0xff;
+
+    s.send(
+        Craft::new(from(40001), server())
+            .syn()
+            .seq(5000)
+            .fast_open(&forged)
+            .payload(b"early"),
+    );
+    let syn_ack = s.take_one();
+    assert_eq!(syn_ack.ack, Some(5001), "the data is left to be sent again");
+    assert_eq!(syn_ack.option(34), Some(&cookie[..]));
+
+    // nothing is there to accept until the client sends it again, with the handshake done
+    assert_eq!(s.accepted(), None);
+    s.send(
+        Craft::new(from(40001), server())
+            .seq(5001)
+            .ack(syn_ack.seq.wrapping_add(1))
+            .payload(b"early"),
+    );
+    let quad = s.accepted().unwrap();
+    assert_eq!(s.read_all(quad), b"early");
+    assert!(!connection(&s.interface, quad).unwrap().fast_open);
+}
+
+#[test]
+fn a_listener_without_fast_open_ignores_it() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    s.send(
+        Craft::new(from(40000), server())
+            .syn()
+            .seq(1000)
+            .fast_open(&[1; 8])
+            .payload(b"early"),
+    );
+    let syn_ack = s.take_one();
+    assert_eq!((syn_ack.ack, syn_ack.option(34)), (Some(1001), None));
+}
+
+#[test]
+fn a_client_keeps_its_cookie_for_the_next_connection() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let peer = from(443);
+
+    // the first SYN asks for a cookie, and the data waits for the handshake
+    let first = s.interface.connect_fast_open(US, peer, b"hello").unwrap();
+    s.advance(TICK);
+    let syn = s.take_one();
+    assert_eq!((syn.option(34), syn.payload.len()), (Some(&[][..]), 0));
+    let cookie = [0xc0, 0x0c, 0x1e, 0, 1, 2, 3, 4];
+    s.send(
+        Craft::new(peer, first.quad().local())
+            .syn()
+            .seq(7000)
+            .ack(syn.seq.wrapping_add(1))
+            .fast_open(&cookie),
+    );
+    s.advance(TICK);
+    let data: Vec<u8> = s.take().into_iter().flat_map(|seg| seg.payload).collect();
+    assert_eq!(data, b"hello");
+
+    // the second brings it back, with the data
+    let second = s.interface.connect_fast_open(US, peer, b"again").unwrap();
+    s.advance(TICK);
+    let syn = s.take_one();
+    assert_eq!(syn.option(34), Some(&cookie[..]));
+    assert_eq!(syn.payload, b"again");
+
+    // a SYN-ACK that takes only part of it has the rest sent again straight away
+    s.send(
+        Craft::new(peer, second.quad().local())
+            .syn()
+            .seq(9000)
+            .ack(syn.seq.wrapping_add(1 + 2)),
+    );
+    let rest: Vec<_> = s.take().into_iter().filter(|seg| seg.len() > 0).collect();
+    assert_eq!(rest.len(), 1);
+    assert_eq!(
+        (rest[0].seq, &rest[0].payload[..]),
+        (syn.seq.wrapping_add(3), &b"ain"[..])
+    );
+}
+
+#[test]
+fn a_retransmitted_syn_leaves_the_data_behind() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let peer = from(443);
+    let first = s.interface.connect_fast_open(US, peer, b"").unwrap();
+    s.advance(TICK);
+    let syn = s.take_one();
+    s.send(
+        Craft::new(peer, first.quad().local())
+            .syn()
+            .seq(7000)
+            .ack(syn.seq.wrapping_add(1))
+            .fast_open(&[9; 8]),
+    );
+    s.take();
+
+    let _second = s.interface.connect_fast_open(US, peer, b"data").unwrap();
+    s.advance(TICK);
+    assert_eq!(s.take_one().payload, b"data");
+    // nobody answers, and the SYN goes again after the RTO, on its own
+    s.advance(Duration::from_millis(1100));
+    let again = s.take_one();
+    assert!(again.syn && again.payload.is_empty());
+}
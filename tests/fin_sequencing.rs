@@ -0,0 +1,102 @@
+//! Closing with data still to go out: the state moves on at once, but the FIN waits its turn
+//! after the last byte, riding on the segment that carries it, sent again with it, and held
+//! back like data when the window has no room for it.
+
+mod common;
+
+use std::io::{ErrorKind, Write};
+use std::net::Shutdown;
+use std::time::Duration;
+
+use common::{pattern, peer, state, Craft, Scripted, TICK, US};
+use trust::{State, TcpConfig, TcpStream};
+
+const MSS: u32 = 1460;
+
+/// Connect to the peer, which offers a window of `window` bytes in its SYN-ACK.
+fn connect(s: &mut Scripted, window: u16) -> (TcpStream, u32) {
+    let stream = s.interface.connect(US, peer()).unwrap();
+    s.advance(TICK);
+    let iss = s.take_one().seq;
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .syn()
+            .seq(1000)
+            .ack(iss.wrapping_add(1))
+            .mss(MSS as u16)
+            .window(window),
+    );
+    assert_eq!(s.take_one().ack, Some(1001));
+    (stream, iss)
+}
+
+/// The peer's ACK of `ack`, offering `window` bytes past it.
+fn ack(s: &mut Scripted, stream: &TcpStream, ack: u32, window: u16) {
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .seq(1001)
+            .ack(ack)
+            .window(window),
+    );
+}
+
+#[test]
+fn the_fin_rides_on_the_last_of_three_segments() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, MSS as u16);
+    let data = pattern(151, 3 * MSS as usize);
+    stream.write_all(&data).unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    // closed as far as the application goes, with its data yet to go out
+    assert_eq!(state(&s.interface, stream.quad()), Some(State::FinWait1));
+    assert_eq!(
+        stream.write(b"more").unwrap_err().kind(),
+        ErrorKind::BrokenPipe
+    );
+
+    // a segment at a time, as the window allows, and the FIN only on the last
+    let mut sent: Vec<u8> = Vec::new();
+    for i in 0..3 {
+        s.advance(TICK);
+        let seg = s.take_one();
+        assert_eq!(seg.seq, iss.wrapping_add(1 + i * MSS));
+        sent.extend(&seg.payload);
+        assert_eq!(seg.fin, i == 2, "segment {}: {}", i, seg.describe());
+        if i < 2 {
+            ack(&mut s, &stream, seg.seq.wrapping_add(MSS), MSS as u16);
+        }
+    }
+    assert!(sent == data);
+
+    // the last segment goes again with its FIN when it is not acknowledged
+    s.advance(Duration::from_millis(1100));
+    let again = s.take_one();
+    assert_eq!(
+        (again.seq, again.payload.len(), again.fin),
+        (iss.wrapping_add(1 + 2 * MSS), MSS as usize, true)
+    );
+    // and the FIN takes the sequence number after the last byte
+    ack(&mut s, &stream, iss.wrapping_add(2 + 3 * MSS), MSS as u16);
+    assert_eq!(state(&s.interface, stream.quad()), Some(State::FinWait2));
+}
+
+#[test]
+fn a_fin_alone_waits_for_the_window() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, MSS as u16);
+    stream.write_all(&[7; MSS as usize]).unwrap();
+    s.advance(TICK);
+    assert_eq!(s.take_one().payload.len(), MSS as usize);
+    // the data is taken, but the window closes behind it, and only then does the stream close
+    ack(&mut s, &stream, iss.wrapping_add(1 + MSS), 0);
+    stream.shutdown(Shutdown::Write).unwrap();
+    s.advance(TICK);
+    assert!(s.take().is_empty(), "the FIN went into a closed window");
+
+    // the window opens, and the FIN goes at once, after the data
+    ack(&mut s, &stream, iss.wrapping_add(1 + MSS), MSS as u16);
+    s.advance(TICK);
+    let fin = s.take_one();
+    assert_eq!(fin.flags(), "F.");
+    assert_eq!(fin.seq, iss.wrapping_add(1 + MSS));
+}
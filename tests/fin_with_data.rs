@@ -0,0 +1,91 @@
+//! Segments carrying both the last of the data and a FIN: taken in, the data comes before the
+//! end of the stream and the ACK covers both, unless the segment had to be trimmed to the
+//! window, which leaves its FIN for later; and sent, the last chunk goes out with our FIN on
+//! it, as one segment, again as one when it is retransmitted.
+
+mod common;
+
+use std::io::{ErrorKind, Read, Write};
+use std::time::Duration;
+
+use common::{connect, pattern, peer, state, Craft, Scripted, TICK};
+use trust::{State, TcpConfig};
+
+#[test]
+fn the_data_comes_before_the_end_of_the_stream() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .fin()
+            .psh()
+            .payload(b"last bit"),
+    );
+    // one ACK, past the data and the FIN
+    let ack = s.take_one();
+    assert_eq!(ack.ack, Some(1001 + 8 + 1));
+    assert_eq!(state(&s.interface, stream.quad()), Some(State::CloseWait));
+
+    let mut buf = [0; 64];
+    let n = stream.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"last bit");
+    assert_eq!(stream.read(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn a_fin_trimmed_off_with_the_data_past_the_window_waits() {
+    let mut s = Scripted::new(TcpConfig {
+        recv_buffer: 1024,
+        ..TcpConfig::default()
+    });
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+    let segment = |seq| Craft::new(peer(), us).seq(seq).ack(iss.wrapping_add(1));
+    let data = pattern(171, 1400);
+    s.send(segment(1001).fin().payload(&data));
+    assert_eq!(s.take_one().ack, Some(1001 + 1024));
+    assert_eq!(state(&s.interface, stream.quad()), Some(State::Estab));
+
+    let mut buf = [0; 2048];
+    let n = stream.read(&mut buf).unwrap();
+    assert!(buf[..n] == data[..1024]);
+    // and no end of the stream yet
+    assert_eq!(
+        s.interface
+            .read_on(stream.quad(), &mut buf)
+            .unwrap_err()
+            .kind(),
+        ErrorKind::WouldBlock
+    );
+
+    // the rest, sent again into the window the read opened, brings the FIN in with it
+    s.advance(TICK);
+    assert!(s.take_one().window > 0);
+    s.send(segment(1001 + 1024).fin().payload(&data[1024..]));
+    assert_eq!(s.take_one().ack, Some(1001 + 1400 + 1));
+    let n = stream.read(&mut buf).unwrap();
+    assert!(buf[..n] == data[1024..]);
+    assert_eq!(stream.read(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn our_last_chunk_goes_out_with_our_fin() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    stream.write_all(b"last bit").unwrap();
+    drop(stream);
+    s.advance(TICK);
+    let last = s.take_one();
+    assert_eq!(last.flags(), "FP.");
+    assert_eq!(
+        (last.seq, last.ack, &last.payload[..]),
+        (iss.wrapping_add(1), Some(1001), &b"last bit"[..])
+    );
+
+    // lost, it goes again as it was, FIN and all
+    s.advance(Duration::from_millis(1100));
+    let again = s.take_one();
+    assert_eq!(again.packet, last.packet);
+}
@@ -0,0 +1,70 @@
+//! What a segment calls for us to send goes out once it has been processed in full: one
+//! segment, whose acknowledgment and window already take in everything the segment brought.
+
+mod common;
+
+use std::io::Write;
+
+use common::{connect, pattern, peer, Craft, Scripted, TICK};
+use trust::TcpConfig;
+
+const MSS: u32 = 1460;
+
+#[test]
+fn a_segment_that_fills_a_gap_and_acks_our_data() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+    let segment = |seq| Craft::new(peer(), us).seq(seq);
+    stream.write_all(b"ours").unwrap();
+    s.advance(TICK);
+    s.take_one();
+
+    // the second half arrives first, and then the first half, ACKing our data
+    s.send(
+        segment(1011)
+            .ack(iss.wrapping_add(1))
+            .payload(b"0123456789"),
+    );
+    assert_eq!(s.take_one().ack, Some(1001));
+    s.send(
+        segment(1001)
+            .ack(iss.wrapping_add(5))
+            .payload(b"abcdefghij"),
+    );
+    let ack = s.take_one();
+    assert_eq!((ack.seq, ack.ack), (iss.wrapping_add(5), Some(1021)));
+    // and the window is what is left once all 20 bytes are in
+    assert_eq!(ack.window, u16::MAX - 20);
+}
+
+#[test]
+fn a_retransmission_carries_the_ack_of_the_data_that_called_for_it() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+    let segment = |seq| Craft::new(peer(), us).seq(seq);
+    stream.write_all(&pattern(161, 6 * MSS as usize)).unwrap();
+    s.advance(TICK);
+    assert_eq!(s.take().len(), 6);
+
+    // the first segment is lost: three duplicate ACKs, and a fast retransmit of it
+    for _ in 0..3 {
+        s.send(segment(1001).ack(iss.wrapping_add(1)));
+    }
+    let retransmit = s.take_one();
+    assert_eq!(retransmit.seq, iss.wrapping_add(1));
+
+    // the peer ACKs the first two segments only, with data of its own: the third is sent
+    // again, acknowledging that data, and no bare ACK goes out besides
+    s.send(
+        segment(1001)
+            .ack(iss.wrapping_add(1 + 2 * MSS))
+            .payload(b"hi"),
+    );
+    let sent = s.take_one();
+    assert_eq!(
+        (sent.seq, sent.payload.len(), sent.ack),
+        (iss.wrapping_add(1 + 2 * MSS), MSS as usize, Some(1003))
+    );
+}
@@ -0,0 +1,50 @@
+//! After a retransmission timeout, sending goes back to SND.UNA, while the peer may have had
+//! everything up to SND.MAX all along.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{accept, peer, us, Craft, Scripted, TICK};
+use trust::TcpConfig;
+
+#[test]
+fn bare_acks_after_going_back_n_carry_snd_max() {
+    // without F-RTO holding it off, the timeout goes back N right away
+    let mut s = Scripted::new(TcpConfig {
+        frto: false,
+        ..TcpConfig::default()
+    });
+    let _listener = s.interface.bind(80).unwrap();
+    let (quad, iss) = accept(&mut s, 80, peer(), 1000);
+    s.interface.write_on(quad, &[7; 3000]).unwrap();
+    s.advance(TICK);
+    let sent = s.take();
+    let max = sent.last().unwrap().seq + sent.last().unwrap().len();
+    assert_eq!(max, iss + 1 + 3000);
+
+    // none of it is acknowledged, so the first segment goes again, and SND.NXT is back after it
+    let mut waited = Duration::ZERO;
+    while s.sent.is_empty() && waited < Duration::from_secs(5) {
+        s.advance(TICK);
+        waited += TICK;
+    }
+    let again = s.take_one();
+    assert_eq!(again.seq, iss + 1);
+
+    // the peer's data calls for an ACK, which must not look like an old duplicate to a peer
+    // that has had all of it, or both ends would be stuck if the peer had gone back too
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1001)
+            .ack(iss + 1)
+            .payload(b"hello"),
+    );
+    s.advance(TICK);
+    let ack = s
+        .take()
+        .into_iter()
+        .find(|segment| segment.payload.is_empty())
+        .expect("the data was acknowledged");
+    assert_eq!((ack.seq, ack.ack), (max, Some(1006)));
+}
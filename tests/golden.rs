@@ -0,0 +1,109 @@
+//! What the stack puts on the wire, byte for byte, against the fixtures under `tests/golden`: a
+//! whole connection with every option, and the RSTs for a port nobody listens on. The ISS is
+//! fixed, which also starts the TSvals at 0, so every run sends the same bytes.
+
+mod common;
+
+use std::net::SocketAddrV4;
+
+use common::{golden, peer, Craft, Scripted, Segment, TICK, US};
+use trust::TcpConfig;
+
+const ISS: u32 = 0x1000_0000;
+const IRS: u32 = 1000;
+
+fn us(port: u16) -> SocketAddrV4 {
+    SocketAddrV4::new(US, port)
+}
+
+fn scripted() -> Scripted {
+    Scripted::with(TcpConfig::default(), |builder| {
+        builder.fixed_iss(ISS);
+    })
+}
+
+/// Whether the IP header checksum and the TCP checksum of `segment` both come out right, summed
+/// here rather than by the stack, in case the fixture was written out from a broken one.
+fn checksums_hold(segment: &Segment) -> bool {
+    let sum = |bytes: &[u8], mut sum: u32| {
+        for word in bytes.chunks(2) {
+            sum += u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]) as u32;
+        }
+        while sum > 0xffff {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        sum
+    };
+    let packet = &segment.packet;
+    let ihl = (packet[0] & 0xf) as usize * 4;
+    let tcp = &packet[ihl..];
+    let pseudo = sum(&packet[12..20], 6 + tcp.len() as u32);
+    sum(&packet[..ihl], 0) == 0xffff && sum(tcp, pseudo) == 0xffff
+}
+
+fn check(name: &str, segment: &Segment) {
+    assert!(checksums_hold(segment), "{} has a bad checksum", name);
+    golden::check(name, segment);
+}
+
+#[test]
+fn connection() {
+    let mut s = scripted();
+    let _listener = s.interface.bind(80).unwrap();
+    s.send(
+        Craft::new(peer(), us(80))
+            .syn()
+            .seq(IRS)
+            .mss(1460)
+            .wscale(7)
+            .sack_permitted()
+            .timestamps(100, 0),
+    );
+    let syn_ack = s.take_one();
+    check("syn_ack", &syn_ack);
+    let (ts_val, _) = syn_ack.timestamps().unwrap();
+    s.send(
+        Craft::new(peer(), us(80))
+            .seq(IRS + 1)
+            .ack(ISS + 1)
+            .timestamps(101, ts_val),
+    );
+    let quad = s.accepted().unwrap();
+
+    s.interface.write_on(quad, b"hello, world\n").unwrap();
+    s.advance(TICK);
+    let data = s.take_one();
+    check("data", &data);
+
+    // far outside the window: answered with an ACK saying what we expect instead
+    s.send(
+        Craft::new(peer(), us(80))
+            .seq(IRS + 1 + (1 << 30))
+            .ack(ISS + 1)
+            .timestamps(102, ts_val)
+            .payload(b"stale"),
+    );
+    check("challenge_ack", &s.take_one());
+
+    s.interface.close_on(quad);
+    s.advance(TICK);
+    check("fin", &s.take_one());
+}
+
+#[test]
+fn resets() {
+    let mut s = scripted();
+
+    // a SYN, with no ACK to take a sequence number from: seq 0, acknowledging all of it
+    s.send(Craft::new(peer(), us(81)).syn().seq(IRS).mss(1460));
+    check("rst_ack", &s.take_one());
+
+    // data with an ACK: the RST takes its sequence number from that
+    s.send(
+        Craft::new(peer(), us(81))
+            .seq(IRS + 1)
+            .ack(777)
+            .payload(b"data"),
+    );
+    check("rst", &s.take_one());
+}
@@ -0,0 +1,124 @@
+//! Handshakes under way on a listener: how many there may be at once, what becomes of a SYN
+//! past that, and how long one that never completes is held on to.
+
+mod common;
+
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use common::{us, Craft, Scripted, PEER, TICK};
+use trust::{DropReason, HalfOpenLimit, InterfaceEvent, TcpConfig, TcpListener, WhenFull};
+
+fn peer(port: u16) -> SocketAddrV4 {
+    SocketAddrV4::new(PEER, port)
+}
+
+/// A listener that lets five handshakes be under way at once, and fifteen SYNs sent to it:
+/// returns the ISS of each SYN-ACK, by the port it went to.
+fn flooded(when_full: WhenFull) -> (Scripted, TcpListener, Vec<(u16, u32)>) {
+    let mut s = Scripted::new(TcpConfig::default());
+    let mut listener = s.interface.bind(80).unwrap();
+    listener.set_half_open_limit(HalfOpenLimit {
+        connections: 5,
+        when_full,
+    });
+    for port in 40000..40015 {
+        s.send(Craft::new(peer(port), us()).syn().seq(1000).mss(1460));
+    }
+    let answered = s
+        .take()
+        .into_iter()
+        .map(|syn_ack| {
+            assert_eq!(syn_ack.flags(), "S.");
+            (syn_ack.dst.port(), syn_ack.seq)
+        })
+        .collect();
+    (s, listener, answered)
+}
+
+fn complete(s: &mut Scripted, port: u16, iss: u32) {
+    s.send(
+        Craft::new(peer(port), us())
+            .seq(1001)
+            .ack(iss.wrapping_add(1)),
+    );
+}
+
+#[test]
+fn syns_past_the_limit_are_dropped() {
+    let (mut s, listener, answered) = flooded(WhenFull::Drop);
+    let ports: Vec<u16> = answered.iter().map(|&(port, _)| port).collect();
+    assert_eq!(ports, (40000..40005).collect::<Vec<_>>());
+    assert_eq!(listener.half_open(), 5);
+    assert_eq!(s.interface.drops().get(DropReason::HalfOpenLimit), 10);
+
+    // a connection goes out once its handshake completes, and no sooner
+    assert!(s.accepted().is_none());
+    let (port, iss) = answered[0];
+    complete(&mut s, port, iss);
+    assert_eq!(s.accepted().map(|quad| quad.remote()), Some(peer(port)));
+    assert_eq!(listener.half_open(), 4);
+}
+
+#[test]
+fn handshakes_that_never_complete_expire() {
+    let (mut s, listener, answered) = flooded(WhenFull::Drop);
+    let (port, iss) = answered[0];
+    complete(&mut s, port, iss);
+    s.take_events();
+
+    // the SYN-ACK goes five more times, and once the last has timed out, the connection is
+    // dropped without a word to the peer
+    let start = s.net.now();
+    let mut resent = 0;
+    while s.net.now() - start < Duration::from_secs(70) {
+        s.advance(TICK);
+        for segment in s.take() {
+            assert_eq!(segment.flags(), "S.");
+            resent += 1;
+        }
+    }
+    assert_eq!(resent, 4 * 5);
+    assert_eq!(listener.half_open(), 0);
+    assert_eq!(listener.half_open_expired(), 4);
+    let left: Vec<_> = s
+        .interface
+        .connections()
+        .into_iter()
+        .map(|(quad, _)| quad.remote())
+        .collect();
+    assert_eq!(left, [peer(port)]);
+    assert!(!s
+        .take_events()
+        .iter()
+        .any(|e| matches!(e, InterfaceEvent::NewConnection(_))));
+}
+
+#[test]
+fn or_the_oldest_handshake_makes_room() {
+    let (mut s, listener, answered) = flooded(WhenFull::RecycleOldest);
+    assert_eq!(answered.len(), 15);
+    assert_eq!(listener.half_open(), 5);
+    assert_eq!(listener.half_open_recycled(), 10);
+    assert_eq!(s.interface.drops().get(DropReason::HalfOpenLimit), 0);
+
+    // the newest complete their handshakes as ever, and the oldest find theirs gone
+    let (newest, iss) = answered[14];
+    complete(&mut s, newest, iss);
+    assert_eq!(s.accepted().map(|quad| quad.remote()), Some(peer(newest)));
+    let (oldest, iss) = answered[0];
+    complete(&mut s, oldest, iss);
+    assert_eq!(s.accepted().map(|quad| quad.remote()), Some(peer(newest)));
+    assert_eq!(listener.half_open(), 4);
+}
+
+#[test]
+fn the_default_limit() {
+    assert_eq!(
+        HalfOpenLimit::default(),
+        HalfOpenLimit {
+            connections: 256,
+            when_full: WhenFull::Drop
+        }
+    );
+}
@@ -0,0 +1,178 @@
+//! Header fields at their most absurd, as a hostile or broken peer might send them: each is
+//! dropped and counted, or bounded, and none panics or wraps into taking in bad data.
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::SocketAddrV4;
+
+use common::{connect, connection, peer, Craft, Scripted, PEER, TICK, US};
+use trust::{DropReason, TcpConfig};
+
+fn server() -> SocketAddrV4 {
+    SocketAddrV4::new(US, 80)
+}
+
+/// The ones' complement of the ones' complement sum of `bytes`, added on to `acc`.
+fn sum(bytes: &[u8], mut acc: u32) -> u16 {
+    for word in bytes.chunks(2) {
+        acc += u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]) as u32;
+    }
+    while acc > 0xffff {
+        acc = (acc & 0xffff) + (acc >> 16);
+    }
+    !(acc as u16)
+}
+
+/// Put right both checksums of `packet`, an IPv4 packet with no IP options, after a test has
+/// tampered with it, taking the TCP segment to run to the end of the buffer.
+fn seal(packet: &mut [u8]) {
+    packet[10..12].fill(0);
+    let ip = sum(&packet[..20], 0);
+    packet[10..12].copy_from_slice(&ip.to_be_bytes());
+
+    let len = packet.len() - 20;
+    packet[36..38].fill(0);
+    let mut pseudo = packet[12..20].to_vec();
+    pseudo.extend([0, 6]);
+    pseudo.extend((len as u16).to_be_bytes());
+    let pseudo = !sum(&pseudo, 0) as u32;
+    let tcp = sum(&packet[20..], pseudo);
+    packet[36..38].copy_from_slice(&tcp.to_be_bytes());
+}
+
+#[test]
+fn lengths_that_do_not_add_up() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    let segment = |seq, payload: &[u8]| {
+        Craft::new(peer(), stream.quad().local())
+            .seq(seq)
+            .ack(iss.wrapping_add(1))
+            .payload(payload)
+            .build()
+    };
+
+    // an IPv4 total length of more than arrived
+    let mut long = segment(1001, b"abc");
+    long[2..4].copy_from_slice(&u16::MAX.to_be_bytes());
+    seal(&mut long);
+    s.send_raw(&long);
+    assert_eq!(s.interface.drops().get(DropReason::Truncated), 1);
+
+    // padding past the total length, as on a short Ethernet frame, is not data
+    let mut padded = segment(1001, b"abc");
+    padded.extend(b"junk!");
+    s.send_raw(&padded);
+    assert_eq!(s.take().last().unwrap().ack, Some(1004));
+
+    // data offsets past the end of the segment, which is then too short for its header, and
+    // short of the fixed header itself
+    let offset = |offset: u8| {
+        let mut bad = segment(1004, b"");
+        bad[32] = offset << 4 | (bad[32] & 0x0f);
+        seal(&mut bad);
+        bad
+    };
+    for bad in [offset(15), offset(6)] {
+        s.send_raw(&bad);
+    }
+    assert_eq!(s.interface.drops().get(DropReason::Truncated), 3);
+    for bad in [offset(4), offset(0)] {
+        s.send_raw(&bad);
+    }
+    assert_eq!(s.interface.drops().get(DropReason::BadTcpHeader), 2);
+    assert!(s.take().is_empty());
+
+    let mut buf = [0; 16];
+    let n = stream.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"abc");
+    assert_eq!(s.interface.drops().total(), 5);
+}
+
+#[test]
+fn options_that_run_off_the_end_stop_the_parse_there() {
+    // with a buffer big enough for us to offer a window scale, if the peer does
+    let mut s = Scripted::new(TcpConfig {
+        recv_buffer: 256 << 10,
+        ..TcpConfig::default()
+    });
+    let _listener = s.interface.bind(80).unwrap();
+    for (port, options) in [
+        // a length of nothing, of less than the kind and length themselves, and of more than
+        // there is, each followed by a window scale that must not be taken
+        (40000, &[5, 0, 3, 3, 7][..]),
+        (40001, &[8, 1, 3, 3, 7]),
+        (40002, &[3, 3, 7, 8, 40, 0, 0]),
+    ] {
+        s.send(
+            Craft::new(SocketAddrV4::new(PEER, port), server())
+                .syn()
+                .seq(1000)
+                .mss(1000)
+                .raw_options(options),
+        );
+        let syn_ack = s.take_one();
+        assert_eq!(syn_ack.ack, Some(1001));
+        // what came before the bad option stands
+        assert_eq!(syn_ack.mss(), Some(1460));
+        assert_eq!(syn_ack.wscale().is_some(), port == 40002);
+    }
+}
+
+#[test]
+fn extreme_syn_options_are_bounded() {
+    let mut s = Scripted::new(TcpConfig {
+        recv_buffer: 256 << 10,
+        ..TcpConfig::default()
+    });
+    let _listener = s.interface.bind(80).unwrap();
+    s.send(
+        Craft::new(peer(), server())
+            .syn()
+            .seq(1000)
+            .mss(0)
+            .wscale(255),
+    );
+    let syn_ack = s.take_one();
+    let iss = syn_ack.seq;
+    s.send(
+        Craft::new(peer(), server())
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .window(u16::MAX),
+    );
+    let quad = s.accepted().unwrap();
+
+    // the shift is the most there is, and the window as big as that makes it
+    let info = connection(&s.interface, quad).unwrap();
+    assert_eq!(info.snd_wscale, 14);
+    assert_eq!(info.peer_window, (u16::MAX as u32) << 14);
+    // an MSS of nothing is taken as 88 bytes, as Linux does
+    assert_eq!(info.peer_mss, Some(88));
+
+    s.interface.write_on(quad, &[1; 200]).unwrap();
+    s.advance(TICK);
+    let sizes: Vec<_> = s.take().iter().map(|seg| seg.payload.len()).collect();
+    assert_eq!(sizes, [88, 88, 24]);
+}
+
+#[test]
+fn an_urgent_pointer_far_past_the_data() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .urgent(u16::MAX)
+            .payload(b"hi"),
+    );
+    let mut buf = [0; 16];
+    let n = stream.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"hi");
+    stream.write_all(b"still here").unwrap();
+    s.advance(TICK);
+    let sent: Vec<u8> = s.take().into_iter().flat_map(|seg| seg.payload).collect();
+    assert_eq!(sent, b"still here");
+}
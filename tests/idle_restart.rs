@@ -0,0 +1,95 @@
+//! A connection that has sent nothing for a retransmission timeout starts over from the initial
+//! window (RFC 5681 S4.1), leaving ssthresh where it was; one that keeps sending a little and
+//! getting it acknowledged never counts as idle.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{accept, connection, peer, us, Craft, Scripted, TICK};
+use trust::{Quad, TcpConfig, TcpListener};
+
+/// What [`accept`] has the peer announce, and so the size of our segments.
+const MSS: u32 = 1460;
+/// The initial window, of ten segments (RFC 6928).
+const IW: u32 = 10 * MSS;
+/// Longer than the retransmission timeout, which starts out at a second and stays there with
+/// round trips as short as these.
+const IDLE: Duration = Duration::from_secs(3);
+
+/// An established connection whose congestion window has grown well past the initial window,
+/// with nothing in flight: returns it, with its listener and the next sequence number it sends.
+fn grown(config: TcpConfig) -> (Scripted, TcpListener, Quad, u32) {
+    let mut s = Scripted::new(config);
+    let listener = s.interface.bind(80).unwrap();
+    let (quad, iss) = accept(&mut s, 80, peer(), 1000);
+    let nxt = send_acked(&mut s, quad, iss.wrapping_add(1), 40 * MSS as usize);
+    let cwnd = connection(&s.interface, quad).unwrap().cwnd;
+    assert!(cwnd >= 3 * IW, "cwnd only grew to {}", cwnd);
+    (s, listener, quad, nxt)
+}
+
+/// Write `len` bytes, and have the peer acknowledge each segment as it comes, until it has all
+/// of them. Returns the sequence number after them.
+fn send_acked(s: &mut Scripted, quad: Quad, mut nxt: u32, len: usize) -> u32 {
+    s.interface.write_on(quad, &vec![7; len]).unwrap();
+    let end = nxt.wrapping_add(len as u32);
+    while nxt != end {
+        s.advance(TICK);
+        for segment in s.take() {
+            nxt = segment.seq.wrapping_add(segment.len());
+            s.send(Craft::new(peer(), us()).seq(1001).ack(nxt));
+        }
+    }
+    nxt
+}
+
+/// How much of a large write goes out before anything of it is acknowledged.
+fn first_flight(s: &mut Scripted, quad: Quad) -> u32 {
+    s.interface.write_on(quad, &[7; 100_000]).unwrap();
+    s.advance(TICK);
+    s.take().iter().map(|segment| segment.len()).sum()
+}
+
+#[test]
+fn first_flight_after_idle_is_the_initial_window() {
+    let (mut s, _listener, quad, _) = grown(TcpConfig::default());
+    let before = connection(&s.interface, quad).unwrap();
+
+    s.advance(IDLE);
+    assert_eq!(first_flight(&mut s, quad), IW);
+    let after = connection(&s.interface, quad).unwrap();
+    assert_eq!(after.cwnd, IW);
+    assert_eq!(after.ssthresh, before.ssthresh);
+}
+
+#[test]
+fn sending_all_along_is_not_idle() {
+    let (mut s, _listener, quad, mut nxt) = grown(TcpConfig::default());
+    let cwnd = connection(&s.interface, quad).unwrap().cwnd;
+
+    // application-limited: a little now and then, acknowledged each time, for as long
+    let mut waited = Duration::ZERO;
+    while waited < IDLE {
+        nxt = send_acked(&mut s, quad, nxt, 100);
+        s.advance(Duration::from_millis(200));
+        waited += Duration::from_millis(200);
+    }
+    // it may have grown meanwhile, but never went back to the initial window
+    assert!(connection(&s.interface, quad).unwrap().cwnd >= cwnd);
+    assert!(first_flight(&mut s, quad) > IW);
+}
+
+#[test]
+fn without_slow_start_after_idle_the_window_is_kept() {
+    let (mut s, _listener, quad, _) = grown(TcpConfig {
+        slow_start_after_idle: false,
+        ..TcpConfig::default()
+    });
+    let cwnd = connection(&s.interface, quad).unwrap().cwnd;
+
+    s.advance(IDLE);
+    // all the peer's window lets out, which is less than the congestion window would
+    assert_eq!(first_flight(&mut s, quad), 65535);
+    assert_eq!(connection(&s.interface, quad).unwrap().cwnd, cwnd);
+}
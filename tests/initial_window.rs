@@ -0,0 +1,66 @@
+//! The initial congestion window (RFC 6928): the first flight after the handshake is as many
+//! segments as configured, ten by default, of however large the peer lets them be, within a
+//! byte budget of 1460 bytes a segment.
+
+mod common;
+
+use std::io::Write;
+use std::net::SocketAddrV4;
+
+use common::{Craft, Scripted, PEER, TICK, US};
+use trust::TcpConfig;
+
+/// The payloads of the first flight from a connection with `config` to a peer that announces
+/// `mss` and `window`, given far more data than the flight can hold.
+fn first_flight(config: TcpConfig, mss: u16, window: u16) -> Vec<usize> {
+    let mut s = Scripted::new(config);
+    let peer = SocketAddrV4::new(PEER, 40000);
+    let mut stream = s.interface.connect(US, peer).unwrap();
+    s.advance(TICK);
+    let iss = s.take_one().seq;
+    s.send(
+        Craft::new(peer, stream.quad().local())
+            .syn()
+            .seq(1000)
+            .ack(iss.wrapping_add(1))
+            .mss(mss)
+            .window(window),
+    );
+    s.take_one();
+    stream.write_all(&[0; 60_000]).unwrap();
+    s.advance(TICK);
+    s.take().iter().map(|seg| seg.payload.len()).collect()
+}
+
+fn segments(n: u32) -> TcpConfig {
+    TcpConfig {
+        initial_cwnd_segments: n,
+        send_buffer: 64 << 10,
+        ..TcpConfig::default()
+    }
+}
+
+#[test]
+fn ten_full_segments_by_default() {
+    assert_eq!(TcpConfig::default().initial_cwnd_segments, 10);
+    assert_eq!(first_flight(segments(10), 1460, u16::MAX), [1460; 10]);
+}
+
+#[test]
+fn as_many_segments_as_configured() {
+    for n in [1, 2, 4] {
+        let flight = first_flight(segments(n), 1460, u16::MAX);
+        assert_eq!(flight, vec![1460; n as usize], "{} segments", n);
+    }
+}
+
+#[test]
+fn small_segments_still_number_ten() {
+    // min(10 * 536, max(2 * 536, 14600)) is ten of them
+    assert_eq!(first_flight(segments(10), 536, u16::MAX), [536; 10]);
+}
+
+#[test]
+fn the_peers_window_still_bounds_the_flight() {
+    assert_eq!(first_flight(segments(10), 1460, 4000), [1460, 1460, 1080]);
+}
@@ -0,0 +1,94 @@
+//! Keepalives and window probes from the peer, one sequence number before RCV.NXT, in both the
+//! forms Linux sends them: empty, or with one byte of garbage. Each draws an ACK of RCV.NXT
+//! right away, however many come, and nothing in them is taken as data or counted as a drop.
+
+mod common;
+
+use std::io::Read;
+
+use common::{connect, peer, state, Craft, Scripted};
+use trust::{State, TcpConfig, TcpStream};
+
+/// A connection that has had "hello" from the peer, so RCV.NXT is 1006.
+fn connected(s: &mut Scripted) -> (TcpStream, u32) {
+    let (mut stream, iss) = connect(s, peer(), 1000);
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .payload(b"hello"),
+    );
+    let mut buf = [0; 5];
+    stream.read_exact(&mut buf).unwrap();
+    s.take();
+    (stream, iss)
+}
+
+#[test]
+fn both_forms_draw_an_ack_of_rcv_nxt() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connected(&mut s);
+    for payload in [&b""[..], b"?"] {
+        s.send(
+            Craft::new(peer(), stream.quad().local())
+                .seq(1005)
+                .ack(iss.wrapping_add(1))
+                .payload(payload),
+        );
+        let ack = s.take_one();
+        assert_eq!(
+            (ack.flags().as_str(), ack.seq, ack.ack),
+            (".", iss.wrapping_add(1), Some(1006))
+        );
+        assert_eq!(ack.window, u16::MAX);
+    }
+    assert_eq!(s.interface.drops().total(), 0);
+
+    // the garbage byte was not taken for the last byte of "hello", or anything after it: the
+    // next byte read is the next one the peer sends
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .seq(1006)
+            .ack(iss.wrapping_add(1))
+            .payload(b"!"),
+    );
+    assert_eq!(s.take_one().ack, Some(1007));
+    let mut buf = [0; 1];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"!");
+}
+
+#[test]
+fn a_burst_of_probes_is_answered_in_full() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, iss) = connected(&mut s);
+    // many more than the limit on challenge ACKs, which probes are not subject to
+    for _ in 0..50 {
+        s.send(
+            Craft::new(peer(), stream.quad().local())
+                .seq(1005)
+                .ack(iss.wrapping_add(1)),
+        );
+    }
+    let acks = s.take();
+    assert_eq!(acks.len(), 50);
+    assert!(acks.iter().all(|ack| ack.ack == Some(1006)));
+    assert_eq!(s.interface.drops().total(), 0);
+    assert_eq!(state(&s.interface, stream.quad()), Some(State::Estab));
+}
+
+#[test]
+fn a_retransmitted_fin_is_not_a_probe() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, iss) = connected(&mut s);
+    let fin = Craft::new(peer(), stream.quad().local())
+        .seq(1006)
+        .ack(iss.wrapping_add(1))
+        .fin();
+    s.send(fin.clone());
+    assert_eq!(s.take_one().ack, Some(1007));
+    // again, one before RCV.NXT like a probe, and answered, but as the FIN it is
+    s.send(fin);
+    assert_eq!(s.take_one().ack, Some(1007));
+    assert_eq!(state(&s.interface, stream.quad()), Some(State::CloseWait));
+}
@@ -0,0 +1,210 @@
+//! Connections with the kernel's own TCP stack over a real tun device, both ways round: the
+//! kernel connecting to our listener, and us connecting to the kernel's. Each side sends the
+//! other a megabyte at once, and then closes. Once more with TCP MD5 signatures, which the
+//! kernel checks on every segment.
+//!
+//! ```text
+//! cargo test --features sys-tests --test kernel_interop
+//! ```
+//!
+//! The test makes the device itself, and sets it up with `ip`, which takes root or
+//! `CAP_NET_ADMIN`; without either it says so and passes. The device goes away with the
+//! interface.
+
+mod common;
+
+use std::io::{self, Read, Write};
+use std::mem;
+use std::net::{Ipv4Addr, Shutdown, SocketAddrV4, TcpListener, TcpStream};
+use std::os::unix::io::AsRawFd;
+use std::process::Command;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use common::pattern;
+use trust::{Interface, InterfaceBuilder};
+
+const SIZE: usize = 1 << 20;
+
+/// How long the kernel's side waits on a call before the test fails rather than hangs.
+const PATIENCE: Duration = Duration::from_secs(30);
+
+/// An interface on a tun device of its own, up with 10.77.`subnet`.1 on the kernel's end and
+/// 10.77.`subnet`.2 on ours, with those addresses; or `None` if this process may not set one
+/// up. Tests running side by side each take a subnet of their own.
+fn tun_interface(name: &str, subnet: u8) -> Option<(Interface, Ipv4Addr, Ipv4Addr)> {
+    let (host, stack) = (
+        Ipv4Addr::new(10, 77, subnet, 1),
+        Ipv4Addr::new(10, 77, subnet, 2),
+    );
+    let nic = match tun_tap::Iface::without_packet_info(name, tun_tap::Mode::Tun) {
+        Ok(nic) => nic,
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::PermissionDenied | io::ErrorKind::NotFound
+            ) =>
+        {
+            eprintln!("skipped: cannot make a tun device ({})", e);
+            return None;
+        }
+        Err(e) => panic!("making a tun device failed: {}", e),
+    };
+    let prefix = format!("{}/24", host);
+    for args in [
+        &["addr", "add", &prefix, "dev", name][..],
+        &["link", "set", "up", "dev", name],
+    ] {
+        let status = Command::new("ip").args(args).status();
+        if !status.as_ref().is_ok_and(|s| s.success()) {
+            eprintln!("skipped: `ip {}` failed ({:?})", args.join(" "), status);
+            return None;
+        }
+    }
+    let mut builder = InterfaceBuilder::new();
+    builder.add_nic(nic, &[stack]);
+    Some((builder.build().unwrap(), host, stack))
+}
+
+/// Have the kernel's end of a connection send `out` and then close, while it reads what comes
+/// in until the end: returns what it read.
+fn kernel_side(stream: TcpStream, out: Vec<u8>) -> io::Result<Vec<u8>> {
+    stream.set_read_timeout(Some(PATIENCE))?;
+    let mut writer = stream.try_clone()?;
+    let sent = thread::spawn(move || -> io::Result<()> {
+        writer.write_all(&out)?;
+        writer.shutdown(Shutdown::Write)
+    });
+    let mut got = Vec::new();
+    (&stream).read_to_end(&mut got)?;
+    sent.join().unwrap()?;
+    Ok(got)
+}
+
+/// Have our end of a connection do the same.
+fn our_side(stream: trust::TcpStream, out: Vec<u8>) -> io::Result<Vec<u8>> {
+    let stream = Arc::new(stream);
+    let sent = {
+        let stream = stream.clone();
+        thread::spawn(move || -> io::Result<()> {
+            (&*stream).write_all(&out)?;
+            stream.shutdown(Shutdown::Write)
+        })
+    };
+    let mut got = Vec::new();
+    (&*stream).read_to_end(&mut got)?;
+    sent.join().unwrap()?;
+    Ok(got)
+}
+
+#[test]
+fn kernel_connects_to_us() {
+    let Some((mut interface, _, stack)) = tun_interface("trust-in0", 1) else {
+        return;
+    };
+    let listener = interface.bind(7000).unwrap();
+    let kernel = thread::spawn(move || -> io::Result<Vec<u8>> {
+        let to = SocketAddrV4::new(stack, 7000).into();
+        kernel_side(TcpStream::connect_timeout(&to, PATIENCE)?, pattern(2, SIZE))
+    });
+
+    let got = our_side(listener.accept().unwrap(), pattern(1, SIZE)).unwrap();
+    assert!(
+        got == pattern(2, SIZE),
+        "what the kernel sent came through corrupted"
+    );
+    let at_kernel = kernel.join().unwrap().unwrap();
+    assert!(
+        at_kernel == pattern(1, SIZE),
+        "what we sent came through corrupted"
+    );
+}
+
+#[test]
+fn we_connect_to_the_kernel() {
+    let Some((mut interface, host, stack)) = tun_interface("trust-out0", 2) else {
+        return;
+    };
+    let listener = TcpListener::bind(SocketAddrV4::new(host, 0)).unwrap();
+    let to = SocketAddrV4::new(host, listener.local_addr().unwrap().port());
+    let kernel = thread::spawn(move || -> io::Result<Vec<u8>> {
+        kernel_side(listener.accept()?.0, pattern(2, SIZE))
+    });
+
+    let got = our_side(interface.connect(stack, to).unwrap(), pattern(1, SIZE)).unwrap();
+    assert!(
+        got == pattern(2, SIZE),
+        "what the kernel sent came through corrupted"
+    );
+    let at_kernel = kernel.join().unwrap().unwrap();
+    assert!(
+        at_kernel == pattern(1, SIZE),
+        "what we sent came through corrupted"
+    );
+}
+
+/// `struct tcp_md5sig` from `<linux/tcp.h>`, which `libc` does not have.
+#[repr(C)]
+struct TcpMd5Sig {
+    addr: libc::sockaddr_storage,
+    flags: u8,
+    prefixlen: u8,
+    keylen: u16,
+    ifindex: libc::c_int,
+    key: [u8; 80],
+}
+
+/// Have the kernel sign and check everything on `socket` to and from `peer` with `key`.
+fn set_md5_key(socket: &impl AsRawFd, peer: Ipv4Addr, key: &[u8]) -> io::Result<()> {
+    let mut sig: TcpMd5Sig = unsafe { mem::zeroed() };
+    // sockaddr_storage has room for, and the alignment of, any address
+    let addr = unsafe { &mut *(&mut sig.addr as *mut _ as *mut libc::sockaddr_in) };
+    addr.sin_family = libc::AF_INET as libc::sa_family_t;
+    addr.sin_addr.s_addr = u32::from(peer).to_be();
+    sig.keylen = key.len() as u16;
+    sig.key[..key.len()].copy_from_slice(key);
+    let r = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_MD5SIG,
+            &sig as *const _ as *const libc::c_void,
+            mem::size_of::<TcpMd5Sig>() as libc::socklen_t,
+        )
+    };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[test]
+fn we_connect_to_the_kernel_with_md5() {
+    let Some((mut interface, host, stack)) = tun_interface("trust-md5", 3) else {
+        return;
+    };
+    let key = b"trust-kernel-md5";
+    let listener = TcpListener::bind(SocketAddrV4::new(host, 0)).unwrap();
+    if let Err(e) = set_md5_key(&listener, stack, key) {
+        eprintln!("skipped: the kernel has no TCP MD5 ({})", e);
+        return;
+    }
+    let to = SocketAddrV4::new(host, listener.local_addr().unwrap().port());
+    let kernel = thread::spawn(move || -> io::Result<Vec<u8>> {
+        kernel_side(listener.accept()?.0, pattern(4, SIZE))
+    });
+
+    let stream = interface.connect_with_md5(stack, to, key).unwrap();
+    let got = our_side(stream, pattern(3, SIZE)).unwrap();
+    assert!(
+        got == pattern(4, SIZE),
+        "what the kernel sent came through corrupted"
+    );
+    let at_kernel = kernel.join().unwrap().unwrap();
+    assert!(
+        at_kernel == pattern(3, SIZE),
+        "what we sent came through corrupted"
+    );
+    assert_eq!(interface.md5_failures(), 0);
+}
@@ -0,0 +1,132 @@
+//! Writes of more than the send buffer holds: a blocking write takes the data in as room frees
+//! up and returns once all of it is in, or how much it got in if the connection fails on the
+//! way, while a non-blocking one takes what fits and no more.
+
+mod common;
+
+use std::io::{self, IoSlice, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{connect, pattern, peer, Craft, Scripted, TICK};
+use trust::{TcpConfig, TcpStream};
+
+const BUFFER: usize = 4096;
+
+fn connected() -> (Scripted, TcpStream, u32) {
+    let mut s = Scripted::new(TcpConfig {
+        send_buffer: BUFFER,
+        ..TcpConfig::default()
+    });
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    (s, stream, iss)
+}
+
+/// Have the peer take in and acknowledge what the stream sends, a tick at a time, until it has
+/// `len` bytes in all, and return them; or, with `reset`, until it has that many and then reset
+/// the connection.
+fn receive(s: &mut Scripted, stream: &TcpStream, iss: u32, len: usize, reset: bool) -> Vec<u8> {
+    let mut got = Vec::new();
+    let start = Instant::now();
+    while got.len() < len {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "got {}",
+            got.len()
+        );
+        s.advance(TICK);
+        let sent = s.take();
+        if sent.is_empty() {
+            // the writer has yet to put more in
+            thread::sleep(Duration::from_millis(1));
+        }
+        for seg in sent {
+            assert_eq!(seg.seq, iss.wrapping_add(1 + got.len() as u32));
+            got.extend(&seg.payload);
+        }
+        let ack = Craft::new(peer(), stream.quad().local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1 + got.len() as u32));
+        s.send(if reset && got.len() >= len {
+            ack.rst()
+        } else {
+            ack
+        });
+    }
+    got
+}
+
+#[test]
+fn a_blocking_write_returns_once_all_of_it_is_in() {
+    let (mut s, stream, iss) = connected();
+    let data = pattern(158, 256 * BUFFER);
+    let (got, wrote) = thread::scope(|scope| {
+        let writer = scope.spawn(|| (&stream).write(&data));
+        let got = receive(&mut s, &stream, iss, data.len(), false);
+        (got, writer.join().unwrap())
+    });
+    assert_eq!(wrote.unwrap(), data.len());
+    assert!(got == data);
+}
+
+#[test]
+fn a_vectored_write_takes_its_slices_in_order() {
+    let (mut s, stream, iss) = connected();
+    let data = pattern(158, 10 * BUFFER + 123);
+    // slices that each end at odd places within the chunks the buffer takes them in
+    let slices: Vec<_> = data.chunks(3 * BUFFER / 2 + 7).map(IoSlice::new).collect();
+    let (got, wrote) = thread::scope(|scope| {
+        let writer = scope.spawn(|| (&stream).write_vectored(&slices));
+        let got = receive(&mut s, &stream, iss, data.len(), false);
+        (got, writer.join().unwrap())
+    });
+    assert_eq!(wrote.unwrap(), data.len());
+    assert!(got == data);
+}
+
+#[test]
+fn a_reset_part_way_through_returns_what_got_in() {
+    let (mut s, stream, iss) = connected();
+    let data = pattern(158, 16 * BUFFER);
+    let (got, wrote) = thread::scope(|scope| {
+        let writer = scope.spawn(|| (&stream).write(&data));
+        let got = receive(&mut s, &stream, iss, 4 * BUFFER, true);
+        (got, writer.join().unwrap())
+    });
+    // what the write got in is what went out, and what is still in the buffer
+    let wrote = wrote.unwrap();
+    assert!(
+        (got.len()..=got.len() + BUFFER).contains(&wrote),
+        "{} bytes written, {} sent",
+        wrote,
+        got.len()
+    );
+    assert!(data[..got.len()] == got);
+    let e = (&stream).write(&data[wrote..]).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionReset);
+}
+
+#[test]
+fn a_non_blocking_write_takes_what_fits() {
+    let (mut s, stream, iss) = connected();
+    let quad = stream.into_quad();
+    let data = pattern(158, 4 * BUFFER);
+    assert_eq!(s.interface.write_on(quad, &data).unwrap(), BUFFER);
+    let e = s.interface.write_on(quad, &data[BUFFER..]).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+
+    // as much room as the peer acknowledges, and no more
+    s.advance(TICK);
+    s.take();
+    s.send(
+        Craft::new(peer(), quad.local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1 + 1000)),
+    );
+    assert_eq!(s.interface.write_on(quad, &data[BUFFER..]).unwrap(), 1000);
+    let e = s
+        .interface
+        .write_on(quad, &data[BUFFER + 1000..])
+        .unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+}
@@ -0,0 +1,115 @@
+//! Limited transmit (RFC 3042) with a congestion window of two segments: the first two
+//! duplicate ACKs each let one new segment out, whose own duplicate ACK is the third that fast
+//! retransmit needs. Without it, the window is too small for a loss to draw three.
+
+mod common;
+
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use common::{accept, pattern, Craft, Scripted, Segment, PEER, TICK};
+use trust::{Quad, TcpConfig};
+
+const MSS: u32 = 1460;
+
+struct Lossy {
+    s: Scripted,
+    quad: Quad,
+    /// SND.UNA: where the lost segment starts
+    una: u32,
+}
+
+impl Lossy {
+    /// A connection with a window of two segments and six queued, whose first two are out.
+    fn new(limited_transmit: bool) -> (Self, [Segment; 2]) {
+        let mut s = Scripted::new(TcpConfig {
+            initial_cwnd_segments: 2,
+            limited_transmit,
+            ..TcpConfig::default()
+        });
+        let _listener = s.interface.bind(80).unwrap();
+        let (quad, iss) = accept(&mut s, 80, SocketAddrV4::new(PEER, 40000), 1000);
+        s.interface
+            .write_on(quad, &pattern(7, 6 * MSS as usize))
+            .unwrap();
+        s.advance(TICK);
+        let [a, b]: [Segment; 2] = s.take().try_into().unwrap();
+        let una = iss.wrapping_add(1);
+        (Lossy { s, quad, una }, [a, b])
+    }
+
+    /// The peer, still missing the first segment, acknowledges what it has once more.
+    fn dup_ack(&mut self) -> Vec<Segment> {
+        self.s.send(
+            Craft::new(self.quad.remote(), self.quad.local())
+                .seq(1001)
+                .ack(self.una),
+        );
+        self.s.take()
+    }
+
+    /// Where the `n`th segment of the stream starts.
+    fn seg(&self, n: u32) -> u32 {
+        self.una.wrapping_add(n * MSS)
+    }
+}
+
+#[test]
+fn one_lost_segment_is_repaired_by_fast_retransmit() {
+    let (mut l, [a, b]) = Lossy::new(true);
+    assert_eq!((a.seq, b.seq), (l.seg(0), l.seg(1)));
+
+    // a is lost; b, then each new segment limited transmit lets out, draws a duplicate ACK
+    let first = l.dup_ack();
+    assert_eq!(first.len(), 1);
+    assert_eq!((first[0].seq, first[0].len()), (l.seg(2), MSS));
+    let second = l.dup_ack();
+    assert_eq!(second.len(), 1);
+    assert_eq!(second[0].seq, l.seg(3));
+
+    // the third sends a again first, without any time passing for a retransmission timeout;
+    // fast recovery may let new data follow it
+    let third = l.dup_ack();
+    assert_eq!(
+        (third[0].seq, third[0].payload.as_slice()),
+        (a.seq, a.payload.as_slice())
+    );
+
+    // and once it arrives the peer has everything sent so far, and the rest follows
+    let last = third.last().unwrap();
+    let upto = last.seq.wrapping_add(last.len());
+    let (remote, local) = (l.quad.remote(), l.quad.local());
+    l.s.send(Craft::new(remote, local).seq(1001).ack(upto));
+    let rest = l.s.take();
+    assert!(
+        !rest.is_empty() && rest[0].seq == upto,
+        "the transfer stopped at {}",
+        upto
+    );
+}
+
+#[test]
+fn without_limited_transmit_the_loss_waits_for_the_timer() {
+    let (mut l, [a, _]) = Lossy::new(false);
+
+    // nothing new goes out for b's duplicate ACK, so there is never a third
+    assert!(l.dup_ack().is_empty());
+    l.s.advance(Duration::from_millis(100));
+    assert!(l.s.take().is_empty());
+
+    let mut waited = Duration::from_millis(100);
+    let resent = loop {
+        l.s.advance(TICK);
+        waited += TICK;
+        if let Some(seg) = l.s.take().into_iter().find(|seg| seg.seq == a.seq) {
+            break seg;
+        }
+        assert!(waited < Duration::from_secs(5), "a was never sent again");
+    };
+    assert_eq!(resent.payload, a.payload);
+    assert!(
+        waited >= Duration::from_secs(1),
+        "a went again after only {:?}",
+        waited
+    );
+}
@@ -0,0 +1,126 @@
+//! Dropping a listener and binding its port again, as a server restarting does: the connections
+//! it took in and nobody accepted are reset and gone from the table at once, those accepted
+//! carry on, and the port is free again, or free to share with them on request.
+
+mod common;
+
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::SocketAddrV4;
+use std::time::{Duration, Instant};
+
+use common::threaded::Threaded;
+use common::{Craft, Segment, PEER, US};
+
+fn from(port: u16) -> Craft {
+    Craft::new(SocketAddrV4::new(PEER, port), SocketAddrV4::new(US, 80))
+}
+
+/// Complete a handshake with the listener on port 80 from `port`, returning our ISS.
+fn handshake(t: &mut Threaded, port: u16) -> u32 {
+    t.send(from(port).syn().seq(1000).mss(1460));
+    let iss = t.take_one().seq;
+    t.send(from(port).seq(1001).ack(iss.wrapping_add(1)));
+    iss
+}
+
+/// What the interface sent to each port, in order.
+fn by_port(sent: Vec<Segment>) -> HashMap<u16, Vec<Segment>> {
+    let mut ports: HashMap<u16, Vec<Segment>> = HashMap::new();
+    for seg in sent {
+        ports.entry(seg.dst.port()).or_default().push(seg);
+    }
+    ports
+}
+
+#[test]
+fn unaccepted_connections_go_with_their_listener() {
+    let mut t = Threaded::new();
+    let listener = t.interface.bind(80).unwrap();
+    let served = handshake(&mut t, 40000);
+    let mut stream = listener.accept().unwrap();
+    // one connection waits to be accepted, and another is halfway through its handshake
+    let waiting = handshake(&mut t, 40001);
+    t.send(from(40002).syn().seq(5000).mss(1460));
+    let half_open = t.take_one().seq;
+    assert_eq!(t.interface.connections().len(), 3);
+
+    drop(listener);
+    let sent = by_port(t.take());
+    assert_eq!(sent.len(), 2, "{:?}", sent.keys());
+    for (port, iss) in [(40001, waiting), (40002, half_open)] {
+        let rst = &sent[&port][0];
+        assert!(rst.rst, "{}", rst.describe());
+        assert_eq!(rst.seq, iss.wrapping_add(1));
+    }
+    let left: Vec<_> = t.interface.connections().into_iter().map(|c| c.0).collect();
+    assert_eq!(left, [stream.quad()]);
+
+    // the accepted stream holds on to the port, unless the new listener asks to share it
+    match t.interface.bind(80) {
+        Err(e) => assert_eq!(e.kind(), ErrorKind::AddrInUse),
+        Ok(_) => panic!("bound over an open connection"),
+    }
+    let again = t.interface.bind_reuse(80).unwrap();
+    t.send(
+        from(40000)
+            .seq(1001)
+            .ack(served.wrapping_add(1))
+            .payload(b"still here"),
+    );
+    let mut buf = [0; 10];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"still here");
+    stream.write_all(b"and so am I").unwrap();
+    // on a busy machine the ACK of what was read may go out on its own first
+    let data: Vec<_> = t
+        .take()
+        .into_iter()
+        .filter(|seg| !seg.payload.is_empty())
+        .collect();
+    assert_eq!(data.len(), 1);
+    assert_eq!(data[0].payload, b"and so am I");
+    drop(again);
+
+    // once it has closed, and sits out TIME-WAIT, the port is free for anyone
+    drop(stream);
+    let fin = t.take_one();
+    assert!(fin.fin);
+    t.send(from(40000).seq(1011).ack(fin.seq.wrapping_add(1)).fin());
+    t.take_one();
+    t.interface.bind(80).unwrap();
+}
+
+#[test]
+fn dropping_and_binding_in_a_tight_loop() {
+    let mut t = Threaded::new();
+    for i in 0..100 {
+        let listener = t.interface.bind(80).unwrap();
+        // one SYN the listener has surely taken in, and one that may be in flight still
+        t.send(from(41000 + i).syn().seq(1000).mss(1460));
+        let start = Instant::now();
+        while t.interface.connections().is_empty() {
+            assert!(start.elapsed() < Duration::from_secs(1));
+            std::thread::yield_now();
+        }
+        t.send(from(42000 + i).syn().seq(1000).mss(1460));
+        drop(listener);
+    }
+
+    // every connection taken in was reset, after its SYN-ACK if that had gone out, and none is
+    // left
+    let sent = by_port(t.take());
+    assert!((100..=200).contains(&sent.len()), "{} ports", sent.len());
+    for (port, segs) in &sent {
+        let flags: Vec<_> = segs.iter().map(Segment::flags).collect();
+        match &flags[..] {
+            [syn_ack, rst] if syn_ack == "S." && rst == "R." => {
+                assert_eq!(segs[1].seq, segs[0].seq.wrapping_add(1));
+            }
+            [rst] if rst == "R." || rst == "R" => {}
+            _ => panic!("port {} got {:?}", port, flags),
+        }
+    }
+    assert!((41000..41100).all(|port| sent.contains_key(&port)));
+    assert!(t.interface.connections().is_empty());
+}
@@ -0,0 +1,100 @@
+//! What a listener sets for the connections it takes in: a configuration of their own, in
+//! force from the SYN-ACK on, and the stream options each accepted stream starts out with.
+
+mod common;
+
+use std::io;
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use common::threaded::Threaded;
+use common::{Craft, Segment, PEER, US};
+use trust::{Priority, TcpConfig, TcpListener, TcpStream};
+
+fn peer(port: u16) -> SocketAddrV4 {
+    SocketAddrV4::new(PEER, port)
+}
+
+/// Take in a connection from the peer at `port`: returns the SYN-ACK and the stream.
+fn take_in(t: &mut Threaded, listener: &TcpListener, port: u16) -> (Segment, TcpStream) {
+    let us = SocketAddrV4::new(US, 80);
+    t.send(Craft::new(peer(port), us).syn().seq(1000).mss(1460));
+    let syn_ack = t.next();
+    t.send(
+        Craft::new(peer(port), us)
+            .seq(1001)
+            .ack(syn_ack.seq.wrapping_add(1)),
+    );
+    (syn_ack, listener.accept().unwrap())
+}
+
+#[test]
+fn the_listeners_config_is_in_force_from_the_syn_ack() {
+    let mut t = Threaded::new();
+    let mut listener = t.interface.bind(80).unwrap();
+    listener
+        .set_config(Some(TcpConfig {
+            recv_buffer: 8192,
+            send_buffer: 16384,
+            ..TcpConfig::default()
+        }))
+        .unwrap();
+    assert_eq!(listener.config().recv_buffer, 8192);
+
+    let (syn_ack, stream) = take_in(&mut t, &listener, 40000);
+    assert_eq!(syn_ack.window, 8192);
+    let info = stream.info().unwrap();
+    assert_eq!((info.rcv_buffer, info.send_buffer), (8192, 16384));
+
+    // and with it gone, connections are configured as the interface's are
+    listener.set_config(None).unwrap();
+    let (syn_ack, _) = take_in(&mut t, &listener, 40001);
+    assert_eq!(
+        syn_ack.window as usize,
+        TcpConfig::default().recv_buffer.min(65535)
+    );
+}
+
+#[test]
+fn a_config_no_connection_could_run_with_is_refused() {
+    let mut t = Threaded::new();
+    let mut listener = t.interface.bind(80).unwrap();
+    let err = listener
+        .set_config(Some(TcpConfig {
+            recv_buffer: 0,
+            ..TcpConfig::default()
+        }))
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(
+        listener.config().recv_buffer,
+        TcpConfig::default().recv_buffer
+    );
+}
+
+#[test]
+fn accepted_streams_start_out_with_the_listeners_options() {
+    let mut t = Threaded::new();
+    let mut listener = t.interface.bind(80).unwrap();
+    listener
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    listener.set_cork(true);
+    listener.set_priority(Priority::High);
+    listener.set_abort_on_drop(true);
+
+    let (_, stream) = take_in(&mut t, &listener, 40000);
+    assert_eq!(stream.read_timeout().unwrap(), Some(Duration::from_secs(2)));
+    assert!(stream.cork().unwrap());
+    assert_eq!(stream.priority().unwrap(), Priority::High);
+    assert!(stream.abort_on_drop().unwrap());
+
+    // which the stream can still change for itself
+    stream.set_cork(false).unwrap();
+    assert!(!stream.cork().unwrap());
+    assert!(listener.cork());
+
+    // and a zero timeout is refused on the listener as on a stream
+    let err = listener.set_read_timeout(Some(Duration::ZERO)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
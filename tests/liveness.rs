@@ -0,0 +1,81 @@
+//! Whoever waits on an interface finds out when its packet loop dies, and dropping the
+//! interface afterwards is quiet about it.
+
+mod common;
+
+use std::io::Read;
+use std::net::SocketAddrV4;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use common::{Craft, PEER, US};
+use trust::{Impairments, InterfaceBuilder, InterfaceError, Nic, SimNet, TcpConfig};
+
+/// Long enough for a blocked call to have noticed, in real time.
+const PROMPTLY: Duration = Duration::from_secs(5);
+
+#[test]
+fn blocked_calls_fail_when_the_device_goes_away() {
+    // both ends on the system clock, with packet loops of their own
+    let (net, a, b) = SimNet::new(0, Impairments::default(), Impairments::default()).unwrap();
+    let mut server = InterfaceBuilder::new();
+    server.config(TcpConfig::default()).add_nic(a, &[US]);
+    let mut server = server.build().unwrap();
+    let mut client = InterfaceBuilder::new();
+    client.add_nic(b, &[PEER]);
+    let mut client = client.build().unwrap();
+
+    let listener = server.bind(80).unwrap();
+    let _stream = client.connect(PEER, SocketAddrV4::new(US, 80)).unwrap();
+    let mut accepted = listener.accept().unwrap();
+
+    let (done, finished) = mpsc::channel();
+    let reader = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut buf = [0; 16];
+            done.send(("read", accepted.read(&mut buf).is_err()))
+                .unwrap();
+        })
+    };
+    let acceptor = thread::spawn(move || {
+        done.send(("accept", listener.accept().is_err())).unwrap();
+    });
+    // let both block
+    thread::sleep(Duration::from_millis(50));
+
+    net.unplug();
+    for _ in 0..2 {
+        let (what, failed) = finished
+            .recv_timeout(PROMPTLY)
+            .expect("a blocked call still waits");
+        assert!(failed, "{} returned without an error", what);
+    }
+    reader.join().unwrap();
+    acceptor.join().unwrap();
+
+    let e = server.run().unwrap_err();
+    let failure = InterfaceError::of(&e).expect("a device failure");
+    assert_eq!(failure.error.raw_os_error(), Some(libc::ENODEV));
+}
+
+#[test]
+fn dropping_after_the_packet_loop_panicked() {
+    let (_net, a, mut b) = SimNet::new(0, Impairments::default(), Impairments::default()).unwrap();
+    let mut builder = InterfaceBuilder::new();
+    builder.add_nic(a, &[US]);
+    let mut interface = builder.build().unwrap();
+    interface.set_raw_handler(|_| panic!("the raw handler failed"));
+
+    // a segment for a port with no listener goes to the raw handler, which takes the packet
+    // loop down with it
+    b.send(&Craft::new(SocketAddrV4::new(PEER, 1234), SocketAddrV4::new(US, 9)).build())
+        .unwrap();
+    let listener = interface.bind(80).unwrap();
+    let (done, failed) = mpsc::channel();
+    thread::spawn(move || done.send(listener.accept().is_err()).unwrap());
+    assert!(failed.recv_timeout(PROMPTLY).unwrap());
+
+    drop(interface);
+}
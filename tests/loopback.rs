@@ -0,0 +1,67 @@
+//! Connections to our own addresses: both ends live in the one connection table, under mirrored
+//! quads, and go through the whole state machine without a packet reaching the device.
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddrV4};
+
+use common::{pattern, state, Scripted, TICK, US};
+use trust::{State, TcpConfig};
+
+#[test]
+fn a_connection_to_ourselves_never_touches_the_device() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let mut client = s.interface.connect(US, SocketAddrV4::new(US, 80)).unwrap();
+    s.advance(TICK);
+    let server = s.accepted().expect("the listener took the connection in");
+    let quad = client.quad();
+    assert_eq!(
+        (server.local(), server.remote()),
+        (quad.remote(), quad.local())
+    );
+    assert_eq!(state(&s.interface, quad), Some(State::Estab));
+    assert_eq!(state(&s.interface, server), Some(State::Estab));
+
+    // both ways at once, and more than a window's worth from the server, whose writes go in as
+    // there is room for them
+    let up = pattern(133, 48 << 10);
+    let down = pattern(134, 96 << 10);
+    let mut got_up = Vec::new();
+    let mut got_down = Vec::new();
+    let (mut written_up, mut written_down) = (0, 0);
+    for _ in 0..100 {
+        if written_up < up.len() {
+            written_up += client.write(&up[written_up..]).unwrap();
+        }
+        if written_down < down.len() {
+            if let Ok(n) = s.interface.write_on(server, &down[written_down..]) {
+                written_down += n;
+            }
+        }
+        s.advance(TICK);
+        got_up.extend(s.read_all(server));
+        got_down.extend(s.read_all(quad));
+    }
+    assert!(got_up == up, "the server got {} bytes", got_up.len());
+    assert!(got_down == down);
+
+    // and both ends close as they would over a wire
+    client.shutdown(Shutdown::Write).unwrap();
+    s.advance(TICK);
+    assert_eq!(s.read_all(server), b"");
+    s.interface.shutdown_on(server, Shutdown::Write).unwrap();
+    s.advance(TICK);
+    assert_eq!(client.read(&mut [0; 8]).unwrap(), 0);
+    assert_eq!(state(&s.interface, quad), Some(State::TimeWait));
+    assert!(matches!(
+        state(&s.interface, server),
+        None | Some(State::Closed)
+    ));
+
+    assert!(
+        s.take().is_empty(),
+        "packets for our own address went out on the device"
+    );
+}
@@ -0,0 +1,147 @@
+//! Loss-response profiles: each resolves to its own duplicate-ACK threshold and RTO bounds,
+//! single values can be overridden, connections report what they settled on, and the
+//! threshold and initial RTO are the ones they go by.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{connect, pattern, peer, Craft, Scripted, TICK, US};
+use trust::{LossResponse, Profile, TcpConfig};
+
+const MSS: u32 = 1460;
+
+fn profile(profile: Profile) -> TcpConfig {
+    TcpConfig {
+        profile,
+        ..TcpConfig::default()
+    }
+}
+
+#[test]
+fn each_profile_resolves_to_its_values() {
+    let ms = Duration::from_millis;
+    let s = Duration::from_secs;
+    assert_eq!(TcpConfig::default().profile, Profile::Internet);
+    for (p, dup_ack_threshold, min_rto, max_rto, initial_rto) in [
+        (Profile::Datacenter, 2, ms(10), s(10), ms(200)),
+        (Profile::Internet, 3, s(1), s(60), s(1)),
+        (Profile::Lossy, 3, s(1), s(120), s(3)),
+    ] {
+        let expected = LossResponse {
+            dup_ack_threshold,
+            min_rto,
+            max_rto,
+            initial_rto,
+        };
+        assert_eq!(p.loss_response(), expected, "{:?}", p);
+        assert_eq!(profile(p).loss_response(), expected, "{:?}", p);
+    }
+}
+
+#[test]
+fn an_override_replaces_only_its_own_value() {
+    let config = TcpConfig {
+        min_rto: Some(Duration::from_millis(50)),
+        ..profile(Profile::Datacenter)
+    };
+    assert_eq!(
+        config.loss_response(),
+        LossResponse {
+            min_rto: Duration::from_millis(50),
+            ..Profile::Datacenter.loss_response()
+        }
+    );
+    // and a threshold of none at all is one
+    let config = TcpConfig {
+        dup_ack_threshold: Some(0),
+        ..TcpConfig::default()
+    };
+    assert_eq!(config.loss_response().dup_ack_threshold, 1);
+}
+
+#[test]
+fn a_connection_reports_what_it_settled_on() {
+    let config = TcpConfig {
+        max_rto: Some(Duration::from_secs(5)),
+        ..profile(Profile::Datacenter)
+    };
+    let mut s = Scripted::new(config.clone());
+    let (stream, _) = connect(&mut s, peer(), 1000);
+    let info = stream.info().unwrap();
+    assert_eq!(info.profile, Profile::Datacenter);
+    assert_eq!(info.loss_response, config.loss_response());
+    assert_eq!(info.dup_ack_threshold, 2);
+}
+
+/// How many duplicate ACKs it takes for the first of six segments to be sent again, under
+/// `config` without limited transmit.
+fn dup_acks_to_retransmit(config: TcpConfig) -> u32 {
+    let mut s = Scripted::new(TcpConfig {
+        limited_transmit: false,
+        ..config
+    });
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    s.interface
+        .write_on(stream.quad(), &pattern(173, 6 * MSS as usize))
+        .unwrap();
+    s.advance(TICK);
+    assert_eq!(s.take().len(), 6);
+    for n in 1..=5 {
+        s.send(
+            Craft::new(peer(), stream.quad().local())
+                .seq(1001)
+                .ack(iss.wrapping_add(1)),
+        );
+        if let Some(seg) = s.take().first() {
+            assert_eq!(seg.seq, iss.wrapping_add(1));
+            return n;
+        }
+    }
+    panic!("no fast retransmit");
+}
+
+#[test]
+fn the_threshold_is_what_fast_retransmit_waits_for() {
+    assert_eq!(dup_acks_to_retransmit(profile(Profile::Datacenter)), 2);
+    assert_eq!(dup_acks_to_retransmit(profile(Profile::Internet)), 3);
+    let config = TcpConfig {
+        dup_ack_threshold: Some(4),
+        ..profile(Profile::Datacenter)
+    };
+    assert_eq!(dup_acks_to_retransmit(config), 4);
+}
+
+/// How long our SYN waits to be sent again, under `config`.
+fn first_rto(config: TcpConfig) -> Duration {
+    let mut s = Scripted::new(config);
+    let _stream = s.interface.connect(US, peer()).unwrap();
+    s.advance(TICK);
+    let syn = s.take_one();
+    let start = s.net.now();
+    loop {
+        assert!(
+            s.net.now() - start < Duration::from_secs(10),
+            "never sent again"
+        );
+        s.advance(TICK);
+        if let Some(again) = s.take().first() {
+            assert_eq!(again.seq, syn.seq);
+            return s.net.now() - start;
+        }
+    }
+}
+
+#[test]
+fn the_syn_waits_the_initial_rto() {
+    for p in [Profile::Datacenter, Profile::Internet, Profile::Lossy] {
+        let rto = first_rto(profile(p));
+        let initial = p.loss_response().initial_rto;
+        assert!(
+            rto >= initial && rto <= initial + 2 * TICK,
+            "{:?}: {:?}",
+            p,
+            rto
+        );
+    }
+}
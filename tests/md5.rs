@@ -0,0 +1,202 @@
+//! TCP MD5 signatures (RFC 2385), checked against a connection from Linux with `TCP_MD5SIG`
+//! (`tests/captures/linux-md5.hex`): the stack has to take Linux's signed segments, sign its
+//! own the way Linux checked them, and drop whatever is unsigned or signed wrongly.
+
+mod common;
+
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+use common::{golden, Craft, Scripted, Segment, PEER, TICK, US};
+use trust::{State, TcpConfig, TcpListener};
+
+const KEY: &[u8] = b"trust-md5-key";
+
+/// The ISS the stack had when the capture was made.
+const ISS: u32 = 0x1000_0000;
+
+/// The packets of the capture, in order: Linux's SYN, our SYN-ACK, then Linux's ACK, its
+/// "ping" and its FIN.
+struct Linux {
+    syn: Vec<u8>,
+    syn_ack: Vec<u8>,
+    ack: Vec<u8>,
+    ping: Vec<u8>,
+    fin: Vec<u8>,
+}
+
+impl Linux {
+    fn capture() -> Self {
+        let text = include_str!("captures/linux-md5.hex");
+        // a blank line after each packet, and after the comment at the top
+        let mut packets = text
+            .split("\n\n")
+            .map(golden::parse)
+            .filter(|p| !p.is_empty());
+        let mut next = || packets.next().unwrap();
+        Linux {
+            syn: next(),
+            syn_ack: next(),
+            ack: next(),
+            ping: next(),
+            fin: next(),
+        }
+    }
+}
+
+/// The peer's address in the capture.
+fn peer() -> SocketAddrV4 {
+    Segment::parse(&Linux::capture().syn).unwrap().src
+}
+
+/// A scripted interface as the stack was set up for the capture, listening on port 80 with
+/// `key` for the peer if there is one.
+fn listening(key: Option<&[u8]>) -> (Scripted, TcpListener) {
+    let mut s = Scripted::with(TcpConfig::default(), |builder| {
+        builder.fixed_iss(ISS);
+    });
+    let mut listener = s.interface.bind(80).unwrap();
+    if let Some(key) = key {
+        listener.set_md5_key(PEER, key).unwrap();
+    }
+    (s, listener)
+}
+
+/// `packet` with the 16-bit word at `at` replaced by `word`, and the TCP checksum fixed up to
+/// match (RFC 1624), so only the signature can tell.
+fn rewrite(packet: &[u8], at: usize, word: u16) -> Vec<u8> {
+    let mut packet = packet.to_vec();
+    let old = u16::from_be_bytes([packet[at], packet[at + 1]]);
+    let sum_at = 20 + 16;
+    let sum = u16::from_be_bytes([packet[sum_at], packet[sum_at + 1]]);
+    let mut folded = (!sum as u32) + (!old as u32) + word as u32;
+    while folded > 0xffff {
+        folded = (folded & 0xffff) + (folded >> 16);
+    }
+    packet[at..at + 2].copy_from_slice(&word.to_be_bytes());
+    packet[sum_at..sum_at + 2].copy_from_slice(&(!(folded as u16)).to_be_bytes());
+    packet
+}
+
+#[test]
+fn the_linux_connection_goes_through_with_the_same_syn_ack() {
+    let linux = Linux::capture();
+    let (mut s, _listener) = listening(Some(KEY));
+
+    s.send_raw(&linux.syn);
+    let syn_ack = s.take_one();
+    assert!(
+        syn_ack.packet == linux.syn_ack,
+        "{} is not what Linux verified:\n{}",
+        syn_ack.describe(),
+        Segment::parse(&linux.syn_ack).unwrap().describe()
+    );
+
+    s.send_raw(&linux.ack);
+    let quad = s
+        .accepted()
+        .expect("Linux's signed ACK completed the handshake");
+    s.send_raw(&linux.ping);
+    s.send_raw(&linux.fin);
+    assert_eq!(s.read_all(quad), b"ping");
+    assert_eq!(common::state(&s.interface, quad), Some(State::CloseWait));
+    assert_eq!(s.interface.md5_failures(), 0);
+
+    // whatever the stack sends from here is signed too
+    s.interface.write_on(quad, b"pong").unwrap();
+    s.advance(TICK);
+    for seg in s.take() {
+        let md5 = seg.option(19).expect("every segment is signed");
+        assert_eq!(md5.len(), 16);
+    }
+}
+
+#[test]
+fn a_bad_signature_is_dropped_and_counted() {
+    let linux = Linux::capture();
+    let (mut s, _listener) = listening(Some(KEY));
+    s.send_raw(&linux.syn);
+    s.send_raw(&linux.ack);
+    let quad = s.accepted().unwrap();
+    s.take();
+
+    // the signature starts after the two NOPs and the option's kind and length
+    let at = 20 + 20 + 4;
+    let word = u16::from_be_bytes([linux.ping[at], linux.ping[at + 1]]);
+    s.send_raw(&rewrite(&linux.ping, at, word ^ 0x0100));
+    assert!(
+        s.take().is_empty(),
+        "a forged segment is not even acknowledged"
+    );
+    assert_eq!(s.read_all(quad), b"");
+    assert_eq!(s.interface.md5_failures(), 1);
+
+    // the genuine one still gets through
+    s.send_raw(&linux.ping);
+    assert_eq!(s.read_all(quad), b"ping");
+    assert_eq!(s.interface.md5_failures(), 1);
+}
+
+#[test]
+fn signatures_are_wanted_from_keyed_peers_and_only_from_them() {
+    let linux = Linux::capture();
+
+    // a signature made with another key, and one nobody asked for
+    let (mut s, _listener) = listening(Some(b"not-the-key"));
+    s.send_raw(&linux.syn);
+    assert!(s.take().is_empty());
+    assert_eq!(s.interface.md5_failures(), 1);
+    let (mut s, _listener) = listening(None);
+    s.send_raw(&linux.syn);
+    assert!(s.take().is_empty());
+    assert_eq!(s.interface.md5_failures(), 1);
+
+    // and a peer with a key that sends an unsigned SYN
+    let (mut s, _listener) = listening(Some(KEY));
+    let us = SocketAddrV4::new(US, 80);
+    s.send(Craft::new(peer(), us).syn().seq(1000).mss(1460));
+    assert!(s.take().is_empty());
+    assert_eq!(s.interface.md5_failures(), 1);
+
+    // while other peers carry on without
+    let other = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 3), 40000);
+    s.send(Craft::new(other, us).syn().seq(1000).mss(1460));
+    let syn_ack = s.take_one();
+    assert_eq!((syn_ack.flags().as_str(), syn_ack.option(19)), ("S.", None));
+}
+
+#[test]
+fn the_signature_comes_out_of_the_options_and_data_budget() {
+    let linux = Linux::capture();
+
+    // Linux left timestamps out of its SYN to make room; ours still has the MSS it needs
+    let syn = Segment::parse(&linux.syn).unwrap();
+    assert_eq!((syn.mss(), syn.timestamps()), (Some(1460), None));
+    let ours = Segment::parse(&linux.syn_ack).unwrap();
+    assert_eq!(ours.mss(), Some(1460));
+    assert!(ours.options.len() <= 40);
+
+    // full-sized segments give up the option's 20 bytes of data to stay within Linux's MSS
+    let (mut s, _listener) = listening(Some(KEY));
+    s.send_raw(&linux.syn);
+    s.send_raw(&linux.ack);
+    let quad = s.accepted().unwrap();
+    s.take();
+    s.interface
+        .write_on(quad, &common::pattern(5, 3000))
+        .unwrap();
+    s.advance(TICK);
+    let sizes: Vec<_> = s.take().iter().map(|seg| seg.payload.len()).collect();
+    assert_eq!(sizes, [1440, 1440, 120]);
+
+    // the same holds for a SYN of our own
+    let stream = s
+        .interface
+        .connect_with_md5(US, SocketAddrV4::new(PEER, 179), KEY)
+        .unwrap();
+    s.advance(TICK);
+    let syn = s.take_one();
+    assert_eq!(syn.src, stream.quad().local());
+    assert_eq!(syn.option(19).map(<[u8]>::len), Some(16));
+    assert_eq!(syn.mss(), Some(1460));
+    assert!(syn.options.len() <= 40);
+}
@@ -0,0 +1,101 @@
+//! The maximum segment lifetime as a setting: TIME-WAIT lasts twice it, to the tick, and so
+//! does the record of a connection closed without one, which keeps old duplicates of its SYN
+//! out of the next connection on its quad.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{accept, peer, state, us, Craft, Scripted, TICK};
+use trust::{DropReason, Quad, State, TcpConfig};
+
+fn with_msl(msl: Duration) -> Scripted {
+    Scripted::new(TcpConfig {
+        msl,
+        ..TcpConfig::default()
+    })
+}
+
+/// How long `quad` stays in the table from now, to the tick.
+fn lingers(s: &mut Scripted, quad: Quad) -> Duration {
+    let start = s.net.now();
+    while state(&s.interface, quad).is_some() {
+        s.advance(TICK);
+    }
+    s.net.now() - start
+}
+
+/// Close a connection we accepted from the peer before the peer does, through to TIME-WAIT.
+fn time_wait(s: &mut Scripted) -> Quad {
+    let (quad, iss) = accept(s, 80, peer(), 1000);
+    s.interface.close_on(quad);
+    s.advance(TICK);
+    assert!(s.take_one().fin);
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1001)
+            .ack(iss.wrapping_add(2))
+            .fin(),
+    );
+    assert_eq!(s.take_one().ack, Some(1002));
+    assert_eq!(state(&s.interface, quad), Some(State::TimeWait));
+    quad
+}
+
+#[test]
+fn time_wait_lasts_two_msl() {
+    assert_eq!(TcpConfig::default().msl, Duration::from_secs(30));
+    for msl in [
+        Duration::from_secs(30),
+        Duration::from_secs(5),
+        Duration::from_secs(1),
+    ] {
+        let mut s = with_msl(msl);
+        let _listener = s.interface.bind(80).unwrap();
+        let quad = time_wait(&mut s);
+        let lingered = lingers(&mut s, quad);
+        assert!(
+            lingered >= 2 * msl && lingered <= 2 * msl + TICK,
+            "TIME-WAIT lasted {:?} with an MSL of {:?}",
+            lingered,
+            msl
+        );
+    }
+}
+
+#[test]
+fn a_closed_connection_guards_its_quad_for_two_msl() {
+    let msl = Duration::from_secs(1);
+    let mut s = with_msl(msl);
+    let _listener = s.interface.bind(80).unwrap();
+    // the peer closes first, so TIME-WAIT is the peer's and we keep only a record
+    let (quad, iss) = accept(&mut s, 80, peer(), 1000);
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .fin(),
+    );
+    s.interface.close_on(quad);
+    s.advance(TICK);
+    let fin = s.take().pop().unwrap();
+    assert!(fin.fin);
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1002)
+            .ack(fin.seq.wrapping_add(1)),
+    );
+    assert_eq!(state(&s.interface, quad), None);
+
+    // a SYN from before where the old connection ended is an old duplicate for 2 MSL, and a
+    // new connection's after that
+    let old = Craft::new(peer(), us()).syn().seq(500).mss(1460);
+    s.advance(2 * msl - TICK);
+    s.send(old.clone());
+    assert!(s.take().is_empty());
+    assert_eq!(s.interface.drops().get(DropReason::OldIncarnation), 1);
+    s.advance(TICK);
+    s.send(old);
+    let syn_ack = s.take_one();
+    assert_eq!((syn_ack.flags().as_str(), syn_ack.ack), ("S.", Some(501)));
+}
@@ -0,0 +1,91 @@
+//! Segment sizes follow the device's MTU: the MSS we advertise, the data segments we send and
+//! the handshake itself all fit, whether the MTU was set when the interface was built or
+//! changed under connections already running.
+
+mod common;
+
+use std::io::{self, Write};
+use std::net::SocketAddrV4;
+
+use common::{accept, connect, pattern, peer, Craft, Scripted, TICK, US};
+use trust::{DeviceId, TcpConfig};
+
+const MTU: usize = 600;
+
+fn small() -> Scripted {
+    Scripted::with(TcpConfig::default(), |builder| {
+        builder.set_mtu(DeviceId::default(), MTU);
+    })
+}
+
+#[test]
+fn the_handshake_offers_what_fits() {
+    let mut s = small();
+    let _listener = s.interface.bind(80).unwrap();
+    s.send(
+        Craft::new(peer(), SocketAddrV4::new(US, 80))
+            .syn()
+            .seq(1000)
+            .mss(1460)
+            .wscale(7)
+            .sack_permitted()
+            .timestamps(1, 0),
+    );
+    let syn_ack = s.take_one();
+    assert!(syn_ack.packet.len() <= MTU);
+    assert_eq!(syn_ack.mss(), Some(MTU as u16 - 40));
+
+    // and connecting out, the same
+    let _stream = s.interface.connect(US, peer()).unwrap();
+    s.advance(TICK);
+    let syn = s.take_one();
+    assert_eq!(
+        (syn.flags().as_str(), syn.mss()),
+        ("S", Some(MTU as u16 - 40))
+    );
+}
+
+#[test]
+fn data_goes_in_segments_that_fit() {
+    let mut s = small();
+    let _listener = s.interface.bind(80).unwrap();
+    // the peer would take full-sized Ethernet segments, but our device cannot carry them
+    let (quad, _) = accept(&mut s, 80, peer(), 1000);
+    let data = pattern(132, 3000);
+    assert_eq!(s.interface.write_on(quad, &data).unwrap(), data.len());
+    s.advance(TICK);
+
+    let sent = s.take();
+    let sizes: Vec<_> = sent.iter().map(|seg| seg.payload.len()).collect();
+    assert_eq!(sizes, [560, 560, 560, 560, 560, 200]);
+    assert!(sent.iter().all(|seg| seg.packet.len() <= MTU));
+}
+
+#[test]
+fn a_smaller_mtu_shrinks_running_connections() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    stream.write_all(&[1; 2000]).unwrap();
+    s.advance(TICK);
+    let before: Vec<_> = s.take().iter().map(|seg| seg.payload.len()).collect();
+    assert_eq!(before, [1460, 540]);
+
+    s.interface.set_mtu(DeviceId::default(), 576).unwrap();
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .seq(1001)
+            .ack(iss.wrapping_add(2001)),
+    );
+    stream.write_all(&[2; 2000]).unwrap();
+    s.advance(TICK);
+    let after: Vec<_> = s.take().iter().map(|seg| seg.payload.len()).collect();
+    assert_eq!(after, [536, 536, 536, 392]);
+}
+
+#[test]
+fn an_mtu_ipv4_cannot_carry_is_refused() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let e = s.interface.set_mtu(DeviceId::default(), 67).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    s.interface.set_mtu(DeviceId::default(), 68).unwrap();
+}
@@ -0,0 +1,101 @@
+//! ACKs for data we have not sent, or only queued for a device that has not taken it, as a
+//! receiver guessing ahead sends: each is discarded and counted, and answered with an ACK
+//! saying where we are.
+
+mod common;
+
+use std::io::Write;
+use std::time::Duration;
+
+use common::{connect, peer, Craft, Scripted, TICK};
+use trust::{Impairments, TcpConfig};
+
+#[test]
+fn an_ack_past_snd_nxt_is_answered_with_ours() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    stream.write_all(&[1; 1000]).unwrap();
+    s.advance(TICK);
+    s.take_one();
+    let cwnd = stream.info().unwrap().cwnd;
+
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1 + 5000)),
+    );
+    let reply = s.take_one();
+    assert_eq!(reply.flags(), ".");
+    assert_eq!(
+        (reply.seq, reply.ack),
+        (iss.wrapping_add(1 + 1000), Some(1001))
+    );
+    let info = stream.info().unwrap();
+    assert_eq!(info.optimistic_acks, 1);
+    assert_eq!((info.bytes_in_flight, info.cwnd), (1000, cwnd));
+}
+
+#[test]
+fn acks_of_data_still_queued_for_the_device_are_discarded() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    // a device that takes nothing, so what is written waits in the queue for it
+    s.net.set_impairments(
+        Impairments {
+            bandwidth: Some(1),
+            queue: Some(0),
+            backpressure: true,
+            ..Impairments::default()
+        },
+        Impairments::default(),
+    );
+    stream.write_all(&[1; 10 * 1460]).unwrap();
+    s.advance(Duration::from_millis(100));
+    assert!(s.take().is_empty());
+    let info = stream.info().unwrap();
+    assert_eq!(info.bytes_in_flight, 10 * 1460);
+
+    // to the connection that data counts as sent, but an ACK for it cannot be honest
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1 + 5 * 1460)),
+    );
+    let after = stream.info().unwrap();
+    assert_eq!(after.optimistic_acks, 1);
+    assert_eq!(
+        (after.bytes_in_flight, after.cwnd),
+        (info.bytes_in_flight, info.cwnd)
+    );
+
+    // once the device takes it, the same ACK is taken too
+    s.net
+        .set_impairments(Impairments::default(), Impairments::default());
+    s.advance(TICK);
+    assert_eq!(s.take().iter().filter(|seg| seg.len() == 1460).count(), 10);
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1 + 5 * 1460)),
+    );
+    let taken = stream.info().unwrap();
+    assert_eq!(taken.optimistic_acks, 1);
+    assert_eq!(taken.bytes_in_flight, 5 * 1460);
+}
+
+#[test]
+fn an_honest_receiver_is_never_counted() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, _) = connect(&mut s, peer(), 1000);
+    stream.write_all(&[1; 10 * 1460]).unwrap();
+    s.advance(TICK);
+    for segment in s.take() {
+        s.send(
+            Craft::new(peer(), stream.quad().local())
+                .seq(1001)
+                .ack(segment.seq.wrapping_add(segment.len())),
+        );
+    }
+    let info = stream.info().unwrap();
+    assert_eq!((info.optimistic_acks, info.bytes_in_flight), (0, 0));
+}
@@ -0,0 +1,152 @@
+//! SACK and timestamps, as the handshake settles them and as they then go out on every segment.
+
+mod common;
+
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use common::{connection, peer, us, Craft, Scripted, PEER, TICK, US};
+use trust::{Quad, TcpConfig};
+
+/// A handshake with a listener on port 80, the peer's SYN offering SACK and timestamps with
+/// TSval 100, and its ACK carrying TSval 101: returns the quad, our ISS and the TSval of our
+/// SYN-ACK.
+fn accept_with_options(s: &mut Scripted) -> (Quad, u32, u32) {
+    s.send(
+        Craft::new(peer(), us())
+            .syn()
+            .seq(1000)
+            .mss(1460)
+            .sack_permitted()
+            .timestamps(100, 0),
+    );
+    let syn_ack = s.take_one();
+    let iss = syn_ack.seq;
+    let (ts_val, ts_ecr) = syn_ack
+        .timestamps()
+        .expect("the SYN-ACK carries timestamps");
+    assert_eq!(ts_ecr, 100);
+    assert!(syn_ack.sack_permitted());
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1001)
+            .ack(iss + 1)
+            .timestamps(101, ts_val),
+    );
+    (s.accepted().unwrap(), iss, ts_val)
+}
+
+#[test]
+fn every_segment_carries_timestamps_once_both_syns_offer_them() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let (quad, _, syn_ack_ts) = accept_with_options(&mut s);
+    let info = connection(&s.interface, quad).unwrap();
+    assert!(info.sack && info.timestamps);
+
+    s.advance(Duration::from_millis(500));
+    s.interface.write_on(quad, &[7; 3000]).unwrap();
+    s.advance(TICK);
+    let sent = s.take();
+    assert_eq!(sent.len(), 3);
+    for segment in &sent {
+        let (ts_val, ts_ecr) = segment.timestamps().expect("data carries timestamps");
+        // a millisecond clock, echoing the peer's latest
+        assert_eq!(ts_val.wrapping_sub(syn_ack_ts), 510);
+        assert_eq!(ts_ecr, 101);
+    }
+    // the option comes out of the data, not on top of the MSS
+    assert_eq!(sent[0].payload.len(), 1460 - 12);
+}
+
+#[test]
+fn data_past_a_gap_is_reported_in_sack_blocks_latest_first() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let (_, iss, syn_ack_ts) = accept_with_options(&mut s);
+    let ack = iss + 1;
+
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1011)
+            .ack(ack)
+            .timestamps(200, syn_ack_ts)
+            .payload(b"world"),
+    );
+    let reply = s.take_one();
+    assert_eq!(reply.ack, Some(1001));
+    assert_eq!(reply.sack(), vec![(1011, 1016)]);
+    // only a segment at the left edge of the window has its TSval echoed (RFC 7323 S4.3)
+    assert_eq!(reply.timestamps().unwrap().1, 101);
+
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1021)
+            .ack(ack)
+            .timestamps(201, syn_ack_ts)
+            .payload(b"again"),
+    );
+    let reply = s.take_one();
+    assert_eq!(reply.sack(), vec![(1021, 1026), (1011, 1016)]);
+
+    // filling the first gap leaves the second
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1001)
+            .ack(ack)
+            .timestamps(202, syn_ack_ts)
+            .payload(b"hello, the"),
+    );
+    let reply = s.take_one();
+    assert_eq!(reply.ack, Some(1016));
+    assert_eq!(reply.sack(), vec![(1021, 1026)]);
+    assert_eq!(reply.timestamps().unwrap().1, 202);
+
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1016)
+            .ack(ack)
+            .timestamps(203, syn_ack_ts)
+            .payload(b" and "),
+    );
+    let reply = s.take_one();
+    assert_eq!(reply.ack, Some(1026));
+    assert!(reply.sack().is_empty());
+    assert!(reply.option(5).is_none());
+}
+
+#[test]
+fn neither_is_offered_when_turned_off() {
+    let mut s = Scripted::new(TcpConfig {
+        sack: false,
+        timestamps: false,
+        ..TcpConfig::default()
+    });
+    let local = SocketAddrV4::new(US, 40000);
+    let server = SocketAddrV4::new(PEER, 80);
+    let quad = s.interface.connect_from(local, server).unwrap().into_quad();
+    s.advance(TICK);
+    let syn = s.take_one();
+    assert_eq!(syn.mss(), Some(1460));
+    assert!(!syn.sack_permitted());
+    assert_eq!(syn.timestamps(), None);
+
+    // what the peer offers all the same is not taken up
+    s.send(
+        Craft::new(server, local)
+            .syn()
+            .seq(5000)
+            .ack(syn.seq + 1)
+            .mss(1460)
+            .sack_permitted()
+            .timestamps(100, 0),
+    );
+    let info = connection(&s.interface, quad).unwrap();
+    assert!(!info.sack && !info.timestamps);
+    s.take();
+    s.interface.write_on(quad, &[7; 2000]).unwrap();
+    s.advance(TICK);
+    let sent = s.take();
+    assert!(sent[0].options.is_empty());
+    assert_eq!(sent[0].payload.len(), 1460);
+}
@@ -0,0 +1,111 @@
+//! The queue between the connections and a device that has stopped taking packets: data backs
+//! up only so far, and what does not fit is sent again later in its place, while handshakes and
+//! resets always get in and go out first once the device has room.
+
+mod common;
+
+use std::io::Write;
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use common::{connect, pattern, Craft, Scripted, PEER, TICK, US};
+use trust::{Impairments, Nic, TcpConfig};
+
+/// How many data packets the queue holds for a device, of each priority.
+const LIMIT: u32 = 256;
+
+/// Connections with a window's worth of data each waiting on the device, more than there is
+/// room for between them.
+const WRITERS: u16 = 30;
+
+/// A link that holds no packets and refuses every one, as a device with a full transmit queue.
+fn stuck() -> Impairments {
+    Impairments {
+        bandwidth: Some(1),
+        queue: Some(0),
+        backpressure: true,
+        ..Impairments::default()
+    }
+}
+
+#[test]
+fn a_device_that_takes_nothing() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let mut writers: Vec<_> = (0..WRITERS)
+        .map(|i| connect(&mut s, SocketAddrV4::new(PEER, 40000 + i), 1000))
+        .collect();
+    let (idle, _) = connect(&mut s, SocketAddrV4::new(PEER, 39999), 1000);
+
+    s.net.set_impairments(stuck(), Impairments::default());
+    let data = pattern(148, 20 * 1460);
+    for (stream, _) in &mut writers {
+        stream.write_all(&data).unwrap();
+    }
+    s.advance(Duration::from_millis(100));
+    // and while it is all stuck, a connection is reset and another opened
+    s.interface.abort(idle.quad()).unwrap();
+    let _late = s
+        .interface
+        .connect(US, SocketAddrV4::new(PEER, 50000))
+        .unwrap();
+    s.advance(TICK);
+    assert!(s.take().is_empty());
+
+    // what the queue refused counts as never sent, so what is in flight is what it holds: up to
+    // its limit, and past it only for a writer with nothing else in it, so that every writer
+    // gets some in
+    let in_flight: Vec<_> = writers
+        .iter()
+        .map(|(stream, _)| stream.info().unwrap().bytes_in_flight / 1460)
+        .collect();
+    let queued: u32 = in_flight.iter().sum();
+    assert!(
+        (LIMIT..=LIMIT + WRITERS as u32).contains(&queued),
+        "{} data segments queued for a device taking none",
+        queued
+    );
+    assert!(in_flight.iter().all(|&n| n > 0));
+
+    s.net
+        .set_impairments(Impairments::default(), Impairments::default());
+    s.advance(TICK);
+    let first = s.take();
+    // the SYN and the RST go out before any of the data that was there before them
+    let mut control: Vec<_> = first[..2]
+        .iter()
+        .map(|seg| (seg.dst.port(), seg.flags()))
+        .collect();
+    control.sort();
+    assert_eq!(
+        control,
+        [(39999, "R.".to_string()), (50000, "S".to_string())]
+    );
+
+    // every writer's data arrives in full and in order, what was refused going out in its place
+    let mut wire: Vec<Vec<u8>> = vec![Vec::new(); WRITERS as usize];
+    let mut sent = first;
+    while wire.iter().any(|w| w.len() < data.len()) {
+        assert!(
+            s.net.now() < Duration::from_secs(5),
+            "the queue never drained"
+        );
+        for seg in sent.iter().filter(|seg| !seg.payload.is_empty()) {
+            let i = (seg.dst.port() - 40000) as usize;
+            let (stream, iss) = &writers[i];
+            let next = iss.wrapping_add(1 + wire[i].len() as u32);
+            assert_eq!(seg.seq, next, "writer {} sent a segment out of place", i);
+            wire[i].extend(&seg.payload);
+            s.peer
+                .send(
+                    &Craft::new(seg.dst, stream.quad().local())
+                        .seq(1001)
+                        .ack(next.wrapping_add(seg.payload.len() as u32))
+                        .build(),
+                )
+                .unwrap();
+        }
+        s.advance(TICK);
+        sent = s.take();
+    }
+    assert!(wire.iter().all(|w| *w == data));
+}
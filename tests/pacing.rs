@@ -0,0 +1,115 @@
+//! Send pacing: new data goes out a few segments at a time rather than a whole window at once,
+//! which a shallow bottleneck queue notices, while ACKs and retransmissions go when they are
+//! due regardless.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{accept, pattern, peer, Craft, Pair, Scripted, TICK};
+use trust::{Impairments, TcpConfig};
+
+fn paced(burst: usize) -> TcpConfig {
+    TcpConfig {
+        pacing: true,
+        pacing_burst: burst,
+        ..TcpConfig::default()
+    }
+}
+
+/// Send 300 KB from one end of a 1 MB/s link with room for 8 packets in its queue, and return
+/// how many packets the queue turned away.
+fn overflows(config: TcpConfig) -> u64 {
+    let bottleneck = Impairments {
+        bandwidth: Some(1 << 20),
+        queue: Some(8),
+        latency: Duration::from_millis(20),
+        ..Default::default()
+    };
+    let back = Impairments {
+        latency: Duration::from_millis(20),
+        ..Default::default()
+    };
+    let mut pair = Pair::new(117, bottleneck, back, config, TcpConfig::default());
+    let data = pattern(3, 300 << 10);
+    let done = pair.exchange(80, &data, &[], Duration::from_secs(30));
+    assert!(done.at_b == data, "the transfer came through corrupted");
+    pair.net.overflows()
+}
+
+#[test]
+fn pacing_overflows_a_shallow_queue_less_often() {
+    let bursting = overflows(TcpConfig::default());
+    let paced = overflows(paced(4));
+    assert!(
+        bursting > 0,
+        "the queue never filled, so there is nothing to compare"
+    );
+    assert!(
+        paced * 3 < bursting * 2,
+        "{} overflows paced against {} without",
+        paced,
+        bursting
+    );
+}
+
+#[test]
+fn a_burst_goes_out_at_once_and_the_rest_follows_over_time() {
+    let mut s = Scripted::new(paced(2));
+    let _listener = s.interface.bind(80).unwrap();
+    let (quad, _) = accept(&mut s, 80, peer(), 1000);
+    s.interface.write_on(quad, &pattern(0, 10 * 1460)).unwrap();
+
+    // nothing is acknowledged, but the initial window has room for all ten segments: what
+    // holds them back is pacing, and it lets them all out in time
+    let mut per_tick = Vec::new();
+    while per_tick.iter().sum::<usize>() < 10 {
+        s.advance(TICK);
+        per_tick.push(s.take().len());
+        assert!(per_tick.len() < 100, "pacing stalled: {:?}", per_tick);
+    }
+    assert!(per_tick.iter().all(|&n| n <= 2), "{:?}", per_tick);
+    assert_eq!(per_tick[0], 2);
+
+    // without pacing the same write goes out in one go
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let (quad, _) = accept(&mut s, 80, peer(), 1000);
+    s.interface.write_on(quad, &pattern(0, 10 * 1460)).unwrap();
+    s.advance(TICK);
+    assert_eq!(s.take().len(), 10);
+}
+
+#[test]
+fn acks_and_retransmissions_do_not_wait_for_credit() {
+    let mut s = Scripted::new(paced(4));
+    let _listener = s.interface.bind(80).unwrap();
+    let (quad, iss) = accept(&mut s, 80, peer(), 1000);
+    let us = quad.local();
+    s.interface.write_on(quad, &pattern(0, 16 * 1460)).unwrap();
+    s.advance(TICK);
+    let burst = s.take();
+    assert_eq!(burst.len(), 4);
+
+    // no time passes from here on, so the credit stays spent: data from the peer is
+    // acknowledged all the same
+    s.send(
+        Craft::new(peer(), us)
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .psh()
+            .payload(b"ping"),
+    );
+    let ack = s.take_one();
+    assert_eq!((ack.ack, ack.payload.len()), (Some(1005), 0));
+
+    // and the first segment lost, by three duplicate ACKs, goes again at once
+    for _ in 0..3 {
+        s.send(Craft::new(peer(), us).seq(1005).ack(iss.wrapping_add(1)));
+    }
+    let resent = s.take_one();
+    assert_eq!(
+        (resent.seq, resent.payload.len()),
+        (burst[0].seq, burst[0].payload.len())
+    );
+}
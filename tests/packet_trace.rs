@@ -0,0 +1,118 @@
+//! The per-connection packet trace: the latest segments either way, as many as configured and
+//! no more, cut to their headers and 64 bytes of data, kept past a reset for the stream and
+//! handed over with the error event.
+
+mod common;
+
+use std::io::Read;
+
+use common::{connect, peer, Craft, Scripted, TICK};
+use trust::{Direction, InterfaceEvent, TcpConfig, TracedPacket};
+
+const RING: usize = 16;
+
+fn traced() -> TcpConfig {
+    TcpConfig {
+        trace_packets: RING,
+        ..TcpConfig::default()
+    }
+}
+
+/// The TCP flags of a traced packet, behind its 20-byte IPv4 header.
+fn tcp_flags(packet: &TracedPacket) -> u8 {
+    packet.bytes[20 + 13]
+}
+
+const RST: u8 = 0x04;
+const SYN: u8 = 0x02;
+
+#[test]
+fn the_ring_ends_with_the_reset_that_ended_the_connection() {
+    let mut s = Scripted::new(traced());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    let segment = |seq| {
+        Craft::new(peer(), stream.quad().local())
+            .seq(seq)
+            .ack(iss.wrapping_add(1))
+    };
+    // twenty segments of data in, each drawing an ACK
+    for i in 0..20 {
+        s.send(segment(1001 + i * 100).payload(&[7; 100]));
+    }
+    s.send(segment(1001 + 20 * 100).rst());
+    assert!(stream.read(&mut [0; 16]).is_err());
+
+    let dump = stream.trace_dump().unwrap();
+    assert_eq!(dump.len(), RING);
+    let last = dump.last().unwrap();
+    assert_eq!(last.direction, Direction::Received);
+    assert_eq!(tcp_flags(last) & RST, RST);
+    // the handshake has long since gone round
+    assert!(dump.iter().all(|p| tcp_flags(p) & SYN == 0));
+    assert!(dump.windows(2).all(|w| w[0].at <= w[1].at));
+}
+
+#[test]
+fn data_is_cut_to_64_bytes() {
+    let mut s = Scripted::new(traced());
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .payload(&[7; 1000]),
+    );
+    let dump = stream.trace_dump().unwrap();
+    let data = dump
+        .iter()
+        .find(|p| p.direction == Direction::Received && p.len > 100)
+        .unwrap();
+    assert_eq!((data.len, data.bytes.len()), (20 + 20 + 1000, 20 + 20 + 64));
+    // the handshake is there too: our SYN first, then the SYN-ACK
+    assert_eq!(dump[0].direction, Direction::Sent);
+    assert_eq!(tcp_flags(&dump[0]) & SYN, SYN);
+    assert_eq!(dump[1].direction, Direction::Received);
+}
+
+#[test]
+fn our_own_reset_is_traced_as_sent() {
+    let mut s = Scripted::new(traced());
+    let (stream, _) = connect(&mut s, peer(), 1000);
+    s.interface.abort(stream.quad()).unwrap();
+    s.advance(TICK);
+    let dump = stream.trace_dump().unwrap();
+    let last = dump.last().unwrap();
+    assert_eq!(last.direction, Direction::Sent);
+    assert_eq!(tcp_flags(last) & RST, RST);
+}
+
+#[test]
+fn nothing_is_kept_by_default() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, _) = connect(&mut s, peer(), 1000);
+    assert!(stream.trace_dump().unwrap().is_empty());
+}
+
+#[test]
+fn the_error_event_carries_the_trace() {
+    let mut s = Scripted::new(traced());
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    s.take_events();
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .rst(),
+    );
+    let trace = s
+        .take_events()
+        .into_iter()
+        .find_map(|e| match e {
+            InterfaceEvent::Error(_, _, trace) => Some(trace),
+            _ => None,
+        })
+        .expect("an error event");
+    // SYN, SYN-ACK, ACK and the RST
+    assert_eq!(trace.len(), 4);
+    assert_eq!(tcp_flags(&trace[3]) & RST, RST);
+}
@@ -0,0 +1,130 @@
+//! With timestamps on, a segment whose timestamp is older than the peer's latest is an old
+//! duplicate, whatever its sequence number (PAWS, RFC 7323 S5), and so is a SYN for a new
+//! connection whose timestamp is older than the last one's on the same quad (RFC 6191).
+
+mod common;
+
+use common::{connection, peer, us, Craft, Scripted, TICK};
+use trust::{DropReason, Quad, State, TcpConfig};
+
+/// A handshake with the listener on port 80 from the peer's ISS `irs`, with timestamps, the
+/// peer's SYN carrying TSval `ts` and its ACK `ts + 1`: returns the quad, our ISS and our
+/// TSval.
+fn accept(s: &mut Scripted, irs: u32, ts: u32) -> (Quad, u32, u32) {
+    s.send(
+        Craft::new(peer(), us())
+            .syn()
+            .seq(irs)
+            .mss(1460)
+            .timestamps(ts, 0),
+    );
+    let syn_ack = s.take_one();
+    assert_eq!(syn_ack.flags(), "S.");
+    let (our_ts, _) = syn_ack.timestamps().unwrap();
+    s.send(
+        Craft::new(peer(), us())
+            .seq(irs + 1)
+            .ack(syn_ack.seq + 1)
+            .timestamps(ts + 1, our_ts),
+    );
+    (s.accepted().unwrap(), syn_ack.seq, our_ts)
+}
+
+#[test]
+fn a_segment_with_an_old_timestamp_is_dropped_and_acknowledged() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let (quad, iss, our_ts) = accept(&mut s, 1000, 100);
+
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1001)
+            .ack(iss + 1)
+            .timestamps(150, our_ts)
+            .payload(b"new"),
+    );
+    s.take();
+    // well inside the window, but from before what the peer sent last
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1004)
+            .ack(iss + 1)
+            .timestamps(120, our_ts)
+            .payload(b"old"),
+    );
+    let reply = s.take_one();
+    assert_eq!(reply.ack, Some(1004));
+    assert_eq!(reply.timestamps().unwrap().1, 150);
+    assert_eq!(s.read_all(quad), b"new");
+    assert_eq!(s.interface.drops().get(DropReason::Paws), 1);
+
+    // an RST is exempt
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1004)
+            .timestamps(120, our_ts)
+            .rst(),
+    );
+    assert_eq!(s.interface.drops().get(DropReason::Paws), 1);
+    assert_eq!(
+        connection(&s.interface, quad).map(|info| info.state),
+        Some(State::Closed)
+    );
+}
+
+#[test]
+fn an_old_incarnations_segments_are_kept_out_of_the_new_one() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let (quad, iss, our_ts) = accept(&mut s, 1000, 100);
+
+    // the peer closes first, so TIME-WAIT is its to sit out, and the quad is free here
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1001)
+            .ack(iss + 1)
+            .timestamps(102, our_ts)
+            .payload(b"first")
+            .fin(),
+    );
+    s.take();
+    s.interface.close_on(quad);
+    s.advance(TICK);
+    let fin = s.take_one();
+    assert!(fin.fin);
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1007)
+            .ack(fin.seq + 1)
+            .timestamps(103, our_ts),
+    );
+    assert_eq!(connection(&s.interface, quad), None);
+
+    // a SYN from before the old connection's last timestamp is an old duplicate, while one
+    // from after is a new connection, even though it starts below where the old one ended
+    s.send(Craft::new(peer(), us()).syn().seq(500).timestamps(90, 0));
+    assert!(s.take().is_empty());
+    assert_eq!(s.interface.drops().get(DropReason::OldIncarnation), 1);
+    let (quad, iss, our_ts) = accept(&mut s, 500, 200);
+    assert_eq!(connection(&s.interface, quad).unwrap().state, State::Estab);
+
+    // a delayed segment of the old connection that lands in the new one's window
+    s.send(
+        Craft::new(peer(), us())
+            .seq(501)
+            .ack(iss + 1)
+            .timestamps(102, our_ts)
+            .payload(b"first"),
+    );
+    assert_eq!(s.interface.drops().get(DropReason::Paws), 1);
+    assert!(s.read_all(quad).is_empty());
+
+    s.send(
+        Craft::new(peer(), us())
+            .seq(501)
+            .ack(iss + 1)
+            .timestamps(202, our_ts)
+            .payload(b"second"),
+    );
+    assert_eq!(s.read_all(quad), b"second");
+}
@@ -0,0 +1,139 @@
+//! `peek`: a look at what is buffered that leaves it there, for framing code that wants to see
+//! a length prefix before it reads.
+
+mod common;
+
+use std::io::{self, Read};
+use std::time::Duration;
+
+use common::{connect, pattern, peer, Craft, Scripted, TICK};
+use trust::{TcpConfig, TcpStream};
+
+/// Have the peer send `data` from `at`, relative to its ISS of 1000; returns the window our
+/// ACK of it advertised.
+fn deliver(s: &mut Scripted, stream: &TcpStream, iss: u32, at: u32, data: &[u8]) -> u16 {
+    let quad = stream.quad();
+    s.send(
+        Craft::new(quad.remote(), quad.local())
+            .seq(1001 + at)
+            .ack(iss.wrapping_add(1))
+            .psh()
+            .payload(data),
+    );
+    s.take().last().expect("the data was acknowledged").window
+}
+
+#[test]
+fn a_length_prefix_is_peeked_and_then_read() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    deliver(&mut s, &stream, iss, 0, b"\x00\x05hello\x00\x03bye");
+
+    let mut prefix = [0; 2];
+    assert_eq!(stream.peek(&mut prefix).unwrap(), 2);
+    let len = u16::from_be_bytes(prefix) as usize;
+    assert_eq!(
+        stream.peek(&mut prefix).unwrap(),
+        2,
+        "peeking again sees the same"
+    );
+
+    let mut frame = vec![0; 2 + len];
+    stream.read_exact(&mut frame).unwrap();
+    assert_eq!(frame, b"\x00\x05hello");
+
+    // what is left peeks and reads alike, and a larger buffer only gets what there is
+    let mut peeked = [0; 64];
+    let n = stream.peek(&mut peeked).unwrap();
+    let mut read = [0; 64];
+    assert_eq!(stream.read(&mut read).unwrap(), n);
+    assert_eq!(&peeked[..n], b"\x00\x03bye");
+    assert_eq!(peeked[..n], read[..n]);
+}
+
+#[test]
+fn peeking_leaves_the_window_where_it_was() {
+    let mut s = Scripted::new(TcpConfig {
+        recv_buffer: 8192,
+        ..TcpConfig::default()
+    });
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    // filled until the window is too small for a full-sized segment, so that opening it up
+    // again is worth a window update
+    let window = deliver(&mut s, &stream, iss, 0, &pattern(1, 7192));
+    assert_eq!(window, 1000);
+
+    let mut buf = [0; 7192];
+    assert_eq!(stream.peek(&mut buf).unwrap(), 7192);
+    s.advance(Duration::from_millis(500));
+    assert!(
+        s.take().is_empty(),
+        "peeking freed no room to tell the peer about"
+    );
+
+    // reading does, and the window update says so
+    stream.read_exact(&mut buf).unwrap();
+    s.advance(TICK);
+    let update = s.take_one();
+    assert_eq!(update.window, 8192);
+}
+
+#[test]
+fn peeking_across_the_end_of_the_buffer() {
+    let mut s = Scripted::new(TcpConfig {
+        recv_buffer: 4096,
+        ..TcpConfig::default()
+    });
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    let data = pattern(2, 4096 * 5);
+
+    // fill, read most of it, and fill again behind what is left, round and round
+    let mut at = 0;
+    let mut done = 0;
+    let mut buf = vec![0; 4096];
+    for _ in 0..5 {
+        let room = 4096 - (at - done);
+        deliver(&mut s, &stream, iss, at as u32, &data[at..at + room]);
+        at += room;
+
+        let n = stream.peek(&mut buf).unwrap();
+        assert_eq!(n, at - done);
+        assert!(
+            buf[..n] == data[done..at],
+            "peeked the wrong bytes at {}",
+            done
+        );
+        let n = stream.read(&mut buf[..3000]).unwrap();
+        done += n;
+        s.advance(TICK);
+        s.take();
+    }
+}
+
+#[test]
+fn peek_waits_like_read_and_ends_like_it() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    stream
+        .set_read_timeout(Some(Duration::from_millis(20)))
+        .unwrap();
+    let mut buf = [0; 8];
+    assert_eq!(
+        stream.peek(&mut buf).unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+
+    // data and then the peer's FIN: peek sees the data until it is read, then the end
+    let quad = stream.quad();
+    deliver(&mut s, &stream, iss, 0, b"last");
+    s.send(
+        Craft::new(quad.remote(), quad.local())
+            .seq(1005)
+            .ack(iss.wrapping_add(1))
+            .fin(),
+    );
+    assert_eq!(stream.peek(&mut buf).unwrap(), 4);
+    assert_eq!(stream.read(&mut buf).unwrap(), 4);
+    assert_eq!(stream.peek(&mut buf).unwrap(), 0);
+    assert_eq!(stream.read(&mut buf).unwrap(), 0);
+}
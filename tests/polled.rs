@@ -0,0 +1,210 @@
+//! An application that owns the packet loop: it calls `poll_once` itself, on one thread, and
+//! dispatches the events it returns, reading and writing connections by quad in between.
+
+mod common;
+
+use std::io;
+use std::net::{Shutdown, SocketAddrV4};
+use std::time::Duration;
+
+use common::{connect, pattern, Craft, Pair, Scripted, PEER, TICK, US};
+use trust::{CloseReason, Impairments, InterfaceBuilder, InterfaceEvent, Quad, SimNet, TcpConfig};
+
+const STEP: Duration = Duration::from_millis(1);
+
+/// The events in `events` by name, along with the connection each is for.
+fn names(events: Vec<InterfaceEvent>) -> Vec<(&'static str, Quad)> {
+    events
+        .into_iter()
+        .map(|e| match e {
+            InterfaceEvent::NewConnection(quad) => ("new", quad),
+            InterfaceEvent::Readable(quad) => ("readable", quad),
+            InterfaceEvent::Writable(quad) => ("writable", quad),
+            InterfaceEvent::Closed(quad, _) => ("closed", quad),
+            InterfaceEvent::Error(quad, _, _) => ("error", quad),
+            e => panic!("{:?}", e),
+        })
+        .collect()
+}
+
+/// Read what there is on `quad` into `into`, until it would block or the stream ends; returns
+/// whether it has ended.
+fn drain(interface: &mut trust::Interface, quad: Quad, into: &mut Vec<u8>) -> bool {
+    let mut buf = [0; 4096];
+    loop {
+        match interface.read_on(quad, &mut buf) {
+            Ok(0) => return true,
+            Ok(n) => into.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return false,
+            Err(e) => panic!("reading {:?} failed: {}", quad, e),
+        }
+    }
+}
+
+/// Write what is left of `out` past `*written` to `quad`, as far as it goes.
+fn fill(interface: &mut trust::Interface, quad: Quad, out: &[u8], written: &mut usize) {
+    while *written < out.len() {
+        match interface.write_on(quad, &out[*written..]) {
+            Ok(n) => *written += n,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+            Err(e) => panic!("writing {:?} failed: {}", quad, e),
+        }
+    }
+}
+
+#[test]
+fn an_echo_server_on_one_thread() {
+    let mut pair = Pair::new(
+        150,
+        Impairments::default(),
+        Impairments::default(),
+        TcpConfig::default(),
+        TcpConfig::default(),
+    );
+    let _listener = pair.b.bind(7).unwrap();
+    let client = pair
+        .a
+        .connect(US, SocketAddrV4::new(PEER, 7))
+        .unwrap()
+        .into_quad();
+
+    let request = pattern(150, 300_000);
+    let (mut sent, mut back) = (0, Vec::new());
+    let (mut server, mut pending, mut eof) = (None, Vec::new(), false);
+    let (mut shut, mut server_done) = (false, false);
+    let mut first = None;
+    let mut client_closed = false;
+    while !(client_closed && server_done) {
+        assert!(pair.net.now() < Duration::from_secs(10), "the echo stalled");
+        pair.net.advance(STEP);
+
+        // the server echoes what it reads, and closes once the client has
+        let events = pair.b.poll_once(Duration::ZERO).unwrap();
+        if first.is_none() {
+            first = events
+                .first()
+                .map(|e| matches!(e, InterfaceEvent::NewConnection(_)));
+        }
+        for e in events {
+            match e {
+                InterfaceEvent::NewConnection(quad) => server = Some(quad),
+                InterfaceEvent::Readable(quad) => {
+                    eof |= drain(&mut pair.b, quad, &mut pending);
+                }
+                InterfaceEvent::Closed(_, reason) => {
+                    assert_eq!(reason, CloseReason::PeerFin);
+                    server_done = true;
+                }
+                InterfaceEvent::Error(quad, e, _) => panic!("{:?} failed: {}", quad, e),
+                _ => {}
+            }
+        }
+        if let Some(quad) = server {
+            let mut written = 0;
+            fill(&mut pair.b, quad, &pending, &mut written);
+            pending.drain(..written);
+            if eof && pending.is_empty() && !shut {
+                pair.b.shutdown_on(quad, Shutdown::Write).unwrap();
+                shut = true;
+            }
+        }
+
+        // the client writes its request as there is room, and reads the echo
+        for e in pair.a.poll_once(Duration::ZERO).unwrap() {
+            match e {
+                InterfaceEvent::Writable(quad) => {
+                    fill(&mut pair.a, quad, &request, &mut sent);
+                    if sent == request.len() {
+                        pair.a.shutdown_on(quad, Shutdown::Write).unwrap();
+                    }
+                }
+                InterfaceEvent::Readable(quad) => {
+                    drain(&mut pair.a, quad, &mut back);
+                }
+                InterfaceEvent::Closed(quad, reason) => {
+                    assert_eq!((quad, reason), (client, CloseReason::Local));
+                    client_closed = true;
+                }
+                InterfaceEvent::Error(quad, e, _) => panic!("{:?} failed: {}", quad, e),
+                e => panic!("the client heard {:?}", e),
+            }
+        }
+    }
+    assert!(
+        back == request,
+        "the echo came back with {} bytes",
+        back.len()
+    );
+    // the server heard of its connection before anything else about it
+    assert_eq!(first, Some(true));
+    pair.a.close_on(client);
+    pair.b.close_on(server.unwrap());
+}
+
+#[test]
+fn readable_and_writable_are_edge_triggered() {
+    let mut s = Scripted::new(TcpConfig {
+        send_buffer: 4096,
+        ..TcpConfig::default()
+    });
+    let (stream, iss) = connect(&mut s, SocketAddrV4::new(PEER, 40000), 1000);
+    let quad = stream.into_quad();
+    assert!(names(s.take_events()).contains(&("writable", quad)));
+    let data = |seq, payload: &[u8]| {
+        Craft::new(quad.remote(), quad.local())
+            .seq(seq)
+            .ack(iss.wrapping_add(1))
+            .payload(payload)
+    };
+
+    // readable once, however much more arrives before a read finds nothing
+    s.send(data(1001, b"one"));
+    s.send(data(1004, b"two"));
+    assert_eq!(names(s.take_events()), [("readable", quad)]);
+    let mut buf = [0; 6];
+    assert_eq!(s.interface.read_on(quad, &mut buf).unwrap(), 6);
+    s.send(data(1007, b"three"));
+    assert!(s.take_events().is_empty(), "the read never found nothing");
+    assert_eq!(s.read_all(quad), b"three");
+    s.send(data(1012, b"four"));
+    assert_eq!(names(s.take_events()), [("readable", quad)]);
+
+    // writable again only once a write has been turned away and there is room
+    let mut written = 0;
+    fill(&mut s.interface, quad, &[0; 8192], &mut written);
+    assert_eq!(written, 4096);
+    s.advance(TICK);
+    let sent = s.take().len() as u32;
+    s.send(data(1016, b"").ack(iss.wrapping_add(1 + 1460 * sent.min(2))));
+    assert_eq!(names(s.take_events()), [("writable", quad)]);
+}
+
+#[test]
+fn a_reset_is_an_error_event() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, _) = connect(&mut s, SocketAddrV4::new(PEER, 40000), 1000);
+    let quad = stream.into_quad();
+    s.take_events();
+    s.send(Craft::new(quad.remote(), quad.local()).rst().seq(1001));
+    match &s.take_events()[..] {
+        [InterfaceEvent::Error(q, e, _)] => {
+            assert_eq!((*q, e.kind()), (quad, io::ErrorKind::ConnectionReset));
+        }
+        events => panic!("{:?}", events),
+    }
+    // and there is nothing more to hear of it once it is closed
+    s.interface.close_on(quad);
+    s.advance(TICK);
+    assert!(s.take_events().is_empty());
+}
+
+#[test]
+fn an_interface_with_its_own_loop_cannot_be_polled() {
+    let (_net, nic, _peer) =
+        SimNet::new(0, Impairments::default(), Impairments::default()).unwrap();
+    let mut builder = InterfaceBuilder::new();
+    builder.add_nic(nic, &[US]);
+    let mut interface = builder.build().unwrap();
+    let e = interface.poll_once(Duration::ZERO).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+}
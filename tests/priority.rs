@@ -0,0 +1,157 @@
+//! Priorities in the outbound queue: on a link a bulk transfer keeps full, an interactive
+//! connection of a higher class gets its segments out ahead of the bulk ones, and its round
+//! trips stay near the path's, while the bulk transfer still moves.
+
+mod common;
+
+use std::io;
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use common::{Pair, PEER, US};
+use trust::{Impairments, InterfaceEvent, Priority, Quad, TcpConfig};
+
+const LATENCY: Duration = Duration::from_millis(10);
+const STEP: Duration = Duration::from_millis(1);
+const PINGS: usize = 20;
+
+/// Both ends of a connection: `a`'s, and `b`'s once it has taken it in.
+struct Conn {
+    a: Quad,
+    b: Option<Quad>,
+}
+
+/// A bulk transfer from `a` to `b` that keeps the link busy, and a connection alongside it.
+struct Busy {
+    pair: Pair,
+    bulk: Conn,
+    ping: Option<Conn>,
+    /// how much of the bulk transfer has arrived
+    received: usize,
+}
+
+/// Connect `a` to `b`'s listener, at `priority`.
+fn open(pair: &mut Pair, priority: Priority) -> Conn {
+    let stream = pair.a.connect(US, SocketAddrV4::new(PEER, 80)).unwrap();
+    stream.set_priority(priority).unwrap();
+    Conn {
+        a: stream.into_quad(),
+        b: None,
+    }
+}
+
+impl Busy {
+    /// Move on a step, keeping the bulk transfer's send buffer full and reading what arrives.
+    fn step(&mut self) {
+        self.pair.step(STEP);
+        for e in self.pair.events_b.drain(..) {
+            if let InterfaceEvent::NewConnection(quad) = e {
+                match &mut self.ping {
+                    Some(ping) if self.bulk.b.is_some() => ping.b = Some(quad),
+                    _ => self.bulk.b = Some(quad),
+                }
+            }
+        }
+        match self.pair.a.write_on(self.bulk.a, &[0; 64 << 10]) {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => panic!("the bulk transfer failed: {}", e),
+        }
+        if let Some(quad) = self.bulk.b {
+            let mut buf = [0; 64 << 10];
+            while let Ok(n) = self.pair.b.read_on(quad, &mut buf) {
+                self.received += n;
+            }
+        }
+    }
+}
+
+/// What came of [`run`].
+struct Run {
+    /// the median round trip of a one-byte ping
+    rtt: Duration,
+    /// how much of the bulk transfer arrived while the pings went back and forth
+    bulk: usize,
+}
+
+/// A bulk transfer and an interactive connection from `a` to `b`, at the priorities given,
+/// over a 1MB/s link with a round trip of 20ms that refuses packets once its 16-packet queue is
+/// full, so that `a`'s own queue backs up behind it.
+fn run(bulk: Priority, interactive: Priority) -> Run {
+    let link = Impairments {
+        latency: LATENCY,
+        bandwidth: Some(1 << 20),
+        queue: Some(16),
+        backpressure: true,
+        ..Impairments::default()
+    };
+    let back = Impairments {
+        latency: LATENCY,
+        ..Impairments::default()
+    };
+    let config = TcpConfig {
+        send_buffer: 1 << 20,
+        recv_buffer: 1 << 20,
+        ..TcpConfig::default()
+    };
+    let mut pair = Pair::new(149, link, back, config.clone(), config);
+    let _listener = pair.b.bind(80).unwrap();
+    // the bulk transfer fills the queues before the pings start
+    let bulk = open(&mut pair, bulk);
+    let mut busy = Busy {
+        pair,
+        bulk,
+        ping: None,
+        received: 0,
+    };
+    while busy.pair.net.now() < Duration::from_millis(500) {
+        busy.step();
+    }
+    let before = busy.received;
+    busy.ping = Some(open(&mut busy.pair, interactive));
+    while busy.ping.as_ref().unwrap().b.is_none() {
+        busy.step();
+    }
+    let (ping, pong) = {
+        let ping = busy.ping.as_ref().unwrap();
+        (ping.a, ping.b.unwrap())
+    };
+
+    let mut rtts = Vec::new();
+    for _ in 0..PINGS {
+        let start = busy.pair.net.now();
+        busy.pair.a.write_on(ping, b"x").unwrap();
+        let mut byte = [0];
+        loop {
+            busy.step();
+            if busy.pair.b.read_on(pong, &mut byte).is_ok() {
+                busy.pair.b.write_on(pong, &byte).unwrap();
+            }
+            if busy.pair.a.read_on(ping, &mut byte).is_ok() {
+                break;
+            }
+            assert!(busy.pair.net.now() - start < Duration::from_secs(10));
+        }
+        rtts.push(busy.pair.net.now() - start);
+    }
+    rtts.sort();
+    Run {
+        rtt: rtts[PINGS / 2],
+        bulk: busy.received - before,
+    }
+}
+
+#[test]
+fn a_high_priority_connection_is_not_stuck_behind_a_bulk_transfer() {
+    let path = 2 * LATENCY;
+
+    let shared = run(Priority::Normal, Priority::Normal);
+    let ahead = run(Priority::Low, Priority::High);
+    // behind the bulk transfer's queue, the round trip is many times the path's, and ahead of
+    // it, little more than the path's and what the link's own queue of 16 packets adds
+    assert!(shared.rtt > 10 * path, "{:?} sharing a class", shared.rtt);
+    assert!(ahead.rtt < 4 * path, "{:?} in a class ahead", ahead.rtt);
+    // and the low class still gets its turn: a second or so of pings leaves most of the link's
+    // megabyte a second to the bulk transfer
+    assert!(ahead.bulk > 512 << 10, "{} bytes of bulk data", ahead.bulk);
+}
@@ -0,0 +1,106 @@
+//! Packets TCP has no use for go to the raw handler exactly as they arrived, and packets the
+//! caller builds go out through the interface's own queue, while TCP carries on alongside.
+
+mod common;
+
+use std::io::{self, Read, Write};
+use std::net::SocketAddrV4;
+use std::sync::mpsc;
+
+use common::{connect, peer, Craft, Scripted, PEER, TICK, US};
+use trust::TcpConfig;
+
+/// An ICMP echo request from the peer to us, checksums and all.
+fn ping() -> Vec<u8> {
+    let checksum = |bytes: &[u8]| {
+        let mut sum: u32 = bytes
+            .chunks(2)
+            .map(|w| u16::from_be_bytes([w[0], *w.get(1).unwrap_or(&0)]) as u32)
+            .sum();
+        while sum > 0xffff {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        !(sum as u16)
+    };
+    let mut icmp = vec![8, 0, 0, 0, 0x12, 0x34, 0, 1];
+    icmp.extend(b"are you there?");
+    let sum = checksum(&icmp);
+    icmp[2..4].copy_from_slice(&sum.to_be_bytes());
+
+    let mut packet = vec![0x45, 0, 0, 0, 0xab, 0xcd, 0, 0, 64, 1, 0, 0];
+    packet[2..4].copy_from_slice(&(20 + icmp.len() as u16).to_be_bytes());
+    packet.extend(PEER.octets());
+    packet.extend(US.octets());
+    let sum = checksum(&packet);
+    packet[10..12].copy_from_slice(&sum.to_be_bytes());
+    packet.extend(icmp);
+    packet
+}
+
+#[test]
+fn the_handler_gets_what_tcp_does_not_want() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (tx, rx) = mpsc::channel();
+    s.interface
+        .set_raw_handler(move |packet| tx.send(packet.to_vec()).unwrap());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+
+    // a ping in the middle of a connection's traffic, which the handler gets and TCP does not
+    let ping = ping();
+    s.send(
+        Craft::new(peer(), us)
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .psh()
+            .payload(b"before"),
+    );
+    s.send_raw(&ping);
+    s.send(
+        Craft::new(peer(), us)
+            .seq(1007)
+            .ack(iss.wrapping_add(1))
+            .psh()
+            .payload(b" after"),
+    );
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), [ping]);
+    let mut buf = [0; 12];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"before after");
+
+    // TCP for a port nobody has is the handler's too, once TCP has refused it
+    let stray = Craft::new(peer(), SocketAddrV4::new(US, 81))
+        .syn()
+        .seq(5000)
+        .build();
+    s.take();
+    s.send_raw(&stray);
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), [stray]);
+    assert!(s.take_one().rst);
+
+    // and the connection's own segments never reach it
+    stream.write_all(b"reply").unwrap();
+    s.advance(TICK);
+    s.send(Craft::new(peer(), us).seq(1013).ack(iss.wrapping_add(6)));
+    assert_eq!(rx.try_iter().count(), 0);
+}
+
+#[test]
+fn raw_packets_go_out_as_built() {
+    let mut s = Scripted::new(TcpConfig::default());
+    // a packet TCP would never send on its own, for a port it knows nothing of
+    let packet = Craft::new(SocketAddrV4::new(US, 9), peer())
+        .seq(77)
+        .ack(88)
+        .fin()
+        .psh()
+        .window(3)
+        .payload(b"hand made")
+        .build();
+    s.interface.send_raw(&packet).unwrap();
+    s.advance(TICK);
+    assert_eq!(s.take_one().packet, packet);
+
+    let e = s.interface.send_raw(&[0x60; 40]).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,140 @@
+//! The `AF_PACKET` backend, [`RawSocket`]: what it refuses to bind to, and, with `sys-tests`,
+//! a connection from the kernel's own stack across a veth pair, which has the socket answer
+//! the kernel's ARP for our address before any TCP gets through.
+//!
+//! ```text
+//! cargo test --features backend-raw,sys-tests --test raw_socket
+//! ```
+
+mod common;
+
+use std::io;
+use std::net::Ipv4Addr;
+
+use trust::RawSocket;
+
+#[test]
+fn binding_takes_an_address() {
+    let e = RawSocket::bind("lo", &[]).err().unwrap();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn binding_takes_an_interface_that_exists() {
+    let e = RawSocket::bind("trust-nonesuch", &[Ipv4Addr::new(10, 78, 9, 2)])
+        .err()
+        .unwrap();
+    assert_eq!(e.raw_os_error(), Some(libc::ENODEV));
+}
+
+#[cfg(feature = "sys-tests")]
+mod veth {
+    use std::io::{self, Read, Write};
+    use std::net::{Ipv4Addr, Shutdown, SocketAddrV4, TcpStream};
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::common::pattern;
+    use trust::{InterfaceBuilder, RawSocket};
+
+    const HOST: Ipv4Addr = Ipv4Addr::new(10, 78, 0, 1);
+    const STACK: Ipv4Addr = Ipv4Addr::new(10, 78, 0, 2);
+
+    /// How long the kernel's end waits on a call before the test fails rather than hangs.
+    const PATIENCE: Duration = Duration::from_secs(30);
+
+    /// A veth pair, trust-veth0 for us and trust-veth1 with [`HOST`] for the kernel, deleted
+    /// again when this goes out of scope.
+    struct Veth;
+
+    impl Veth {
+        fn new() -> Option<Veth> {
+            let pair = "link add trust-veth0 type veth peer name trust-veth1";
+            if !ip(&pair.split(' ').collect::<Vec<_>>()) {
+                return None;
+            }
+            // from here on, failing takes the pair down again
+            let veth = Veth;
+            let host = format!("{}/24", HOST);
+            for args in [
+                &["addr", "add", &host, "dev", "trust-veth1"][..],
+                &["link", "set", "up", "dev", "trust-veth1"],
+                &["link", "set", "up", "dev", "trust-veth0"],
+            ] {
+                if !ip(args) {
+                    return None;
+                }
+            }
+            Some(veth)
+        }
+    }
+
+    /// Run `ip` with `args`, saying why the test is skipped if that fails.
+    fn ip(args: &[&str]) -> bool {
+        let status = Command::new("ip").args(args).status();
+        let ok = status.as_ref().is_ok_and(|s| s.success());
+        if !ok {
+            eprintln!("skipped: `ip {}` failed ({:?})", args.join(" "), status);
+        }
+        ok
+    }
+
+    impl Drop for Veth {
+        fn drop(&mut self) {
+            let _ = Command::new("ip")
+                .args(["link", "del", "trust-veth0"])
+                .status();
+        }
+    }
+
+    #[test]
+    fn kernel_connects_over_a_veth_pair() {
+        let Some(_veth) = Veth::new() else {
+            return;
+        };
+        let nic = match RawSocket::bind("trust-veth0", &[STACK]) {
+            Ok(nic) => nic,
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                eprintln!("skipped: cannot open a packet socket ({})", e);
+                return;
+            }
+            Err(e) => panic!("binding the packet socket failed: {}", e),
+        };
+        let mut builder = InterfaceBuilder::new();
+        builder.add_nic(nic, &[STACK]);
+        let mut interface = builder.build().unwrap();
+        let listener = interface.bind(7000).unwrap();
+
+        // our end echoes it all back, on a thread of its own so that the kernel's end failing
+        // fails the test rather than leaving it waiting to accept
+        let echo = thread::spawn(move || -> io::Result<Vec<u8>> {
+            let mut stream = listener.accept()?;
+            let mut got = Vec::new();
+            stream.read_to_end(&mut got)?;
+            stream.write_all(&got)?;
+            stream.shutdown(Shutdown::Write)?;
+            Ok(got)
+        });
+
+        let data = pattern(1, 256 << 10);
+        let to = SocketAddrV4::new(STACK, 7000).into();
+        let mut stream = TcpStream::connect_timeout(&to, PATIENCE).unwrap();
+        stream.set_read_timeout(Some(PATIENCE)).unwrap();
+        let writer = {
+            let mut stream = stream.try_clone().unwrap();
+            let data = data.clone();
+            thread::spawn(move || -> io::Result<()> {
+                stream.write_all(&data)?;
+                stream.shutdown(Shutdown::Write)
+            })
+        };
+        let mut back = Vec::new();
+        stream.read_to_end(&mut back).unwrap();
+        writer.join().unwrap().unwrap();
+        assert!(back == data, "the echo came back corrupted");
+        assert!(echo.join().unwrap().unwrap() == data);
+        // the interface outlives the echo still in its send buffer
+        drop(interface);
+    }
+}
@@ -0,0 +1,165 @@
+//! Read coalescing: a reader blocked on a stream is woken once enough data is buffered, the
+//! peer pushes, or the oldest data has waited long enough, rather than for every segment, while
+//! a read that finds data waiting returns it at once. And our own segments carry PSH where the
+//! data written so far ends.
+
+mod common;
+
+use std::io::{Read, Write};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use common::{connect, peer, Craft, Scripted, TICK};
+use trust::{ReadCoalescing, TcpConfig, TcpStream};
+
+/// Long enough for a reader that was going to wake up to have done so.
+const WHILE: Duration = Duration::from_millis(50);
+
+enum Step {
+    /// the peer sends this many bytes, without PSH
+    Data(usize),
+    /// or with it
+    Pushed(usize),
+    /// virtual time moves on this much
+    Wait(Duration),
+}
+
+use Step::*;
+
+/// With a reader blocked on a stream under `coalescing`, go through `steps`, then have the
+/// peer send a FIN, and return how much each read the reader woke up to got, along with the
+/// step it woke during (`steps.len()` for the FIN).
+fn wakes(coalescing: Option<ReadCoalescing>, steps: &[Step]) -> Vec<(usize, usize)> {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    stream.set_read_coalescing(coalescing).unwrap();
+    let segment = |seq| {
+        Craft::new(peer(), stream.quad().local())
+            .seq(seq)
+            .ack(iss.wrapping_add(1))
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let reader = |stream: &TcpStream| {
+        let mut buf = [0; 64 << 10];
+        loop {
+            let n = (&*stream).read(&mut buf).unwrap();
+            tx.send(n).unwrap();
+            if n == 0 {
+                return;
+            }
+        }
+    };
+    let mut got = Vec::new();
+    thread::scope(|scope| {
+        scope.spawn(|| reader(&stream));
+        thread::sleep(WHILE);
+
+        let mut seq = 1001;
+        for (i, step) in steps.iter().enumerate() {
+            match *step {
+                Data(len) => s.send(segment(seq).payload(&vec![0; len])),
+                Pushed(len) => s.send(segment(seq).psh().payload(&vec![0; len])),
+                Wait(by) => s.advance(by),
+            }
+            if let Data(len) | Pushed(len) = *step {
+                seq += len as u32;
+            }
+            while let Ok(n) = rx.recv_timeout(WHILE) {
+                got.push((i, n));
+            }
+        }
+        s.send(segment(seq).fin());
+        loop {
+            match rx.recv_timeout(Duration::from_secs(5)) {
+                Ok(0) | Err(RecvTimeoutError::Disconnected) => break,
+                Ok(n) => got.push((steps.len(), n)),
+                Err(RecvTimeoutError::Timeout) => panic!("the reader never saw the FIN"),
+            }
+        }
+    });
+    got
+}
+
+/// Coalescing with a delay too long to run out on its own.
+fn patient(bytes: usize) -> Option<ReadCoalescing> {
+    Some(ReadCoalescing {
+        bytes,
+        delay: Duration::from_secs(5),
+    })
+}
+
+#[test]
+fn without_coalescing_every_segment_wakes_the_reader() {
+    assert_eq!(wakes(None, &[Data(100), Data(100)]), [(0, 100), (1, 100)]);
+}
+
+#[test]
+fn a_push_wakes_the_reader_at_once() {
+    assert_eq!(
+        wakes(patient(16 << 10), &[Data(100), Data(100), Pushed(10)]),
+        [(2, 210)]
+    );
+}
+
+#[test]
+fn enough_data_wakes_the_reader() {
+    // the twelfth full-sized segment takes what is buffered past 16 KiB
+    let steps: Vec<_> = (0..12).map(|_| Data(1460)).collect();
+    assert_eq!(wakes(patient(16 << 10), &steps), [(11, 12 * 1460)]);
+}
+
+#[test]
+fn data_that_has_waited_wakes_the_reader() {
+    let coalescing = Some(ReadCoalescing {
+        bytes: 16 << 10,
+        delay: 5 * TICK,
+    });
+    assert_eq!(
+        wakes(coalescing, &[Data(100), Wait(4 * TICK), Wait(2 * TICK)]),
+        [(2, 100)]
+    );
+}
+
+#[test]
+fn the_end_of_the_stream_wakes_the_reader() {
+    assert_eq!(wakes(patient(16 << 10), &[Data(100)]), [(1, 100)]);
+}
+
+#[test]
+fn a_read_that_finds_data_waiting_returns_it() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    stream.set_read_coalescing(patient(16 << 10)).unwrap();
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .payload(b"not pushed"),
+    );
+    let mut buf = [0; 64];
+    let n = stream.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"not pushed");
+}
+
+#[test]
+fn we_push_where_what_was_written_ends() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, _) = connect(&mut s, peer(), 1000);
+    stream.write_all(&[0; 3000]).unwrap();
+    s.advance(TICK);
+    let sent: Vec<_> = s
+        .take()
+        .iter()
+        .map(|seg| (seg.payload.len(), seg.flags()))
+        .collect();
+    assert_eq!(
+        sent,
+        [
+            (1460, ".".to_string()),
+            (1460, ".".to_string()),
+            (80, "P.".to_string())
+        ]
+    );
+}
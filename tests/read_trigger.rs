@@ -0,0 +1,160 @@
+//! Read triggers: a reader blocked on a stream is only woken once what its trigger waits for
+//! has arrived, or the stream has ended, while a read that finds data waiting returns it
+//! whatever the trigger.
+
+mod common;
+
+use std::io::Read;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use common::{connect, peer, Craft, Scripted};
+use trust::{InterfaceEvent, ReadTrigger, TcpConfig, TcpStream};
+
+/// Long enough for a reader that was going to wake up to have done so.
+const WHILE: Duration = Duration::from_millis(50);
+
+/// With a reader blocked on `stream` under `trigger`, have the peer send `chunks` one segment
+/// at a time, then a FIN, and return what each read the reader woke up to got, along with
+/// how many segments had been sent when it did.
+fn reads(trigger: ReadTrigger, chunks: &[&[u8]]) -> Vec<(usize, Vec<u8>)> {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    stream.set_read_trigger(trigger).unwrap();
+    let us = stream.quad().local();
+
+    let (tx, rx) = mpsc::channel();
+    let reader = |stream: &TcpStream| {
+        let mut buf = [0; 64];
+        loop {
+            let n = (&*stream).read(&mut buf).unwrap();
+            tx.send(buf[..n].to_vec()).unwrap();
+            if n == 0 {
+                return;
+            }
+        }
+    };
+    let mut got = Vec::new();
+    thread::scope(|scope| {
+        scope.spawn(|| reader(&stream));
+        thread::sleep(WHILE);
+
+        let mut seq = 1001;
+        for (i, chunk) in chunks.iter().enumerate() {
+            s.send(
+                Craft::new(peer(), us)
+                    .seq(seq)
+                    .ack(iss.wrapping_add(1))
+                    .psh()
+                    .payload(chunk),
+            );
+            seq += chunk.len() as u32;
+            while let Ok(read) = rx.recv_timeout(WHILE) {
+                got.push((i + 1, read));
+            }
+        }
+        s.send(
+            Craft::new(peer(), us)
+                .seq(seq)
+                .ack(iss.wrapping_add(1))
+                .fin(),
+        );
+        loop {
+            match rx.recv_timeout(Duration::from_secs(5)) {
+                Ok(read) if read.is_empty() => break,
+                Ok(read) => got.push((chunks.len() + 1, read)),
+                Err(RecvTimeoutError::Timeout) => panic!("the reader never saw the FIN"),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    });
+    got
+}
+
+#[test]
+fn a_line_wakes_the_reader_once() {
+    let got = reads(ReadTrigger::Delimiter(b'\n'), &[b"ab", b"cd", b"ef\ng"]);
+    assert_eq!(got, [(3, b"abcdef\ng".to_vec())]);
+}
+
+#[test]
+fn so_does_a_frame_of_so_many_bytes() {
+    let got = reads(ReadTrigger::Bytes(5), &[b"ab", b"cd", b"ef"]);
+    assert_eq!(got, [(3, b"abcdef".to_vec())]);
+}
+
+#[test]
+fn without_a_trigger_every_segment_wakes_the_reader() {
+    let got = reads(ReadTrigger::Any, &[b"ab", b"cd", b"ef\n"]);
+    assert_eq!(
+        got,
+        [
+            (1, b"ab".to_vec()),
+            (2, b"cd".to_vec()),
+            (3, b"ef\n".to_vec())
+        ]
+    );
+}
+
+#[test]
+fn the_end_of_the_stream_wakes_the_reader_with_half_a_line() {
+    let got = reads(ReadTrigger::Delimiter(b'\n'), &[b"no ", b"newline"]);
+    assert_eq!(got, [(3, b"no newline".to_vec())]);
+}
+
+#[test]
+fn data_already_waiting_is_read_at_once() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    stream
+        .set_read_trigger(ReadTrigger::Delimiter(b'\n'))
+        .unwrap();
+    assert_eq!(
+        stream.read_trigger().unwrap(),
+        ReadTrigger::Delimiter(b'\n')
+    );
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .payload(b"partial"),
+    );
+    let mut buf = [0; 64];
+    let n = stream.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"partial");
+}
+
+#[test]
+fn a_polled_application_hears_of_a_line_once() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    stream
+        .set_read_trigger(ReadTrigger::Delimiter(b'\n'))
+        .unwrap();
+    let quad = stream.quad();
+    s.take_events();
+
+    // how many times the application is told it can read, after each segment
+    let mut seq = 1001;
+    let mut readable = Vec::new();
+    for chunk in [&b"ab"[..], b"cd", b"ef\ng", b"h"] {
+        s.send(
+            Craft::new(peer(), quad.local())
+                .seq(seq)
+                .ack(iss.wrapping_add(1))
+                .psh()
+                .payload(chunk),
+        );
+        seq += chunk.len() as u32;
+        let events = s.take_events();
+        readable.push(
+            events
+                .iter()
+                .filter(|e| matches!(e, InterfaceEvent::Readable(q) if *q == quad))
+                .count(),
+        );
+    }
+    assert_eq!(readable, [0, 0, 1, 0]);
+    assert_eq!(s.read_all(quad), b"abcdef\ngh");
+}
@@ -0,0 +1,50 @@
+//! Data that arrives ahead of a gap is held, and delivered in order once the gap fills.
+
+mod common;
+
+use common::{accept, connection, peer, state, us, Craft, Scripted};
+use trust::{State, TcpConfig};
+
+#[test]
+fn data_past_a_gap_is_held_until_it_fills() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let (quad, iss) = accept(&mut s, 80, peer(), 1000);
+    let ack = iss.wrapping_add(1);
+
+    // "world" and the FIN overtake "hello ", and "lo w" overlaps the start of "world"
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1007)
+            .ack(ack)
+            .payload(b"world")
+            .fin(),
+    );
+    assert_eq!(
+        s.take_one().ack,
+        Some(1001),
+        "the gap was not reported at once"
+    );
+    assert_eq!(
+        connection(&s.interface, quad).unwrap().out_of_order_bytes,
+        5
+    );
+    assert!(s.read_all(quad).is_empty());
+    assert_eq!(state(&s.interface, quad), Some(State::Estab));
+
+    s.send(Craft::new(peer(), us()).seq(1004).ack(ack).payload(b"lo w"));
+    assert_eq!(s.take_one().ack, Some(1001));
+    assert_eq!(
+        connection(&s.interface, quad).unwrap().out_of_order_bytes,
+        8
+    );
+
+    s.send(Craft::new(peer(), us()).seq(1001).ack(ack).payload(b"hel"));
+    // everything, the FIN included, is in now, and acknowledged at once
+    assert_eq!(s.take_one().ack, Some(1013));
+    assert_eq!(s.read_all(quad), b"hello world");
+    let info = connection(&s.interface, quad).unwrap();
+    assert_eq!(info.state, State::CloseWait);
+    assert_eq!(info.out_of_order_bytes, 0);
+    assert_eq!(info.duplicate_bytes, 1);
+}
@@ -0,0 +1,124 @@
+//! A cap on what the receive buffers of all an interface's connections hold, kept by the
+//! windows they advertise: peers filling every window they are offered, with nobody reading,
+//! never get the total past it, and none of what they sent is lost.
+
+mod common;
+
+use std::net::SocketAddrV4;
+
+use common::{accept, pattern, Craft, Scripted, PEER, TICK, US};
+use trust::{MemoryPressure, Quad, TcpConfig};
+
+const LIMIT: usize = 100_000;
+const BUFFER: usize = 32 * 1024;
+const MSS: u32 = 1460;
+
+fn capped() -> Scripted {
+    Scripted::with(
+        TcpConfig {
+            recv_buffer: BUFFER,
+            ..TcpConfig::default()
+        },
+        |builder| {
+            builder.recv_memory_limit(LIMIT);
+        },
+    )
+}
+
+/// A connection the peer sends into, with how far it has sent and how far it may.
+struct Sender {
+    quad: Quad,
+    iss: u32,
+    data: Vec<u8>,
+    sent: u32,
+    window_end: u32,
+}
+
+/// A handshake from the peer at `port`, whose data is a pattern of 40 KB.
+fn open(s: &mut Scripted, port: u16) -> Sender {
+    let peer = SocketAddrV4::new(PEER, port);
+    let us = SocketAddrV4::new(US, 80);
+    s.send(Craft::new(peer, us).syn().seq(1000).mss(MSS as u16));
+    let syn_ack = s.take_one();
+    s.send(
+        Craft::new(peer, us)
+            .seq(1001)
+            .ack(syn_ack.seq.wrapping_add(1)),
+    );
+    Sender {
+        quad: s.accepted().unwrap(),
+        iss: syn_ack.seq,
+        data: pattern(port as u64, 40 * 1024),
+        sent: 0,
+        window_end: syn_ack.window as u32,
+    }
+}
+
+#[test]
+fn peers_filling_their_windows_stay_within_the_limit() {
+    let mut s = capped();
+    let _listener = s.interface.bind(80).unwrap();
+    let mut senders: Vec<Sender> = (0..7).map(|i| open(&mut s, 40000 + i)).collect();
+
+    for _ in 0..100 {
+        for sender in &mut senders {
+            while sender.sent < sender.window_end && (sender.sent as usize) < sender.data.len() {
+                let end = (sender.sent + MSS)
+                    .min(sender.window_end)
+                    .min(sender.data.len() as u32);
+                s.send(
+                    Craft::new(sender.quad.remote(), sender.quad.local())
+                        .seq(1001 + sender.sent)
+                        .ack(sender.iss.wrapping_add(1))
+                        .payload(&sender.data[sender.sent as usize..end as usize]),
+                );
+                sender.sent = end;
+            }
+        }
+        s.advance(TICK);
+        for segment in s.take() {
+            let sender = senders
+                .iter_mut()
+                .find(|sender| sender.quad.remote() == segment.dst)
+                .unwrap();
+            let edge = segment.ack.unwrap() - 1001 + segment.window as u32;
+            sender.window_end = sender.window_end.max(edge);
+        }
+        let memory = s.interface.recv_memory();
+        assert_eq!(memory.limit, Some(LIMIT));
+        assert!(memory.committed <= LIMIT, "{:?}", memory);
+    }
+    // with nobody having read for a while, it is the unread connections that are clamped
+    let memory = s.interface.recv_memory();
+    assert_eq!(memory.pressure, MemoryPressure::ClampingUnread);
+    assert!(memory.buffered > LIMIT / 2 && memory.buffered <= LIMIT);
+
+    // a connection opened now is offered less than its buffer would hold
+    let late = open(&mut s, 41000);
+    assert!((late.window_end as usize) < BUFFER);
+    assert!(s.interface.recv_memory().committed <= LIMIT);
+
+    // and everything that was taken in reads back as it was sent
+    for sender in &senders {
+        let read = s.read_all(sender.quad);
+        assert!(!read.is_empty());
+        assert_eq!(read, sender.data[..read.len()]);
+    }
+}
+
+#[test]
+fn without_a_limit_nothing_is_clamped() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let (quad, iss) = accept(&mut s, 80, SocketAddrV4::new(PEER, 40000), 1000);
+    s.send(
+        Craft::new(quad.remote(), SocketAddrV4::new(US, 80))
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .payload(&[1; 1000]),
+    );
+    let memory = s.interface.recv_memory();
+    assert_eq!(memory.limit, None);
+    assert_eq!(memory.buffered, 1000);
+    assert_eq!(memory.pressure, MemoryPressure::Normal);
+}
@@ -0,0 +1,80 @@
+//! A device that refuses a send with `EAGAIN`, as a tun device with a full queue does: the
+//! segment does not count as sent, so it goes out again in its place rather than leaving a hole
+//! for the retransmission timer to find.
+
+mod common;
+
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use common::{connect, pattern, peer, Craft, Scripted, TICK};
+use trust::{Nic, TcpConfig};
+
+#[test]
+fn refused_segments_leave_no_gaps() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    let quad = stream.quad();
+    let data = pattern(131, 60_000);
+    stream.write_all(&data).unwrap();
+
+    // the first segment sent in answer to every other ACK is turned away
+    let mut wire = Vec::new();
+    for round in 0.. {
+        s.advance(TICK);
+        for seg in s.take() {
+            assert_eq!(
+                seg.seq,
+                iss.wrapping_add(1 + wire.len() as u32),
+                "a segment out of place"
+            );
+            wire.extend(seg.payload);
+        }
+        if wire.len() == data.len() {
+            break;
+        }
+        assert!(
+            s.net.now() < Duration::from_millis(500),
+            "the transfer waited on the retransmission timer, with {} of {} bytes through",
+            wire.len(),
+            data.len()
+        );
+        let ack = Craft::new(peer(), quad.local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1 + wire.len() as u32))
+            .window(u16::MAX)
+            .build();
+        s.peer.send(&ack).unwrap();
+        if round % 2 == 0 {
+            s.net.fail_sends(1, libc::EAGAIN);
+        }
+        s.settle();
+    }
+    assert!(wire == data, "the data on the wire is not what was written");
+}
+
+#[test]
+fn a_refused_ack_goes_out_later() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    let quad = stream.quad();
+
+    // the peer's data arrives, and the interface's ACK of it is refused
+    let data = Craft::new(peer(), quad.local())
+        .seq(1001)
+        .ack(iss.wrapping_add(1))
+        .psh()
+        .payload(b"hello")
+        .build();
+    s.peer.send(&data).unwrap();
+    s.net.fail_sends(1, libc::EAGAIN);
+    s.settle();
+    assert!(s.take().is_empty());
+    let mut buf = [0; 5];
+    stream.read_exact(&mut buf).unwrap();
+
+    // held back rather than lost, it goes once the device takes packets again
+    s.advance(TICK);
+    let ack = s.take_one();
+    assert_eq!((ack.flags().as_str(), ack.ack), (".", Some(1006)));
+}
@@ -0,0 +1,147 @@
+//! Reordering, measured from holes that fill in on their own: it is counted, a fast retransmit
+//! it set off is undone, and with a cap set the duplicate ACK threshold rises past it until a
+//! retransmission timeout puts it back.
+
+mod common;
+
+use std::io::Write;
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use common::{connect, Craft, Scripted, Segment, PEER, TICK};
+use trust::{ConnectionInfo, TcpConfig, TcpStream};
+
+const MSS: usize = 1460;
+const RTT: Duration = Duration::from_millis(50);
+
+/// A connection sending to a scripted peer 50ms away.
+struct Sender {
+    s: Scripted,
+    stream: TcpStream,
+    /// SND.UNA and SND.MAX, as far as the peer knows
+    una: u32,
+    max: u32,
+}
+
+impl Sender {
+    fn new(max_dup_ack_threshold: Option<u32>) -> Self {
+        let mut s = Scripted::new(TcpConfig {
+            max_dup_ack_threshold,
+            ..TcpConfig::default()
+        });
+        let (stream, iss) = connect(&mut s, SocketAddrV4::new(PEER, 40000), 1000);
+        let una = iss.wrapping_add(1);
+        let mut sender = Sender {
+            s,
+            stream,
+            una,
+            max: una,
+        };
+        // a round trip first, for the connection to know how long one is
+        sender.flight(1);
+        sender.una = sender.max;
+        sender.ack(sender.una);
+        sender
+    }
+
+    /// Send `segments` full segments, and let a round trip's time pass.
+    fn flight(&mut self, segments: usize) {
+        self.stream.write_all(&vec![1; segments * MSS]).unwrap();
+        self.s.advance(TICK);
+        let sent = self.s.take();
+        self.note(sent);
+        self.s.advance(RTT - TICK);
+    }
+
+    /// Keep track of SND.MAX from what was sent.
+    fn note(&mut self, sent: Vec<Segment>) -> Vec<Segment> {
+        for segment in &sent {
+            let end = segment.seq.wrapping_add(segment.len());
+            if end.wrapping_sub(self.max) as i32 > 0 {
+                self.max = end;
+            }
+        }
+        sent
+    }
+
+    /// The peer acknowledges up to `ack`: returns what was sent in answer.
+    fn ack(&mut self, ack: u32) -> Vec<Segment> {
+        self.s.send(
+            Craft::new(self.stream.quad().remote(), self.stream.quad().local())
+                .seq(1001)
+                .ack(ack),
+        );
+        let sent = self.s.take();
+        self.note(sent)
+    }
+
+    /// The first segment of the flight arrives after the next `dups` of them: returns whether
+    /// it was sent again before the peer's ACK for everything came.
+    fn reorder(&mut self, dups: u32) -> bool {
+        let mut again = false;
+        for _ in 0..dups {
+            again |= self.ack(self.una).iter().any(|seg| seg.seq == self.una);
+        }
+        self.una = self.max;
+        self.ack(self.max);
+        again
+    }
+
+    fn info(&self) -> ConnectionInfo {
+        self.stream.info().unwrap()
+    }
+}
+
+#[test]
+fn reordering_below_the_threshold_is_counted() {
+    let mut c = Sender::new(None);
+    c.flight(4);
+    assert!(!c.reorder(2));
+    let info = c.info();
+    assert_eq!((info.reordering_seen, info.dup_ack_threshold), (2, 3));
+    assert_eq!(info.spurious_fast_retransmits, 0);
+}
+
+#[test]
+fn a_fast_retransmit_the_hole_filling_in_shows_was_not_needed_is_undone() {
+    let mut c = Sender::new(Some(16));
+    c.flight(8);
+    let cwnd = c.info().cwnd;
+    assert!(c.reorder(3));
+    let info = c.info();
+    assert_eq!(info.spurious_fast_retransmits, 1);
+    assert_eq!(info.cwnd, cwnd);
+    // and the threshold is now one past the reordering seen
+    assert_eq!((info.reordering_seen, info.dup_ack_threshold), (3, 4));
+
+    c.flight(8);
+    assert!(!c.reorder(3));
+    assert_eq!(c.info().spurious_fast_retransmits, 1);
+}
+
+#[test]
+fn without_a_cap_the_threshold_stays() {
+    let mut c = Sender::new(None);
+    c.flight(8);
+    assert!(c.reorder(3));
+    assert_eq!(c.info().dup_ack_threshold, 3);
+    c.flight(8);
+    assert!(c.reorder(3));
+    assert_eq!(c.info().spurious_fast_retransmits, 2);
+}
+
+#[test]
+fn a_timeout_puts_the_threshold_back() {
+    let mut c = Sender::new(Some(16));
+    c.flight(8);
+    c.reorder(3);
+    assert_eq!(c.info().dup_ack_threshold, 4);
+
+    c.flight(2);
+    let start = c.s.net.now();
+    while c.s.take().is_empty() {
+        assert!(c.s.net.now() - start < Duration::from_secs(5), "no timeout");
+        c.s.advance(TICK);
+    }
+    assert_eq!(c.info().dup_ack_threshold, 3);
+}
@@ -0,0 +1,92 @@
+//! Retransmissions are cut afresh from the send buffer by byte range: small segments lost
+//! together come back as full-sized ones, and our FIN goes along only with the segment that
+//! reaches it.
+
+mod common;
+
+use std::net::Shutdown;
+use std::time::Duration;
+
+use common::{accept, peer, us, Craft, Scripted, Segment, TICK};
+use trust::{Quad, TcpConfig};
+
+/// Writes `count` pieces of 512 bytes, one tick apart, so each goes out as a segment of its
+/// own.
+fn small_writes(s: &mut Scripted, quad: Quad, count: u8) -> Vec<Segment> {
+    let mut sent = Vec::new();
+    for i in 0..count {
+        s.interface.write_on(quad, &[i; 512]).unwrap();
+        s.advance(TICK);
+        sent.extend(s.take());
+    }
+    sent
+}
+
+fn wait_for_retransmission(s: &mut Scripted) -> Vec<Segment> {
+    let mut waited = Duration::ZERO;
+    while s.sent.is_empty() && waited < Duration::from_secs(5) {
+        s.advance(TICK);
+        waited += TICK;
+    }
+    s.take()
+}
+
+#[test]
+fn a_fast_retransmit_bundles_the_lost_segments() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let (quad, iss) = accept(&mut s, 80, peer(), 1000);
+    let sent = small_writes(&mut s, quad, 8);
+    assert_eq!(sent.len(), 8);
+    assert!(sent.iter().all(|seg| seg.len() == 512));
+
+    // the first arrives, the next three are lost, and the three after them each bring a
+    // duplicate ACK
+    let start = iss.wrapping_add(1);
+    let ack = Craft::new(peer(), us()).seq(1001).ack(start + 512);
+    s.send(ack.clone());
+    for _ in 0..3 {
+        s.send(ack.clone());
+    }
+    let again = s.take_one();
+    assert_eq!((again.seq, again.len()), (start + 512, 1460));
+    // byte for byte what the three writes after the first had sent
+    let original: Vec<u8> = sent[1..4]
+        .iter()
+        .flat_map(|seg| seg.payload.clone())
+        .collect();
+    assert_eq!(again.payload, original[..1460]);
+
+    // the partial ACK for it brings the next full segment's worth
+    s.send(Craft::new(peer(), us()).seq(1001).ack(start + 1972));
+    let next = s.take_one();
+    assert_eq!((next.seq, next.len()), (start + 1972, 1460));
+}
+
+#[test]
+fn the_fin_goes_only_with_the_range_that_reaches_it() {
+    let mut s = Scripted::new(TcpConfig {
+        frto: false,
+        ..TcpConfig::default()
+    });
+    let _listener = s.interface.bind(80).unwrap();
+    let (quad, iss) = accept(&mut s, 80, peer(), 1000);
+    let mut sent = small_writes(&mut s, quad, 3);
+    s.interface.shutdown_on(quad, Shutdown::Write).unwrap();
+    s.advance(TICK);
+    sent.extend(s.take());
+    assert!(sent.last().unwrap().fin);
+
+    // none of it arrives: the timeout resends the first 1460 bytes, and no FIN with them
+    let first = wait_for_retransmission(&mut s);
+    assert_eq!(first.len(), 1);
+    let start = iss.wrapping_add(1);
+    assert_eq!((first[0].seq, first[0].payload.len()), (start, 1460));
+    assert!(!first[0].fin);
+
+    // once those are in, the rest goes, with the FIN
+    s.send(Craft::new(peer(), us()).seq(1001).ack(start + 1460));
+    let rest = s.take_one();
+    assert_eq!((rest.seq, rest.payload.len()), (start + 1460, 1536 - 1460));
+    assert!(rest.fin);
+}
@@ -0,0 +1,57 @@
+//! Captures of real connections, replayed against the stack by the `replay` example: the stack,
+//! standing in for one side, has to send what that side did.
+
+#[path = "../examples/replay/replay.rs"]
+mod replay;
+
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+#[test]
+fn linux_handshake_replays_without_a_difference() {
+    // the stack as a client fetching a page from Linux over a tun device (see `http_get`)
+    let us = SocketAddrV4::new(Ipv4Addr::new(192, 168, 0, 2), 40000);
+    let peer = SocketAddrV4::new(Ipv4Addr::new(192, 168, 0, 1), 8080);
+    let capture = include_bytes!("captures/linux-handshake.pcap");
+    let outcome = replay::replay(capture, us, peer).unwrap();
+    assert!(
+        outcome.diffs.is_empty(),
+        "the replay differs from the capture:\n{}",
+        outcome.diffs.join("\n")
+    );
+    assert_eq!(outcome.sent.len(), outcome.captured.len());
+
+    let (iss, irs) = (
+        outcome.iss,
+        outcome.irs.expect("the capture has the server's SYN"),
+    );
+    let sent: Vec<_> = outcome
+        .sent
+        .iter()
+        .map(|s| {
+            (
+                s.flags.as_str(),
+                s.seq.wrapping_sub(iss),
+                s.flags.contains('.').then(|| s.ack.wrapping_sub(irs)),
+                s.payload.len(),
+            )
+        })
+        .collect();
+    // with sequence numbers relative to either side's ISS: the request, then ACKs of the
+    // response and of the server's FIN, then ours
+    assert_eq!(
+        sent,
+        [
+            ("S", 0, None, 0),
+            (".", 1, Some(1), 0),
+            ("P.", 1, Some(1), 37),
+            (".", 38, Some(45), 0),
+            (".", 38, Some(46), 0),
+            ("F.", 38, Some(46), 0),
+        ]
+    );
+    assert_eq!(outcome.sent[0].mss(), Some(1460));
+    assert_eq!(
+        outcome.sent[2].payload,
+        b"GET / HTTP/1.0\r\nHost: 192.168.0.1\r\n\r\n"
+    );
+}
@@ -0,0 +1,212 @@
+//! SYN, SYN-ACK and FIN take up sequence space like data does, and are retransmitted with
+//! backoff like it when they go unacknowledged, on a timeout RFC 6298 works out.
+
+mod common;
+
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use common::{accept, peer, state, us, Craft, Pair, Scripted, PEER, TICK, US};
+use trust::{Impairments, InterfaceEvent, State, TcpConfig};
+
+/// Advance until the interface sends something, up to `limit`: returns what it sent and how
+/// long that took.
+fn wait_for_send(s: &mut Scripted, limit: Duration) -> (Vec<common::Segment>, Duration) {
+    let start = s.net.now();
+    while s.net.now() - start < limit {
+        s.advance(TICK);
+        let sent = s.take();
+        if !sent.is_empty() {
+            return (sent, s.net.now() - start);
+        }
+    }
+    panic!("nothing was sent in {:?}", limit);
+}
+
+#[test]
+fn unanswered_syn_is_retransmitted_with_backoff() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let local = SocketAddrV4::new(US, 40000);
+    let quad = s.interface.connect_from(local, peer()).unwrap().into_quad();
+    s.advance(TICK);
+    let syn = s.take_one();
+    assert_eq!(syn.flags(), "S");
+
+    let mut last = Duration::ZERO;
+    for _ in 0..3 {
+        let (sent, after) = wait_for_send(&mut s, Duration::from_secs(30));
+        assert_eq!(sent.len(), 1);
+        assert_eq!((sent[0].flags().as_str(), sent[0].seq), ("S", syn.seq));
+        assert!(after > last, "no backoff: {:?} after {:?}", after, last);
+        last = after;
+    }
+
+    // the handshake completes on the last of them
+    s.send(
+        Craft::new(peer(), local)
+            .syn()
+            .seq(7000)
+            .ack(syn.seq.wrapping_add(1))
+            .mss(1460),
+    );
+    assert_eq!(state(&s.interface, quad), Some(State::Estab));
+}
+
+#[test]
+fn unanswered_syn_ack_is_retransmitted_with_backoff() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    s.send(Craft::new(peer(), us()).syn().seq(1000).mss(1460));
+    let syn_ack = s.take_one();
+    assert_eq!(syn_ack.flags(), "S.");
+
+    let mut last = Duration::ZERO;
+    for _ in 0..3 {
+        let (sent, after) = wait_for_send(&mut s, Duration::from_secs(30));
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].flags(), "S.");
+        assert_eq!((sent[0].seq, sent[0].ack), (syn_ack.seq, Some(1001)));
+        assert!(after > last, "no backoff: {:?} after {:?}", after, last);
+        last = after;
+    }
+
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1001)
+            .ack(syn_ack.seq.wrapping_add(1)),
+    );
+    assert!(s.accepted().is_some());
+}
+
+#[test]
+fn lost_fin_is_retransmitted_and_the_close_completes() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let (quad, iss) = accept(&mut s, 80, peer(), 1000);
+    s.interface.close_on(quad);
+    s.advance(TICK);
+    let fin = s.take_one();
+    assert_eq!((fin.flags().as_str(), fin.seq), ("F.", iss.wrapping_add(1)));
+
+    // that one never arrived
+    let (sent, first) = wait_for_send(&mut s, Duration::from_secs(30));
+    assert_eq!(sent.len(), 1);
+    assert_eq!((sent[0].flags().as_str(), sent[0].seq), ("F.", fin.seq));
+    let (sent, second) = wait_for_send(&mut s, Duration::from_secs(30));
+    assert_eq!((sent[0].flags().as_str(), sent[0].seq), ("F.", fin.seq));
+    assert!(second > first, "no backoff: {:?} after {:?}", second, first);
+
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1001)
+            .ack(iss.wrapping_add(2))
+            .fin(),
+    );
+    let ack = s.take_one();
+    assert_eq!(ack.ack, Some(1002));
+    assert_eq!(state(&s.interface, quad), Some(State::TimeWait));
+}
+
+#[test]
+fn close_completes_when_the_device_loses_the_first_fin() {
+    let mut pair = Pair::new(
+        7,
+        Impairments::default(),
+        Impairments::default(),
+        TcpConfig::default(),
+        TcpConfig::default(),
+    );
+    let _listener = pair.b.bind(80).unwrap();
+    let quad = pair
+        .a
+        .connect(US, SocketAddrV4::new(PEER, 80))
+        .unwrap()
+        .into_quad();
+    for _ in 0..5 {
+        pair.step(TICK);
+    }
+    let quad_b = pair
+        .events_b
+        .iter()
+        .find_map(|e| match e {
+            InterfaceEvent::NewConnection(q) => Some(*q),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(state(&pair.a, quad), Some(State::Estab));
+
+    // the FIN goes out on the next tick, and the device loses it
+    pair.a.close_on(quad);
+    pair.net.fail_sends(1, libc::ENOBUFS);
+    pair.step(TICK);
+    assert_eq!(pair.a.send_failures(), 1);
+    assert_eq!(state(&pair.b, quad_b), Some(State::Estab));
+
+    let mut waited = Duration::ZERO;
+    while state(&pair.b, quad_b) == Some(State::Estab) {
+        assert!(waited < Duration::from_secs(10), "the FIN was never resent");
+        pair.step(TICK);
+        waited += TICK;
+    }
+    assert_eq!(state(&pair.b, quad_b), Some(State::CloseWait));
+    pair.b.close_on(quad_b);
+    for _ in 0..5 {
+        pair.step(TICK);
+    }
+    assert_eq!(state(&pair.a, quad), Some(State::TimeWait));
+    assert_eq!(state(&pair.b, quad_b), None);
+}
+
+#[test]
+fn rto_follows_srtt_and_rttvar() {
+    let config = TcpConfig {
+        min_rto: Some(Duration::from_millis(10)),
+        ..TcpConfig::default()
+    };
+    let mut s = Scripted::new(config);
+    let _listener = s.interface.bind(80).unwrap();
+    s.send(Craft::new(peer(), us()).syn().seq(1000).mss(1460));
+    let syn_ack = s.take_one();
+    // the first sample, 100ms: SRTT = 100ms, RTTVAR = 50ms, so RTO = 100 + 4 * 50 = 300ms
+    s.advance(Duration::from_millis(100));
+    let iss = syn_ack.seq;
+    s.send(Craft::new(peer(), us()).seq(1001).ack(iss.wrapping_add(1)));
+    let quad = s.accepted().unwrap();
+    let info = common::connection(&s.interface, quad).unwrap();
+    assert_eq!(info.srtt, Duration::from_millis(100));
+
+    s.interface.write_on(quad, b"one").unwrap();
+    s.advance(TICK);
+    let data = s.take_one();
+    let (sent, after) = wait_for_send(&mut s, Duration::from_secs(5));
+    assert_eq!(sent[0].seq, data.seq);
+    let rto = Duration::from_millis(300);
+    assert!(after >= rto && after <= rto + TICK, "RTO of {:?}", after);
+
+    // the ACK of a retransmission makes no sample (Karn's algorithm), so a second one comes
+    // from new data, 200ms: RTTVAR = 3/4 * 50 + 1/4 * |100 - 200| = 62.5ms, and SRTT =
+    // 7/8 * 100 + 1/8 * 200 = 112.5ms, so RTO = 112.5 + 4 * 62.5 = 362.5ms
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1001)
+            .ack(data.seq.wrapping_add(3)),
+    );
+    s.interface.write_on(quad, b"two").unwrap();
+    s.advance(TICK);
+    let data = s.take_one();
+    s.advance(Duration::from_millis(200));
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1001)
+            .ack(data.seq.wrapping_add(3)),
+    );
+    let info = common::connection(&s.interface, quad).unwrap();
+    assert_eq!(info.srtt, Duration::from_micros(112_500));
+
+    s.interface.write_on(quad, b"three").unwrap();
+    s.advance(TICK);
+    s.take_one();
+    let (_, after) = wait_for_send(&mut s, Duration::from_secs(5));
+    let rto = Duration::from_micros(362_500);
+    assert!(after >= rto && after <= rto + TICK, "RTO of {:?}", after);
+}
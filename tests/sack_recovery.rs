@@ -0,0 +1,110 @@
+//! Loss recovery driven by the peer's SACK blocks (RFC 6675): what the scoreboard takes for
+//! lost goes again, and pipe rather than SND.NXT - SND.UNA says how much more may go.
+
+mod common;
+
+use common::{connection, peer, us, Craft, Scripted, Segment, TICK};
+use trust::{Quad, TcpConfig, TcpListener};
+
+const MSS: u32 = 1000;
+
+/// A connection with SACK on both sides and segments of 1000 bytes, which has sent ten of
+/// them out of the 12 it was given: returns it, with its listener and the sequence number of
+/// the first.
+fn ten_out() -> (Scripted, TcpListener, Quad, u32) {
+    let config = TcpConfig {
+        // so that only pipe lets anything more go before recovery is over
+        limited_transmit: false,
+        ..TcpConfig::default()
+    };
+    let mut s = Scripted::new(config);
+    let listener = s.interface.bind(80).unwrap();
+    s.send(
+        Craft::new(peer(), us())
+            .syn()
+            .seq(1000)
+            .mss(MSS as u16)
+            .sack_permitted(),
+    );
+    let syn_ack = s.take_one();
+    assert!(syn_ack.sack_permitted());
+    let start = syn_ack.seq.wrapping_add(1);
+    s.send(Craft::new(peer(), us()).seq(1001).ack(start));
+    let quad = s.accepted().unwrap();
+    s.interface
+        .write_on(quad, &common::pattern(1, 12 * MSS as usize))
+        .unwrap();
+    s.advance(TICK);
+    let sent = s.take();
+    assert_eq!(sent.len(), 10);
+    assert!(sent
+        .iter()
+        .all(|segment| segment.payload.len() == MSS as usize));
+    (s, listener, quad, start)
+}
+
+/// A duplicate ACK of `start`, SACKing `blocks` of segments, by their number from 0.
+fn sack(start: u32, blocks: &[(u32, u32)]) -> Craft {
+    let blocks: Vec<_> = blocks
+        .iter()
+        .map(|&(from, to)| (start + from * MSS, start + to * MSS))
+        .collect();
+    Craft::new(peer(), us()).seq(1001).ack(start).sack(&blocks)
+}
+
+fn in_flight(s: &Scripted, quad: Quad) -> u32 {
+    connection(&s.interface, quad).unwrap().bytes_in_flight
+}
+
+/// The segment numbers of what was sent.
+fn numbers(sent: &[Segment], start: u32) -> Vec<u32> {
+    sent.iter()
+        .map(|segment| segment.seq.wrapping_sub(start) / MSS)
+        .collect()
+}
+
+#[test]
+fn lost_segments_go_again_as_pipe_allows() {
+    // segments 0 and 2 are lost; the rest arrive, and are SACKed
+    let (mut s, _listener, quad, start) = ten_out();
+
+    s.send(sack(start, &[(1, 2)]));
+    s.send(sack(start, &[(3, 4), (1, 2)]));
+    // short of recovery, everything sent is in flight
+    assert!(s.take().is_empty());
+    assert_eq!(in_flight(&s, quad), 10 * MSS);
+
+    // the third duplicate ACK starts recovery, with cwnd = ssthresh = 5 segments. Segment 0
+    // goes again at once; with three segments SACKed above it, it is lost, while segment 2
+    // has only two above it. Pipe: segments 2 and 5-9, and segment 0 once more
+    s.send(sack(start, &[(3, 5), (1, 2)]));
+    assert_eq!(numbers(&s.take(), start), [0]);
+    assert_eq!(in_flight(&s, quad), 7 * MSS);
+
+    // now segment 2 is lost too, which leaves pipe at segment 0 and 6-9: a full window
+    s.send(sack(start, &[(3, 6), (1, 2)]));
+    assert!(s.take().is_empty());
+    assert_eq!(in_flight(&s, quad), 5 * MSS);
+
+    // one more leaves the network, which makes room for segment 2
+    s.send(sack(start, &[(3, 7), (1, 2)]));
+    assert_eq!(numbers(&s.take(), start), [2]);
+    assert_eq!(in_flight(&s, quad), 5 * MSS);
+
+    // with nothing more lost, room in the window goes to new data
+    s.send(sack(start, &[(3, 8), (1, 2)]));
+    assert_eq!(numbers(&s.take(), start), [10]);
+    assert_eq!(in_flight(&s, quad), 5 * MSS);
+
+    // segment 0 arrives again: a partial ACK, up to segment 2, which went again already, so
+    // the room it makes goes to the last of the new data
+    s.send(sack(start, &[(3, 8)]).ack(start + 2 * MSS));
+    assert_eq!(numbers(&s.take(), start), [11]);
+    assert_eq!(in_flight(&s, quad), 5 * MSS);
+
+    // and then everything does, which ends recovery
+    s.send(Craft::new(peer(), us()).seq(1001).ack(start + 12 * MSS));
+    s.advance(TICK);
+    assert!(s.take().is_empty());
+    assert_eq!(in_flight(&s, quad), 0);
+}
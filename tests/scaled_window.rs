@@ -0,0 +1,74 @@
+//! A scaled receive window with the buffer behind it full: the right edge we advertise stays
+//! put rather than creeping out a scale unit at a time, so a peer that fills every window it
+//! is offered never gets more than the buffer in.
+
+mod common;
+
+use std::net::SocketAddrV4;
+
+use common::{pattern, Craft, Scripted, PEER, TICK, US};
+use trust::{Nic, TcpConfig};
+
+const BUFFER: usize = 1 << 20;
+
+#[test]
+fn a_full_buffer_holds_no_more_than_it_should() {
+    let mut s = Scripted::new(TcpConfig {
+        recv_buffer: BUFFER,
+        ..TcpConfig::default()
+    });
+    let _listener = s.interface.bind(80).unwrap();
+    let (peer, us) = (SocketAddrV4::new(PEER, 40000), SocketAddrV4::new(US, 80));
+    s.send(Craft::new(peer, us).syn().seq(1000).mss(1460).wscale(7));
+    let syn_ack = s.take_one();
+    let scale = syn_ack.wscale().expect("a window scale for a 1 MiB buffer");
+    assert_eq!(scale, 5);
+    let iss = syn_ack.seq;
+    s.send(Craft::new(peer, us).seq(1001).ack(iss.wrapping_add(1)));
+    let quad = s.accepted().unwrap();
+
+    // the peer sends up to whatever right edge it is shown, nobody reads, and when the window
+    // is closed it probes past the edge with one byte
+    let data = pattern(202, 3 * BUFFER);
+    let (mut sent, mut edge) = (0, syn_ack.window as usize);
+    let mut probes = 0;
+    for _ in 0..200 {
+        let segment = |from: usize, to: usize| {
+            Craft::new(peer, us)
+                .seq(1001 + from as u32)
+                .ack(iss.wrapping_add(1))
+                .payload(&data[from..to])
+        };
+        if sent < edge {
+            while sent < edge {
+                let to = edge.min(sent + 1460);
+                s.peer.send(&segment(sent, to).build()).unwrap();
+                sent = to;
+            }
+        } else {
+            s.peer.send(&segment(sent, sent + 1).build()).unwrap();
+            probes += 1;
+        }
+        s.settle();
+        s.advance(TICK);
+        for ack in s.take() {
+            let right =
+                ack.ack.unwrap().wrapping_sub(1001) as usize + ((ack.window as usize) << scale);
+            edge = edge.max(right);
+        }
+        let buffered = s.interface.recv_memory().buffered;
+        assert!(
+            buffered < BUFFER + (1 << scale),
+            "{} bytes in a buffer of {}",
+            buffered,
+            BUFFER
+        );
+    }
+    assert!(probes > 100);
+    assert!(edge < BUFFER + (1 << scale));
+
+    // and all that was taken in reads back as it was sent
+    let read = s.read_all(quad);
+    assert!(read.len() >= BUFFER - 1460);
+    assert_eq!(read, data[..read.len()]);
+}
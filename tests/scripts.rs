@@ -0,0 +1,144 @@
+//! Whole exchanges written as scripts (see [`common::script`]): the handshake both ways, a
+//! duplicate SYN, challenge ACKs, both ends closing at once, and Nagle's algorithm.
+
+mod common;
+
+use common::script::run;
+use trust::TcpConfig;
+
+#[test]
+fn passive_open() {
+    run(
+        TcpConfig::default(),
+        "
+        +0    bind 80
+        +0    < S seq=0 <mss 1460>
+        +0    > S. seq=0 ack=1 <mss 1460>
+        +0    < . seq=1 ack=1
+        +0    state Estab
+
+        +0    write 100
+        +0.01 > P. seq=1 ack=1 len=100
+        +0    < . seq=1 ack=101
+        +0    < P. seq=1 ack=101 len=20
+        # a delayed ACK, at most 200ms on (RFC 1122 S4.2.3.2)
+        +0.2  > . seq=101 ack=21
+        ",
+    );
+}
+
+#[test]
+fn active_open() {
+    run(
+        TcpConfig::default(),
+        "
+        +0    connect
+        +0.01 > S seq=0
+        +0    state SynSent
+        +0    < S. seq=0 ack=1 <mss 1460>
+        +0    > . seq=1 ack=1
+        +0    state Estab
+        ",
+    );
+}
+
+#[test]
+fn syn_ack_is_retransmitted_until_the_handshake_completes() {
+    run(
+        TcpConfig::default(),
+        "
+        +0    bind 80
+        +0    < S seq=0 <mss 1460>
+        +0    > S. seq=0 ack=1
+        # our SYN-ACK was lost: the peer's SYN comes again, and is answered again
+        +0.5  < S seq=0 <mss 1460>
+        +0    > S. seq=0 ack=1
+        +0    state SynRcvd
+        # and with nothing from the peer, the timer sends it once more
+        +1    > S. seq=0 ack=1
+        +0    < . seq=1 ack=1
+        +0    state Estab
+        ",
+    );
+}
+
+#[test]
+fn challenge_acks() {
+    run(
+        TcpConfig::default(),
+        "
+        +0    bind 80
+        +0    < S seq=0 <mss 1460>
+        +0    > S. seq=0 ack=1
+        +0    < . seq=1 ack=1
+        +0    write 10
+        +0.01 > P. seq=1 ack=1 len=10
+
+        # data far outside the window is answered with what we expect instead
+        +0    < . seq=1000000 ack=11 len=10
+        +0    > . seq=11 ack=1
+        # as is an ACK of data we never sent
+        +0    < . seq=1 ack=5000
+        +0    > . seq=11 ack=1
+        # but a reset outside the window is not, nor does it take
+        +0    < R seq=1000000
+        +0    state Estab
+        # one within it does
+        +0    < R seq=1
+        +0    state Closed
+        ",
+    );
+}
+
+#[test]
+fn simultaneous_close() {
+    run(
+        TcpConfig::default(),
+        "
+        +0    bind 80
+        +0    < S seq=0 <mss 1460>
+        +0    > S. seq=0 ack=1
+        +0    < . seq=1 ack=1
+
+        +0    close
+        +0.01 > F. seq=1 ack=1
+        # the peer's FIN crosses ours, before it has seen it
+        +0    < F. seq=1 ack=1
+        +0    > . seq=2 ack=2
+        +0    state Closing
+        +0    < . seq=2 ack=2
+        +0    state TimeWait
+        ",
+    );
+}
+
+#[test]
+fn nagle_holds_small_segments_while_data_is_unacknowledged() {
+    run(
+        TcpConfig {
+            nagle: true,
+            ..TcpConfig::default()
+        },
+        "
+        +0    bind 80
+        +0    < S seq=0 <mss 1460>
+        +0    > S. seq=0 ack=1
+        +0    < . seq=1 ack=1
+
+        # nothing in flight, so a small write goes right away
+        +0    write 100
+        +0.01 > P. seq=1 ack=1 len=100
+        # but the next ones wait for its ACK, and then go together
+        +0    write 100
+        +0.01 write 100
+        +0.1  < . seq=1 ack=101
+        +0.01 > P. seq=101 ack=1 len=200
+        # a full segment never waits
+        +0    write 2000
+        +0.01 > . seq=301 ack=1 len=1460
+        # nor does what is left once the application has closed
+        +0    close
+        +0.01 > FP. seq=1761 ack=1 len=540
+        ",
+    );
+}
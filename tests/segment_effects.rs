@@ -0,0 +1,98 @@
+//! What a segment changes is what wakes the application: readers for data and the end of the
+//! stream, writers for ACKs that make room, and the segment that finishes a connection takes
+//! it out of the table there and then.
+
+mod common;
+
+use std::io::Write;
+use std::net::{Shutdown, SocketAddrV4};
+use std::thread;
+
+use common::{connect, Craft, Scripted, TICK, US};
+use common::{peer, threaded::Threaded};
+use trust::{CloseReason, InterfaceEvent, TcpConfig};
+
+#[test]
+fn the_segment_that_finishes_a_connection_removes_it() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    let quad = stream.quad();
+    s.send(
+        Craft::new(peer(), quad.local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .fin(),
+    );
+    stream.shutdown(Shutdown::Write).unwrap();
+    s.advance(TICK);
+    let fin = s.take().into_iter().find(|seg| seg.fin).unwrap();
+    s.take_events();
+
+    // the ACK of our FIN, with no time passing after it
+    s.send(
+        Craft::new(peer(), quad.local())
+            .seq(1002)
+            .ack(fin.seq.wrapping_add(1)),
+    );
+    assert!(s.interface.connections().is_empty());
+    s.advance(10 * TICK);
+    let closed: Vec<_> = s
+        .take_events()
+        .into_iter()
+        .filter(|e| matches!(e, InterfaceEvent::Closed(..)))
+        .collect();
+    assert!(
+        matches!(closed[..], [InterfaceEvent::Closed(q, CloseReason::PeerFin)] if q == quad),
+        "{:?}",
+        closed
+    );
+}
+
+#[test]
+fn an_ack_does_not_wake_readers() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    stream.write_all(b"request").unwrap();
+    s.advance(TICK);
+    s.take();
+    s.take_events();
+
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .seq(1001)
+            .ack(iss.wrapping_add(8)),
+    );
+    let events = s.take_events();
+    assert!(
+        !events
+            .iter()
+            .any(|e| matches!(e, InterfaceEvent::Readable(_))),
+        "{:?}",
+        events
+    );
+}
+
+#[test]
+fn a_blocked_writer_wakes_as_the_peer_makes_room() {
+    let mut t = Threaded::with(TcpConfig {
+        send_buffer: 4096,
+        ..TcpConfig::default()
+    });
+    let us = SocketAddrV4::new(US, 80);
+    let listener = t.interface.bind(80).unwrap();
+    t.send(Craft::new(peer(), us).syn().seq(1000).mss(1460));
+    let iss = t.next().seq;
+    t.send(Craft::new(peer(), us).seq(1001).ack(iss.wrapping_add(1)));
+    let mut stream = listener.accept().unwrap();
+
+    let writer = thread::spawn(move || stream.write_all(&[1; 20_000]).unwrap());
+    let mut acked = 0;
+    while acked < 20_000 {
+        let segment = t.next();
+        let end = segment.seq.wrapping_add(segment.len());
+        acked = acked.max(end.wrapping_sub(iss.wrapping_add(1)));
+        t.send(Craft::new(peer(), us).seq(1001).ack(end));
+    }
+    // it only got all of it in as ACKs made room
+    writer.join().unwrap();
+}
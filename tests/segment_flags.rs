@@ -0,0 +1,71 @@
+//! Each segment's flags are its own: an RST or a FIN sent along the way never carries over to
+//! the segments after it.
+
+mod common;
+
+use std::net::{Shutdown, SocketAddrV4};
+
+use common::{accept, Craft, Scripted, PEER, TICK, US};
+use trust::TcpConfig;
+
+#[test]
+fn flags_do_not_carry_over_from_one_segment_to_the_next() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let (peer, us) = (SocketAddrV4::new(PEER, 40000), SocketAddrV4::new(US, 80));
+    s.send(Craft::new(peer, us).syn().seq(1000).mss(1460));
+    let syn_ack = s.take_one();
+    assert_eq!(syn_ack.flags(), "S.");
+    let iss = syn_ack.seq;
+
+    // an ACK of something we never sent, in SYN-RECEIVED, draws a bare RST at it
+    let bogus = iss.wrapping_add(5000);
+    s.send(Craft::new(peer, us).seq(1001).ack(bogus));
+    let rst = s.take_one();
+    assert_eq!((rst.flags(), rst.seq), ("R".to_string(), bogus));
+
+    // the handshake completes all the same, and what follows has nothing of the RST about it
+    s.send(Craft::new(peer, us).seq(1001).ack(iss.wrapping_add(1)));
+    let quad = s.accepted().unwrap();
+    s.interface.write_on(quad, b"hello").unwrap();
+    s.advance(TICK);
+    let data = s.take_one();
+    assert_eq!(data.flags(), "P.");
+    assert_eq!(data.payload, b"hello");
+
+    // a pure ACK of the peer's data carries no PSH
+    s.send(
+        Craft::new(peer, us)
+            .seq(1001)
+            .ack(iss.wrapping_add(6))
+            .psh()
+            .payload(b"hi"),
+    );
+    assert_eq!(s.take_one().flags(), ".");
+
+    s.interface.shutdown_on(quad, Shutdown::Write).unwrap();
+    s.advance(TICK);
+    let fin = s.take_one();
+    assert_eq!(fin.flags(), "F.");
+    assert!(fin.payload.is_empty());
+}
+
+#[test]
+fn an_rst_on_one_connection_leaves_anothers_data_alone() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let _listener = s.interface.bind(80).unwrap();
+    let (aborted, _) = accept(&mut s, 80, SocketAddrV4::new(PEER, 40000), 1000);
+    let (writing, _) = accept(&mut s, 80, SocketAddrV4::new(PEER, 40001), 1000);
+    s.take();
+
+    s.interface.abort(aborted).unwrap();
+    s.interface.write_on(writing, b"hello").unwrap();
+    s.advance(TICK);
+    let mut sent: Vec<_> = s
+        .take()
+        .iter()
+        .map(|seg| (seg.dst.port(), seg.flags()))
+        .collect();
+    sent.sort();
+    assert_eq!(sent, [(40000, "R.".to_string()), (40001, "P.".to_string())]);
+}
@@ -0,0 +1,75 @@
+//! Active opens and the quads they pick: an ephemeral port is never one that would connect us
+//! to ourselves or one a connection to the same peer already has, a quad asked for outright
+//! that is taken fails with `AddrInUse`, and one that connects to itself on purpose works.
+
+mod common;
+
+use std::io;
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use common::{peer, state, Scripted, TICK, US};
+use trust::{State, TcpConfig};
+
+#[test]
+fn the_ephemeral_port_skips_a_self_connect() {
+    let mut s = Scripted::new(TcpConfig::default());
+    // the first port handed out would make the quad the same both ways
+    let ourselves = SocketAddrV4::new(US, 49152);
+    let stream = s.interface.connect(US, ourselves).unwrap();
+    assert_eq!(stream.quad().local(), SocketAddrV4::new(US, 49153));
+}
+
+#[test]
+fn the_ephemeral_port_skips_a_quad_in_use() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let first = s
+        .interface
+        .connect_from(SocketAddrV4::new(US, 49152), peer())
+        .unwrap();
+    let second = s.interface.connect(US, peer()).unwrap();
+    assert_ne!(second.quad().local(), first.quad().local());
+}
+
+#[test]
+fn a_quad_in_use_is_not_clobbered() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let local = SocketAddrV4::new(US, 5000);
+    let stream = s.interface.connect_from(local, peer()).unwrap();
+    s.advance(TICK);
+    assert_eq!(s.take_one().flags(), "S");
+
+    let err = s
+        .interface
+        .connect_from(local, peer())
+        .map(|_| ())
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+    // the connection there goes on as it was, with no second SYN
+    s.advance(TICK);
+    assert!(s.take().is_empty());
+    assert_eq!(state(&s.interface, stream.quad()), Some(State::SynSent));
+    assert_eq!(stream.info().unwrap().state, State::SynSent);
+}
+
+#[test]
+fn a_self_connect_asked_for_opens_and_echoes() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let ourselves = SocketAddrV4::new(US, 5000);
+    let quad = s
+        .interface
+        .connect_from(ourselves, ourselves)
+        .unwrap()
+        .into_quad();
+    let start = s.net.now();
+    while state(&s.interface, quad) != Some(State::Estab) {
+        assert!(s.net.now() - start < Duration::from_secs(5), "never opened");
+        s.advance(TICK);
+    }
+    // none of it leaves the interface
+    assert!(s.take().is_empty());
+
+    s.interface.write_on(quad, b"talking to myself").unwrap();
+    s.advance(TICK);
+    assert_eq!(s.read_all(quad), b"talking to myself");
+}
@@ -0,0 +1,99 @@
+//! Sending part of a file with `send_file`: over a lossy link, what arrives is the file as it
+//! was when sent, in full, even though the file is overwritten as soon as the call returns,
+//! since retransmissions come from the send buffer rather than the file.
+
+mod common;
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddrV4};
+use std::os::unix::fs::FileExt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use common::{pattern, PEER, US};
+use trust::{Impairments, InterfaceBuilder, SimNet};
+
+const LEN: usize = 5_000_000;
+
+/// A file holding `contents`, removed again when dropped.
+struct Scratch(std::path::PathBuf);
+
+impl Scratch {
+    fn new(name: &str, contents: &[u8]) -> Self {
+        let path = std::env::temp_dir().join(format!("trust-{}-{}", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        Scratch(path)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+#[test]
+fn a_file_arrives_whole_over_a_lossy_link() {
+    let lossy = Impairments {
+        drop: 0.02,
+        reorder: 0.01,
+        latency: Duration::from_millis(5),
+        ..Impairments::default()
+    };
+    // both ends on the system clock, with packet loops of their own, and the network's
+    // virtual time kept in step with it so that delayed packets arrive
+    let (net, a, b) = SimNet::new(168, lossy.clone(), lossy).unwrap();
+    let done = Arc::new(AtomicBool::new(false));
+    let wire = {
+        let done = done.clone();
+        thread::spawn(move || {
+            while !done.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+                net.advance(Duration::from_millis(1));
+            }
+        })
+    };
+    let mut server = InterfaceBuilder::new();
+    server.add_nic(a, &[US]);
+    let mut server = server.build().unwrap();
+    let mut client = InterfaceBuilder::new();
+    client.add_nic(b, &[PEER]);
+    let mut client = client.build().unwrap();
+
+    let data = pattern(168, LEN);
+    let scratch = Scratch::new("send-file", &data);
+    let listener = server.bind(80).unwrap();
+    let mut receiving = client.connect(PEER, SocketAddrV4::new(US, 80)).unwrap();
+    let mut sending = listener.accept().unwrap();
+
+    let reader = thread::spawn(move || {
+        let mut got = Vec::new();
+        receiving.read_to_end(&mut got).unwrap();
+        got
+    });
+
+    let file = File::open(&scratch.0).unwrap();
+    // in two calls, the second asking for more than is left
+    assert_eq!(sending.send_file(&file, 0, LEN / 2).unwrap(), LEN / 2);
+    let rest = LEN - LEN / 2;
+    assert_eq!(
+        sending
+            .send_file(&file, (LEN / 2) as u64, rest + 1000)
+            .unwrap(),
+        rest
+    );
+    // nothing more is read from the file, so it may change at once
+    let writer = File::options().write(true).open(&scratch.0).unwrap();
+    writer.write_all_at(&vec![0; LEN], 0).unwrap();
+    sending.flush().unwrap();
+    sending.shutdown(Shutdown::Write).unwrap();
+
+    let got = reader.join().unwrap();
+    done.store(true, Ordering::Relaxed);
+    wire.join().unwrap();
+    assert_eq!(got.len(), LEN);
+    assert!(got == data, "the data arrived changed");
+}
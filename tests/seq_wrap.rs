@@ -0,0 +1,83 @@
+//! Sequence numbers wrapping around mid-connection, on either side, with the ISS fixed close
+//! to the wrap: data, RTT samples and FINs carry on across it as anywhere else.
+
+mod common;
+
+use std::io::Write;
+use std::net::Shutdown;
+use std::time::Duration;
+
+use common::{connect, pattern, peer, state, Craft, Scripted, TICK};
+use trust::{State, TcpConfig};
+
+fn fixed(iss: u32) -> Scripted {
+    Scripted::with(TcpConfig::default(), |builder| {
+        builder.fixed_iss(iss);
+    })
+}
+
+#[test]
+fn rtt_samples_are_taken_across_the_wrap() {
+    let iss = u32::MAX - 1000;
+    let mut s = fixed(iss);
+    let (mut stream, ours) = connect(&mut s, peer(), 1000);
+    assert_eq!(ours, iss);
+
+    // three segments, the first of them straddling the wrap
+    stream.write_all(&[1; 3 * 1460]).unwrap();
+    s.advance(TICK);
+    let sent = s.take();
+    let ends: Vec<u32> = sent
+        .iter()
+        .map(|segment| segment.seq.wrapping_add(segment.len()))
+        .collect();
+    assert_eq!(ends, [460, 1920, 3380]);
+
+    // the ACK for the segment across the wrap alone makes for a sample
+    let before = stream.info().unwrap().srtt;
+    s.advance(Duration::from_millis(300) - TICK);
+    let ack = |end| Craft::new(peer(), stream.quad().local()).seq(1001).ack(end);
+    s.send(ack(ends[0]));
+    let after = stream.info().unwrap().srtt;
+    assert!(after > before, "{:?} after {:?}", after, before);
+
+    s.send(ack(ends[2]));
+    assert_eq!(stream.info().unwrap().bytes_in_flight, 0);
+}
+
+#[test]
+fn data_from_the_peer_is_taken_in_across_the_wrap() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let irs = u32::MAX - 1000;
+    let (stream, iss) = connect(&mut s, peer(), irs);
+    let data = pattern(191, 3 * 1460);
+    let mut seq = irs.wrapping_add(1);
+    for chunk in data.chunks(1460) {
+        s.send(
+            Craft::new(peer(), stream.quad().local())
+                .seq(seq)
+                .ack(iss.wrapping_add(1))
+                .payload(chunk),
+        );
+        seq = seq.wrapping_add(chunk.len() as u32);
+    }
+    assert_eq!(s.take().last().unwrap().ack, Some(seq));
+    assert_eq!(s.read_all(stream.quad()), data);
+}
+
+#[test]
+fn a_fin_on_the_wrap() {
+    // four bytes of data take the last four sequence numbers, so the FIN has 0
+    let mut s = fixed(u32::MAX - 4);
+    let (mut stream, _) = connect(&mut s, peer(), 1000);
+    stream.write_all(b"last").unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    s.advance(TICK);
+    let sent = s.take();
+    let fin = sent.last().unwrap();
+    assert!(fin.fin);
+    assert_eq!(fin.seq.wrapping_add(fin.len()), 1);
+
+    s.send(Craft::new(peer(), stream.quad().local()).seq(1001).ack(1));
+    assert_eq!(state(&s.interface, stream.quad()), Some(State::FinWait2));
+}
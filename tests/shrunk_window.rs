@@ -0,0 +1,113 @@
+//! A peer that shrinks its window, taken as it is: nothing new goes out past the new right
+//! edge, what is in flight beyond it is not taken for lost, retransmissions stay inside it, and
+//! the transfer still completes. And our own right edge never moves back.
+
+mod common;
+
+use std::io::Write;
+use std::time::Duration;
+
+use common::{connect, pattern, peer, Craft, Scripted, TICK};
+use trust::{Strictness, TcpConfig};
+
+const MSS: u32 = 1460;
+
+fn permissive() -> TcpConfig {
+    TcpConfig {
+        strictness: Strictness::Permissive,
+        send_buffer: 64 << 10,
+        ..TcpConfig::default()
+    }
+}
+
+#[test]
+fn a_transfer_completes_inside_a_window_shrunk_mid_way() {
+    let mut s = Scripted::new(permissive());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+    let data = pattern(162, 60 << 10);
+    stream.write_all(&data).unwrap();
+    s.advance(TICK);
+    let first = s.take();
+    assert_eq!(first.len(), 10);
+
+    // two segments in, the window shrinks from 64 KiB to 4 KiB, short of what is in flight
+    let window = 4096;
+    let mut got = data[..2 * MSS as usize].to_vec();
+    let ack = |s: &mut Scripted, got: &[u8]| {
+        s.send(
+            Craft::new(peer(), us)
+                .seq(1001)
+                .ack(iss.wrapping_add(1 + got.len() as u32))
+                .window(window as u16),
+        )
+    };
+    ack(&mut s, &got);
+    s.advance(TICK);
+    assert!(s.take().is_empty(), "sent past the new right edge");
+
+    // the peer dropped what lay past its window, so the rest goes again from there, a
+    // segment at a time past each ACK, never beyond the edge the ACK set
+    let start = s.net.now();
+    while got.len() < data.len() {
+        assert!(s.net.now() - start < Duration::from_secs(60), "stalled");
+        s.advance(TICK);
+        let edge = iss.wrapping_add(1 + got.len() as u32 + window);
+        let mut advanced = false;
+        for seg in s.take() {
+            let end = seg.seq.wrapping_add(seg.payload.len() as u32);
+            assert!(end.wrapping_sub(edge) as i32 <= 0, "{}", seg.describe());
+            if seg.seq == iss.wrapping_add(1 + got.len() as u32) {
+                got.extend(&seg.payload);
+                advanced = true;
+            }
+        }
+        if advanced {
+            ack(&mut s, &got);
+        }
+    }
+    assert!(got == data);
+}
+
+#[test]
+fn a_retransmission_is_cut_to_a_window_shorter_than_a_segment() {
+    let mut s = Scripted::new(permissive());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    stream.write_all(&pattern(162, 3 * MSS as usize)).unwrap();
+    s.advance(TICK);
+    assert_eq!(s.take().len(), 3);
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .window(1000),
+    );
+    // the retransmission timeout comes round, and resends no more than the window holds
+    s.advance(Duration::from_millis(1100));
+    let again = s.take_one();
+    assert_eq!(
+        (again.seq, again.payload.len()),
+        (iss.wrapping_add(1), 1000)
+    );
+}
+
+#[test]
+fn our_right_edge_never_moves_back() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    // data nobody reads, filling our buffer and then some
+    let mut edge = 1001 + u16::MAX as u32;
+    for i in 0..50 {
+        s.send(
+            Craft::new(peer(), stream.quad().local())
+                .seq(1001 + i * MSS)
+                .ack(iss.wrapping_add(1))
+                .payload(&[0; MSS as usize]),
+        );
+        for ack in s.take() {
+            let right = ack.ack.unwrap() + ack.window as u32;
+            assert!(right >= edge, "the right edge moved back to {}", right);
+            edge = right;
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! What a stream does with data written before its handshake completes, and with data that
+//! arrives after it is shut down for reading.
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddrV4};
+
+use common::{Craft, Scripted, PEER, TICK, US};
+use trust::TcpConfig;
+
+fn peer() -> SocketAddrV4 {
+    SocketAddrV4::new(PEER, 8080)
+}
+
+#[test]
+fn writes_queued_in_syn_sent_go_out_in_order_once_established() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let local = SocketAddrV4::new(US, 40000);
+    let stream = s.interface.connect_from(local, peer()).unwrap();
+    let quad = stream.into_quad();
+    s.advance(TICK);
+    let syn = s.take_one();
+    assert_eq!(syn.flags(), "S");
+
+    // still in SYN-SENT: all of it is taken, and none of it sent
+    let data: Vec<u8> = (0..2048).map(|i| (i % 251) as u8).collect();
+    assert_eq!(s.interface.write_on(quad, &data).unwrap(), data.len());
+    s.advance(TICK);
+    assert!(s.take().is_empty());
+
+    s.send(
+        Craft::new(peer(), local)
+            .syn()
+            .seq(5000)
+            .ack(syn.seq.wrapping_add(1))
+            .mss(1460),
+    );
+    s.advance(TICK);
+    let sent = s.take();
+    let segments: Vec<_> = sent.iter().filter(|seg| !seg.payload.is_empty()).collect();
+    assert!(!segments.is_empty(), "nothing followed the handshake");
+    let mut expected = syn.seq.wrapping_add(1);
+    let mut received = Vec::new();
+    for seg in &segments {
+        assert_eq!(seg.seq, expected, "out of order: {}", seg.describe());
+        assert_eq!(seg.ack, Some(5001));
+        expected = expected.wrapping_add(seg.payload.len() as u32);
+        received.extend_from_slice(&seg.payload);
+    }
+    assert_eq!(received, data);
+}
+
+#[test]
+fn data_after_shutdown_read_is_acknowledged_and_discarded() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let local = SocketAddrV4::new(US, 40000);
+    let mut stream = s.interface.connect_from(local, peer()).unwrap();
+    s.advance(TICK);
+    let iss = s.take_one().seq;
+    s.send(
+        Craft::new(peer(), local)
+            .syn()
+            .seq(1000)
+            .ack(iss.wrapping_add(1))
+            .mss(1460),
+    );
+    s.advance(TICK);
+    s.take();
+
+    s.send(
+        Craft::new(peer(), local)
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .psh()
+            .payload(b"unread"),
+    );
+    stream.shutdown(Shutdown::Read).unwrap();
+    let mut buf = [0; 64];
+    assert_eq!(stream.read(&mut buf).unwrap(), 0, "unread data was kept");
+
+    s.advance(TICK);
+    s.take();
+    s.send(
+        Craft::new(peer(), local)
+            .seq(1007)
+            .ack(iss.wrapping_add(1))
+            .psh()
+            .payload(b"late"),
+    );
+    s.advance(TICK);
+    let acks = s.take();
+    assert_eq!(acks.last().and_then(|seg| seg.ack), Some(1011));
+    assert_eq!(stream.read(&mut buf).unwrap(), 0, "late data was delivered");
+
+    // the other direction still works
+    stream.write_all(b"still writing").unwrap();
+    s.advance(TICK);
+    let sent = s.take();
+    assert!(sent.iter().any(|seg| seg.payload == b"still writing"));
+}
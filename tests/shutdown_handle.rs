@@ -0,0 +1,108 @@
+//! Shutting an interface down through a [`ShutdownHandle`] signaled from another thread:
+//! [`Interface::run`] returns soon after, with every connection reset or closed, the way the
+//! handle was asked to, and a handle dropped without a signal changes nothing.
+
+mod common;
+
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::Craft;
+use common::{peer, threaded::Threaded, us};
+use trust::{CloseReason, Interface, Nic, ShutdownMode, TcpStream};
+
+/// A connection accepted on port 80 with the peer, whose ISS is 1000: returns the stream and
+/// our ISS.
+fn accepted(t: &mut Threaded) -> (TcpStream, u32) {
+    let listener = t.interface.bind(80).unwrap();
+    t.send(Craft::new(peer(), us()).syn().seq(1000).mss(1460));
+    let iss = t.next().seq;
+    t.send(Craft::new(peer(), us()).seq(1001).ack(iss.wrapping_add(1)));
+    let stream = listener.accept().unwrap();
+    t.take();
+    (stream, iss)
+}
+
+/// Signal `mode` from another thread a little while from now, and run the interface until
+/// that has shut it down: returns how long that took.
+fn signal_and_run(interface: &mut Interface, mode: ShutdownMode) -> Duration {
+    let handle = interface.shutdown_handle(mode);
+    let start = Instant::now();
+    let signaler = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        handle.signal();
+    });
+    interface.run().unwrap();
+    signaler.join().unwrap();
+    start.elapsed()
+}
+
+#[test]
+fn abort_resets_a_connection_mid_transfer() {
+    let mut t = Threaded::new();
+    let (stream, _) = accepted(&mut t);
+    thread::scope(|scope| {
+        // more than the peer's window and our buffer hold, so the writer is blocked
+        let writer = scope.spawn(|| (&stream).write_all(&vec![0; 1 << 20]));
+        thread::sleep(Duration::from_millis(50));
+        assert!(!t.take().is_empty());
+
+        let took = signal_and_run(&mut t.interface, ShutdownMode::Abort);
+        assert!(took < Duration::from_secs(1), "run() took {:?}", took);
+        let err = writer.join().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionAborted);
+    });
+    assert!(t.take().iter().any(|seg| seg.rst));
+    assert_eq!(stream.close_reason(), Some(CloseReason::InterfaceShutdown));
+}
+
+#[test]
+fn graceful_closes_and_waits_for_the_peer() {
+    let mut t = Threaded::new();
+    let (stream, iss) = accepted(&mut t);
+    let grace = ShutdownMode::Graceful(Duration::from_secs(5));
+    thread::scope(|scope| {
+        let run = scope.spawn(|| signal_and_run(&mut t.interface, grace));
+        // the peer closes its end in answer to our FIN, which leaves us in TIME-WAIT: done,
+        // as far as the shutdown goes, well before its time runs out
+        thread::sleep(Duration::from_millis(100));
+        let fin = Craft::new(peer(), us())
+            .seq(1001)
+            .ack(iss.wrapping_add(2))
+            .fin();
+        t.peer.send(&fin.build()).unwrap();
+        let took = run.join().unwrap();
+        assert!(took < Duration::from_secs(1), "run() took {:?}", took);
+    });
+    let flags: Vec<_> = t.take().iter().map(|seg| seg.flags()).collect();
+    assert_eq!(flags, ["F.", "."]);
+    assert_eq!(stream.close_reason(), Some(CloseReason::Local));
+}
+
+#[test]
+fn graceful_resets_what_is_left_when_time_runs_out() {
+    let mut t = Threaded::new();
+    let (stream, _) = accepted(&mut t);
+    let took = signal_and_run(
+        &mut t.interface,
+        ShutdownMode::Graceful(Duration::from_millis(300)),
+    );
+    assert!(took >= Duration::from_millis(300), "run() took {:?}", took);
+    assert!(took < Duration::from_secs(2), "run() took {:?}", took);
+    let flags: Vec<_> = t.take().iter().map(|seg| seg.flags()).collect();
+    assert_eq!(flags.first().map(String::as_str), Some("F."));
+    assert!(flags.last().unwrap().starts_with('R'), "{:?}", flags);
+    assert_eq!(stream.close_reason(), Some(CloseReason::InterfaceShutdown));
+}
+
+#[test]
+fn a_dropped_handle_shuts_nothing_down() {
+    let mut t = Threaded::new();
+    let (mut stream, _) = accepted(&mut t);
+    drop(t.interface.shutdown_handle(ShutdownMode::Abort));
+    thread::sleep(Duration::from_millis(50));
+    stream.write_all(b"still here").unwrap();
+    assert_eq!(t.take_one().payload, b"still here");
+    assert_eq!(stream.close_reason(), None);
+}
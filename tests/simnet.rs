@@ -0,0 +1,77 @@
+//! Bulk transfers between two interfaces over a [`SimNet`] that mistreats the packets.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{pattern, Pair};
+use trust::{Impairments, TcpConfig};
+
+const STALL: Duration = Duration::from_secs(60);
+
+#[test]
+fn bulk_transfer_completes_over_5_percent_loss() {
+    let lossy = Impairments {
+        drop: 0.05,
+        latency: Duration::from_millis(5),
+        ..Default::default()
+    };
+    let mut pair = Pair::new(
+        1,
+        lossy.clone(),
+        lossy,
+        TcpConfig::default(),
+        TcpConfig::default(),
+    );
+    let data = pattern(1, 1 << 20);
+    let done = pair.exchange(80, &data, &[], STALL);
+    assert!(done.at_b == data, "the data came through corrupted");
+    assert!(done.at_a.is_empty());
+}
+
+#[test]
+fn heavy_reordering_does_not_corrupt_data() {
+    let reordering = Impairments {
+        reorder: 0.3,
+        reorder_window: Duration::from_millis(20),
+        latency: Duration::from_millis(5),
+        jitter: Duration::from_millis(5),
+        ..Default::default()
+    };
+    let mut pair = Pair::new(
+        2,
+        reordering.clone(),
+        reordering,
+        TcpConfig::default(),
+        TcpConfig::default(),
+    );
+    let data = pattern(2, 1 << 20);
+    let back = pattern(3, 256 << 10);
+    let done = pair.exchange(80, &data, &back, STALL);
+    assert!(done.at_b == data, "a to b came through corrupted");
+    assert!(done.at_a == back, "b to a came through corrupted");
+}
+
+#[test]
+fn duplication_does_not_deliver_bytes_twice() {
+    let duplicating = Impairments {
+        duplicate: 0.2,
+        latency: Duration::from_millis(5),
+        ..Default::default()
+    };
+    let mut pair = Pair::new(
+        3,
+        duplicating.clone(),
+        duplicating,
+        TcpConfig::default(),
+        TcpConfig::default(),
+    );
+    let data = pattern(4, 1 << 20);
+    let done = pair.exchange(80, &data, &[], STALL);
+    assert_eq!(done.at_b.len(), data.len());
+    assert!(done.at_b == data, "the data came through corrupted");
+    assert!(
+        done.info_b.duplicate_bytes > 0,
+        "nothing was duplicated, so nothing was tested"
+    );
+}
@@ -0,0 +1,107 @@
+//! Both ends closing at once, so that each FIN crosses the other on the way: each end ACKs the
+//! other's FIN from CLOSING and goes on to TIME-WAIT on the ACK of its own, with one FIN and one
+//! ACK each way and nothing sent again.
+
+mod common;
+
+use std::io::Read;
+use std::net::{Shutdown, SocketAddrV4};
+use std::time::Duration;
+
+use common::{connect, state, Craft, Pair, Scripted, PEER, TICK, US};
+use trust::{Impairments, InterfaceEvent, State, TcpConfig};
+
+#[test]
+fn the_fins_cross_over_a_pair() {
+    let link = Impairments {
+        latency: Duration::from_millis(5),
+        ..Impairments::default()
+    };
+    let mut pair = Pair::new(
+        152,
+        link.clone(),
+        link,
+        TcpConfig::default(),
+        TcpConfig::default(),
+    );
+    let _listener = pair.b.bind(80).unwrap();
+    let a = pair
+        .a
+        .connect(US, SocketAddrV4::new(PEER, 80))
+        .unwrap()
+        .into_quad();
+    let b = loop {
+        pair.step(Duration::from_millis(1));
+        if let Some(InterfaceEvent::NewConnection(quad)) = pair.events_b.pop() {
+            break quad;
+        }
+    };
+    pair.step(Duration::from_millis(10));
+    assert_eq!(state(&pair.a, a), Some(State::Estab));
+
+    let before = pair.net.received();
+    pair.a.shutdown_on(a, Shutdown::Write).unwrap();
+    pair.b.shutdown_on(b, Shutdown::Write).unwrap();
+    // each FIN arrives while the other is still unacknowledged, and the ACKs of both after
+    for (from, to) in [
+        (State::FinWait1, State::Closing),
+        (State::Closing, State::TimeWait),
+    ] {
+        let start = pair.net.now();
+        while [state(&pair.a, a), state(&pair.b, b)] != [Some(to); 2] {
+            assert!(pair.net.now() - start < Duration::from_millis(50));
+            for seen in [state(&pair.a, a), state(&pair.b, b)] {
+                assert!([Some(from), Some(to)].contains(&seen), "{:?}", seen);
+            }
+            pair.step(Duration::from_millis(1));
+        }
+    }
+
+    // and nothing more goes over the network for the rest of a second
+    for _ in 0..100 {
+        pair.step(TICK);
+    }
+    assert_eq!(pair.net.received() - before, 4);
+}
+
+#[test]
+fn closing_takes_only_the_ack_of_our_fin() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let peer = SocketAddrV4::new(PEER, 40000);
+    let (mut stream, iss) = connect(&mut s, peer, 1000);
+    let quad = stream.quad();
+    let segment = |seq| Craft::new(peer, quad.local()).seq(seq);
+
+    stream.shutdown(Shutdown::Write).unwrap();
+    s.advance(TICK);
+    let fin = s.take_one();
+    assert_eq!(fin.flags(), "F.");
+
+    // the peer's FIN, ACKing only what was there before ours
+    s.send(segment(1001).ack(iss.wrapping_add(1)).fin());
+    assert_eq!(s.take_one().ack, Some(1002));
+    assert_eq!(state(&s.interface, quad), Some(State::Closing));
+
+    // data past the peer's FIN is not taken, and its FIN again draws an ACK and no more
+    s.send(
+        segment(1002)
+            .ack(iss.wrapping_add(1))
+            .payload(b"after the end"),
+    );
+    s.send(segment(1001).ack(iss.wrapping_add(1)).fin());
+    let acks: Vec<_> = s.take().iter().map(|seg| (seg.flags(), seg.ack)).collect();
+    assert!(
+        acks.iter().all(|ack| *ack == (".".to_string(), Some(1002))),
+        "{:?}",
+        acks
+    );
+    assert_eq!(state(&s.interface, quad), Some(State::Closing));
+    let mut buf = [0; 32];
+    assert_eq!(stream.read(&mut buf).unwrap(), 0);
+
+    // the ACK of our FIN, and nothing else, takes it on to TIME-WAIT
+    s.send(segment(1002).ack(iss.wrapping_add(2)));
+    assert_eq!(state(&s.interface, quad), Some(State::TimeWait));
+    s.advance(Duration::from_secs(1));
+    assert!(s.take().is_empty());
+}
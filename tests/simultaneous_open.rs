@@ -0,0 +1,159 @@
+//! A simultaneous open (RFC 793 p.32): two ends that connect to each other at once each take
+//! the other's SYN in SYN-SENT, answer it with a SYN-ACK repeating their own ISS, and go on
+//! from SYN-RECEIVED to a connection that works like any other.
+
+mod common;
+
+use std::io;
+use std::net::{Shutdown, SocketAddrV4};
+use std::time::Duration;
+
+use common::{connection, pattern, state, Craft, Pair, Scripted, PEER, TICK, US};
+use trust::{Impairments, Interface, Quad, State, TcpConfig};
+
+fn link() -> Impairments {
+    Impairments {
+        latency: Duration::from_millis(50),
+        ..Impairments::default()
+    }
+}
+
+/// Write what `interface` takes of `data` past `written` on `quad`, shutting down once all of
+/// it is, and read what there is into `got`: returns whether the end of the stream is in.
+fn pump(
+    interface: &mut Interface,
+    quad: Quad,
+    data: &[u8],
+    written: &mut usize,
+    got: &mut Vec<u8>,
+) -> bool {
+    while *written < data.len() {
+        match interface.write_on(quad, &data[*written..]) {
+            Ok(n) => *written += n,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => panic!("{}", e),
+        }
+    }
+    if *written == data.len() {
+        let _ = interface.shutdown_on(quad, Shutdown::Write);
+    }
+    let mut buf = [0; 65536];
+    loop {
+        match interface.read_on(quad, &mut buf) {
+            Ok(0) => return true,
+            Ok(n) => got.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return false,
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+/// Have `a` and `b` connect to each other, `b` only after `delay`, and step until both are
+/// established: returns the two quads.
+fn open(pair: &mut Pair, delay: Duration) -> (Quad, Quad) {
+    let (at_a, at_b) = (SocketAddrV4::new(US, 5000), SocketAddrV4::new(PEER, 6000));
+    let a = pair.a.connect_from(at_a, at_b).unwrap().into_quad();
+    let start = pair.net.now();
+    while pair.net.now() - start < delay {
+        pair.step(TICK);
+    }
+    let b = pair.b.connect_from(at_b, at_a).unwrap().into_quad();
+    while state(&pair.a, a) != Some(State::Estab) || state(&pair.b, b) != Some(State::Estab) {
+        assert!(
+            pair.net.now() - start < Duration::from_secs(10),
+            "never established"
+        );
+        pair.step(TICK);
+    }
+    (a, b)
+}
+
+/// Open as [`open`] does, with the window scales the two SYNs settled on matching, then send
+/// 300 KB each way and close: both ends get all of it, and close cleanly.
+fn open_and_exchange(delay: Duration) {
+    let config = TcpConfig {
+        recv_buffer: 256 << 10,
+        ..TcpConfig::default()
+    };
+    let mut pair = Pair::new(170, link(), link(), config.clone(), config);
+    let (a, b) = open(&mut pair, delay);
+    let (info_a, info_b) = (
+        connection(&pair.a, a).unwrap(),
+        connection(&pair.b, b).unwrap(),
+    );
+    assert_eq!(
+        (info_a.snd_wscale, info_a.rcv_wscale),
+        (info_b.rcv_wscale, info_b.snd_wscale)
+    );
+
+    let (to_b, to_a) = (pattern(1, 300 << 10), pattern(2, 300 << 10));
+    let (mut written_a, mut written_b) = (0, 0);
+    let (mut at_a, mut at_b) = (Vec::new(), Vec::new());
+    let start = pair.net.now();
+    loop {
+        assert!(pair.net.now() - start < Duration::from_secs(30), "stalled");
+        let eof_a = pump(&mut pair.a, a, &to_b, &mut written_a, &mut at_a);
+        let eof_b = pump(&mut pair.b, b, &to_a, &mut written_b, &mut at_b);
+        if eof_a && eof_b {
+            break;
+        }
+        pair.step(TICK);
+    }
+    assert!(at_a == to_a && at_b == to_b);
+
+    // and both close cleanly
+    for _ in 0..50 {
+        pair.step(TICK);
+    }
+    for end in [state(&pair.a, a), state(&pair.b, b)] {
+        assert!(matches!(end, None | Some(State::TimeWait)), "{:?}", end);
+    }
+}
+
+#[test]
+fn both_ends_connect_at_once() {
+    open_and_exchange(Duration::ZERO);
+}
+
+#[test]
+fn one_end_a_little_after_the_other() {
+    // the SYN of `a` is still on its way when `b` connects
+    open_and_exchange(Duration::from_millis(30));
+}
+
+#[test]
+fn a_syn_in_syn_sent_is_answered_with_our_own_iss() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let local = SocketAddrV4::new(US, 5000);
+    let peer = SocketAddrV4::new(PEER, 6000);
+    let quad = s.interface.connect_from(local, peer).unwrap().into_quad();
+    s.advance(TICK);
+    let iss = s.take_one().seq;
+
+    s.send(Craft::new(peer, local).syn().seq(7000).mss(1460));
+    let syn_ack = s.take_one();
+    assert_eq!(syn_ack.flags(), "S.");
+    assert_eq!((syn_ack.seq, syn_ack.ack), (iss, Some(7001)));
+    assert_eq!(state(&s.interface, quad), Some(State::SynRcvd));
+
+    // the peer's SYN-ACK repeats the SYN we already have: its ACK completes the handshake,
+    // and draws nothing in reply
+    s.send(
+        Craft::new(peer, local)
+            .syn()
+            .seq(7000)
+            .ack(iss.wrapping_add(1))
+            .mss(1460),
+    );
+    assert!(s.take().is_empty());
+    assert_eq!(state(&s.interface, quad), Some(State::Estab));
+
+    s.send(
+        Craft::new(peer, local)
+            .seq(7001)
+            .ack(iss.wrapping_add(1))
+            .payload(b"hello"),
+    );
+    assert_eq!(s.take_one().ack, Some(7006));
+    assert_eq!(s.read_all(quad), b"hello");
+}
@@ -0,0 +1,96 @@
+//! A listener's cap on the connections one address holds against it: a SYN past the cap is
+//! dropped or reset, and counted, while other addresses still get in, and a connection frees
+//! its slot once it is done with, TIME-WAIT included.
+
+mod common;
+
+use std::net::{Ipv4Addr, Shutdown, SocketAddrV4};
+
+use common::{accept, state, us, Craft, Scripted, PEER, TICK};
+use trust::{DropReason, Rejection, SourceLimit, State, TcpConfig, TcpListener};
+
+const OTHER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 3);
+
+/// A listener on port 80 that lets an address hold two connections.
+fn capped(over: Rejection) -> (Scripted, TcpListener) {
+    let mut s = Scripted::new(TcpConfig::default());
+    let mut listener = s.interface.bind(80).unwrap();
+    listener.set_source_limit(Some(SourceLimit {
+        connections: 2,
+        over,
+    }));
+    (s, listener)
+}
+
+fn syn(from: SocketAddrV4) -> Craft {
+    Craft::new(from, us()).syn().seq(1000).mss(1460)
+}
+
+#[test]
+fn the_connection_past_the_cap_is_reset() {
+    let (mut s, listener) = capped(Rejection::Reset);
+    accept(&mut s, 80, SocketAddrV4::new(PEER, 40000), 1000);
+    accept(&mut s, 80, SocketAddrV4::new(PEER, 40001), 1000);
+    s.take();
+
+    s.send(syn(SocketAddrV4::new(PEER, 40002)));
+    let rst = s.take_one();
+    assert_eq!(rst.flags(), "R.");
+    assert_eq!((rst.seq, rst.ack), (0, Some(1001)));
+    assert_eq!(s.interface.drops().get(DropReason::SourceLimit), 1);
+
+    // while another address still gets in
+    s.send(syn(SocketAddrV4::new(OTHER, 40000)));
+    assert_eq!(s.take_one().flags(), "S.");
+    assert_eq!(listener.top_sources(5), [(PEER, 2), (OTHER, 1)]);
+}
+
+#[test]
+fn or_dropped() {
+    let (mut s, _listener) = capped(Rejection::Drop);
+    accept(&mut s, 80, SocketAddrV4::new(PEER, 40000), 1000);
+    accept(&mut s, 80, SocketAddrV4::new(PEER, 40001), 1000);
+    s.take();
+    s.send(syn(SocketAddrV4::new(PEER, 40002)));
+    assert!(s.take().is_empty());
+    assert_eq!(s.interface.drops().get(DropReason::SourceLimit), 1);
+}
+
+#[test]
+fn handshakes_under_way_count() {
+    let (mut s, listener) = capped(Rejection::Drop);
+    for port in [40000, 40001] {
+        s.send(syn(SocketAddrV4::new(PEER, port)));
+        assert_eq!(s.take_one().flags(), "S.");
+    }
+    assert_eq!(listener.top_sources(1), [(PEER, 2)]);
+    s.send(syn(SocketAddrV4::new(PEER, 40002)));
+    assert!(s.take().is_empty());
+}
+
+#[test]
+fn a_connection_in_time_wait_frees_its_slot() {
+    let (mut s, listener) = capped(Rejection::Reset);
+    let peer = SocketAddrV4::new(PEER, 40000);
+    let (quad, iss) = accept(&mut s, 80, peer, 1000);
+    accept(&mut s, 80, SocketAddrV4::new(PEER, 40001), 1000);
+    s.take();
+
+    // we close first, and the peer follows
+    s.interface.shutdown_on(quad, Shutdown::Write).unwrap();
+    s.advance(TICK);
+    assert!(s.take_one().fin);
+    s.send(
+        Craft::new(peer, us())
+            .seq(1001)
+            .ack(iss.wrapping_add(2))
+            .fin(),
+    );
+    s.take();
+    assert_eq!(state(&s.interface, quad), Some(State::TimeWait));
+    assert_eq!(listener.top_sources(1), [(PEER, 1)]);
+
+    s.send(syn(SocketAddrV4::new(PEER, 40002)));
+    assert_eq!(s.take_one().flags(), "S.");
+    assert_eq!(s.interface.drops().get(DropReason::SourceLimit), 0);
+}
@@ -0,0 +1,133 @@
+//! Splitting a stream into read and write halves: the halves go to threads of their own, the
+//! write half shuts the stream down for writing as it goes, and `reunite` only puts back
+//! together what came apart.
+
+mod common;
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddrV4};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{connect, pattern, Craft, Scripted, PEER, TICK, US};
+use trust::{Impairments, Interface, InterfaceBuilder, ReuniteError, SimNet, State, TcpConfig};
+
+/// Two interfaces with packet loops of their own, on the system clock: a server at [`US`] and
+/// a client at [`PEER`]. The network has to outlive them.
+fn server_and_client() -> (SimNet, Interface, Interface) {
+    let (net, a, b) = SimNet::new(0, Impairments::default(), Impairments::default()).unwrap();
+    let mut server = InterfaceBuilder::new();
+    server.add_nic(a, &[US]);
+    let mut client = InterfaceBuilder::new();
+    client.add_nic(b, &[PEER]);
+    (net, server.build().unwrap(), client.build().unwrap())
+}
+
+#[test]
+fn a_reader_and_a_writer_on_threads_of_their_own() {
+    let (_net, mut server, mut client) = server_and_client();
+    let listener = server.bind(80).unwrap();
+    let stream = client.connect(PEER, SocketAddrV4::new(US, 80)).unwrap();
+    let quad = stream.quad();
+    let (mut read, mut write) = stream.split();
+
+    let data = pattern(1, 256 << 10);
+    let writer = {
+        let data = data.clone();
+        thread::spawn(move || -> io::Result<()> {
+            write.write_all(&data)?;
+            // no shutdown: dropping the half does that
+            drop(write);
+            Ok(())
+        })
+    };
+    let reader = thread::spawn(move || -> io::Result<Vec<u8>> {
+        let mut back = Vec::new();
+        read.read_to_end(&mut back)?;
+        Ok(back)
+    });
+
+    // the server sees the end of the stream, which only the write half going away sent, and
+    // then echoes it all back and closes in turn
+    let mut accepted = listener.accept().unwrap();
+    let mut got = Vec::new();
+    accepted.read_to_end(&mut got).unwrap();
+    assert!(got == data, "the server got the data corrupted");
+    accepted.write_all(&got).unwrap();
+    accepted.shutdown(Shutdown::Write).unwrap();
+
+    writer.join().unwrap().unwrap();
+    let back = reader.join().unwrap().unwrap();
+    assert!(back == data, "the echo came back corrupted");
+
+    // with both halves gone and the server done, the client end finishes closing
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while client
+        .connections()
+        .iter()
+        .any(|(q, info)| *q == quad && info.state != State::TimeWait)
+    {
+        assert!(
+            Instant::now() < deadline,
+            "the connection never finished closing"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+    drop(accepted);
+}
+
+#[test]
+fn the_read_half_outlives_the_write_half() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, iss) = connect(&mut s, SocketAddrV4::new(PEER, 40000), 1000);
+    let quad = stream.quad();
+    let (mut read, write) = stream.split();
+
+    drop(write);
+    s.advance(TICK);
+    let fin = s.take_one();
+    assert_eq!((fin.flags().as_str(), fin.seq), ("F.", iss.wrapping_add(1)));
+
+    // half closed, the peer still has things to say, and the read half hears them
+    s.send(
+        Craft::new(quad.remote(), quad.local())
+            .seq(1001)
+            .ack(iss.wrapping_add(2))
+            .psh()
+            .payload(b"still here"),
+    );
+    let mut buf = [0; 32];
+    let n = read.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"still here");
+    assert_eq!(common::state(&s.interface, quad), Some(State::FinWait2));
+}
+
+#[test]
+fn only_halves_of_the_same_stream_reunite() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (a, _) = connect(&mut s, SocketAddrV4::new(PEER, 40000), 1000);
+    let (b, _) = connect(&mut s, SocketAddrV4::new(PEER, 40001), 5000);
+    let (quad_a, quad_b) = (a.quad(), b.quad());
+    let (read_a, write_a) = a.split();
+    let (read_b, write_b) = b.split();
+
+    // the wrong pairing hands both halves back as they were, shutting nothing down
+    let Err(ReuniteError(read_a, write_b)) = read_a.reunite(write_b) else {
+        panic!("halves of different streams reunited");
+    };
+    s.advance(TICK);
+    assert!(s.take().is_empty());
+
+    let mut a = read_a.reunite(write_a).unwrap();
+    let b = read_b.reunite(write_b).unwrap();
+    assert_eq!((a.quad(), b.quad()), (quad_a, quad_b));
+
+    // and a stream put back together is a whole stream again
+    a.write_all(b"whole").unwrap();
+    s.advance(TICK);
+    let seg = s.take_one();
+    assert_eq!(
+        (seg.dst, seg.payload.as_slice()),
+        (quad_a.remote(), &b"whole"[..])
+    );
+}
@@ -0,0 +1,66 @@
+//! A receiver that acknowledges each segment in several small pieces, to have the congestion
+//! window grow faster, gets it no faster than one that acknowledges each segment once, and
+//! the pieces are counted as suspicious.
+
+mod common;
+
+use std::io::Write;
+
+use common::{connect, peer, Craft, Scripted, TICK};
+use trust::{Nic, TcpConfig};
+
+/// Two round trips of slow start with a receiver that acknowledges each segment in
+/// `pieces` ACKs: returns the congestion window after each, how many segments were
+/// acknowledged, and the suspicious ACKs counted.
+fn slow_start(config: TcpConfig, pieces: u32) -> (Vec<u32>, u64, u64) {
+    let mut s = Scripted::new(config);
+    let (mut stream, _) = connect(&mut s, peer(), 1000);
+    stream.write_all(&[7; 60_000]).unwrap();
+    let mut cwnds = Vec::new();
+    let mut segments = 0;
+    for _ in 0..2 {
+        s.advance(TICK);
+        for segment in s.take() {
+            segments += 1;
+            let step = segment.len() / pieces;
+            for i in 1..=pieces {
+                let ack = if i == pieces {
+                    segment.seq.wrapping_add(segment.len())
+                } else {
+                    segment.seq.wrapping_add(i * step)
+                };
+                let ack = Craft::new(peer(), stream.quad().local()).seq(1001).ack(ack);
+                s.peer.send(&ack.build()).unwrap();
+            }
+        }
+        // taken in all at once, as a round trip's worth
+        s.settle();
+        cwnds.push(stream.info().unwrap().cwnd);
+    }
+    (cwnds, segments, stream.info().unwrap().suspicious_acks)
+}
+
+#[test]
+fn split_acks_grow_the_window_no_faster() {
+    let (honest, _, none) = slow_start(TcpConfig::default(), 1);
+    assert_eq!(none, 0);
+    let (split, segments, suspicious) = slow_start(TcpConfig::default(), 8);
+    for (split, honest) in split.iter().zip(&honest) {
+        assert!(split <= honest, "{:?} against {:?}", split, honest);
+    }
+    assert_eq!(split.last(), honest.last());
+    // all but the last piece of each segment end inside it
+    assert_eq!(suspicious, 7 * segments);
+}
+
+#[test]
+fn the_check_can_be_turned_off() {
+    let (_, _, suspicious) = slow_start(
+        TcpConfig {
+            split_ack_floor: 0,
+            ..TcpConfig::default()
+        },
+        8,
+    );
+    assert_eq!(suspicious, 0);
+}
@@ -0,0 +1,119 @@
+//! A retransmission timeout that a delay spike set off, with nothing lost: the TSecr of the
+//! first ACK after it shows the original segments got through (Eifel, RFC 3522), and the
+//! congestion response is undone rather than going back N.
+
+mod common;
+
+use std::io;
+use std::net::SocketAddrV4;
+use std::time::Duration;
+
+use common::{connection, pattern, Pair, PEER, TICK, US};
+use trust::{Impairments, InterfaceEvent, Quad, TcpConfig};
+
+const RTO: Duration = Duration::from_millis(200);
+
+fn path(latency: Duration) -> Impairments {
+    Impairments {
+        latency,
+        ..Default::default()
+    }
+}
+
+/// Read everything there is on `quad` into `into`.
+fn read(pair: &mut Pair, quad: Quad, into: &mut Vec<u8>) {
+    let mut buf = [0; 65536];
+    loop {
+        match pair.b.read_on(quad, &mut buf) {
+            Ok(n) => into.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+            Err(e) => panic!("reading failed: {}", e),
+        }
+    }
+}
+
+/// Write all of `data` on `quad` of `a`, and step until `b` has read it into `got`.
+fn deliver(pair: &mut Pair, quad: Quad, quad_b: Quad, data: &[u8], got: &mut Vec<u8>) {
+    let want = got.len() + data.len();
+    let mut written = 0;
+    for _ in 0..1000 {
+        if written < data.len() {
+            written += pair.a.write_on(quad, &data[written..]).unwrap_or(0);
+        }
+        pair.step(TICK);
+        read(pair, quad_b, got);
+        if got.len() == want {
+            return;
+        }
+    }
+    panic!("only {} of {} bytes arrived", got.len(), want);
+}
+
+#[test]
+fn delay_spike_timeout_is_undone() {
+    let normal = path(Duration::from_millis(5));
+    let sender = TcpConfig {
+        min_rto: Some(RTO),
+        ..TcpConfig::default()
+    };
+    let mut pair = Pair::new(
+        7,
+        normal.clone(),
+        normal.clone(),
+        sender,
+        TcpConfig::default(),
+    );
+    let _listener = pair.b.bind(80).unwrap();
+    let quad = pair
+        .a
+        .connect(US, SocketAddrV4::new(PEER, 80))
+        .unwrap()
+        .into_quad();
+    let mut quad_b = None;
+    while quad_b.is_none() {
+        pair.step(TICK);
+        quad_b = pair.events_b.iter().find_map(|e| match e {
+            InterfaceEvent::NewConnection(q) => Some(*q),
+            _ => None,
+        });
+    }
+    let quad_b = quad_b.unwrap();
+
+    // open the window up and measure the round trip first
+    let data = pattern(1, 192 << 10);
+    let mut got = Vec::new();
+    deliver(&mut pair, quad, quad_b, &data[..64 << 10], &mut got);
+    let before = connection(&pair.a, quad).unwrap();
+    assert!(before.timestamps);
+
+    // what goes out now takes three RTOs to get there: the timeout fires, and fires again, well
+    // before the ACK of the first flight comes back
+    pair.net.set_impairments(path(3 * RTO), normal.clone());
+    pair.a.write_on(quad, &data[64 << 10..80 << 10]).unwrap();
+    let mut waited = Duration::ZERO;
+    while waited < 3 * RTO + 5 * TICK {
+        pair.step(TICK);
+        read(&mut pair, quad_b, &mut got);
+        waited += TICK;
+    }
+    pair.net.set_impairments(normal.clone(), normal);
+    deliver(&mut pair, quad, quad_b, &data[80 << 10..], &mut got);
+
+    let after = connection(&pair.a, quad).unwrap();
+    assert_eq!(after.spurious_rtos, 1);
+    assert_eq!(after.ssthresh, before.ssthresh);
+    assert!(
+        after.cwnd >= before.cwnd,
+        "cwnd {} is short of the {} it was before the timeout",
+        after.cwnd,
+        before.cwnd
+    );
+    assert!(got == data, "the data came through corrupted");
+    // only the segment at SND.UNA went again, once for each timeout, not the whole flight
+    let resent = connection(&pair.b, quad_b).unwrap().duplicate_bytes;
+    assert!(
+        resent <= 2 * after.mss as u64,
+        "{} bytes arrived twice",
+        resent
+    );
+}
@@ -0,0 +1,111 @@
+//! Strict and permissive handling of peers that bend the smaller rules: the same script of
+//! quirky segments, run under each mode, is counted the same way, but strict discards what
+//! permissive makes the most of.
+
+mod common;
+
+use std::io::Read;
+use std::net::SocketAddrV4;
+
+use common::threaded::Threaded;
+use common::{Craft, PEER, US};
+use trust::{DropReason, Strictness, TcpConfig, Violation};
+
+/// What came of the script.
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    /// how many segments we sent in answer to each of its steps
+    replies: Vec<usize>,
+    /// what the application read
+    read: Vec<u8>,
+    /// the send window, after the peer shrank it
+    peer_window: u32,
+    /// how many segments were discarded for breaking a rule
+    discarded: u64,
+}
+
+/// A listener takes in the peer's SYN, and then, with its window still 1000 at first:
+/// 0. "hello" without the ACK bit that would complete the handshake
+/// 1. "hello" again, with it
+/// 2. an ACK shrinking the window to 100, from 1000
+/// 3. a keepalive without the ACK bit
+fn script(strictness: Strictness) -> Outcome {
+    let mut t = Threaded::with(TcpConfig {
+        strictness,
+        ..TcpConfig::default()
+    });
+    let (peer, us) = (SocketAddrV4::new(PEER, 40000), SocketAddrV4::new(US, 80));
+    let listener = t.interface.bind(80).unwrap();
+    t.send(Craft::new(peer, us).syn().seq(1000).mss(1460).window(1000));
+    let ack = t.take_one().seq.wrapping_add(1);
+
+    let steps = [
+        Craft::new(peer, us)
+            .seq(1001)
+            .window(1000)
+            .payload(b"hello"),
+        Craft::new(peer, us)
+            .seq(1001)
+            .ack(ack)
+            .window(1000)
+            .payload(b"hello"),
+        Craft::new(peer, us).seq(1006).ack(ack).window(100),
+        Craft::new(peer, us).seq(1005).window(100).payload(&[0]),
+    ];
+    let mut replies = Vec::new();
+    for step in steps {
+        t.send(step);
+        replies.push(t.take().len());
+    }
+
+    let mut stream = listener.accept().unwrap();
+    let violations = stream.violations().unwrap();
+    for rule in Violation::ALL {
+        assert_eq!(violations.get(rule), 1, "{:?} under {:?}", rule, strictness);
+    }
+    let mut read = vec![0; 16];
+    let n = stream.read(&mut read).unwrap();
+    read.truncate(n);
+    Outcome {
+        replies,
+        read,
+        peer_window: stream.info().unwrap().peer_window,
+        discarded: t.interface.drops().get(DropReason::Violation),
+    }
+}
+
+#[test]
+fn strict_discards_what_breaks_a_rule() {
+    assert_eq!(
+        script(Strictness::Strict),
+        Outcome {
+            // nothing for the ACK-less data or keepalive, and the first ACK for the data
+            // when it comes again properly
+            replies: vec![0, 1, 0, 0],
+            read: b"hello".to_vec(),
+            // the right edge stays where it was
+            peer_window: 1000,
+            discarded: 2,
+        }
+    );
+}
+
+#[test]
+fn permissive_makes_the_most_of_it() {
+    assert_eq!(
+        script(Strictness::Permissive),
+        Outcome {
+            // the ACK-less data ACKed, its repeat ACKed as a duplicate, and the keepalive
+            // answered
+            replies: vec![1, 1, 0, 1],
+            read: b"hello".to_vec(),
+            peer_window: 100,
+            discarded: 0,
+        }
+    );
+}
+
+#[test]
+fn strict_is_the_default() {
+    assert_eq!(TcpConfig::default().strictness, Strictness::Strict);
+}
@@ -0,0 +1,82 @@
+//! Rate limits on the replies a flood can draw out of us: SYN-ACKs per listener and challenge
+//! ACKs across the interface, each a token bucket on the interface's clock.
+
+mod common;
+
+use std::net::SocketAddrV4;
+use std::ops::Range;
+use std::time::Duration;
+
+use common::{connect, Craft, Scripted, PEER, TICK, US};
+use trust::{DropReason, RateLimit, TcpConfig};
+
+const LIMIT: RateLimit = RateLimit {
+    per_second: 100,
+    burst: 10,
+};
+
+/// Send a SYN to port 80 from each of `ports`, and return how many of them drew a SYN-ACK.
+fn syns(s: &mut Scripted, ports: Range<u16>) -> usize {
+    for port in ports.clone() {
+        s.send(
+            Craft::new(SocketAddrV4::new(PEER, port), SocketAddrV4::new(US, 80))
+                .syn()
+                .seq(1000),
+        );
+    }
+    // not counting the SYN-ACKs of earlier SYNs, sent again
+    s.take()
+        .iter()
+        .filter(|seg| seg.syn && ports.contains(&seg.dst.port()))
+        .count()
+}
+
+#[test]
+fn a_flood_of_syns_draws_replies_at_the_configured_rate() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let mut listener = s.interface.bind(80).unwrap();
+    listener.set_syn_limit(Some(LIMIT));
+
+    // 1000 SYNs from as many ports over 100ms: the burst of 10 goes at once, and each of the
+    // nine 10ms ticks after that earns one more
+    let mut answered = 0;
+    for batch in 0..10 {
+        if batch > 0 {
+            s.advance(TICK);
+        }
+        let from = 10000 + batch * 100;
+        answered += syns(&mut s, from..from + 100);
+    }
+    assert_eq!(answered, 19);
+    assert_eq!(s.interface.rate_limited().syn_acks, 981);
+    assert_eq!(s.interface.drops().get(DropReason::RateLimited), 981);
+
+    // after a quiet second, the bucket is full again, and no fuller
+    s.advance(Duration::from_secs(1));
+    assert_eq!(syns(&mut s, 20000..20050), 10);
+}
+
+#[test]
+fn challenge_acks_are_limited_across_connections() {
+    let mut s = Scripted::with(TcpConfig::default(), |builder| {
+        builder.challenge_ack_limit(LIMIT);
+    });
+    let a = connect(&mut s, SocketAddrV4::new(PEER, 40000), 1000);
+    let b = connect(&mut s, SocketAddrV4::new(PEER, 40001), 5000);
+
+    // out-of-window data to both, alternately, 30 segments in all at one instant
+    for i in 0..15 {
+        for ((stream, iss), irs) in [(&a, 1000u32), (&b, 5000)] {
+            let quad = stream.quad();
+            s.send(
+                Craft::new(quad.remote(), quad.local())
+                    .seq(irs + 1_000_000 + i)
+                    .ack(iss.wrapping_add(1))
+                    .payload(b"x"),
+            );
+        }
+    }
+    let acks = s.take();
+    assert_eq!(acks.len(), 10, "the two connections share one bucket");
+    assert_eq!(s.interface.rate_limited().challenge_acks, 20);
+}
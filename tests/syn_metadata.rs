@@ -0,0 +1,70 @@
+//! What the peer's SYN looked like, kept for connections a listener takes in: its options byte
+//! for byte, its window, and the TTL and IP identification it came with.
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::{connect, us, Craft, Scripted};
+use common::{peer, threaded::Threaded};
+use etherparse::Ipv4HeaderSlice;
+use trust::{Nic, SynMetadata, TcpConfig};
+
+/// NOP NOP SACK-permitted WS NOP MSS NOP EOL, an order no stack we know of uses.
+const OPTIONS: [u8; 16] = [1, 1, 4, 2, 3, 3, 7, 1, 2, 4, 5, 0xb4, 1, 0, 0, 0];
+
+/// The peer's SYN, sent with a TTL of 51 and an IP identification of 0xbeef.
+fn syn() -> Vec<u8> {
+    let mut packet = Craft::new(peer(), us())
+        .syn()
+        .seq(1000)
+        .window(29200)
+        .raw_options(&OPTIONS)
+        .build();
+    let mut ip = Ipv4HeaderSlice::from_slice(&packet).unwrap().to_header();
+    ip.time_to_live = 51;
+    ip.identification = 0xbeef;
+    let mut header = Vec::new();
+    ip.write(&mut header).unwrap();
+    packet[..header.len()].copy_from_slice(&header);
+    packet
+}
+
+fn check(metadata: &SynMetadata) {
+    assert_eq!(metadata.options, OPTIONS);
+    assert_eq!(metadata.window, 29200);
+    assert_eq!((metadata.ttl, metadata.ip_id), (51, 0xbeef));
+}
+
+#[test]
+fn an_accepted_connection_keeps_the_syn() {
+    let mut t = Threaded::new();
+    let listener = t.interface.bind(80).unwrap();
+    t.peer.send(&syn()).unwrap();
+    let iss = t.next().seq;
+    t.send(Craft::new(peer(), us()).seq(1001).ack(iss.wrapping_add(1)));
+    let stream = listener.accept().unwrap();
+    check(&stream.syn_metadata().unwrap().expect("taken in from a SYN"));
+}
+
+#[test]
+fn the_accept_hook_sees_it_before_the_syn_ack() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let seen = Arc::new(Mutex::new(None));
+    let hook = seen.clone();
+    s.interface.set_accept_hook(move |pending| {
+        *hook.lock().unwrap() = Some(pending.syn_metadata().clone());
+        true
+    });
+    let _listener = s.interface.bind(80).unwrap();
+    s.send_raw(&syn());
+    assert_eq!(s.take_one().flags(), "S.");
+    check(seen.lock().unwrap().as_ref().expect("the hook ran"));
+}
+
+#[test]
+fn a_connection_we_opened_has_none() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, _) = connect(&mut s, peer(), 1000);
+    assert_eq!(stream.syn_metadata().unwrap(), None);
+}
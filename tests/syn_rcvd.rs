@@ -0,0 +1,73 @@
+//! How SYN-RECEIVED takes the ACK that should complete the handshake (RFC 793 p.72).
+
+mod common;
+
+use common::{connection, peer, us, Craft, Scripted};
+use trust::{ConnectionInfo, DropReason, State, TcpConfig, TcpListener};
+
+/// A listener that has answered the peer's SYN, whose ISS was 1000, with a window scale of
+/// 2 on both sides: returns it, with the listener and our ISS.
+fn syn_received() -> (Scripted, TcpListener, u32) {
+    // a buffer big enough that we offer a window scale too, or the peer's goes unused
+    let config = TcpConfig {
+        recv_buffer: 256 * 1024,
+        ..TcpConfig::default()
+    };
+    let mut s = Scripted::new(config);
+    let listener = s.interface.bind(80).unwrap();
+    s.send(Craft::new(peer(), us()).syn().seq(1000).mss(1460).wscale(2));
+    let syn_ack = s.take_one();
+    assert_eq!(syn_ack.flags(), "S.");
+    (s, listener, syn_ack.seq)
+}
+
+fn only_connection(s: &Scripted) -> Option<ConnectionInfo> {
+    s.interface
+        .connections()
+        .into_iter()
+        .next()
+        .and_then(|(quad, _)| connection(&s.interface, quad))
+}
+
+#[test]
+fn ack_of_our_syn_completes_the_handshake() {
+    let (mut s, _listener, iss) = syn_received();
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .window(1000),
+    );
+    assert!(s.take().is_empty());
+    assert!(s.accepted().is_some());
+    let info = only_connection(&s).unwrap();
+    assert_eq!(info.state, State::Estab);
+    // the window of the ACK, under the scale the SYN offered
+    assert_eq!(info.peer_window, 1000 << 2);
+}
+
+#[test]
+fn ack_of_anything_else_is_answered_with_a_reset() {
+    let (mut s, _listener, iss) = syn_received();
+    let before = s.interface.drops().get(DropReason::BadAck);
+    let wrong = iss.wrapping_add(100);
+    s.send(Craft::new(peer(), us()).seq(1001).ack(wrong));
+    let rst = s.take_one();
+    assert_eq!((rst.flags().as_str(), rst.seq), ("R", wrong));
+    assert_eq!(s.interface.drops().get(DropReason::BadAck), before + 1);
+    assert!(s.accepted().is_none());
+    assert_eq!(only_connection(&s).unwrap().state, State::SynRcvd);
+}
+
+#[test]
+fn reset_with_a_bad_ack_is_not_answered() {
+    let (mut s, _listener, iss) = syn_received();
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1001)
+            .ack(iss.wrapping_add(100))
+            .rst(),
+    );
+    assert!(s.take().is_empty());
+    assert!(s.accepted().is_none());
+}
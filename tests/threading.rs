@@ -0,0 +1,86 @@
+//! The threading model described on `Interface`: the packet loop runs on a thread of its own,
+//! and the interface, its listeners and its streams may be moved to other threads, with a
+//! stream shared between a reader and a writer.
+
+mod common;
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddrV4};
+use std::sync::Arc;
+use std::thread;
+
+use common::{pattern, PEER, US};
+use trust::{
+    Impairments, Interface, InterfaceBuilder, ReadHalf, SimNet, SimNic, TcpListener, TcpStream,
+    WriteHalf,
+};
+
+// Checked when the tests build, so that a regression fails to compile. The interface keeps the
+// connections behind a lock it shares with the packet loop, so its being `Send` takes theirs.
+const _: () = {
+    fn send<T: Send>() {}
+    fn sync<T: Sync>() {}
+    #[allow(dead_code)]
+    fn check() {
+        send::<Interface>();
+        send::<TcpStream>();
+        sync::<TcpStream>();
+        send::<ReadHalf>();
+        sync::<ReadHalf>();
+        send::<WriteHalf>();
+        sync::<WriteHalf>();
+        send::<TcpListener>();
+        sync::<TcpListener>();
+        send::<SimNet>();
+        sync::<SimNet>();
+        send::<SimNic>();
+    }
+};
+
+#[test]
+fn streams_and_listeners_are_used_from_other_threads() {
+    // both ends on the system clock, with packet loops of their own
+    let (_net, a, b) = SimNet::new(0, Impairments::default(), Impairments::default()).unwrap();
+    let mut server = InterfaceBuilder::new();
+    server.add_nic(a, &[US]);
+    let mut server = server.build().unwrap();
+    let mut client = InterfaceBuilder::new();
+    client.add_nic(b, &[PEER]);
+    let mut client = client.build().unwrap();
+
+    // the server, listener and all, goes to a thread that echoes what it is sent
+    let listener = server.bind(80).unwrap();
+    let echo = thread::spawn(move || -> io::Result<(Interface, Vec<u8>)> {
+        let mut stream = listener.accept()?;
+        let mut got = Vec::new();
+        stream.read_to_end(&mut got)?;
+        stream.write_all(&got)?;
+        stream.shutdown(Shutdown::Write)?;
+        // the interface has to outlive what is still in its send buffer
+        drop(stream);
+        Ok((server, got))
+    });
+
+    // one thread writes while another reads, through the same stream
+    let stream = Arc::new(client.connect(PEER, SocketAddrV4::new(US, 80)).unwrap());
+    let data = pattern(1, 256 << 10);
+    let writer = {
+        let stream = stream.clone();
+        let data = data.clone();
+        thread::spawn(move || -> io::Result<()> {
+            (&*stream).write_all(&data)?;
+            stream.shutdown(Shutdown::Write)
+        })
+    };
+    let reader = thread::spawn(move || -> io::Result<Vec<u8>> {
+        let mut back = Vec::new();
+        (&*stream).read_to_end(&mut back)?;
+        Ok(back)
+    });
+
+    writer.join().unwrap().unwrap();
+    let back = reader.join().unwrap().unwrap();
+    let (_server, got) = echo.join().unwrap().unwrap();
+    assert!(got == data, "the server got the data corrupted");
+    assert!(back == data, "the echo came back corrupted");
+}
@@ -0,0 +1,236 @@
+//! What a connection in TIME-WAIT does with each kind of segment it can see (RFC 793 p.73,
+//! RFC 1337).
+
+mod common;
+
+use std::time::Duration;
+
+use common::{accept, peer, script, state, us, Craft, Scripted, TICK};
+use trust::{DropReason, Quad, State, TcpConfig};
+
+const MSL: Duration = Duration::from_secs(1);
+
+/// A connection we closed first, through to TIME-WAIT, with "hello" received along the way:
+/// returns it, with our ISS.
+fn time_wait() -> (Scripted, Quad, u32) {
+    let config = TcpConfig {
+        msl: MSL,
+        ..TcpConfig::default()
+    };
+    let mut s = Scripted::new(config);
+    let listener = s.interface.bind(80).unwrap();
+    let (quad, iss) = accept(&mut s, 80, peer(), 1000);
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .psh()
+            .payload(b"hello"),
+    );
+    s.advance(TICK);
+    s.interface.close_on(quad);
+    s.advance(TICK);
+    let fin = s.take().pop().unwrap();
+    assert!(fin.fin);
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1006)
+            .ack(iss.wrapping_add(2))
+            .fin(),
+    );
+    let ack = s.take_one();
+    assert_eq!(ack.ack, Some(1007));
+    assert_eq!(state(&s.interface, quad), Some(State::TimeWait));
+    drop(listener);
+    (s, quad, iss)
+}
+
+#[test]
+fn retransmitted_fin_is_acked_and_restarts_the_timer() {
+    script::run(
+        TcpConfig {
+            msl: MSL,
+            ..TcpConfig::default()
+        },
+        "
+        +0    bind 80
+        +0    < S seq=0 <mss 1460>
+        +0    > S. seq=0 ack=1
+        +0    < . seq=1 ack=1
+        +0    < P. seq=1 ack=1 len=5
+        +0    > . seq=1 ack=6
+        +0    close
+        +0.01 > F. seq=1 ack=6
+        +0    < F. seq=6 ack=2
+        +0    > . seq=2 ack=7
+        +0    state TimeWait
+
+        # half a second before 2MSL is up, our ACK of the FIN turns out to have been lost
+        +1.5  < F. seq=6 ack=2
+        +0    > . seq=2 ack=7
+        # past where the first timer would have run out, but not the restarted one
+        +1    state TimeWait
+        +1    state gone
+        ",
+    );
+}
+
+#[test]
+fn retransmitted_data_is_acked_and_dropped() {
+    let (mut s, quad, iss) = time_wait();
+    let before = s.interface.drops().get(DropReason::TimeWait);
+    // the peer timed out before our ACK of its FIN arrived, and went back to SND.UNA
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1001)
+            .ack(iss.wrapping_add(2))
+            .payload(b"hello"),
+    );
+    let ack = s.take_one();
+    assert_eq!(ack.ack, Some(1007));
+    assert_eq!(s.interface.drops().get(DropReason::TimeWait), before + 1);
+    assert_eq!(state(&s.interface, quad), Some(State::TimeWait));
+}
+
+/// Like [`time_wait`], with no data, but timestamps on the connection, the peer's last TSval 100,
+/// and `time_wait_reuse` as given.
+fn time_wait_with_timestamps(time_wait_reuse: bool) -> (Scripted, Quad, u32) {
+    let config = TcpConfig {
+        msl: MSL,
+        time_wait_reuse,
+        ..TcpConfig::default()
+    };
+    let mut s = Scripted::new(config);
+    let listener = s.interface.bind(80).unwrap();
+    s.send(
+        Craft::new(peer(), us())
+            .syn()
+            .seq(1000)
+            .mss(1460)
+            .timestamps(97, 0),
+    );
+    let syn_ack = s.take_one();
+    let (iss, (ts_val, _)) = (syn_ack.seq, syn_ack.timestamps().unwrap());
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .timestamps(98, ts_val),
+    );
+    let quad = s.accepted().unwrap();
+    s.interface.close_on(quad);
+    s.advance(TICK);
+    let fin = s.take().pop().unwrap();
+    assert!(fin.fin);
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1001)
+            .ack(iss.wrapping_add(2))
+            .fin()
+            .timestamps(100, fin.timestamps().unwrap().0),
+    );
+    let ack = s.take_one();
+    assert_eq!(ack.ack, Some(1002));
+    assert_eq!(state(&s.interface, quad), Some(State::TimeWait));
+    drop(listener);
+    (s, quad, iss)
+}
+
+#[test]
+fn new_syn_is_dropped_without_time_wait_reuse() {
+    let (mut s, quad, _) = time_wait_with_timestamps(false);
+    let _listener = s.interface.bind(80).unwrap();
+    let before = s.interface.drops().get(DropReason::TimeWait);
+    s.send(
+        Craft::new(peer(), us())
+            .syn()
+            .seq(5000)
+            .mss(1460)
+            .timestamps(200, 0),
+    );
+    assert!(s.take().is_empty());
+    assert_eq!(s.interface.drops().get(DropReason::TimeWait), before + 1);
+    assert_eq!(state(&s.interface, quad), Some(State::TimeWait));
+}
+
+#[test]
+fn new_syn_opens_a_new_connection_with_time_wait_reuse() {
+    let (mut s, quad, iss) = time_wait_with_timestamps(true);
+    let _listener = s.interface.bind(80).unwrap();
+    s.take_events();
+
+    // an old timestamp does not do, however far on the SYN starts
+    s.send(
+        Craft::new(peer(), us())
+            .syn()
+            .seq(5000)
+            .mss(1460)
+            .timestamps(99, 0),
+    );
+    assert!(s.take().is_empty());
+    assert_eq!(state(&s.interface, quad), Some(State::TimeWait));
+    // nor a SYN that starts within what the old connection received
+    s.send(
+        Craft::new(peer(), us())
+            .syn()
+            .seq(1000)
+            .mss(1460)
+            .timestamps(200, 0),
+    );
+    assert!(s.take().is_empty());
+    assert_eq!(state(&s.interface, quad), Some(State::TimeWait));
+
+    s.send(
+        Craft::new(peer(), us())
+            .syn()
+            .seq(5000)
+            .mss(1460)
+            .timestamps(200, 0),
+    );
+    let syn_ack = s.take_one();
+    assert_eq!((syn_ack.flags().as_str(), syn_ack.ack), ("S.", Some(5001)));
+    // past everything the old connection sent, so none of the peer's old ACKs fit
+    assert!(syn_ack.seq.wrapping_sub(iss.wrapping_add(2)) < 1 << 31);
+    assert_eq!(state(&s.interface, quad), Some(State::SynRcvd));
+    s.send(
+        Craft::new(peer(), us())
+            .seq(5001)
+            .ack(syn_ack.seq.wrapping_add(1))
+            .timestamps(201, syn_ack.timestamps().unwrap().0),
+    );
+    assert_eq!(s.accepted(), Some(quad));
+    assert_eq!(state(&s.interface, quad), Some(State::Estab));
+}
+
+#[test]
+fn other_segments_are_dropped() {
+    let (mut s, quad, iss) = time_wait();
+    let before = s.interface.drops().get(DropReason::TimeWait);
+    // a bare ACK, and data past the FIN, which cannot be from this incarnation
+    s.send(Craft::new(peer(), us()).seq(1007).ack(iss.wrapping_add(2)));
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1007)
+            .ack(iss.wrapping_add(2))
+            .payload(b"late"),
+    );
+    assert!(s.take().is_empty());
+    assert_eq!(s.interface.drops().get(DropReason::TimeWait), before + 2);
+    assert_eq!(state(&s.interface, quad), Some(State::TimeWait));
+}
+
+#[test]
+fn rst_does_not_cut_time_wait_short() {
+    let (mut s, quad, iss) = time_wait();
+    s.send(
+        Craft::new(peer(), us())
+            .seq(1007)
+            .ack(iss.wrapping_add(2))
+            .rst(),
+    );
+    assert!(s.take().is_empty());
+    s.advance(2 * MSL - Duration::from_millis(100));
+    assert_eq!(state(&s.interface, quad), Some(State::TimeWait));
+    s.advance(Duration::from_millis(200));
+    assert_eq!(state(&s.interface, quad), None);
+}
@@ -0,0 +1,96 @@
+//! Read and write timeouts, which run on real time like std's: a read with nothing to read, or
+//! a write with no room for any more, gives up with `WouldBlock` once the timeout is up, and a
+//! write cut short keeps what it got in.
+
+mod common;
+
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+use common::{connect, pattern, peer, Craft, Scripted, TICK};
+use trust::TcpConfig;
+
+const TIMEOUT: Duration = Duration::from_millis(50);
+
+#[test]
+fn a_read_with_nothing_to_read_gives_up() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    assert_eq!(stream.read_timeout().unwrap(), None);
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    assert_eq!(stream.read_timeout().unwrap(), Some(TIMEOUT));
+
+    let started = Instant::now();
+    let mut buf = [0; 16];
+    let e = stream.read(&mut buf).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+    assert!(started.elapsed() >= TIMEOUT);
+
+    // the connection is none the worse for it
+    let us = stream.quad().local();
+    s.send(
+        Craft::new(peer(), us)
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .psh()
+            .payload(b"late"),
+    );
+    assert_eq!(stream.read(&mut buf).unwrap(), 4);
+    assert_eq!(&buf[..4], b"late");
+}
+
+#[test]
+fn a_write_into_a_full_buffer_gives_up_and_keeps_what_it_took() {
+    let mut s = Scripted::new(TcpConfig {
+        send_buffer: 4096,
+        ..TcpConfig::default()
+    });
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+    // the peer's window closes, so nothing leaves the send buffer
+    s.send(
+        Craft::new(peer(), us)
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .window(0),
+    );
+    stream.set_write_timeout(Some(TIMEOUT)).unwrap();
+
+    let data = pattern(6, 10_000);
+    let started = Instant::now();
+    let took = stream.write(&data).unwrap();
+    assert_eq!(took, 4096, "a write takes what fits before it would wait");
+    let e = stream.write(&data[took..]).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+    assert!(started.elapsed() >= TIMEOUT);
+
+    // once the window opens, what the write took goes out, and only that
+    s.send(
+        Craft::new(peer(), us)
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .window(u16::MAX),
+    );
+    s.advance(TICK);
+    let sent: Vec<u8> = s.take().into_iter().flat_map(|seg| seg.payload).collect();
+    assert!(sent == data[..took], "sent {} bytes", sent.len());
+}
+
+#[test]
+fn a_zero_timeout_is_refused() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, _) = connect(&mut s, peer(), 1000);
+    for e in [
+        stream.set_read_timeout(Some(Duration::ZERO)).unwrap_err(),
+        stream.set_write_timeout(Some(Duration::ZERO)).unwrap_err(),
+    ] {
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+    assert_eq!(stream.read_timeout().unwrap(), None);
+    assert_eq!(stream.write_timeout().unwrap(), None);
+
+    // and None takes a timeout away again
+    stream.set_write_timeout(Some(TIMEOUT)).unwrap();
+    stream.set_write_timeout(None).unwrap();
+    assert_eq!(stream.write_timeout().unwrap(), None);
+}
@@ -0,0 +1,138 @@
+//! Urgent data, BSD style: the last byte of what `send_urgent` is given is the urgent one, and
+//! the pointer on the wire points just past it. On the way in, that byte is taken out of the
+//! stream for `recv_urgent`, and reads stop at it so the application can tell where it was.
+
+mod common;
+
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use common::{connect, peer, Craft, Scripted, TICK};
+use trust::{TcpConfig, TcpStream};
+
+/// Read what there is to read right now, which stops at the urgent mark.
+fn read_some(stream: &mut TcpStream) -> Vec<u8> {
+    let mut buf = [0; 64];
+    let n = stream.read(&mut buf).unwrap();
+    buf[..n].to_vec()
+}
+
+#[test]
+fn the_pointer_goes_just_past_the_urgent_byte() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+
+    stream.write_all(b"status").unwrap();
+    stream.send_urgent(b"\xff\xf2").unwrap();
+    s.advance(TICK);
+    let seg = s.take_one();
+    assert_eq!(seg.payload, b"status\xff\xf2");
+    assert_eq!(seg.urgent, Some(8));
+
+    // the urgent byte has been acknowledged, so what follows goes out without URG
+    s.send(Craft::new(peer(), us).seq(1001).ack(iss.wrapping_add(9)));
+    stream.write_all(b"more").unwrap();
+    s.advance(TICK);
+    let seg = s.take_one();
+    assert_eq!((seg.payload.as_slice(), seg.urgent), (&b"more"[..], None));
+}
+
+#[test]
+fn urgent_bytes_come_out_of_band_between_ordinary_ones() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+    let ack = iss.wrapping_add(1);
+
+    s.send(Craft::new(peer(), us).seq(1001).ack(ack).payload(b"abc"));
+    s.send(
+        Craft::new(peer(), us)
+            .seq(1004)
+            .ack(ack)
+            .urgent(3)
+            .payload(b"de!"),
+    );
+    s.send(
+        Craft::new(peer(), us)
+            .seq(1007)
+            .ack(ack)
+            .psh()
+            .payload(b"fg"),
+    );
+    assert_eq!(s.take().last().unwrap().ack, Some(1009));
+
+    // everything before the mark, without the urgent byte itself
+    assert!(!stream.at_urgent_mark().unwrap());
+    assert_eq!(read_some(&mut stream), b"abcde");
+    assert!(stream.at_urgent_mark().unwrap());
+    assert_eq!(stream.recv_urgent().unwrap(), b'!');
+    assert_eq!(
+        stream.recv_urgent().unwrap_err().kind(),
+        io::ErrorKind::InvalidInput
+    );
+
+    // and the stream carries on after it, in order
+    assert_eq!(read_some(&mut stream), b"fg");
+    assert!(!stream.at_urgent_mark().unwrap());
+}
+
+#[test]
+fn a_pointer_beyond_the_data_announces_urgent_data_still_to_come() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+    let ack = iss.wrapping_add(1);
+
+    // the urgent byte is the tenth of what the peer sends, and the segment carries four
+    s.send(
+        Craft::new(peer(), us)
+            .seq(1001)
+            .ack(ack)
+            .urgent(10)
+            .psh()
+            .payload(b"1234"),
+    );
+    assert_eq!(
+        stream.recv_urgent().unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+    assert_eq!(read_some(&mut stream), b"1234");
+
+    s.send(
+        Craft::new(peer(), us)
+            .seq(1005)
+            .ack(ack)
+            .urgent(6)
+            .psh()
+            .payload(b"56789#xyz"),
+    );
+    stream.wait_urgent().unwrap();
+    assert_eq!(read_some(&mut stream), b"56789");
+    assert!(stream.at_urgent_mark().unwrap());
+    assert_eq!(stream.recv_urgent().unwrap(), b'#');
+    assert_eq!(read_some(&mut stream), b"xyz");
+}
+
+#[test]
+fn waiting_for_urgent_data_gives_up_at_the_read_timeout() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+
+    // urgent data is announced, but what it points at never comes
+    s.send(
+        Craft::new(peer(), us)
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .urgent(10)
+            .payload(b"1234"),
+    );
+    stream
+        .set_read_timeout(Some(Duration::from_millis(50)))
+        .unwrap();
+    assert_eq!(
+        stream.wait_urgent().unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+}
@@ -0,0 +1,176 @@
+//! Vectored reads and writes, and reads into uninitialized memory, against the plain `read` and
+//! `write` they have to agree with. The receive buffer is kept small and only partly drained
+//! between segments, so the data it holds keeps wrapping around its end.
+
+mod common;
+
+use std::io::{IoSlice, IoSliceMut, Read, Write};
+use std::mem::MaybeUninit;
+use std::net::SocketAddrV4;
+
+use common::{connect, pattern, Craft, Scripted, PEER, TICK};
+use trust::{TcpConfig, TcpStream};
+
+const BUFFER: usize = 4096;
+
+/// The ways of reading, one to each connection.
+#[derive(Clone, Copy, Debug)]
+enum Reader {
+    Plain,
+    Vectored,
+    Uninit,
+}
+
+impl Reader {
+    fn read(self, stream: &mut TcpStream, want: usize) -> Vec<u8> {
+        match self {
+            Reader::Plain => {
+                let mut buf = vec![0; want];
+                let n = stream.read(&mut buf).unwrap();
+                buf.truncate(n);
+                buf
+            }
+            Reader::Vectored => {
+                // uneven slices, one of them empty, that add up to what the others read
+                let first = want.min(7);
+                let (mut a, mut b, mut c) = (vec![0; first], vec![0; 0], vec![0; want - first]);
+                let mut slices = [
+                    IoSliceMut::new(&mut a),
+                    IoSliceMut::new(&mut b),
+                    IoSliceMut::new(&mut c),
+                ];
+                let n = stream.read_vectored(&mut slices).unwrap();
+                let mut got = [a, b, c].concat();
+                got.truncate(n);
+                got
+            }
+            Reader::Uninit => {
+                let mut buf = vec![MaybeUninit::uninit(); want];
+                let n = stream.read_buf(&mut buf).unwrap();
+                buf[..n]
+                    .iter()
+                    .map(|b| unsafe { b.assume_init() })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// One connection being read from one way, and how far it has got.
+struct End {
+    reader: Reader,
+    stream: TcpStream,
+    iss: u32,
+    /// how much of the data the peer has sent
+    sent: usize,
+    /// how much of it the peer may send, as far as our window goes
+    edge: usize,
+    got: Vec<u8>,
+}
+
+#[test]
+fn every_way_of_reading_gets_the_same_bytes() {
+    let mut s = Scripted::new(TcpConfig {
+        recv_buffer: BUFFER,
+        ..TcpConfig::default()
+    });
+    let data = pattern(9, 64 << 10);
+    let readers = [Reader::Plain, Reader::Vectored, Reader::Uninit];
+    let mut ends = Vec::new();
+    for (i, reader) in readers.into_iter().enumerate() {
+        let peer = SocketAddrV4::new(PEER, 40000 + i as u16);
+        let (stream, iss) = connect(&mut s, peer, 1000);
+        ends.push(End {
+            reader,
+            stream,
+            iss,
+            sent: 0,
+            edge: BUFFER,
+            got: Vec::new(),
+        });
+    }
+
+    // segments of varying sizes, as far as the window lets them, each followed by a read that
+    // leaves a little behind
+    let mut size = 1000;
+    for round in 0.. {
+        if ends.iter().all(|end| end.got.len() == data.len()) {
+            break;
+        }
+        assert!(round < 1000, "the transfer stalled");
+        for end in &mut ends {
+            let quad = end.stream.quad();
+            let len = size.min(end.edge - end.sent).min(data.len() - end.sent);
+            if len > 0 {
+                s.send(
+                    Craft::new(quad.remote(), quad.local())
+                        .seq(1001 + end.sent as u32)
+                        .ack(end.iss.wrapping_add(1))
+                        .psh()
+                        .payload(&data[end.sent..end.sent + len]),
+                );
+                end.sent += len;
+            }
+            let held = end.sent - end.got.len();
+            let want = if end.sent == data.len() {
+                held
+            } else {
+                held.saturating_sub(37)
+            };
+            if want > 0 {
+                end.got.extend(end.reader.read(&mut end.stream, want));
+            }
+            // the read may have opened the window
+            s.advance(TICK);
+            for seg in s.take().iter().filter(|seg| seg.dst == quad.remote()) {
+                let acked = seg.ack.unwrap().wrapping_sub(1001) as usize;
+                end.edge = end.edge.max(acked + seg.window as usize);
+            }
+        }
+        size = size * 7 % 3001 + 1;
+    }
+    for end in &ends {
+        assert!(end.got == data, "{:?} reads came out different", end.reader);
+    }
+}
+
+#[test]
+fn a_vectored_write_sends_what_a_plain_one_would() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut plain, _) = connect(&mut s, SocketAddrV4::new(PEER, 40000), 1000);
+    let (mut vectored, _) = connect(&mut s, SocketAddrV4::new(PEER, 40001), 1000);
+    assert!(vectored.is_read_vectored() && vectored.is_write_vectored());
+
+    let data = pattern(4, 5000);
+    plain.write_all(&data).unwrap();
+    let (a, rest) = data.split_at(3);
+    let (b, c) = rest.split_at(2900);
+    let slices = [
+        IoSlice::new(a),
+        IoSlice::new(&[]),
+        IoSlice::new(b),
+        IoSlice::new(c),
+    ];
+    assert_eq!(vectored.write_vectored(&slices).unwrap(), data.len());
+
+    s.advance(TICK);
+    let sent = s.take();
+    let payload = |stream: &TcpStream| -> Vec<u8> {
+        let to = stream.quad().remote();
+        sent.iter()
+            .filter(|seg| seg.dst == to)
+            .flat_map(|seg| seg.payload.clone())
+            .collect()
+    };
+    assert!(payload(&plain) == data);
+    assert!(payload(&vectored) == data);
+    // cut into the same segments, too: the slices leave no mark on the wire
+    let sizes = |stream: &TcpStream| -> Vec<usize> {
+        let to = stream.quad().remote();
+        sent.iter()
+            .filter(|seg| seg.dst == to)
+            .map(|seg| seg.payload.len())
+            .collect()
+    };
+    assert_eq!(sizes(&plain), sizes(&vectored));
+}
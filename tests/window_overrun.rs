@@ -0,0 +1,85 @@
+//! Data past the right edge of the window we advertised: the part of a segment inside it is
+//! taken, and the rest is neither buffered, in order or out of it, nor acknowledged, only
+//! counted in `window_overrun_bytes`.
+
+mod common;
+
+use std::io::Read;
+
+use common::{connect, pattern, peer, Craft, Scripted};
+use trust::{Nic, TcpConfig, TcpStream};
+
+const BUFFER: u32 = 1024;
+
+/// A connection with a receive buffer, and so a window, of `BUFFER` bytes: returns its stream,
+/// and what makes the peer's segments, given where they start.
+fn small_window() -> (Scripted, TcpStream, impl Fn(u32) -> Craft) {
+    let mut s = Scripted::new(TcpConfig {
+        recv_buffer: BUFFER as usize,
+        ..TcpConfig::default()
+    });
+    let (stream, iss) = connect(&mut s, peer(), 1000);
+    let us = stream.quad().local();
+    let segment = move |seq| Craft::new(peer(), us).seq(seq).ack(iss.wrapping_add(1));
+    (s, stream, segment)
+}
+
+#[test]
+fn a_segment_straddling_the_edge_is_cut_at_it() {
+    let (mut s, mut stream, segment) = small_window();
+    let data = pattern(174, 1400);
+    s.send(segment(1001).payload(&data));
+    let ack = s.take_one();
+    assert_eq!((ack.ack, ack.window), (Some(1001 + BUFFER), 0));
+    let info = stream.info().unwrap();
+    assert_eq!(info.window_overrun_bytes, 1400 - BUFFER as u64);
+
+    let mut buf = [0; 2048];
+    let n = stream.read(&mut buf).unwrap();
+    assert!(buf[..n] == data[..BUFFER as usize]);
+}
+
+#[test]
+fn segments_in_one_batch_are_held_to_the_edge_advertised_before_it() {
+    let (mut s, stream, segment) = small_window();
+    // three segments arrive before we get to send anything
+    for (seq, len) in [(1001, 400), (1401, 400), (1801, 400)] {
+        let packet = segment(seq).payload(&vec![1; len]).build();
+        s.peer.send(&packet).unwrap();
+    }
+    s.settle();
+    let acks = s.take();
+    assert_eq!(acks.last().unwrap().ack, Some(1001 + BUFFER));
+    let info = stream.info().unwrap();
+    assert_eq!(info.window_overrun_bytes, 3 * 400 - BUFFER as u64);
+}
+
+#[test]
+fn data_wholly_past_the_edge_is_not_held_out_of_order() {
+    let (mut s, stream, segment) = small_window();
+    s.send(segment(1001 + BUFFER).payload(&[2; 100]));
+    let ack = s.take_one();
+    assert_eq!(ack.ack, Some(1001));
+    let info = stream.info().unwrap();
+    assert_eq!(info.out_of_order_bytes, 0);
+    assert_eq!(info.window_overrun_bytes, 100);
+}
+
+#[test]
+fn data_held_out_of_order_stops_at_the_edge_too() {
+    let (mut s, mut stream, segment) = small_window();
+    let data = pattern(174, 1400);
+    // past a gap of 100 bytes, and on past the edge
+    s.send(segment(1101).payload(&data[100..]));
+    assert_eq!(s.take_one().ack, Some(1001));
+    let info = stream.info().unwrap();
+    assert_eq!(info.out_of_order_bytes, BUFFER - 100);
+    assert_eq!(info.window_overrun_bytes, 1400 - BUFFER as u64);
+
+    // filling the gap brings it up to the edge, and no further
+    s.send(segment(1001).payload(&data[..100]));
+    assert_eq!(s.take_one().ack, Some(1001 + BUFFER));
+    let mut buf = [0; 2048];
+    let n = stream.read(&mut buf).unwrap();
+    assert!(buf[..n] == data[..BUFFER as usize]);
+}
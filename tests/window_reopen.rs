@@ -0,0 +1,80 @@
+//! The window update that reopens a window we had closed is sent again, further apart each
+//! time, until the peer sends something, in case the first was lost and the peer has no
+//! persist timer of its own.
+
+mod common;
+
+use std::io::Read;
+use std::time::Duration;
+
+use common::{connect, peer, Craft, Scripted, TICK};
+use trust::{TcpConfig, TcpStream};
+
+const BUFFER: u16 = 3000;
+
+/// A connection whose peer has filled the window, and whose application has just read it all
+/// out, with the ACK that says so taken.
+fn reopened() -> (Scripted, TcpStream, u32) {
+    let mut s = Scripted::new(TcpConfig {
+        recv_buffer: BUFFER as usize,
+        initial_rto: Some(Duration::from_secs(1)),
+        max_rto: Some(Duration::from_secs(5)),
+        ..TcpConfig::default()
+    });
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    for (seq, len) in [(1001, 1460), (2461, 1460), (3921, 80)] {
+        s.send(
+            Craft::new(peer(), stream.quad().local())
+                .seq(seq)
+                .ack(iss.wrapping_add(1))
+                .payload(&vec![1; len]),
+        );
+    }
+    assert_eq!(s.take().last().unwrap().window, 0);
+    stream.read_exact(&mut [0; BUFFER as usize]).unwrap();
+    s.advance(TICK);
+    let update = s.take_one();
+    assert_eq!((update.ack, update.window), (Some(4001), BUFFER));
+    (s, stream, iss)
+}
+
+/// When each update goes out over the next `over`, counting from now.
+fn updates(s: &mut Scripted, over: Duration) -> Vec<Duration> {
+    let start = s.net.now();
+    let mut when = Vec::new();
+    while s.net.now() - start < over {
+        s.advance(TICK);
+        for segment in s.take() {
+            assert_eq!((segment.ack, segment.window), (Some(4001), BUFFER));
+            when.push(s.net.now() - start);
+        }
+    }
+    when
+}
+
+#[test]
+fn the_update_is_repeated_further_apart_each_time() {
+    let (mut s, stream, _) = reopened();
+    // an RTO after the first, then twice that, and so on until the wait would pass the
+    // maximum RTO
+    let rto = Duration::from_secs(1);
+    assert_eq!(
+        updates(&mut s, Duration::from_secs(60)),
+        [rto, 3 * rto, 7 * rto]
+    );
+    assert_eq!(stream.info().unwrap().window_reopen_acks, 4);
+}
+
+#[test]
+fn anything_from_the_peer_stops_it() {
+    let (mut s, stream, iss) = reopened();
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .seq(4001)
+            .ack(iss.wrapping_add(1))
+            .payload(b"more"),
+    );
+    s.take();
+    assert!(updates(&mut s, Duration::from_secs(60)).is_empty());
+    assert_eq!(stream.info().unwrap().window_reopen_acks, 1);
+}
@@ -0,0 +1,109 @@
+//! What `write` and `flush` promise: a write reports the bytes the send buffer took, which is
+//! fewer than it was given if it stops waiting for room, every one of them goes out and
+//! nothing else, and `flush` returns only once the peer has acknowledged the last of them.
+
+mod common;
+
+use std::io::{self, Write};
+use std::thread;
+use std::time::Duration;
+
+use common::{connect, pattern, peer, Craft, Scripted, TICK};
+use trust::{TcpConfig, TcpStream};
+
+/// Have the peer acknowledge `upto` bytes of ours, with `window` of room.
+fn ack(s: &mut Scripted, stream: &TcpStream, iss: u32, upto: usize, window: u16) {
+    let quad = stream.quad();
+    s.send(
+        Craft::new(quad.remote(), quad.local())
+            .seq(1001)
+            .ack(iss.wrapping_add(1 + upto as u32))
+            .window(window),
+    );
+}
+
+#[test]
+fn a_write_takes_what_fits_and_says_so() {
+    let mut s = Scripted::new(TcpConfig {
+        send_buffer: 4096,
+        ..TcpConfig::default()
+    });
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    ack(&mut s, &stream, iss, 0, 0);
+
+    stream
+        .set_write_timeout(Some(Duration::from_millis(30)))
+        .unwrap();
+
+    let data = pattern(30, 10_000);
+    assert_eq!(stream.write(&data[..1000]).unwrap(), 1000);
+    // the rest does not fit: the write takes what does, and gives up waiting for more room
+    assert_eq!(stream.write(&data[1000..]).unwrap(), 3096);
+    assert_eq!(
+        stream.write(&[]).unwrap(),
+        0,
+        "an empty write does not wait for room"
+    );
+
+    // acknowledging some makes room for as much again, and no more
+    ack(&mut s, &stream, iss, 0, u16::MAX);
+    s.advance(TICK);
+    let first: Vec<u8> = s.take().into_iter().flat_map(|seg| seg.payload).collect();
+    assert!(first == data[..4096], "sent {} bytes", first.len());
+    ack(&mut s, &stream, iss, 1500, u16::MAX);
+    assert_eq!(stream.write(&data[4096..]).unwrap(), 1500);
+    s.advance(TICK);
+    let second: Vec<u8> = s.take().into_iter().flat_map(|seg| seg.payload).collect();
+    assert!(second == data[4096..5596], "sent {} bytes", second.len());
+}
+
+#[test]
+fn flush_waits_for_the_last_ack() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    stream.write_all(&pattern(31, 3000)).unwrap();
+    s.advance(TICK);
+    let sent = s.take();
+    assert_eq!(
+        sent.iter().map(|seg| seg.payload.len()).sum::<usize>(),
+        3000
+    );
+
+    thread::scope(|scope| {
+        let flushing = scope.spawn(|| (&stream).flush());
+        // nothing is acknowledged yet, and then all but the last byte
+        thread::sleep(Duration::from_millis(50));
+        assert!(
+            !flushing.is_finished(),
+            "flush returned with nothing acknowledged"
+        );
+        ack(&mut s, &stream, iss, 2999, u16::MAX);
+        thread::sleep(Duration::from_millis(50));
+        assert!(!flushing.is_finished(), "flush returned a byte short");
+
+        ack(&mut s, &stream, iss, 3000, u16::MAX);
+        flushing.join().unwrap().unwrap();
+    });
+    // with nothing outstanding, there is nothing to wait for
+    stream.flush().unwrap();
+}
+
+#[test]
+fn flush_gives_up_with_the_write_timeout() {
+    let mut s = Scripted::new(TcpConfig::default());
+    let (mut stream, iss) = connect(&mut s, peer(), 1000);
+    stream.write_all(b"unheard").unwrap();
+    s.advance(TICK);
+    assert_eq!(s.take_one().payload, b"unheard");
+    stream
+        .set_write_timeout(Some(Duration::from_millis(30)))
+        .unwrap();
+    assert_eq!(
+        stream.flush().unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+
+    // and tries again later for the same bytes
+    ack(&mut s, &stream, iss, 7, u16::MAX);
+    stream.flush().unwrap();
+}
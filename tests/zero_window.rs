@@ -0,0 +1,118 @@
+//! A peer that closes its window and keeps it closed: the persist timer probes it, backing off,
+//! and with a zero-window timeout the connection gives up on it, failing the writer waiting on
+//! it, unless the window opens in between.
+
+mod common;
+
+use std::io::{self, Write};
+use std::thread;
+use std::time::Duration;
+
+use common::{pattern, peer, Craft, Scripted, TICK, US};
+use trust::{CloseReason, TcpConfig, TcpStream};
+
+const LIMIT: Duration = Duration::from_secs(10);
+
+/// What [`watch`] sees of a window closed for good, in milliseconds from when it closed.
+const PROBED: [(u64, &str); 4] = [
+    (1010, "probe"),
+    (3020, "probe"),
+    (7030, "probe"),
+    (10000, "rst"),
+];
+
+fn config() -> TcpConfig {
+    TcpConfig {
+        send_buffer: 4096,
+        zero_window_timeout: Some(LIMIT),
+        ..TcpConfig::default()
+    }
+}
+
+/// Connect to a peer whose window is closed from its SYN on, returning our ISS.
+fn connect(s: &mut Scripted) -> (TcpStream, u32) {
+    let stream = s.interface.connect(US, peer()).unwrap();
+    s.advance(TICK);
+    let iss = s.take_one().seq;
+    s.send(
+        Craft::new(peer(), stream.quad().local())
+            .syn()
+            .seq(1000)
+            .ack(iss.wrapping_add(1))
+            .window(0),
+    );
+    assert_eq!(s.take_one().ack, Some(1001));
+    (stream, iss)
+}
+
+/// What the interface sent over `span`, a tick at a time, as (milliseconds in, probe or RST),
+/// answering each probe with a closed window and an ACK of `una`.
+fn watch(s: &mut Scripted, una: u32, span: Duration) -> Vec<(u64, &'static str)> {
+    let us = s.interface.connections()[0].0.local();
+    let mut seen = Vec::new();
+    for tick in 1..=span.as_millis() as u64 / 10 {
+        s.advance(TICK);
+        for seg in s.take() {
+            if seg.rst {
+                seen.push((tick * 10, "rst"));
+            } else {
+                // one byte short of what we have had acknowledged, and empty
+                assert_eq!((seg.seq, seg.payload.len()), (una.wrapping_sub(1), 0));
+                seen.push((tick * 10, "probe"));
+                s.send(Craft::new(peer(), us).seq(1001).ack(una).window(0));
+            }
+        }
+    }
+    seen
+}
+
+#[test]
+fn a_window_that_never_opens_is_given_up_on() {
+    let mut s = Scripted::new(config());
+    let (stream, iss) = connect(&mut s);
+
+    let (seen, e) = thread::scope(|scope| {
+        let writer = scope.spawn(|| (&stream).write_all(&pattern(144, 10_000)));
+        // the writer fills the send buffer and waits
+        thread::sleep(Duration::from_millis(50));
+        let seen = watch(&mut s, iss.wrapping_add(1), Duration::from_secs(12));
+        (seen, writer.join().unwrap().unwrap_err())
+    });
+    // probes after the RTO, then twice and four times that, each timed from the tick that set
+    // it, and the RST once the window has been closed for the limit, counted from the SYN-ACK
+    assert_eq!(seen, PROBED);
+    assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    assert_eq!(s.interface.zero_window_aborts(), 1);
+    assert_eq!(stream.close_reason(), Some(CloseReason::ZeroWindowTimeout));
+}
+
+#[test]
+fn a_window_that_opens_starts_the_wait_over() {
+    let mut s = Scripted::new(config());
+    let (mut stream, iss) = connect(&mut s);
+    let us = stream.quad().local();
+    stream.write_all(&pattern(144, 4000)).unwrap();
+    watch(&mut s, iss.wrapping_add(1), Duration::from_secs(8));
+
+    // eight seconds in, the peer takes a little, and closes again behind it
+    s.send(
+        Craft::new(peer(), us)
+            .seq(1001)
+            .ack(iss.wrapping_add(1))
+            .window(100),
+    );
+    s.advance(TICK);
+    let seg = s.take_one();
+    assert_eq!((seg.seq, seg.payload.len()), (iss.wrapping_add(1), 100));
+    s.send(
+        Craft::new(peer(), us)
+            .seq(1001)
+            .ack(iss.wrapping_add(101))
+            .window(0),
+    );
+
+    // the backoff starts over along with the wait, which has as long to go as at the start
+    let seen = watch(&mut s, iss.wrapping_add(101), Duration::from_secs(12));
+    assert_eq!(seen, PROBED);
+    assert_eq!(s.interface.zero_window_aborts(), 1);
+}